@@ -3,34 +3,71 @@
 //! These commands are called from the frontend via Tauri's IPC mechanism.
 //! They communicate with the orchestrator daemon via Unix socket IPC.
 
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
-use kt_core::ipc::{IpcRequest, IpcResponse};
+use kt_core::ipc::{
+    validate_env_vars, validate_idempotency_key, validate_session_ttl, validate_term,
+    validate_terminal_size, IpcRequest, IpcResponse, OrchestratorCapabilities, RejectionInfo,
+    TerminalSize,
+};
+use kt_orchestrator::session::ResizeDebouncer;
 
+use crate::input_confirm::{write_input, WriteOutcome};
+use crate::ipc_client::{
+    check_authentication, snapshot_page_request, AuthFailure, PersistentIpcClient,
+};
+use crate::logs::LogControl;
+use crate::palette::{PaletteAction, PaletteCommand, PaletteContext, PaletteOutcome};
+use crate::recents::RecentMachinePayload;
 use crate::state::AppState;
+use crate::terminal_prefs::{PrefsScope, TerminalPrefsOverrides, TerminalPrefsPayload};
 
-/// Machine information for frontend
+/// Machine as sent to the frontend (`Machine` in `src/types/index.ts`)
+///
+/// Used by commands, the state snapshot and `machine-event`, so every path
+/// gives the frontend the same shape. Changing a field changes that contract.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Machine {
+pub struct MachinePayload {
+    /// Machine ID
     pub id: String,
+    /// User-facing alias, if set
     pub alias: Option<String>,
+    /// Free-form label reported by the agent
+    pub label: Option<String>,
+    /// Hostname reported by the agent
     pub hostname: String,
+    /// Operating system, e.g. "linux"
     pub os: String,
+    /// CPU architecture, e.g. "aarch64"
     pub arch: String,
+    /// "connected", "disconnected" or "connecting"
     pub status: String,
+    /// When the machine connected (ISO-8601 UTC)
     pub connected_at: Option<String>,
+    /// Last heartbeat from the agent (ISO-8601 UTC)
     pub last_heartbeat: Option<String>,
+    /// Number of active sessions
     pub session_count: usize,
+    /// Tags, or none if there are no tags
     pub tags: Option<Vec<String>>,
+    /// Optional agent features, e.g. "cwd"
+    pub capabilities: Vec<String>,
+    /// Times the machine has reconnected
+    pub reconnect_count: u64,
 }
 
-impl From<kt_core::ipc::MachineInfo> for Machine {
+impl From<kt_core::ipc::MachineInfo> for MachinePayload {
     fn from(info: kt_core::ipc::MachineInfo) -> Self {
         Self {
             id: info.id,
             alias: info.alias,
+            label: info.label,
             hostname: info.hostname,
             os: info.os,
             arch: info.arch,
@@ -43,22 +80,38 @@ impl From<kt_core::ipc::MachineInfo> for Machine {
             } else {
                 Some(info.tags)
             },
+            capabilities: info.capabilities,
+            reconnect_count: info.reconnect_count,
         }
     }
 }
 
-/// Session information for frontend
+/// Session as sent to the frontend (`Session` in `src/types/index.ts`)
+///
+/// Used by commands, the state snapshot and `session-event`, like
+/// [`MachinePayload`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Session {
+pub struct SessionPayload {
+    /// Session ID
     pub id: String,
+    /// ID of the machine the session runs on
     pub machine_id: String,
+    /// Shell, if one was requested
     pub shell: Option<String>,
+    /// When the session was created (ISO-8601 UTC)
     pub created_at: String,
+    /// PID of the remote shell, once it is running
     pub pid: Option<u32>,
+    /// User-given label
+    pub name: Option<String>,
+    /// Terminal size, if known
+    pub size: Option<TerminalSize>,
+    /// Whether the session was detached and can be reattached
+    pub detached: bool,
 }
 
-impl From<kt_core::ipc::SessionInfo> for Session {
+impl From<kt_core::ipc::SessionInfo> for SessionPayload {
     fn from(info: kt_core::ipc::SessionInfo) -> Self {
         Self {
             id: info.id,
@@ -66,7 +119,90 @@ impl From<kt_core::ipc::SessionInfo> for Session {
             shell: info.shell,
             created_at: info.created_at,
             pid: info.pid,
+            name: info.name,
+            size: info.size,
+            detached: info.detached,
+        }
+    }
+}
+
+/// Terminal type xterm.js emulates, used when the frontend doesn't say
+const XTERM_JS_TERM: &str = "xterm-256color";
+
+/// Options from the frontend's new-session dialog
+///
+/// Every field is optional. `cols`/`rows` should be the webview terminal's
+/// current dimensions so the PTY starts at the right size; they must be
+/// given together. `term` and `truecolor` describe the webview terminal and
+/// default to what xterm.js supports. With `idempotencyKey`, retrying a
+/// create that timed out returns the session it made rather than a new one.
+/// `ttlSecs` closes the session that long after it's created, and
+/// `closeOnExit: false` keeps it open after its shell exits.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CreateSessionOptions {
+    pub shell: Option<String>,
+    pub cwd: Option<String>,
+    pub env: HashMap<String, String>,
+    pub name: Option<String>,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+    pub term: Option<String>,
+    pub truecolor: Option<bool>,
+    pub idempotency_key: Option<String>,
+    pub ttl_secs: Option<u64>,
+    pub close_on_exit: Option<bool>,
+}
+
+impl CreateSessionOptions {
+    /// Validate the options and build the IPC request for `machine_id`
+    ///
+    /// Uses the same rules as the orchestrator so bad input is reported
+    /// before anything is sent.
+    fn into_request(self, machine_id: String) -> Result<IpcRequest, String> {
+        let size = match (self.cols, self.rows) {
+            (Some(cols), Some(rows)) => {
+                validate_terminal_size(cols, rows)?;
+                Some(TerminalSize { cols, rows })
+            }
+            (None, None) => None,
+            _ => return Err("Terminal cols and rows must be given together".to_string()),
+        };
+
+        // Sort so the request is deterministic regardless of map order
+        let mut env: Vec<(String, String)> = self.env.into_iter().collect();
+        env.sort();
+        validate_env_vars(&env)?;
+
+        // Treat blank text fields from the dialog as unset
+        let non_empty = |value: Option<String>| value.filter(|v| !v.trim().is_empty());
+
+        let term = non_empty(self.term).unwrap_or_else(|| XTERM_JS_TERM.to_string());
+        validate_term(&term)?;
+
+        let idempotency_key = non_empty(self.idempotency_key);
+        if let Some(key) = &idempotency_key {
+            validate_idempotency_key(key)?;
+        }
+        if let Some(ttl_secs) = self.ttl_secs {
+            validate_session_ttl(ttl_secs)?;
         }
+
+        Ok(IpcRequest::CreateSession {
+            machine_id,
+            shell: non_empty(self.shell),
+            cwd: non_empty(self.cwd),
+            env,
+            name: non_empty(self.name),
+            size,
+            allocate_pty: true,
+            log_level: None,
+            term: Some(term),
+            truecolor: self.truecolor.unwrap_or(true),
+            idempotency_key,
+            ttl_secs: self.ttl_secs,
+            close_on_exit: self.close_on_exit,
+        })
     }
 }
 
@@ -76,8 +212,8 @@ impl From<kt_core::ipc::SessionInfo> for Session {
 pub struct StateSnapshot {
     pub epoch_id: String,
     pub current_seq: u64,
-    pub machines: Vec<Machine>,
-    pub sessions: Vec<Session>,
+    pub machines: Vec<MachinePayload>,
+    pub sessions: Vec<SessionPayload>,
 }
 
 /// Orchestrator status for frontend
@@ -92,6 +228,11 @@ pub struct OrchestratorStatus {
     pub tailscale_hostname: Option<String>,
     pub pairing_code: Option<String>,
     pub bind_address: Option<String>,
+    pub listen_address: Option<String>,
+    /// Terminal output chunks dropped because the webview fell behind
+    pub dropped_terminal_output: u64,
+    /// Why new sessions are refused, while in maintenance mode
+    pub maintenance: Option<String>,
 }
 
 impl From<kt_core::ipc::OrchestratorStatus> for OrchestratorStatus {
@@ -105,6 +246,9 @@ impl From<kt_core::ipc::OrchestratorStatus> for OrchestratorStatus {
             tailscale_hostname: status.tailscale_hostname,
             pairing_code: status.pairing_code,
             bind_address: Some(status.bind_address),
+            listen_address: status.listen_address,
+            dropped_terminal_output: 0,
+            maintenance: status.maintenance,
         }
     }
 }
@@ -120,23 +264,72 @@ impl Default for OrchestratorStatus {
             tailscale_hostname: None,
             pairing_code: None,
             bind_address: None,
+            listen_address: None,
+            dropped_terminal_output: 0,
+            maintenance: None,
+        }
+    }
+}
+
+/// Orchestrator capabilities for frontend (`OrchestratorCapabilities` in
+/// `src/types/index.ts`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesPayload {
+    /// IPC protocol version, 0 for orchestrators that predate feature
+    /// detection
+    pub protocol_version: u32,
+    /// Optional features supported, e.g. "event_replay"
+    pub features: Vec<String>,
+}
+
+impl From<OrchestratorCapabilities> for CapabilitiesPayload {
+    fn from(capabilities: OrchestratorCapabilities) -> Self {
+        Self {
+            protocol_version: capabilities.protocol_version,
+            features: capabilities.features.names(),
         }
     }
 }
 
+/// Get the optional features the orchestrator supports
+///
+/// An orchestrator too old to answer supports none, so the UI can hide
+/// what depends on them.
+#[tauri::command]
+pub async fn get_capabilities(state: State<'_, AppState>) -> Result<CapabilitiesPayload, String> {
+    match state.ipc.request(IpcRequest::GetCapabilities).await {
+        Ok(IpcResponse::Capabilities(capabilities)) => Ok(capabilities.into()),
+        Ok(IpcResponse::Error { .. }) => Ok(OrchestratorCapabilities::legacy().into()),
+        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
+        Err(e) => Err(format!("Failed to get capabilities: {}", e)),
+    }
+}
+
 /// Get orchestrator status
+///
+/// Also reports how much terminal output this app dropped because the
+/// webview fell behind.
 #[tauri::command]
 pub async fn get_status(state: State<'_, AppState>) -> Result<OrchestratorStatus, String> {
-    match state.ipc.request(IpcRequest::GetStatus).await {
-        Ok(IpcResponse::Status(status)) => Ok(status.into()),
-        Ok(IpcResponse::Error { message }) => Err(message),
-        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
+    let mut status = match state
+        .ipc
+        .request(IpcRequest::GetStatus {
+            include_pairing_code: true,
+        })
+        .await
+    {
+        Ok(IpcResponse::Status(status)) => status.into(),
+        Ok(IpcResponse::Error { message }) => return Err(message),
+        Ok(_) => return Err("Unexpected response from orchestrator".to_string()),
         Err(e) => {
             // Orchestrator not running - return offline status
             tracing::debug!("Orchestrator not running: {}", e);
-            Ok(OrchestratorStatus::default())
+            OrchestratorStatus::default()
         }
-    }
+    };
+    status.dropped_terminal_output = state.forward_stats.dropped_output();
+    Ok(status)
 }
 
 /// Get full state snapshot for synchronization
@@ -145,22 +338,63 @@ pub async fn get_status(state: State<'_, AppState>) -> Result<OrchestratorStatus
 /// Used for initial sync and recovery after event gaps.
 #[tauri::command]
 pub async fn get_state_snapshot(state: State<'_, AppState>) -> Result<StateSnapshot, String> {
-    match state.ipc.request(IpcRequest::GetStateSnapshot).await {
-        Ok(IpcResponse::StateSnapshot {
-            epoch_id,
-            current_seq,
-            machines,
-            sessions,
-        }) => Ok(StateSnapshot {
+    let snapshot = fetch_state_snapshot(&state.ipc).await?;
+    state.recents.sync_machines(&snapshot.machines);
+    state.recents.sync_sessions(&snapshot.sessions);
+    state.terminal_prefs.retain_sessions(&snapshot.sessions);
+    state
+        .input_confirmations
+        .retain_sessions(&snapshot.sessions);
+    forget_orphaned_prefs(&state);
+    Ok(snapshot)
+}
+
+/// Fetch the state snapshot page by page
+///
+/// The epoch and sequence number are those of the first page. Starts over
+/// if the orchestrator restarts between pages.
+async fn fetch_state_snapshot(ipc: &PersistentIpcClient) -> Result<StateSnapshot, String> {
+    let mut snapshot: Option<StateSnapshot> = None;
+    let mut cursor = None;
+    loop {
+        let request = snapshot_page_request(cursor.take());
+        let (epoch_id, current_seq, machines, sessions, next_cursor) =
+            match ipc.request(request).await {
+                Ok(IpcResponse::StateSnapshot {
+                    epoch_id,
+                    current_seq,
+                    machines,
+                    sessions,
+                    next_cursor,
+                }) => (epoch_id, current_seq, machines, sessions, next_cursor),
+                Ok(IpcResponse::Error { message }) => return Err(message),
+                Ok(_) => return Err("Unexpected response from orchestrator".to_string()),
+                Err(e) => return Err(format!("Failed to get state snapshot: {}", e)),
+            };
+
+        if snapshot.as_ref().is_some_and(|s| s.epoch_id != epoch_id) {
+            tracing::warn!(
+                "Orchestrator restarted while fetching the state snapshot, starting over"
+            );
+            snapshot = None;
+            continue;
+        }
+        let current = snapshot.get_or_insert_with(|| StateSnapshot {
             epoch_id,
             current_seq,
-            machines: machines.into_iter().map(Into::into).collect(),
-            sessions: sessions.into_iter().map(Into::into).collect(),
-        }),
-        Ok(IpcResponse::Error { message }) => Err(message),
-        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
-        Err(e) => Err(format!("Failed to get state snapshot: {}", e)),
+            machines: Vec::new(),
+            sessions: Vec::new(),
+        });
+        let (machines, sessions) = (machines.into_iter(), sessions.into_iter());
+        current.machines.extend(machines.map(Into::into));
+        current.sessions.extend(sessions.map(Into::into));
+
+        match next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
     }
+    snapshot.ok_or_else(|| "Empty state snapshot".to_string())
 }
 
 /// Start the orchestrator (embedded in the GUI)
@@ -198,10 +432,13 @@ pub async fn stop_orchestrator(state: State<'_, AppState>) -> Result<(), String>
 
 /// List all connected machines
 #[tauri::command]
-pub async fn list_machines(state: State<'_, AppState>) -> Result<Vec<Machine>, String> {
+pub async fn list_machines(state: State<'_, AppState>) -> Result<Vec<MachinePayload>, String> {
     match state.ipc.request(IpcRequest::ListMachines).await {
         Ok(IpcResponse::Machines { machines }) => {
-            Ok(machines.into_iter().map(Into::into).collect())
+            let machines: Vec<MachinePayload> = machines.into_iter().map(Into::into).collect();
+            state.recents.sync_machines(&machines);
+            forget_orphaned_prefs(&state);
+            Ok(machines)
         }
         Ok(IpcResponse::Error { message }) => Err(message),
         Ok(_) => Err("Unexpected response from orchestrator".to_string()),
@@ -214,7 +451,7 @@ pub async fn list_machines(state: State<'_, AppState>) -> Result<Vec<Machine>, S
 
 /// Get a specific machine by ID
 #[tauri::command]
-pub async fn get_machine(state: State<'_, AppState>, id: String) -> Result<Machine, String> {
+pub async fn get_machine(state: State<'_, AppState>, id: String) -> Result<MachinePayload, String> {
     match state
         .ipc
         .request(IpcRequest::GetMachine {
@@ -246,18 +483,34 @@ pub async fn disconnect_machine(state: State<'_, AppState>, id: String) -> Resul
     }
 }
 
+/// List recent agent registrations the orchestrator rejected
+#[tauri::command]
+pub async fn list_rejections(state: State<'_, AppState>) -> Result<Vec<RejectionInfo>, String> {
+    match state.ipc.request(IpcRequest::ListRejections).await {
+        Ok(IpcResponse::Rejections { rejections }) => Ok(rejections),
+        Ok(IpcResponse::Error { message }) => Err(message),
+        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
+        Err(e) => Err(format!("Failed to list rejected agents: {}", e)),
+    }
+}
+
 /// List sessions, optionally filtered by machine
 #[tauri::command]
 pub async fn list_sessions(
     state: State<'_, AppState>,
     machine_id: Option<String>,
-) -> Result<Vec<Session>, String> {
+) -> Result<Vec<SessionPayload>, String> {
     match state
         .ipc
-        .request(IpcRequest::ListSessions { machine_id })
+        .request(IpcRequest::ListSessions {
+            machine_id,
+            cursor: None,
+            offset: None,
+            limit: None,
+        })
         .await
     {
-        Ok(IpcResponse::Sessions { sessions }) => {
+        Ok(IpcResponse::Sessions { sessions, .. }) => {
             Ok(sessions.into_iter().map(Into::into).collect())
         }
         Ok(IpcResponse::Error { message }) => Err(message),
@@ -274,15 +527,23 @@ pub async fn list_sessions(
 pub async fn create_session(
     state: State<'_, AppState>,
     machine_id: String,
-    shell: Option<String>,
-) -> Result<Session, String> {
-    match state
-        .ipc
-        .request(IpcRequest::CreateSession { machine_id, shell })
-        .await
-    {
-        Ok(IpcResponse::SessionCreated(session)) => Ok(session.into()),
+    options: Option<CreateSessionOptions>,
+) -> Result<SessionPayload, String> {
+    let request = options.unwrap_or_default().into_request(machine_id)?;
+
+    match state.ipc.request(request).await {
+        Ok(IpcResponse::SessionCreated(session)) => {
+            let session = SessionPayload::from(session);
+            // Ours, so typing into it needs no confirmation
+            state.input_confirmations.confirm(&session.id);
+            state.recents.record_session(&session);
+            state.recents.touch_machine(&session.machine_id);
+            // Touching may push the oldest machine out of the recents
+            forget_orphaned_prefs(&state);
+            Ok(session)
+        }
         Ok(IpcResponse::Error { message }) => Err(message),
+        Ok(IpcResponse::CapacityExceeded { message, .. }) => Err(message),
         Ok(_) => Err("Unexpected response from orchestrator".to_string()),
         Err(e) => Err(format!("Failed to create session: {}", e)),
     }
@@ -308,54 +569,162 @@ pub async fn kill_session(
 }
 
 /// Write data to a terminal session
+///
+/// The first input to a session this app didn't create isn't sent until
+/// the user confirms it (see [`crate::input_confirm`]).
 #[tauri::command]
 pub async fn terminal_write(
     state: State<'_, AppState>,
     session_id: String,
     data: Vec<u8>,
+) -> Result<WriteOutcome, String> {
+    let needs_confirmation = state
+        .input_confirmations
+        .needs_confirmation(&session_id, || {
+            terminal_prefs_at(&state, PrefsScope::Session(session_id.clone()))
+                .map_or(true, |prefs| prefs.effective.confirm_foreign_input)
+        });
+    write_input(needs_confirmation, session_id, data, |request| {
+        state.ipc.request(request)
+    })
+    .await
+}
+
+/// Let input through to a session this app didn't create, for the rest of
+/// the app run
+#[tauri::command]
+pub async fn confirm_session_input(
+    state: State<'_, AppState>,
+    session_id: String,
 ) -> Result<(), String> {
-    match state
-        .ipc
-        .request(IpcRequest::SessionInput { session_id, data })
+    state.input_confirmations.confirm(&session_id);
+    Ok(())
+}
+
+/// Interval between checks for held-back resizes that are due
+const RESIZE_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Resize a terminal session
+///
+/// Resizes arriving faster than the orchestrator's default `resize_debounce`
+/// (while a window is being dragged) are held back, and
+/// [`run_resize_flusher`] sends the latest size once it is due, so the IPC
+/// socket isn't flooded.
+#[tauri::command]
+pub async fn terminal_resize(
+    state: State<'_, AppState>,
+    session_id: String,
+    cols: u16,
+    rows: u16,
+) -> Result<(), String> {
+    let size = kt_protocol::TerminalSize::new(rows, cols);
+    let now = Instant::now();
+    let Some(size) = state.resizes.submit(session_id.clone(), size, now) else {
+        return Ok(());
+    };
+    send_resize(&state.ipc, session_id, size).await
+}
+
+/// Send the latest size of terminals resized in quick succession
+pub async fn run_resize_flusher(
+    ipc: Arc<PersistentIpcClient>,
+    resizes: Arc<ResizeDebouncer<String>>,
+) {
+    let mut interval = tokio::time::interval(RESIZE_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        for (session_id, size) in resizes.take_due(Instant::now()) {
+            if let Err(e) = send_resize(&ipc, session_id, size).await {
+                tracing::debug!("Failed to send held-back resize: {}", e);
+            }
+        }
+    }
+}
+
+async fn send_resize(
+    ipc: &PersistentIpcClient,
+    session_id: String,
+    size: kt_protocol::TerminalSize,
+) -> Result<(), String> {
+    match ipc
+        .request(IpcRequest::SessionResize {
+            session_id,
+            cols: size.cols,
+            rows: size.rows,
+        })
         .await
     {
         Ok(IpcResponse::Ok) => Ok(()),
         Ok(IpcResponse::Error { message }) => Err(message),
         Ok(_) => Err("Unexpected response from orchestrator".to_string()),
-        Err(e) => Err(format!("Failed to write to terminal: {}", e)),
+        Err(e) => Err(format!("Failed to resize terminal: {}", e)),
     }
 }
 
-/// Resize a terminal session
+/// Configure activity and silence notifications for a terminal session
+///
+/// Bells are always reported as `session-activity` events; this turns on
+/// activity-after-quiet and, with `silence_secs`, silence notifications.
 #[tauri::command]
-pub async fn terminal_resize(
+pub async fn set_session_monitor(
     state: State<'_, AppState>,
     session_id: String,
-    cols: u16,
-    rows: u16,
+    activity: bool,
+    silence_secs: Option<u64>,
 ) -> Result<(), String> {
     match state
         .ipc
-        .request(IpcRequest::SessionResize {
+        .request(IpcRequest::SetSessionMonitor {
             session_id,
-            cols,
-            rows,
+            activity,
+            silence_secs,
         })
         .await
     {
         Ok(IpcResponse::Ok) => Ok(()),
         Ok(IpcResponse::Error { message }) => Err(message),
         Ok(_) => Err("Unexpected response from orchestrator".to_string()),
-        Err(e) => Err(format!("Failed to resize terminal: {}", e)),
+        Err(e) => Err(format!("Failed to set session monitor: {}", e)),
     }
 }
 
-/// Close a terminal session
+/// Close a terminal session, terminating the remote shell
 #[tauri::command]
 pub async fn terminal_close(state: State<'_, AppState>, session_id: String) -> Result<(), String> {
     kill_session(state, session_id, false).await
 }
 
+/// Detach from a terminal session, leaving the remote shell running
+///
+/// The session stays listed, marked as detached, and is reattached by
+/// subscribing to it again.
+#[tauri::command]
+pub async fn terminal_detach(state: State<'_, AppState>, session_id: String) -> Result<(), String> {
+    // Output goes to the subscriber connection, so stop it there
+    state
+        .event_subscriber
+        .read()
+        .await
+        .send(IpcRequest::Unsubscribe {
+            session_id: session_id.clone(),
+        })
+        .await
+        .map_err(|e| format!("Failed to unsubscribe from session {}: {}", session_id, e))?;
+
+    match state
+        .ipc
+        .request(IpcRequest::DetachSession {
+            session_id: session_id.clone(),
+        })
+        .await
+    {
+        Ok(IpcResponse::Ok) => Ok(()),
+        Ok(IpcResponse::Error { message }) => Err(message),
+        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
+        Err(e) => Err(format!("Failed to detach session {}: {}", session_id, e)),
+    }
+}
+
 /// Subscribe to a session's events (terminal output)
 #[tauri::command]
 pub async fn subscribe_session(
@@ -369,7 +738,15 @@ pub async fn subscribe_session(
             session_id: session_id.clone(),
         })
         .await
-        .map_err(|e| format!("Failed to subscribe to session {}: {}", session_id, e))
+        .map_err(|e| format!("Failed to subscribe to session {}: {}", session_id, e))?;
+
+    if !state.recents.touch_session(&session_id) {
+        tracing::debug!(
+            "Machine of session {} not known, recents unchanged",
+            session_id
+        );
+    }
+    Ok(())
 }
 
 /// Unsubscribe from a session's events
@@ -386,3 +763,307 @@ pub async fn unsubscribe_session(
         .await
         .map_err(|e| format!("Failed to unsubscribe from session {}: {}", session_id, e))
 }
+
+/// Recent and pinned machines, pinned first, each most recently used first
+///
+/// Entries carry their current connection status; machines the
+/// orchestrator has forgotten are marked stale.
+#[tauri::command]
+pub async fn get_recents(state: State<'_, AppState>) -> Result<Vec<RecentMachinePayload>, String> {
+    Ok(state.recents.recents())
+}
+
+/// Pin a machine to the recents list
+#[tauri::command]
+pub async fn pin_machine(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state.recents.pin(&id)
+}
+
+/// Unpin a machine; it stays in the recents list if it was used
+#[tauri::command]
+pub async fn unpin_machine(state: State<'_, AppState>, id: String) -> Result<(), String> {
+    state.recents.unpin(&id)?;
+    forget_orphaned_prefs(&state);
+    Ok(())
+}
+
+/// Terminal preferences at a scope, with the effective preferences there
+///
+/// For a session scope, the effective preferences are what the session's
+/// terminal should use.
+#[tauri::command]
+pub async fn get_terminal_prefs(
+    state: State<'_, AppState>,
+    scope: PrefsScope,
+) -> Result<TerminalPrefsPayload, String> {
+    terminal_prefs_at(&state, scope)
+}
+
+/// Replace the terminal preferences set at a scope
+///
+/// Unset fields are inherited from the scope below; setting none removes
+/// the scope's entry.
+#[tauri::command]
+pub async fn set_terminal_prefs(
+    state: State<'_, AppState>,
+    scope: PrefsScope,
+    prefs: TerminalPrefsOverrides,
+) -> Result<TerminalPrefsPayload, String> {
+    state.terminal_prefs.set(scope.clone(), prefs)?;
+    terminal_prefs_at(&state, scope)
+}
+
+fn terminal_prefs_at(state: &AppState, scope: PrefsScope) -> Result<TerminalPrefsPayload, String> {
+    let machine_id = match &scope {
+        PrefsScope::Session(session_id) => state.recents.session_machine(session_id),
+        _ => None,
+    };
+    state.terminal_prefs.get(scope, machine_id.as_deref())
+}
+
+/// Drop the terminal preferences of machines that are no longer known or
+/// remembered
+fn forget_orphaned_prefs(state: &AppState) {
+    if let Some(machine_ids) = state.recents.machines_in_use() {
+        state.terminal_prefs.retain_machines(&machine_ids);
+    }
+}
+
+/// Retry authenticating with the orchestrator right away
+///
+/// Called by the UI after an `orchestrator-auth-failed` event, once the user
+/// has fixed the token. Resumes normal-speed reconnects and checks that the
+/// token on disk is now accepted.
+#[tauri::command]
+pub async fn reauthenticate(state: State<'_, AppState>) -> Result<(), String> {
+    state.auth.reset();
+
+    match check_authentication(&PersistentIpcClient::default_address()).await {
+        Ok(()) => Ok(()),
+        Err(e) => match e.downcast_ref::<AuthFailure>() {
+            Some(failure) => Err(format!(
+                "{}. {}",
+                failure.message,
+                failure.reason.guidance()
+            )),
+            None => Err(format!("Failed to reach orchestrator: {}", e)),
+        },
+    }
+}
+
+/// Set the level of orchestrator log lines streamed to the Logs panel
+///
+/// Accepts "error", "warn", "info", "debug", "trace" or "off".
+#[tauri::command]
+pub async fn set_log_level(logs: State<'_, LogControl>, level: String) -> Result<(), String> {
+    logs.set_level(&level)
+}
+
+/// Run `f` on the command palette's view of the current state
+///
+/// Without an orchestrator to ask there are no machines or sessions.
+async fn with_palette_context<R>(state: &AppState, f: impl FnOnce(&PaletteContext<'_>) -> R) -> R {
+    let (machines, sessions) = match fetch_state_snapshot(&state.ipc).await {
+        Ok(snapshot) => (snapshot.machines, snapshot.sessions),
+        Err(e) => {
+            tracing::debug!("Palette without orchestrator state: {}", e);
+            (Vec::new(), Vec::new())
+        }
+    };
+    let context = PaletteContext {
+        mode: state.get_mode().await,
+        embedded_running: state.orchestrator.read().await.is_running(),
+        machines: &machines,
+        sessions: &sessions,
+    };
+    f(&context)
+}
+
+/// Actions for the command palette, computed from the current state
+#[tauri::command]
+pub async fn list_palette_actions(
+    state: State<'_, AppState>,
+) -> Result<Vec<PaletteAction>, String> {
+    Ok(with_palette_context(&state, |context| context.actions()).await)
+}
+
+/// Invoke a command palette action by ID
+///
+/// The action is checked against the current state first, so one listed
+/// before its machine disconnected is refused rather than attempted.
+#[tauri::command]
+pub async fn invoke_palette_action(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<PaletteOutcome, String> {
+    let command =
+        PaletteCommand::parse(&id).ok_or_else(|| format!("Unknown palette action '{}'", id))?;
+    if !with_palette_context(&state, |context| context.is_enabled(&command)).await {
+        return Err(format!(
+            "Palette action '{}' is not available right now",
+            id
+        ));
+    }
+
+    match command {
+        PaletteCommand::NewSession { machine_id } => {
+            let session = create_session(state, machine_id, None).await?;
+            Ok(PaletteOutcome::SessionCreated { session })
+        }
+        PaletteCommand::AttachSession { session_id } => {
+            subscribe_session(state, session_id.clone()).await?;
+            Ok(PaletteOutcome::SessionAttached { session_id })
+        }
+        PaletteCommand::DisconnectMachine { machine_id } => {
+            disconnect_machine(state, machine_id).await?;
+            Ok(PaletteOutcome::Done)
+        }
+        PaletteCommand::StartOrchestrator => {
+            start_orchestrator(state).await?;
+            Ok(PaletteOutcome::Done)
+        }
+        PaletteCommand::StopOrchestrator => {
+            stop_orchestrator(state).await?;
+            Ok(PaletteOutcome::Done)
+        }
+        PaletteCommand::OpenSettings => Ok(PaletteOutcome::OpenSettings),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_session_options_deserialize() {
+        let options: CreateSessionOptions = serde_json::from_str(
+            r#"{"shell":"/bin/zsh","cwd":"/srv/app","env":{"RUST_LOG":"debug"},"name":"build","cols":132,"rows":43}"#,
+        )
+        .unwrap();
+
+        assert_eq!(options.shell.as_deref(), Some("/bin/zsh"));
+        assert_eq!(options.cwd.as_deref(), Some("/srv/app"));
+        assert_eq!(
+            options.env.get("RUST_LOG").map(String::as_str),
+            Some("debug")
+        );
+        assert_eq!(options.name.as_deref(), Some("build"));
+        assert_eq!((options.cols, options.rows), (Some(132), Some(43)));
+
+        // The dialog may send only the fields the user touched
+        let empty: CreateSessionOptions = serde_json::from_str("{}").unwrap();
+        assert!(empty.shell.is_none());
+        assert!(empty.env.is_empty());
+    }
+
+    #[test]
+    fn test_create_session_options_into_request() {
+        let options = CreateSessionOptions {
+            shell: Some(String::new()),
+            cwd: Some("/srv/app".to_string()),
+            env: HashMap::from([
+                ("B".to_string(), "2".to_string()),
+                ("A".to_string(), "1".to_string()),
+            ]),
+            name: None,
+            cols: Some(120),
+            rows: Some(40),
+            term: None,
+            truecolor: None,
+        };
+
+        match options.into_request("machine-1".to_string()).unwrap() {
+            IpcRequest::CreateSession {
+                machine_id,
+                shell,
+                cwd,
+                env,
+                name,
+                size,
+                term,
+                truecolor,
+                ..
+            } => {
+                assert_eq!(term.as_deref(), Some("xterm-256color"));
+                assert!(truecolor);
+                assert_eq!(machine_id, "machine-1");
+                assert!(shell.is_none());
+                assert_eq!(cwd.as_deref(), Some("/srv/app"));
+                assert_eq!(
+                    env,
+                    vec![
+                        ("A".to_string(), "1".to_string()),
+                        ("B".to_string(), "2".to_string()),
+                    ]
+                );
+                assert!(name.is_none());
+                assert_eq!(
+                    size,
+                    Some(TerminalSize {
+                        cols: 120,
+                        rows: 40
+                    })
+                );
+            }
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    #[test]
+    fn test_create_session_options_validation() {
+        let bad_env = CreateSessionOptions {
+            env: HashMap::from([("BAD-NAME".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        assert!(bad_env.into_request("m".to_string()).is_err());
+
+        let bad_size = CreateSessionOptions {
+            cols: Some(0),
+            rows: Some(24),
+            ..Default::default()
+        };
+        assert!(bad_size.into_request("m".to_string()).is_err());
+
+        let half_size = CreateSessionOptions {
+            cols: Some(80),
+            ..Default::default()
+        };
+        assert!(half_size.into_request("m".to_string()).is_err());
+
+        let bad_term = CreateSessionOptions {
+            term: Some("xterm;reboot".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_term.into_request("m".to_string()).is_err());
+
+        let bad_ttl = CreateSessionOptions {
+            ttl_secs: Some(0),
+            ..Default::default()
+        };
+        assert!(bad_ttl.into_request("m".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_session_serializes_size_and_name() {
+        let session = SessionPayload::from(kt_core::ipc::SessionInfo {
+            id: "session-1".to_string(),
+            machine_id: "machine-1".to_string(),
+            shell: Some("/bin/zsh".to_string()),
+            created_at: "0Z".to_string(),
+            pid: None,
+            size: Some(TerminalSize { cols: 80, rows: 24 }),
+            name: Some("build".to_string()),
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        });
+
+        let json = serde_json::to_value(&session).unwrap();
+        assert_eq!(json["machineId"], "machine-1");
+        assert_eq!(json["name"], "build");
+        assert_eq!(json["size"]["cols"], 80);
+        assert_eq!(json["size"]["rows"], 24);
+    }
+}
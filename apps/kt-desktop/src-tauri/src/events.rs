@@ -0,0 +1,283 @@
+//! Forwarding of orchestrator events to the webview
+//!
+//! Events are taken off the subscriber's channel as soon as they arrive and
+//! queued here, so a webview that is slow to take them (e.g. during a heavy
+//! render) doesn't back up the subscriber. Terminal output is what piles up
+//! then, so only [`OUTPUT_QUEUE_CAPACITY`] chunks of it are queued and the
+//! oldest is dropped to make room. Other events are never dropped, as the
+//! frontend's state depends on each of them. Dropped chunks are counted in
+//! [`ForwardStats`].
+//!
+//! An event that fails or panics while being emitted is logged and skipped;
+//! it doesn't stop the events after it.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use kt_core::ipc::IpcEvent;
+use parking_lot::Mutex;
+use tokio::sync::{mpsc, Notify};
+
+/// Most terminal output chunks queued for the webview
+pub const OUTPUT_QUEUE_CAPACITY: usize = 1024;
+
+/// Counters of the event forwarder, kept across restarts
+#[derive(Debug, Default)]
+pub struct ForwardStats {
+    dropped_output: AtomicU64,
+}
+
+impl ForwardStats {
+    /// Terminal output chunks dropped because the webview fell behind
+    pub fn dropped_output(&self) -> u64 {
+        self.dropped_output.load(Ordering::Relaxed)
+    }
+}
+
+/// Events waiting to be emitted, oldest first
+#[derive(Debug)]
+struct EventQueue {
+    events: VecDeque<IpcEvent>,
+    /// Number of `TerminalOutput` events in `events`
+    output_count: usize,
+    output_capacity: usize,
+}
+
+impl EventQueue {
+    fn new(output_capacity: usize) -> Self {
+        Self {
+            events: VecDeque::new(),
+            output_count: 0,
+            output_capacity: output_capacity.max(1),
+        }
+    }
+
+    /// Queue `event`, returning `true` if the oldest terminal output was
+    /// dropped to make room for it
+    fn push(&mut self, event: IpcEvent) -> bool {
+        let mut dropped = false;
+        if is_output(&event) {
+            if self.output_count >= self.output_capacity {
+                if let Some(oldest) = self.events.iter().position(is_output) {
+                    self.events.remove(oldest);
+                    self.output_count -= 1;
+                    dropped = true;
+                }
+            }
+            self.output_count += 1;
+        }
+        self.events.push_back(event);
+        dropped
+    }
+
+    fn pop(&mut self) -> Option<IpcEvent> {
+        let event = self.events.pop_front()?;
+        if is_output(&event) {
+            self.output_count -= 1;
+        }
+        Some(event)
+    }
+}
+
+fn is_output(event: &IpcEvent) -> bool {
+    matches!(event, IpcEvent::TerminalOutput { .. })
+}
+
+/// Queue shared by the task receiving events and the one emitting them
+struct SharedQueue {
+    queue: Mutex<EventQueue>,
+    /// Notified when an event is queued or the receiving task stops
+    ready: Notify,
+    /// Set once the event channel has closed
+    closed: AtomicBool,
+}
+
+/// Forward events from `event_rx` to `emit` until the channel closes
+///
+/// Queues at most `output_capacity` terminal output chunks while `emit`
+/// falls behind, counting the ones dropped in `stats`.
+pub async fn forward_events<F>(
+    mut event_rx: mpsc::Receiver<IpcEvent>,
+    output_capacity: usize,
+    stats: Arc<ForwardStats>,
+    mut emit: F,
+) where
+    F: FnMut(IpcEvent) + Send,
+{
+    let shared = Arc::new(SharedQueue {
+        queue: Mutex::new(EventQueue::new(output_capacity)),
+        ready: Notify::new(),
+        closed: AtomicBool::new(false),
+    });
+
+    let receiver = shared.clone();
+    tokio::spawn(async move {
+        while let Some(event) = event_rx.recv().await {
+            if receiver.queue.lock().push(event) {
+                stats.dropped_output.fetch_add(1, Ordering::Relaxed);
+            }
+            receiver.ready.notify_one();
+        }
+        receiver.closed.store(true, Ordering::SeqCst);
+        receiver.ready.notify_one();
+    });
+
+    loop {
+        // Read before popping, so no event queued before closing is missed
+        let closed = shared.closed.load(Ordering::SeqCst);
+        let event = shared.queue.lock().pop();
+        match event {
+            Some(event) => {
+                if panic::catch_unwind(AssertUnwindSafe(|| emit(event))).is_err() {
+                    tracing::error!("Panic while forwarding an event to the frontend, skipped it");
+                }
+                // Let other tasks run between events of a long backlog
+                tokio::task::yield_now().await;
+            }
+            None if closed => break,
+            None => shared.ready.notified().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn output(n: u8) -> IpcEvent {
+        IpcEvent::TerminalOutput {
+            session_id: "session-1".to_string(),
+            data: vec![n],
+            stream: Default::default(),
+        }
+    }
+
+    fn disconnected(n: u8) -> IpcEvent {
+        IpcEvent::MachineDisconnected {
+            machine_id: format!("machine-{}", n),
+        }
+    }
+
+    /// Short name of an event built by `output` or `disconnected`
+    fn label(event: &IpcEvent) -> String {
+        match event {
+            IpcEvent::TerminalOutput { data, .. } => format!("output-{}", data[0]),
+            IpcEvent::MachineDisconnected { machine_id } => machine_id.clone(),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    /// Forward `events` to an emitter that takes `delay` per event,
+    /// returning the labels of what it received
+    async fn forward_slowly(
+        events: Vec<IpcEvent>,
+        capacity: usize,
+        stats: Arc<ForwardStats>,
+        delay: Duration,
+    ) -> Vec<String> {
+        let (tx, rx) = mpsc::channel(events.len().max(1));
+        for event in events {
+            tx.send(event).await.unwrap();
+        }
+        drop(tx);
+
+        let mut emitted = Vec::new();
+        forward_events(rx, capacity, stats, |event| {
+            std::thread::sleep(delay);
+            emitted.push(label(&event));
+        })
+        .await;
+        emitted
+    }
+
+    #[test]
+    fn test_queue_drops_oldest_output_only() {
+        let mut queue = EventQueue::new(2);
+        assert!(!queue.push(output(1)));
+        assert!(!queue.push(disconnected(1)));
+        assert!(!queue.push(output(2)));
+        assert!(queue.push(output(3)));
+        assert!(!queue.push(disconnected(2)));
+        assert!(!queue.push(disconnected(3)));
+
+        let mut popped = Vec::new();
+        while let Some(event) = queue.pop() {
+            popped.push(label(&event));
+        }
+        assert_eq!(
+            popped,
+            [
+                "machine-1",
+                "output-2",
+                "output-3",
+                "machine-2",
+                "machine-3"
+            ]
+        );
+        assert_eq!(queue.output_count, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_slow_emitter_drops_output_but_not_other_events() {
+        let mut events = Vec::new();
+        for i in 0..100u8 {
+            events.push(output(i));
+            if i % 10 == 0 {
+                events.push(disconnected(i));
+            }
+        }
+        let stats = Arc::new(ForwardStats::default());
+        let delay = Duration::from_millis(2);
+        let emitted = forward_slowly(events, 4, stats.clone(), delay).await;
+
+        let (outputs, others): (Vec<_>, Vec<_>) =
+            emitted.into_iter().partition(|l| l.starts_with("output-"));
+        let expected: Vec<_> = (0..100)
+            .step_by(10)
+            .map(|i| format!("machine-{}", i))
+            .collect();
+        assert_eq!(others, expected);
+        assert!(stats.dropped_output() > 0);
+        assert_eq!(outputs.len() as u64 + stats.dropped_output(), 100);
+
+        // What is left of the output keeps its order and ends with the latest
+        let numbers: Vec<u8> = outputs.iter().map(|l| l[7..].parse().unwrap()).collect();
+        assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(numbers.last(), Some(&99));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_fast_emitter_drops_nothing() {
+        let events: Vec<_> = (0..50).map(output).collect();
+        let expected: Vec<_> = events.iter().map(label).collect();
+        let stats = Arc::new(ForwardStats::default());
+        let emitted = forward_slowly(events, 64, stats.clone(), Duration::ZERO).await;
+        assert_eq!(emitted, expected);
+        assert_eq!(stats.dropped_output(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_panicking_emit_skips_only_that_event() {
+        let (tx, rx) = mpsc::channel(8);
+        for i in 0..3 {
+            tx.send(disconnected(i)).await.unwrap();
+        }
+        drop(tx);
+
+        let mut emitted = Vec::new();
+        let stats = Arc::new(ForwardStats::default());
+        forward_events(rx, 4, stats, |event| {
+            let label = label(&event);
+            if label == "machine-1" {
+                panic!("emit failed");
+            }
+            emitted.push(label);
+        })
+        .await;
+        assert_eq!(emitted, ["machine-0", "machine-2"]);
+    }
+}
@@ -0,0 +1,213 @@
+//! Confirmation before typing into a session this app didn't create
+//!
+//! Sessions can be adopted from or shared by other clients, and a keystroke
+//! meant for one of ours runs in someone else's shell if it lands in the
+//! wrong pane. So the first input to a session this app run didn't create is
+//! held back: `terminal_write` answers [`WriteOutcome::NeedsConfirmation`]
+//! without sending anything, the frontend asks, and
+//! `confirm_session_input` lets input through for the rest of the run.
+//! Sessions created here are confirmed as they are created. The
+//! `confirmForeignInput` terminal preference turns the check off, globally
+//! or per machine or session.
+//!
+//! Confirmations are kept in memory only, and dropped when their session
+//! closes or the orchestrator stops reporting it.
+
+use std::collections::HashSet;
+use std::future::Future;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use kt_core::ipc::{IpcEvent, IpcRequest, IpcResponse};
+
+use crate::commands::SessionPayload;
+
+/// What became of input given to `terminal_write` (`WriteOutcome` in
+/// `src/types/index.ts`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum WriteOutcome {
+    /// Sent to the session
+    Sent,
+    /// Not sent: ask the user, then `confirm_session_input` and write again
+    NeedsConfirmation,
+}
+
+/// Sessions input may be sent to without asking
+#[derive(Debug, Default)]
+pub struct InputConfirmations {
+    confirmed: Mutex<HashSet<String>>,
+}
+
+impl InputConfirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let input through to `session_id` from now on
+    pub fn confirm(&self, session_id: &str) {
+        self.confirmed.lock().insert(session_id.to_string());
+    }
+
+    /// Whether input to `session_id` has to be confirmed first
+    ///
+    /// `enabled` reads the session's `confirmForeignInput` preference; it's
+    /// only consulted for sessions not confirmed yet.
+    pub fn needs_confirmation(&self, session_id: &str, enabled: impl FnOnce() -> bool) -> bool {
+        !self.confirmed.lock().contains(session_id) && enabled()
+    }
+
+    /// Drop the confirmations of sessions missing from a full list from the
+    /// orchestrator
+    pub fn retain_sessions(&self, sessions: &[SessionPayload]) {
+        let ids: HashSet<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+        self.confirmed.lock().retain(|id| ids.contains(id.as_str()));
+    }
+
+    /// Drop a session's confirmation once it closes
+    pub fn apply_event(&self, event: &IpcEvent) {
+        if let IpcEvent::SessionClosed { session_id, .. } = event {
+            self.confirmed.lock().remove(session_id);
+        }
+    }
+}
+
+/// Send `data` to a session through `send`, unless it needs confirmation
+pub async fn write_input<F, Fut>(
+    needs_confirmation: bool,
+    session_id: String,
+    data: Vec<u8>,
+    send: F,
+) -> Result<WriteOutcome, String>
+where
+    F: FnOnce(IpcRequest) -> Fut,
+    Fut: Future<Output = anyhow::Result<IpcResponse>>,
+{
+    if needs_confirmation {
+        return Ok(WriteOutcome::NeedsConfirmation);
+    }
+    match send(IpcRequest::SessionInput { session_id, data }).await {
+        Ok(IpcResponse::Ok) => Ok(WriteOutcome::Sent),
+        Ok(IpcResponse::Error { message }) => Err(message),
+        Ok(IpcResponse::InputHeld { holder, .. }) => Err(format!("Input is held by {}", holder)),
+        Ok(_) => Err("Unexpected response from orchestrator".to_string()),
+        Err(e) => Err(format!("Failed to write to terminal: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the IPC client, recording the input it's asked to send
+    #[derive(Default)]
+    struct MockIpc {
+        sent: Mutex<Vec<(String, Vec<u8>)>>,
+    }
+
+    impl MockIpc {
+        async fn request(&self, request: IpcRequest) -> anyhow::Result<IpcResponse> {
+            let IpcRequest::SessionInput { session_id, data } = request else {
+                anyhow::bail!("unexpected request {:?}", request);
+            };
+            self.sent.lock().push((session_id, data));
+            Ok(IpcResponse::Ok)
+        }
+    }
+
+    async fn write(
+        confirmations: &InputConfirmations,
+        ipc: &MockIpc,
+        session_id: &str,
+        enabled: bool,
+    ) -> WriteOutcome {
+        let needs_confirmation = confirmations.needs_confirmation(session_id, || enabled);
+        write_input(
+            needs_confirmation,
+            session_id.to_string(),
+            b"ls\r".to_vec(),
+            |request| ipc.request(request),
+        )
+        .await
+        .unwrap()
+    }
+
+    fn closed(session_id: &str) -> IpcEvent {
+        IpcEvent::SessionClosed {
+            session_id: session_id.to_string(),
+            exit_code: Some(0),
+            reason: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_foreign_session_input_held_until_confirmed() {
+        let confirmations = InputConfirmations::new();
+        let ipc = MockIpc::default();
+
+        // Adopted or shared: nothing is sent until the user confirms
+        assert_eq!(
+            write(&confirmations, &ipc, "s-1", true).await,
+            WriteOutcome::NeedsConfirmation
+        );
+        assert!(ipc.sent.lock().is_empty());
+
+        confirmations.confirm("s-1");
+        assert_eq!(
+            write(&confirmations, &ipc, "s-1", true).await,
+            WriteOutcome::Sent
+        );
+        assert_eq!(
+            write(&confirmations, &ipc, "s-1", true).await,
+            WriteOutcome::Sent
+        );
+        assert_eq!(ipc.sent.lock().len(), 2);
+        assert_eq!(ipc.sent.lock()[0], ("s-1".to_string(), b"ls\r".to_vec()));
+
+        // With the preference off nothing is held back
+        assert_eq!(
+            write(&confirmations, &ipc, "s-2", false).await,
+            WriteOutcome::Sent
+        );
+        assert_eq!(ipc.sent.lock().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_confirmations_dropped_with_their_sessions() {
+        let confirmations = InputConfirmations::new();
+        let ipc = MockIpc::default();
+        confirmations.confirm("s-1");
+        confirmations.confirm("s-2");
+
+        confirmations.apply_event(&closed("s-1"));
+        assert_eq!(
+            write(&confirmations, &ipc, "s-1", true).await,
+            WriteOutcome::NeedsConfirmation
+        );
+
+        // A full list without the session drops it too
+        confirmations.retain_sessions(&[]);
+        assert_eq!(
+            write(&confirmations, &ipc, "s-2", true).await,
+            WriteOutcome::NeedsConfirmation
+        );
+        assert!(ipc.sent.lock().is_empty());
+    }
+
+    #[test]
+    fn test_preference_only_read_for_unconfirmed_sessions() {
+        let confirmations = InputConfirmations::new();
+        confirmations.confirm("mine");
+        assert!(!confirmations.needs_confirmation("mine", || panic!("read the preference")));
+        assert!(confirmations.needs_confirmation("theirs", || true));
+    }
+
+    #[test]
+    fn test_outcome_serialization() {
+        assert_eq!(
+            serde_json::to_string(&WriteOutcome::NeedsConfirmation).unwrap(),
+            r#"{"kind":"needsConfirmation"}"#
+        );
+    }
+}
@@ -7,20 +7,30 @@
 //!
 //! The `EventSubscriber` tracks sequence numbers from `IpcEventEnvelope` messages
 //! to detect gaps (missing events). If a gap is detected, the client will request
-//! a state snapshot to recover.
+//! a state snapshot to recover, [`SNAPSHOT_PAGE_LIMIT`] machines at a time.
+//!
+//! ## Authentication Failures
+//!
+//! The token is re-read from disk on every connection attempt, so a token that
+//! rotated because the orchestrator was restarted is picked up automatically.
+//! Both connections report token problems to a shared `AuthMonitor`, which
+//! notifies the frontend after `AUTH_FAILURE_THRESHOLD` consecutive failures
+//! and slows retries down until the user calls `reauthenticate`.
 
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use parking_lot::RwLock;
+use serde::Serialize;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, Mutex, oneshot};
+use tokio::sync::{broadcast, mpsc, Mutex, Notify, oneshot};
 use tokio_util::sync::CancellationToken;
 
-use kt_core::ipc::{IpcEvent, IpcEventEnvelope, IpcRequest, IpcResponse};
+use kt_core::ipc::{IpcEvent, IpcEventEnvelope, IpcRequest, IpcResponse, OutputStream};
 use kt_core::read_ipc_token;
 
 /// Default IPC port
@@ -31,10 +41,188 @@ pub fn default_ipc_address() -> String {
     format!("127.0.0.1:{}", DEFAULT_IPC_PORT)
 }
 
+/// Most machines asked for per state snapshot page
+pub const SNAPSHOT_PAGE_LIMIT: usize = 50;
+
+/// Request the state snapshot page after `cursor` (None = first page)
+pub fn snapshot_page_request(cursor: Option<String>) -> IpcRequest {
+    IpcRequest::GetStateSnapshot {
+        machine_filter: None,
+        include_sessions: true,
+        cursor,
+        limit: Some(SNAPSHOT_PAGE_LIMIT),
+    }
+}
+
+/// Consecutive authentication failures before the frontend is notified
+pub const AUTH_FAILURE_THRESHOLD: u32 = 3;
+
+/// Delay between connection attempts
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Delay between connection attempts once authentication keeps failing
+///
+/// Retrying a rejected token every 2 seconds only fills the orchestrator's
+/// auth rate limiter, so back off until the user fixes things.
+const AUTH_FAILED_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Why authenticating with the orchestrator failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthFailureReason {
+    /// The token file does not exist or could not be read
+    TokenMissing,
+    /// The orchestrator rejected the token
+    TokenRejected,
+}
+
+impl AuthFailureReason {
+    /// What the user can do about this failure
+    pub fn guidance(&self) -> &'static str {
+        match self {
+            AuthFailureReason::TokenMissing => {
+                "The IPC token file was not found. Start the orchestrator \
+                 (`k-terminus serve`) or restart k-Terminus, then retry."
+            }
+            AuthFailureReason::TokenRejected => {
+                "The orchestrator rejected the IPC token. It was probably restarted \
+                 with a new token by another process; retry, or restart the orchestrator."
+            }
+        }
+    }
+}
+
+/// An authentication attempt that failed because of the token, as opposed
+/// to the orchestrator being unreachable
+#[derive(Debug, Clone)]
+pub struct AuthFailure {
+    pub reason: AuthFailureReason,
+    pub message: String,
+}
+
+impl std::fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for AuthFailure {}
+
+/// Notification sent when authentication has failed `AUTH_FAILURE_THRESHOLD` times in a row
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthFailedNotice {
+    pub reason: AuthFailureReason,
+    pub message: String,
+    pub guidance: String,
+    pub attempts: u32,
+}
+
+/// Tracks consecutive authentication failures across IPC connections
+///
+/// Shared by the `PersistentIpcClient` and the `EventSubscriber` so that one
+/// notice is sent per failure streak, however many connections are retrying.
+pub struct AuthMonitor {
+    consecutive_failures: AtomicU32,
+    notice_tx: broadcast::Sender<AuthFailedNotice>,
+    /// Wakes connection loops out of the slow retry delay
+    retry_now: Notify,
+}
+
+impl AuthMonitor {
+    pub fn new() -> Self {
+        let (notice_tx, _) = broadcast::channel(16);
+        Self {
+            consecutive_failures: AtomicU32::new(0),
+            notice_tx,
+            retry_now: Notify::new(),
+        }
+    }
+
+    /// Subscribe to auth-failed notices
+    pub fn subscribe(&self) -> broadcast::Receiver<AuthFailedNotice> {
+        self.notice_tx.subscribe()
+    }
+
+    /// Record a successful authentication, ending any failure streak
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+    }
+
+    /// Record a failed authentication
+    ///
+    /// Sends a notice when the streak reaches `AUTH_FAILURE_THRESHOLD`.
+    pub fn record_failure(&self, failure: &AuthFailure) {
+        let attempts = self.consecutive_failures.fetch_add(1, Ordering::SeqCst) + 1;
+        tracing::warn!(
+            "IPC authentication failed ({} in a row): {}",
+            attempts,
+            failure.message
+        );
+
+        if attempts == AUTH_FAILURE_THRESHOLD {
+            // No receivers just means the frontend isn't listening yet
+            let _ = self.notice_tx.send(AuthFailedNotice {
+                reason: failure.reason,
+                message: failure.message.clone(),
+                guidance: failure.reason.guidance().to_string(),
+                attempts,
+            });
+        }
+    }
+
+    /// Delay before the next connection attempt
+    pub fn retry_delay(&self) -> Duration {
+        if self.consecutive_failures.load(Ordering::SeqCst) >= AUTH_FAILURE_THRESHOLD {
+            AUTH_FAILED_RETRY_DELAY
+        } else {
+            RETRY_DELAY
+        }
+    }
+
+    /// Clear the failure streak and wake all connection loops to retry now
+    pub fn reset(&self) {
+        self.consecutive_failures.store(0, Ordering::SeqCst);
+        self.retry_now.notify_waiters();
+    }
+
+    /// Wait before the next connection attempt
+    ///
+    /// Returns `false` if cancelled while waiting.
+    async fn wait_before_retry(&self, cancel: &CancellationToken) -> bool {
+        tokio::select! {
+            _ = tokio::time::sleep(self.retry_delay()) => true,
+            _ = self.retry_now.notified() => true,
+            _ = cancel.cancelled() => false,
+        }
+    }
+
+    /// Record the outcome of a failed connection attempt
+    ///
+    /// Only token problems count towards the streak; an unreachable
+    /// orchestrator is not an authentication failure.
+    fn record_connect_error(&self, error: &anyhow::Error) {
+        match error.downcast_ref::<AuthFailure>() {
+            Some(failure) => self.record_failure(failure),
+            None => tracing::debug!("Failed to connect to orchestrator: {}", error),
+        }
+    }
+}
+
+impl Default for AuthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Internal connection state
-struct Connection {
-    reader: BufReader<OwnedReadHalf>,
-    writer: OwnedWriteHalf,
+pub(crate) struct Connection {
+    pub(crate) reader: BufReader<OwnedReadHalf>,
+    pub(crate) writer: OwnedWriteHalf,
+    /// Epoch ID reported when authenticating
+    epoch_id: String,
+    /// Sequence number reported when authenticating
+    current_seq: u64,
 }
 
 /// Persistent IPC client that maintains a single connection for all requests
@@ -56,6 +244,8 @@ pub struct PersistentIpcClient {
     request_rx: std::sync::Mutex<Option<mpsc::Receiver<(IpcRequest, oneshot::Sender<IpcResponse>)>>>,
     /// Cancellation token for shutdown
     cancel: CancellationToken,
+    /// Shared authentication failure tracking
+    auth: Arc<AuthMonitor>,
 }
 
 impl PersistentIpcClient {
@@ -77,9 +267,16 @@ impl PersistentIpcClient {
             request_tx,
             request_rx: std::sync::Mutex::new(Some(request_rx)),
             cancel,
+            auth: Arc::new(AuthMonitor::new()),
         }
     }
 
+    /// Report authentication failures to a shared monitor
+    pub fn with_auth_monitor(mut self, auth: Arc<AuthMonitor>) -> Self {
+        self.auth = auth;
+        self
+    }
+
     /// Ensure the connection loop is running
     fn ensure_started(&self) {
         // Take the receiver if we have it (only happens once)
@@ -88,9 +285,10 @@ impl PersistentIpcClient {
             let addr = self.address.clone();
             let cid = self.client_id.clone();
             let cancel_clone = self.cancel.clone();
+            let auth = self.auth.clone();
 
             tokio::spawn(async move {
-                connection_loop(addr, cid, request_rx, cancel_clone).await;
+                connection_loop(addr, cid, request_rx, cancel_clone, auth).await;
             });
         }
     }
@@ -115,9 +313,14 @@ impl PersistentIpcClient {
             .await
             .map_err(|_| anyhow::anyhow!("IPC connection task is not running"))?;
 
-        response_rx
+        match response_rx
             .await
-            .map_err(|_| anyhow::anyhow!("IPC connection dropped before response"))
+            .map_err(|_| anyhow::anyhow!("IPC connection dropped before response"))?
+        {
+            // Surface rate limiting as an error so every command reports it
+            IpcResponse::RateLimited { message, .. } => Err(anyhow::anyhow!(message)),
+            response => Ok(response),
+        }
     }
 
     /// Check if orchestrator is running by sending a ping
@@ -143,6 +346,7 @@ async fn connection_loop(
     client_id: String,
     mut request_rx: mpsc::Receiver<(IpcRequest, oneshot::Sender<IpcResponse>)>,
     cancel: CancellationToken,
+    auth: Arc<AuthMonitor>,
 ) {
     loop {
         if cancel.is_cancelled() {
@@ -150,15 +354,17 @@ async fn connection_loop(
             break;
         }
 
-        // Try to connect and authenticate
-        let conn = match connect_and_authenticate(&address, &client_id).await {
-            Ok(c) => c,
+        // Try to connect and authenticate (re-reads the token every attempt)
+        let conn = match connect_and_authenticate(&address, Some(&client_id)).await {
+            Ok(c) => {
+                auth.record_success();
+                c
+            }
             Err(e) => {
-                tracing::debug!("Failed to connect to orchestrator: {}", e);
+                auth.record_connect_error(&e);
                 // Wait before retry, but check for cancellation
-                tokio::select! {
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(2)) => {}
-                    _ = cancel.cancelled() => break,
+                if !auth.wait_before_retry(&cancel).await {
+                    break;
                 }
                 continue;
             }
@@ -184,7 +390,15 @@ async fn connection_loop(
 }
 
 /// Connect to the orchestrator and authenticate
-async fn connect_and_authenticate(address: &str, client_id: &str) -> Result<Connection> {
+///
+/// The token is read from disk on every call. If it is rejected and the token
+/// file has changed in the meantime (the orchestrator rotated it), the new
+/// token is tried once on the same connection. Token problems are returned as
+/// an `AuthFailure` so callers can tell them apart from connection errors.
+pub(crate) async fn connect_and_authenticate(
+    address: &str,
+    client_id: Option<&str>,
+) -> Result<Connection> {
     let stream = TcpStream::connect(address)
         .await
         .with_context(|| format!("Failed to connect to orchestrator at {}", address))?;
@@ -192,46 +406,84 @@ async fn connect_and_authenticate(address: &str, client_id: &str) -> Result<Conn
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
 
-    // Read token for authentication
-    let token = read_ipc_token().with_context(|| "Failed to read IPC authentication token")?;
-
-    tracing::debug!(
-        "Authenticating with IPC token: {}...{} (client_id: {})",
-        &token[..8],
-        &token[token.len() - 8..],
-        client_id
-    );
-
-    // Authenticate with client_id for session ownership
-    let auth_request = IpcRequest::Authenticate {
-        token,
-        client_id: Some(client_id.to_string()),
-    };
-    let mut auth_json = serde_json::to_string(&auth_request)?;
-    auth_json.push('\n');
-    writer.write_all(auth_json.as_bytes()).await?;
-
-    // Read auth response
-    let mut line = String::new();
-    reader.read_line(&mut line).await?;
-
-    let auth_response: IpcResponse = serde_json::from_str(line.trim())?;
-    match auth_response {
-        IpcResponse::Authenticated { epoch_id, current_seq } => {
-            tracing::debug!(
-                "IPC authentication successful (client_id: {}, epoch: {}, seq: {})",
-                client_id, epoch_id, current_seq
-            );
-        }
-        IpcResponse::Error { message } => {
-            return Err(anyhow::anyhow!("Authentication failed: {}", message));
+    let mut token = read_auth_token()?;
+    let mut retried = false;
+    loop {
+        tracing::debug!("Authenticating with IPC token (client_id: {:?})", client_id);
+
+        // Authenticate with client_id for session ownership
+        let auth_request = IpcRequest::Authenticate {
+            token: token.clone(),
+            client_id: client_id.map(String::from),
+            instance_id: Some(kt_core::ipc::client_instance_id().to_string()),
+            force_takeover: false,
+            close_on_disconnect: false,
+        };
+        let mut auth_json = serde_json::to_string(&auth_request)?;
+        auth_json.push('\n');
+        writer.write_all(auth_json.as_bytes()).await?;
+
+        // Read auth response
+        let mut line = String::new();
+        reader.read_line(&mut line).await?;
+
+        if line.is_empty() {
+            return Err(anyhow::anyhow!("Disconnected during authentication"));
         }
-        other => {
-            return Err(anyhow::anyhow!("Unexpected auth response: {:?}", other));
+
+        let auth_response: IpcResponse = serde_json::from_str(line.trim())?;
+        match auth_response {
+            IpcResponse::Authenticated { epoch_id, current_seq, .. } => {
+                tracing::debug!(
+                    "IPC authentication successful (client_id: {:?}, epoch: {}, seq: {})",
+                    client_id, epoch_id, current_seq
+                );
+                return Ok(Connection {
+                    reader,
+                    writer,
+                    epoch_id,
+                    current_seq,
+                });
+            }
+            IpcResponse::Error { message } => {
+                if !retried {
+                    if let Ok(fresh) = read_auth_token() {
+                        if fresh != token {
+                            tracing::debug!("IPC token changed, retrying authentication");
+                            token = fresh;
+                            retried = true;
+                            continue;
+                        }
+                    }
+                }
+                return Err(AuthFailure {
+                    reason: AuthFailureReason::TokenRejected,
+                    message: format!("Authentication failed: {}", message),
+                }
+                .into());
+            }
+            other => return Err(anyhow::anyhow!("Unexpected auth response: {:?}", other)),
         }
     }
+}
 
-    Ok(Connection { reader, writer })
+/// Read the IPC token, reporting a missing token as an `AuthFailure`
+fn read_auth_token() -> Result<String> {
+    read_ipc_token().map_err(|e| {
+        AuthFailure {
+            reason: AuthFailureReason::TokenMissing,
+            message: format!("Failed to read IPC authentication token: {}", e),
+        }
+        .into()
+    })
+}
+
+/// Check that the current token is accepted by the orchestrator
+///
+/// Uses a throwaway connection without a client ID so it doesn't touch
+/// session ownership.
+pub async fn check_authentication(address: &str) -> Result<()> {
+    connect_and_authenticate(address, None).await.map(|_| ())
 }
 
 /// Handle requests on an established connection
@@ -332,6 +584,8 @@ pub struct EventSubscriber {
     last_seen_seq: Arc<AtomicU64>,
     /// Current epoch ID (changes on orchestrator restart)
     epoch_id: Arc<RwLock<Option<String>>>,
+    /// Shared authentication failure tracking
+    auth: Arc<AuthMonitor>,
 }
 
 impl EventSubscriber {
@@ -343,6 +597,7 @@ impl EventSubscriber {
             cancel: CancellationToken::new(),
             last_seen_seq: Arc::new(AtomicU64::new(0)),
             epoch_id: Arc::new(RwLock::new(None)),
+            auth: Arc::new(AuthMonitor::new()),
         }
     }
 
@@ -352,6 +607,12 @@ impl EventSubscriber {
         self
     }
 
+    /// Report authentication failures to a shared monitor
+    pub fn with_auth_monitor(mut self, auth: Arc<AuthMonitor>) -> Self {
+        self.auth = auth;
+        self
+    }
+
     /// Get the current epoch ID
     pub fn epoch_id(&self) -> Option<String> {
         self.epoch_id.read().clone()
@@ -401,9 +662,10 @@ impl EventSubscriber {
         let cancel = self.cancel.clone();
         let last_seen_seq = self.last_seen_seq.clone();
         let epoch_id = self.epoch_id.clone();
+        let auth = self.auth.clone();
 
         tokio::spawn(async move {
-            event_loop(address, client_id, event_tx, request_rx, cancel, last_seen_seq, epoch_id).await;
+            event_loop(address, client_id, event_tx, request_rx, cancel, last_seen_seq, epoch_id, auth).await;
         });
 
         event_rx
@@ -424,9 +686,18 @@ impl EventSubscriber {
     pub fn stop(&self) {
         self.cancel.cancel();
     }
+
+    /// Stop the event subscriber if it was started, and start it again on a
+    /// new connection, returning a receiver for its events
+    pub fn restart(&mut self) -> mpsc::Receiver<IpcEvent> {
+        self.stop();
+        self.cancel = CancellationToken::new();
+        self.start()
+    }
 }
 
 /// Internal event loop for the persistent connection
+#[allow(clippy::too_many_arguments)]
 async fn event_loop(
     address: String,
     client_id: Option<String>,
@@ -435,6 +706,7 @@ async fn event_loop(
     cancel: CancellationToken,
     last_seen_seq: Arc<AtomicU64>,
     epoch_id: Arc<RwLock<Option<String>>>,
+    auth: Arc<AuthMonitor>,
 ) {
     loop {
         if cancel.is_cancelled() {
@@ -442,15 +714,17 @@ async fn event_loop(
             break;
         }
 
-        // Try to connect
-        let stream = match TcpStream::connect(&address).await {
-            Ok(s) => s,
+        // Connect and authenticate (re-reads the token every attempt)
+        let conn = match connect_and_authenticate(&address, client_id.as_deref()).await {
+            Ok(c) => {
+                auth.record_success();
+                c
+            }
             Err(e) => {
-                tracing::debug!("Failed to connect to orchestrator: {}", e);
+                auth.record_connect_error(&e);
                 // Wait before retry
-                tokio::select! {
-                    _ = tokio::time::sleep(tokio::time::Duration::from_secs(2)) => {}
-                    _ = cancel.cancelled() => break,
+                if !auth.wait_before_retry(&cancel).await {
+                    break;
                 }
                 continue;
             }
@@ -458,100 +732,53 @@ async fn event_loop(
 
         tracing::info!("Connected to orchestrator for events");
 
-        let (reader, writer) = stream.into_split();
-        let mut reader = BufReader::new(reader);
+        let Connection {
+            mut reader,
+            writer,
+            epoch_id: new_epoch,
+            current_seq,
+        } = conn;
         let writer = Arc::new(Mutex::new(writer));
         let mut line = String::new();
 
-        // Authenticate with the orchestrator
-        let token = match read_ipc_token() {
-            Ok(t) => {
-                tracing::debug!(
-                    "Read IPC token for authentication: {}...{}",
-                    &t[..std::cmp::min(8, t.len())],
-                    if t.len() > 8 { &t[t.len()-8..] } else { "" }
+        // Check if epoch changed (orchestrator restarted)
+        let old_epoch = epoch_id.read().clone();
+        if let Some(ref old) = old_epoch {
+            if old != &new_epoch {
+                tracing::warn!(
+                    "Orchestrator epoch changed: {} -> {} (restart detected)",
+                    old, new_epoch
                 );
-                t
+                // Reset sequence tracking - full resync needed
+                last_seen_seq.store(0, Ordering::SeqCst);
             }
-            Err(e) => {
-                tracing::error!("Failed to read IPC token: {}", e);
-                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                continue;
-            }
-        };
-
-        // Send auth request with client_id if available
-        let auth_request = IpcRequest::Authenticate {
-            token,
-            client_id: client_id.clone(),
-        };
-        if let Err(e) = send_request(&writer, auth_request).await {
-            tracing::error!("Failed to send auth request: {}", e);
-            continue;
         }
 
-        // Read auth response
-        match reader.read_line(&mut line).await {
-            Ok(0) => {
-                tracing::warn!("Disconnected during authentication");
-                continue;
-            }
-            Ok(_) => {
-                match serde_json::from_str::<IpcResponse>(line.trim()) {
-                    Ok(IpcResponse::Authenticated { epoch_id: new_epoch, current_seq }) => {
-                        // Check if epoch changed (orchestrator restarted)
-                        let old_epoch = epoch_id.read().clone();
-                        if let Some(ref old) = old_epoch {
-                            if old != &new_epoch {
-                                tracing::warn!(
-                                    "Orchestrator epoch changed: {} -> {} (restart detected)",
-                                    old, new_epoch
-                                );
-                                // Reset sequence tracking - full resync needed
-                                last_seen_seq.store(0, Ordering::SeqCst);
-                            }
-                        }
-
-                        // Store new epoch and sequence
-                        *epoch_id.write() = Some(new_epoch.clone());
-                        last_seen_seq.store(current_seq, Ordering::SeqCst);
+        // Store new epoch and sequence
+        *epoch_id.write() = Some(new_epoch.clone());
+        last_seen_seq.store(current_seq, Ordering::SeqCst);
 
-                        tracing::debug!(
-                            "Authenticated with orchestrator (client_id: {:?}, epoch: {}, seq: {})",
-                            client_id, new_epoch, current_seq
-                        );
-                    }
-                    Ok(other) => {
-                        tracing::error!("Authentication failed: {:?}", other);
-                        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                        continue;
-                    }
-                    Err(e) => {
-                        tracing::error!("Failed to parse auth response: {}", e);
-                        continue;
-                    }
-                }
-                line.clear();
-            }
-            Err(e) => {
-                tracing::error!("Failed to read auth response: {}", e);
-                continue;
-            }
-        }
+        tracing::debug!(
+            "Authenticated with orchestrator (client_id: {:?}, epoch: {}, seq: {})",
+            client_id, new_epoch, current_seq
+        );
 
         // Track if we need recovery
         let mut needs_recovery = false;
+        // Set while later pages of the recovery snapshot are being fetched
+        let mut paging_snapshot = false;
 
         // Process messages until disconnection
         loop {
             // If recovery is needed, request state snapshot
             if needs_recovery {
                 tracing::info!("Requesting state snapshot for recovery");
-                if let Err(e) = send_request(&writer, IpcRequest::GetStateSnapshot).await {
+                if let Err(e) = send_request(&writer, snapshot_page_request(None)).await {
                     tracing::warn!("Failed to request state snapshot: {}", e);
                     break; // Reconnect
                 }
                 needs_recovery = false;
+                paging_snapshot = false;
             }
 
             tokio::select! {
@@ -590,15 +817,18 @@ async fn event_loop(
                                 } else if let Ok(response) = serde_json::from_str::<IpcResponse>(trimmed) {
                                     // Handle state snapshot response for recovery
                                     match response {
-                                        IpcResponse::StateSnapshot { epoch_id: snap_epoch, current_seq, machines, sessions } => {
+                                        IpcResponse::StateSnapshot { epoch_id: snap_epoch, current_seq, machines, sessions, next_cursor } => {
                                             tracing::info!(
                                                 "Received state snapshot: epoch={}, seq={}, {} machines, {} sessions",
                                                 snap_epoch, current_seq, machines.len(), sessions.len()
                                             );
 
-                                            // Update epoch and sequence
-                                            *epoch_id.write() = Some(snap_epoch);
-                                            last_seen_seq.store(current_seq, Ordering::SeqCst);
+                                            // Update epoch and sequence from the first page; the
+                                            // events after it cover changes made while paging
+                                            if !paging_snapshot {
+                                                *epoch_id.write() = Some(snap_epoch);
+                                                last_seen_seq.store(current_seq, Ordering::SeqCst);
+                                            }
 
                                             // Emit synthetic events for current state
                                             for machine in machines {
@@ -615,6 +845,15 @@ async fn event_loop(
                                                     return;
                                                 }
                                             }
+
+                                            paging_snapshot = next_cursor.is_some();
+                                            if let Some(cursor) = next_cursor {
+                                                let request = snapshot_page_request(Some(cursor));
+                                                if let Err(e) = send_request(&writer, request).await {
+                                                    tracing::warn!("Failed to request state snapshot page: {}", e);
+                                                    break; // Reconnect
+                                                }
+                                            }
                                         }
                                         IpcResponse::EventsSince { events, truncated, oldest_available_seq } => {
                                             if truncated {
@@ -634,6 +873,19 @@ async fn event_loop(
                                                 }
                                             }
                                         }
+                                        // The attach message is shown in the terminal ahead of the
+                                        // session's output, which follows this response
+                                        IpcResponse::Subscribed { session, motd: Some(motd), .. } => {
+                                            let event = IpcEvent::TerminalOutput {
+                                                session_id: session.id,
+                                                data: motd.into_bytes(),
+                                                stream: OutputStream::Stdout,
+                                            };
+                                            if event_tx.send(event).await.is_err() {
+                                                tracing::warn!("Event channel closed");
+                                                return;
+                                            }
+                                        }
                                         _ => {
                                             // Other responses - we don't need to forward these
                                             // since the persistent client handles request/response
@@ -679,3 +931,88 @@ async fn send_request(writer: &Arc<Mutex<OwnedWriteHalf>>, request: IpcRequest)
     writer.write_all(json.as_bytes()).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejected() -> AuthFailure {
+        AuthFailure {
+            reason: AuthFailureReason::TokenRejected,
+            message: "Authentication failed: Invalid authentication token".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_auth_monitor_notifies_once_at_threshold() {
+        let monitor = AuthMonitor::new();
+        let mut rx = monitor.subscribe();
+
+        for _ in 0..AUTH_FAILURE_THRESHOLD - 1 {
+            monitor.record_failure(&rejected());
+        }
+        assert!(rx.try_recv().is_err());
+        assert_eq!(monitor.retry_delay(), RETRY_DELAY);
+
+        monitor.record_failure(&rejected());
+        let notice = rx.try_recv().expect("notice at threshold");
+        assert_eq!(notice.reason, AuthFailureReason::TokenRejected);
+        assert_eq!(notice.attempts, AUTH_FAILURE_THRESHOLD);
+        assert_eq!(monitor.retry_delay(), AUTH_FAILED_RETRY_DELAY);
+
+        // Further failures in the same streak don't notify again
+        monitor.record_failure(&rejected());
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_auth_monitor_reset_and_success_end_streak() {
+        let monitor = AuthMonitor::new();
+        for _ in 0..AUTH_FAILURE_THRESHOLD {
+            monitor.record_failure(&rejected());
+        }
+
+        monitor.reset();
+        assert_eq!(monitor.retry_delay(), RETRY_DELAY);
+
+        for _ in 0..AUTH_FAILURE_THRESHOLD {
+            monitor.record_failure(&rejected());
+        }
+        monitor.record_success();
+        assert_eq!(monitor.retry_delay(), RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_auth_monitor_ignores_connection_errors() {
+        let monitor = AuthMonitor::new();
+        let refused = anyhow::anyhow!("Failed to connect to orchestrator at 127.0.0.1:22230");
+        for _ in 0..AUTH_FAILURE_THRESHOLD {
+            monitor.record_connect_error(&refused);
+        }
+        assert_eq!(monitor.retry_delay(), RETRY_DELAY);
+
+        let missing: anyhow::Error = AuthFailure {
+            reason: AuthFailureReason::TokenMissing,
+            message: "Failed to read IPC authentication token: No token file found".to_string(),
+        }
+        .into();
+        for _ in 0..AUTH_FAILURE_THRESHOLD {
+            monitor.record_connect_error(&missing);
+        }
+        assert_eq!(monitor.retry_delay(), AUTH_FAILED_RETRY_DELAY);
+    }
+
+    #[test]
+    fn test_auth_failed_notice_payload() {
+        let notice = AuthFailedNotice {
+            reason: AuthFailureReason::TokenMissing,
+            message: "missing".to_string(),
+            guidance: AuthFailureReason::TokenMissing.guidance().to_string(),
+            attempts: 3,
+        };
+        let json = serde_json::to_value(&notice).unwrap();
+        assert_eq!(json["reason"], "token_missing");
+        assert_eq!(json["attempts"], 3);
+        assert!(json["guidance"].as_str().unwrap().contains("k-terminus serve"));
+    }
+}
@@ -1,23 +1,39 @@
 //! k-Terminus Desktop - Tauri Backend
 
 mod commands;
+mod events;
+mod input_confirm;
 mod ipc_client;
+mod logs;
 mod orchestrator;
+mod palette;
+mod recents;
 mod state;
+mod terminal_prefs;
 
 use std::sync::Arc;
+use std::time::Duration;
 
-use kt_core::ipc::IpcEvent;
+use kt_core::ipc::{ActivityKind, CloseReason, IpcEvent};
 use kt_core::try_ipc_ping;
 use serde::Serialize;
 use tauri::{async_runtime, Emitter, Manager};
 use tokio::sync::RwLock;
 
-use crate::ipc_client::PersistentIpcClient;
+use crate::commands::{MachinePayload, SessionPayload};
+use crate::events::{ForwardStats, OUTPUT_QUEUE_CAPACITY};
+use crate::input_confirm::InputConfirmations;
+use crate::ipc_client::{AuthFailedNotice, EventSubscriber, PersistentIpcClient};
+use crate::logs::LogControl;
 use crate::orchestrator::EmbeddedOrchestrator;
+use crate::recents::{RecentsStore, RECENTS_FILE_NAME};
+use crate::terminal_prefs::{TerminalPrefsStore, TERMINAL_PREFS_FILE_NAME};
 
 pub use state::{AppState, OrchestratorMode};
 
+/// Wait before restarting event forwarding that stopped
+const FORWARDER_RESTART_DELAY: Duration = Duration::from_secs(1);
+
 /// Terminal output event payload for frontend
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -26,46 +42,93 @@ struct TerminalOutputPayload {
     data: Vec<u8>,
 }
 
-/// Machine event payload for frontend
+/// `machine-event` payload for frontend (`MachineEvent` in `src/types/index.ts`)
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct MachineEventPayload {
+    /// "connected", "disconnected" or "updated"
     #[serde(rename = "type")]
     event_type: String,
-    machine: Option<serde_json::Value>,
+    /// The machine, for "connected" and "updated"
+    machine: Option<MachinePayload>,
+    /// The machine's ID, for "disconnected"
     machine_id: Option<String>,
 }
 
-/// Session event payload for frontend
+/// `session-event` payload for frontend (`SessionEvent` in `src/types/index.ts`)
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 struct SessionEventPayload {
+    /// "created" or "closed"
     #[serde(rename = "type")]
     event_type: String,
-    session: Option<serde_json::Value>,
+    /// The session, for "created"
+    session: Option<SessionPayload>,
+    /// The session's ID, for "closed"
     session_id: Option<String>,
+    /// Exit code of the remote shell, for "closed" when it exited
+    exit_code: Option<i32>,
+    /// Why the session ended, for "closed"
+    reason: Option<CloseReason>,
+}
+
+/// `session-activity` payload for frontend (`SessionActivityEvent` in `src/types/index.ts`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionActivityPayload {
+    session_id: String,
+    /// "bell", "activity" or "silence"
+    kind: ActivityKind,
 }
 
 /// Initialize and run the Tauri application
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    // Initialize logging first
-    tracing_subscriber::fmt::init();
-
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .setup(|app| {
+            // Initialize logging first so the Logs panel sees startup messages
+            let log_control = logs::init_tracing(app.path().app_log_dir().ok());
+
             // Initialize application state
-            let state = AppState::new();
+            let data_dir = app.path().app_data_dir().ok();
+            let recents_path = data_dir.as_ref().map(|dir| dir.join(RECENTS_FILE_NAME));
+            let prefs_path = data_dir.map(|dir| dir.join(TERMINAL_PREFS_FILE_NAME));
+            let state = AppState::new()
+                .with_log_source(log_control.source())
+                .with_recents(RecentsStore::load(recents_path))
+                .with_terminal_prefs(TerminalPrefsStore::load(prefs_path));
+            async_runtime::spawn(state.recents.clone().run_saver());
+            async_runtime::spawn(state.terminal_prefs.clone().run_saver());
+            async_runtime::spawn(commands::run_resize_flusher(
+                state.ipc.clone(),
+                state.resizes.clone(),
+            ));
 
             // Clone what we need for the async initialization
             let orchestrator = state.orchestrator.clone();
             let orchestrator_mode = state.orchestrator_mode.clone();
             let event_subscriber = state.event_subscriber.clone();
+            let recents = state.recents.clone();
+            let terminal_prefs = state.terminal_prefs.clone();
+            let input_confirmations = state.input_confirmations.clone();
+            let forward_stats = state.forward_stats.clone();
             let app_handle = app.handle().clone();
 
+            // Subscribe before any connection attempt so no notice is missed
+            let auth_rx = state.auth.subscribe();
+            async_runtime::spawn(forward_auth_failures(app.handle().clone(), auth_rx));
+
+            // Stream orchestrator logs to the Logs panel
+            spawn_log_forwarding(
+                app.handle().clone(),
+                &log_control,
+                orchestrator_mode.clone(),
+            );
+
             app.manage(state);
+            app.manage(log_control);
 
             // Spawn async initialization after Tauri's runtime is ready
             async_runtime::spawn(async move {
@@ -75,14 +138,16 @@ pub fn run() {
 
                 tracing::info!("Orchestrator mode: {:?}", mode);
 
-                // Start event subscriber
-                let event_rx = {
-                    let mut subscriber = event_subscriber.write().await;
-                    subscriber.start()
-                };
-
-                // Forward events to frontend
-                forward_events(app_handle, event_rx).await;
+                // Start event subscriber and forward its events to frontend
+                run_event_forwarding(
+                    app_handle,
+                    event_subscriber,
+                    recents,
+                    terminal_prefs,
+                    input_confirmations,
+                    forward_stats,
+                )
+                .await;
             });
 
             // Open devtools in debug builds
@@ -95,20 +160,34 @@ pub fn run() {
         })
         .invoke_handler(tauri::generate_handler![
             commands::get_status,
+            commands::get_capabilities,
             commands::get_state_snapshot,
             commands::start_orchestrator,
             commands::stop_orchestrator,
             commands::list_machines,
             commands::get_machine,
             commands::disconnect_machine,
+            commands::list_rejections,
             commands::list_sessions,
             commands::create_session,
             commands::kill_session,
             commands::terminal_write,
+            commands::confirm_session_input,
             commands::terminal_resize,
+            commands::set_session_monitor,
             commands::terminal_close,
+            commands::terminal_detach,
             commands::subscribe_session,
             commands::unsubscribe_session,
+            commands::reauthenticate,
+            commands::set_log_level,
+            commands::get_recents,
+            commands::pin_machine,
+            commands::unpin_machine,
+            commands::get_terminal_prefs,
+            commands::set_terminal_prefs,
+            commands::list_palette_actions,
+            commands::invoke_palette_action,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -133,7 +212,7 @@ async fn initialize_orchestrator(
     // Check token ownership first - this is authoritative
     match kt_core::read_token_info() {
         Ok(Some(info)) => {
-            if kt_core::is_process_alive(info.pid) {
+            if info.owner_process().is_alive() {
                 // There's a live orchestrator process - verify it's responding
                 tracing::info!(
                     "Found live orchestrator process (PID {}) at {}",
@@ -214,100 +293,374 @@ async fn initialize_orchestrator(
     }
 }
 
-/// Forward IPC events from orchestrator to frontend
-async fn forward_events(
+/// Forward IPC events from orchestrator to frontend for as long as the app
+/// runs
+///
+/// Should forwarding stop, because the event subscriber did or something
+/// panicked outside of emitting a single event, the subscriber is restarted
+/// on a new connection and forwarding resumes.
+async fn run_event_forwarding(
     app_handle: tauri::AppHandle,
-    mut event_rx: tokio::sync::mpsc::Receiver<IpcEvent>,
+    event_subscriber: Arc<RwLock<EventSubscriber>>,
+    recents: Arc<RecentsStore>,
+    terminal_prefs: Arc<TerminalPrefsStore>,
+    input_confirmations: Arc<InputConfirmations>,
+    stats: Arc<ForwardStats>,
 ) {
-    tracing::info!("Starting event forwarder");
-
-    while let Some(event) = event_rx.recv().await {
-        match event {
-            IpcEvent::TerminalOutput { session_id, data } => {
-                let event_name = format!("terminal-output:{}", session_id);
-                let payload = TerminalOutputPayload {
-                    session_id: session_id.clone(),
-                    data,
-                };
-
-                if let Err(e) = app_handle.emit(&event_name, payload) {
-                    tracing::warn!("Failed to emit terminal output event: {}", e);
-                }
+    loop {
+        tracing::info!("Starting event forwarder");
+        let event_rx = event_subscriber.write().await.restart();
+
+        let app_handle = app_handle.clone();
+        let recents = recents.clone();
+        let terminal_prefs = terminal_prefs.clone();
+        let input_confirmations = input_confirmations.clone();
+        let forwarder = tokio::spawn(events::forward_events(
+            event_rx,
+            OUTPUT_QUEUE_CAPACITY,
+            stats.clone(),
+            move |event| {
+                // Keeps the connection status shown in the recents list current
+                recents.apply_event(&event);
+                // Drops the preferences and input confirmations of sessions that close
+                terminal_prefs.apply_event(&event);
+                input_confirmations.apply_event(&event);
+                emit_event(&app_handle, event);
+            },
+        ));
+
+        match forwarder.await {
+            Ok(()) => tracing::warn!("Event forwarder stopped, restarting it"),
+            Err(e) => tracing::error!("Event forwarder failed, restarting it: {}", e),
+        }
+        tokio::time::sleep(FORWARDER_RESTART_DELAY).await;
+    }
+}
+
+/// Emit an IPC event to the frontend
+fn emit_event(app_handle: &tauri::AppHandle, event: IpcEvent) {
+    match event {
+        IpcEvent::TerminalOutput {
+            session_id, data, ..
+        } => {
+            let event_name = format!("terminal-output:{}", session_id);
+            let payload = TerminalOutputPayload {
+                session_id: session_id.clone(),
+                data,
+            };
+
+            if let Err(e) = app_handle.emit(&event_name, payload) {
+                tracing::warn!("Failed to emit terminal output event: {}", e);
             }
+        }
 
-            IpcEvent::MachineConnected(machine) => {
-                let payload = MachineEventPayload {
-                    event_type: "connected".to_string(),
-                    machine: Some(serde_json::to_value(&machine).unwrap_or_default()),
-                    machine_id: None,
-                };
-                if let Err(e) = app_handle.emit("machine-event", payload) {
-                    tracing::debug!("Failed to emit machine-connected event: {}", e);
-                }
+        IpcEvent::MachineConnected(machine) => {
+            let payload = MachineEventPayload {
+                event_type: "connected".to_string(),
+                machine: Some(machine.into()),
+                machine_id: None,
+            };
+            if let Err(e) = app_handle.emit("machine-event", payload) {
+                tracing::debug!("Failed to emit machine-connected event: {}", e);
             }
+        }
 
-            IpcEvent::MachineDisconnected { machine_id } => {
-                let payload = MachineEventPayload {
-                    event_type: "disconnected".to_string(),
-                    machine: None,
-                    machine_id: Some(machine_id),
-                };
-                if let Err(e) = app_handle.emit("machine-event", payload) {
-                    tracing::debug!("Failed to emit machine-disconnected event: {}", e);
-                }
+        IpcEvent::MachineDisconnected { machine_id } => {
+            let payload = MachineEventPayload {
+                event_type: "disconnected".to_string(),
+                machine: None,
+                machine_id: Some(machine_id),
+            };
+            if let Err(e) = app_handle.emit("machine-event", payload) {
+                tracing::debug!("Failed to emit machine-disconnected event: {}", e);
             }
+        }
 
-            IpcEvent::MachineUpdated(machine) => {
-                let payload = MachineEventPayload {
-                    event_type: "updated".to_string(),
-                    machine: Some(serde_json::to_value(&machine).unwrap_or_default()),
-                    machine_id: None,
-                };
-                if let Err(e) = app_handle.emit("machine-event", payload) {
-                    tracing::debug!("Failed to emit machine-updated event: {}", e);
-                }
+        IpcEvent::MachineUpdated(machine) => {
+            let payload = MachineEventPayload {
+                event_type: "updated".to_string(),
+                machine: Some(machine.into()),
+                machine_id: None,
+            };
+            if let Err(e) = app_handle.emit("machine-event", payload) {
+                tracing::debug!("Failed to emit machine-updated event: {}", e);
             }
+        }
 
-            IpcEvent::SessionCreated(session) => {
-                let payload = SessionEventPayload {
-                    event_type: "created".to_string(),
-                    session: Some(serde_json::to_value(&session).unwrap_or_default()),
-                    session_id: None,
-                };
-                if let Err(e) = app_handle.emit("session-event", payload) {
-                    tracing::debug!("Failed to emit session-created event: {}", e);
-                }
+        IpcEvent::MachineRejected {
+            hostname,
+            reason,
+            peer_addr,
+        } => {
+            let payload = serde_json::json!({
+                "hostname": hostname,
+                "reason": reason,
+                "peerAddr": peer_addr,
+            });
+            if let Err(e) = app_handle.emit("machine-rejected", payload) {
+                tracing::debug!("Failed to emit machine-rejected event: {}", e);
             }
+        }
 
-            IpcEvent::SessionClosed { session_id } => {
-                let payload = SessionEventPayload {
-                    event_type: "closed".to_string(),
-                    session: None,
-                    session_id: Some(session_id),
-                };
-                if let Err(e) = app_handle.emit("session-event", payload) {
-                    tracing::debug!("Failed to emit session-closed event: {}", e);
-                }
+        IpcEvent::SessionCreated(session) => {
+            let payload = SessionEventPayload {
+                event_type: "created".to_string(),
+                session: Some(session.into()),
+                session_id: None,
+                exit_code: None,
+                reason: None,
+            };
+            if let Err(e) = app_handle.emit("session-event", payload) {
+                tracing::debug!("Failed to emit session-created event: {}", e);
             }
+        }
 
-            IpcEvent::StatusChanged(status) => {
-                if let Err(e) = app_handle.emit("orchestrator-status", status) {
-                    tracing::debug!("Failed to emit orchestrator-status event: {}", e);
-                }
+        IpcEvent::SessionClosed {
+            session_id,
+            exit_code,
+            reason,
+        } => {
+            let payload = SessionEventPayload {
+                event_type: "closed".to_string(),
+                session: None,
+                session_id: Some(session_id),
+                exit_code,
+                reason,
+            };
+            if let Err(e) = app_handle.emit("session-event", payload) {
+                tracing::debug!("Failed to emit session-closed event: {}", e);
+            }
+        }
+
+        IpcEvent::SessionActivity { session_id, kind } => {
+            let payload = SessionActivityPayload { session_id, kind };
+            if let Err(e) = app_handle.emit("session-activity", payload) {
+                tracing::debug!("Failed to emit session-activity event: {}", e);
+            }
+        }
+
+        IpcEvent::StatusChanged(status) => {
+            if let Err(e) = app_handle.emit("orchestrator-status", status) {
+                tracing::debug!("Failed to emit orchestrator-status event: {}", e);
+            }
+        }
+
+        IpcEvent::EventsDropped { count } => {
+            // Notify frontend that events were dropped so it can refresh state
+            tracing::warn!(
+                "IPC event queue lagged, {} events dropped - frontend should refresh",
+                count
+            );
+            if let Err(e) = app_handle.emit("events-dropped", serde_json::json!({ "count": count })) {
+                tracing::debug!("Failed to emit events-dropped event: {}", e);
+            }
+        }
+        IpcEvent::Notice {
+            message,
+            session_id,
+            client_id,
+        } => {
+            let payload = serde_json::json!({
+                "message": message,
+                "sessionId": session_id,
+                "clientId": client_id,
+            });
+            if let Err(e) = app_handle.emit("notice", payload) {
+                tracing::debug!("Failed to emit notice event: {}", e);
+            }
+        }
+
+        IpcEvent::SessionInputHolder { session_id, holder } => {
+            let payload = serde_json::json!({
+                "sessionId": session_id,
+                "holder": holder,
+            });
+            if let Err(e) = app_handle.emit("session-input-holder", payload) {
+                tracing::debug!("Failed to emit session-input-holder event: {}", e);
+            }
+        }
+
+        IpcEvent::ConfigReloaded {
+            changed_keys,
+            restart_required,
+        } => {
+            let payload = serde_json::json!({
+                "changedKeys": changed_keys,
+                "restartRequired": restart_required,
+            });
+            if let Err(e) = app_handle.emit("config-reloaded", payload) {
+                tracing::debug!("Failed to emit config-reloaded event: {}", e);
+            }
+        }
+
+        IpcEvent::ConfigReloadFailed { error } => {
+            let payload = serde_json::json!({ "error": error });
+            if let Err(e) = app_handle.emit("config-reload-failed", payload) {
+                tracing::debug!("Failed to emit config-reload-failed event: {}", e);
             }
+        }
+    }
+}
 
-            IpcEvent::EventsDropped { count } => {
-                // Notify frontend that events were dropped so it can refresh state
+/// Forward repeated IPC authentication failures to the frontend
+async fn forward_auth_failures(
+    app_handle: tauri::AppHandle,
+    mut auth_rx: tokio::sync::broadcast::Receiver<AuthFailedNotice>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+
+    loop {
+        match auth_rx.recv().await {
+            Ok(notice) => {
                 tracing::warn!(
-                    "IPC event queue lagged, {} events dropped - frontend should refresh",
-                    count
+                    "Repeated IPC authentication failures ({:?}), notifying frontend",
+                    notice.reason
                 );
-                if let Err(e) = app_handle.emit("events-dropped", serde_json::json!({ "count": count })) {
-                    tracing::debug!("Failed to emit events-dropped event: {}", e);
+                if let Err(e) = app_handle.emit("orchestrator-auth-failed", notice) {
+                    tracing::debug!("Failed to emit orchestrator-auth-failed event: {}", e);
                 }
             }
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => break,
         }
     }
+}
 
-    tracing::info!("Event forwarder stopped");
+/// Forward orchestrator log lines to the frontend as `orchestrator-log` events
+///
+/// Lines come from this process in Embedded mode and are followed over IPC in
+/// External mode. Each event carries a batch of lines.
+fn spawn_log_forwarding(
+    app_handle: tauri::AppHandle,
+    log_control: &LogControl,
+    mode: Arc<RwLock<OrchestratorMode>>,
+) {
+    let (external_tx, external_rx) =
+        tokio::sync::mpsc::channel(kt_orchestrator::logging::LOG_CHANNEL_CAPACITY);
+    async_runtime::spawn(logs::follow_external_logs(
+        PersistentIpcClient::default_address(),
+        mode.clone(),
+        external_tx,
+    ));
+    async_runtime::spawn(logs::forward_logs(
+        log_control.source(),
+        log_control.level_handle(),
+        mode,
+        external_rx,
+        move |lines| {
+            // Not logged on failure: the warning would be forwarded here again
+            let _ = app_handle.emit("orchestrator-log", lines);
+        },
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_event_payload_shape() {
+        let machine = kt_core::ipc::MachineInfo {
+            id: "gpu-box".to_string(),
+            alias: Some("gpu".to_string()),
+            label: None,
+            hostname: "gpu-box.lan".to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: kt_core::ipc::MachineStatus::Connected,
+            connected_at: Some("2024-01-01T00:00:00Z".to_string()),
+            last_heartbeat: None,
+            session_count: 2,
+            tags: vec![],
+            capabilities: vec!["cwd".to_string()],
+            reconnect_count: 0,
+        };
+        let payload = MachineEventPayload {
+            event_type: "connected".to_string(),
+            machine: Some(machine.into()),
+            machine_id: None,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&payload).unwrap(),
+            serde_json::json!({
+                "type": "connected",
+                "machine": {
+                    "id": "gpu-box",
+                    "alias": "gpu",
+                    "hostname": "gpu-box.lan",
+                    "os": "linux",
+                    "arch": "x86_64",
+                    "status": "connected",
+                    "connectedAt": "2024-01-01T00:00:00Z",
+                    "lastHeartbeat": null,
+                    "sessionCount": 2,
+                    "tags": null,
+                    "capabilities": ["cwd"],
+                },
+                "machineId": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_session_event_payload_shape() {
+        let session = kt_core::ipc::SessionInfo {
+            id: "session-1".to_string(),
+            machine_id: "gpu-box".to_string(),
+            shell: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            pid: Some(42),
+            name: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+            size: Some(kt_core::ipc::TerminalSize { cols: 80, rows: 24 }),
+        };
+        let created = SessionEventPayload {
+            event_type: "created".to_string(),
+            session: Some(session.into()),
+            session_id: None,
+            exit_code: None,
+            reason: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&created).unwrap(),
+            serde_json::json!({
+                "type": "created",
+                "session": {
+                    "id": "session-1",
+                    "machineId": "gpu-box",
+                    "shell": null,
+                    "createdAt": "2024-01-01T00:00:00Z",
+                    "pid": 42,
+                    "name": null,
+                    "size": { "cols": 80, "rows": 24 },
+                },
+                "sessionId": null,
+                "exitCode": null,
+                "reason": null,
+            })
+        );
+
+        let closed = SessionEventPayload {
+            event_type: "closed".to_string(),
+            session: None,
+            session_id: Some("session-1".to_string()),
+            exit_code: Some(1),
+            reason: Some(CloseReason::ProcessExited { code: Some(1) }),
+        };
+        assert_eq!(
+            serde_json::to_value(&closed).unwrap(),
+            serde_json::json!({
+                "type": "closed",
+                "session": null,
+                "sessionId": "session-1",
+                "exitCode": 1,
+                "reason": { "kind": "process_exited", "code": 1 },
+            })
+        );
+    }
 }
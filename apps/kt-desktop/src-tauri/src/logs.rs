@@ -0,0 +1,296 @@
+//! Orchestrator log streaming for the Logs panel
+//!
+//! Where the lines come from depends on the orchestrator mode:
+//!
+//! - **Embedded**: the orchestrator runs in this process, so its logs are
+//!   captured by a tracing layer installed in [`init_tracing`] and handed to
+//!   the embedded orchestrator's IPC server as its log source. They are also
+//!   written to the app's log directory.
+//! - **External**: the daemon's logs are followed over IPC with
+//!   `IpcRequest::TailLogs` ([`follow_external_logs`]).
+//!
+//! [`forward_logs`] merges both, keeps only the lines for the current mode,
+//! and batches them every `LOG_BATCH_INTERVAL` so a debug-level flood can't
+//! freeze the webview.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tracing::Level;
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Layer, Registry};
+
+use kt_core::ipc::{IpcRequest, IpcResponse, LogLine};
+use kt_orchestrator::logging::{
+    LogBatcher, LogFileLayer, LogFileSettings, LogSource, LOG_FILE_NAME,
+};
+
+use crate::ipc_client::{connect_and_authenticate, Connection};
+use crate::state::OrchestratorMode;
+
+/// Past lines requested when starting to follow an external orchestrator
+const INITIAL_TAIL_LINES: usize = 500;
+
+/// Delay before reconnecting after the log stream ended
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Delay before retrying an orchestrator that doesn't support `TailLogs`
+const UNAVAILABLE_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Reload handle for the level of captured log lines
+pub type LevelHandle = reload::Handle<LevelFilter, Registry>;
+
+/// Captured logs of this process and the level they're filtered at
+pub struct LogControl {
+    source: LogSource,
+    level: LevelHandle,
+}
+
+/// Install the global tracing subscriber.
+///
+/// Stderr output still follows `RUST_LOG`; captured lines start at INFO and
+/// can be changed at runtime with [`LogControl::set_level`]. INFO and above
+/// also go to the orchestrator log file, `orchestrator.log` in `log_dir`
+/// unless the config names another, rotated and formatted as the config
+/// says; it backs the history the embedded orchestrator returns for
+/// `TailLogs`.
+pub fn init_tracing(log_dir: Option<PathBuf>) -> LogControl {
+    let log_file = log_dir.and_then(|dir| {
+        let settings = LogFileSettings::load(None, dir.join(LOG_FILE_NAME));
+        match LogFileLayer::open(&settings) {
+            Ok(layer) => Some((layer, settings.path)),
+            Err(e) => {
+                eprintln!(
+                    "Warning: failed to open log file {:?}: {}",
+                    settings.path, e
+                );
+                None
+            }
+        }
+    });
+    let (log_file, log_path) = log_file.unzip();
+    let source = LogSource::new(log_path);
+    let (level_filter, level) = reload::Layer::new(LevelFilter::INFO);
+
+    tracing_subscriber::registry()
+        .with(source.broadcast_layer().with_filter(level_filter))
+        .with(log_file.with_filter(LevelFilter::INFO))
+        .with(fmt::layer().with_filter(EnvFilter::from_default_env()))
+        .init();
+
+    LogControl { source, level }
+}
+
+impl LogControl {
+    /// Log source for the embedded orchestrator's IPC server
+    pub fn source(&self) -> LogSource {
+        self.source.clone()
+    }
+
+    /// Handle for reading the current level
+    pub fn level_handle(&self) -> LevelHandle {
+        self.level.clone()
+    }
+
+    /// Change the level of lines shown in the Logs panel.
+    ///
+    /// Applies to captured lines directly and to lines followed from an
+    /// external orchestrator when they arrive.
+    pub fn set_level(&self, level: &str) -> Result<(), String> {
+        let filter = parse_level(level)?;
+        self.level
+            .modify(|current| *current = filter)
+            .map_err(|e| format!("Failed to change log level: {}", e))?;
+        tracing::info!("Log panel level set to {}", filter);
+        Ok(())
+    }
+}
+
+/// Parse a level name ("error", "warn", "info", "debug", "trace" or "off")
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .trim()
+        .parse::<LevelFilter>()
+        .map_err(|_| format!("Invalid log level: {}", level))
+}
+
+/// Check whether a line passes a level filter
+fn line_enabled(line: &LogLine, filter: LevelFilter) -> bool {
+    line.level
+        .parse::<Level>()
+        .map(|level| filter >= level)
+        .unwrap_or(true)
+}
+
+/// Follow an external orchestrator's logs whenever we're in External mode.
+///
+/// Runs for the lifetime of the app, reconnecting when the stream ends.
+pub async fn follow_external_logs(
+    address: String,
+    mode: Arc<RwLock<OrchestratorMode>>,
+    tx: mpsc::Sender<LogLine>,
+) {
+    loop {
+        if tx.is_closed() {
+            break;
+        }
+        if *mode.read().await != OrchestratorMode::External {
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            continue;
+        }
+
+        let delay = match follow_once(&address, &tx).await {
+            Ok(()) => RECONNECT_DELAY,
+            Err(e) => {
+                tracing::debug!("Orchestrator log stream unavailable: {}", e);
+                UNAVAILABLE_RETRY_DELAY
+            }
+        };
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Follow logs over one connection until it closes
+async fn follow_once(address: &str, tx: &mpsc::Sender<LogLine>) -> Result<()> {
+    let Connection {
+        mut reader,
+        mut writer,
+        ..
+    } = connect_and_authenticate(address, None).await?;
+
+    let request = IpcRequest::TailLogs {
+        follow: true,
+        lines: INITIAL_TAIL_LINES,
+    };
+    let mut json = serde_json::to_string(&request)?;
+    json.push('\n');
+    writer.write_all(json.as_bytes()).await?;
+
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(());
+        }
+        match serde_json::from_str::<IpcResponse>(line.trim()) {
+            Ok(IpcResponse::LogLines { lines }) => {
+                for log_line in lines {
+                    if tx.send(log_line).await.is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(IpcResponse::Error { message }) => anyhow::bail!("{}", message),
+            // Not a response to us (e.g. an event); ignore it
+            _ => {}
+        }
+    }
+}
+
+/// Batch log lines for the current mode and pass each batch to `emit`.
+///
+/// Captured lines are used in Embedded mode, lines from `external_rx` in
+/// External mode. `emit` is called at most once per `LOG_BATCH_INTERVAL`.
+pub async fn forward_logs<F>(
+    source: LogSource,
+    level: LevelHandle,
+    mode: Arc<RwLock<OrchestratorMode>>,
+    mut external_rx: mpsc::Receiver<LogLine>,
+    mut emit: F,
+) where
+    F: FnMut(Vec<LogLine>),
+{
+    let mut local_rx = source.subscribe();
+    let mut batch = LogBatcher::new();
+
+    loop {
+        tokio::select! {
+            result = local_rx.recv() => {
+                let embedded = *mode.read().await == OrchestratorMode::Embedded;
+                match result {
+                    Ok(line) if embedded => batch.push(line),
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) if embedded => batch.record_dropped(n),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+
+            Some(line) = external_rx.recv() => {
+                let filter = level.with_current(|f| *f).unwrap_or(LevelFilter::INFO);
+                let external = *mode.read().await == OrchestratorMode::External;
+                if external && line_enabled(&line, filter) {
+                    batch.push(line);
+                }
+            }
+
+            _ = tokio::time::sleep_until(
+                batch.deadline().unwrap_or_else(tokio::time::Instant::now)
+            ), if batch.deadline().is_some() => {
+                emit(batch.take());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(level: &str, message: &str) -> LogLine {
+        LogLine {
+            timestamp_ms: 0,
+            level: level.to_string(),
+            target: "kt_orchestrator".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level("debug"), Ok(LevelFilter::DEBUG));
+        assert_eq!(parse_level(" WARN "), Ok(LevelFilter::WARN));
+        assert_eq!(parse_level("off"), Ok(LevelFilter::OFF));
+        assert!(parse_level("loud").is_err());
+    }
+
+    #[test]
+    fn test_line_enabled() {
+        assert!(line_enabled(&line("ERROR", ""), LevelFilter::INFO));
+        assert!(line_enabled(&line("INFO", ""), LevelFilter::INFO));
+        assert!(!line_enabled(&line("DEBUG", ""), LevelFilter::INFO));
+        assert!(!line_enabled(&line("ERROR", ""), LevelFilter::OFF));
+        // Unknown levels are shown rather than silently hidden
+        assert!(line_enabled(&line("NOTICE", ""), LevelFilter::ERROR));
+    }
+
+    #[tokio::test]
+    async fn test_forward_logs_batches_external_lines() {
+        let source = LogSource::new(None);
+        let (_level_filter, level) = reload::Layer::<LevelFilter, Registry>::new(LevelFilter::INFO);
+        let mode = Arc::new(RwLock::new(OrchestratorMode::External));
+        let (tx, rx) = mpsc::channel(16);
+        let (batch_tx, mut batch_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(forward_logs(source, level, mode, rx, move |lines| {
+            let _ = batch_tx.send(lines);
+        }));
+
+        tx.send(line("INFO", "one")).await.unwrap();
+        tx.send(line("DEBUG", "hidden")).await.unwrap();
+        tx.send(line("WARN", "two")).await.unwrap();
+
+        let batch = tokio::time::timeout(Duration::from_secs(2), batch_rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        let messages: Vec<_> = batch.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(messages, vec!["one", "two"]);
+    }
+}
@@ -4,15 +4,18 @@
 //! for a separate daemon.
 
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{Context, Result};
 use tokio::sync::{broadcast, mpsc};
 use tokio_util::sync::CancellationToken;
 
-use kt_core::config::{self, ConfigFile, OrchestratorConfig};
-use kt_core::ipc::{IpcEvent, IpcEventEnvelope, StateEpoch};
+use kt_core::config::{self, ConfigFile, ConfigLoader, OrchestratorConfig};
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope, OrchestratorOwner, StateEpoch};
+use kt_core::TokenGuard;
 use kt_orchestrator::connection::TunnelConnection;
 use kt_orchestrator::ipc::IpcServer;
+use kt_orchestrator::logging::LogSource;
 use kt_orchestrator::server::{load_or_generate_host_key, ConnectionEvent, SshServer};
 use kt_orchestrator::OrchestratorState;
 
@@ -22,6 +25,10 @@ pub struct EmbeddedOrchestrator {
     cancel: CancellationToken,
     /// Whether the orchestrator is running
     running: bool,
+    /// Captured logs served to `TailLogs` requests
+    log_source: Option<LogSource>,
+    /// Removes the IPC token file once the orchestrator is dropped
+    token_guard: Option<TokenGuard>,
 }
 
 impl EmbeddedOrchestrator {
@@ -30,9 +37,17 @@ impl EmbeddedOrchestrator {
         Self {
             cancel: CancellationToken::new(),
             running: false,
+            log_source: None,
+            token_guard: None,
         }
     }
 
+    /// Serve `TailLogs` requests from the app's captured logs
+    pub fn with_log_source(mut self, log_source: LogSource) -> Self {
+        self.log_source = Some(log_source);
+        self
+    }
+
     /// Start the embedded orchestrator
     pub async fn start(&mut self) -> Result<()> {
         if self.running {
@@ -45,6 +60,9 @@ impl EmbeddedOrchestrator {
         // Load configuration
         let config = load_config()?;
 
+        // Tighten permissions on secrets an older version may have left readable
+        kt_core::permissions::check_permissions(&[&config.host_key_path]);
+
         // Load or generate host key
         let host_key = load_or_generate_host_key(&config.host_key_path).await?;
         let host_key_fingerprint = host_key
@@ -57,17 +75,22 @@ impl EmbeddedOrchestrator {
         tracing::info!("Host key fingerprint: {}", host_key_fingerprint);
 
         // Create orchestrator state
-        let state = Arc::new(OrchestratorState::new(config.clone()));
+        let state = Arc::new(
+            OrchestratorState::new(config.clone()).with_owner(OrchestratorOwner::DesktopEmbedded),
+        );
 
         // Create event channel for connection events
         let (event_tx, mut event_rx) = mpsc::channel::<ConnectionEvent>(256);
 
         // Start IPC server
         let ipc_address = config.ipc_address();
-        let ipc_server = Arc::new(
-            IpcServer::new(ipc_address.clone(), Arc::clone(&state))?
-                .with_shutdown_token(self.cancel.clone()),
-        );
+        let mut ipc_server = IpcServer::new(ipc_address.clone(), Arc::clone(&state))?
+            .with_shutdown_token(self.cancel.clone());
+        self.token_guard = Some(TokenGuard::for_default_path()?);
+        if let Some(log_source) = &self.log_source {
+            ipc_server = ipc_server.with_log_source(log_source.clone());
+        }
+        let ipc_server = Arc::new(ipc_server);
         let ipc_event_tx = ipc_server.event_sender();
 
         // Spawn event handler
@@ -80,6 +103,20 @@ impl EmbeddedOrchestrator {
             }
         });
 
+        // Notify webhooks of lifecycle events
+        let _webhook_handle = kt_orchestrator::webhook::spawn_notifier(
+            config.webhook.clone(),
+            ipc_server.event_sender().subscribe(),
+            self.cancel.clone(),
+        );
+
+        // Journal events to disk, if configured
+        let _journal_handle = kt_orchestrator::journal::spawn_journal(
+            &config,
+            ipc_server.event_sender().subscribe(),
+            self.cancel.clone(),
+        );
+
         // Spawn IPC server task
         let ipc_server_clone = Arc::clone(&ipc_server);
         let cancel_ipc = self.cancel.clone();
@@ -99,16 +136,55 @@ impl EmbeddedOrchestrator {
 
         // Start health monitor
         let health_monitor = kt_orchestrator::connection::HealthMonitor::new(
-            config.heartbeat_interval,
-            config.heartbeat_timeout,
+            *config.heartbeat_interval,
+            *config.heartbeat_timeout,
+        );
+        let _health_handle = health_monitor.spawn(
+            Arc::clone(&state),
+            ipc_server.event_sender(),
+            self.cancel.clone(),
         );
-        let _health_handle = health_monitor.spawn(Arc::clone(&state), self.cancel.clone());
         tracing::info!(
-            "Health monitor started (interval={:?}, timeout={:?})",
+            "Health monitor started (interval={}, timeout={})",
             config.heartbeat_interval,
             config.heartbeat_timeout
         );
 
+        // Start silence monitor for sessions that asked for silence events
+        tokio::spawn(kt_orchestrator::session::run_silence_monitor(
+            Arc::clone(&state),
+            ipc_server.event_sender(),
+            self.cancel.clone(),
+        ));
+
+        // Send output held back while coalescing for lagging clients
+        tokio::spawn(kt_orchestrator::session::run_output_flusher(
+            Arc::clone(&state),
+            ipc_server.event_sender(),
+            self.cancel.clone(),
+        ));
+
+        // Close orphaned sessions after their grace period, and sessions
+        // whose TTL ran out
+        tokio::spawn(kt_orchestrator::session::run_orphan_cleanup(
+            Arc::clone(&state),
+            ipc_server.event_sender(),
+            self.cancel.clone(),
+        ));
+
+        // Send the latest size of sessions resized in quick succession
+        tokio::spawn(kt_orchestrator::session::run_resize_flusher(
+            Arc::clone(&state),
+            self.cancel.clone(),
+        ));
+
+        // Remove machines that don't reconnect within their grace period
+        tokio::spawn(kt_orchestrator::connection::run_reconnect_grace(
+            Arc::clone(&state),
+            ipc_server.event_sender(),
+            self.cancel.clone(),
+        ));
+
         // Create and run SSH server
         let server = SshServer::new(host_key, Arc::clone(&state), self.cancel.clone(), event_tx);
 
@@ -118,9 +194,9 @@ impl EmbeddedOrchestrator {
         // Spawn SSH server in background
         tokio::spawn(async move {
             tracing::info!("Starting SSH server on {}", bind_addr);
-            if let Err(e) = server.run(&bind_addr).await {
+            if let Err(e) = server.run().await {
                 if !cancel.is_cancelled() {
-                    tracing::error!("SSH server error: {}", e);
+                    tracing::error!("SSH server error: {:#}", e);
                 }
             }
             tracing::info!("SSH server stopped");
@@ -158,28 +234,24 @@ impl Default for EmbeddedOrchestrator {
 impl Drop for EmbeddedOrchestrator {
     fn drop(&mut self) {
         self.stop();
-        // Clean up token file on shutdown
-        if let Err(e) = kt_core::remove_ipc_token() {
-            tracing::warn!("Failed to remove IPC token file: {}", e);
-        }
+        // The token guard then removes the token file
     }
 }
 
-/// Load orchestrator configuration
+/// Load orchestrator configuration (defaults <- file <- KT_ORCHESTRATOR__*)
 fn load_config() -> Result<OrchestratorConfig> {
     let default_path = config::default_config_path();
     tracing::info!("Looking for config at {:?}", default_path);
+    let mut loader = ConfigLoader::<ConfigFile>::new();
     if default_path.exists() {
-        // Config file has [orchestrator] section, so we need to use ConfigFile wrapper
-        let config_file: ConfigFile = config::load_config(&default_path)
+        loader
+            .with_file(&default_path)
             .with_context(|| format!("Failed to load config from {:?}", default_path))?;
-        let config = config_file.orchestrator;
         tracing::info!("Loaded orchestrator config");
-        Ok(config)
     } else {
         tracing::info!("Config file not found, using default configuration");
-        Ok(OrchestratorConfig::default())
     }
+    Ok(loader.with_env().load()?.orchestrator)
 }
 
 /// Handle connection events from SSH handlers
@@ -193,55 +265,109 @@ async fn handle_connection_event(
         ConnectionEvent::MachineConnected {
             machine_id,
             alias,
+            label,
             hostname,
             os,
             arch,
+            capabilities,
+            reconnect_count,
             command_tx,
             cancel,
         } => {
-            tracing::info!(
-                "Machine connected: {} (alias: {}, hostname: {}, os: {}, arch: {})",
-                machine_id,
-                alias,
-                hostname,
-                os,
-                arch
-            );
+            tracing::info!(%machine_id, %alias, %hostname, %os, %arch, "Machine connected");
+            // Back within its grace period, with its sessions
+            let reconnected = state.coordinator.connections.is_reconnecting(&machine_id);
             // Register in connection pool with command channel
-            state.coordinator.connections.insert(TunnelConnection::new(
-                machine_id.clone(),
-                Some(alias.clone()),
-                Some(hostname.clone()),
-                os.clone(),
-                arch.clone(),
-                command_tx,
-                cancel,
-            ));
-
-            // Broadcast to IPC clients wrapped in envelope
-            let event = IpcEvent::MachineConnected(kt_core::ipc::MachineInfo {
+            state.coordinator.connections.insert(
+                TunnelConnection::new(
+                    machine_id.clone(),
+                    Some(alias.clone()),
+                    Some(hostname.clone()),
+                    os.clone(),
+                    arch.clone(),
+                    command_tx,
+                    cancel,
+                )
+                .with_label(label.clone())
+                .with_capabilities(capabilities)
+                .with_reconnect_count(reconnect_count),
+            );
+            // The pool keeps counting across agent restarts
+            let reconnect_count = state
+                .coordinator
+                .connections
+                .get(&machine_id)
+                .map_or(reconnect_count, |conn| conn.reconnect_count);
+
+            // Broadcast to IPC clients wrapped in envelope; clients still
+            // list a reconnecting machine, so they get an update instead
+            let sessions = state.coordinator.sessions.list_for_machine(&machine_id);
+            let info = kt_core::ipc::MachineInfo {
                 id: machine_id.to_string(),
                 alias: Some(alias),
+                label,
                 hostname,
                 os,
                 arch,
                 status: kt_core::ipc::MachineStatus::Connected,
-                connected_at: None,
+                connected_at: Some(kt_core::time::format_iso8601(std::time::SystemTime::now())),
                 last_heartbeat: None,
-                session_count: 0,
+                session_count: sessions.len(),
                 tags: vec![],
-            });
+                capabilities: capabilities.names(),
+                reconnect_count,
+            };
+            let event = if reconnected {
+                tracing::info!(%machine_id, "Machine reconnected within its grace period");
+                IpcEvent::MachineUpdated(info)
+            } else {
+                IpcEvent::MachineConnected(info)
+            };
             let _ = ipc_event_tx.send(epoch.wrap_event(event));
         }
 
         ConnectionEvent::MachineDisconnected { machine_id } => {
-            tracing::info!("Machine disconnected: {}", machine_id);
-            // Remove from connection pool
-            state.coordinator.connections.remove(&machine_id);
+            tracing::info!(%machine_id, "Machine disconnected");
+            // Keep it and its sessions for a while in case it comes back
+            if kt_orchestrator::connection::begin_reconnect_grace(state, ipc_event_tx, &machine_id)
+                .await
+            {
+                return;
+            }
+            // Remove the connection and its sessions together
+            let (connection, removed_sessions) =
+                state.coordinator.atomic_disconnect(&machine_id).await;
+            for session in &removed_sessions {
+                if session.try_close() {
+                    let event = IpcEvent::SessionClosed {
+                        session_id: session.id.to_string(),
+                        exit_code: None,
+                        reason: Some(CloseReason::MachineDisconnected),
+                    };
+                    let _ = ipc_event_tx.send(epoch.wrap_event(event));
+                }
+            }
 
-            // Broadcast to IPC clients wrapped in envelope
-            let event = IpcEvent::MachineDisconnected {
-                machine_id: machine_id.to_string(),
+            // Broadcast to IPC clients wrapped in envelope, unless the
+            // health monitor already removed and announced it
+            if connection.is_some() {
+                let event = IpcEvent::MachineDisconnected {
+                    machine_id: machine_id.to_string(),
+                };
+                let _ = ipc_event_tx.send(epoch.wrap_event(event));
+            }
+        }
+
+        ConnectionEvent::MachineRejected {
+            hostname,
+            reason,
+            peer_addr,
+        } => {
+            // The handler has recorded it for ListRejections
+            let event = IpcEvent::MachineRejected {
+                hostname,
+                reason,
+                peer_addr: peer_addr.ip().to_string(),
             };
             let _ = ipc_event_tx.send(epoch.wrap_event(event));
         }
@@ -251,12 +377,7 @@ async fn handle_connection_event(
             session_id,
             pid,
         } => {
-            tracing::info!(
-                "Session {} ready on {} (pid={})",
-                session_id,
-                machine_id,
-                pid
-            );
+            tracing::info!(%machine_id, %session_id, pid, "Session ready");
             // Update session with PID from agent
             state.coordinator.sessions.set_pid(session_id, pid);
 
@@ -268,6 +389,12 @@ async fn handle_connection_event(
                 created_at: String::new(),
                 pid: Some(pid),
                 size: None,
+                name: None,
+                bytes_in: 0,
+                bytes_out: 0,
+                detached: false,
+                input_bytes_per_min: 0,
+                input_requests_per_min: 0,
             });
             let _ = ipc_event_tx.send(epoch.wrap_event(event));
         }
@@ -275,29 +402,54 @@ async fn handle_connection_event(
         ConnectionEvent::SessionClosed {
             machine_id,
             session_id,
+            exit_code,
+            reason,
         } => {
-            tracing::info!("Session {} closed on {}", session_id, machine_id);
-            // Remove session from session manager
-            state.coordinator.sessions.remove(session_id);
-
-            // Broadcast to IPC clients wrapped in envelope
-            let event = IpcEvent::SessionClosed {
-                session_id: session_id.to_string(),
-            };
-            let _ = ipc_event_tx.send(epoch.wrap_event(event));
+            tracing::info!(%machine_id, %session_id, ?exit_code, "Session closed");
+            // Sessions created to outlive their shell stay until closed
+            if kt_orchestrator::session::keep_exited_session(
+                state,
+                ipc_event_tx,
+                session_id,
+                exit_code,
+                &reason,
+            ) {
+                return;
+            }
+            // Remove session; if it's already gone, whoever removed it
+            // (e.g. an explicit close) has announced it
+            if state.coordinator.sessions.remove(session_id).is_some() {
+                // Broadcast to IPC clients wrapped in envelope
+                let event = IpcEvent::SessionClosed {
+                    session_id: session_id.to_string(),
+                    exit_code,
+                    reason: Some(reason),
+                };
+                let _ = ipc_event_tx.send(epoch.wrap_event(event));
+            }
         }
 
         ConnectionEvent::SessionData {
             machine_id: _,
             session_id,
             data,
+            stream,
         } => {
-            // Broadcast to IPC clients wrapped in envelope
-            let event = IpcEvent::TerminalOutput {
-                session_id: session_id.to_string(),
-                data,
+            let Some(session) = state.coordinator.sessions.get(session_id) else {
+                return;
             };
-            let _ = ipc_event_tx.send(epoch.wrap_event(event));
+            let activity = session.monitor().on_output(&data, Instant::now());
+            // Broadcast to IPC clients wrapped in envelope
+            if !session.emit_output(ipc_event_tx, epoch, &state.coalescing, data, stream) {
+                return;
+            }
+            for kind in activity {
+                let event = IpcEvent::SessionActivity {
+                    session_id: session_id.to_string(),
+                    kind,
+                };
+                session.emit(ipc_event_tx, epoch, event);
+            }
         }
     }
 }
@@ -0,0 +1,317 @@
+//! Actions for the frontend's command palette
+//!
+//! The palette lists what can be done right now: a new session on each
+//! connected machine, attaching to each session, disconnecting machines,
+//! starting or stopping the orchestrator and opening settings. The list is
+//! computed here from the orchestrator mode and the machines and sessions
+//! the orchestrator reports, on every `list_palette_actions` call, so it
+//! never offers something the backend can't do.
+//!
+//! Action IDs are `<kind>` or `<kind>:<target>`, e.g.
+//! `new_session:home-server`; `invoke_palette_action` parses them back with
+//! [`PaletteCommand::parse`] and routes them to the existing commands.
+
+use serde::Serialize;
+
+use crate::commands::{MachinePayload, SessionPayload};
+use crate::state::OrchestratorMode;
+
+/// Action of the command palette as sent to the frontend (`PaletteAction`
+/// in `src/types/index.ts`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PaletteAction {
+    /// ID to pass to `invoke_palette_action`
+    pub id: String,
+    /// Title shown in the palette
+    pub title: String,
+    /// Whether it can be invoked right now; disabled actions are listed
+    /// so the palette can show them greyed out
+    pub enabled: bool,
+}
+
+/// What a palette action does, parsed from its ID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaletteCommand {
+    NewSession { machine_id: String },
+    AttachSession { session_id: String },
+    DisconnectMachine { machine_id: String },
+    StartOrchestrator,
+    StopOrchestrator,
+    OpenSettings,
+}
+
+impl PaletteCommand {
+    /// The command of action `id`, if it names one
+    pub fn parse(id: &str) -> Option<Self> {
+        let (kind, target) = match id.split_once(':') {
+            Some((kind, target)) if !target.is_empty() => (kind, Some(target.to_string())),
+            Some(_) => return None,
+            None => (id, None),
+        };
+        match (kind, target) {
+            ("new_session", Some(machine_id)) => Some(Self::NewSession { machine_id }),
+            ("attach_session", Some(session_id)) => Some(Self::AttachSession { session_id }),
+            ("disconnect_machine", Some(machine_id)) => {
+                Some(Self::DisconnectMachine { machine_id })
+            }
+            ("start_orchestrator", None) => Some(Self::StartOrchestrator),
+            ("stop_orchestrator", None) => Some(Self::StopOrchestrator),
+            ("open_settings", None) => Some(Self::OpenSettings),
+            _ => None,
+        }
+    }
+
+    /// ID of the action running this command
+    pub fn id(&self) -> String {
+        match self {
+            Self::NewSession { machine_id } => format!("new_session:{}", machine_id),
+            Self::AttachSession { session_id } => format!("attach_session:{}", session_id),
+            Self::DisconnectMachine { machine_id } => format!("disconnect_machine:{}", machine_id),
+            Self::StartOrchestrator => "start_orchestrator".to_string(),
+            Self::StopOrchestrator => "stop_orchestrator".to_string(),
+            Self::OpenSettings => "open_settings".to_string(),
+        }
+    }
+}
+
+/// What the app is doing, as far as the palette is concerned
+#[derive(Debug, Clone, Copy)]
+pub struct PaletteContext<'a> {
+    /// How the orchestrator was started
+    pub mode: OrchestratorMode,
+    /// Whether the embedded orchestrator is running
+    pub embedded_running: bool,
+    /// Machines the orchestrator reports
+    pub machines: &'a [MachinePayload],
+    /// Sessions the orchestrator reports
+    pub sessions: &'a [SessionPayload],
+}
+
+impl PaletteContext<'_> {
+    fn is_connected(&self, machine_id: &str) -> bool {
+        self.machines
+            .iter()
+            .any(|machine| machine.id == machine_id && machine.status == "connected")
+    }
+
+    fn machine_name(&self, machine_id: &str) -> String {
+        self.machines
+            .iter()
+            .find(|machine| machine.id == machine_id)
+            .map(|machine| {
+                machine
+                    .alias
+                    .clone()
+                    .unwrap_or_else(|| machine.hostname.clone())
+            })
+            .unwrap_or_else(|| machine_id.to_string())
+    }
+
+    /// Whether `command` can run right now
+    pub fn is_enabled(&self, command: &PaletteCommand) -> bool {
+        match command {
+            PaletteCommand::NewSession { machine_id }
+            | PaletteCommand::DisconnectMachine { machine_id } => self.is_connected(machine_id),
+            PaletteCommand::AttachSession { session_id } => self
+                .sessions
+                .iter()
+                .find(|session| session.id == *session_id)
+                .is_some_and(|session| self.is_connected(&session.machine_id)),
+            // An external orchestrator is not ours to start or stop
+            PaletteCommand::StartOrchestrator => {
+                self.mode != OrchestratorMode::External && !self.embedded_running
+            }
+            PaletteCommand::StopOrchestrator => {
+                self.mode == OrchestratorMode::Embedded && self.embedded_running
+            }
+            PaletteCommand::OpenSettings => true,
+        }
+    }
+
+    fn action(&self, command: PaletteCommand, title: String) -> PaletteAction {
+        PaletteAction {
+            id: command.id(),
+            enabled: self.is_enabled(&command),
+            title,
+        }
+    }
+
+    /// Every palette action, machines and sessions in the order reported
+    pub fn actions(&self) -> Vec<PaletteAction> {
+        let mut actions = Vec::new();
+        for machine in self.machines {
+            let name = self.machine_name(&machine.id);
+            actions.push(self.action(
+                PaletteCommand::NewSession {
+                    machine_id: machine.id.clone(),
+                },
+                format!("New session on {}", name),
+            ));
+        }
+        for session in self.sessions {
+            let label = session.name.as_deref().unwrap_or(&session.id);
+            actions.push(self.action(
+                PaletteCommand::AttachSession {
+                    session_id: session.id.clone(),
+                },
+                format!(
+                    "Attach to {} on {}",
+                    label,
+                    self.machine_name(&session.machine_id)
+                ),
+            ));
+        }
+        for machine in self.machines {
+            actions.push(self.action(
+                PaletteCommand::DisconnectMachine {
+                    machine_id: machine.id.clone(),
+                },
+                format!("Disconnect {}", self.machine_name(&machine.id)),
+            ));
+        }
+        actions.push(self.action(
+            PaletteCommand::StartOrchestrator,
+            "Start orchestrator".to_string(),
+        ));
+        actions.push(self.action(
+            PaletteCommand::StopOrchestrator,
+            "Stop orchestrator".to_string(),
+        ));
+        actions.push(self.action(PaletteCommand::OpenSettings, "Open settings".to_string()));
+        actions
+    }
+}
+
+/// What the frontend should do after invoking a palette action
+/// (`PaletteOutcome` in `src/types/index.ts`)
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum PaletteOutcome {
+    /// Nothing more
+    Done,
+    /// Open a terminal for this new session
+    SessionCreated { session: SessionPayload },
+    /// Show the terminal of this session, now subscribed to
+    #[serde(rename_all = "camelCase")]
+    SessionAttached { session_id: String },
+    /// Show the settings
+    OpenSettings,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(id: &str, status: &str) -> MachinePayload {
+        MachinePayload {
+            id: id.to_string(),
+            alias: None,
+            label: None,
+            hostname: format!("{}.lan", id),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: status.to_string(),
+            connected_at: None,
+            last_heartbeat: None,
+            session_count: 0,
+            tags: None,
+            capabilities: vec![],
+            reconnect_count: 0,
+        }
+    }
+
+    fn session(id: &str, machine_id: &str) -> SessionPayload {
+        SessionPayload {
+            id: id.to_string(),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            pid: None,
+            name: None,
+            size: None,
+            detached: false,
+        }
+    }
+
+    fn enabled(context: &PaletteContext<'_>, id: &str) -> bool {
+        context
+            .actions()
+            .iter()
+            .find(|action| action.id == id)
+            .unwrap_or_else(|| panic!("no action {}", id))
+            .enabled
+    }
+
+    #[test]
+    fn test_orchestrator_actions_follow_mode() {
+        let context = |mode, embedded_running| PaletteContext {
+            mode,
+            embedded_running,
+            machines: &[],
+            sessions: &[],
+        };
+
+        let external = context(OrchestratorMode::External, false);
+        assert!(!enabled(&external, "stop_orchestrator"));
+        assert!(!enabled(&external, "start_orchestrator"));
+
+        let embedded = context(OrchestratorMode::Embedded, true);
+        assert!(enabled(&embedded, "stop_orchestrator"));
+        assert!(!enabled(&embedded, "start_orchestrator"));
+
+        let stopped = context(OrchestratorMode::NotConnected, false);
+        assert!(!enabled(&stopped, "stop_orchestrator"));
+        assert!(enabled(&stopped, "start_orchestrator"));
+        assert!(enabled(&stopped, "open_settings"));
+    }
+
+    #[test]
+    fn test_machine_and_session_actions() {
+        let machines = [machine("web", "connected"), machine("db", "disconnected")];
+        let sessions = [session("s-1", "web"), session("s-2", "db")];
+        let context = PaletteContext {
+            mode: OrchestratorMode::External,
+            embedded_running: false,
+            machines: &machines,
+            sessions: &sessions,
+        };
+
+        assert!(enabled(&context, "new_session:web"));
+        assert!(!enabled(&context, "new_session:db"));
+        assert!(enabled(&context, "attach_session:s-1"));
+        assert!(!enabled(&context, "attach_session:s-2"));
+        assert!(enabled(&context, "disconnect_machine:web"));
+        assert!(!enabled(&context, "disconnect_machine:db"));
+
+        let titles: Vec<String> = context.actions().into_iter().map(|a| a.title).collect();
+        assert!(titles.contains(&"New session on web.lan".to_string()));
+        assert!(titles.contains(&"Attach to s-1 on web.lan".to_string()));
+
+        // Things that went away since the palette was listed are disabled
+        let gone = PaletteCommand::AttachSession {
+            session_id: "s-3".to_string(),
+        };
+        assert!(!context.is_enabled(&gone));
+    }
+
+    #[test]
+    fn test_parse_ids() {
+        for command in [
+            PaletteCommand::NewSession {
+                machine_id: "web".to_string(),
+            },
+            PaletteCommand::AttachSession {
+                session_id: "s-1".to_string(),
+            },
+            PaletteCommand::StopOrchestrator,
+            PaletteCommand::OpenSettings,
+        ] {
+            assert_eq!(PaletteCommand::parse(&command.id()), Some(command));
+        }
+        assert_eq!(PaletteCommand::parse("new_session"), None);
+        assert_eq!(PaletteCommand::parse("new_session:"), None);
+        assert_eq!(PaletteCommand::parse("open_settings:now"), None);
+        assert_eq!(PaletteCommand::parse("reboot"), None);
+    }
+}
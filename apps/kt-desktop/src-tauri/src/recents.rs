@@ -0,0 +1,616 @@
+//! Recent and pinned machines for the frontend's "recents" list
+//!
+//! Kept in the backend so the list survives frontend reloads. Every
+//! successful `create_session`/`subscribe_session` bumps the machine's
+//! recency, and `pin_machine`/`unpin_machine` manage favorites. The list is
+//! saved to `recents.json` in the app data dir, at most once per
+//! [`SAVE_DEBOUNCE`], by renaming a complete temporary file over the old one
+//! so a crash never leaves it half-written.
+//!
+//! [`RecentsStore::recents`] annotates each entry with its connection status
+//! from the machines the app last heard about (state snapshots, listings and
+//! events). Machines the orchestrator no longer reports are marked stale
+//! rather than dropped.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use kt_core::ipc::IpcEvent;
+use kt_core::time::current_time_millis;
+use kt_core::MachineId;
+
+use crate::commands::{MachinePayload, SessionPayload};
+
+/// Name of the recents file in the app data dir
+pub const RECENTS_FILE_NAME: &str = "recents.json";
+
+/// How long after a change the recents file is saved; later changes in
+/// that window are saved with it
+pub const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Most unpinned machines remembered (pinned machines are never dropped)
+pub const MAX_RECENTS: usize = 20;
+
+/// A remembered machine, as saved in `recents.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RememberedMachine {
+    id: String,
+    /// Alias or hostname when last seen, so stale entries can still be named
+    #[serde(default)]
+    label: Option<String>,
+    /// When a session was last opened on it (milliseconds since Unix epoch)
+    #[serde(default)]
+    last_used_ms: Option<u64>,
+    #[serde(default)]
+    pinned: bool,
+}
+
+/// Contents of `recents.json`
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RecentsFile {
+    #[serde(default)]
+    machines: Vec<RememberedMachine>,
+}
+
+/// Entry of the recents list as sent to the frontend (`RecentMachine` in
+/// `src/types/index.ts`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentMachinePayload {
+    /// Machine ID
+    pub machine_id: String,
+    /// Alias or hostname, as last seen
+    pub label: Option<String>,
+    /// Pinned as a favorite
+    pub pinned: bool,
+    /// When a session was last opened on it (milliseconds since Unix epoch)
+    pub last_used_ms: Option<u64>,
+    /// Current status, "disconnected" if the orchestrator doesn't report it
+    pub status: String,
+    /// The orchestrator has forgotten this machine
+    pub stale: bool,
+}
+
+/// Recent and pinned machines, with the connection state they're shown with
+pub struct RecentsStore {
+    inner: Mutex<Inner>,
+    /// Where the list is saved (None keeps it in memory only)
+    path: Option<PathBuf>,
+    /// Signalled on every change to the saved list
+    changed: Notify,
+}
+
+struct Inner {
+    /// Remembered machines, pinned or recently used
+    machines: Vec<RememberedMachine>,
+    /// Machines the orchestrator last reported, by ID
+    known: HashMap<String, MachinePayload>,
+    /// Machine of each session the orchestrator last reported
+    session_machines: HashMap<String, String>,
+    /// Whether `known` has been filled from a full machine list, so absent
+    /// machines are known to be forgotten
+    synced: bool,
+}
+
+impl RecentsStore {
+    /// Load the list saved at `path`, starting empty if there is none
+    ///
+    /// Entries with invalid machine IDs are dropped, and an unreadable file
+    /// is replaced on the next change.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let machines = match path.as_deref().map(read_recents_file) {
+            Some(Ok(file)) => sanitize(file.machines),
+            Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Some(Err(e)) => {
+                tracing::warn!("Failed to read recents from {:?}: {}", path, e);
+                Vec::new()
+            }
+            None => Vec::new(),
+        };
+
+        Self {
+            inner: Mutex::new(Inner {
+                machines,
+                known: HashMap::new(),
+                session_machines: HashMap::new(),
+                synced: false,
+            }),
+            path,
+            changed: Notify::new(),
+        }
+    }
+
+    /// Bump a machine to the top of the recents
+    pub fn touch_machine(&self, machine_id: &str) {
+        let Ok(id) = MachineId::parse(machine_id) else {
+            return;
+        };
+        {
+            let mut inner = self.inner.lock();
+            let label = inner.label_of(id.as_str());
+            let machine = inner.remember(id.as_str());
+            machine.last_used_ms = Some(current_time_millis());
+            if label.is_some() {
+                machine.label = label;
+            }
+            inner.trim();
+        }
+        self.changed.notify_one();
+    }
+
+    /// Bump the machine a session runs on, if the session is known
+    ///
+    /// Returns false if the session's machine isn't known.
+    pub fn touch_session(&self, session_id: &str) -> bool {
+        match self.session_machine(session_id) {
+            Some(machine_id) => {
+                self.touch_machine(&machine_id);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pin a machine as a favorite
+    pub fn pin(&self, machine_id: &str) -> Result<(), String> {
+        let id = MachineId::parse(machine_id).map_err(|e| e.to_string())?;
+        {
+            let mut inner = self.inner.lock();
+            let label = inner.label_of(id.as_str());
+            let machine = inner.remember(id.as_str());
+            if machine.pinned {
+                return Ok(());
+            }
+            machine.pinned = true;
+            if label.is_some() {
+                machine.label = label;
+            }
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Unpin a machine, keeping it as a recent if a session was opened on it
+    pub fn unpin(&self, machine_id: &str) -> Result<(), String> {
+        let id = MachineId::parse(machine_id).map_err(|e| e.to_string())?;
+        {
+            let mut inner = self.inner.lock();
+            let Some(index) = inner.machines.iter().position(|m| m.id == id.as_str()) else {
+                return Ok(());
+            };
+            if !inner.machines[index].pinned {
+                return Ok(());
+            }
+            if inner.machines[index].last_used_ms.is_some() {
+                inner.machines[index].pinned = false;
+            } else {
+                inner.machines.remove(index);
+            }
+            inner.trim();
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Pinned machines, then the rest, each most recently used first
+    pub fn recents(&self) -> Vec<RecentMachinePayload> {
+        let inner = self.inner.lock();
+        let mut machines = inner.machines.clone();
+        machines.sort_by(|a, b| {
+            b.pinned
+                .cmp(&a.pinned)
+                .then_with(|| b.last_used_ms.cmp(&a.last_used_ms))
+        });
+        machines
+            .into_iter()
+            .map(|machine| {
+                let known = inner.known.get(&machine.id);
+                RecentMachinePayload {
+                    label: known.and_then(label_of).or(machine.label),
+                    pinned: machine.pinned,
+                    last_used_ms: machine.last_used_ms,
+                    status: known
+                        .map(|m| m.status.clone())
+                        .unwrap_or_else(|| "disconnected".to_string()),
+                    stale: inner.synced && known.is_none(),
+                    machine_id: machine.id,
+                }
+            })
+            .collect()
+    }
+
+    /// Machine a known session runs on
+    pub fn session_machine(&self, session_id: &str) -> Option<String> {
+        self.inner.lock().session_machines.get(session_id).cloned()
+    }
+
+    /// Machines the orchestrator reports or the list remembers, or None
+    /// until the orchestrator's machines are known
+    pub fn machines_in_use(&self) -> Option<HashSet<String>> {
+        let inner = self.inner.lock();
+        if !inner.synced {
+            return None;
+        }
+        let remembered = inner.machines.iter().map(|m| m.id.clone());
+        Some(inner.known.keys().cloned().chain(remembered).collect())
+    }
+
+    /// Replace the known machines with a full list from the orchestrator
+    pub fn sync_machines(&self, machines: &[MachinePayload]) {
+        let mut inner = self.inner.lock();
+        inner.known = machines
+            .iter()
+            .map(|machine| (machine.id.clone(), machine.clone()))
+            .collect();
+        inner.synced = true;
+    }
+
+    /// Replace the known sessions with a full list from the orchestrator
+    pub fn sync_sessions(&self, sessions: &[SessionPayload]) {
+        self.inner.lock().session_machines = sessions
+            .iter()
+            .map(|session| (session.id.clone(), session.machine_id.clone()))
+            .collect();
+    }
+
+    /// Remember which machine a session runs on
+    pub fn record_session(&self, session: &SessionPayload) {
+        self.inner
+            .lock()
+            .session_machines
+            .insert(session.id.clone(), session.machine_id.clone());
+    }
+
+    /// Keep the known machines and sessions up to date with an event
+    pub fn apply_event(&self, event: &IpcEvent) {
+        let mut inner = self.inner.lock();
+        match event {
+            IpcEvent::MachineConnected(machine) | IpcEvent::MachineUpdated(machine) => {
+                inner
+                    .known
+                    .insert(machine.id.clone(), MachinePayload::from(machine.clone()));
+            }
+            IpcEvent::MachineDisconnected { machine_id } => {
+                inner.known.remove(machine_id);
+                inner.session_machines.retain(|_, m| m != machine_id);
+            }
+            IpcEvent::SessionCreated(session) => {
+                inner
+                    .session_machines
+                    .insert(session.id.clone(), session.machine_id.clone());
+            }
+            IpcEvent::SessionClosed { session_id, .. } => {
+                inner.session_machines.remove(session_id);
+            }
+            _ => {}
+        }
+    }
+
+    /// Save the list after each change, once no change has followed for
+    /// [`SAVE_DEBOUNCE`]
+    pub async fn run_saver(self: Arc<Self>) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        loop {
+            self.changed.notified().await;
+            tokio::time::sleep(SAVE_DEBOUNCE).await;
+
+            let file = RecentsFile {
+                machines: self.inner.lock().machines.clone(),
+            };
+            let path = path.clone();
+            let result =
+                tokio::task::spawn_blocking(move || write_recents_file(&path, &file)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to save recents: {}", e),
+                Err(e) => tracing::warn!("Recents save task failed: {}", e),
+            }
+        }
+    }
+}
+
+impl Inner {
+    /// Current label of a known machine
+    fn label_of(&self, machine_id: &str) -> Option<String> {
+        self.known.get(machine_id).and_then(label_of)
+    }
+
+    /// The remembered entry for a machine, added if there is none
+    fn remember(&mut self, machine_id: &str) -> &mut RememberedMachine {
+        let index = match self.machines.iter().position(|m| m.id == machine_id) {
+            Some(index) => index,
+            None => {
+                self.machines.push(RememberedMachine {
+                    id: machine_id.to_string(),
+                    label: None,
+                    last_used_ms: None,
+                    pinned: false,
+                });
+                self.machines.len() - 1
+            }
+        };
+        &mut self.machines[index]
+    }
+
+    /// Forget the least recently used unpinned machines past [`MAX_RECENTS`]
+    fn trim(&mut self) {
+        let mut unpinned: Vec<&RememberedMachine> =
+            self.machines.iter().filter(|m| !m.pinned).collect();
+        if unpinned.len() <= MAX_RECENTS {
+            return;
+        }
+        unpinned.sort_by_key(|m| std::cmp::Reverse(m.last_used_ms));
+        let forgotten: HashSet<String> = unpinned[MAX_RECENTS..]
+            .iter()
+            .map(|m| m.id.clone())
+            .collect();
+        self.machines.retain(|m| !forgotten.contains(&m.id));
+    }
+}
+
+/// Alias or hostname of a machine
+fn label_of(machine: &MachinePayload) -> Option<String> {
+    let label = machine.alias.as_deref().unwrap_or(&machine.hostname);
+    (!label.is_empty()).then(|| label.to_string())
+}
+
+/// Drop entries with invalid IDs and merge duplicates
+fn sanitize(machines: Vec<RememberedMachine>) -> Vec<RememberedMachine> {
+    let mut sanitized: Vec<RememberedMachine> = Vec::with_capacity(machines.len());
+    for mut machine in machines {
+        let id = match MachineId::parse(&machine.id) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!("Dropping recent machine {:?}: {}", machine.id, e);
+                continue;
+            }
+        };
+        machine.id = id.to_string();
+        match sanitized.iter_mut().find(|m| m.id == machine.id) {
+            Some(existing) => {
+                existing.pinned |= machine.pinned;
+                existing.last_used_ms = existing.last_used_ms.max(machine.last_used_ms);
+                existing.label = existing.label.take().or(machine.label);
+            }
+            None => sanitized.push(machine),
+        }
+    }
+    sanitized
+}
+
+fn read_recents_file(path: &Path) -> io::Result<RecentsFile> {
+    let json = std::fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write the recents file, replacing the old one in a single rename
+fn write_recents_file(path: &Path, file: &RecentsFile) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(file)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let tmp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&tmp_path, json)?;
+    let result = std::fs::rename(&tmp_path, path);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(id: &str, alias: &str) -> MachinePayload {
+        MachinePayload {
+            id: id.to_string(),
+            alias: Some(alias.to_string()),
+            label: None,
+            hostname: format!("{}.lan", id),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: "connected".to_string(),
+            connected_at: None,
+            last_heartbeat: None,
+            session_count: 0,
+            tags: None,
+            capabilities: vec![],
+            reconnect_count: 0,
+        }
+    }
+
+    fn session(id: &str, machine_id: &str) -> SessionPayload {
+        SessionPayload {
+            id: id.to_string(),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: String::new(),
+            pid: None,
+            name: None,
+            size: None,
+            detached: false,
+        }
+    }
+
+    fn ids(store: &RecentsStore) -> Vec<String> {
+        store.recents().into_iter().map(|r| r.machine_id).collect()
+    }
+
+    #[test]
+    fn test_recents_order_pinned_then_most_recent() {
+        let store = RecentsStore::load(None);
+        store.touch_machine("alpha");
+        std::thread::sleep(Duration::from_millis(2));
+        store.touch_machine("beta");
+        store.pin("gamma").unwrap();
+        assert_eq!(ids(&store), ["gamma", "beta", "alpha"]);
+
+        std::thread::sleep(Duration::from_millis(2));
+        store.touch_machine("alpha");
+        assert_eq!(ids(&store), ["gamma", "alpha", "beta"]);
+
+        // Never used, so unpinning forgets it
+        store.unpin("gamma").unwrap();
+        assert_eq!(ids(&store), ["alpha", "beta"]);
+
+        store.pin("beta").unwrap();
+        store.unpin("beta").unwrap();
+        assert_eq!(ids(&store), ["alpha", "beta"]);
+    }
+
+    #[test]
+    fn test_pin_validates_machine_id() {
+        let store = RecentsStore::load(None);
+        assert!(store.pin("").is_err());
+        assert!(store.pin("bad id").is_err());
+
+        // IDs are canonicalized, so both name one machine
+        store.pin("GPU-Box").unwrap();
+        store.touch_machine("gpu-box");
+        assert_eq!(ids(&store), ["gpu-box"]);
+    }
+
+    #[test]
+    fn test_forgotten_machines_marked_stale() {
+        let store = RecentsStore::load(None);
+        store.touch_machine("alpha");
+        store.pin("beta").unwrap();
+
+        // Nothing is stale until the orchestrator's machines are known
+        assert!(store.recents().iter().all(|r| !r.stale));
+
+        store.sync_machines(&[machine("alpha", "build")]);
+        let recents = store.recents();
+        assert_eq!(recents[0].machine_id, "beta");
+        assert!(recents[0].stale);
+        assert_eq!(recents[0].status, "disconnected");
+        assert_eq!(recents[1].machine_id, "alpha");
+        assert!(!recents[1].stale);
+        assert_eq!(recents[1].status, "connected");
+        assert_eq!(recents[1].label.as_deref(), Some("build"));
+
+        // The label is remembered once the machine is gone
+        store.touch_machine("alpha");
+        store.apply_event(&IpcEvent::MachineDisconnected {
+            machine_id: "alpha".to_string(),
+        });
+        let alpha = store
+            .recents()
+            .into_iter()
+            .find(|r| r.machine_id == "alpha")
+            .unwrap();
+        assert!(alpha.stale);
+        assert_eq!(alpha.label.as_deref(), Some("build"));
+    }
+
+    #[test]
+    fn test_touch_session_bumps_its_machine() {
+        let store = RecentsStore::load(None);
+        assert!(!store.touch_session("session-1"));
+
+        store.sync_sessions(&[session("session-1", "alpha")]);
+        store.record_session(&session("session-2", "beta"));
+        assert!(store.touch_session("session-1"));
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(store.touch_session("session-2"));
+        assert_eq!(ids(&store), ["beta", "alpha"]);
+
+        store.apply_event(&IpcEvent::SessionClosed {
+            session_id: "session-2".to_string(),
+            exit_code: None,
+            reason: None,
+        });
+        assert!(!store.touch_session("session-2"));
+    }
+
+    #[test]
+    fn test_machines_in_use() {
+        let store = RecentsStore::load(None);
+        store.pin("alpha").unwrap();
+        assert_eq!(store.machines_in_use(), None);
+
+        store.sync_machines(&[machine("beta", "build")]);
+        let in_use = store.machines_in_use().unwrap();
+        assert_eq!(in_use.len(), 2);
+        assert!(in_use.contains("alpha") && in_use.contains("beta"));
+
+        store.unpin("alpha").unwrap();
+        assert!(!store.machines_in_use().unwrap().contains("alpha"));
+    }
+
+    #[test]
+    fn test_unpinned_recents_are_capped() {
+        let store = RecentsStore::load(None);
+        store.pin("pinned").unwrap();
+        for i in 0..MAX_RECENTS + 5 {
+            store.touch_machine(&format!("machine-{}", i));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        let recents = ids(&store);
+        assert_eq!(recents.len(), MAX_RECENTS + 1);
+        assert_eq!(recents[0], "pinned");
+        assert_eq!(recents[1], format!("machine-{}", MAX_RECENTS + 4));
+        assert!(!recents.contains(&"machine-4".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_saved_list_survives_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(RECENTS_FILE_NAME);
+
+        let store = Arc::new(RecentsStore::load(Some(path.clone())));
+        let saver = tokio::spawn(store.clone().run_saver());
+        store.pin("alpha").unwrap();
+        store.touch_machine("beta");
+        tokio::time::sleep(SAVE_DEBOUNCE + Duration::from_millis(500)).await;
+        saver.abort();
+
+        // Saved by rename, so no temporary file is left behind
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let reloaded = RecentsStore::load(Some(path));
+        assert_eq!(reloaded.recents(), store.recents());
+    }
+
+    #[test]
+    fn test_load_drops_invalid_and_duplicate_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(RECENTS_FILE_NAME);
+        std::fs::write(
+            &path,
+            r#"{"machines":[
+                {"id":"Alpha","lastUsedMs":5},
+                {"id":"bad id","pinned":true},
+                {"id":"alpha","pinned":true,"label":"build"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let recents = RecentsStore::load(Some(path)).recents();
+        assert_eq!(recents.len(), 1);
+        assert_eq!(recents[0].machine_id, "alpha");
+        assert!(recents[0].pinned);
+        assert_eq!(recents[0].last_used_ms, Some(5));
+        assert_eq!(recents[0].label.as_deref(), Some("build"));
+
+        // An unreadable file starts an empty list
+        let corrupt = dir.path().join("corrupt.json");
+        std::fs::write(&corrupt, "{").unwrap();
+        assert!(RecentsStore::load(Some(corrupt)).recents().is_empty());
+    }
+}
@@ -2,11 +2,18 @@
 
 use std::sync::Arc;
 
+use kt_core::config::DEFAULT_RESIZE_DEBOUNCE;
+use kt_orchestrator::logging::LogSource;
+use kt_orchestrator::session::ResizeDebouncer;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
-use crate::ipc_client::{EventSubscriber, PersistentIpcClient};
+use crate::events::ForwardStats;
+use crate::input_confirm::InputConfirmations;
+use crate::ipc_client::{AuthMonitor, EventSubscriber, PersistentIpcClient};
 use crate::orchestrator::EmbeddedOrchestrator;
+use crate::recents::RecentsStore;
+use crate::terminal_prefs::TerminalPrefsStore;
 
 /// How the orchestrator was started
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -30,6 +37,19 @@ pub struct AppState {
     pub orchestrator: Arc<RwLock<EmbeddedOrchestrator>>,
     /// How the orchestrator was started (embedded vs external)
     pub orchestrator_mode: Arc<RwLock<OrchestratorMode>>,
+    /// Authentication failure tracking shared by both IPC connections
+    pub auth: Arc<AuthMonitor>,
+    /// Recent and pinned machines
+    pub recents: Arc<RecentsStore>,
+    /// Terminal preferences, global, per machine and per session
+    pub terminal_prefs: Arc<TerminalPrefsStore>,
+    /// Terminal resizes held back so they aren't sent faster than the
+    /// orchestrator forwards them
+    pub resizes: Arc<ResizeDebouncer<String>>,
+    /// Counters of the event forwarder, e.g. terminal output dropped
+    pub forward_stats: Arc<ForwardStats>,
+    /// Sessions input may be sent to without asking first
+    pub input_confirmations: Arc<InputConfirmations>,
 }
 
 impl AppState {
@@ -41,16 +61,49 @@ impl AppState {
         let client_id = Uuid::new_v4().to_string();
         tracing::info!("Generated client ID for session ownership: {}", client_id);
 
+        let auth = Arc::new(AuthMonitor::new());
+
         Self {
-            ipc: Arc::new(PersistentIpcClient::new(address.clone(), client_id.clone())),
+            ipc: Arc::new(
+                PersistentIpcClient::new(address.clone(), client_id.clone())
+                    .with_auth_monitor(auth.clone()),
+            ),
             event_subscriber: Arc::new(RwLock::new(
-                EventSubscriber::new(address).with_client_id(client_id),
+                EventSubscriber::new(address)
+                    .with_client_id(client_id)
+                    .with_auth_monitor(auth.clone()),
             )),
             orchestrator: Arc::new(RwLock::new(EmbeddedOrchestrator::new())),
             orchestrator_mode: Arc::new(RwLock::new(OrchestratorMode::NotConnected)),
+            auth,
+            recents: Arc::new(RecentsStore::load(None)),
+            terminal_prefs: Arc::new(TerminalPrefsStore::load(None)),
+            resizes: Arc::new(ResizeDebouncer::new(DEFAULT_RESIZE_DEBOUNCE)),
+            forward_stats: Arc::new(ForwardStats::default()),
+            input_confirmations: Arc::new(InputConfirmations::new()),
         }
     }
 
+    /// Serve the embedded orchestrator's `TailLogs` requests from captured logs
+    pub fn with_log_source(mut self, log_source: LogSource) -> Self {
+        self.orchestrator = Arc::new(RwLock::new(
+            EmbeddedOrchestrator::new().with_log_source(log_source),
+        ));
+        self
+    }
+
+    /// Keep recent and pinned machines in `recents` (loaded from disk)
+    pub fn with_recents(mut self, recents: RecentsStore) -> Self {
+        self.recents = Arc::new(recents);
+        self
+    }
+
+    /// Keep terminal preferences in `terminal_prefs` (loaded from disk)
+    pub fn with_terminal_prefs(mut self, terminal_prefs: TerminalPrefsStore) -> Self {
+        self.terminal_prefs = Arc::new(terminal_prefs);
+        self
+    }
+
     /// Set the orchestrator mode
     pub async fn set_mode(&self, mode: OrchestratorMode) {
         *self.orchestrator_mode.write().await = mode;
@@ -0,0 +1,646 @@
+//! Terminal preferences (font size, scrollback, cursor, bell, input
+//! confirmation)
+//!
+//! Kept in the backend so they survive restarts and frontend reloads.
+//! Preferences are set at three scopes: global, per machine and per
+//! session. Each scope only overrides the fields it sets, and the effective
+//! preferences of a session are the defaults overlaid by the global, its
+//! machine's and then its own overrides, so the frontend just applies them.
+//!
+//! The overrides are saved to `terminal-prefs.json` in the app data dir the
+//! same way as the recents list. Entries that can no longer apply are
+//! dropped: a session's when it closes or the orchestrator stops reporting
+//! it, and a machine's once it is forgotten, that is when the orchestrator
+//! doesn't report it and the recents list no longer remembers it. A machine
+//! that comes back under a new ID (e.g. after its host was renamed) starts
+//! from the global preferences, and its old entry goes when the old ID is
+//! forgotten.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+use kt_core::ipc::IpcEvent;
+use kt_core::MachineId;
+
+use crate::commands::SessionPayload;
+use crate::recents::SAVE_DEBOUNCE;
+
+/// Name of the terminal preferences file in the app data dir
+pub const TERMINAL_PREFS_FILE_NAME: &str = "terminal-prefs.json";
+
+/// Smallest font size accepted, in pixels
+pub const MIN_FONT_SIZE: u16 = 6;
+
+/// Largest font size accepted, in pixels
+pub const MAX_FONT_SIZE: u16 = 72;
+
+/// Most scrollback lines accepted
+pub const MAX_SCROLLBACK: u32 = 100_000;
+
+/// Cursor shape (xterm.js `cursorStyle`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CursorStyle {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+/// What a bell in the output does
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BellStyle {
+    /// Nothing
+    Off,
+    /// Flash the terminal and mark the session
+    #[default]
+    Visual,
+    /// Play a sound as well
+    Sound,
+}
+
+/// Effective terminal preferences (`TerminalPrefs` in `src/types/index.ts`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TerminalPrefs {
+    /// Font size in pixels
+    pub font_size: u16,
+    /// Lines kept above the screen
+    pub scrollback: u32,
+    pub cursor_style: CursorStyle,
+    pub bell: BellStyle,
+    /// Ask before the first input to a session this app didn't create
+    pub confirm_foreign_input: bool,
+}
+
+impl Default for TerminalPrefs {
+    fn default() -> Self {
+        Self {
+            font_size: 13,
+            scrollback: 1000,
+            cursor_style: CursorStyle::default(),
+            bell: BellStyle::default(),
+            confirm_foreign_input: true,
+        }
+    }
+}
+
+/// Preferences set at one scope; unset fields are inherited from the
+/// scope below (`TerminalPrefsOverrides` in `src/types/index.ts`)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TerminalPrefsOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub font_size: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scrollback: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor_style: Option<CursorStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bell: Option<BellStyle>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirm_foreign_input: Option<bool>,
+}
+
+impl TerminalPrefsOverrides {
+    /// Whether nothing is overridden
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check that the values set are in range
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(size) = self.font_size {
+            if !(MIN_FONT_SIZE..=MAX_FONT_SIZE).contains(&size) {
+                return Err(format!(
+                    "Font size must be between {} and {}, got {}",
+                    MIN_FONT_SIZE, MAX_FONT_SIZE, size
+                ));
+            }
+        }
+        if let Some(lines) = self.scrollback {
+            if lines > MAX_SCROLLBACK {
+                return Err(format!(
+                    "Scrollback must be at most {} lines, got {}",
+                    MAX_SCROLLBACK, lines
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Override the fields of `prefs` that are set here
+    fn apply_to(&self, prefs: &mut TerminalPrefs) {
+        if let Some(font_size) = self.font_size {
+            prefs.font_size = font_size;
+        }
+        if let Some(scrollback) = self.scrollback {
+            prefs.scrollback = scrollback;
+        }
+        if let Some(cursor_style) = self.cursor_style {
+            prefs.cursor_style = cursor_style;
+        }
+        if let Some(bell) = self.bell {
+            prefs.bell = bell;
+        }
+        if let Some(confirm) = self.confirm_foreign_input {
+            prefs.confirm_foreign_input = confirm;
+        }
+    }
+}
+
+/// Where preferences are set (`TerminalPrefsScope` in `src/types/index.ts`)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "id", rename_all = "camelCase")]
+pub enum PrefsScope {
+    /// Every terminal
+    Global,
+    /// Terminals on a machine, by machine ID
+    Machine(String),
+    /// One terminal, by session ID
+    Session(String),
+}
+
+impl PrefsScope {
+    /// Check the scope's ID, canonicalizing machine IDs
+    fn normalize(self) -> Result<Self, String> {
+        match self {
+            Self::Global => Ok(Self::Global),
+            Self::Machine(id) => MachineId::parse(&id)
+                .map(|id| Self::Machine(id.to_string()))
+                .map_err(|e| e.to_string()),
+            Self::Session(id) if id.is_empty() => Err("Session ID cannot be empty".to_string()),
+            Self::Session(id) => Ok(Self::Session(id)),
+        }
+    }
+}
+
+/// Preferences at a scope, as sent to the frontend
+/// (`TerminalPrefsState` in `src/types/index.ts`)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalPrefsPayload {
+    pub scope: PrefsScope,
+    /// What is set at this scope itself
+    pub overrides: TerminalPrefsOverrides,
+    /// What applies at this scope once the scopes below are merged in
+    pub effective: TerminalPrefs,
+}
+
+/// Contents of `terminal-prefs.json`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+struct PrefsFile {
+    global: TerminalPrefsOverrides,
+    machines: BTreeMap<String, TerminalPrefsOverrides>,
+    sessions: BTreeMap<String, TerminalPrefsOverrides>,
+}
+
+impl PrefsFile {
+    /// The overrides at `scope`
+    fn overrides(&self, scope: &PrefsScope) -> Option<&TerminalPrefsOverrides> {
+        match scope {
+            PrefsScope::Global => Some(&self.global),
+            PrefsScope::Machine(id) => self.machines.get(id),
+            PrefsScope::Session(id) => self.sessions.get(id),
+        }
+    }
+}
+
+/// Terminal preferences at every scope
+pub struct TerminalPrefsStore {
+    file: Mutex<PrefsFile>,
+    /// Where the preferences are saved (None keeps them in memory only)
+    path: Option<PathBuf>,
+    /// Signalled on every change
+    changed: Notify,
+}
+
+impl TerminalPrefsStore {
+    /// Load the preferences saved at `path`, starting with none if there
+    /// are none
+    ///
+    /// Entries with invalid IDs or out-of-range values are dropped, and an
+    /// unreadable file is replaced on the next change.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = match path.as_deref().map(read_prefs_file) {
+            Some(Ok(file)) => sanitize(file),
+            Some(Err(e)) if e.kind() == io::ErrorKind::NotFound => PrefsFile::default(),
+            Some(Err(e)) => {
+                tracing::warn!("Failed to read terminal preferences from {:?}: {}", path, e);
+                PrefsFile::default()
+            }
+            None => PrefsFile::default(),
+        };
+
+        Self {
+            file: Mutex::new(file),
+            path,
+            changed: Notify::new(),
+        }
+    }
+
+    /// Preferences at `scope`
+    ///
+    /// `machine_id` is the machine a session scope's session runs on, if
+    /// known; without it the session's machine overrides don't apply.
+    pub fn get(
+        &self,
+        scope: PrefsScope,
+        machine_id: Option<&str>,
+    ) -> Result<TerminalPrefsPayload, String> {
+        let scope = scope.normalize()?;
+        let file = self.file.lock();
+
+        let mut effective = TerminalPrefs::default();
+        file.global.apply_to(&mut effective);
+        let machine = match &scope {
+            PrefsScope::Global => None,
+            PrefsScope::Machine(id) => Some(id.clone()),
+            PrefsScope::Session(_) => machine_id
+                .and_then(|id| MachineId::parse(id).ok())
+                .map(|id| id.to_string()),
+        };
+        if let Some(overrides) = machine.and_then(|id| file.machines.get(&id)) {
+            overrides.apply_to(&mut effective);
+        }
+        if let PrefsScope::Session(id) = &scope {
+            if let Some(overrides) = file.sessions.get(id) {
+                overrides.apply_to(&mut effective);
+            }
+        }
+
+        Ok(TerminalPrefsPayload {
+            overrides: file.overrides(&scope).cloned().unwrap_or_default(),
+            scope,
+            effective,
+        })
+    }
+
+    /// Replace the overrides at `scope`; empty overrides remove the entry
+    pub fn set(&self, scope: PrefsScope, overrides: TerminalPrefsOverrides) -> Result<(), String> {
+        let scope = scope.normalize()?;
+        overrides.validate()?;
+        {
+            let mut file = self.file.lock();
+            if file.overrides(&scope) == Some(&overrides) {
+                return Ok(());
+            }
+            match scope {
+                PrefsScope::Global => file.global = overrides,
+                PrefsScope::Machine(id) if overrides.is_empty() => {
+                    file.machines.remove(&id);
+                }
+                PrefsScope::Machine(id) => {
+                    file.machines.insert(id, overrides);
+                }
+                PrefsScope::Session(id) if overrides.is_empty() => {
+                    file.sessions.remove(&id);
+                }
+                PrefsScope::Session(id) => {
+                    file.sessions.insert(id, overrides);
+                }
+            }
+        }
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Drop the entries of sessions missing from a full list from the
+    /// orchestrator
+    pub fn retain_sessions(&self, sessions: &[SessionPayload]) {
+        let ids: HashSet<&str> = sessions.iter().map(|s| s.id.as_str()).collect();
+        let removed = {
+            let mut file = self.file.lock();
+            let before = file.sessions.len();
+            file.sessions.retain(|id, _| ids.contains(id.as_str()));
+            file.sessions.len() != before
+        };
+        if removed {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Drop the entries of machines not in `machine_ids`, the machines
+    /// still known or remembered
+    pub fn retain_machines(&self, machine_ids: &HashSet<String>) {
+        let removed = {
+            let mut file = self.file.lock();
+            let before = file.machines.len();
+            file.machines.retain(|id, _| machine_ids.contains(id));
+            file.machines.len() != before
+        };
+        if removed {
+            self.changed.notify_one();
+        }
+    }
+
+    /// Drop a session's entry once it closes
+    pub fn apply_event(&self, event: &IpcEvent) {
+        if let IpcEvent::SessionClosed { session_id, .. } = event {
+            if self.file.lock().sessions.remove(session_id).is_some() {
+                self.changed.notify_one();
+            }
+        }
+    }
+
+    /// Save the preferences after each change, once no change has followed
+    /// for [`SAVE_DEBOUNCE`]
+    pub async fn run_saver(self: Arc<Self>) {
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        loop {
+            self.changed.notified().await;
+            tokio::time::sleep(SAVE_DEBOUNCE).await;
+
+            let file = self.file.lock().clone();
+            let path = path.clone();
+            let result = tokio::task::spawn_blocking(move || write_prefs_file(&path, &file)).await;
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!("Failed to save terminal preferences: {}", e),
+                Err(e) => tracing::warn!("Terminal preferences save task failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Drop invalid entries, canonicalizing machine IDs
+fn sanitize(file: PrefsFile) -> PrefsFile {
+    let global = if keep("all terminals", &file.global) {
+        file.global
+    } else {
+        TerminalPrefsOverrides::default()
+    };
+    let mut machines = BTreeMap::new();
+    for (id, overrides) in file.machines {
+        match MachineId::parse(&id) {
+            Ok(machine_id) => {
+                if keep(&format!("machine {:?}", id), &overrides) {
+                    machines.entry(machine_id.to_string()).or_insert(overrides);
+                }
+            }
+            Err(e) => tracing::warn!("Dropping terminal preferences for machine {:?}: {}", id, e),
+        }
+    }
+    let sessions = file
+        .sessions
+        .into_iter()
+        .filter(|(id, overrides)| !id.is_empty() && keep(&format!("session {:?}", id), overrides))
+        .collect();
+
+    PrefsFile {
+        global,
+        machines,
+        sessions,
+    }
+}
+
+/// Whether loaded overrides are worth keeping, warning about invalid ones
+fn keep(scope: &str, overrides: &TerminalPrefsOverrides) -> bool {
+    if let Err(e) = overrides.validate() {
+        tracing::warn!("Dropping terminal preferences for {}: {}", scope, e);
+        return false;
+    }
+    !overrides.is_empty()
+}
+
+fn read_prefs_file(path: &Path) -> io::Result<PrefsFile> {
+    let json = std::fs::read_to_string(path)?;
+    serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write the preferences file, replacing the old one in a single rename
+fn write_prefs_file(path: &Path, file: &PrefsFile) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(file)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let tmp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    std::fs::write(&tmp_path, json)?;
+    let result = std::fs::rename(&tmp_path, path);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn session(id: &str, machine_id: &str) -> SessionPayload {
+        SessionPayload {
+            id: id.to_string(),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: String::new(),
+            pid: None,
+            name: None,
+            size: None,
+            detached: false,
+        }
+    }
+
+    fn font_size(size: u16) -> TerminalPrefsOverrides {
+        TerminalPrefsOverrides {
+            font_size: Some(size),
+            ..Default::default()
+        }
+    }
+
+    fn machine(id: &str) -> PrefsScope {
+        PrefsScope::Machine(id.to_string())
+    }
+
+    fn sess(id: &str) -> PrefsScope {
+        PrefsScope::Session(id.to_string())
+    }
+
+    #[test]
+    fn test_overlay_merge_order() {
+        let store = TerminalPrefsStore::load(None);
+        let effective = |scope, machine_id| store.get(scope, machine_id).unwrap().effective;
+        assert_eq!(
+            effective(sess("s1"), Some("alpha")),
+            TerminalPrefs::default()
+        );
+
+        store
+            .set(
+                PrefsScope::Global,
+                TerminalPrefsOverrides {
+                    font_size: Some(15),
+                    scrollback: Some(5000),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        store
+            .set(
+                machine("alpha"),
+                TerminalPrefsOverrides {
+                    font_size: Some(16),
+                    cursor_style: Some(CursorStyle::Bar),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        store
+            .set(
+                sess("s1"),
+                TerminalPrefsOverrides {
+                    cursor_style: Some(CursorStyle::Underline),
+                    bell: Some(BellStyle::Off),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        // Each scope overrides only what it sets, session over machine over global
+        assert_eq!(
+            effective(sess("s1"), Some("alpha")),
+            TerminalPrefs {
+                font_size: 16,
+                scrollback: 5000,
+                cursor_style: CursorStyle::Underline,
+                bell: BellStyle::Off,
+                confirm_foreign_input: true,
+            }
+        );
+        assert_eq!(
+            effective(machine("alpha"), None),
+            TerminalPrefs {
+                font_size: 16,
+                scrollback: 5000,
+                cursor_style: CursorStyle::Bar,
+                bell: BellStyle::Visual,
+                confirm_foreign_input: true,
+            }
+        );
+        // Another machine's session, or one whose machine isn't known
+        assert_eq!(effective(sess("s1"), Some("beta")).font_size, 15);
+        assert_eq!(
+            effective(sess("s1"), None).cursor_style,
+            CursorStyle::Underline
+        );
+        assert_eq!(effective(PrefsScope::Global, None).font_size, 15);
+
+        let payload = store.get(machine("alpha"), None).unwrap();
+        assert_eq!(payload.overrides.font_size, Some(16));
+        assert_eq!(payload.overrides.scrollback, None);
+
+        // Empty overrides remove the entry, falling back to the scope below
+        store
+            .set(machine("alpha"), TerminalPrefsOverrides::default())
+            .unwrap();
+        assert_eq!(effective(sess("s1"), Some("alpha")).font_size, 15);
+    }
+
+    #[test]
+    fn test_set_validates_scope_and_values() {
+        let store = TerminalPrefsStore::load(None);
+        assert!(store.set(machine("bad id"), font_size(14)).is_err());
+        assert!(store.set(sess(""), font_size(14)).is_err());
+        assert!(store.set(PrefsScope::Global, font_size(2)).is_err());
+        let too_long = TerminalPrefsOverrides {
+            scrollback: Some(MAX_SCROLLBACK + 1),
+            ..Default::default()
+        };
+        assert!(store.set(PrefsScope::Global, too_long).is_err());
+
+        // Machine IDs are canonicalized, so both name one machine
+        store.set(machine("GPU-Box"), font_size(20)).unwrap();
+        let payload = store.get(machine("gpu-box"), None).unwrap();
+        assert_eq!(payload.scope, machine("gpu-box"));
+        assert_eq!(payload.effective.font_size, 20);
+    }
+
+    #[test]
+    fn test_orphaned_entries_are_dropped() {
+        let store = TerminalPrefsStore::load(None);
+        store.set(machine("alpha"), font_size(16)).unwrap();
+        store.set(machine("beta"), font_size(17)).unwrap();
+        store.set(sess("s1"), font_size(18)).unwrap();
+        store.set(sess("s2"), font_size(19)).unwrap();
+        store.set(sess("s3"), font_size(20)).unwrap();
+
+        store.apply_event(&IpcEvent::SessionClosed {
+            session_id: "s1".to_string(),
+            exit_code: None,
+            reason: None,
+        });
+        store.retain_sessions(&[session("s2", "alpha")]);
+        store.retain_machines(&HashSet::from(["alpha".to_string()]));
+
+        let file = store.file.lock();
+        assert_eq!(file.sessions.keys().collect::<Vec<_>>(), ["s2"]);
+        assert_eq!(file.machines.keys().collect::<Vec<_>>(), ["alpha"]);
+    }
+
+    #[tokio::test]
+    async fn test_saved_prefs_survive_reload() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TERMINAL_PREFS_FILE_NAME);
+
+        let store = Arc::new(TerminalPrefsStore::load(Some(path.clone())));
+        let saver = tokio::spawn(store.clone().run_saver());
+        store.set(PrefsScope::Global, font_size(15)).unwrap();
+        store.set(machine("alpha"), font_size(16)).unwrap();
+        store.set(sess("s1"), font_size(17)).unwrap();
+        tokio::time::sleep(SAVE_DEBOUNCE + Duration::from_millis(500)).await;
+        saver.abort();
+
+        let reloaded = TerminalPrefsStore::load(Some(path));
+        for scope in [PrefsScope::Global, machine("alpha"), sess("s1")] {
+            assert_eq!(
+                reloaded.get(scope.clone(), Some("alpha")),
+                store.get(scope, Some("alpha"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_load_drops_invalid_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(TERMINAL_PREFS_FILE_NAME);
+        std::fs::write(
+            &path,
+            r#"{
+                "global": {"fontSize": 1},
+                "machines": {
+                    "Alpha": {"cursorStyle": "bar"},
+                    "bad id": {"fontSize": 14},
+                    "beta": {}
+                },
+                "sessions": {"s1": {"scrollback": 500, "unknown": true}}
+            }"#,
+        )
+        .unwrap();
+
+        let store = TerminalPrefsStore::load(Some(path));
+        let file = store.file.lock();
+        assert!(file.global.is_empty());
+        assert_eq!(file.machines.keys().collect::<Vec<_>>(), ["alpha"]);
+        assert_eq!(file.sessions["s1"].scrollback, Some(500));
+        drop(file);
+
+        // An unreadable file starts with no preferences
+        let corrupt = dir.path().join("corrupt.json");
+        std::fs::write(&corrupt, "{").unwrap();
+        let store = TerminalPrefsStore::load(Some(corrupt));
+        assert!(store.file.lock().global.is_empty());
+    }
+}
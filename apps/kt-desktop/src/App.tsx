@@ -5,8 +5,10 @@ import { useAppStore } from "./stores/app";
 import { useMachinesStore } from "./stores/machines";
 import { useTerminalsStore } from "./stores/terminals";
 import { useSyncStore } from "./stores/sync";
+import { useLogsStore } from "./stores/logs";
 import { toast } from "./stores/toast";
 import * as tauri from "./lib/tauri";
+import { describeAbnormalClose } from "./lib/utils";
 
 /**
  * Custom hook to get stable store action references.
@@ -32,6 +34,8 @@ function useStoreActions() {
     setLastSeq: useSyncStore.getState().setLastSeq,
     setEpochId: useSyncStore.getState().setEpochId,
     reconcile: useSyncStore.getState().reconcile,
+    // Logs store actions
+    appendLogs: useLogsStore.getState().appendLogs,
   }), []);
 }
 
@@ -49,6 +53,7 @@ function App() {
     setLastSeq,
     setEpochId,
     reconcile,
+    appendLogs,
   } = useStoreActions();
 
   // Use ref to access current tabs in event handlers without causing re-renders
@@ -164,6 +169,10 @@ function App() {
             const tab = tabsRef.current.find((t) => t.sessionId === event.sessionId);
             if (tab) {
               removeTab(tab.id);
+              const abnormal = describeAbnormalClose(event.reason);
+              if (abnormal) {
+                toast.warning(`Session "${tab.title}" ended: ${abnormal}`);
+              }
             }
             removeSession(event.sessionId);
           }
@@ -171,6 +180,18 @@ function App() {
       }
     }).then(registerUnlistener);
 
+    tauri.onAuthFailed((event) => {
+      if (signal.aborted) return;
+      console.warn(`[App] Orchestrator auth failed (${event.reason}):`, event.message);
+      setConnected(false);
+      toast.error(event.guidance, 0);
+    }).then(registerUnlistener);
+
+    tauri.onOrchestratorLog((lines) => {
+      if (signal.aborted) return;
+      appendLogs(lines);
+    }).then(registerUnlistener);
+
     // Cleanup event listeners
     return () => {
       abortController.abort();
@@ -190,6 +211,7 @@ function App() {
     setLastSeq,
     setEpochId,
     reconcile,
+    appendLogs,
   ]);
 
   return (
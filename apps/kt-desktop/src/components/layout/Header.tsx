@@ -76,6 +76,22 @@ export function Header() {
       {/* Spacer */}
       <div className="flex-1" />
 
+      {/* Maintenance mode */}
+      {isConnected && status?.maintenance && (
+        <>
+          <div
+            className="flex items-center gap-2 px-3 py-1 text-xs rounded-zen bg-ochre/10 border border-ochre/30"
+            title={status.maintenance}
+          >
+            <span className="uppercase text-[10px] tracking-wide font-bold text-ochre">
+              Maintenance
+            </span>
+            <span className="text-text-muted">New sessions refused</span>
+          </div>
+          <div className="w-px h-4 bg-border" />
+        </>
+      )}
+
       {/* Pairing code */}
       {isConnected && status?.pairingCode && (
         <>
@@ -1,6 +1,9 @@
+import { useEffect, useState } from "react";
 import { useAppStore } from "../../stores/app";
 import { useMachinesStore } from "../../stores/machines";
 import { formatUptime } from "../../lib/utils";
+import * as tauri from "../../lib/tauri";
+import type { Rejection } from "../../types";
 
 export function HealthView() {
   const status = useAppStore((s) => s.orchestratorStatus);
@@ -33,6 +36,10 @@ export function HealthView() {
             label="Sessions"
             value={status?.sessionCount.toString() ?? "0"}
           />
+          <MetricCard
+            label="SSH Address"
+            value={status?.listenAddress ?? status?.bindAddress ?? "-"}
+          />
         </div>
       </section>
 
@@ -51,10 +58,65 @@ export function HealthView() {
           )}
         </div>
       </section>
+
+      <RejectedAgents />
     </div>
   );
 }
 
+/** Agents the orchestrator turned away, refreshed as more are rejected */
+function RejectedAgents() {
+  const [rejections, setRejections] = useState<Rejection[]>([]);
+
+  useEffect(() => {
+    const refresh = () =>
+      tauri
+        .listRejections()
+        .then(setRejections)
+        .catch((e) => console.debug("[health] Failed to list rejected agents:", e));
+    refresh();
+    const unlisten = tauri.onMachineRejected(refresh);
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, []);
+
+  if (rejections.length === 0) {
+    return null;
+  }
+
+  return (
+    <section className="mt-8">
+      <h2 className="text-lg font-medium mb-4 text-text-secondary">
+        Rejected Agents ({rejections.length})
+      </h2>
+      <div className="space-y-3">
+        {rejections.map((rejection) => (
+          <div
+            key={`${rejection.peerAddr}|${rejection.hostname ?? ""}|${rejection.reason}`}
+            className="bg-bg-surface rounded p-4 border-2 border-border-faint flex items-center gap-4"
+          >
+            <div className="w-3 h-3 rounded-full bg-terracotta" />
+            <div className="flex-1">
+              <div className="font-medium text-text-primary">
+                {rejection.hostname ?? rejection.peerAddr}
+              </div>
+              <div className="text-sm text-text-muted">{rejection.reason}</div>
+            </div>
+            <div className="text-sm text-text-ghost text-right">
+              <div>{rejection.peerAddr}</div>
+              <div>
+                {rejection.count > 1 ? `${rejection.count}× • ` : ""}
+                {new Date(rejection.lastSeen).toLocaleTimeString()}
+              </div>
+            </div>
+          </div>
+        ))}
+      </div>
+    </section>
+  );
+}
+
 function MetricCard({
   label,
   value,
@@ -93,7 +155,7 @@ function MachineHealthCard({
         className={`w-3 h-3 rounded-full ${
           machine.status === "connected"
             ? "bg-sage"
-            : machine.status === "connecting"
+            : machine.status === "connecting" || machine.status === "reconnecting"
             ? "bg-ochre animate-breathe"
             : "bg-terracotta-dim"
         }`}
@@ -31,7 +31,7 @@ export function Layout() {
         {showSidebar && <ResizeHandle />}
 
         {/* Main content */}
-        <div className="flex-1 overflow-hidden">
+        <div className="flex-1 overflow-hidden" data-main-content>
           <MainContent />
         </div>
       </div>
@@ -1,32 +1,72 @@
-import { useState } from "react";
+import { useEffect, useRef, useState } from "react";
 import { clsx } from "clsx";
 import { DocumentIcon } from "../Icons";
+import { useLogsStore, type LogEntry } from "../../stores/logs";
+import { toast } from "../../stores/toast";
+import * as tauri from "../../lib/tauri";
 
 type LogLevel = "all" | "error" | "warn" | "info" | "debug";
 
-interface LogEntry {
-  id: string; // Unique identifier for React key
-  timestamp: string;
-  level: "error" | "warn" | "info" | "debug";
-  message: string;
-  source?: string;
-}
+/** Levels the orchestrator can stream at, most to least severe */
+const STREAM_LEVELS = ["error", "warn", "info", "debug", "trace"] as const;
+type StreamLevel = (typeof STREAM_LEVELS)[number];
 
 export function LogsView() {
+  const logs = useLogsStore((state) => state.logs);
+  const clearLogs = useLogsStore((state) => state.clearLogs);
   const [filter, setFilter] = useState<LogLevel>("all");
   const [search, setSearch] = useState("");
-
-  // Log streaming from orchestrator is not yet implemented
-  const logs: LogEntry[] = [];
-  const isLogsAvailable = false;
+  const [streamLevel, setStreamLevel] = useState<StreamLevel>("info");
+  const scrollRef = useRef<HTMLDivElement>(null);
+  const stickToBottom = useRef(true);
 
   const filteredLogs = logs.filter((log) => {
-    if (filter !== "all" && log.level !== filter) return false;
+    if (filter !== "all" && log.level.toLowerCase() !== filter) return false;
     if (search && !log.message.toLowerCase().includes(search.toLowerCase()))
       return false;
     return true;
   });
 
+  // Follow new lines unless the user scrolled up to read
+  useEffect(() => {
+    const el = scrollRef.current;
+    if (el && stickToBottom.current) {
+      el.scrollTop = el.scrollHeight;
+    }
+  }, [filteredLogs.length]);
+
+  const handleScroll = () => {
+    const el = scrollRef.current;
+    if (!el) return;
+    stickToBottom.current = el.scrollHeight - el.scrollTop - el.clientHeight < 20;
+  };
+
+  const handleStreamLevel = async (level: StreamLevel) => {
+    const previous = streamLevel;
+    setStreamLevel(level);
+    try {
+      await tauri.setLogLevel(level);
+    } catch (e) {
+      setStreamLevel(previous);
+      toast.error(`Failed to set log level: ${e}`);
+    }
+  };
+
+  const handleCopy = async () => {
+    const text = filteredLogs
+      .map(
+        (log) =>
+          `${new Date(log.timestampMs).toISOString()} ${log.level} ${log.target}: ${log.message}`
+      )
+      .join("\n");
+    try {
+      await navigator.clipboard.writeText(text);
+      toast.success(`Copied ${filteredLogs.length} log lines`);
+    } catch (e) {
+      toast.error(`Failed to copy logs: ${e}`);
+    }
+  };
+
   return (
     <div className="h-full flex flex-col">
       {/* Toolbar */}
@@ -60,43 +100,60 @@ export function LogsView() {
           className="input flex-1 max-w-xs text-sm"
         />
 
+        {/* Streamed level */}
+        <select
+          value={streamLevel}
+          onChange={(e) => handleStreamLevel(e.target.value as StreamLevel)}
+          className="input text-xs capitalize"
+          title="Most verbose level streamed from the orchestrator"
+          aria-label="Log level"
+        >
+          {STREAM_LEVELS.map((level) => (
+            <option key={level} value={level}>
+              {level}
+            </option>
+          ))}
+        </select>
+
         {/* Actions */}
         <button
+          onClick={clearLogs}
           className="btn btn-secondary text-xs disabled:opacity-50 disabled:cursor-not-allowed"
-          disabled={!isLogsAvailable || logs.length === 0}
-          title={!isLogsAvailable ? "Log streaming coming soon" : "Clear all logs"}
+          disabled={logs.length === 0}
+          title="Clear all logs"
           aria-label="Clear logs"
         >
           Clear
         </button>
         <button
+          onClick={handleCopy}
           className="btn btn-secondary text-xs disabled:opacity-50 disabled:cursor-not-allowed"
-          disabled={!isLogsAvailable || logs.length === 0}
-          title={!isLogsAvailable ? "Log streaming coming soon" : "Export logs to file"}
-          aria-label="Export logs"
+          disabled={filteredLogs.length === 0}
+          title="Copy shown logs to the clipboard"
+          aria-label="Copy logs"
         >
-          Export
+          Copy
         </button>
       </div>
 
       {/* Log entries */}
-      <div className="flex-1 overflow-auto font-mono text-sm">
-        {!isLogsAvailable ? (
+      <div
+        ref={scrollRef}
+        onScroll={handleScroll}
+        className="flex-1 overflow-auto font-mono text-sm"
+      >
+        {filteredLogs.length === 0 ? (
           <div className="flex flex-col items-center justify-center h-full text-text-muted">
             <div className="w-16 h-16 mb-4 rounded-full bg-mauve/10 flex items-center justify-center">
               <DocumentIcon className="w-8 h-8 text-mauve" />
             </div>
-            <div className="text-lg mb-2 font-medium">Log Streaming Coming Soon</div>
-            <div className="text-sm text-text-ghost max-w-sm text-center">
-              Real-time log streaming from the orchestrator and connected machines
-              will be available in a future update.
+            <div className="text-lg mb-2">
+              {logs.length === 0 ? "No logs yet" : "No matching logs"}
             </div>
-          </div>
-        ) : filteredLogs.length === 0 ? (
-          <div className="flex flex-col items-center justify-center h-full text-text-muted">
-            <div className="text-lg mb-2">No logs yet</div>
             <div className="text-sm text-text-ghost">
-              Logs will appear here when the orchestrator is running
+              {logs.length === 0
+                ? "Logs will appear here when the orchestrator is running"
+                : "Try a different level or search"}
             </div>
           </div>
         ) : (
@@ -114,22 +171,23 @@ export function LogsView() {
 }
 
 function LogRow({ log }: { log: LogEntry }) {
-  const levelColors = {
-    error: "text-terracotta",
-    warn: "text-ochre",
-    info: "text-sage",
-    debug: "text-text-muted",
+  const levelColors: Record<string, string> = {
+    ERROR: "text-terracotta",
+    WARN: "text-ochre",
+    INFO: "text-sage",
+    DEBUG: "text-text-muted",
+    TRACE: "text-text-ghost",
   };
 
-  const formatTime = (ts: string) => {
-    const date = new Date(ts);
+  const formatTime = (ms: number) => {
+    const date = new Date(ms);
     return date.toLocaleTimeString();
   };
 
   return (
     <tr className="hover:bg-bg-hover/30 border-b border-border-faint/50">
       <td className="px-3 py-1.5 text-text-ghost whitespace-nowrap">
-        {formatTime(log.timestamp)}
+        {formatTime(log.timestampMs)}
       </td>
       <td
         className={clsx(
@@ -140,9 +198,9 @@ function LogRow({ log }: { log: LogEntry }) {
         {log.level}
       </td>
       <td className="px-3 py-1.5 text-text-muted whitespace-nowrap">
-        {log.source}
+        {log.target}
       </td>
-      <td className="px-3 py-1.5 text-text-secondary">{log.message}</td>
+      <td className="px-3 py-1.5 text-text-secondary whitespace-pre-wrap">{log.message}</td>
     </tr>
   );
 }
@@ -4,6 +4,7 @@ import { useMachinesStore } from "../../stores/machines";
 import { useTerminalsStore } from "../../stores/terminals";
 import { toast } from "../../stores/toast";
 import * as tauri from "../../lib/tauri";
+import { initialTerminalSize } from "../../lib/terminalSize";
 import { clsx } from "clsx";
 import { PlusIcon, XIcon } from "../Icons";
 import type { Machine } from "../../types";
@@ -253,7 +254,7 @@ const MachineItem = memo(function MachineItem({
 
     setIsCreatingSession(true);
     try {
-      const session = await tauri.createSession(machine.id);
+      const session = await tauri.createSession(machine.id, initialTerminalSize());
       addSession(session);
       addTab({
         id: `tab-${session.id}`,
@@ -284,7 +285,7 @@ const MachineItem = memo(function MachineItem({
   };
 
   const isConnected = machine.status === "connected";
-  const isConnecting = machine.status === "connecting";
+  const isConnecting = machine.status === "connecting" || machine.status === "reconnecting";
 
   return (
     <div
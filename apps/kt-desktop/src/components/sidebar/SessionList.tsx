@@ -2,11 +2,12 @@ import { useMemo, useCallback, useRef, memo } from "react";
 import { useVirtualizer } from "@tanstack/react-virtual";
 import { useTerminalsStore } from "../../stores/terminals";
 import { useMachinesStore } from "../../stores/machines";
+import { useAppStore, type TabCloseAction } from "../../stores/app";
 import { toast } from "../../stores/toast";
 import * as tauri from "../../lib/tauri";
 import { clsx } from "clsx";
 import { TerminalIcon, XIcon } from "../Icons";
-import type { TerminalTab } from "../../types";
+import type { Session, TerminalTab } from "../../types";
 
 // Row height for virtual list
 const SESSION_ROW_HEIGHT = 52;
@@ -16,9 +17,20 @@ export function SessionList() {
   const activeTabId = useTerminalsStore((s) => s.activeTabId);
   const setActiveTab = useTerminalsStore((s) => s.setActiveTab);
   const removeTab = useTerminalsStore((s) => s.removeTab);
+  const addTab = useTerminalsStore((s) => s.addTab);
+  const sessions = useTerminalsStore((s) => s.sessions);
+  const setSessionDetached = useTerminalsStore((s) => s.setSessionDetached);
   const machines = useMachinesStore((s) => s.machines);
+  const tabCloseAction = useAppStore((s) => s.tabCloseAction);
+  const setTabCloseAction = useAppStore((s) => s.setTabCloseAction);
   const parentRef = useRef<HTMLDivElement>(null);
 
+  // Sessions left running when their tab was closed
+  const detachedSessions = useMemo(
+    () => [...sessions.values()].filter((session) => session.detached),
+    [sessions]
+  );
+
   // Create O(1) lookup map for machines
   const machineMap = useMemo(
     () => new Map(machines.map((m) => [m.id, m])),
@@ -33,6 +45,20 @@ export function SessionList() {
     [machineMap]
   );
 
+  // Opening a tab subscribes to the session, which reattaches it
+  const reattach = useCallback(
+    (session: Session) => {
+      setSessionDetached(session.id, false);
+      addTab({
+        id: `tab-${session.id}`,
+        sessionId: session.id,
+        machineId: session.machineId,
+        title: session.name || getMachineName(session.machineId),
+      });
+    },
+    [addTab, setSessionDetached, getMachineName]
+  );
+
   const virtualizer = useVirtualizer({
     count: tabs.length,
     getScrollElement: () => parentRef.current,
@@ -47,6 +73,16 @@ export function SessionList() {
         <span className="text-xs text-text-ghost uppercase tracking-wider">
           Active Sessions ({tabs.length})
         </span>
+        <select
+          value={tabCloseAction}
+          onChange={(e) => setTabCloseAction(e.target.value as TabCloseAction)}
+          className="input text-xs"
+          title="What closing a terminal tab does to its session"
+          aria-label="On tab close"
+        >
+          <option value="close">Tab close kills</option>
+          <option value="detach">Tab close detaches</option>
+        </select>
       </div>
 
       {/* Session list */}
@@ -94,6 +130,33 @@ export function SessionList() {
           </div>
         )}
       </div>
+
+      {/* Detached sessions */}
+      {detachedSessions.length > 0 && (
+        <div className="border-t border-border-faint">
+          <div className="p-2 text-xs text-text-ghost uppercase tracking-wider">
+            Detached ({detachedSessions.length})
+          </div>
+          {detachedSessions.map((session) => (
+            <button
+              key={session.id}
+              onClick={() => reattach(session)}
+              className="w-full flex items-center gap-2 px-3 py-2 text-left hover:bg-bg-hover transition-colors"
+              title="Reattach session"
+            >
+              <div className="w-5 h-5 flex items-center justify-center text-text-ghost">
+                <TerminalIcon className="w-4 h-4" />
+              </div>
+              <div className="flex-1 min-w-0">
+                <div className="text-sm truncate text-text-secondary">
+                  {session.name || getMachineName(session.machineId)}
+                </div>
+                <div className="text-xs text-text-ghost truncate">{session.id.slice(0, 8)}</div>
+              </div>
+            </button>
+          ))}
+        </div>
+      )}
     </div>
   );
 }
@@ -7,6 +7,7 @@ import "@xterm/xterm/css/xterm.css";
 
 import * as tauri from "../../lib/tauri";
 import { terminalTheme, terminalConfig } from "../../lib/theme";
+import { rememberTerminalSize } from "../../lib/terminalSize";
 import { shouldPassThroughTerminal } from "../../lib/keyboard";
 import { toast } from "../../stores/toast";
 
@@ -81,15 +82,39 @@ export function TerminalPane({ sessionId, isActive = true }: TerminalPaneProps)
     terminalRef.current = terminal;
     fitAddonRef.current = fitAddon;
 
-    // Handle input - send to backend
+    // Handle input - send to backend. The first input to a session this app
+    // didn't create waits until the user confirms it; what's typed while
+    // asking is dropped
+    let confirming = false;
     terminal.onData((data) => {
-      tauri.terminalWrite(sessionId, tauri.stringToBytes(data)).catch((err) => {
-        console.error("Failed to write to terminal:", err);
-      });
+      const bytes = tauri.stringToBytes(data);
+      tauri
+        .terminalWrite(sessionId, bytes)
+        .then(async (outcome) => {
+          if (outcome.kind !== "needsConfirmation" || confirming) {
+            return;
+          }
+          confirming = true;
+          try {
+            const confirmed = window.confirm(
+              "This session was not started from this app. Send your input to it?"
+            );
+            if (confirmed) {
+              await tauri.confirmSessionInput(sessionId);
+              await tauri.terminalWrite(sessionId, bytes);
+            }
+          } finally {
+            confirming = false;
+          }
+        })
+        .catch((err) => {
+          console.error("Failed to write to terminal:", err);
+        });
     });
 
     // Handle resize
     terminal.onResize(({ cols, rows }) => {
+      rememberTerminalSize(cols, rows);
       tauri.terminalResize(sessionId, cols, rows).catch((err) => {
         console.error("Failed to resize terminal:", err);
       });
@@ -103,6 +128,7 @@ export function TerminalPane({ sessionId, isActive = true }: TerminalPaneProps)
           fitAddonRef.current.fit();
           // Send initial size after fit
           const { cols, rows } = terminalRef.current;
+          rememberTerminalSize(cols, rows);
           tauri.terminalResize(sessionId, cols, rows).catch((err) => {
             console.error("Failed to initial resize:", err);
           });
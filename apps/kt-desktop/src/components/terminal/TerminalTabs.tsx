@@ -1,6 +1,7 @@
 import { useCallback, useMemo, memo } from "react";
 import { useTerminalsStore } from "../../stores/terminals";
 import { useLayoutStore } from "../../stores/layout";
+import { useAppStore } from "../../stores/app";
 import { clsx } from "clsx";
 import * as tauri from "../../lib/tauri";
 import { XIcon } from "../Icons";
@@ -72,7 +73,9 @@ export function TerminalTabs() {
   const activeTabId = useTerminalsStore((s) => s.activeTabId);
   const setActiveTab = useTerminalsStore((s) => s.setActiveTab);
   const removeTab = useTerminalsStore((s) => s.removeTab);
+  const setSessionDetached = useTerminalsStore((s) => s.setSessionDetached);
   const removeTabFromLayout = useLayoutStore((s) => s.removeTabFromLayout);
+  const tabCloseAction = useAppStore((s) => s.tabCloseAction);
 
   const getPaneForTab = useLayoutStore((s) => s.getPaneForTab);
   const setActivePane = useLayoutStore((s) => s.setActivePane);
@@ -84,14 +87,20 @@ export function TerminalTabs() {
     (tabId: string, sessionId: string) => async (e: React.MouseEvent) => {
       e.stopPropagation();
       try {
-        await tauri.terminalClose(sessionId);
+        if (tabCloseAction === "detach") {
+          // The session stays listed so it can be reattached
+          await tauri.terminalDetach(sessionId);
+          setSessionDetached(sessionId, true);
+        } else {
+          await tauri.terminalClose(sessionId);
+        }
       } catch (err) {
-        console.error("Failed to close terminal:", err);
+        console.error(`Failed to ${tabCloseAction} terminal:`, err);
       }
       removeTabFromLayout(tabId);
       removeTab(tabId);
     },
-    [removeTab, removeTabFromLayout]
+    [removeTab, removeTabFromLayout, setSessionDetached, tabCloseAction]
   );
 
   // Memoize select handler factory
@@ -5,6 +5,7 @@ import { useTerminalsStore } from "../../stores/terminals";
 import { useAppStore } from "../../stores/app";
 import { toast } from "../../stores/toast";
 import * as tauri from "../../lib/tauri";
+import { initialTerminalSize } from "../../lib/terminalSize";
 
 interface MachineNodeProps {
   data: { machine: Machine };
@@ -17,7 +18,7 @@ export function MachineNode({ data }: MachineNodeProps) {
   const setViewMode = useAppStore((s) => s.setViewMode);
 
   const isConnected = machine.status === "connected";
-  const isConnecting = machine.status === "connecting";
+  const isConnecting = machine.status === "connecting" || machine.status === "reconnecting";
 
   const handleDoubleClick = async () => {
     if (!isConnected) return;
@@ -29,7 +30,7 @@ export function MachineNode({ data }: MachineNodeProps) {
     }
 
     try {
-      const session = await tauri.createSession(machine.id);
+      const session = await tauri.createSession(machine.id, initialTerminalSize());
       addSession(session);
       addTab({
         id: `tab-${session.id}`,
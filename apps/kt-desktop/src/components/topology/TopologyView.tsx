@@ -133,7 +133,7 @@ function TopologyViewInner() {
       // Edge from orchestrator to machine
       const edgeColor = machine.status === "connected"
         ? colors.sage
-        : machine.status === "connecting"
+        : machine.status === "connecting" || machine.status === "reconnecting"
           ? colors.ochre
           : colors.terracottaDim;
 
@@ -226,7 +226,7 @@ function TopologyViewInner() {
             const data = node.data as TopologyNodeData;
             if (isMachineNodeData(data)) {
               if (data.machine.status === "connected") return colors.sage;
-              if (data.machine.status === "connecting") return colors.ochre;
+              if (data.machine.status === "connecting" || data.machine.status === "reconnecting") return colors.ochre;
             }
             return colors.terracottaDim;
           }}
@@ -3,10 +3,27 @@ import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import type {
   Machine,
   Session,
+  CreateSessionOptions,
   OrchestratorStatus,
+  OrchestratorCapabilities,
   MachineEvent,
   SessionEvent,
+  SessionActivityEvent,
   TerminalOutputEvent,
+  AuthFailedEvent,
+  LogLine,
+  MachineRejectedEvent,
+  NoticeEvent,
+  ConfigReloadedEvent,
+  ConfigReloadFailedEvent,
+  PaletteAction,
+  PaletteOutcome,
+  WriteOutcome,
+  Rejection,
+  RecentMachine,
+  TerminalPrefsOverrides,
+  TerminalPrefsScope,
+  TerminalPrefsState,
 } from "../types";
 import type { StateSnapshot } from "../stores/sync";
 
@@ -15,6 +32,11 @@ export async function getStatus(): Promise<OrchestratorStatus> {
   return invoke("get_status");
 }
 
+// Optional features, for hiding UI the orchestrator can't back
+export async function getCapabilities(): Promise<OrchestratorCapabilities> {
+  return invoke("get_capabilities");
+}
+
 export async function startOrchestrator(): Promise<void> {
   return invoke("start_orchestrator");
 }
@@ -36,14 +58,22 @@ export async function disconnectMachine(id: string): Promise<void> {
   return invoke("disconnect_machine", { id });
 }
 
+// Agents the orchestrator turned away, most recently seen first
+export async function listRejections(): Promise<Rejection[]> {
+  return invoke("list_rejections");
+}
+
 // Session commands
 export async function listSessions(machineId?: string): Promise<Session[]> {
   return invoke("list_sessions", { machineId });
 }
 
-export async function createSession(machineId: string, shell?: string): Promise<Session> {
+export async function createSession(
+  machineId: string,
+  options: CreateSessionOptions = {}
+): Promise<Session> {
   console.info("[tauri] createSession:", machineId, "appReady:", window.__appReady);
-  return invoke("create_session", { machineId, shell });
+  return invoke("create_session", { machineId, options });
 }
 
 export async function killSession(sessionId: string, force: boolean = false): Promise<void> {
@@ -51,18 +81,37 @@ export async function killSession(sessionId: string, force: boolean = false): Pr
 }
 
 // Terminal I/O commands
-export async function terminalWrite(sessionId: string, data: Uint8Array): Promise<void> {
+// Input to a session this app didn't create needs confirmSessionInput first
+export async function terminalWrite(sessionId: string, data: Uint8Array): Promise<WriteOutcome> {
   return invoke("terminal_write", { sessionId, data: Array.from(data) });
 }
 
+export async function confirmSessionInput(sessionId: string): Promise<void> {
+  return invoke("confirm_session_input", { sessionId });
+}
+
 export async function terminalResize(sessionId: string, cols: number, rows: number): Promise<void> {
   return invoke("terminal_resize", { sessionId, cols, rows });
 }
 
+// Terminates the remote shell
 export async function terminalClose(sessionId: string): Promise<void> {
   return invoke("terminal_close", { sessionId });
 }
 
+// Leaves the remote shell running; subscribing again reattaches it
+export async function terminalDetach(sessionId: string): Promise<void> {
+  return invoke("terminal_detach", { sessionId });
+}
+
+export async function setSessionMonitor(
+  sessionId: string,
+  activity: boolean,
+  silenceSecs?: number
+): Promise<void> {
+  return invoke("set_session_monitor", { sessionId, activity, silenceSecs });
+}
+
 // Session subscription commands
 export async function subscribeSession(sessionId: string): Promise<void> {
   return invoke("subscribe_session", { sessionId });
@@ -77,10 +126,40 @@ export function onMachineEvent(callback: (event: MachineEvent) => void): Promise
   return listen<MachineEvent>("machine-event", (event) => callback(event.payload));
 }
 
+export function onMachineRejected(
+  callback: (event: MachineRejectedEvent) => void
+): Promise<UnlistenFn> {
+  return listen<MachineRejectedEvent>("machine-rejected", (event) => callback(event.payload));
+}
+
+export function onNotice(callback: (event: NoticeEvent) => void): Promise<UnlistenFn> {
+  return listen<NoticeEvent>("notice", (event) => callback(event.payload));
+}
+
+export function onConfigReloaded(
+  callback: (event: ConfigReloadedEvent) => void
+): Promise<UnlistenFn> {
+  return listen<ConfigReloadedEvent>("config-reloaded", (event) => callback(event.payload));
+}
+
+export function onConfigReloadFailed(
+  callback: (event: ConfigReloadFailedEvent) => void
+): Promise<UnlistenFn> {
+  return listen<ConfigReloadFailedEvent>("config-reload-failed", (event) =>
+    callback(event.payload)
+  );
+}
+
 export function onSessionEvent(callback: (event: SessionEvent) => void): Promise<UnlistenFn> {
   return listen<SessionEvent>("session-event", (event) => callback(event.payload));
 }
 
+export function onSessionActivity(
+  callback: (event: SessionActivityEvent) => void
+): Promise<UnlistenFn> {
+  return listen<SessionActivityEvent>("session-activity", (event) => callback(event.payload));
+}
+
 export function onTerminalOutput(
   sessionId: string,
   callback: (data: Uint8Array) => void
@@ -101,6 +180,62 @@ export function onOrchestratorStatus(
   );
 }
 
+export function onAuthFailed(callback: (event: AuthFailedEvent) => void): Promise<UnlistenFn> {
+  return listen<AuthFailedEvent>("orchestrator-auth-failed", (event) => callback(event.payload));
+}
+
+// Retry authentication after the user has fixed a missing or rejected token
+export async function reauthenticate(): Promise<void> {
+  return invoke("reauthenticate");
+}
+
+// Orchestrator log lines arrive in batches of at most one per 100 ms
+export function onOrchestratorLog(callback: (lines: LogLine[]) => void): Promise<UnlistenFn> {
+  return listen<LogLine[]>("orchestrator-log", (event) => callback(event.payload));
+}
+
+// Change which orchestrator log lines are streamed ("error" ... "trace", or "off")
+export async function setLogLevel(level: string): Promise<void> {
+  return invoke("set_log_level", { level });
+}
+
+// Recent and pinned machines, kept by the backend across reloads
+export async function getRecents(): Promise<RecentMachine[]> {
+  return invoke("get_recents");
+}
+
+export async function pinMachine(id: string): Promise<void> {
+  return invoke("pin_machine", { id });
+}
+
+export async function unpinMachine(id: string): Promise<void> {
+  return invoke("unpin_machine", { id });
+}
+
+// Terminal preferences, kept by the backend across restarts
+export async function getTerminalPrefs(
+  scope: TerminalPrefsScope
+): Promise<TerminalPrefsState> {
+  return invoke("get_terminal_prefs", { scope });
+}
+
+// Replace what is set at a scope; an empty object clears it
+export async function setTerminalPrefs(
+  scope: TerminalPrefsScope,
+  prefs: TerminalPrefsOverrides
+): Promise<TerminalPrefsState> {
+  return invoke("set_terminal_prefs", { scope, prefs });
+}
+
+// Command palette actions, recomputed from the current state on each call
+export async function listPaletteActions(): Promise<PaletteAction[]> {
+  return invoke("list_palette_actions");
+}
+
+export async function invokePaletteAction(id: string): Promise<PaletteOutcome> {
+  return invoke("invoke_palette_action", { id });
+}
+
 // Utility to convert string to Uint8Array for terminal input
 export function stringToBytes(str: string): Uint8Array {
   return new TextEncoder().encode(str);
@@ -0,0 +1,53 @@
+/**
+ * Size to request for a new session's PTY
+ *
+ * The xterm.js terminal for a session only exists once the session does, so
+ * new sessions start at the size the last terminal was fitted to, or failing
+ * that, the size a terminal filling the main content area would get.
+ * TerminalPane resizes the PTY again after its first fit either way.
+ */
+
+import { terminalConfig } from "./theme";
+
+export interface TerminalSize {
+  cols: number;
+  rows: number;
+}
+
+/** Smallest size worth asking for (what a terminal assumes without one) */
+const MIN_SIZE: TerminalSize = { cols: 80, rows: 24 };
+
+let lastFitted: TerminalSize | null = null;
+
+/** Remember the size a terminal was fitted to */
+export function rememberTerminalSize(cols: number, rows: number): void {
+  if (cols > 0 && rows > 0) {
+    lastFitted = { cols, rows };
+  }
+}
+
+/** Size for a session created now */
+export function initialTerminalSize(): TerminalSize {
+  return lastFitted ?? measureTerminalSize();
+}
+
+/** Cells that fit in the main content area with the terminal font */
+function measureTerminalSize(): TerminalSize {
+  const container = document.querySelector<HTMLElement>("[data-main-content]");
+  const width = container?.clientWidth || window.innerWidth;
+  const height = container?.clientHeight || window.innerHeight;
+
+  const { fontSize, fontFamily, lineHeight, letterSpacing } = terminalConfig;
+  let cellWidth = fontSize * 0.6;
+  const context = document.createElement("canvas").getContext("2d");
+  if (context) {
+    context.font = `${fontSize}px ${fontFamily}`;
+    cellWidth = context.measureText("W").width + letterSpacing;
+  }
+  const cellHeight = Math.ceil(fontSize * lineHeight);
+
+  return {
+    cols: Math.max(MIN_SIZE.cols, Math.floor(width / cellWidth)),
+    rows: Math.max(MIN_SIZE.rows, Math.floor(height / cellHeight)),
+  };
+}
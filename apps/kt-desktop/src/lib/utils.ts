@@ -1,3 +1,5 @@
+import type { CloseReason } from "../types";
+
 /**
  * Format uptime in seconds to a human-readable string.
  * @param secs - Total seconds of uptime
@@ -12,3 +14,27 @@ export function formatUptime(secs: number): string {
   if (hours > 0) return `${hours}h ${mins}m`;
   return `${mins}m`;
 }
+
+/**
+ * Describe why a session ended, if it ended abnormally.
+ * @param reason - Close reason from a "closed" session event
+ * @returns A sentence such as "machine disconnected", or null for a normal
+ *   close (the user closed it, its TTL ran out, its client disconnected
+ *   and asked for that, or the shell exited with code 0)
+ */
+export function describeAbnormalClose(reason?: CloseReason): string | null {
+  switch (reason?.kind) {
+    case "process_exited":
+      return reason.code ? `shell exited with code ${reason.code}` : null;
+    case "machine_disconnected":
+      return "machine disconnected";
+    case "orphan_timeout":
+      return "no client reclaimed it in time";
+    case "limit_reaped":
+      return "closed to stay within the session limit";
+    case "error":
+      return `error: ${reason.message}`;
+    default:
+      return null;
+  }
+}
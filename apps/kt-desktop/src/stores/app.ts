@@ -4,6 +4,8 @@ import type { OrchestratorStatus } from "../types";
 
 export type ViewMode = "terminals" | "topology" | "health" | "logs";
 export type SidebarSection = "machines" | "sessions";
+/** What closing a terminal tab does to its session */
+export type TabCloseAction = "close" | "detach";
 
 interface AppState {
   // UI state (persisted)
@@ -11,6 +13,7 @@ interface AppState {
   sidebarSection: SidebarSection;
   sidebarWidth: number;
   showSidebar: boolean;
+  tabCloseAction: TabCloseAction;
 
   // Orchestrator state (not persisted)
   orchestratorStatus: OrchestratorStatus | null;
@@ -21,6 +24,7 @@ interface AppState {
   setSidebarSection: (section: SidebarSection) => void;
   setSidebarWidth: (width: number) => void;
   toggleSidebar: () => void;
+  setTabCloseAction: (action: TabCloseAction) => void;
   setOrchestratorStatus: (status: OrchestratorStatus | null) => void;
   setConnected: (connected: boolean) => void;
 }
@@ -56,6 +60,11 @@ function validatePersistedState(data: unknown): Partial<AppState> | null {
     result.showSidebar = obj.showSidebar;
   }
 
+  // Validate tabCloseAction
+  if (obj.tabCloseAction === "close" || obj.tabCloseAction === "detach") {
+    result.tabCloseAction = obj.tabCloseAction;
+  }
+
   return result;
 }
 
@@ -66,6 +75,7 @@ export const useAppStore = create<AppState>()(
       sidebarSection: "machines",
       sidebarWidth: 240,
       showSidebar: true,
+      tabCloseAction: "close",
       orchestratorStatus: null,
       isConnected: false,
 
@@ -73,6 +83,7 @@ export const useAppStore = create<AppState>()(
       setSidebarSection: (section) => set({ sidebarSection: section }),
       setSidebarWidth: (width) => set({ sidebarWidth: Math.max(180, Math.min(400, width)) }),
       toggleSidebar: () => set((state) => ({ showSidebar: !state.showSidebar })),
+      setTabCloseAction: (action) => set({ tabCloseAction: action }),
       setOrchestratorStatus: (status) => set({ orchestratorStatus: status }),
       setConnected: (connected) => set({ isConnected: connected }),
     }),
@@ -84,6 +95,7 @@ export const useAppStore = create<AppState>()(
         sidebarSection: state.sidebarSection,
         sidebarWidth: state.sidebarWidth,
         showSidebar: state.showSidebar,
+        tabCloseAction: state.tabCloseAction,
       }),
       // Validate persisted data before merging
       merge: (persistedState, currentState) => {
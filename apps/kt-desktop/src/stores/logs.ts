@@ -0,0 +1,31 @@
+import { create } from "zustand";
+import type { LogLine } from "../types";
+
+/** Most lines kept for the Logs panel; older lines are discarded */
+export const MAX_LOG_LINES = 5000;
+
+export interface LogEntry extends LogLine {
+  id: number; // Unique identifier for React key
+}
+
+interface LogsState {
+  logs: LogEntry[];
+  appendLogs: (lines: LogLine[]) => void;
+  clearLogs: () => void;
+}
+
+let logId = 0;
+
+export const useLogsStore = create<LogsState>((set) => ({
+  logs: [],
+
+  appendLogs: (lines) => {
+    if (lines.length === 0) return;
+    const entries = lines.map((line) => ({ ...line, id: ++logId }));
+    set((state) => ({
+      logs: [...state.logs, ...entries].slice(-MAX_LOG_LINES),
+    }));
+  },
+
+  clearLogs: () => set({ logs: [] }),
+}));
@@ -14,6 +14,7 @@ interface TerminalsState {
   updateTabTitle: (id: string, title: string) => void;
   addSession: (session: Session) => void;
   removeSession: (sessionId: string) => void;
+  setSessionDetached: (sessionId: string, detached: boolean) => void;
 }
 
 export const useTerminalsStore = create<TerminalsState>((set) => ({
@@ -86,6 +87,15 @@ export const useTerminalsStore = create<TerminalsState>((set) => ({
       newSessions.delete(sessionId);
       return { sessions: newSessions };
     }),
+
+  setSessionDetached: (sessionId, detached) =>
+    set((state) => {
+      const session = state.sessions.get(sessionId);
+      if (!session) return state;
+      const newSessions = new Map(state.sessions);
+      newSessions.set(sessionId, { ...session, detached });
+      return { sessions: newSessions };
+    }),
 }));
 
 // Reset terminals on HMR to prevent stale state
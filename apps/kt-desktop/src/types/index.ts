@@ -2,25 +2,118 @@
 export interface Machine {
   id: string;
   alias?: string;
+  /** Free-form label, e.g. "CI runner" */
+  label?: string;
   hostname: string;
   os: string;
   arch: string;
   status: MachineStatus;
+  /** ISO-8601 UTC timestamps */
   connectedAt?: string;
   lastHeartbeat?: string;
   sessionCount: number;
   tags?: string[];
+  /** Optional agent features, e.g. "cwd" or "signals" */
+  capabilities?: string[];
+  /** Times the machine has reconnected; high counts flag an unstable machine */
+  reconnectCount?: number;
 }
 
-export type MachineStatus = "connected" | "disconnected" | "connecting";
+export type MachineStatus = "connected" | "disconnected" | "connecting" | "reconnecting";
+
+// Entry of the recents list, pinned machines first (get_recents)
+export interface RecentMachine {
+  machineId: string;
+  /** Alias or hostname, as last seen */
+  label?: string;
+  pinned: boolean;
+  /** When a session was last opened on it (ms since Unix epoch) */
+  lastUsedMs?: number;
+  status: MachineStatus;
+  /** The orchestrator has forgotten this machine */
+  stale: boolean;
+}
+
+// Command palette action (list_palette_actions); ids look like
+// "new_session:<machineId>" or "open_settings"
+export interface PaletteAction {
+  id: string;
+  title: string;
+  /** Disabled actions are listed so they can be shown greyed out */
+  enabled: boolean;
+}
+
+// What to do after invoke_palette_action
+export type PaletteOutcome =
+  | { kind: "done" }
+  | { kind: "sessionCreated"; session: Session }
+  | { kind: "sessionAttached"; sessionId: string }
+  | { kind: "openSettings" };
+
+/** What became of input given to terminal_write */
+export type WriteOutcome = { kind: "sent" } | { kind: "needsConfirmation" };
+
+// Terminal preferences, kept by the backend (get_terminal_prefs)
+export interface TerminalPrefs {
+  /** Font size in pixels */
+  fontSize: number;
+  /** Lines kept above the screen */
+  scrollback: number;
+  cursorStyle: "block" | "underline" | "bar";
+  bell: "off" | "visual" | "sound";
+  /** Ask before the first input to a session this app didn't create */
+  confirmForeignInput: boolean;
+}
+
+/** Preferences set at one scope; unset fields are inherited */
+export type TerminalPrefsOverrides = Partial<TerminalPrefs>;
+
+/** Global, then per machine, then per session, each overriding the last */
+export type TerminalPrefsScope =
+  | { kind: "global" }
+  | { kind: "machine"; id: string }
+  | { kind: "session"; id: string };
+
+export interface TerminalPrefsState {
+  scope: TerminalPrefsScope;
+  /** What is set at this scope itself */
+  overrides: TerminalPrefsOverrides;
+  /** What applies at this scope, for a session what its terminal uses */
+  effective: TerminalPrefs;
+}
 
 // Session types
 export interface Session {
   id: string;
   machineId: string;
   shell?: string;
+  /** ISO-8601 UTC timestamp */
   createdAt: string;
   pid?: number;
+  name?: string;
+  size?: { cols: number; rows: number };
+  /** Left running with no tab; reattached by opening a tab for it */
+  detached?: boolean;
+}
+
+// Options for the new-session dialog (all optional)
+export interface CreateSessionOptions {
+  shell?: string;
+  cwd?: string;
+  env?: Record<string, string>;
+  name?: string;
+  // Current terminal dimensions, so the PTY starts at the right size
+  cols?: number;
+  rows?: number;
+  // Terminal type and 24-bit color support (default: xterm.js's)
+  term?: string;
+  truecolor?: boolean;
+  // Repeating a create with the same key returns the session it made
+  idempotencyKey?: string;
+  // Close the session this many seconds after it's created
+  ttlSecs?: number;
+  // Close it when its shell exits (default true); false keeps it until closed
+  closeOnExit?: boolean;
 }
 
 // Terminal types
@@ -42,6 +135,20 @@ export interface OrchestratorStatus {
   tailscaleHostname?: string;
   pairingCode?: string;
   bindAddress?: string;
+  // Concrete address the SSH server listens on (bindAddress may be "tailnet")
+  listenAddress?: string;
+  // Terminal output chunks dropped because the UI fell behind (get_status only)
+  droppedTerminalOutput?: number;
+  // Why new sessions are refused, while in maintenance mode
+  maintenance?: string;
+}
+
+// Optional features of the orchestrator (get_capabilities)
+export interface OrchestratorCapabilities {
+  /** IPC protocol version, 0 if the orchestrator predates feature detection */
+  protocolVersion: number;
+  /** e.g. "event_replay", "log_tailing" */
+  features: string[];
 }
 
 // IPC message types (for Tauri commands)
@@ -73,6 +180,79 @@ export interface SessionEvent {
   session?: Session;
   sessionId?: string;
   exitCode?: number;
+  reason?: CloseReason;
+}
+
+// Bell, activity or silence detected in a session (`session-activity` event)
+export interface SessionActivityEvent {
+  sessionId: string;
+  kind: "bell" | "activity" | "silence";
+}
+
+// Why a session ended (`CloseReason` in kt-core)
+export type CloseReason =
+  | { kind: "user_requested" }
+  | { kind: "process_exited"; code?: number | null }
+  | { kind: "machine_disconnected" }
+  | { kind: "orphan_timeout" }
+  | { kind: "limit_reaped" }
+  | { kind: "ttl" }
+  | { kind: "owner_disconnected" }
+  | { kind: "error"; message: string };
+
+// Emitted after repeated IPC authentication failures
+export interface AuthFailedEvent {
+  reason: "token_missing" | "token_rejected";
+  message: string;
+  guidance: string;
+  attempts: number;
+}
+
+// Agent registration the orchestrator rejected
+export interface Rejection {
+  // Only known if the agent got as far as registering
+  hostname: string | null;
+  reason: string;
+  peerAddr: string;
+  firstSeen: string;
+  lastSeen: string;
+  count: number;
+}
+
+// Emitted as "machine-rejected"; repeats are only announced once a minute
+export interface MachineRejectedEvent {
+  hostname: string | null;
+  reason: string;
+  peerAddr: string;
+}
+
+// Emitted as "notice" for things the user should know about, such as a
+// session whose input was refused for coming too fast
+export interface NoticeEvent {
+  message: string;
+  sessionId: string | null;
+  clientId: string | null;
+}
+
+// Emitted as "config-reloaded" once the orchestrator reloaded its config
+// (on SIGHUP); keys are dotted, such as "orchestrator.max_total_sessions"
+export interface ConfigReloadedEvent {
+  changedKeys: string[];
+  // Changed, but only applied once the orchestrator restarts
+  restartRequired: string[];
+}
+
+// Emitted as "config-reload-failed"; the previous config still applies
+export interface ConfigReloadFailedEvent {
+  error: string;
+}
+
+// Orchestrator log line, emitted in batches as "orchestrator-log"
+export interface LogLine {
+  timestampMs: number;
+  level: "ERROR" | "WARN" | "INFO" | "DEBUG" | "TRACE";
+  target: string;
+  message: string;
 }
 
 export interface TerminalOutputEvent {
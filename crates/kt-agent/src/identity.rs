@@ -0,0 +1,214 @@
+//! What the agent reports about its machine at registration
+//!
+//! Inside a container the hostname is a random ID and the OS is the image's,
+//! which makes the machine list useless and gives every restart a new
+//! identity. So the machine ID is derived from an install ID: a UUID written
+//! to the agent's state directory on first run and read back on every start
+//! after. As long as the state directory persists (e.g. on a volume), a
+//! restarted container registers as the same machine and the orchestrator
+//! replaces its old entry instead of adding another.
+//!
+//! The hostname, OS and architecture can be overridden in [`AgentConfig`],
+//! and a detected container is marked in the reported OS.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use uuid::Uuid;
+
+use kt_core::config::AgentConfig;
+
+/// File in the state directory holding the install ID
+pub const INSTALL_ID_FILE: &str = "install_id";
+
+/// Appended to the reported OS when running in a container
+pub const CONTAINER_MARKER: &str = " (container)";
+
+/// Hex digits of the install ID used for the machine ID
+const MACHINE_ID_LEN: usize = 12;
+
+/// Identity the agent registers with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentIdentity {
+    /// Stable machine ID derived from the install ID
+    pub machine_id: String,
+    /// Machine alias
+    pub alias: String,
+    /// Free-form label
+    pub label: Option<String>,
+    /// Reported hostname
+    pub hostname: String,
+    /// Reported operating system
+    pub os: String,
+    /// Reported CPU architecture
+    pub arch: String,
+}
+
+impl AgentIdentity {
+    /// Work out the identity for `config`, creating the install ID in its
+    /// state directory on first run
+    pub fn load(config: &AgentConfig) -> io::Result<Self> {
+        let install_id = load_or_create_install_id(&config.state_dir())?;
+        let os = match &config.os {
+            Some(os) => os.clone(),
+            None if in_container(Path::new("/")) => {
+                format!("{}{}", std::env::consts::OS, CONTAINER_MARKER)
+            }
+            None => std::env::consts::OS.to_string(),
+        };
+        Ok(Self {
+            machine_id: machine_id_from_install_id(&install_id),
+            alias: config.machine_alias(),
+            label: config.label.clone(),
+            hostname: config.reported_hostname(),
+            os,
+            arch: config
+                .arch
+                .clone()
+                .unwrap_or_else(|| std::env::consts::ARCH.to_string()),
+        })
+    }
+}
+
+/// Read the install ID from `dir`, or create it if there is none yet
+///
+/// An unreadable or malformed ID is an error rather than replaced, so the
+/// machine doesn't silently get a new identity.
+pub fn load_or_create_install_id(dir: &Path) -> io::Result<Uuid> {
+    let path = dir.join(INSTALL_ID_FILE);
+    match fs::read_to_string(&path) {
+        Ok(contents) => return parse_install_id(&contents, &path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    kt_core::permissions::create_private_dir_all(dir)?;
+    let install_id = Uuid::new_v4();
+    // Another agent starting at the same time may have created it first
+    let created = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path);
+    match created {
+        Ok(mut file) => {
+            writeln!(file, "{}", install_id)?;
+            file.sync_all()?;
+            tracing::info!("Created install ID {} in {}", install_id, path.display());
+            Ok(install_id)
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            parse_install_id(&fs::read_to_string(&path)?, &path)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_install_id(contents: &str, path: &Path) -> io::Result<Uuid> {
+    Uuid::parse_str(contents.trim()).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid install ID in {}: {}", path.display(), e),
+        )
+    })
+}
+
+/// Machine ID for an install ID
+pub fn machine_id_from_install_id(install_id: &Uuid) -> String {
+    install_id.simple().to_string()[..MACHINE_ID_LEN].to_string()
+}
+
+/// Whether the filesystem at `root` is a container's
+///
+/// Looks for the marker files Docker and Podman create, the `container`
+/// variable systemd-nspawn and LXC set, and container runtimes in the
+/// cgroup of PID 1.
+pub fn in_container(root: &Path) -> bool {
+    root.join(".dockerenv").exists()
+        || root.join("run/.containerenv").exists()
+        || std::env::var_os("container").is_some()
+        || fs::read_to_string(root.join("proc/1/cgroup"))
+            .is_ok_and(|cgroup| is_container_cgroup(&cgroup))
+}
+
+fn is_container_cgroup(cgroup: &str) -> bool {
+    const RUNTIMES: [&str; 5] = ["docker", "kubepods", "containerd", "libpod", "lxc"];
+    cgroup
+        .lines()
+        .any(|line| RUNTIMES.iter().any(|runtime| line.contains(runtime)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_install_id_persists_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_dir = dir.path().join("state");
+
+        // First run creates it, later runs read the same one back
+        let first = load_or_create_install_id(&state_dir).unwrap();
+        let second = load_or_create_install_id(&state_dir).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(
+            machine_id_from_install_id(&first),
+            machine_id_from_install_id(&second)
+        );
+
+        // A fresh state directory is a different install
+        let other = load_or_create_install_id(&dir.path().join("other")).unwrap();
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn test_identity_ignores_hostname() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = |hostname: &str| AgentConfig {
+            state_dir: Some(dir.path().to_path_buf()),
+            hostname: Some(hostname.to_string()),
+            label: Some("build runner".to_string()),
+            os: Some("linux".to_string()),
+            arch: Some("aarch64".to_string()),
+            ..Default::default()
+        };
+
+        // A container restart comes back with a new random hostname
+        let before = AgentIdentity::load(&config("3f2a9c1b7d4e")).unwrap();
+        let after = AgentIdentity::load(&config("9e8d7c6b5a41")).unwrap();
+        assert_eq!(before.machine_id, after.machine_id);
+        assert_eq!(before.machine_id.len(), MACHINE_ID_LEN);
+        assert!(kt_core::types::MachineId::parse(&before.machine_id).is_ok());
+        assert_eq!(after.hostname, "9e8d7c6b5a41");
+        assert_eq!(after.label.as_deref(), Some("build runner"));
+        assert_eq!(after.os, "linux");
+        assert_eq!(after.arch, "aarch64");
+    }
+
+    #[test]
+    fn test_malformed_install_id_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(INSTALL_ID_FILE), "not-a-uuid\n").unwrap();
+        let err = load_or_create_install_id(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_container_detection() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir_all(root.path().join("proc/1")).unwrap();
+        let cgroup = root.path().join("proc/1/cgroup");
+
+        fs::write(&cgroup, "0::/init.scope\n").unwrap();
+        if std::env::var_os("container").is_none() {
+            assert!(!in_container(root.path()));
+        }
+
+        fs::write(&cgroup, "0::/system.slice/docker-0123abcd.scope\n").unwrap();
+        assert!(in_container(root.path()));
+
+        fs::write(&cgroup, "0::/init.scope\n").unwrap();
+        fs::write(root.path().join(".dockerenv"), "").unwrap();
+        assert!(in_container(root.path()));
+    }
+}
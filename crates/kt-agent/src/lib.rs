@@ -4,11 +4,15 @@
 //! reverse SSH tunnel to the orchestrator. It manages local PTY
 //! sessions and streams I/O over the multiplexed tunnel.
 
+pub mod identity;
 pub mod metrics;
 pub mod pairing;
 pub mod pty;
+pub mod runtime_config;
+pub mod session_log;
 pub mod state;
 pub mod tunnel;
+pub mod version;
 
 pub use pairing::{discover_orchestrator, prompt_pairing_code, DiscoveredOrchestrator};
 pub use state::AgentState;
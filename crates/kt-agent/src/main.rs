@@ -13,23 +13,48 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use clap::Parser;
+use futures::future::{BoxFuture, FutureExt};
 use tokio::sync::{mpsc, Mutex};
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-
-use kt_agent::pty::PtyManager;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+use kt_agent::pty::{
+    journal, terminal_env, OutputReader, PtyManager, PtySession, ReaderWatchdog, SessionJournal,
+    WatchedReader,
+};
+use kt_agent::runtime_config::RuntimeConfig;
+use kt_agent::session_log::{SessionLevelFilter, SessionSpans};
 use kt_agent::tunnel::{ConnectionError, ExponentialBackoff, TunnelConnector, TunnelEvent};
-use kt_core::config::{self, AgentConfig};
+use kt_agent::version::{compare_versions, AGENT_VERSION};
+use kt_core::config::{self, AgentConfig, ConfigLoader, SessionJournalConfig};
+use kt_core::ipc::OutputStream;
 use kt_core::tailscale;
 use kt_protocol::SessionId;
 
 /// Message sent from PTY reader tasks to the main event loop
 struct PtyOutput {
     session_id: SessionId,
+    /// Stderr only for sessions without a PTY
+    stream: OutputStream,
+    /// Empty once the stream has ended, which usually means the shell exited
     data: Vec<u8>,
 }
 
+/// How long to wait for a reader task to forward the last of an exited
+/// session's output
+const READER_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Delay before checking again whether a session whose output ended has
+/// exited, in case the shell hadn't quite finished when its output closed
+const EXIT_RECHECK_DELAY: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// A PTY spawned off the event loop, sent back to it
+struct PtyCreated {
+    session_id: SessionId,
+    result: Result<PtySession>,
+}
+
 #[derive(Parser)]
 #[command(name = "kt-agent")]
 #[command(about = "k-Terminus agent - connects to orchestrator via Tailscale")]
@@ -68,6 +93,11 @@ struct Args {
     /// Local testing mode - skip Tailscale requirement
     #[arg(long)]
     local: bool,
+
+    /// Exit instead of just warning when the orchestrator's version differs
+    /// by more than a patch
+    #[arg(long)]
+    strict_version: bool,
 }
 
 #[tokio::main]
@@ -80,11 +110,12 @@ async fn main() -> Result<()> {
     } else {
         &args.log_level
     };
+    // Sessions can ask for their own level (`connect --log-level`)
+    let filter = tracing_subscriber::EnvFilter::new(
+        std::env::var("RUST_LOG").unwrap_or_else(|_| log_level.into()),
+    );
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| log_level.into()),
-        ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(tracing_subscriber::fmt::layer().with_filter(SessionLevelFilter::new(filter)))
         .init();
 
     tracing::info!("k-Terminus Agent starting...");
@@ -123,30 +154,24 @@ async fn main() -> Result<()> {
         .clone()
         .unwrap_or_else(|| config::default_config_dir().join("agent.toml"));
 
-    let mut config = if config_path.exists() {
-        config::load_config(&config_path).unwrap_or_else(|e| {
+    // Defaults <- agent.toml <- KT_AGENT__* (flags are applied below)
+    let mut loader = ConfigLoader::<AgentConfig>::new();
+    if config_path.exists() {
+        if let Err(e) = loader.with_file(&config_path) {
             tracing::warn!("Failed to load config from {:?}: {}", config_path, e);
-            AgentConfig::default()
-        })
-    } else {
-        AgentConfig::default()
-    };
+        }
+    }
+    let mut config = loader.with_env().load()?;
 
     // Apply command-line overrides
     if let Some(orchestrator) = args.orchestrator {
-        let resolved = if let Some(ref ts) = ts_info {
-            // Resolve short name to full Tailscale hostname
-            tailscale::resolve_device_name(&orchestrator, &ts.tailnet)
-        } else {
-            // Local mode - use address as-is
-            orchestrator
-        };
-        // Add default port if not specified
-        config.orchestrator_address = if resolved.contains(':') {
-            resolved
-        } else {
-            format!("{}:2222", resolved)
-        };
+        // Resolve short names to full Tailscale hostnames (local mode uses
+        // the address as-is) and add the default port if not specified
+        let tailnet = ts_info
+            .as_ref()
+            .map(|ts| ts.tailnet_or(config.tailnet_domain.as_deref()));
+        config.orchestrator_address =
+            kt_core::net::orchestrator_dial_address(&orchestrator, tailnet);
     } else if let Some(code) = args.code {
         // Use pairing code discovery
         if args.local {
@@ -190,6 +215,9 @@ async fn main() -> Result<()> {
     if let Some(alias) = args.alias {
         config.alias = Some(alias);
     }
+    if args.strict_version {
+        config.strict_version = true;
+    }
 
     // Ensure SSH key exists (auto-generate if needed)
     ensure_ssh_key(&config.private_key_path).await?;
@@ -203,20 +231,40 @@ async fn main() -> Result<()> {
     // Create tunnel connector (no host key verification needed - Tailscale handles security)
     let connector =
         TunnelConnector::new(config.clone()).context("Failed to create tunnel connector")?;
+    let identity = connector.identity();
+    tracing::info!(
+        "Machine ID: {} ({} {})",
+        identity.machine_id,
+        identity.os,
+        identity.arch
+    );
+
+    if config.session_journal.enabled {
+        archive_session_journals(&config.session_journal);
+    }
 
     // Create PTY manager
-    let pty_manager = Arc::new(Mutex::new(PtyManager::with_defaults(
-        config.default_shell.clone(),
-        config.default_env.clone(),
-    )));
+    let pty_manager = Arc::new(Mutex::new(
+        PtyManager::with_defaults(config.default_shell.clone(), config.default_env.clone())
+            .with_default_term(config.default_term.clone()),
+    ));
+
+    // Cuts off readers that hang past their session's close
+    let watchdog = ReaderWatchdog::new();
+    tokio::spawn(watchdog.clone().run());
+
+    // Shared by every reconnect so the attempt count and elapsed time cover
+    // the whole outage; a successful connect resets it
+    let mut backoff = ExponentialBackoff::from_config(&config.backoff);
+
+    // Sessions that ended with the last connection, and the orchestrator it
+    // was to
+    let mut lost_sessions: Option<(String, Vec<SessionId>)> = None;
 
     // Main loop with reconnection
     loop {
-        // Create backoff for this connection attempt
-        let backoff = ExponentialBackoff::from_config(&config.backoff);
-
         // Connect to orchestrator
-        let mut tunnel = match connector.connect_with_retry(backoff).await {
+        let mut tunnel = match connector.connect_with_retry(&mut backoff).await {
             Ok(tunnel) => tunnel,
             Err(ConnectionError::AuthRejected) => {
                 tracing::error!(
@@ -243,12 +291,27 @@ async fn main() -> Result<()> {
 
         tracing::info!("Connected to orchestrator, entering event loop");
 
+        // The same orchestrator may still hold sessions lost with the last
+        // connection while it waits for us to reconnect; tell it they're gone
+        if let Some((address, sessions)) = lost_sessions.take() {
+            if address == tunnel.address() {
+                report_lost_sessions(&tunnel, &sessions).await;
+            }
+        }
+
         // Create channel for PTY output (reader tasks -> event loop)
         let (pty_output_tx, pty_output_rx) = mpsc::channel::<PtyOutput>(256);
 
         // Track reader tasks and their cancellation tokens for cleanup
         let reader_tasks: HashMap<SessionId, (JoinHandle<()>, CancellationToken)> = HashMap::new();
 
+        // On a fallback, move back to the primary as soon as it returns
+        let failback = if connector.is_primary(&tunnel) {
+            std::future::pending().boxed()
+        } else {
+            connector.wait_for_primary().boxed()
+        };
+
         // Event loop
         let disconnect_reason = run_event_loop(
             &mut tunnel,
@@ -256,17 +319,43 @@ async fn main() -> Result<()> {
             pty_output_tx,
             pty_output_rx,
             reader_tasks,
+            &watchdog,
+            &config.session_journal,
+            config.strict_version,
+            config.metrics_interval(),
+            failback,
         )
         .await;
 
+        let disconnect_reason = match disconnect_reason {
+            Ok(reason) => reason,
+            Err(e) => {
+                // Not worth reconnecting to (e.g. a refused version)
+                if let Err(close_err) = tunnel.close().await {
+                    tracing::debug!("Failed to close tunnel: {}", close_err);
+                }
+                return Err(e);
+            }
+        };
         tracing::warn!("Disconnected: {:?}", disconnect_reason);
+        let address = tunnel.address().to_string();
+        if let Err(e) = tunnel.close().await {
+            tracing::debug!("Failed to close tunnel: {}", e);
+        }
 
-        // Clean up any active sessions
+        // Clean up any active sessions; they can't move to another
+        // orchestrator, so a reconnect starts without them
         {
             let mut manager = pty_manager.lock().await;
-            for session_id in manager.list_sessions() {
+            let lost = manager.list_sessions();
+            if !lost.is_empty() {
+                tracing::warn!("{} session(s) ended with the connection", lost.len());
+            }
+            for &session_id in &lost {
                 manager.close(session_id);
             }
+            manager.clear_pending();
+            lost_sessions = Some((address, lost));
         }
 
         // Brief delay before reconnecting
@@ -275,27 +364,59 @@ async fn main() -> Result<()> {
     }
 }
 
+/// Report sessions that ended when the previous connection dropped, so an
+/// orchestrator keeping them for a reconnect closes them
+async fn report_lost_sessions(tunnel: &kt_agent::tunnel::ActiveTunnel, sessions: &[SessionId]) {
+    for &session_id in sessions {
+        let sent = tunnel
+            .send_error(
+                session_id,
+                kt_protocol::ErrorCode::SessionNotFound,
+                "Session ended when the connection to the orchestrator dropped".to_string(),
+            )
+            .await;
+        if let Err(e) = sent {
+            tracing::warn!("Failed to report lost session {}: {}", session_id, e);
+        }
+    }
+}
+
 /// Run the main event loop for handling orchestrator events
+#[allow(clippy::too_many_arguments)]
 async fn run_event_loop(
     tunnel: &mut kt_agent::tunnel::ActiveTunnel,
     pty_manager: Arc<Mutex<PtyManager>>,
     pty_output_tx: mpsc::Sender<PtyOutput>,
     mut pty_output_rx: mpsc::Receiver<PtyOutput>,
     mut reader_tasks: HashMap<SessionId, (JoinHandle<()>, CancellationToken)>,
-) -> String {
+    watchdog: &ReaderWatchdog,
+    journal_config: &SessionJournalConfig,
+    strict_version: bool,
+    metrics_interval: Option<std::time::Duration>,
+    mut failback: BoxFuture<'_, ()>,
+) -> Result<String> {
+    // PTYs are spawned on blocking threads; input and resizes that arrive
+    // meanwhile are queued by the PTY manager
+    let (pty_created_tx, mut pty_created_rx) = mpsc::channel::<PtyCreated>(16);
+    let mut exit_recheck: Option<tokio::time::Instant> = None;
+    let mut session_spans = SessionSpans::new();
+    // Settings the orchestrator changes, for this connection only
+    let runtime_config = RuntimeConfig::start(metrics_interval);
+
     loop {
         tokio::select! {
             // Handle events from the orchestrator
             event = tunnel.recv_event() => {
                 let event = match event {
                     Some(event) => event,
-                    None => return "Channel closed".to_string(),
+                    None => return Ok("Channel closed".to_string()),
                 };
 
                 match event {
-                    TunnelEvent::Registered { accepted, reason } => {
+                    TunnelEvent::Registered { accepted, reason, version } => {
                         if accepted {
                             tracing::info!("Registration accepted by orchestrator");
+                            check_orchestrator_version(version.as_deref(), strict_version)?;
                         } else {
                             // Issue #18: Detect and handle protocol version mismatch
                             let reason_str = reason.as_deref().unwrap_or("Unknown reason");
@@ -320,47 +441,52 @@ async fn run_event_loop(
                                 ).await;
                                 tracing::debug!("Reader task cleaned up for session {} on rejection", session_id);
                             }
-                            return format!("Registration rejected: {}", reason_str);
+                            return Ok(format!("Registration rejected: {}", reason_str));
                         }
                     }
 
-                    TunnelEvent::CreateSession { session_id, shell, env, size } => {
-                        tracing::info!("Creating session {}", session_id);
-
-                        let mut manager = pty_manager.lock().await;
-                        match manager.create_session(session_id, shell, env, size) {
-                            Ok(pid) => {
-                                // Send session ready notification
-                                if let Err(e) = tunnel.send_session_ready(session_id, pid).await {
-                                    tracing::error!("Failed to send session ready: {}", e);
-                                }
-
-                                // Take the PTY reader and spawn a blocking task to read from it
-                                match manager.take_reader(session_id) {
-                                    Ok(reader) => {
-                                        let tx = pty_output_tx.clone();
-                                        let cancel_token = CancellationToken::new();
-                                        let handle = spawn_pty_reader(session_id, reader, tx, cancel_token.clone());
-                                        reader_tasks.insert(session_id, (handle, cancel_token));
-                                        tracing::debug!("Spawned reader task for session {}", session_id);
-                                    }
-                                    Err(e) => {
-                                        tracing::error!("Failed to take reader for session {}: {}", session_id, e);
-                                    }
+                    TunnelEvent::CreateSession {
+                        session_id,
+                        shell,
+                        mut env,
+                        size,
+                        cwd,
+                        allocate_pty,
+                        log_level,
+                        term,
+                        truecolor,
+                    } => {
+                        let span = session_spans.open(session_id, log_level.as_deref());
+                        tracing::info!(parent: &span, "Creating session {}", session_id);
+                        tracing::debug!(
+                            parent: &span,
+                            "Session {}: shell {:?}, cwd {:?}, {}x{}, pty {}, term {:?}",
+                            session_id,
+                            shell,
+                            cwd,
+                            size.cols,
+                            size.rows,
+                            allocate_pty,
+                            term
+                        );
+                        env.extend(terminal_env(term.as_deref(), truecolor));
+
+                        let spec = pty_manager
+                            .lock()
+                            .await
+                            .begin_session(session_id, shell, env, cwd, size, allocate_pty);
+                        let tx = pty_created_tx.clone();
+                        tokio::task::spawn_blocking(move || {
+                            let result = spec.spawn();
+                            if let Err(mpsc::error::SendError(created)) =
+                                tx.blocking_send(PtyCreated { session_id, result })
+                            {
+                                // The event loop is gone (disconnected), so nobody owns the shell
+                                if let Ok(session) = created.result {
+                                    session.kill();
                                 }
                             }
-                            Err(e) => {
-                                tracing::error!("Failed to create session: {}", e);
-                                // Send error back to orchestrator
-                                if let Err(send_err) = tunnel.send_error(
-                                    session_id,
-                                    kt_protocol::ErrorCode::PtyAllocationFailed,
-                                    format!("Failed to create session: {}", e),
-                                ).await {
-                                    tracing::error!("Failed to send error to orchestrator: {}", send_err);
-                                }
-                            }
-                        }
+                        });
                     }
 
                     TunnelEvent::SessionData { session_id, data } => {
@@ -371,14 +497,28 @@ async fn run_event_loop(
                     }
 
                     TunnelEvent::SessionResize { session_id, size } => {
+                        let span = session_spans.get(session_id);
                         let mut manager = pty_manager.lock().await;
-                        if let Err(e) = manager.resize(session_id, size) {
-                            tracing::error!("Failed to resize session {}: {}", session_id, e);
+                        match manager.resize(session_id, size) {
+                            Ok(()) => tracing::debug!(
+                                parent: &span,
+                                "Resized session {} to {}x{}",
+                                session_id,
+                                size.cols,
+                                size.rows
+                            ),
+                            Err(e) => tracing::error!(
+                                parent: &span,
+                                "Failed to resize session {}: {}",
+                                session_id,
+                                e
+                            ),
                         }
                     }
 
                     TunnelEvent::SessionClose { session_id } => {
-                        tracing::info!("Closing session {}", session_id);
+                        let span = session_spans.close(session_id);
+                        tracing::info!(parent: &span, "Closing session {}", session_id);
 
                         // Close the PTY first - this will cause the reader to exit
                         let exit_code = {
@@ -395,8 +535,9 @@ async fn run_event_loop(
                                 std::time::Duration::from_millis(500),
                                 handle
                             ).await;
-                            tracing::debug!("Reader task cleaned up for session {}", session_id);
+                            tracing::debug!(parent: &span, "Reader task cleaned up for session {}", session_id);
                         }
+                        remove_session_journal(journal_config, session_id);
 
                         // Send close notification
                         if let Err(e) = tunnel.send_session_close(session_id, exit_code).await {
@@ -411,6 +552,19 @@ async fn run_event_loop(
                         }
                     }
 
+                    TunnelEvent::ConfigUpdate { key, value } => {
+                        let result = runtime_config.apply(&key, &value);
+                        match &result {
+                            Ok(()) => tracing::info!("Orchestrator set {} to {}", key, value),
+                            Err(reason) => {
+                                tracing::warn!("Not applying {} = {}: {}", key, value, reason)
+                            }
+                        }
+                        if let Err(e) = tunnel.send_config_ack(key, result).await {
+                            tracing::error!("Failed to send config ack: {}", e);
+                        }
+                    }
+
                     TunnelEvent::Disconnected => {
                         // Gracefully cancel all reader tasks and wait for cleanup
                         for (session_id, (handle, cancel_token)) in reader_tasks.drain() {
@@ -421,50 +575,161 @@ async fn run_event_loop(
                             ).await;
                             tracing::debug!("Reader task cleaned up for session {} on disconnect", session_id);
                         }
-                        return "Disconnected by orchestrator".to_string();
+                        return Ok("Disconnected by orchestrator".to_string());
                     }
                 }
 
-                // Check for exited sessions - collect all at once while holding lock
-                let exited_sessions: Vec<(SessionId, i32)> = {
-                    let mut manager = pty_manager.lock().await;
-                    let mut exited = Vec::new();
+                reap_exited_sessions(
+                    tunnel,
+                    &pty_manager,
+                    &mut reader_tasks,
+                    &mut pty_output_rx,
+                    &mut session_spans,
+                    journal_config,
+                ).await;
+            }
+
+            // A session's output ended: its shell has most likely exited
+            _ = sleep_until_opt(exit_recheck) => {
+                exit_recheck = None;
+                reap_exited_sessions(
+                    tunnel,
+                    &pty_manager,
+                    &mut reader_tasks,
+                    &mut pty_output_rx,
+                    &mut session_spans,
+                    journal_config,
+                ).await;
+            }
 
-                    for session_id in manager.list_sessions() {
-                        if let Ok(Some(exit_code)) = manager.try_wait(session_id) {
-                            tracing::info!("Session {} exited with code {}", session_id, exit_code);
-                            manager.close(session_id);
-                            exited.push((session_id, exit_code));
+            // Finish creating sessions once their PTY is spawned
+            Some(created) = pty_created_rx.recv() => {
+                let session_id = created.session_id;
+                match created.result {
+                    Ok(session) => {
+                        let mut manager = pty_manager.lock().await;
+                        // Applies queued resizes and input; None if closed meanwhile
+                        let Some(pid) = manager.complete_session(session) else {
+                            continue;
+                        };
+
+                        // Send session ready notification
+                        if let Err(e) = tunnel.send_session_ready(session_id, pid).await {
+                            tracing::error!("Failed to send session ready: {}", e);
                         }
-                    }
-                    exited
-                };
 
-                // Clean up reader tasks and notify orchestrator (outside the lock)
-                for (session_id, exit_code) in exited_sessions {
-                    if let Some((handle, cancel_token)) = reader_tasks.remove(&session_id) {
-                        cancel_token.cancel();
-                        let _ = tokio::time::timeout(
-                            std::time::Duration::from_millis(500),
-                            handle
-                        ).await;
+                        // Take the output readers and spawn a blocking task for each
+                        match manager.take_readers(session_id) {
+                            Ok(readers) => {
+                                let cancel_token = CancellationToken::new();
+                                let mut journal = open_session_journal(journal_config, session_id);
+                                let handles: Vec<_> = readers
+                                    .into_iter()
+                                    .map(|OutputReader { stream, reader, fd }| {
+                                        // Only stdout is journaled
+                                        let journal = match stream {
+                                            OutputStream::Stdout => journal.take(),
+                                            OutputStream::Stderr => None,
+                                        };
+                                        let reader = watchdog.watch(
+                                            session_id,
+                                            stream,
+                                            reader,
+                                            fd,
+                                            cancel_token.clone(),
+                                        );
+                                        spawn_pty_reader(
+                                            session_id,
+                                            stream,
+                                            reader,
+                                            pty_output_tx.clone(),
+                                            journal,
+                                            cancel_token.clone(),
+                                        )
+                                    })
+                                    .collect();
+                                let handle = tokio::spawn(async move {
+                                    for handle in handles {
+                                        let _ = handle.await;
+                                    }
+                                });
+                                reader_tasks.insert(session_id, (handle, cancel_token));
+                                tracing::debug!(
+                                    parent: &session_spans.get(session_id),
+                                    "Spawned reader task for session {} (pid {})",
+                                    session_id,
+                                    pid
+                                );
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to take reader for session {}: {}", session_id, e);
+                            }
+                        }
                     }
+                    Err(e) => {
+                        let span = session_spans.close(session_id);
+                        tracing::error!(parent: &span, "Failed to create session {}: {}", session_id, e);
+                        pty_manager.lock().await.fail_session(session_id);
+                        // Send error back to orchestrator
+                        if let Err(send_err) = tunnel.send_error(
+                            session_id,
+                            kt_protocol::ErrorCode::PtyAllocationFailed,
+                            format!("Failed to create session: {}", e),
+                        ).await {
+                            tracing::error!("Failed to send error to orchestrator: {}", send_err);
+                        }
+                    }
+                }
+            }
 
-                    if let Err(e) = tunnel.send_session_close(session_id, Some(exit_code)).await {
+            // The primary orchestrator is back: end sessions here so this
+            // fallback reports them closed, then reconnect to the primary
+            _ = &mut failback => {
+                let closed: Vec<(SessionId, Option<i32>)> = {
+                    let mut manager = pty_manager.lock().await;
+                    let closed = manager
+                        .list_sessions()
+                        .into_iter()
+                        .map(|session_id| (session_id, manager.close(session_id)))
+                        .collect();
+                    manager.clear_pending();
+                    closed
+                };
+                for (session_id, (handle, cancel_token)) in reader_tasks.drain() {
+                    cancel_token.cancel();
+                    let _ = tokio::time::timeout(
+                        std::time::Duration::from_millis(500),
+                        handle
+                    ).await;
+                    tracing::debug!("Reader task cleaned up for session {} on failback", session_id);
+                }
+                for (session_id, exit_code) in closed {
+                    remove_session_journal(journal_config, session_id);
+                    if let Err(e) = tunnel.send_session_close(session_id, exit_code).await {
                         tracing::error!("Failed to send session close: {}", e);
                     }
                 }
+                return Ok("Primary orchestrator is back, failing back".to_string());
             }
 
             // Handle PTY output from reader tasks
             pty_output = pty_output_rx.recv() => {
                 match pty_output {
-                    Some(output) => {
-                        // Send the PTY output to the orchestrator
-                        if let Err(e) = tunnel.send_data(output.session_id, &output.data).await {
-                            tracing::error!("Failed to send PTY data for session {}: {}", output.session_id, e);
+                    Some(output) if output.data.is_empty() => {
+                        // Report the exit without waiting for the next event
+                        reap_exited_sessions(
+                            tunnel,
+                            &pty_manager,
+                            &mut reader_tasks,
+                            &mut pty_output_rx,
+                            &mut session_spans,
+                            journal_config,
+                        ).await;
+                        if pty_manager.lock().await.get(output.session_id).is_some() {
+                            exit_recheck = Some(tokio::time::Instant::now() + EXIT_RECHECK_DELAY);
                         }
                     }
+                    Some(output) => forward_output(tunnel, output).await,
                     None => {
                         // All senders dropped - this shouldn't happen during normal operation
                         tracing::warn!("PTY output channel closed unexpectedly");
@@ -475,14 +740,146 @@ async fn run_event_loop(
     }
 }
 
+/// Compare the orchestrator's reported version with ours
+///
+/// A patch difference is fine. Anything larger is warned about loudly, or
+/// refused with `strict_version`. Never fails otherwise, so older
+/// orchestrators that don't report a version still connect.
+fn check_orchestrator_version(orchestrator: Option<&str>, strict_version: bool) -> Result<()> {
+    let Some(orchestrator) = orchestrator else {
+        tracing::debug!("Orchestrator did not report its version");
+        return Ok(());
+    };
+    let skew = compare_versions(AGENT_VERSION, orchestrator);
+    if !skew.is_mismatch() {
+        tracing::debug!(
+            "Orchestrator version {} ({} with agent {})",
+            orchestrator,
+            skew,
+            AGENT_VERSION
+        );
+        return Ok(());
+    }
+
+    if strict_version {
+        anyhow::bail!(
+            "Orchestrator version {} does not match agent version {} ({}); \
+             refusing to continue because strict_version is set",
+            orchestrator,
+            AGENT_VERSION,
+            skew
+        );
+    }
+    tracing::warn!(
+        "==============================================================\n\
+         Orchestrator version {} does not match agent version {} ({}).\n\
+         Sessions may misbehave; update the older side.\n\
+         ==============================================================",
+        orchestrator,
+        AGENT_VERSION,
+        skew
+    );
+    Ok(())
+}
+
+/// Check every session for an exited shell and report the ones that have,
+/// once their remaining output has been forwarded
+async fn reap_exited_sessions(
+    tunnel: &kt_agent::tunnel::ActiveTunnel,
+    pty_manager: &Mutex<PtyManager>,
+    reader_tasks: &mut HashMap<SessionId, (JoinHandle<()>, CancellationToken)>,
+    pty_output_rx: &mut mpsc::Receiver<PtyOutput>,
+    session_spans: &mut SessionSpans,
+    journal_config: &SessionJournalConfig,
+) {
+    // Collect all at once while holding the lock
+    let exited_sessions: Vec<(SessionId, i32)> = {
+        let mut manager = pty_manager.lock().await;
+        let mut exited = Vec::new();
+
+        for session_id in manager.list_sessions() {
+            if let Ok(Some(exit_code)) = manager.try_wait(session_id) {
+                tracing::info!(
+                    parent: &session_spans.close(session_id),
+                    "Session {} exited with code {}",
+                    session_id,
+                    exit_code
+                );
+                manager.close(session_id);
+                exited.push((session_id, exit_code));
+            }
+        }
+        exited
+    };
+
+    // Clean up reader tasks and notify orchestrator (outside the lock)
+    for (session_id, exit_code) in exited_sessions {
+        if let Some((mut handle, cancel_token)) = reader_tasks.remove(&session_id) {
+            // Let the readers reach the end of the output so it all arrives
+            // before the close
+            let deadline = tokio::time::sleep(READER_DRAIN_TIMEOUT);
+            tokio::pin!(deadline);
+            loop {
+                tokio::select! {
+                    _ = &mut handle => break,
+                    Some(output) = pty_output_rx.recv() => forward_output(tunnel, output).await,
+                    _ = &mut deadline => break,
+                }
+            }
+            while let Ok(output) = pty_output_rx.try_recv() {
+                forward_output(tunnel, output).await;
+            }
+            cancel_token.cancel();
+        }
+        remove_session_journal(journal_config, session_id);
+
+        if let Err(e) = tunnel.send_session_close(session_id, Some(exit_code)).await {
+            tracing::error!("Failed to send session close: {}", e);
+        }
+    }
+}
+
+/// Send a reader's output to the orchestrator (end-of-stream markers are
+/// dropped)
+async fn forward_output(tunnel: &kt_agent::tunnel::ActiveTunnel, output: PtyOutput) {
+    if output.data.is_empty() {
+        return;
+    }
+    let result = match output.stream {
+        OutputStream::Stdout => tunnel.send_data(output.session_id, &output.data).await,
+        OutputStream::Stderr => tunnel.send_stderr(output.session_id, &output.data).await,
+    };
+    if let Err(e) = result {
+        tracing::error!(
+            "Failed to send PTY data for session {}: {}",
+            output.session_id,
+            e
+        );
+    }
+}
+
+/// Sleep until `deadline`, or forever without one
+async fn sleep_until_opt(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Spawn a blocking task to read from a PTY and send output to the channel.
 ///
+/// Output is also appended to `journal`, if given. Unless cancelled, the
+/// task sends an empty chunk when the stream ends.
+///
 /// Uses a `CancellationToken` for graceful shutdown instead of task abort.
-/// The token is checked between reads to allow clean termination.
+/// The token is checked between reads to allow clean termination; a read
+/// that blocks past it is cut off by the [`ReaderWatchdog`].
 fn spawn_pty_reader(
     session_id: SessionId,
-    mut reader: Box<dyn Read + Send>,
+    stream: OutputStream,
+    mut reader: WatchedReader,
     tx: mpsc::Sender<PtyOutput>,
+    mut journal: Option<SessionJournal>,
     cancel_token: CancellationToken,
 ) -> JoinHandle<()> {
     tokio::task::spawn_blocking(move || {
@@ -503,8 +900,26 @@ fn spawn_pty_reader(
                 }
                 Ok(n) => {
                     let data = buf[..n].to_vec();
+                    if let Some(writer) = journal.as_mut() {
+                        if let Err(e) = writer.append(&data) {
+                            // Stop journaling rather than fail on every read
+                            tracing::warn!(
+                                "Session {} journal write failed, no longer journaling: {}",
+                                session_id,
+                                e
+                            );
+                            journal = None;
+                        }
+                    }
                     // Try to send the data - if the channel is closed, the session was closed
-                    if tx.blocking_send(PtyOutput { session_id, data }).is_err() {
+                    if tx
+                        .blocking_send(PtyOutput {
+                            session_id,
+                            stream,
+                            data,
+                        })
+                        .is_err()
+                    {
                         tracing::debug!("PTY output channel closed for session {}", session_id);
                         break;
                     }
@@ -525,14 +940,73 @@ fn spawn_pty_reader(
             }
         }
 
+        if !cancel_token.is_cancelled() {
+            let _ = tx.blocking_send(PtyOutput {
+                session_id,
+                stream,
+                data: Vec::new(),
+            });
+        }
         tracing::debug!("PTY reader task exiting cleanly for session {}", session_id);
     })
 }
 
+/// Open the output journal for a new session, if journaling is enabled
+fn open_session_journal(
+    config: &SessionJournalConfig,
+    session_id: SessionId,
+) -> Option<SessionJournal> {
+    if !config.enabled {
+        return None;
+    }
+    let dir = config.journal_dir();
+    match SessionJournal::open(&dir, session_id, config.max_bytes()) {
+        Ok(journal) => Some(journal),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to open journal for session {} in {:?}: {}",
+                session_id,
+                dir,
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Delete a cleanly closed session's output journal
+fn remove_session_journal(config: &SessionJournalConfig, session_id: SessionId) {
+    if !config.enabled {
+        return;
+    }
+    if let Err(e) = journal::remove(&config.journal_dir(), session_id) {
+        tracing::warn!("Failed to remove journal for session {}: {}", session_id, e);
+    }
+}
+
+/// Keep journals of sessions cut short by the last agent exit out of the way
+/// of new sessions
+fn archive_session_journals(config: &SessionJournalConfig) {
+    let dir = config.journal_dir();
+    match journal::archive_previous(&dir) {
+        Ok(0) => {}
+        Ok(count) => tracing::info!(
+            "Kept output of {} session(s) from the previous run in {:?}",
+            count,
+            dir.join("previous")
+        ),
+        Err(e) => tracing::warn!("Failed to archive session journals in {:?}: {}", dir, e),
+    }
+}
+
 /// Ensure an SSH key exists at the given path, generating one if needed
+///
+/// ssh-keygen creates the private key readable by the owner only; an existing
+/// key that other users can read is tightened, as is the config directory.
 async fn ensure_ssh_key(path: &std::path::Path) -> Result<()> {
     if path.exists() {
         tracing::debug!("Using existing SSH key at {:?}", path);
+        kt_core::permissions::check_permissions(&[path]);
         return Ok(());
     }
 
@@ -540,8 +1014,7 @@ async fn ensure_ssh_key(path: &std::path::Path) -> Result<()> {
 
     // Ensure parent directory exists
     if let Some(parent) = path.parent() {
-        tokio::fs::create_dir_all(parent)
-            .await
+        kt_core::permissions::create_private_dir_all(parent)
             .with_context(|| format!("Failed to create directory {:?}", parent))?;
     }
 
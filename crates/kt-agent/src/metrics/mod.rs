@@ -1,6 +1,19 @@
 //! System metrics collection
+//!
+//! While a metrics interval is set, [`run_metrics_reporter`] logs the host's
+//! metrics at that interval. The agent config sets it
+//! (`metrics_interval_secs`) and the orchestrator can change it for a
+//! connection (`metrics_interval`, see [`crate::runtime_config`]).
+//!
+//! Reports reuse one [`MetricsCollector`], refreshing only CPU usage,
+//! memory and disk space rather than everything `sysinfo` knows. With no
+//! interval set there is no collector and no timer: the reporter just waits
+//! for the interval to change.
 
-use sysinfo::System;
+use std::time::{Duration, Instant};
+
+use sysinfo::{Disks, System, MINIMUM_CPU_UPDATE_INTERVAL};
+use tokio::sync::watch;
 
 /// System metrics for a machine
 #[derive(Debug, Clone)]
@@ -23,17 +36,72 @@ pub struct SystemMetrics {
 
 impl SystemMetrics {
     /// Collect current system metrics
+    ///
+    /// Blocks for [`MINIMUM_CPU_UPDATE_INTERVAL`] to measure CPU usage; use
+    /// a [`MetricsCollector`] to collect repeatedly.
     pub fn collect() -> Self {
-        let mut sys = System::new_all();
-        sys.refresh_all();
+        MetricsCollector::new().collect()
+    }
+
+    /// Get a human-readable summary of the metrics
+    pub fn summary(&self) -> String {
+        format!(
+            "CPU: {:.1}%, Memory: {:.1}% ({}/{}), Disk: {}/{} available",
+            self.cpu_percent,
+            self.memory_percent,
+            human_bytes(self.memory_used),
+            human_bytes(self.memory_total),
+            human_bytes(self.disk_available),
+            human_bytes(self.disk_total),
+        )
+    }
+}
+
+/// Collects [`SystemMetrics`] repeatedly from the same `sysinfo` state
+///
+/// CPU usage is the difference between two samples, so each collection
+/// measures it since the one before (or since the collector was created).
+pub struct MetricsCollector {
+    system: System,
+    disks: Disks,
+    /// When CPU usage was last sampled
+    cpu_sampled_at: Instant,
+}
+
+impl MetricsCollector {
+    /// Create a collector, taking the first CPU sample
+    pub fn new() -> Self {
+        let mut system = System::new();
+        system.refresh_cpu_usage();
+        Self {
+            system,
+            disks: Disks::new_with_refreshed_list(),
+            cpu_sampled_at: Instant::now(),
+        }
+    }
+
+    /// Collect current system metrics
+    ///
+    /// Blocks until [`MINIMUM_CPU_UPDATE_INTERVAL`] has passed since the
+    /// last sample, as CPU usage can't be measured over less.
+    pub fn collect(&mut self) -> SystemMetrics {
+        let since_sample = self.cpu_sampled_at.elapsed();
+        if since_sample < MINIMUM_CPU_UPDATE_INTERVAL {
+            std::thread::sleep(MINIMUM_CPU_UPDATE_INTERVAL - since_sample);
+        }
+        self.system.refresh_cpu_usage();
+        self.cpu_sampled_at = Instant::now();
+        self.system.refresh_memory();
+        self.disks.refresh();
 
         // Calculate CPU usage (average across all cores)
-        let cpu_percent = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).sum::<f32>()
-            / sys.cpus().len().max(1) as f32;
+        let cpus = self.system.cpus();
+        let cpu_percent =
+            cpus.iter().map(|cpu| cpu.cpu_usage()).sum::<f32>() / cpus.len().max(1) as f32;
 
         // Memory metrics
-        let memory_total = sys.total_memory();
-        let memory_used = sys.used_memory();
+        let memory_total = self.system.total_memory();
+        let memory_used = self.system.used_memory();
         let memory_percent = if memory_total > 0 {
             (memory_used as f32 / memory_total as f32) * 100.0
         } else {
@@ -41,16 +109,17 @@ impl SystemMetrics {
         };
 
         // Disk metrics (sum all disks)
-        let disks = sysinfo::Disks::new_with_refreshed_list();
-        let (disk_total, disk_available) =
-            disks.iter().fold((0u64, 0u64), |(total, avail), disk| {
+        let (disk_total, disk_available) = self
+            .disks
+            .iter()
+            .fold((0u64, 0u64), |(total, avail), disk| {
                 (total + disk.total_space(), avail + disk.available_space())
             });
 
         // Load average (Unix only)
         let load_avg_1m = System::load_average().one as f32;
 
-        Self {
+        SystemMetrics {
             cpu_percent,
             memory_percent,
             memory_total,
@@ -60,18 +129,55 @@ impl SystemMetrics {
             load_avg_1m,
         }
     }
+}
 
-    /// Get a human-readable summary of the metrics
-    pub fn summary(&self) -> String {
-        format!(
-            "CPU: {:.1}%, Memory: {:.1}% ({}/{}), Disk: {}/{} available",
-            self.cpu_percent,
-            self.memory_percent,
-            human_bytes(self.memory_used),
-            human_bytes(self.memory_total),
-            human_bytes(self.disk_available),
-            human_bytes(self.disk_total),
-        )
+impl Default for MetricsCollector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Log host metrics every `interval`, while it's set
+///
+/// Follows changes of `interval`, starting over with each, and returns once
+/// its sender is dropped. The collector is created when an interval is set
+/// and dropped when it's cleared.
+pub async fn run_metrics_reporter(mut interval: watch::Receiver<Option<Duration>>) {
+    let mut collector: Option<MetricsCollector> = None;
+    loop {
+        let period = *interval.borrow_and_update();
+        if period.is_none() {
+            collector = None;
+        }
+        let tick = async {
+            match period {
+                Some(period) => tokio::time::sleep(period).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            changed = interval.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+            }
+            _ = tick => {
+                // Collecting takes a moment and blocks
+                let mut current = collector.take().unwrap_or_default();
+                let collected = tokio::task::spawn_blocking(move || {
+                    let metrics = current.collect();
+                    (current, metrics)
+                })
+                .await;
+                match collected {
+                    Ok((current, metrics)) => {
+                        tracing::info!("Host metrics: {}", metrics.summary());
+                        collector = Some(current);
+                    }
+                    Err(e) => tracing::warn!("Failed to collect host metrics: {}", e),
+                }
+            }
+        }
     }
 }
 
@@ -108,6 +214,16 @@ mod tests {
         assert!(metrics.memory_total > 0);
     }
 
+    #[test]
+    fn test_collector_reuses_state() {
+        let mut collector = MetricsCollector::new();
+        for _ in 0..2 {
+            let metrics = collector.collect();
+            assert!(metrics.cpu_percent >= 0.0 && metrics.cpu_percent <= 100.0);
+            assert!(metrics.memory_total > 0);
+        }
+    }
+
     #[test]
     fn test_human_bytes() {
         assert_eq!(human_bytes(0), "0B");
@@ -0,0 +1,274 @@
+//! On-disk journal of session output
+//!
+//! Each session's output is appended to `<dir>/session-<id>.log` as it is read
+//! from the PTY. Writes go to the OS without `fsync`, which is enough to
+//! survive the agent crashing or being restarted but not the machine losing
+//! power.
+//!
+//! A journal is capped at `max_bytes`: once the current file reaches half the
+//! cap it is moved to `session-<id>.log.1` (replacing the previous one) and a
+//! new file is started, so the journal holds between half and all of the cap
+//! of the most recent output. Journals are removed when their session closes
+//! cleanly; whatever is left when the agent starts again came from sessions
+//! that were cut short, and is moved to `<dir>/previous/` so new sessions
+//! reusing the same IDs don't append to it.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use kt_protocol::SessionId;
+
+/// Subdirectory journals from the previous agent run are moved to
+const PREVIOUS_DIR: &str = "previous";
+
+/// Output journal for one session
+#[derive(Debug)]
+pub struct SessionJournal {
+    path: PathBuf,
+    rotated_path: PathBuf,
+    file: File,
+    len: u64,
+    segment_max: u64,
+}
+
+impl SessionJournal {
+    /// Open the journal for `session_id` in `dir`, creating the directory if
+    /// needed. Existing output for the session is kept.
+    pub fn open(dir: &Path, session_id: SessionId, max_bytes: u64) -> io::Result<Self> {
+        kt_core::permissions::create_private_dir_all(dir)?;
+        let path = journal_path(dir, session_id);
+        let file = open_append(&path)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            rotated_path: rotated_path(&path),
+            path,
+            file,
+            len,
+            segment_max: (max_bytes / 2).max(1),
+        })
+    }
+
+    /// Append output, rotating first if it would take the current file past
+    /// half the cap
+    pub fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        // A single chunk bigger than a segment keeps only its tail
+        let data = &data[data.len().saturating_sub(self.segment_max as usize)..];
+        if self.len > 0 && self.len + data.len() as u64 > self.segment_max {
+            self.rotate()?;
+        }
+        self.file.write_all(data)?;
+        self.len += data.len() as u64;
+        Ok(())
+    }
+
+    /// Journaled output, oldest first
+    pub fn replay(&self) -> io::Result<Vec<u8>> {
+        read_journal(&self.path)
+    }
+
+    /// Delete the journal's files
+    pub fn remove(self) -> io::Result<()> {
+        remove_journal_files(&self.path)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, &self.rotated_path)?;
+        self.file = open_append(&self.path)?;
+        self.len = 0;
+        Ok(())
+    }
+}
+
+/// Journaled output for `session_id` in `dir`, oldest first; empty if there
+/// is none
+pub fn replay(dir: &Path, session_id: SessionId) -> io::Result<Vec<u8>> {
+    read_journal(&journal_path(dir, session_id))
+}
+
+/// Delete the journal for `session_id` in `dir`, if any
+pub fn remove(dir: &Path, session_id: SessionId) -> io::Result<()> {
+    remove_journal_files(&journal_path(dir, session_id))
+}
+
+/// Move journals left by an earlier run to `<dir>/previous/`, replacing what
+/// was there.
+///
+/// Returns how many sessions' journals were moved.
+pub fn archive_previous(dir: &Path) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let leftovers: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && is_journal_file(path))
+        .collect();
+    if leftovers.is_empty() {
+        return Ok(0);
+    }
+
+    let previous = dir.join(PREVIOUS_DIR);
+    match fs::remove_dir_all(&previous) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    kt_core::permissions::create_private_dir_all(&previous)?;
+
+    let mut sessions = 0;
+    for path in leftovers {
+        if let Some(name) = path.file_name() {
+            fs::rename(&path, previous.join(name))?;
+            if path.extension().is_some_and(|ext| ext == "log") {
+                sessions += 1;
+            }
+        }
+    }
+    Ok(sessions)
+}
+
+fn journal_path(dir: &Path, session_id: SessionId) -> PathBuf {
+    dir.join(format!("session-{}.log", session_id.0))
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+fn is_journal_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| {
+            name.starts_with("session-") && (name.ends_with(".log") || name.ends_with(".log.1"))
+        })
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+fn read_journal(path: &Path) -> io::Result<Vec<u8>> {
+    let mut output = read_if_exists(&rotated_path(path))?;
+    output.extend(read_if_exists(path)?);
+    Ok(output)
+}
+
+fn read_if_exists(path: &Path) -> io::Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(data) => Ok(data),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+fn remove_journal_files(path: &Path) -> io::Result<()> {
+    for path in [rotated_path(path), path.to_path_buf()] {
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_rotates_at_cap() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = SessionJournal::open(dir.path(), SessionId(1), 8).unwrap();
+
+        journal.append(b"abc").unwrap();
+        journal.append(b"d").unwrap();
+        assert_eq!(journal.replay().unwrap(), b"abcd");
+
+        // Past half the cap: "abcd" becomes the rotated segment
+        journal.append(b"ef").unwrap();
+        assert_eq!(journal.replay().unwrap(), b"abcdef");
+
+        // Rotating again drops the oldest segment
+        journal.append(b"ghi").unwrap();
+        assert_eq!(journal.replay().unwrap(), b"efghi");
+
+        let total: u64 = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().metadata().unwrap().len())
+            .sum();
+        assert!(total <= 8);
+
+        // An oversized chunk keeps its most recent bytes
+        journal.append(b"0123456789").unwrap();
+        assert_eq!(journal.replay().unwrap(), b"ghi6789");
+    }
+
+    #[test]
+    fn test_journal_removed_on_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = SessionJournal::open(dir.path(), SessionId(2), 4).unwrap();
+        journal.append(b"hello").unwrap();
+        journal.append(b"world").unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        journal.remove().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+        assert!(replay(dir.path(), SessionId(2)).unwrap().is_empty());
+
+        // Removing a journal that was never written is fine
+        remove(dir.path(), SessionId(3)).unwrap();
+    }
+
+    #[test]
+    fn test_replay_precedes_live_output() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut journal = SessionJournal::open(dir.path(), SessionId(4), 1024).unwrap();
+            journal.append(b"before restart\n").unwrap();
+        }
+
+        // Reopening (as after a restart) keeps what was there and appends
+        // new output after it
+        let mut journal = SessionJournal::open(dir.path(), SessionId(4), 1024).unwrap();
+        let replayed = journal.replay().unwrap();
+        journal.append(b"live\n").unwrap();
+
+        let all = replay(dir.path(), SessionId(4)).unwrap();
+        assert_eq!(replayed, b"before restart\n");
+        assert_eq!(all, b"before restart\nlive\n");
+        assert!(all.starts_with(&replayed));
+    }
+
+    #[test]
+    fn test_archive_previous_moves_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = SessionJournal::open(dir.path(), SessionId(5), 8).unwrap();
+        journal.append(b"abc").unwrap();
+        journal.append(b"def").unwrap();
+        drop(journal);
+
+        assert_eq!(archive_previous(dir.path()).unwrap(), 1);
+        assert!(replay(dir.path(), SessionId(5)).unwrap().is_empty());
+        assert_eq!(
+            replay(&dir.path().join(PREVIOUS_DIR), SessionId(5)).unwrap(),
+            b"abcdef"
+        );
+
+        // Nothing left over: the previous archive is kept
+        assert_eq!(archive_previous(dir.path()).unwrap(), 0);
+        assert!(dir.path().join(PREVIOUS_DIR).join("session-5.log").exists());
+    }
+}
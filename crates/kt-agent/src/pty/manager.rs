@@ -1,16 +1,37 @@
 //! PTY session management
 //!
 //! Manages pseudo-terminal sessions using the portable-pty crate.
+//!
+//! Spawning a shell can take a while, so the agent creates PTYs off its event
+//! loop: [`PtyManager::begin_session`] marks the session as pending and
+//! returns a [`SessionSpec`] to spawn elsewhere, and
+//! [`PtyManager::complete_session`] adds the spawned session. Input and
+//! resizes for a pending session are queued rather than dropped, then applied
+//! once the PTY exists: the most recent resize, followed by the input in the
+//! order it arrived.
+//!
+//! Sessions created without a PTY run the shell on plain pipes instead, so
+//! stdout and stderr stay apart and carry no terminal control codes. Writing
+//! empty input to such a session closes the shell's stdin.
+//!
+//! Every session gets a `TERM`: the client's terminal type when it reports
+//! one (see [`terminal_env`]), otherwise the manager's default, so remote
+//! programs don't inherit whatever the agent daemon was started with.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
 use std::path::Path;
+use std::process::{Child, ChildStderr, ChildStdout, Command, ExitStatus, Stdio};
 
 use anyhow::{Context, Result};
 use portable_pty::{native_pty_system, CommandBuilder, PtyPair, PtySize, PtySystem};
 
+use kt_core::ipc::{validate_term, OutputStream};
 use kt_protocol::{SessionId, TerminalSize};
 
+/// `TERM` for sessions whose client doesn't report its terminal type
+pub const DEFAULT_TERM: &str = "xterm-256color";
+
 /// Allowed shell paths for security (prevents arbitrary command execution)
 const ALLOWED_SHELLS_UNIX: &[&str] = &[
     "/bin/sh",
@@ -91,16 +112,23 @@ fn validate_shell_path(shell: &str) -> Result<String> {
     Ok(shell.to_string())
 }
 
+/// Most input bytes queued for a session whose PTY is still being created
+pub const MAX_PENDING_INPUT: usize = 1024 * 1024;
+
 /// Manages PTY sessions on the local machine
 pub struct PtyManager {
     /// The PTY system
     pty_system: Box<dyn PtySystem + Send>,
     /// Active sessions
     sessions: HashMap<SessionId, PtySession>,
+    /// Sessions whose PTY is being created, with what arrived for them meanwhile
+    pending: HashMap<SessionId, PendingOps>,
     /// Default shell
     default_shell: Option<String>,
     /// Default environment variables
     default_env: Vec<(String, String)>,
+    /// `TERM` unless the client or `default_env` sets one
+    default_term: String,
 }
 
 /// A PTY session with its associated I/O handles
@@ -109,14 +137,128 @@ pub struct PtySession {
     pub session_id: SessionId,
     /// Process ID of the shell
     pub pid: Option<u32>,
-    /// The PTY pair (master + slave)
-    pty_pair: PtyPair,
-    /// Child process handle
-    child: Box<dyn portable_pty::Child + Send + Sync>,
-    /// Writer to send data to the PTY
-    writer: Box<dyn Write + Send>,
-    /// Reader to receive data from the PTY
-    reader: Box<dyn Read + Send>,
+    /// The shell and how it is attached
+    process: SessionProcess,
+    /// Writer to send data to the PTY or the shell's stdin (None once a
+    /// pipe session's stdin is closed)
+    writer: Option<Box<dyn Write + Send>>,
+}
+
+/// How a session's shell is attached
+enum SessionProcess {
+    /// Behind a PTY, which merges stdout and stderr
+    Pty {
+        /// The PTY pair (master + slave)
+        pty_pair: PtyPair,
+        /// Child process handle
+        child: Box<dyn portable_pty::Child + Send + Sync>,
+        /// Reader to receive data from the PTY
+        reader: Box<dyn Read + Send>,
+    },
+    /// On plain pipes, in its own process group
+    Pipes {
+        child: Child,
+        /// Taken by [`PtyManager::take_readers`]
+        stdout: Option<ChildStdout>,
+        stderr: Option<ChildStderr>,
+    },
+}
+
+/// One of a session's output streams, taken by [`PtyManager::take_readers`]
+pub struct OutputReader {
+    /// Stderr only for sessions without a PTY
+    pub stream: OutputStream,
+    pub reader: Box<dyn Read + Send>,
+    /// Descriptor `reader` reads from (unix), so a hung read can be cut off
+    pub fd: Option<i32>,
+}
+
+/// Reader for a PTY master that treats the slave closing as the end of output
+#[cfg(unix)]
+struct PtyReader(std::fs::File);
+
+#[cfg(unix)]
+impl Read for PtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            // EIO means the slave side has been closed
+            Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// Descriptor of a pipe, for the reader watchdog
+#[cfg(unix)]
+fn raw_fd(pipe: &impl std::os::fd::AsRawFd) -> Option<i32> {
+    Some(pipe.as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn raw_fd<T>(_pipe: &T) -> Option<i32> {
+    None
+}
+
+/// A PTY session ready to be spawned, with the manager's defaults applied
+#[derive(Debug, Clone)]
+pub struct SessionSpec {
+    /// Session ID
+    pub session_id: SessionId,
+    /// Requested shell (not validated yet)
+    pub shell: String,
+    /// Environment, defaults first
+    pub env: Vec<(String, String)>,
+    /// Working directory (None = inherit)
+    pub cwd: Option<String>,
+    /// Initial terminal size
+    pub size: TerminalSize,
+    /// Spawn in a PTY (false = plain pipes)
+    pub allocate_pty: bool,
+}
+
+/// Environment for the terminal type a client reported
+///
+/// Goes after the session's other variables so it wins over them. An
+/// invalid `term` is dropped with a warning, leaving the default.
+pub fn terminal_env(term: Option<&str>, truecolor: bool) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    if let Some(term) = term {
+        match validate_term(term) {
+            Ok(()) => env.push(("TERM".to_string(), term.to_string())),
+            Err(e) => tracing::warn!("Ignoring client terminal type: {}", e),
+        }
+    }
+    if truecolor {
+        env.push(("COLORTERM".to_string(), "truecolor".to_string()));
+    }
+    env
+}
+
+/// Input and resizes received for a session before its PTY was ready
+#[derive(Debug, Default)]
+struct PendingOps {
+    /// Input chunks in arrival order
+    input: VecDeque<Vec<u8>>,
+    /// Total bytes in `input`
+    input_bytes: usize,
+    /// Most recent resize (earlier ones are superseded)
+    resize: Option<TerminalSize>,
+}
+
+impl PendingOps {
+    /// Queue input, dropping it if the queue is full
+    fn push_input(&mut self, session_id: SessionId, data: &[u8]) {
+        if self.input_bytes + data.len() > MAX_PENDING_INPUT {
+            tracing::warn!(
+                "Dropping {} bytes of input for session {}: PTY not ready and queue full",
+                data.len(),
+                session_id
+            );
+            return;
+        }
+        self.input_bytes += data.len();
+        self.input.push_back(data.to_vec());
+    }
 }
 
 /// Output from a PTY session
@@ -141,8 +283,10 @@ impl PtyManager {
         Self {
             pty_system: native_pty_system(),
             sessions: HashMap::new(),
+            pending: HashMap::new(),
             default_shell: None,
-            default_env: vec![("TERM".to_string(), "xterm-256color".to_string())],
+            default_env: vec![],
+            default_term: DEFAULT_TERM.to_string(),
         }
     }
 
@@ -151,45 +295,131 @@ impl PtyManager {
         default_shell: Option<String>,
         default_env: Vec<(String, String)>,
     ) -> Self {
-        let mut env = vec![("TERM".to_string(), "xterm-256color".to_string())];
-        env.extend(default_env);
-
         Self {
             pty_system: native_pty_system(),
             sessions: HashMap::new(),
+            pending: HashMap::new(),
             default_shell,
-            default_env: env,
+            default_env,
+            default_term: DEFAULT_TERM.to_string(),
         }
     }
 
+    /// Use `term` as the `TERM` of sessions whose client doesn't report one
+    pub fn with_default_term(mut self, term: impl Into<String>) -> Self {
+        self.default_term = term.into();
+        self
+    }
+
     /// Create a new PTY session
+    ///
+    /// Spawns the shell on the calling thread; see [`Self::begin_session`]
+    /// to spawn it elsewhere.
     pub fn create_session(
         &mut self,
         session_id: SessionId,
         shell: Option<String>,
         env: Vec<(String, String)>,
+        cwd: Option<String>,
         size: TerminalSize,
+        allocate_pty: bool,
     ) -> Result<u32> {
-        tracing::info!(
-            "Creating PTY session {} with size {}x{}",
-            session_id,
-            size.cols,
-            size.rows
-        );
+        let spec = self.session_spec(session_id, shell, env, cwd, size, allocate_pty);
+        let session = spec.spawn_with(self.pty_system.as_ref())?;
+        let pid = session.pid.unwrap_or(0);
+        self.sessions.insert(session_id, session);
+        Ok(pid)
+    }
 
-        // Open a PTY pair
-        let pty_pair = self
-            .pty_system
-            .openpty(PtySize {
-                rows: size.rows,
-                cols: size.cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            })
-            .with_context(|| "Failed to open PTY")?;
+    /// Start creating a PTY session without spawning it yet
+    ///
+    /// Until [`Self::complete_session`] (or [`Self::fail_session`]) is
+    /// called, input and resizes for the session are queued.
+    pub fn begin_session(
+        &mut self,
+        session_id: SessionId,
+        shell: Option<String>,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
+        size: TerminalSize,
+        allocate_pty: bool,
+    ) -> SessionSpec {
+        self.pending.insert(session_id, PendingOps::default());
+        self.session_spec(session_id, shell, env, cwd, size, allocate_pty)
+    }
+
+    /// Add a session spawned from [`Self::begin_session`]'s spec
+    ///
+    /// Applies the latest queued resize, then the queued input in order.
+    /// Returns the shell's PID, or None if the session was closed while it
+    /// was being created, in which case the new process is killed.
+    pub fn complete_session(&mut self, mut session: PtySession) -> Option<u32> {
+        let session_id = session.session_id;
+        let Some(pending) = self.pending.remove(&session_id) else {
+            tracing::info!("Session {} was closed before its PTY was ready", session_id);
+            session.kill();
+            return None;
+        };
+
+        if let Some(size) = pending.resize {
+            if let Err(e) = session.resize(size) {
+                tracing::warn!(
+                    "Failed to apply pending resize to session {}: {}",
+                    session_id,
+                    e
+                );
+            }
+        }
+        for data in &pending.input {
+            if let Err(e) = session.write(data) {
+                tracing::warn!(
+                    "Failed to apply pending input to session {}: {}",
+                    session_id,
+                    e
+                );
+                break;
+            }
+        }
+        if !pending.input.is_empty() {
+            tracing::debug!(
+                "Applied {} bytes of pending input to session {}",
+                pending.input_bytes,
+                session_id
+            );
+        }
 
-        // Determine which shell to use with security validation
-        let requested_shell = shell
+        let pid = session.pid.unwrap_or(0);
+        self.sessions.insert(session_id, session);
+        Some(pid)
+    }
+
+    /// Forget a session whose PTY could not be created, with its queued input
+    pub fn fail_session(&mut self, session_id: SessionId) {
+        self.pending.remove(&session_id);
+    }
+
+    /// Check if a session's PTY is still being created
+    pub fn is_pending(&self, session_id: SessionId) -> bool {
+        self.pending.contains_key(&session_id)
+    }
+
+    /// Forget all sessions still being created (their PTYs are killed when
+    /// they complete)
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Apply the manager's defaults to a session request
+    fn session_spec(
+        &self,
+        session_id: SessionId,
+        shell: Option<String>,
+        env: Vec<(String, String)>,
+        cwd: Option<String>,
+        size: TerminalSize,
+        allocate_pty: bool,
+    ) -> SessionSpec {
+        let shell = shell
             .or_else(|| self.default_shell.clone())
             .or_else(|| std::env::var("SHELL").ok())
             .unwrap_or_else(|| {
@@ -200,77 +430,35 @@ impl PtyManager {
                 }
             });
 
-        // Validate the shell path for security
-        let shell_path = validate_shell_path(&requested_shell)
-            .with_context(|| format!("Invalid shell requested: {}", requested_shell))?;
-
-        tracing::debug!("Using validated shell: {}", shell_path);
-
-        // Build the command
-        let mut cmd = CommandBuilder::new(&shell_path);
+        let mut all_env = vec![("TERM".to_string(), self.default_term.clone())];
+        all_env.extend(self.default_env.iter().cloned());
+        all_env.extend(env);
 
-        // Add environment variables
-        for (key, value) in &self.default_env {
-            cmd.env(key, value);
-        }
-        for (key, value) in &env {
-            cmd.env(key, value);
-        }
-
-        // Spawn the shell process
-        let child = pty_pair
-            .slave
-            .spawn_command(cmd)
-            .with_context(|| format!("Failed to spawn shell: {}", shell_path))?;
-
-        // Get the process ID
-        let pid = child.process_id();
-        tracing::info!("Spawned shell process with PID: {:?}", pid);
-
-        // Get reader/writer handles for the master side
-        let reader = pty_pair
-            .master
-            .try_clone_reader()
-            .with_context(|| "Failed to clone PTY reader")?;
-
-        let writer = pty_pair
-            .master
-            .take_writer()
-            .with_context(|| "Failed to take PTY writer")?;
-
-        // Create the session
-        let session = PtySession {
+        SessionSpec {
             session_id,
-            pid,
-            pty_pair,
-            child,
-            writer,
-            reader,
-        };
-
-        self.sessions.insert(session_id, session);
-
-        Ok(pid.unwrap_or(0))
+            shell,
+            env: all_env,
+            cwd,
+            size,
+            allocate_pty,
+        }
     }
 
     /// Write data to a session's PTY
+    ///
+    /// Input for a session whose PTY is still being created is queued.
     pub fn write(&mut self, session_id: SessionId, data: &[u8]) -> Result<()> {
+        if let Some(pending) = self.pending.get_mut(&session_id) {
+            pending.push_input(session_id, data);
+            return Ok(());
+        }
+
         let session = self
             .sessions
             .get_mut(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found: {}", session_id))?;
 
-        session
-            .writer
-            .write_all(data)
-            .with_context(|| "Failed to write to PTY")?;
-
-        session
-            .writer
-            .flush()
-            .with_context(|| "Failed to flush PTY")?;
-
-        Ok(())
+        session.write(data)
     }
 
     /// Read data from a session's PTY (non-blocking)
@@ -280,9 +468,13 @@ impl PtyManager {
             .get_mut(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found: {}", session_id))?;
 
+        let SessionProcess::Pty { reader, .. } = &mut session.process else {
+            anyhow::bail!("Session {} has no PTY", session_id);
+        };
+
         // Use non-blocking read
         // Note: portable-pty readers are blocking by default, so we need to handle this carefully
-        match session.reader.read(buf) {
+        match reader.read(buf) {
             Ok(0) => Ok(None), // EOF
             Ok(n) => Ok(Some(n)),
             Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => Ok(Some(0)),
@@ -290,49 +482,89 @@ impl PtyManager {
         }
     }
 
-    /// Take the reader from a session (for async I/O)
-    pub fn take_reader(&mut self, session_id: SessionId) -> Result<Box<dyn Read + Send>> {
+    /// Take the output readers from a session (for async I/O)
+    ///
+    /// A PTY session has a single reader for its merged output; a pipe
+    /// session has one for stdout and one for stderr, which can only be taken
+    /// once.
+    pub fn take_readers(&mut self, session_id: SessionId) -> Result<Vec<OutputReader>> {
         let session = self
             .sessions
             .get_mut(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found: {}", session_id))?;
 
-        // We need to clone the reader - this is a limitation of portable-pty
-        let reader = session
-            .pty_pair
-            .master
-            .try_clone_reader()
-            .with_context(|| "Failed to clone PTY reader")?;
-
-        Ok(reader)
+        match &mut session.process {
+            SessionProcess::Pty { pty_pair, .. } => {
+                // A reader of our own on unix, so its descriptor is known
+                #[cfg(unix)]
+                if let Some(fd) = pty_pair.master.as_raw_fd() {
+                    use std::os::fd::{AsRawFd, BorrowedFd};
+                    // SAFETY: the master stays open while the pair is borrowed
+                    let file = std::fs::File::from(
+                        unsafe { BorrowedFd::borrow_raw(fd) }
+                            .try_clone_to_owned()
+                            .with_context(|| "Failed to clone PTY reader")?,
+                    );
+                    return Ok(vec![OutputReader {
+                        stream: OutputStream::Stdout,
+                        fd: Some(file.as_raw_fd()),
+                        reader: Box::new(PtyReader(file)),
+                    }]);
+                }
+                // We need to clone the reader - this is a limitation of portable-pty
+                let reader = pty_pair
+                    .master
+                    .try_clone_reader()
+                    .with_context(|| "Failed to clone PTY reader")?;
+                Ok(vec![OutputReader {
+                    stream: OutputStream::Stdout,
+                    reader,
+                    fd: None,
+                }])
+            }
+            SessionProcess::Pipes { stdout, stderr, .. } => {
+                let mut readers = Vec::new();
+                if let Some(stdout) = stdout.take() {
+                    readers.push(OutputReader {
+                        stream: OutputStream::Stdout,
+                        fd: raw_fd(&stdout),
+                        reader: Box::new(stdout),
+                    });
+                }
+                if let Some(stderr) = stderr.take() {
+                    readers.push(OutputReader {
+                        stream: OutputStream::Stderr,
+                        fd: raw_fd(&stderr),
+                        reader: Box::new(stderr),
+                    });
+                }
+                Ok(readers)
+            }
+        }
     }
 
     /// Resize a session's PTY
+    ///
+    /// For a session whose PTY is still being created, only the most recent
+    /// size is kept and applied once it is ready.
     pub fn resize(&mut self, session_id: SessionId, size: TerminalSize) -> Result<()> {
+        if let Some(pending) = self.pending.get_mut(&session_id) {
+            tracing::debug!(
+                "Queueing resize of session {} to {}x{} until its PTY is ready",
+                session_id,
+                size.cols,
+                size.rows
+            );
+            pending.resize = Some(size);
+            return Ok(());
+        }
+
         let session = self
             .sessions
             .get_mut(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found: {}", session_id))?;
 
-        tracing::debug!(
-            "Resizing session {} to {}x{}",
-            session_id,
-            size.cols,
-            size.rows
-        );
-
-        session
-            .pty_pair
-            .master
-            .resize(PtySize {
-                rows: size.rows,
-                cols: size.cols,
-                pixel_width: 0,
-                pixel_height: 0,
-            })
-            .with_context(|| "Failed to resize PTY")?;
-
-        Ok(())
+        session.resize(size)
     }
 
     /// Check if a session's process has exited
@@ -342,9 +574,16 @@ impl PtyManager {
             .get_mut(&session_id)
             .ok_or_else(|| anyhow::anyhow!("Session not found: {}", session_id))?;
 
-        match session.child.try_wait() {
-            Ok(Some(status)) => {
-                let code = status.exit_code() as i32;
+        let status = match &mut session.process {
+            SessionProcess::Pty { child, .. } => child
+                .try_wait()
+                .map(|status| status.map(|s| s.exit_code() as i32)),
+            SessionProcess::Pipes { child, .. } => {
+                child.try_wait().map(|status| status.map(pipe_exit_code))
+            }
+        };
+        match status {
+            Ok(Some(code)) => {
                 tracing::info!("Session {} exited with code {}", session_id, code);
                 Ok(Some(code))
             }
@@ -357,21 +596,16 @@ impl PtyManager {
     }
 
     /// Close a session
+    ///
+    /// Closing a session whose PTY is still being created discards its
+    /// queued input; the PTY is killed as soon as it is ready.
     pub fn close(&mut self, session_id: SessionId) -> Option<i32> {
         tracing::info!("Closing PTY session {}", session_id);
 
-        if let Some(mut session) = self.sessions.remove(&session_id) {
-            // Try to kill the child process
-            let _ = session.child.kill();
-
-            // Wait for it to exit and get the exit code
-            match session.child.wait() {
-                Ok(status) => Some(status.exit_code() as i32),
-                Err(_) => None,
-            }
-        } else {
-            None
+        if self.pending.remove(&session_id).is_some() {
+            return None;
         }
+        self.sessions.remove(&session_id)?.kill()
     }
 
     /// Get a session by ID
@@ -406,9 +640,490 @@ impl Default for PtyManager {
     }
 }
 
+impl SessionSpec {
+    /// Spawn the shell in a new PTY (blocking)
+    pub fn spawn(self) -> Result<PtySession> {
+        self.spawn_with(native_pty_system().as_ref())
+    }
+
+    fn spawn_with(self, pty_system: &dyn PtySystem) -> Result<PtySession> {
+        if !self.allocate_pty {
+            return self.spawn_pipes();
+        }
+        let SessionSpec {
+            session_id,
+            shell,
+            env,
+            cwd,
+            size,
+            allocate_pty: _,
+        } = self;
+
+        tracing::info!(
+            "Creating PTY session {} with size {}x{}",
+            session_id,
+            size.cols,
+            size.rows
+        );
+
+        // Open a PTY pair
+        let pty_pair = pty_system
+            .openpty(pty_size(size))
+            .with_context(|| "Failed to open PTY")?;
+
+        // Validate the shell path for security
+        let shell_path = validate_shell_path(&shell)
+            .with_context(|| format!("Invalid shell requested: {}", shell))?;
+
+        tracing::debug!("Using validated shell: {}", shell_path);
+
+        // Build the command
+        let mut cmd = CommandBuilder::new(&shell_path);
+
+        // Add environment variables
+        for (key, value) in &env {
+            cmd.env(key, value);
+        }
+
+        // Start in the requested working directory, if any
+        if let Some(cwd) = cwd {
+            if !Path::new(&cwd).is_dir() {
+                anyhow::bail!("Working directory does not exist: {}", cwd);
+            }
+            cmd.cwd(cwd);
+        }
+
+        // Spawn the shell process
+        let child = pty_pair
+            .slave
+            .spawn_command(cmd)
+            .with_context(|| format!("Failed to spawn shell: {}", shell_path))?;
+
+        // Get the process ID
+        let pid = child.process_id();
+        tracing::info!("Spawned shell process with PID: {:?}", pid);
+
+        // Get reader/writer handles for the master side
+        let reader = pty_pair
+            .master
+            .try_clone_reader()
+            .with_context(|| "Failed to clone PTY reader")?;
+
+        let writer = pty_pair
+            .master
+            .take_writer()
+            .with_context(|| "Failed to take PTY writer")?;
+
+        Ok(PtySession {
+            session_id,
+            pid,
+            process: SessionProcess::Pty {
+                pty_pair,
+                child,
+                reader,
+            },
+            writer: Some(writer),
+        })
+    }
+
+    /// Spawn the shell on plain pipes, in its own process group so closing
+    /// the session also ends what it started
+    fn spawn_pipes(self) -> Result<PtySession> {
+        let SessionSpec {
+            session_id,
+            shell,
+            env,
+            cwd,
+            ..
+        } = self;
+
+        tracing::info!("Creating pipe session {} (no PTY)", session_id);
+
+        let shell_path = validate_shell_path(&shell)
+            .with_context(|| format!("Invalid shell requested: {}", shell))?;
+
+        let mut cmd = Command::new(&shell_path);
+        cmd.envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(cwd) = cwd {
+            if !Path::new(&cwd).is_dir() {
+                anyhow::bail!("Working directory does not exist: {}", cwd);
+            }
+            cmd.current_dir(cwd);
+        }
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to spawn shell: {}", shell_path))?;
+        let pid = child.id();
+        tracing::info!("Spawned shell process with PID: {}", pid);
+
+        let writer = child
+            .stdin
+            .take()
+            .map(|stdin| Box::new(stdin) as Box<dyn Write + Send>);
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        Ok(PtySession {
+            session_id,
+            pid: Some(pid),
+            process: SessionProcess::Pipes {
+                child,
+                stdout,
+                stderr,
+            },
+            writer,
+        })
+    }
+}
+
 impl PtySession {
-    /// Get the writer for this session
-    pub fn writer(&mut self) -> &mut Box<dyn Write + Send> {
-        &mut self.writer
+    /// Get the writer for this session (None once its stdin is closed)
+    pub fn writer(&mut self) -> Option<&mut Box<dyn Write + Send>> {
+        self.writer.as_mut()
+    }
+
+    /// Whether the shell runs in a PTY rather than on pipes
+    pub fn has_pty(&self) -> bool {
+        matches!(self.process, SessionProcess::Pty { .. })
+    }
+
+    /// Write data to the PTY
+    ///
+    /// Empty data closes a pipe session's stdin, so the shell sees end of
+    /// input; for a PTY session it does nothing.
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        if data.is_empty() {
+            if !self.has_pty() && self.writer.take().is_some() {
+                tracing::debug!("Closed stdin of session {}", self.session_id);
+            }
+            return Ok(());
+        }
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Stdin of session {} is closed", self.session_id))?;
+
+        writer
+            .write_all(data)
+            .with_context(|| "Failed to write to PTY")?;
+
+        writer.flush().with_context(|| "Failed to flush PTY")?;
+
+        Ok(())
+    }
+
+    /// Resize the PTY
+    fn resize(&mut self, size: TerminalSize) -> Result<()> {
+        let SessionProcess::Pty { pty_pair, .. } = &self.process else {
+            // Nothing to resize without a terminal
+            return Ok(());
+        };
+        tracing::debug!(
+            "Resizing session {} to {}x{}",
+            self.session_id,
+            size.cols,
+            size.rows
+        );
+
+        pty_pair
+            .master
+            .resize(pty_size(size))
+            .with_context(|| "Failed to resize PTY")?;
+
+        Ok(())
+    }
+
+    /// Kill the shell and wait for it, returning its exit code
+    pub fn kill(self) -> Option<i32> {
+        match self.process {
+            SessionProcess::Pty { mut child, .. } => {
+                // Try to kill the child process
+                let _ = child.kill();
+
+                // Wait for it to exit and get the exit code
+                match child.wait() {
+                    Ok(status) => Some(status.exit_code() as i32),
+                    Err(_) => None,
+                }
+            }
+            SessionProcess::Pipes { mut child, .. } => {
+                kill_process_group(&mut child);
+                child.wait().ok().map(pipe_exit_code)
+            }
+        }
+    }
+}
+
+/// Kill a pipe session's process group (just the shell where there are no
+/// process groups)
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // The shell leads its own group (see `SessionSpec::spawn_pipes`)
+        // SAFETY: kill() has no memory safety requirements
+        if unsafe { libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL) } == 0 {
+            return;
+        }
+    }
+    let _ = child.kill();
+}
+
+/// Exit code of a pipe session's shell; killed by a signal counts as 128 plus
+/// the signal number, as shells report it
+fn pipe_exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+fn pty_size(size: TerminalSize) -> PtySize {
+    PtySize {
+        rows: size.rows,
+        cols: size.cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    fn size(cols: u16, rows: u16) -> TerminalSize {
+        TerminalSize { cols, rows }
+    }
+
+    fn pty_pair(session: &PtySession) -> &PtyPair {
+        match &session.process {
+            SessionProcess::Pty { pty_pair, .. } => pty_pair,
+            SessionProcess::Pipes { .. } => panic!("session has no PTY"),
+        }
+    }
+
+    /// Last value of `name` in a spec's environment (the one the shell sees)
+    fn env_value<'a>(spec: &'a SessionSpec, name: &str) -> Option<&'a str> {
+        spec.env
+            .iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn test_session_term_defaults_and_client_override() {
+        let mut manager = PtyManager::new().with_default_term("screen-256color");
+        let spec = manager.begin_session(SessionId::new(1), None, vec![], None, size(80, 24), true);
+        assert_eq!(env_value(&spec, "TERM"), Some("screen-256color"));
+        assert_eq!(env_value(&spec, "COLORTERM"), None);
+
+        let spec = manager.begin_session(
+            SessionId::new(2),
+            None,
+            terminal_env(Some("xterm-kitty"), true),
+            None,
+            size(80, 24),
+            true,
+        );
+        assert_eq!(env_value(&spec, "TERM"), Some("xterm-kitty"));
+        assert_eq!(env_value(&spec, "COLORTERM"), Some("truecolor"));
+    }
+
+    #[test]
+    fn test_terminal_env_drops_invalid_term() {
+        assert!(terminal_env(Some("xterm\nevil"), false).is_empty());
+        assert!(terminal_env(None, false).is_empty());
+        assert_eq!(
+            terminal_env(Some("xterm-256color"), false),
+            vec![("TERM".to_string(), "xterm-256color".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_pending_ops_keep_input_order_and_latest_resize() {
+        let mut manager = PtyManager::new();
+        let session_id = SessionId::new(1);
+        manager.begin_session(
+            session_id,
+            Some("/bin/sh".to_string()),
+            vec![],
+            None,
+            size(80, 24),
+            true,
+        );
+        assert!(manager.is_pending(session_id));
+
+        manager.write(session_id, b"one").unwrap();
+        manager.resize(session_id, size(100, 30)).unwrap();
+        manager.write(session_id, b"two").unwrap();
+        manager.resize(session_id, size(120, 40)).unwrap();
+
+        let pending = &manager.pending[&session_id];
+        assert_eq!(pending.input, vec![b"one".to_vec(), b"two".to_vec()]);
+        assert_eq!(pending.resize, Some(size(120, 40)));
+    }
+
+    #[test]
+    fn test_pending_input_is_capped() {
+        let mut pending = PendingOps::default();
+        let session_id = SessionId::new(1);
+        pending.push_input(session_id, &vec![0; MAX_PENDING_INPUT]);
+        pending.push_input(session_id, b"x");
+        assert_eq!(pending.input.len(), 1);
+        assert_eq!(pending.input_bytes, MAX_PENDING_INPUT);
+    }
+
+    #[test]
+    fn test_complete_session_applies_pending_ops() {
+        let mut manager = PtyManager::new();
+        let session_id = SessionId::new(1);
+        let spec = manager.begin_session(
+            session_id,
+            Some("/bin/sh".to_string()),
+            vec![],
+            None,
+            size(80, 24),
+            true,
+        );
+
+        manager.resize(session_id, size(100, 30)).unwrap();
+        manager.resize(session_id, size(132, 43)).unwrap();
+        // Split mid-word so the output only comes out right in order. The
+        // shell works out the 42 itself, so the terminal's echo of the
+        // command line can't be mistaken for its output.
+        manager.write(session_id, b"echo got:$((6*").unwrap();
+        manager.write(session_id, b"7))-fir").unwrap();
+        manager.write(session_id, b"st\n").unwrap();
+
+        let session = spec.spawn().unwrap();
+        let mut reader = pty_pair(&session).master.try_clone_reader().unwrap();
+        assert!(manager.complete_session(session).is_some());
+        assert!(!manager.is_pending(session_id));
+
+        let pty_size = pty_pair(manager.get(session_id).unwrap())
+            .master
+            .get_size()
+            .unwrap();
+        assert_eq!((pty_size.cols, pty_size.rows), (132, 43));
+
+        // The shell ran the input in order
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            let mut buf = [0u8; 1024];
+            while let Ok(n) = reader.read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..n]);
+                if String::from_utf8_lossy(&output).contains("got:42-first") {
+                    let _ = tx.send(());
+                    break;
+                }
+            }
+        });
+        let echoed = rx.recv_timeout(Duration::from_secs(10));
+        manager.close(session_id);
+        assert!(echoed.is_ok(), "shell did not run the pending input");
+    }
+
+    #[test]
+    fn test_session_closed_while_pending_is_killed() {
+        let mut manager = PtyManager::new();
+        let session_id = SessionId::new(1);
+        let spec = manager.begin_session(
+            session_id,
+            Some("/bin/sh".to_string()),
+            vec![],
+            None,
+            size(80, 24),
+            true,
+        );
+        manager.write(session_id, b"ignored\n").unwrap();
+
+        assert_eq!(manager.close(session_id), None);
+        assert!(!manager.is_pending(session_id));
+
+        let session = spec.spawn().unwrap();
+        assert_eq!(manager.complete_session(session), None);
+        assert!(manager.is_empty());
+    }
+
+    #[test]
+    fn test_failed_session_forgets_pending_input() {
+        let mut manager = PtyManager::new();
+        let session_id = SessionId::new(1);
+        let spec = manager.begin_session(
+            session_id,
+            Some("/not/a/shell".to_string()),
+            vec![],
+            None,
+            size(80, 24),
+            true,
+        );
+        manager.write(session_id, b"lost").unwrap();
+
+        assert!(spec.spawn().is_err());
+        manager.fail_session(session_id);
+        assert!(!manager.is_pending(session_id));
+        assert!(manager.write(session_id, b"late").is_err());
+    }
+
+    #[test]
+    fn test_pipe_session_keeps_streams_apart() {
+        let mut manager = PtyManager::new();
+        let session_id = SessionId::new(1);
+        manager
+            .create_session(
+                session_id,
+                Some("/bin/sh".to_string()),
+                vec![],
+                None,
+                size(80, 24),
+                false,
+            )
+            .unwrap();
+        assert!(!manager.get(session_id).unwrap().has_pty());
+
+        manager
+            .write(session_id, b"echo out; echo err >&2; exit 3\n")
+            .unwrap();
+        // Empty input closes stdin
+        manager.write(session_id, b"").unwrap();
+        assert!(manager.write(session_id, b"late").is_err());
+        // No terminal to resize
+        manager.resize(session_id, size(100, 30)).unwrap();
+
+        let mut output = HashMap::new();
+        for mut output_reader in manager.take_readers(session_id).unwrap() {
+            assert!(output_reader.fd.is_some());
+            let mut data = String::new();
+            output_reader.reader.read_to_string(&mut data).unwrap();
+            output.insert(output_reader.stream, data);
+        }
+        assert_eq!(output[&OutputStream::Stdout], "out\n");
+        assert_eq!(output[&OutputStream::Stderr], "err\n");
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+        let code = loop {
+            if let Some(code) = manager.try_wait(session_id).unwrap() {
+                break code;
+            }
+            assert!(std::time::Instant::now() < deadline, "shell did not exit");
+            std::thread::sleep(Duration::from_millis(10));
+        };
+        assert_eq!(code, 3);
     }
 }
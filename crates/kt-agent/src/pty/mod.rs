@@ -1,5 +1,12 @@
 //! PTY management
 
+pub mod journal;
 mod manager;
+pub mod watchdog;
 
-pub use manager::PtyManager;
+pub use journal::SessionJournal;
+pub use manager::{
+    terminal_env, OutputReader, PtyManager, PtySession, SessionSpec, DEFAULT_TERM,
+    MAX_PENDING_INPUT,
+};
+pub use watchdog::{ReaderWatchdog, WatchedReader};
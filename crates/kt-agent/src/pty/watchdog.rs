@@ -0,0 +1,349 @@
+//! Watchdog for hung output readers
+//!
+//! Session output is read on blocking threads, and a read can block forever
+//! (a dead network filesystem behind a pipe, say), out of reach of the
+//! cancellation token checked between reads. Each reader records when it
+//! last made progress; once its session is cancelled, the watchdog gives it
+//! [`READER_EXIT_DEADLINE`] to exit and then cuts it off: the descriptor is
+//! pointed at `/dev/null` so any further read ends at once, and the reading
+//! thread is interrupted so the blocked read returns. Reads the kernel
+//! won't interrupt (uninterruptible filesystem I/O) still return eventually
+//! on their own, and then end the reader.
+
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use kt_core::ipc::OutputStream;
+use kt_protocol::SessionId;
+
+/// How long a cancelled reader has to exit before it is cut off
+pub const READER_EXIT_DEADLINE: Duration = Duration::from_secs(5);
+
+/// How often the watchdog looks at the readers
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Tracks output readers and cuts off cancelled ones that fail to exit
+#[derive(Clone)]
+pub struct ReaderWatchdog {
+    readers: Arc<Mutex<HashMap<u64, WatchedEntry>>>,
+    next_id: Arc<std::sync::atomic::AtomicU64>,
+    deadline: Duration,
+}
+
+/// What the watchdog knows about one reader
+struct WatchedEntry {
+    session_id: SessionId,
+    stream: OutputStream,
+    /// Descriptor the reader reads from (unix)
+    fd: Option<i32>,
+    /// Thread blocked in the reader, once it has started reading (unix)
+    #[cfg(unix)]
+    thread: Option<libc::pthread_t>,
+    /// Set by each successful read
+    last_progress: Instant,
+    cancel_token: CancellationToken,
+    /// When the watchdog first saw the session cancelled
+    cancelled_at: Option<Instant>,
+}
+
+impl ReaderWatchdog {
+    /// Create a watchdog with the default exit deadline
+    pub fn new() -> Self {
+        Self::with_deadline(READER_EXIT_DEADLINE)
+    }
+
+    /// Create a watchdog that cuts off cancelled readers after `deadline`
+    pub fn with_deadline(deadline: Duration) -> Self {
+        Self {
+            readers: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(std::sync::atomic::AtomicU64::new(0)),
+            deadline,
+        }
+    }
+
+    /// Watch a reader that stops once `cancel_token` is cancelled
+    ///
+    /// `fd` is the descriptor `reader` reads from; without one a hung reader
+    /// is only reported.
+    pub fn watch(
+        &self,
+        session_id: SessionId,
+        stream: OutputStream,
+        reader: Box<dyn Read + Send>,
+        fd: Option<i32>,
+        cancel_token: CancellationToken,
+    ) -> WatchedReader {
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        self.lock().insert(
+            id,
+            WatchedEntry {
+                session_id,
+                stream,
+                fd,
+                #[cfg(unix)]
+                thread: None,
+                last_progress: Instant::now(),
+                cancel_token,
+                cancelled_at: None,
+            },
+        );
+        WatchedReader {
+            id,
+            readers: Arc::clone(&self.readers),
+            reader,
+        }
+    }
+
+    /// Number of readers still running
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Check whether any readers are running
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Check the readers every second, for as long as the agent runs
+    pub async fn run(self) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+
+    /// Cut off cancelled readers that are past the deadline, returning how
+    /// many were
+    pub fn check(&self) -> usize {
+        let now = Instant::now();
+        let mut readers = self.lock();
+        let mut hung = Vec::new();
+        for (&id, entry) in readers.iter_mut() {
+            if !entry.cancel_token.is_cancelled() {
+                continue;
+            }
+            let cancelled_at = *entry.cancelled_at.get_or_insert(now);
+            if now.duration_since(cancelled_at) >= self.deadline {
+                hung.push(id);
+            }
+        }
+
+        for id in &hung {
+            // Cut off under the lock: a reader leaves the map before its
+            // descriptor is closed, so the descriptor can't have been reused
+            let Some(entry) = readers.remove(id) else {
+                continue;
+            };
+            tracing::warn!(
+                "{:?} reader for session {} still running {:?} after close (no output for {:?}), cutting it off",
+                entry.stream,
+                entry.session_id,
+                self.deadline,
+                now.duration_since(entry.last_progress)
+            );
+            #[cfg(unix)]
+            cut_off(&entry);
+        }
+        hung.len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, WatchedEntry>> {
+        self.readers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for ReaderWatchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A reader registered with a [`ReaderWatchdog`]
+///
+/// Reads record progress, and dropping it tells the watchdog the reader has
+/// exited.
+pub struct WatchedReader {
+    id: u64,
+    readers: Arc<Mutex<HashMap<u64, WatchedEntry>>>,
+    reader: Box<dyn Read + Send>,
+}
+
+impl Read for WatchedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        #[cfg(unix)]
+        if let Some(entry) = self.lock().get_mut(&self.id) {
+            // SAFETY: pthread_self has no preconditions
+            entry.thread = Some(unsafe { libc::pthread_self() });
+        }
+        let n = self.reader.read(buf)?;
+        if n > 0 {
+            if let Some(entry) = self.lock().get_mut(&self.id) {
+                entry.last_progress = Instant::now();
+            }
+        }
+        Ok(n)
+    }
+}
+
+impl WatchedReader {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<u64, WatchedEntry>> {
+        self.readers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for WatchedReader {
+    fn drop(&mut self) {
+        // Runs before `reader` (and its descriptor) is dropped
+        self.lock().remove(&self.id);
+    }
+}
+
+/// Point a hung reader's descriptor at `/dev/null` and interrupt its thread
+#[cfg(unix)]
+fn cut_off(entry: &WatchedEntry) {
+    use std::os::fd::AsRawFd;
+
+    if let Some(fd) = entry.fd {
+        match std::fs::File::open("/dev/null") {
+            // SAFETY: `fd` is still open, as its reader hasn't been dropped;
+            // dup2 swaps what it refers to without freeing the number
+            Ok(null) => {
+                if unsafe { libc::dup2(null.as_raw_fd(), fd) } < 0 {
+                    tracing::warn!(
+                        "Failed to close descriptor of session {}: {}",
+                        entry.session_id,
+                        io::Error::last_os_error()
+                    );
+                }
+            }
+            Err(e) => tracing::warn!("Failed to open /dev/null: {}", e),
+        }
+    }
+    if let Some(thread) = entry.thread {
+        install_wake_handler();
+        // SAFETY: the thread is still in the reader, as it hasn't been
+        // dropped; the handler does nothing, so the signal only makes the
+        // blocked read fail with EINTR
+        unsafe {
+            libc::pthread_kill(thread, WAKE_SIGNAL);
+        }
+    }
+}
+
+/// Signal sent to a hung reader's thread to interrupt its read
+#[cfg(unix)]
+const WAKE_SIGNAL: libc::c_int = libc::SIGURG;
+
+/// Install a do-nothing handler for [`WAKE_SIGNAL`], without `SA_RESTART` so
+/// interrupted reads return instead of resuming
+#[cfg(unix)]
+fn install_wake_handler() {
+    extern "C" fn wake(_: libc::c_int) {}
+
+    static INSTALL: std::sync::Once = std::sync::Once::new();
+    INSTALL.call_once(|| {
+        // SAFETY: the action is fully initialized and its handler is
+        // async-signal-safe
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = wake as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(WAKE_SIGNAL, &action, std::ptr::null_mut()) != 0 {
+                tracing::warn!(
+                    "Failed to install reader wake handler: {}",
+                    io::Error::last_os_error()
+                );
+            }
+        }
+    });
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::fd::AsRawFd;
+
+    #[test]
+    fn test_hung_fifo_reader_is_cut_off_after_close() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hang");
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) }, 0);
+        // Opened for writing too, so reads block rather than end: the FIFO
+        // never produces data
+        let fifo = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let fd = fifo.as_raw_fd();
+
+        let watchdog = ReaderWatchdog::with_deadline(Duration::from_millis(50));
+        let cancel_token = CancellationToken::new();
+        let mut reader = watchdog.watch(
+            SessionId::new(1),
+            OutputStream::Stdout,
+            Box::new(fifo),
+            Some(fd),
+            cancel_token.clone(),
+        );
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        let thread = std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            let result = reader.read(&mut buf).map_err(|e| e.kind());
+            drop(reader);
+            let _ = done_tx.send(result);
+        });
+
+        // Not cut off before the session is closed, however long it waits
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(watchdog.check(), 0);
+        assert_eq!(watchdog.len(), 1);
+
+        cancel_token.cancel();
+        assert_eq!(watchdog.check(), 0, "cut off before the deadline");
+        assert!(done_rx.try_recv().is_err(), "the read should be hung");
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(watchdog.check(), 1);
+
+        let result = done_rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("reader still blocked after being cut off");
+        assert!(
+            matches!(result, Err(io::ErrorKind::Interrupted) | Ok(0)),
+            "{:?}",
+            result
+        );
+        thread.join().unwrap();
+        assert!(watchdog.is_empty());
+        assert_eq!(watchdog.check(), 0);
+    }
+
+    #[test]
+    fn test_reader_that_exits_leaves_watchdog() {
+        let watchdog = ReaderWatchdog::new();
+        let cancel_token = CancellationToken::new();
+        let mut reader = watchdog.watch(
+            SessionId::new(2),
+            OutputStream::Stderr,
+            Box::new(&b"done"[..]),
+            None,
+            cancel_token.clone(),
+        );
+        let mut data = String::new();
+        reader.read_to_string(&mut data).unwrap();
+        assert_eq!(data, "done");
+
+        cancel_token.cancel();
+        drop(reader);
+        assert!(watchdog.is_empty());
+    }
+}
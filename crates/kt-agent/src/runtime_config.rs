@@ -0,0 +1,89 @@
+//! Settings the orchestrator changes at runtime
+//!
+//! The orchestrator sends `AgentConfigUpdate`s on the control session (see
+//! [`kt_protocol::agent_config`]); [`RuntimeConfig::apply`] applies them and
+//! says why not when it can't, for the ack. Keys this agent doesn't know are
+//! refused, never ignored. Settings last for one connection: each starts
+//! over with a fresh [`RuntimeConfig`], from the agent config's defaults.
+
+use std::time::Duration;
+
+use kt_protocol::{parse_metrics_interval, AgentConfigKey};
+use tokio::sync::watch;
+
+use crate::metrics::run_metrics_reporter;
+use crate::version::AGENT_VERSION;
+
+/// Runtime settings of the current connection
+pub struct RuntimeConfig {
+    /// Interval between host metrics reports (None = off)
+    metrics_interval: watch::Sender<Option<Duration>>,
+}
+
+impl RuntimeConfig {
+    /// Settings for a new connection, starting the tasks following them
+    ///
+    /// `metrics_interval` is the configured one (`AgentConfig::metrics_interval`).
+    /// The tasks stop when this is dropped.
+    pub fn start(metrics_interval: Option<Duration>) -> Self {
+        let (metrics_interval, interval_rx) = watch::channel(metrics_interval);
+        tokio::spawn(run_metrics_reporter(interval_rx));
+        Self { metrics_interval }
+    }
+
+    /// Interval between host metrics reports (None = off)
+    pub fn metrics_interval(&self) -> Option<Duration> {
+        *self.metrics_interval.borrow()
+    }
+
+    /// Apply `value` to setting `key`
+    ///
+    /// Returns why not if it isn't applied: the key is unknown or the value
+    /// invalid.
+    pub fn apply(&self, key: &str, value: &str) -> Result<(), String> {
+        let Some(known) = AgentConfigKey::from_name(key) else {
+            return Err(format!(
+                "Unknown setting '{}' (agent version {})",
+                key, AGENT_VERSION
+            ));
+        };
+        match known {
+            AgentConfigKey::MetricsInterval => {
+                let interval = parse_metrics_interval(value)?;
+                self.metrics_interval.send_replace(interval);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_apply_metrics_interval() {
+        let config = RuntimeConfig::start(Some(Duration::from_secs(60)));
+        assert_eq!(config.metrics_interval(), Some(Duration::from_secs(60)));
+
+        config.apply("metrics_interval", "30").unwrap();
+        assert_eq!(config.metrics_interval(), Some(Duration::from_secs(30)));
+        config.apply("metrics_interval", "0").unwrap();
+        assert_eq!(config.metrics_interval(), None);
+
+        // An invalid value leaves the setting as it was
+        assert!(config.apply("metrics_interval", "often").is_err());
+        assert_eq!(config.metrics_interval(), None);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_setting_refused() {
+        let config = RuntimeConfig::start(None);
+        let reason = config.apply("output_rate_cap", "1024").unwrap_err();
+        assert!(
+            reason.contains("Unknown setting 'output_rate_cap'"),
+            "{}",
+            reason
+        );
+    }
+}
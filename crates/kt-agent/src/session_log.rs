@@ -0,0 +1,307 @@
+//! Per-session log levels
+//!
+//! A client can ask for more (or less) detail about one session with
+//! `connect --log-level`, without touching the agent's own level or any
+//! other session. The agent logs each session's lifecycle (create, resize,
+//! close, exit) inside a `session` span carrying the requested level, and
+//! [`SessionLevelFilter`] decides events inside such a span by that level
+//! alone. Everything else goes through the agent's normal filter.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::subscriber::Interest;
+use tracing::{span, Event, Metadata, Span, Subscriber};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::registry::LookupSpan;
+
+use kt_protocol::SessionId;
+
+/// Name of the span session lifecycle events are logged in
+pub const SESSION_SPAN: &str = "session";
+
+/// Level requested for a session, stored on its span
+struct SessionLevel(LevelFilter);
+
+/// Wraps the agent's log filter to honor per-session levels
+///
+/// While no session has a level of its own this is the inner filter. Once
+/// one does, events the inner filter would drop are looked at again, since
+/// they may belong to that session.
+pub struct SessionLevelFilter<F> {
+    inner: F,
+    /// Open session spans that carry a level
+    overrides: AtomicUsize,
+}
+
+impl<F> SessionLevelFilter<F> {
+    /// Wrap `inner`, the agent-wide filter
+    pub fn new(inner: F) -> Self {
+        Self {
+            inner,
+            overrides: AtomicUsize::new(0),
+        }
+    }
+
+    fn has_overrides(&self) -> bool {
+        self.overrides.load(Ordering::Relaxed) > 0
+    }
+}
+
+impl<F: fmt::Debug> fmt::Debug for SessionLevelFilter<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionLevelFilter")
+            .field("inner", &self.inner)
+            .field("overrides", &self.overrides.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+fn is_session_span(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && metadata.name() == SESSION_SPAN
+}
+
+impl<S, F> Filter<S> for SessionLevelFilter<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    F: Filter<S>,
+{
+    fn enabled(&self, metadata: &Metadata<'_>, cx: &Context<'_, S>) -> bool {
+        // Session spans must exist for their level to be found
+        is_session_span(metadata)
+            || self.inner.enabled(metadata, cx)
+            || (metadata.is_event() && self.has_overrides())
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if is_session_span(metadata) {
+            return Interest::always();
+        }
+        let interest = self.inner.callsite_enabled(metadata);
+        // A session may ask for any level at any time, so every event has
+        // to be looked at
+        if metadata.is_event() {
+            Interest::sometimes()
+        } else {
+            interest
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, cx: &Context<'_, S>) -> bool {
+        if self.has_overrides() {
+            if let Some(level) = session_level(event, cx) {
+                return level >= *event.metadata().level();
+            }
+            if !self.inner.enabled(event.metadata(), cx) {
+                return false;
+            }
+        }
+        self.inner.event_enabled(event, cx)
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        // Any level may be needed by some session
+        None
+    }
+
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, cx: Context<'_, S>) {
+        self.inner.on_new_span(attrs, id, cx.clone());
+        if !is_session_span(attrs.metadata()) {
+            return;
+        }
+        let mut visitor = LevelVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(level), Some(span)) = (visitor.0, cx.span(id)) {
+            span.extensions_mut().insert(SessionLevel(level));
+            self.overrides.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, cx: Context<'_, S>) {
+        self.inner.on_record(id, values, cx);
+    }
+
+    fn on_enter(&self, id: &span::Id, cx: Context<'_, S>) {
+        self.inner.on_enter(id, cx);
+    }
+
+    fn on_exit(&self, id: &span::Id, cx: Context<'_, S>) {
+        self.inner.on_exit(id, cx);
+    }
+
+    fn on_close(&self, id: span::Id, cx: Context<'_, S>) {
+        if let Some(span) = cx.span(&id) {
+            if span.extensions().get::<SessionLevel>().is_some() {
+                self.overrides.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        self.inner.on_close(id, cx);
+    }
+}
+
+/// Level of the innermost session span around `event` that has one
+fn session_level<S>(event: &Event<'_>, cx: &Context<'_, S>) -> Option<LevelFilter>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    cx.event_scope(event)?
+        .find_map(|span| span.extensions().get::<SessionLevel>().map(|l| l.0))
+}
+
+/// Reads the `log_level` field of a session span
+struct LevelVisitor(Option<LevelFilter>);
+
+impl Visit for LevelVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "log_level" {
+            self.0 = value.parse().ok();
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+/// Lifecycle spans of the agent's sessions
+#[derive(Debug, Default)]
+pub struct SessionSpans {
+    spans: HashMap<SessionId, Span>,
+}
+
+impl SessionSpans {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the span for a new session, with the level its client asked
+    /// for (an unknown level is ignored with a warning)
+    pub fn open(&mut self, session_id: SessionId, log_level: Option<&str>) -> Span {
+        let log_level = log_level.and_then(|level| match level.parse::<LevelFilter>() {
+            Ok(_) => Some(level),
+            Err(_) => {
+                tracing::warn!(
+                    "Ignoring unknown log level {:?} for session {}",
+                    level,
+                    session_id
+                );
+                None
+            }
+        });
+        let span = tracing::error_span!(SESSION_SPAN, id = %session_id, log_level);
+        self.spans.insert(session_id, span.clone());
+        span
+    }
+
+    /// The span of a session, or a plain one if it has none
+    pub fn get(&self, session_id: SessionId) -> Span {
+        match self.spans.get(&session_id) {
+            Some(span) => span.clone(),
+            None => tracing::error_span!(SESSION_SPAN, id = %session_id),
+        }
+    }
+
+    /// Forget a session's span, returning it for the final log lines
+    pub fn close(&mut self, session_id: SessionId) -> Span {
+        match self.spans.remove(&session_id) {
+            Some(span) => span,
+            None => tracing::error_span!(SESSION_SPAN, id = %session_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::Layer;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run `f` with an agent-style subscriber at `info`, returning the logs
+    fn capture(f: impl FnOnce()) -> String {
+        let out = Captured::default();
+        let writer = out.clone();
+        let subscriber = tracing_subscriber::registry().with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .with_filter(SessionLevelFilter::new(LevelFilter::INFO)),
+        );
+        tracing::subscriber::with_default(subscriber, f);
+        let logs = out.0.lock().unwrap().clone();
+        String::from_utf8(logs).unwrap()
+    }
+
+    #[test]
+    fn test_session_level_raises_verbosity_for_that_session_only() {
+        let logs = capture(|| {
+            let mut spans = SessionSpans::new();
+            let verbose = spans.open(SessionId::new(1), Some("debug"));
+            let plain = spans.open(SessionId::new(2), None);
+
+            tracing::debug!(parent: &verbose, "verbose-debug");
+            tracing::trace!(parent: &verbose, "verbose-trace");
+            tracing::debug!(parent: &plain, "plain-debug");
+            tracing::info!(parent: &plain, "plain-info");
+            tracing::debug!("agent-debug");
+        });
+
+        assert!(logs.contains("verbose-debug"), "{}", logs);
+        assert!(!logs.contains("verbose-trace"), "{}", logs);
+        assert!(!logs.contains("plain-debug"), "{}", logs);
+        assert!(logs.contains("plain-info"), "{}", logs);
+        assert!(!logs.contains("agent-debug"), "{}", logs);
+    }
+
+    #[test]
+    fn test_session_level_can_lower_verbosity() {
+        let logs = capture(|| {
+            let mut spans = SessionSpans::new();
+            let quiet = spans.open(SessionId::new(1), Some("error"));
+            tracing::info!(parent: &quiet, "quiet-info");
+            tracing::error!(parent: &quiet, "quiet-error");
+            tracing::info!("agent-info");
+        });
+
+        assert!(!logs.contains("quiet-info"), "{}", logs);
+        assert!(logs.contains("quiet-error"), "{}", logs);
+        assert!(logs.contains("agent-info"), "{}", logs);
+    }
+
+    #[test]
+    fn test_closed_and_unknown_levels_fall_back_to_agent_level() {
+        let logs = capture(|| {
+            let mut spans = SessionSpans::new();
+            let verbose = spans.open(SessionId::new(1), Some("debug"));
+            drop(verbose);
+            let closed = spans.close(SessionId::new(1));
+            drop(closed);
+
+            let bogus = spans.open(SessionId::new(2), Some("loud"));
+            tracing::debug!(parent: &bogus, "bogus-debug");
+            tracing::debug!("agent-debug");
+        });
+
+        assert!(!logs.contains("bogus-debug"), "{}", logs);
+        assert!(!logs.contains("agent-debug"), "{}", logs);
+        assert!(logs.contains("Ignoring unknown log level"), "{}", logs);
+    }
+}
@@ -2,7 +2,9 @@
 //!
 //! Establishes and maintains the reverse tunnel connection to the orchestrator.
 
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
@@ -11,14 +13,32 @@ use russh::client::{self, Config, Handle, Msg};
 use russh::{Channel, ChannelId, Disconnect};
 use russh_keys::key::{KeyPair, PublicKey};
 use thiserror::Error;
+use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::time::timeout_at;
 use tokio_util::codec::{Decoder, Encoder};
 
 use kt_core::config::AgentConfig;
-use kt_protocol::{Frame, FrameCodec, Message, SessionId, TerminalSize};
+use kt_protocol::{
+    AgentCapabilities, Capability, Frame, FrameCodec, Message, SessionId, TerminalSize,
+};
 
+use crate::identity::AgentIdentity;
+
+use super::failover::FailoverAddresses;
+use super::progress::{ConnectProgress, ConnectStage, Progress, StageTimer};
 use super::reconnect::ExponentialBackoff;
 
+/// Optional protocol features this agent build supports
+fn supported_capabilities() -> AgentCapabilities {
+    AgentCapabilities::empty()
+        .with(Capability::Cwd)
+        .with(Capability::Pipes)
+        .with(Capability::SessionLogLevel)
+        .with(Capability::Term)
+        .with(Capability::AgentConfig)
+}
+
 /// Channel capacity for events from the orchestrator.
 ///
 /// This buffer holds events (session create, data, resize, etc.) between
@@ -54,16 +74,38 @@ pub enum ConnectionError {
     HostKeyRejected { message: String },
 
     /// Other connection error
-    #[error("{0}")]
+    #[error("{0:#}")]
     Other(#[from] anyhow::Error),
 }
 
+impl ConnectionError {
+    /// What went wrong and what caused it, outermost first
+    pub fn chain(&self) -> Vec<String> {
+        match self {
+            Self::KeyNotFound { path, source } => {
+                let mut chain = vec![format!("Private key not found at {}", path)];
+                chain.extend(source.chain().map(|e| e.to_string()));
+                chain
+            }
+            Self::Other(e) => e.chain().map(|e| e.to_string()).collect(),
+            _ => vec![self.to_string()],
+        }
+    }
+}
+
 /// Establishes and maintains the outbound SSH tunnel to the orchestrator
 pub struct TunnelConnector {
     /// Agent configuration
     config: AgentConfig,
+    /// What the agent registers as
+    identity: AgentIdentity,
     /// Private key for authentication
     key: Arc<KeyPair>,
+    /// Told about each connection attempt's progress
+    progress: Progress,
+    /// Connections established so far; each registration reports it as the
+    /// reconnect count, so the orchestrator can flag unstable machines
+    connections: AtomicU64,
 }
 
 impl TunnelConnector {
@@ -85,33 +127,76 @@ impl TunnelConnector {
             }
         })?;
 
+        let identity = AgentIdentity::load(&config).map_err(|e| {
+            ConnectionError::Other(anyhow::anyhow!("Failed to load machine identity: {}", e))
+        })?;
+
         Ok(Self {
             config,
+            identity,
             key: Arc::new(key),
+            progress: Progress::default(),
+            connections: AtomicU64::new(0),
         })
     }
 
+    /// Report each connection attempt's progress to `callback`
+    pub fn with_progress(
+        mut self,
+        callback: impl Fn(&ConnectProgress<'_>) + Send + Sync + 'static,
+    ) -> Self {
+        self.progress = Progress::new(Arc::new(callback));
+        self
+    }
+
     /// Get the agent configuration
     pub fn config(&self) -> &AgentConfig {
         &self.config
     }
 
+    /// Get the identity the agent registers with
+    pub fn identity(&self) -> &AgentIdentity {
+        &self.identity
+    }
+
     /// Connect to the orchestrator with automatic retry
     ///
     /// Returns `ConnectionError::AuthRejected` if authentication fails,
     /// or `ConnectionError::HostKeyRejected` if host key verification fails.
     /// These errors indicate that Tailscale verification may have failed.
+    ///
+    /// `backoff` keeps counting across calls until a connection succeeds,
+    /// which resets it.
+    ///
+    /// Each call starts with the primary orchestrator and moves through the
+    /// fallbacks after repeated failures (see [`FailoverAddresses`]).
     pub async fn connect_with_retry(
         &self,
-        mut backoff: ExponentialBackoff,
+        backoff: &mut ExponentialBackoff,
     ) -> Result<ActiveTunnel, ConnectionError> {
+        let mut addresses = FailoverAddresses::from_config(&self.config);
         loop {
-            match self.try_connect().await {
+            match self.try_connect(addresses.current()).await {
                 Ok(tunnel) => {
-                    tracing::info!(
-                        "Connected to orchestrator at {}",
-                        self.config.orchestrator_address
-                    );
+                    let state = backoff.state();
+                    let role = if addresses.is_primary() {
+                        ""
+                    } else {
+                        "fallback "
+                    };
+                    if state.attempt > 0 {
+                        tracing::info!(
+                            "Connected to {}orchestrator at {} after {} attempts ({}s)",
+                            role,
+                            tunnel.address(),
+                            state.attempt + 1,
+                            state.elapsed.as_secs()
+                        );
+                    } else {
+                        tracing::info!("Connected to {}orchestrator at {}", role, tunnel.address());
+                    }
+                    backoff.reset();
+                    self.connections.fetch_add(1, Ordering::Relaxed);
                     return Ok(tunnel);
                 }
                 Err(ConnectionError::AuthRejected) => {
@@ -126,67 +211,161 @@ impl TunnelConnector {
                 }
                 Err(e) => {
                     let delay = backoff.next_delay();
-                    tracing::warn!("Connection failed: {}. Retrying in {:?}", e, delay);
+                    let state = backoff.state();
+                    tracing::warn!(
+                        "Connection failed: {}. Reconnecting (attempt {}, {}s elapsed) in {:?}",
+                        e,
+                        state.attempt,
+                        state.elapsed.as_secs(),
+                        delay
+                    );
+                    let failed = addresses.current().to_string();
+                    if let Some(next) = addresses.record_failure() {
+                        tracing::warn!(
+                            "Orchestrator at {} unreachable, trying {} next",
+                            failed,
+                            next
+                        );
+                    }
                     tokio::time::sleep(delay).await;
                 }
             }
         }
     }
 
-    /// Attempt a single connection to the orchestrator
-    async fn try_connect(&self) -> Result<ActiveTunnel, ConnectionError> {
-        let ssh_config = Config::default();
-        let ssh_config = Arc::new(ssh_config);
+    /// Whether `tunnel` is connected to the primary orchestrator
+    pub fn is_primary(&self, tunnel: &ActiveTunnel) -> bool {
+        tunnel.address() == self.config.orchestrator_address
+    }
+
+    /// Wait until the primary orchestrator accepts connections again
+    ///
+    /// Checks every `failback_check_interval` with a plain TCP connection,
+    /// so the agent can move back from a fallback.
+    pub async fn wait_for_primary(&self) {
+        let address = &self.config.orchestrator_address;
+        loop {
+            tokio::time::sleep(*self.config.failback_check_interval).await;
+            let probe = tokio::net::TcpStream::connect(address);
+            if let Ok(Ok(_)) = tokio::time::timeout(*self.config.connect_timeout, probe).await {
+                tracing::info!("Primary orchestrator at {} is reachable again", address);
+                return;
+            }
+            tracing::debug!("Primary orchestrator at {} still unreachable", address);
+        }
+    }
+
+    /// Attempt a single connection to the orchestrator at `address`
+    ///
+    /// Each stage is reported to the progress callback; the last one,
+    /// registration, finishes when the orchestrator's acknowledgement
+    /// arrives through [`ActiveTunnel::recv_event`].
+    async fn try_connect(&self, address: &str) -> Result<ActiveTunnel, ConnectionError> {
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + *self.config.connect_timeout;
+        let timed_out = || ConnectionError::Other(anyhow::anyhow!("Connection timed out"));
+        self.progress.report(&ConnectProgress::Attempt { address });
+
+        // Resolve the address
+        tracing::debug!("Connecting to {}", address);
+        let stage = self.progress.start(ConnectStage::Resolve);
+        let resolved: Vec<_> = match timeout_at(deadline, tokio::net::lookup_host(address)).await {
+            Ok(Ok(addrs)) => addrs.collect(),
+            Ok(Err(e)) => {
+                let e = anyhow::Error::new(e).context(format!("Failed to resolve {}", address));
+                return Err(stage.failed(e.into()));
+            }
+            Err(_) => return Err(stage.failed(timed_out())),
+        };
+        if resolved.is_empty() {
+            let e = anyhow::anyhow!("{} did not resolve to any address", address);
+            return Err(stage.failed(e.into()));
+        }
+        let shown: Vec<_> = resolved.iter().map(|a| a.to_string()).collect();
+        stage.finished(Some(shown.join(", ")));
+
+        // Open the TCP connection
+        let stage = self.progress.start(ConnectStage::TcpConnect);
+        let stream = match timeout_at(deadline, TcpStream::connect(&resolved[..])).await {
+            Ok(Ok(stream)) => stream,
+            Ok(Err(e)) => {
+                let e = anyhow::Error::new(e).context(format!("Failed to connect to {}", address));
+                return Err(stage.failed(e.into()));
+            }
+            Err(_) => return Err(stage.failed(timed_out())),
+        };
+        stage.finished(stream.peer_addr().ok().map(|a| a.to_string()));
 
-        // Create the client handler
+        // SSH handshake
+        let stage = self.progress.start(ConnectStage::SshHandshake);
         let (event_tx, event_rx) = mpsc::channel(TUNNEL_EVENT_CHANNEL_CAPACITY);
         let handler = ClientHandler::new(self.config.orchestrator_host_key.clone(), event_tx);
-
-        // Connect to the orchestrator
-        tracing::debug!("Connecting to {}", self.config.orchestrator_address);
-        let mut session = tokio::time::timeout(
-            self.config.connect_timeout,
-            client::connect(ssh_config, &self.config.orchestrator_address, handler),
+        let ssh_config = Arc::new(Config::default());
+        let mut session = match timeout_at(
+            deadline,
+            client::connect_stream(ssh_config, stream, handler),
         )
         .await
-        .map_err(|_| anyhow::anyhow!("Connection timed out"))?
-        .map_err(|e| {
-            let err_str = e.to_string();
-            // Detect host key rejection (russh returns "Unknown server key" error)
-            if err_str.contains("Unknown server key") || err_str.contains("server key") {
-                return ConnectionError::HostKeyRejected {
-                    message: "Server's host key was rejected. Ensure both machines are on the same Tailscale network.".to_string(),
-                };
+        {
+            Ok(Ok(session)) => session,
+            Ok(Err(e)) => {
+                // Detect host key rejection (russh returns "Unknown server key" error)
+                let err_str = e.to_string();
+                if err_str.contains("Unknown server key") || err_str.contains("server key") {
+                    return Err(stage.failed(ConnectionError::HostKeyRejected {
+                            message: "Server's host key was rejected. Ensure both machines are on the same Tailscale network.".to_string(),
+                        }));
+                }
+                let e = e.context(format!("SSH handshake with {} failed", address));
+                return Err(stage.failed(e.into()));
             }
-            ConnectionError::Other(anyhow::anyhow!("Failed to connect to {}: {}", self.config.orchestrator_address, e))
-        })?;
+            Err(_) => return Err(stage.failed(timed_out())),
+        };
+        stage.finished(None);
 
         // Authenticate with public key
         tracing::debug!("Authenticating as user '{}'", self.config.username);
-        let authenticated = session
+        let stage = self.progress.start(ConnectStage::Authenticate);
+        let authenticated = match session
             .authenticate_publickey(&self.config.username, Arc::clone(&self.key))
             .await
-            .map_err(|e| anyhow::anyhow!("Authentication error: {}", e))?;
-
+        {
+            Ok(authenticated) => authenticated,
+            Err(e) => {
+                let e = anyhow::Error::new(e).context("Authentication error");
+                return Err(stage.failed(e.into()));
+            }
+        };
         if !authenticated {
-            return Err(ConnectionError::AuthRejected);
+            return Err(stage.failed(ConnectionError::AuthRejected));
         }
+        stage.finished(None);
 
         tracing::debug!("Authentication successful, opening channel");
 
-        // Open a session channel for multiplexed communication
-        let channel = session
-            .channel_open_session()
-            .await
-            .map_err(|e| anyhow::anyhow!("Failed to open session channel: {}", e))?;
-
-        let _channel_id = channel.id();
+        // Open a session channel for multiplexed communication, then register
+        let stage = self.progress.start(ConnectStage::Register);
+        let channel = match session.channel_open_session().await {
+            Ok(channel) => channel,
+            Err(e) => {
+                let e = anyhow::Error::new(e).context("Failed to open session channel");
+                return Err(stage.failed(e.into()));
+            }
+        };
 
         // Create the active tunnel
-        let tunnel = ActiveTunnel::new(session, channel, event_rx);
+        let mut tunnel = ActiveTunnel::new(address.to_string(), session, channel, event_rx);
 
         // Send registration message
-        tunnel.register(&self.config).await?;
+        let reconnect_count = self.connections.load(Ordering::Relaxed);
+        if let Err(e) = tunnel.register(&self.identity, reconnect_count).await {
+            return Err(stage.failed(e.context("Failed to register").into()));
+        }
+        tunnel.registering = Some(Registering {
+            stage,
+            progress: self.progress.clone(),
+            started,
+        });
 
         Ok(tunnel)
     }
@@ -194,6 +373,8 @@ impl TunnelConnector {
 
 /// An active tunnel connection to the orchestrator
 pub struct ActiveTunnel {
+    /// Address of the orchestrator this tunnel is connected to
+    address: String,
     /// SSH session handle
     session: Handle<ClientHandler>,
     /// Main channel for communication
@@ -206,6 +387,17 @@ pub struct ActiveTunnel {
     /// Buffer for outgoing data
     #[allow(dead_code)]
     write_buffer: BytesMut,
+    /// Registration waiting for the orchestrator's acknowledgement
+    registering: Option<Registering>,
+}
+
+/// Registration sent, acknowledgement not yet received
+#[derive(Debug)]
+struct Registering {
+    stage: StageTimer,
+    progress: Progress,
+    /// When the connection attempt started
+    started: Instant,
 }
 
 /// Events received from the orchestrator
@@ -215,6 +407,8 @@ pub enum TunnelEvent {
     Registered {
         accepted: bool,
         reason: Option<String>,
+        /// Orchestrator release version, if it reported one
+        version: Option<String>,
     },
     /// Request to create a new session
     CreateSession {
@@ -222,6 +416,15 @@ pub enum TunnelEvent {
         shell: Option<String>,
         env: Vec<(String, String)>,
         size: TerminalSize,
+        cwd: Option<String>,
+        /// Plain pipes instead of a PTY when false
+        allocate_pty: bool,
+        /// Log level for this session's lifecycle logs (None = agent level)
+        log_level: Option<String>,
+        /// The client's terminal type (None = agent default)
+        term: Option<String>,
+        /// The client renders 24-bit color
+        truecolor: bool,
     },
     /// Data for a session
     SessionData {
@@ -237,36 +440,49 @@ pub enum TunnelEvent {
     SessionClose { session_id: SessionId },
     /// Heartbeat request
     Heartbeat { timestamp: u64 },
+    /// Change of a runtime setting, to be acked
+    ConfigUpdate { key: String, value: String },
     /// Connection closed
     Disconnected,
 }
 
 impl ActiveTunnel {
     fn new(
+        address: String,
         session: Handle<ClientHandler>,
         channel: Channel<Msg>,
         event_rx: mpsc::Receiver<TunnelEvent>,
     ) -> Self {
         Self {
+            address,
             session,
             channel,
             event_rx,
             codec: FrameCodec::new(),
             write_buffer: BytesMut::with_capacity(4096),
+            registering: None,
         }
     }
 
-    /// Send a registration message to the orchestrator
-    async fn register(&self, config: &AgentConfig) -> Result<()> {
-        let hostname = gethostname::gethostname().to_string_lossy().into_owned();
-        let machine_id = config.machine_alias();
+    /// Address of the orchestrator this tunnel is connected to
+    pub fn address(&self) -> &str {
+        &self.address
+    }
 
+    /// Send a registration message to the orchestrator
+    ///
+    /// `reconnect_count` is the number of earlier connections of this agent.
+    async fn register(&self, identity: &AgentIdentity, reconnect_count: u64) -> Result<()> {
         let message = Message::Register {
-            machine_id,
-            hostname,
-            os: std::env::consts::OS.to_string(),
-            arch: std::env::consts::ARCH.to_string(),
+            machine_id: identity.machine_id.clone(),
+            hostname: identity.hostname.clone(),
+            os: identity.os.clone(),
+            arch: identity.arch.clone(),
             version: Some(kt_protocol::PROTOCOL_VERSION.to_string()),
+            capabilities: supported_capabilities(),
+            reconnect_count,
+            alias: Some(identity.alias.clone()),
+            label: identity.label.clone(),
         };
 
         self.send_message(SessionId::CONTROL, message).await
@@ -299,6 +515,15 @@ impl ActiveTunnel {
         .await
     }
 
+    /// Send stderr output of a session without a PTY
+    pub async fn send_stderr(&self, session_id: SessionId, data: &[u8]) -> Result<()> {
+        self.send_message(
+            session_id,
+            Message::Stderr(bytes::Bytes::copy_from_slice(data)),
+        )
+        .await
+    }
+
     /// Send session ready notification
     pub async fn send_session_ready(&self, session_id: SessionId, pid: u32) -> Result<()> {
         self.send_message(session_id, Message::SessionReady { pid })
@@ -321,6 +546,17 @@ impl ActiveTunnel {
             .await
     }
 
+    /// Acknowledge a setting change: `result` is why it wasn't applied, if
+    /// it wasn't
+    pub async fn send_config_ack(&self, key: String, result: Result<(), String>) -> Result<()> {
+        let message = Message::AgentConfigAck {
+            key,
+            applied: result.is_ok(),
+            reason: result.err(),
+        };
+        self.send_message(SessionId::CONTROL, message).await
+    }
+
     /// Send error notification for a session
     pub async fn send_error(
         &self,
@@ -334,7 +570,30 @@ impl ActiveTunnel {
 
     /// Receive the next event from the orchestrator
     pub async fn recv_event(&mut self) -> Option<TunnelEvent> {
-        self.event_rx.recv().await
+        let event = self.event_rx.recv().await;
+        if let Some(registering) = self.registering.take() {
+            match &event {
+                Some(TunnelEvent::Registered { accepted: true, .. }) => {
+                    registering.stage.finished(None);
+                    registering.progress.report(&ConnectProgress::Ready {
+                        address: &self.address,
+                        elapsed: registering.started.elapsed(),
+                    });
+                }
+                Some(TunnelEvent::Registered { reason, .. }) => {
+                    let reason = reason.as_deref().unwrap_or("no reason given");
+                    let e = anyhow::anyhow!("Registration rejected: {}", reason);
+                    registering.stage.failed(e.into());
+                }
+                Some(TunnelEvent::Disconnected) | None => {
+                    let e = anyhow::anyhow!("Connection closed before registration was accepted");
+                    registering.stage.failed(e.into());
+                }
+                // Anything else can't come before the acknowledgement
+                Some(_) => self.registering = Some(registering),
+            }
+        }
+        event
     }
 
     /// Close the tunnel
@@ -374,19 +633,35 @@ impl ClientHandler {
     /// Process a decoded frame
     async fn handle_frame(&self, frame: Frame) {
         let event = match frame.message {
-            Message::RegisterAck { accepted, reason } => {
-                TunnelEvent::Registered { accepted, reason }
-            }
+            Message::RegisterAck {
+                accepted,
+                reason,
+                version,
+            } => TunnelEvent::Registered {
+                accepted,
+                reason,
+                version,
+            },
 
             Message::SessionCreate {
                 shell,
                 env,
                 initial_size,
+                cwd,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
             } => TunnelEvent::CreateSession {
                 session_id: frame.session_id,
                 shell,
                 env,
                 size: initial_size,
+                cwd,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
             },
 
             Message::Data(data) => TunnelEvent::SessionData {
@@ -405,6 +680,8 @@ impl ClientHandler {
 
             Message::Heartbeat { timestamp } => TunnelEvent::Heartbeat { timestamp },
 
+            Message::AgentConfigUpdate { key, value } => TunnelEvent::ConfigUpdate { key, value },
+
             _ => {
                 tracing::warn!("Unexpected message from orchestrator: {:?}", frame.message);
                 return;
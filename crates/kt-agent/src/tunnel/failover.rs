@@ -0,0 +1,128 @@
+//! Orchestrator failover order
+//!
+//! The agent tries the primary orchestrator first. After `attempts_per_address`
+//! consecutive failures it moves on to the next fallback, and after the last
+//! fallback it starts over from the primary. Each outage starts over from the
+//! primary too, so the agent never stays on a fallback longer than it has to.
+
+use kt_core::config::AgentConfig;
+
+/// Cycles through the primary and fallback orchestrator addresses
+#[derive(Debug, Clone)]
+pub struct FailoverAddresses {
+    /// Primary first, then fallbacks in order
+    addresses: Vec<String>,
+    /// Index of the address to try next
+    current: usize,
+    /// Consecutive failures on the current address
+    failures: u32,
+    /// Failures on one address before moving to the next
+    attempts_per_address: u32,
+}
+
+impl FailoverAddresses {
+    /// Addresses from the agent configuration
+    pub fn from_config(config: &AgentConfig) -> Self {
+        Self::new(
+            config.orchestrator_address.clone(),
+            config.fallback_orchestrators.clone(),
+            config.failover_attempts,
+        )
+    }
+
+    /// Create a rotation starting at `primary`
+    pub fn new(primary: String, fallbacks: Vec<String>, attempts_per_address: u32) -> Self {
+        let mut addresses = vec![primary];
+        for fallback in fallbacks {
+            if !addresses.contains(&fallback) {
+                addresses.push(fallback);
+            }
+        }
+        Self {
+            addresses,
+            current: 0,
+            failures: 0,
+            attempts_per_address: attempts_per_address.max(1),
+        }
+    }
+
+    /// Address to try next
+    pub fn current(&self) -> &str {
+        &self.addresses[self.current]
+    }
+
+    /// Whether the address to try next is the primary
+    pub fn is_primary(&self) -> bool {
+        self.current == 0
+    }
+
+    /// Whether any fallbacks are configured
+    pub fn has_fallbacks(&self) -> bool {
+        self.addresses.len() > 1
+    }
+
+    /// Record a failed attempt on the current address.
+    ///
+    /// Returns the address failed over to, if this used up its attempts.
+    pub fn record_failure(&mut self) -> Option<&str> {
+        self.failures += 1;
+        if self.failures < self.attempts_per_address || !self.has_fallbacks() {
+            return None;
+        }
+        self.failures = 0;
+        self.current = (self.current + 1) % self.addresses.len();
+        Some(self.current())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fails_over_in_order_and_wraps_to_primary() {
+        let mut addresses = FailoverAddresses::new(
+            "primary:2222".into(),
+            vec!["backup-a:2222".into(), "backup-b:2222".into()],
+            2,
+        );
+        assert_eq!(addresses.current(), "primary:2222");
+        assert!(addresses.is_primary());
+
+        assert_eq!(addresses.record_failure(), None);
+        assert_eq!(addresses.record_failure(), Some("backup-a:2222"));
+        assert!(!addresses.is_primary());
+
+        assert_eq!(addresses.record_failure(), None);
+        assert_eq!(addresses.record_failure(), Some("backup-b:2222"));
+
+        assert_eq!(addresses.record_failure(), None);
+        assert_eq!(addresses.record_failure(), Some("primary:2222"));
+        assert!(addresses.is_primary());
+    }
+
+    #[test]
+    fn test_without_fallbacks_stays_on_primary() {
+        let mut addresses = FailoverAddresses::new("primary:2222".into(), vec![], 1);
+        assert!(!addresses.has_fallbacks());
+        for _ in 0..5 {
+            assert_eq!(addresses.record_failure(), None);
+        }
+        assert_eq!(addresses.current(), "primary:2222");
+    }
+
+    #[test]
+    fn test_duplicate_fallbacks_ignored() {
+        let mut addresses = FailoverAddresses::new(
+            "primary:2222".into(),
+            vec![
+                "primary:2222".into(),
+                "backup:2222".into(),
+                "backup:2222".into(),
+            ],
+            1,
+        );
+        assert_eq!(addresses.record_failure(), Some("backup:2222"));
+        assert_eq!(addresses.record_failure(), Some("primary:2222"));
+    }
+}
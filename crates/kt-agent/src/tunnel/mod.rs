@@ -1,7 +1,11 @@
 //! Tunnel management for connecting to orchestrator
 
 mod connector;
+mod failover;
+mod progress;
 mod reconnect;
 
 pub use connector::{ActiveTunnel, ConnectionError, TunnelConnector, TunnelEvent};
-pub use reconnect::ExponentialBackoff;
+pub use failover::FailoverAddresses;
+pub use progress::{ConnectProgress, ConnectStage, ProgressFn};
+pub use reconnect::{BackoffState, ExponentialBackoff};
@@ -0,0 +1,176 @@
+//! Connection progress reporting
+//!
+//! Each connection attempt goes through the same stages, in order: resolve
+//! the orchestrator's address, open a TCP connection, do the SSH handshake,
+//! authenticate, and register. A callback given to
+//! [`TunnelConnector::with_progress`](super::TunnelConnector::with_progress)
+//! hears when each stage starts, finishes or fails, so a frontend can show
+//! exactly where an attempt got stuck instead of a single opaque error.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::ConnectionError;
+
+/// A step of connecting to the orchestrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectStage {
+    /// Resolve the orchestrator's address
+    Resolve,
+    /// Open a TCP connection
+    TcpConnect,
+    /// SSH key exchange and host key check
+    SshHandshake,
+    /// SSH public key authentication
+    Authenticate,
+    /// Open the tunnel channel and wait for the orchestrator to accept the
+    /// registration
+    Register,
+}
+
+impl fmt::Display for ConnectStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Resolve => write!(f, "resolve"),
+            Self::TcpConnect => write!(f, "TCP connect"),
+            Self::SshHandshake => write!(f, "SSH handshake"),
+            Self::Authenticate => write!(f, "authenticate"),
+            Self::Register => write!(f, "register"),
+        }
+    }
+}
+
+/// What happened while connecting
+#[derive(Debug)]
+pub enum ConnectProgress<'a> {
+    /// An attempt to connect to `address` is starting
+    Attempt { address: &'a str },
+    /// A stage has started
+    Started { stage: ConnectStage },
+    /// A stage has finished, with what it found if that's worth showing
+    /// (e.g. the resolved addresses)
+    Finished {
+        stage: ConnectStage,
+        elapsed: Duration,
+        detail: Option<String>,
+    },
+    /// A stage has failed, ending the attempt
+    Failed {
+        stage: ConnectStage,
+        elapsed: Duration,
+        error: &'a ConnectionError,
+    },
+    /// The orchestrator accepted the registration; the tunnel is ready
+    Ready {
+        address: &'a str,
+        elapsed: Duration,
+    },
+}
+
+/// Callback told about connection progress
+pub type ProgressFn = dyn Fn(&ConnectProgress<'_>) + Send + Sync;
+
+/// Where progress goes (nowhere unless a callback was given)
+#[derive(Clone, Default)]
+pub(crate) struct Progress(Option<Arc<ProgressFn>>);
+
+impl Progress {
+    pub(crate) fn new(callback: Arc<ProgressFn>) -> Self {
+        Self(Some(callback))
+    }
+
+    pub(crate) fn report(&self, progress: &ConnectProgress<'_>) {
+        if let Some(callback) = &self.0 {
+            callback(progress);
+        }
+    }
+
+    /// Report `stage` as started, returning its timer
+    pub(crate) fn start(&self, stage: ConnectStage) -> StageTimer {
+        self.report(&ConnectProgress::Started { stage });
+        StageTimer {
+            progress: self.clone(),
+            stage,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl fmt::Debug for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Progress").field(&self.0.is_some()).finish()
+    }
+}
+
+/// A stage in progress
+#[derive(Debug)]
+pub(crate) struct StageTimer {
+    progress: Progress,
+    stage: ConnectStage,
+    started: Instant,
+}
+
+impl StageTimer {
+    pub(crate) fn finished(self, detail: Option<String>) {
+        self.progress.report(&ConnectProgress::Finished {
+            stage: self.stage,
+            elapsed: self.started.elapsed(),
+            detail,
+        });
+    }
+
+    /// Report the stage as failed with `error`, handing the error back
+    pub(crate) fn failed(self, error: ConnectionError) -> ConnectionError {
+        self.progress.report(&ConnectProgress::Failed {
+            stage: self.stage,
+            elapsed: self.started.elapsed(),
+            error: &error,
+        });
+        error
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[test]
+    fn test_stage_timer_reports_start_and_outcome() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&seen);
+        let progress = Progress::new(Arc::new(move |p: &ConnectProgress<'_>| {
+            let line = match p {
+                ConnectProgress::Started { stage } => format!("start {}", stage),
+                ConnectProgress::Finished { stage, detail, .. } => {
+                    format!("done {} {:?}", stage, detail)
+                }
+                ConnectProgress::Failed { stage, error, .. } => {
+                    format!("fail {} {}", stage, error)
+                }
+                other => format!("{:?}", other),
+            };
+            sink.lock().unwrap().push(line);
+        }));
+
+        progress
+            .start(ConnectStage::Resolve)
+            .finished(Some("100.64.0.1:2222".to_string()));
+        let error = progress
+            .start(ConnectStage::Authenticate)
+            .failed(ConnectionError::AuthRejected);
+        assert!(matches!(error, ConnectionError::AuthRejected));
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![
+                "start resolve",
+                "done resolve Some(\"100.64.0.1:2222\")",
+                "start authenticate",
+                "fail authenticate Authentication rejected by orchestrator",
+            ]
+        );
+    }
+}
@@ -1,11 +1,21 @@
 //! Exponential backoff for reconnection
+//!
+//! Delays grow by `multiplier` from `initial` up to `max`, and jitter never
+//! pushes a delay past `max`, so a long outage settles into retrying every
+//! `max`. The backoff also counts attempts and the time since the first
+//! failure, for logging; [`ExponentialBackoff::reset`] clears both once a
+//! connection succeeds.
 
 use std::time::Duration;
 
+use tokio::time::Instant;
+
 use kt_core::config::BackoffConfig;
 
 /// Exponential backoff with jitter for reconnection attempts
 pub struct ExponentialBackoff {
+    /// Delay to start from (and return to on reset)
+    initial: Duration,
     /// Current delay
     current: Duration,
     /// Maximum delay
@@ -14,45 +24,78 @@ pub struct ExponentialBackoff {
     multiplier: f64,
     /// Jitter factor (0.0 to 1.0)
     jitter: f64,
+    /// Delays handed out since the last reset
+    attempt: u32,
+    /// When the first delay since the last reset was handed out
+    started: Option<Instant>,
+}
+
+/// Progress of an [`ExponentialBackoff`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackoffState {
+    /// Delays handed out since the last reset
+    pub attempt: u32,
+    /// Delay (before jitter) the next attempt will wait
+    pub next_delay: Duration,
+    /// Time since the first delay after the last reset
+    pub elapsed: Duration,
 }
 
 impl ExponentialBackoff {
     /// Create a new backoff from configuration
     pub fn from_config(config: &BackoffConfig) -> Self {
-        Self {
-            current: config.initial,
-            max: config.max,
-            multiplier: config.multiplier,
-            jitter: config.jitter,
-        }
+        Self::new(
+            *config.initial,
+            *config.max,
+            config.multiplier,
+            config.jitter,
+        )
     }
 
     /// Create a new backoff with custom parameters
     pub fn new(initial: Duration, max: Duration, multiplier: f64, jitter: f64) -> Self {
         Self {
-            current: initial,
+            initial,
+            current: std::cmp::min(initial, max),
             max,
             multiplier,
             jitter,
+            attempt: 0,
+            started: None,
         }
     }
 
     /// Get the next delay and advance the backoff
     pub fn next_delay(&mut self) -> Duration {
         let delay = self.current;
+        self.attempt = self.attempt.saturating_add(1);
+        self.started.get_or_insert_with(Instant::now);
 
         // Calculate next delay with multiplier
-        let next = Duration::from_secs_f64(self.current.as_secs_f64() * self.multiplier);
+        let next = Duration::try_from_secs_f64(self.current.as_secs_f64() * self.multiplier)
+            .unwrap_or(self.max);
         self.current = std::cmp::min(next, self.max);
 
-        // Add jitter
+        // Add jitter, without going past the cap
         let jitter_amount = delay.as_secs_f64() * self.jitter * rand::random::<f64>();
-        delay + Duration::from_secs_f64(jitter_amount)
+        let jitter_amount = Duration::try_from_secs_f64(jitter_amount).unwrap_or_default();
+        std::cmp::min(delay + jitter_amount, self.max)
     }
 
-    /// Reset the backoff to initial delay
-    pub fn reset(&mut self, initial: Duration) {
-        self.current = initial;
+    /// Reset to the initial delay and clear the attempt count and elapsed time
+    pub fn reset(&mut self) {
+        self.current = std::cmp::min(self.initial, self.max);
+        self.attempt = 0;
+        self.started = None;
+    }
+
+    /// Current progress
+    pub fn state(&self) -> BackoffState {
+        BackoffState {
+            attempt: self.attempt,
+            next_delay: self.current,
+            elapsed: self.started.map(|s| s.elapsed()).unwrap_or_default(),
+        }
     }
 }
 
@@ -91,4 +134,44 @@ mod tests {
         assert_eq!(d2, Duration::from_secs(60)); // Capped at max
         assert_eq!(d3, Duration::from_secs(60)); // Still capped
     }
+
+    #[test]
+    fn test_backoff_plateaus_at_max_with_jitter() {
+        let max = Duration::from_secs(60);
+        let mut backoff = ExponentialBackoff::new(Duration::from_secs(1), max, 2.0, 1.0);
+
+        let delays: Vec<_> = (0..50).map(|_| backoff.next_delay()).collect();
+        assert!(delays.iter().all(|d| *d <= max), "{:?}", delays);
+        assert_eq!(delays[49], max);
+        assert_eq!(backoff.state().next_delay, max);
+        assert_eq!(backoff.state().attempt, 50);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_backoff_tracks_elapsed_and_resets() {
+        let mut backoff =
+            ExponentialBackoff::new(Duration::from_secs(1), Duration::from_secs(60), 2.0, 0.0);
+        assert_eq!(
+            backoff.state(),
+            BackoffState {
+                attempt: 0,
+                next_delay: Duration::from_secs(1),
+                elapsed: Duration::ZERO,
+            }
+        );
+
+        for _ in 0..3 {
+            let delay = backoff.next_delay();
+            tokio::time::advance(delay).await;
+        }
+        let state = backoff.state();
+        assert_eq!(state.attempt, 3);
+        assert_eq!(state.next_delay, Duration::from_secs(8));
+        assert_eq!(state.elapsed, Duration::from_secs(7));
+
+        backoff.reset();
+        assert_eq!(backoff.state().attempt, 0);
+        assert_eq!(backoff.state().elapsed, Duration::ZERO);
+        assert_eq!(backoff.next_delay(), Duration::from_secs(1));
+    }
 }
@@ -0,0 +1,98 @@
+//! Agent/orchestrator release version comparison
+//!
+//! After registering, the agent compares its own version with the one the
+//! orchestrator reports in `RegisterAck`. A patch difference is expected
+//! during rolling upgrades and is only logged at debug level; a minor or
+//! major difference is likely to cause surprises and is warned about.
+
+use std::fmt;
+
+/// This agent's release version
+pub const AGENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How far apart the agent and orchestrator versions are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionSkew {
+    /// Same major and minor version (patch may differ)
+    Compatible,
+    /// Same major version, different minor version
+    Minor,
+    /// Different major version
+    Major,
+    /// The orchestrator didn't report a version, or it couldn't be parsed
+    Unknown,
+}
+
+impl VersionSkew {
+    /// Whether the versions differ by more than a patch
+    pub fn is_mismatch(&self) -> bool {
+        matches!(self, Self::Minor | Self::Major)
+    }
+}
+
+impl fmt::Display for VersionSkew {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compatible => write!(f, "compatible"),
+            Self::Minor => write!(f, "minor version mismatch"),
+            Self::Major => write!(f, "major version mismatch"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Compare two release versions (`major.minor.patch`, optionally with a
+/// `-pre` or `+build` suffix)
+pub fn compare_versions(agent: &str, orchestrator: &str) -> VersionSkew {
+    let (Some(agent), Some(orchestrator)) = (parse_version(agent), parse_version(orchestrator))
+    else {
+        return VersionSkew::Unknown;
+    };
+    if agent.0 != orchestrator.0 {
+        VersionSkew::Major
+    } else if agent.1 != orchestrator.1 {
+        VersionSkew::Minor
+    } else {
+        VersionSkew::Compatible
+    }
+}
+
+/// Parse the major and minor components of a version
+fn parse_version(version: &str) -> Option<(u64, u64)> {
+    let core = version
+        .trim()
+        .trim_start_matches('v')
+        .split(['-', '+'])
+        .next()?;
+    let mut parts = core.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patch_difference_is_compatible() {
+        assert_eq!(compare_versions("0.1.0", "0.1.7"), VersionSkew::Compatible);
+        assert_eq!(compare_versions("1.2.3", "v1.2.3-rc.1"), VersionSkew::Compatible);
+        assert!(!VersionSkew::Compatible.is_mismatch());
+    }
+
+    #[test]
+    fn test_minor_and_major_differences_are_mismatches() {
+        assert_eq!(compare_versions("0.1.0", "0.2.0"), VersionSkew::Minor);
+        assert_eq!(compare_versions("1.4.0", "2.0.0"), VersionSkew::Major);
+        assert!(VersionSkew::Minor.is_mismatch());
+        assert!(VersionSkew::Major.is_mismatch());
+    }
+
+    #[test]
+    fn test_unparseable_version_is_unknown() {
+        assert_eq!(compare_versions("0.1.0", ""), VersionSkew::Unknown);
+        assert_eq!(compare_versions("0.1.0", "latest"), VersionSkew::Unknown);
+        assert!(!VersionSkew::Unknown.is_mismatch());
+    }
+}
@@ -0,0 +1,220 @@
+//! Bench command implementation
+//!
+//! Measures the whole terminal path (IPC, orchestrator, SSH tunnel, agent,
+//! PTY and back) from the client's side, for catching performance
+//! regressions. Results vary with the machines and network involved, so
+//! compare runs against the same machine.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::ipc::{BenchSamples, OrchestratorClient, TerminalSession};
+use crate::output::{print_error, print_info, print_warning};
+
+/// Command run by default to produce output: as fast as the PTY allows,
+/// with a fixed line length
+pub const DEFAULT_BENCH_COMMAND: &str = "yes k-terminus-bench-0123456789abcdef";
+
+/// What to measure
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Shell to spawn instead of the machine's default
+    pub shell: Option<String>,
+    /// Command whose output is measured
+    pub command: String,
+    /// How long to measure throughput for
+    pub duration: Duration,
+    /// Output discarded before measuring
+    pub warmup: Duration,
+    /// Keystroke echo round trips to time
+    pub echo_samples: usize,
+    /// Print the report as JSON
+    pub json: bool,
+}
+
+/// Summary of a benchmark run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchReport {
+    /// Output throughput in MB/s (10^6 bytes)
+    pub throughput_mb_s: f64,
+    /// Output bytes measured
+    pub bytes: u64,
+    /// Output chunks measured
+    pub chunks: usize,
+    /// Seconds throughput was measured for
+    pub duration_secs: f64,
+    /// Median gap between output chunks, in milliseconds
+    pub chunk_p50_ms: f64,
+    /// 99th percentile gap between output chunks, in milliseconds
+    pub chunk_p99_ms: f64,
+    /// Median keystroke echo round trip, in milliseconds
+    pub echo_p50_ms: f64,
+    /// 99th percentile keystroke echo round trip, in milliseconds
+    pub echo_p99_ms: f64,
+    /// Echo round trips timed
+    pub echo_samples: usize,
+}
+
+impl BenchReport {
+    /// Summarize the samples from a run
+    pub fn from_samples(samples: &BenchSamples) -> Self {
+        let secs = samples.elapsed.as_secs_f64();
+        let mut gaps = samples.chunk_gaps.clone();
+        let mut echo = samples.echo.clone();
+        Self {
+            throughput_mb_s: if secs > 0.0 {
+                samples.bytes as f64 / 1_000_000.0 / secs
+            } else {
+                0.0
+            },
+            bytes: samples.bytes,
+            // One more chunk than gaps between them
+            chunks: samples.chunk_gaps.len() + usize::from(samples.bytes > 0),
+            duration_secs: secs,
+            chunk_p50_ms: percentile_ms(&mut gaps, 50),
+            chunk_p99_ms: percentile_ms(&mut gaps, 99),
+            echo_p50_ms: percentile_ms(&mut echo, 50),
+            echo_p99_ms: percentile_ms(&mut echo, 99),
+            echo_samples: samples.echo.len(),
+        }
+    }
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Throughput:    {:.2} MB/s ({:.1} MB in {:.1}s, {} chunks)",
+            self.throughput_mb_s,
+            self.bytes as f64 / 1_000_000.0,
+            self.duration_secs,
+            self.chunks
+        )?;
+        writeln!(
+            f,
+            "Chunk latency: p50 {:.2} ms, p99 {:.2} ms",
+            self.chunk_p50_ms, self.chunk_p99_ms
+        )?;
+        write!(
+            f,
+            "Echo latency:  p50 {:.2} ms, p99 {:.2} ms ({} samples)",
+            self.echo_p50_ms, self.echo_p99_ms, self.echo_samples
+        )
+    }
+}
+
+/// Nearest-rank percentile in milliseconds; 0 with no samples
+fn percentile_ms(samples: &mut [Duration], percentile: usize) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.sort_unstable();
+    let rank = (percentile * samples.len()).div_ceil(100).max(1);
+    samples[rank - 1].as_secs_f64() * 1000.0
+}
+
+/// Execute the bench command - measure a new session on `machine`, then
+/// close it
+pub async fn bench_command(
+    mut client: OrchestratorClient,
+    machine: &str,
+    options: &BenchOptions,
+) -> Result<()> {
+    let session = match client
+        .create_session(machine, options.shell.as_deref())
+        .await
+    {
+        Ok(session) => session,
+        Err(e) => {
+            print_error(&format!("Failed to create session: {}", e));
+            return Err(e);
+        }
+    };
+    let mut cleanup = OrchestratorClient::with_address(client.address().to_string())
+        .with_client_id(client.client_id().to_string());
+
+    if !options.json {
+        print_info(&format!(
+            "Benchmarking '{}' for {:?} after a {:?} warm-up...",
+            machine, options.duration, options.warmup
+        ));
+    }
+    let run = async {
+        let terminal = TerminalSession::new(client, session.id.clone()).await?;
+        terminal
+            .bench(
+                &options.command,
+                options.echo_samples,
+                options.warmup,
+                options.duration,
+            )
+            .await
+    };
+    let result = tokio::select! {
+        result = run => result,
+        _ = tokio::signal::ctrl_c() => Err(anyhow::anyhow!("Benchmark interrupted")),
+    };
+
+    // Close the session however the run ended; reconnecting with the same
+    // client ID keeps ownership
+    if let Err(e) = cleanup.kill_session(&session.id, true).await {
+        if !e.to_string().contains("not found") {
+            print_warning(&format!(
+                "Failed to close benchmark session {}: {}",
+                session.id, e
+            ));
+        }
+    }
+
+    let report = BenchReport::from_samples(&result?);
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{}", report);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|&v| Duration::from_millis(v)).collect()
+    }
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let mut samples = ms(&[5, 1, 4, 2, 3, 10, 6, 7, 9, 8]);
+        assert_eq!(percentile_ms(&mut samples, 50), 5.0);
+        assert_eq!(percentile_ms(&mut samples, 99), 10.0);
+        assert_eq!(percentile_ms(&mut ms(&[7]), 50), 7.0);
+        assert_eq!(percentile_ms(&mut [], 99), 0.0);
+    }
+
+    #[test]
+    fn test_report_from_samples() {
+        let samples = BenchSamples {
+            echo: ms(&[2, 1, 3]),
+            chunk_gaps: ms(&[1, 1, 2, 1]),
+            bytes: 5_000_000,
+            elapsed: Duration::from_secs(2),
+        };
+        let report = BenchReport::from_samples(&samples);
+        assert_eq!(report.throughput_mb_s, 2.5);
+        assert_eq!(report.chunks, 5);
+        assert_eq!(report.chunk_p50_ms, 1.0);
+        assert_eq!(report.chunk_p99_ms, 2.0);
+        assert_eq!(report.echo_p50_ms, 2.0);
+        assert_eq!(report.echo_samples, 3);
+
+        let text = report.to_string();
+        assert!(
+            text.contains("2.50 MB/s (5.0 MB in 2.0s, 5 chunks)"),
+            "{}",
+            text
+        );
+    }
+}
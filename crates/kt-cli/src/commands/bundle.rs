@@ -0,0 +1,946 @@
+//! Export and import of the orchestrator's state
+//!
+//! Moving the orchestrator to another machine means taking its host key
+//! along, since agents pin it, together with the config (machine profiles,
+//! tags, groups, token lifetime and webhooks) and the agent key. `export`
+//! packages these files, found where the active config says
+//! ([`BundlePaths`]), into a zstd-compressed tar with a manifest, and
+//! `import` restores them on the new machine.
+//!
+//! The IPC token isn't exported: an orchestrator generates a new one each
+//! time it starts. Paths in the config files that pointed at the old files
+//! or into the old config directory are rewritten to the new ones on import.
+//!
+//! A bundle can be encrypted with a passphrase: the tar is then sealed with
+//! XChaCha20-Poly1305 under a key derived from the passphrase with scrypt
+//! (as `age` does for passphrases), and prefixed with [`ENCRYPTION_MAGIC`],
+//! the scrypt cost, salt and nonce.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use toml_edit::{Document, Item, Value};
+
+use crate::ipc::OrchestratorClient;
+use crate::output::{print_info, print_success, print_warning};
+use kt_core::config::{self, AgentConfig, ConfigFile, VersionedConfig};
+use kt_core::permissions::{create_private_dir_all, write_private_file};
+
+/// Version of the bundle layout, bumped when older versions can't read it
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Start of an encrypted bundle
+pub const ENCRYPTION_MAGIC: &[u8] = b"k-terminus encrypted bundle\n";
+
+/// Name of the manifest in the tar
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Directory of the bundled files in the tar
+const FILES_DIR: &str = "files/";
+
+/// Marker file of an initialized config directory (see `kt_core::setup`)
+const INITIALIZED_MARKER: &str = "initialized";
+
+/// Largest file a bundle may hold
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Most tar entries a bundle may have, read or skipped
+const MAX_ENTRIES: usize = 64;
+
+/// Largest total size of a bundle's tar entries, read or skipped
+const MAX_BUNDLE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// scrypt cost (log2 of N) for new bundles; cheap in tests
+const SCRYPT_LOG_N: u8 = if cfg!(test) { 8 } else { 15 };
+
+/// Highest scrypt cost accepted from a bundle
+const MAX_SCRYPT_LOG_N: u8 = 20;
+
+/// zstd level of new bundles
+const ZSTD_LEVEL: i32 = 3;
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// A file of the config directory that is exported
+struct BundledFile {
+    name: &'static str,
+    description: &'static str,
+    /// Whether it grants access to the orchestrator or its machines
+    secret: bool,
+}
+
+/// Everything exported, in the order it is restored
+const BUNDLED_FILES: &[BundledFile] = &[
+    BundledFile {
+        name: "config.toml",
+        description: "orchestrator config, machine profiles, tags and groups",
+        secret: false,
+    },
+    BundledFile {
+        name: "agent.toml",
+        description: "agent config",
+        secret: false,
+    },
+    BundledFile {
+        name: "host_key",
+        description: "orchestrator host key, which agents trust",
+        secret: true,
+    },
+    BundledFile {
+        name: "host_key.pub",
+        description: "orchestrator public key",
+        secret: false,
+    },
+    BundledFile {
+        name: "agent_key",
+        description: "agent key",
+        secret: true,
+    },
+    BundledFile {
+        name: "agent_key.pub",
+        description: "agent public key",
+        secret: false,
+    },
+];
+
+fn bundled_file(name: &str) -> Option<&'static BundledFile> {
+    BUNDLED_FILES.iter().find(|file| file.name == name)
+}
+
+/// Where the active config keeps the files a bundle holds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundlePaths {
+    /// Orchestrator config file (`--config`, or the default)
+    pub config: PathBuf,
+    /// Orchestrator host key (`host_key_path`)
+    pub host_key: PathBuf,
+    /// Agent config, next to the orchestrator config
+    pub agent_config: PathBuf,
+    /// Agent key (`private_key_path` of the agent config)
+    pub agent_key: PathBuf,
+}
+
+impl BundlePaths {
+    /// Paths of the config file `config_path` (the default one if None);
+    /// keys it doesn't configure are looked for next to it
+    pub fn resolve(config_path: Option<&Path>) -> Result<Self> {
+        let config = config_path.map_or_else(config::default_config_path, Path::to_path_buf);
+        let dir = config.parent().unwrap_or(Path::new(".")).to_path_buf();
+        let agent_config = dir.join("agent.toml");
+
+        let host_key = if config.exists() {
+            config::load_config::<ConfigFile>(&config)
+                .with_context(|| format!("Failed to load config from {:?}", config))?
+                .orchestrator
+                .host_key_path
+        } else {
+            dir.join("host_key")
+        };
+        let agent_key = if agent_config.exists() {
+            config::load_config::<AgentConfig>(&agent_config)
+                .with_context(|| format!("Failed to load config from {:?}", agent_config))?
+                .private_key_path
+        } else {
+            dir.join("agent_key")
+        };
+
+        Ok(Self {
+            config,
+            host_key,
+            agent_config,
+            agent_key,
+        })
+    }
+
+    /// Directory holding the config file
+    pub fn config_dir(&self) -> &Path {
+        self.config.parent().unwrap_or(Path::new("."))
+    }
+
+    /// Where the bundled file `name` lives
+    fn path(&self, name: &str) -> PathBuf {
+        match name {
+            "config.toml" => self.config.clone(),
+            "agent.toml" => self.agent_config.clone(),
+            "host_key" => self.host_key.clone(),
+            "host_key.pub" => public_key_path(&self.host_key),
+            "agent_key" => self.agent_key.clone(),
+            "agent_key.pub" => public_key_path(&self.agent_key),
+            _ => self.config_dir().join(name),
+        }
+    }
+}
+
+fn public_key_path(key: &Path) -> PathBuf {
+    let mut path = key.as_os_str().to_os_string();
+    path.push(".pub");
+    PathBuf::from(path)
+}
+
+/// What a bundle holds
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Bundle layout version ([`BUNDLE_FORMAT_VERSION`])
+    pub format_version: u32,
+    /// Version of k-terminus that wrote it
+    pub k_terminus_version: String,
+    /// Schema version of `config.toml` it was written with
+    pub config_version: u32,
+    /// When it was written
+    pub created_at: String,
+    /// Config directory it was exported from
+    pub config_dir: PathBuf,
+    /// Files it holds
+    pub files: Vec<BundleEntry>,
+}
+
+impl BundleManifest {
+    /// Files that grant access to the orchestrator or its machines
+    pub fn secrets(&self) -> impl Iterator<Item = &BundleEntry> {
+        self.files.iter().filter(|file| file.secret)
+    }
+}
+
+/// A file in a bundle
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleEntry {
+    /// Name in the bundle
+    pub name: String,
+    /// Where it was exported from
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// SHA-256 of the contents, hex-encoded
+    pub sha256: String,
+    /// Whether it is restored readable by its owner only
+    pub secret: bool,
+}
+
+impl BundleEntry {
+    fn description(&self) -> &'static str {
+        bundled_file(&self.name).map_or("", |file| file.description)
+    }
+}
+
+/// A bundle read back, its files checked against the manifest
+#[derive(Debug)]
+pub struct Bundle {
+    pub manifest: BundleManifest,
+    files: HashMap<String, Vec<u8>>,
+}
+
+/// Package the files at `paths` into a bundle at `out`, encrypted if a
+/// `passphrase` is given
+pub fn export_bundle(
+    paths: &BundlePaths,
+    out: &Path,
+    passphrase: Option<&str>,
+) -> Result<BundleManifest> {
+    if out.exists() {
+        bail!("{} already exists", out.display());
+    }
+
+    let mut files = Vec::new();
+    for file in BUNDLED_FILES {
+        let path = paths.path(file.name);
+        let contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+        };
+        let secret = file.secret || (file.name == "config.toml" && has_webhook_secrets(&contents));
+        files.push((file.name, path, secret, contents));
+    }
+    if !files.iter().any(|(name, ..)| *name == "host_key") {
+        bail!(
+            "No host key at {}; nothing worth exporting",
+            paths.host_key.display()
+        );
+    }
+
+    let manifest = BundleManifest {
+        format_version: BUNDLE_FORMAT_VERSION,
+        k_terminus_version: env!("CARGO_PKG_VERSION").to_string(),
+        config_version: <ConfigFile as VersionedConfig>::VERSION,
+        created_at: kt_core::time::format_iso8601(SystemTime::now()),
+        config_dir: paths.config_dir().to_path_buf(),
+        files: files
+            .iter()
+            .map(|(name, path, secret, contents)| BundleEntry {
+                name: name.to_string(),
+                path: path.clone(),
+                size: contents.len() as u64,
+                sha256: sha256_hex(contents),
+                secret: *secret,
+            })
+            .collect(),
+    };
+
+    let mut tar = tar::Builder::new(zstd::Encoder::new(Vec::new(), ZSTD_LEVEL)?);
+    append(
+        &mut tar,
+        MANIFEST_NAME,
+        &serde_json::to_vec_pretty(&manifest)?,
+        false,
+    )?;
+    for (name, _, secret, contents) in &files {
+        append(
+            &mut tar,
+            &format!("{}{}", FILES_DIR, name),
+            contents,
+            *secret,
+        )?;
+    }
+    let archive = tar.into_inner()?.finish()?;
+
+    let bytes = match passphrase {
+        Some(passphrase) => encrypt(&archive, passphrase)?,
+        None => archive,
+    };
+    // The bundle holds secrets whether or not it is encrypted
+    write_private_file(out, &bytes).with_context(|| format!("Failed to write {:?}", out))?;
+    Ok(manifest)
+}
+
+/// The tar of a bundle being written
+type BundleBuilder = tar::Builder<zstd::Encoder<'static, Vec<u8>>>;
+
+fn append(tar: &mut BundleBuilder, path: &str, contents: &[u8], secret: bool) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    header.set_mode(if secret { 0o600 } else { 0o644 });
+    header.set_mtime(kt_core::time::current_time_secs());
+    header.set_cksum();
+    tar.append_data(&mut header, path, contents)?;
+    Ok(())
+}
+
+/// Whether a `config.toml` configures webhooks with signing secrets
+fn has_webhook_secrets(contents: &[u8]) -> bool {
+    let Ok(value) = std::str::from_utf8(contents)
+        .map_err(|_| ())
+        .and_then(|text| text.parse::<toml::Value>().map_err(|_| ()))
+    else {
+        return false;
+    };
+    value
+        .get("orchestrator")
+        .and_then(|orchestrator| orchestrator.get("webhook"))
+        .and_then(toml::Value::as_array)
+        .is_some_and(|webhooks| webhooks.iter().any(|hook| hook.get("secret").is_some()))
+}
+
+/// Read the bundle at `path`, decrypting it with `passphrase`, and check it
+/// can be imported by this version
+pub fn read_bundle(path: &Path, passphrase: Option<&str>) -> Result<Bundle> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    let archive = match bytes.strip_prefix(ENCRYPTION_MAGIC) {
+        Some(sealed) => {
+            let Some(passphrase) = passphrase else {
+                bail!("The bundle is encrypted; pass its passphrase with --passphrase");
+            };
+            decrypt(sealed, passphrase)?
+        }
+        None => bytes,
+    };
+
+    // Only the manifest and the files a bundle can hold are read; the rest
+    // is skipped, and the entries are bounded so a crafted bundle can't
+    // exhaust memory (or take forever to decompress)
+    let mut manifest = None;
+    let mut files = HashMap::new();
+    let mut total_size = 0;
+    let decoder = zstd::Decoder::new(archive.as_slice()).context("Not a k-terminus bundle")?;
+    let mut tar = tar::Archive::new(decoder);
+    for (index, entry) in tar
+        .entries()
+        .context("Not a k-terminus bundle")?
+        .enumerate()
+    {
+        if index >= MAX_ENTRIES {
+            bail!("Damaged bundle: more than {} entries", MAX_ENTRIES);
+        }
+        let mut entry = entry.context("Damaged bundle")?;
+        if entry.size() > MAX_FILE_SIZE {
+            bail!("Damaged bundle: {} is too large", entry.path()?.display());
+        }
+        total_size += entry.size();
+        if total_size > MAX_BUNDLE_SIZE {
+            bail!("Damaged bundle: its contents are too large");
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let file = name
+            .strip_prefix(FILES_DIR)
+            .filter(|file| bundled_file(file).is_some());
+        if name != MANIFEST_NAME && file.is_none() {
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        match file {
+            Some(file) => {
+                files.insert(file.to_string(), contents);
+            }
+            None => manifest = Some(serde_json::from_slice::<BundleManifest>(&contents)?),
+        }
+    }
+    let manifest = manifest.context("Not a k-terminus bundle: it has no manifest")?;
+
+    if manifest.format_version > BUNDLE_FORMAT_VERSION {
+        bail!(
+            "The bundle was written by k-terminus {} in a newer format (version {}); upgrade to import it",
+            manifest.k_terminus_version,
+            manifest.format_version
+        );
+    }
+    let config_version = <ConfigFile as VersionedConfig>::VERSION;
+    if manifest.config_version > config_version {
+        bail!(
+            "The bundle's config has schema version {}, newer than the supported version {}; upgrade to import it",
+            manifest.config_version,
+            config_version
+        );
+    }
+    for entry in &manifest.files {
+        if bundled_file(&entry.name).is_none() {
+            bail!("Damaged bundle: unexpected file {}", entry.name);
+        }
+        let contents = files
+            .get(&entry.name)
+            .with_context(|| format!("Damaged bundle: {} is missing", entry.name))?;
+        if sha256_hex(contents) != entry.sha256 {
+            bail!("Damaged bundle: {} doesn't match its checksum", entry.name);
+        }
+    }
+
+    Ok(Bundle { manifest, files })
+}
+
+/// Restore `bundle` to `paths`, replacing an initialized setup only with
+/// `force`
+///
+/// The keys go next to the config file, where it is then pointed.
+pub fn import_bundle(bundle: &Bundle, paths: &BundlePaths, force: bool) -> Result<()> {
+    let config_dir = paths.config_dir();
+    if config_dir.join(INITIALIZED_MARKER).exists() && !force {
+        bail!(
+            "{} already holds a k-terminus setup; pass --force to replace it",
+            config_dir.display()
+        );
+    }
+    create_private_dir_all(config_dir)
+        .with_context(|| format!("Failed to create {:?}", config_dir))?;
+
+    let target = |name: &str| match name {
+        "config.toml" | "agent.toml" => paths.path(name),
+        _ => config_dir.join(name),
+    };
+    // Old paths to new ones, the files before the directory holding them
+    let mut moves: Vec<(PathBuf, PathBuf)> = bundle
+        .manifest
+        .files
+        .iter()
+        .map(|entry| (entry.path.clone(), target(&entry.name)))
+        .collect();
+    moves.push((bundle.manifest.config_dir.clone(), config_dir.to_path_buf()));
+
+    for entry in &bundle.manifest.files {
+        let mut contents = bundle.files[&entry.name].clone();
+        if entry.name.ends_with(".toml") {
+            contents = relocate_config(&contents, &moves)
+                .with_context(|| format!("Failed to update paths in {}", entry.name))?;
+        }
+        let path = target(&entry.name);
+        if entry.secret {
+            write_private_file(&path, &contents)
+        } else {
+            fs::write(&path, &contents)
+        }
+        .with_context(|| format!("Failed to write {:?}", path))?;
+    }
+    fs::write(config_dir.join(INITIALIZED_MARKER), "")
+        .context("Failed to create initialized marker")?;
+    Ok(())
+}
+
+/// Rewrite paths in a TOML file by the first of `moves` (old path, new
+/// path) they are or lie under
+fn relocate_config(contents: &[u8], moves: &[(PathBuf, PathBuf)]) -> Result<Vec<u8>> {
+    let moves: Vec<(String, String)> = moves
+        .iter()
+        .filter(|(from, to)| from != to)
+        .map(|(from, to)| {
+            let from = from.to_string_lossy().into_owned();
+            (from, to.to_string_lossy().into_owned())
+        })
+        .collect();
+    if moves.is_empty() {
+        return Ok(contents.to_vec());
+    }
+    let mut doc: Document = std::str::from_utf8(contents)?.parse()?;
+    relocate_item(doc.as_item_mut(), &moves);
+    Ok(doc.to_string().into_bytes())
+}
+
+fn relocate_item(item: &mut Item, moves: &[(String, String)]) {
+    match item {
+        Item::Value(value) => relocate_value(value, moves),
+        Item::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                relocate_item(item, moves);
+            }
+        }
+        Item::ArrayOfTables(tables) => {
+            for (_, item) in tables.iter_mut().flat_map(|table| table.iter_mut()) {
+                relocate_item(item, moves);
+            }
+        }
+        Item::None => {}
+    }
+}
+
+fn relocate_value(value: &mut Value, moves: &[(String, String)]) {
+    match value {
+        Value::String(text) => {
+            let Some((to, rest)) = moves.iter().find_map(|(from, to)| {
+                let rest = text.value().strip_prefix(from.as_str())?;
+                (rest.is_empty() || rest.starts_with(['/', '\\'])).then_some((to, rest))
+            }) else {
+                return;
+            };
+            let decor = text.decor().clone();
+            *value = Value::from(format!("{}{}", to, rest));
+            *value.decor_mut() = decor;
+        }
+        Value::Array(array) => {
+            for value in array.iter_mut() {
+                relocate_value(value, moves);
+            }
+        }
+        Value::InlineTable(table) => {
+            for (_, value) in table.iter_mut() {
+                relocate_value(value, moves);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], log_n: u8) -> Result<Key> {
+    let params = scrypt::Params::new(log_n, 8, 1, 32)
+        .map_err(|_| anyhow!("Invalid scrypt cost {}", log_n))?;
+    let mut key = Key::default();
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| anyhow!("Failed to derive the bundle key"))?;
+    Ok(key)
+}
+
+fn encrypt(archive: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut sealed = Vec::with_capacity(ENCRYPTION_MAGIC.len() + 1 + SALT_LEN + NONCE_LEN);
+    sealed.extend_from_slice(ENCRYPTION_MAGIC);
+    sealed.push(SCRYPT_LOG_N);
+    sealed.extend_from_slice(&salt);
+    sealed.extend_from_slice(&nonce);
+
+    // The header is authenticated too, so its cost can't be swapped
+    let key = derive_key(passphrase, &salt, SCRYPT_LOG_N)?;
+    let payload = Payload {
+        msg: archive,
+        aad: &sealed[ENCRYPTION_MAGIC.len()..],
+    };
+    let ciphertext = XChaCha20Poly1305::new(&key)
+        .encrypt(XNonce::from_slice(&nonce), payload)
+        .map_err(|_| anyhow!("Failed to encrypt the bundle"))?;
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt what follows [`ENCRYPTION_MAGIC`] in an encrypted bundle
+fn decrypt(sealed: &[u8], passphrase: &str) -> Result<Vec<u8>> {
+    let Some((&log_n, rest)) = sealed.split_first() else {
+        bail!("Damaged bundle: it is truncated");
+    };
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        bail!("Damaged bundle: it is truncated");
+    }
+    if log_n > MAX_SCRYPT_LOG_N {
+        bail!("Damaged bundle: unsupported scrypt cost {}", log_n);
+    }
+    let (header, ciphertext) = sealed.split_at(1 + SALT_LEN + NONCE_LEN);
+    let (salt, nonce) = header[1..].split_at(SALT_LEN);
+
+    let key = derive_key(passphrase, salt, log_n)?;
+    let payload = Payload {
+        msg: ciphertext,
+        aad: header,
+    };
+    XChaCha20Poly1305::new(&key)
+        .decrypt(XNonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow!("Wrong passphrase, or the bundle is damaged"))
+}
+
+/// Refuse to run while an orchestrator is using the config directory
+async fn ensure_stopped(client: &mut OrchestratorClient) -> Result<()> {
+    if client.ping().await.unwrap_or(false) {
+        bail!("The orchestrator is running; stop it first with 'k-terminus stop'");
+    }
+    Ok(())
+}
+
+fn describe(entry: &BundleEntry) -> String {
+    let mut line = format!("  {:<14} {}", entry.name, entry.description());
+    if entry.secret {
+        line.push_str(" [secret]");
+    }
+    line
+}
+
+/// Execute `export` of the files the config at `config_path` names
+pub async fn export_command(
+    client: &mut OrchestratorClient,
+    config_path: Option<&Path>,
+    out: &Path,
+    passphrase: Option<&str>,
+) -> Result<()> {
+    ensure_stopped(client).await?;
+    let paths = BundlePaths::resolve(config_path)?;
+    let manifest = export_bundle(&paths, out, passphrase)?;
+
+    print_success(&format!(
+        "Exported {} files from {} to {}{}",
+        manifest.files.len(),
+        paths.config_dir().display(),
+        out.display(),
+        if passphrase.is_some() {
+            " (encrypted)"
+        } else {
+            ""
+        }
+    ));
+    for entry in &manifest.files {
+        println!("{}", describe(entry));
+    }
+
+    let secrets: Vec<&str> = manifest.secrets().map(|file| file.name.as_str()).collect();
+    print_warning(&format!(
+        "The bundle holds secrets ({}): anyone with it can impersonate the orchestrator. \
+         Delete it once imported",
+        secrets.join(", ")
+    ));
+    if passphrase.is_none() {
+        print_warning("The bundle is not encrypted; pass --passphrase to encrypt it");
+    }
+    print_info("The IPC token isn't exported; the orchestrator generates a new one when it starts");
+    Ok(())
+}
+
+/// Execute `import` to where the config at `config_path` lives
+pub async fn import_command(
+    client: &mut OrchestratorClient,
+    config_path: Option<&Path>,
+    path: &Path,
+    passphrase: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    ensure_stopped(client).await?;
+    let bundle = read_bundle(path, passphrase)?;
+    let paths = BundlePaths::resolve(config_path)?;
+    import_bundle(&bundle, &paths, force)?;
+
+    let manifest = &bundle.manifest;
+    print_success(&format!(
+        "Imported {} files into {} (exported by k-terminus {} at {})",
+        manifest.files.len(),
+        paths.config_dir().display(),
+        manifest.k_terminus_version,
+        manifest.created_at
+    ));
+    for entry in &manifest.files {
+        println!("{}", describe(entry));
+    }
+
+    let secrets: Vec<&str> = manifest.secrets().map(|file| file.name.as_str()).collect();
+    print_warning(&format!(
+        "Restored secrets ({}) readable by you only; delete the bundle now",
+        secrets.join(", ")
+    ));
+    print_info("Start the orchestrator with 'k-terminus serve'; agents reconnect on their own");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Paths of the config file in `dir`
+    fn paths(dir: &Path) -> BundlePaths {
+        BundlePaths::resolve(Some(&dir.join("config.toml"))).unwrap()
+    }
+
+    fn old_setup(dir: &Path) {
+        let config = format!(
+            "version = {}\n\n[orchestrator]\nhost_key_path = \"{}/host_key\"\n\n\
+             [orchestrator.groups]\ngpu = [\"gpu-box\"]\n",
+            <ConfigFile as VersionedConfig>::VERSION,
+            dir.display()
+        );
+        fs::write(dir.join("config.toml"), config).unwrap();
+        fs::write(dir.join("host_key"), "host key").unwrap();
+        fs::write(dir.join("host_key.pub"), "host key pub").unwrap();
+        fs::write(dir.join("agent_key"), "agent key").unwrap();
+        fs::write(dir.join("ipc_auth_token.json"), "{}").unwrap();
+        fs::write(dir.join(INITIALIZED_MARKER), "").unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        old_setup(old.path());
+        let out = old.path().join("bundle.tar.zst");
+
+        let manifest = export_bundle(&paths(old.path()), &out, None).unwrap();
+        let names: Vec<&str> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["config.toml", "host_key", "host_key.pub", "agent_key"]
+        );
+        let secrets: Vec<&str> = manifest.secrets().map(|f| f.name.as_str()).collect();
+        assert_eq!(secrets, ["host_key", "agent_key"]);
+        assert!(export_bundle(&paths(old.path()), &out, None).is_err());
+
+        let bundle = read_bundle(&out, None).unwrap();
+        assert_eq!(bundle.manifest, manifest);
+        let target = new.path().join("k-terminus");
+        import_bundle(&bundle, &paths(&target), false).unwrap();
+
+        assert_eq!(fs::read(target.join("host_key")).unwrap(), b"host key");
+        assert!(target.join(INITIALIZED_MARKER).exists());
+        assert!(!target.join("ipc_auth_token.json").exists());
+        let config = fs::read_to_string(target.join("config.toml")).unwrap();
+        assert!(
+            config.contains(&format!("\"{}/host_key\"", target.display())),
+            "{}",
+            config
+        );
+        assert!(config.contains("gpu = [\"gpu-box\"]"), "{}", config);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |name| {
+                fs::metadata(target.join(name))
+                    .unwrap()
+                    .permissions()
+                    .mode()
+            };
+            assert_eq!(mode("host_key") & 0o777, 0o600);
+            assert_eq!(mode("agent_key") & 0o777, 0o600);
+        }
+
+        // An initialized setup is only replaced with force
+        let err = import_bundle(&bundle, &paths(&target), false).unwrap_err();
+        assert!(err.to_string().contains("--force"), "{}", err);
+        import_bundle(&bundle, &paths(&target), true).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let old = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+        old_setup(old.path());
+        let out = old.path().join("bundle.tar.zst");
+        export_bundle(&paths(old.path()), &out, Some("correct horse")).unwrap();
+
+        let sealed = fs::read(&out).unwrap();
+        assert!(sealed.starts_with(ENCRYPTION_MAGIC));
+        assert!(!sealed.windows(8).any(|window| window == b"host key"));
+
+        let err = read_bundle(&out, None).unwrap_err();
+        assert!(err.to_string().contains("--passphrase"), "{}", err);
+        let err = read_bundle(&out, Some("wrong")).unwrap_err();
+        assert!(err.to_string().contains("Wrong passphrase"), "{}", err);
+
+        // The header is authenticated along with the contents
+        let mut tampered = sealed.clone();
+        tampered[ENCRYPTION_MAGIC.len() + 1] ^= 1;
+        let tampered_path = old.path().join("tampered.tar.zst");
+        fs::write(&tampered_path, tampered).unwrap();
+        assert!(read_bundle(&tampered_path, Some("correct horse")).is_err());
+
+        let bundle = read_bundle(&out, Some("correct horse")).unwrap();
+        import_bundle(&bundle, &paths(new.path()), false).unwrap();
+        assert_eq!(
+            fs::read(new.path().join("agent_key")).unwrap(),
+            b"agent key"
+        );
+    }
+
+    #[test]
+    fn test_rejects_newer_or_damaged_bundles() {
+        let old = tempfile::tempdir().unwrap();
+        old_setup(old.path());
+        fs::write(
+            old.path().join("config.toml"),
+            "[[orchestrator.webhook]]\nurl = \"https://example.com\"\nsecret = \"s3cret\"\n",
+        )
+        .unwrap();
+        let out = old.path().join("bundle.tar.zst");
+        let mut manifest = export_bundle(&paths(old.path()), &out, None).unwrap();
+        // Webhook secrets make the config a secret too
+        assert!(manifest.files[0].secret);
+
+        let rewrite = |manifest: &BundleManifest, config: &[u8]| {
+            let path = old.path().join("edited.tar.zst");
+            let _ = fs::remove_file(&path);
+            let mut tar = tar::Builder::new(zstd::Encoder::new(Vec::new(), ZSTD_LEVEL).unwrap());
+            append(
+                &mut tar,
+                MANIFEST_NAME,
+                &serde_json::to_vec(manifest).unwrap(),
+                false,
+            )
+            .unwrap();
+            append(&mut tar, "files/config.toml", config, false).unwrap();
+            fs::write(&path, tar.into_inner().unwrap().finish().unwrap()).unwrap();
+            read_bundle(&path, None).unwrap_err().to_string()
+        };
+
+        manifest.files.truncate(1);
+        let config = fs::read(old.path().join("config.toml")).unwrap();
+        assert!(rewrite(&manifest, b"tampered").contains("checksum"));
+
+        let mut newer = manifest.clone();
+        newer.format_version += 1;
+        assert!(rewrite(&newer, &config).contains("newer format"));
+
+        let mut newer = manifest.clone();
+        newer.config_version += 1;
+        assert!(rewrite(&newer, &config).contains("schema version"));
+
+        manifest.files[0].name = "../escape".to_string();
+        assert!(rewrite(&manifest, &config).contains("unexpected file"));
+    }
+
+    #[test]
+    fn test_skips_unknown_entries_and_bounds_the_rest() {
+        let dir = tempfile::tempdir().unwrap();
+        old_setup(dir.path());
+        let out = dir.path().join("bundle.tar.zst");
+        let manifest = export_bundle(&paths(dir.path()), &out, None).unwrap();
+
+        let write = |extra: &dyn Fn(&mut BundleBuilder)| {
+            let path = dir.path().join("edited.tar.zst");
+            let bundle = read_bundle(&out, None).unwrap();
+            let mut tar = tar::Builder::new(zstd::Encoder::new(Vec::new(), ZSTD_LEVEL).unwrap());
+            extra(&mut tar);
+            append(
+                &mut tar,
+                MANIFEST_NAME,
+                &serde_json::to_vec(&manifest).unwrap(),
+                false,
+            )
+            .unwrap();
+            for (name, contents) in &bundle.files {
+                append(&mut tar, &format!("{}{}", FILES_DIR, name), contents, false).unwrap();
+            }
+            fs::write(&path, tar.into_inner().unwrap().finish().unwrap()).unwrap();
+            read_bundle(&path, None)
+        };
+
+        let bundle = write(&|tar| {
+            append(tar, "notes.txt", b"not bundled", false).unwrap();
+            append(tar, "files/other", b"not bundled", false).unwrap();
+        })
+        .unwrap();
+        assert_eq!(bundle.manifest, manifest);
+        assert_eq!(bundle.files.len(), manifest.files.len());
+
+        let err = write(&|tar| {
+            for i in 0..MAX_ENTRIES {
+                append(tar, &format!("padding-{}", i), b"", false).unwrap();
+            }
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("entries"), "{}", err);
+
+        let large = vec![0; MAX_FILE_SIZE as usize];
+        let err = write(&|tar| {
+            for i in 0..(MAX_BUNDLE_SIZE / MAX_FILE_SIZE) {
+                append(tar, &format!("padding-{}", i), &large, false).unwrap();
+            }
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("too large"), "{}", err);
+    }
+
+    #[test]
+    fn test_paths_follow_the_active_config() {
+        let old = tempfile::tempdir().unwrap();
+        let keys = tempfile::tempdir().unwrap();
+        let new = tempfile::tempdir().unwrap();
+
+        // A config passed with --config, keeping its host key elsewhere
+        let config_path = old.path().join("orchestrator.toml");
+        let host_key = keys.path().join("kt_host_key");
+        fs::write(
+            &config_path,
+            format!(
+                "version = {}\n\n[orchestrator]\nhost_key_path = \"{}\"\n",
+                <ConfigFile as VersionedConfig>::VERSION,
+                host_key.display()
+            ),
+        )
+        .unwrap();
+        fs::write(&host_key, "host key").unwrap();
+        fs::write(old.path().join("agent_key"), "agent key").unwrap();
+
+        let old_paths = BundlePaths::resolve(Some(&config_path)).unwrap();
+        assert_eq!(old_paths.host_key, host_key);
+        assert_eq!(old_paths.agent_key, old.path().join("agent_key"));
+        let out = old.path().join("bundle.tar.zst");
+        let manifest = export_bundle(&old_paths, &out, None).unwrap();
+        let names: Vec<&str> = manifest.files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["config.toml", "host_key", "agent_key"]);
+
+        // Restored to the new --config, its host key next to it
+        let new_config = new.path().join("kt").join("orchestrator.toml");
+        let new_paths = BundlePaths::resolve(Some(&new_config)).unwrap();
+        import_bundle(&read_bundle(&out, None).unwrap(), &new_paths, false).unwrap();
+
+        let new_host_key = new.path().join("kt").join("host_key");
+        assert_eq!(fs::read(&new_host_key).unwrap(), b"host key");
+        let config = fs::read_to_string(&new_config).unwrap();
+        assert!(
+            config.contains(&format!("\"{}\"", new_host_key.display())),
+            "{}",
+            config
+        );
+        assert_eq!(
+            BundlePaths::resolve(Some(&new_config)).unwrap().host_key,
+            new_host_key
+        );
+    }
+}
@@ -1,13 +1,15 @@
 //! Config command implementations
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use toml_edit::{Document, Item, TableLike, Value};
 
+use super::config_key::{self, KeyPath, SetOp};
 use crate::output::{print_error, print_info, print_success, print_warning};
-use kt_core::config;
+use kt_core::config::{self, layered, ConfigFile, ConfigLoader, VersionedConfig};
 
-/// Get a config value by key
+/// Get a config value by key (see [`config_key`] for the key syntax)
 pub fn config_get(config_path: Option<&PathBuf>, key: &str) -> Result<()> {
     let path = config_path
         .cloned()
@@ -19,121 +21,86 @@ pub fn config_get(config_path: Option<&PathBuf>, key: &str) -> Result<()> {
         return Ok(());
     }
 
-    let content = std::fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    let key_path: KeyPath = key.parse()?;
+    let doc = read_document(&path)?;
+    let Some(item) = config_key::get(&doc, &key_path) else {
+        print_error(&format!("Key not found: {}", key));
+        return Ok(());
+    };
 
-    let table: toml::Table =
-        toml::from_str(&content).with_context(|| "Failed to parse config file")?;
-
-    // Navigate through the key path (e.g., "orchestrator.bind_address")
-    let parts: Vec<&str> = key.split('.').collect();
-    let mut current: &toml::Value = &toml::Value::Table(table);
-
-    for part in &parts {
-        match current {
-            toml::Value::Table(t) => {
-                if let Some(v) = t.get(*part) {
-                    current = v;
-                } else {
-                    print_error(&format!("Key not found: {}", key));
-                    return Ok(());
-                }
-            }
-            _ => {
-                print_error(&format!("Key not found: {}", key));
-                return Ok(());
+    // Print the value; arrays one element per line
+    match item {
+        Item::Value(Value::Array(array)) => {
+            for element in array {
+                println!("{}", display_value(element));
             }
         }
-    }
-
-    // Print the value
-    match current {
-        toml::Value::String(s) => println!("{}", s),
-        toml::Value::Integer(i) => println!("{}", i),
-        toml::Value::Float(f) => println!("{}", f),
-        toml::Value::Boolean(b) => println!("{}", b),
-        toml::Value::Array(a) => {
-            for item in a {
-                println!("{}", item);
-            }
-        }
-        toml::Value::Table(_) => {
-            // Print sub-table as TOML
-            println!("{}", toml::to_string_pretty(current)?);
-        }
-        toml::Value::Datetime(d) => println!("{}", d),
+        Item::Value(value) => println!("{}", display_value(value)),
+        // Print sub-tables as TOML
+        Item::Table(_) | Item::ArrayOfTables(_) => print!("{}", item),
+        Item::None => {}
     }
 
     Ok(())
 }
 
-/// Set a config value by key
-pub fn config_set(config_path: Option<&PathBuf>, key: &str, value: &str) -> Result<()> {
+/// A value as `config get` prints it: strings without quotes, anything
+/// else as TOML
+fn display_value(value: &Value) -> String {
+    match value.as_str() {
+        Some(s) => s.to_string(),
+        None => value.to_string().trim().to_string(),
+    }
+}
+
+/// Set a config value by key (see [`config_key`] for the key syntax)
+///
+/// `args` is the value, or `+=`/`-=` and a value to add it to or remove it
+/// from an array. Only that value changes in the file; its comments and
+/// formatting are kept.
+pub fn config_set(config_path: Option<&PathBuf>, key: &str, args: &[String]) -> Result<()> {
     let path = config_path
         .cloned()
         .unwrap_or_else(|| config::default_config_dir().join("config.toml"));
 
+    let key_path: KeyPath = key.parse()?;
+    let (op, value) = SetOp::parse(args)?;
+
     // Create default config if it doesn't exist
     if !path.exists() {
         print_info("Creating default configuration...");
         config_init(config_path, false)?;
     }
 
-    let content = std::fs::read_to_string(&path)
-        .with_context(|| format!("Failed to read config file: {:?}", path))?;
-
-    let mut table: toml::Table =
-        toml::from_str(&content).with_context(|| "Failed to parse config file")?;
-
-    // Navigate through the key path and set the value
-    let parts: Vec<&str> = key.split('.').collect();
-
-    if parts.is_empty() {
-        anyhow::bail!("Invalid key");
-    }
-
-    // Navigate/create path to the parent
-    let mut current = &mut table;
-    for part in &parts[..parts.len() - 1] {
-        if !current.contains_key(*part) {
-            current.insert(part.to_string(), toml::Value::Table(toml::Table::new()));
-        }
-        current = current
-            .get_mut(*part)
-            .and_then(|v| v.as_table_mut())
-            .ok_or_else(|| anyhow::anyhow!("Cannot navigate to key: {}", key))?;
-    }
-
-    // Set the value (try to parse as appropriate type)
-    // Safety: We already checked parts.is_empty() above and returned early if true,
-    // so parts.last() is guaranteed to return Some
-    let last_key = parts
-        .last()
-        .ok_or_else(|| anyhow::anyhow!("Invalid key: key path cannot be empty"))?;
-    let toml_value = if value == "true" {
-        toml::Value::Boolean(true)
-    } else if value == "false" {
-        toml::Value::Boolean(false)
-    } else if let Ok(i) = value.parse::<i64>() {
-        toml::Value::Integer(i)
-    } else if let Ok(f) = value.parse::<f64>() {
-        toml::Value::Float(f)
-    } else {
-        toml::Value::String(value.to_string())
-    };
-
-    current.insert(last_key.to_string(), toml_value);
+    let mut doc = read_document(&path)?;
+    config_key::set(&mut doc, &key_path, op, config_key::parse_value(value))?;
 
     // Write back
-    let new_content = toml::to_string_pretty(&table)?;
-    std::fs::write(&path, new_content)
+    std::fs::write(&path, doc.to_string())
         .with_context(|| format!("Failed to write config file: {:?}", path))?;
 
-    print_success(&format!("Set {} = {}", key, value));
+    let message = match op {
+        SetOp::Assign => format!("Set {} = {}", key, value),
+        SetOp::Append => format!("Added {} to {}", value, key),
+        SetOp::Remove => format!("Removed {} from {}", value, key),
+    };
+    print_success(&message);
     Ok(())
 }
 
+/// Read the config file as an editable TOML document
+fn read_document(path: &Path) -> Result<Document> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file: {:?}", path))?;
+    content
+        .parse()
+        .with_context(|| "Failed to parse config file")
+}
+
 /// Show current configuration
+///
+/// Durations the file gives as a bare number of seconds (as older files do)
+/// are shown the way they are written now, e.g. `"30s"`.
 pub fn config_show(config_path: Option<&PathBuf>) -> Result<()> {
     let path = config_path
         .cloned()
@@ -146,13 +113,96 @@ pub fn config_show(config_path: Option<&PathBuf>) -> Result<()> {
     }
 
     print_info(&format!("Configuration file: {:?}", path));
-    println!();
 
     // Read and display the config file
     let content = std::fs::read_to_string(&path)
         .with_context(|| format!("Failed to read config file: {:?}", path))?;
 
-    println!("{}", content);
+    let current = <ConfigFile as VersionedConfig>::VERSION;
+    match toml::from_str(&content).map(|v| config::migration::schema_version(&v)) {
+        Ok(Ok(version)) if version < current => print_info(&format!(
+            "Schema version: {} (upgraded to {} when next loaded)",
+            version, current
+        )),
+        Ok(Ok(version)) => print_info(&format!("Schema version: {}", version)),
+        Ok(Err(e)) => print_warning(&e.to_string()),
+        Err(e) => print_warning(&format!("Failed to parse config file: {}", e)),
+    }
+    println!();
+
+    let shown = match (content.parse::<Document>(), load_file(&path)) {
+        (Ok(mut doc), Ok(effective)) => {
+            humanize_durations(doc.as_table_mut(), &effective);
+            doc.to_string()
+        }
+        _ => content,
+    };
+    println!("{}", shown);
+
+    Ok(())
+}
+
+/// The config in the file at `path` with defaults filled in, as TOML
+fn load_file(path: &Path) -> Result<toml::Value> {
+    let mut loader = ConfigLoader::<ConfigFile>::new();
+    loader.with_file(path)?;
+    Ok(toml::Value::try_from(loader.load()?)?)
+}
+
+/// Rewrite the durations in `table` that are given as a bare number the way
+/// they are written now, keeping comments and spacing
+///
+/// Durations are the integers whose value in `effective` (the loaded
+/// config, which writes durations like `"30s"`) is a string.
+fn humanize_durations(table: &mut dyn TableLike, effective: &toml::Value) {
+    for (key, item) in table.iter_mut() {
+        let Some(effective) = effective.get(key.get()) else {
+            continue;
+        };
+        if let Some(table) = item.as_table_like_mut() {
+            humanize_durations(table, effective);
+            continue;
+        }
+        let (Some(value), Some(text)) = (item.as_value_mut(), effective.as_str()) else {
+            continue;
+        };
+        if value.is_integer() {
+            let decor = value.decor().clone();
+            *value = Value::from(text);
+            *value.decor_mut() = decor;
+        }
+    }
+}
+
+/// Show every effective config value and the layer it came from
+pub fn config_show_origins(config_path: Option<&PathBuf>) -> Result<()> {
+    let path = config_path
+        .cloned()
+        .unwrap_or_else(|| config::default_config_dir().join("config.toml"));
+
+    let mut loader = ConfigLoader::<ConfigFile>::new();
+    if path.exists() {
+        loader
+            .with_file(&path)
+            .with_context(|| format!("Failed to load config from {:?}", path))?;
+        print_info(&format!("Configuration file: {:?}", path));
+    } else {
+        print_info(&format!("No configuration file at {:?}", path));
+    }
+    let effective = toml::Value::try_from(loader.with_env().load()?)?;
+    println!();
+
+    let values: Vec<(String, String)> = layered::leaves(&effective)
+        .into_iter()
+        .map(|(key, value)| {
+            let line = format!("{} = {}", key, value);
+            (line, loader.sources().get(&key).to_string())
+        })
+        .collect();
+    let width = values.iter().map(|(line, _)| line.len()).max().unwrap_or(0);
+    for (line, source) in values {
+        println!("{:<width$}  # {}", line, source, width = width);
+    }
 
     Ok(())
 }
@@ -236,9 +286,13 @@ pub fn config_edit(config_path: Option<&PathBuf>) -> Result<()> {
 
 /// Generate default configuration content
 fn generate_default_config() -> String {
-    r#"# k-Terminus Configuration
+    format!(
+        r#"# k-Terminus Configuration
 # See https://github.com/your-org/k-terminus for documentation
 
+# Config schema version (upgraded automatically when it changes)
+version = {}
+
 [orchestrator]
 # Address to bind SSH server
 bind_address = "0.0.0.0:2222"
@@ -246,29 +300,65 @@ bind_address = "0.0.0.0:2222"
 # Host key file path (will be generated if missing)
 host_key_path = "~/.config/k-terminus/host_key"
 
-# Heartbeat interval in seconds
-heartbeat_interval = 30
+# Durations are written like "500ms", "30s", "5m" or "1h"
+
+# Heartbeat interval
+heartbeat_interval = "30s"
 
-# Heartbeat timeout in seconds
-heartbeat_timeout = 90
+# Heartbeat timeout
+heartbeat_timeout = "90s"
 
 [orchestrator.backoff]
-# Initial retry delay in seconds
-initial = 1
-# Maximum retry delay in seconds
-max = 60
+# Initial retry delay
+initial = "1s"
+# Maximum retry delay
+max = "1m"
 # Backoff multiplier
 multiplier = 2.0
 # Jitter factor
 jitter = 0.25
 
 # Example machine profiles
-# [[machines]]
+# [orchestrator.machines.dev-server]
 # alias = "dev-server"
 # tags = ["development"]
 # default_shell = "/bin/bash"
-# [machines.env]
+# [orchestrator.machines.dev-server.env]
 # CUSTOM_VAR = "value"
-"#
-    .to_string()
+"#,
+        <ConfigFile as VersionedConfig>::VERSION
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_humanize_durations() {
+        let file = r#"version = 2
+
+[orchestrator]
+ipc_port = 22230
+heartbeat_interval = 30 # seconds
+resize_debounce = 500
+
+[orchestrator.memory_limit]
+check_interval = 120
+"#;
+        let mut doc: Document = file.parse().unwrap();
+        let config: ConfigFile = toml::from_str(file).unwrap();
+        let effective = toml::Value::try_from(config).unwrap();
+
+        humanize_durations(doc.as_table_mut(), &effective);
+        let shown = doc.to_string();
+        assert!(shown.contains("ipc_port = 22230\n"), "{}", shown);
+        assert!(
+            shown.contains("heartbeat_interval = \"30s\" # seconds\n"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("resize_debounce = \"500ms\"\n"), "{}", shown);
+        assert!(shown.contains("check_interval = \"2m\"\n"), "{}", shown);
+    }
 }
@@ -0,0 +1,447 @@
+//! Key paths into the config file, for `config get` and `config set`
+//!
+//! A key names a value by its sections and key, separated by dots, e.g.
+//! `orchestrator.backoff.max`. Keys that don't start with a top-level key of
+//! the file (`version`, `orchestrator` or `agent`) are relative to
+//! `[orchestrator]`, so `backoff.max` names the same value. Keys containing
+//! dots are quoted (`machines."gpu.lab".alias`), array elements are picked
+//! with `[N]` (`webhook[0].url`), and a trailing `[]` names the array itself
+//! for appending (`groups.lab[]`).
+//!
+//! The file is edited with `toml_edit`, so its comments, key order and
+//! formatting are kept; only the value that is set changes.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use toml_edit::{Array, Document, InlineTable, Item, Table, Value};
+
+/// Top-level keys of the config file; other keys are relative to
+/// [`DEFAULT_SECTION`]
+const ROOT_KEYS: [&str; 3] = ["version", "orchestrator", "agent"];
+
+/// Section keys are relative to unless they start with a [`ROOT_KEYS`] entry
+const DEFAULT_SECTION: &str = "orchestrator";
+
+/// One step of a [`KeyPath`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Key of a table
+    Key(String),
+    /// Element of an array
+    Index(usize),
+}
+
+/// Parsed config key, e.g. `orchestrator.webhook[0].url`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPath {
+    segments: Vec<Segment>,
+    /// Ends in `[]`: the array itself, to append to
+    append: bool,
+}
+
+impl FromStr for KeyPath {
+    type Err = anyhow::Error;
+
+    fn from_str(key: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut append = false;
+        let mut chars = key.chars().peekable();
+        let mut expect_key = true;
+
+        while let Some(&c) = chars.peek() {
+            if append {
+                bail!("Invalid key {:?}: `[]` must come last", key);
+            }
+            match c {
+                '.' if !expect_key => {
+                    chars.next();
+                    expect_key = true;
+                }
+                '[' if !expect_key => {
+                    chars.next();
+                    let index: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                    if index.is_empty() {
+                        append = true;
+                    } else {
+                        let index = index.parse().with_context(|| {
+                            format!("Invalid index [{}] in key {:?}", index, key)
+                        })?;
+                        segments.push(Segment::Index(index));
+                    }
+                }
+                '"' if expect_key => {
+                    chars.next();
+                    let name: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                    segments.push(Segment::Key(name));
+                    expect_key = false;
+                }
+                _ if expect_key => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if matches!(c, '.' | '[') {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    if name.is_empty() {
+                        bail!("Invalid key {:?}: empty key", key);
+                    }
+                    segments.push(Segment::Key(name));
+                    expect_key = false;
+                }
+                _ => bail!("Invalid key {:?}: unexpected {:?}", key, c),
+            }
+        }
+        if expect_key {
+            bail!("Invalid key {:?}: empty key", key);
+        }
+
+        if let Some(Segment::Key(first)) = segments.first() {
+            if !ROOT_KEYS.contains(&first.as_str()) {
+                segments.insert(0, Segment::Key(DEFAULT_SECTION.to_string()));
+            }
+        }
+        Ok(Self { segments, append })
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.segments.iter().enumerate() {
+            match segment {
+                Segment::Key(key) => {
+                    if i > 0 {
+                        write!(f, ".")?;
+                    }
+                    if key.contains('.') {
+                        write!(f, "\"{}\"", key)?;
+                    } else {
+                        write!(f, "{}", key)?;
+                    }
+                }
+                Segment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        if self.append {
+            write!(f, "[]")?;
+        }
+        Ok(())
+    }
+}
+
+/// How `config set` changes the value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    /// Replace the value (`KEY VALUE`)
+    Assign,
+    /// Add to an array (`KEY += VALUE` or `KEY[] VALUE`)
+    Append,
+    /// Remove from an array (`KEY -= VALUE`)
+    Remove,
+}
+
+impl SetOp {
+    /// Parse the arguments after the key: `VALUE`, `+= VALUE` or `-= VALUE`
+    pub fn parse(args: &[String]) -> Result<(Self, &str)> {
+        match args {
+            [value] => Ok((SetOp::Assign, value)),
+            [op, value] if op == "+=" => Ok((SetOp::Append, value)),
+            [op, value] if op == "-=" => Ok((SetOp::Remove, value)),
+            _ => bail!("Expected VALUE, += VALUE or -= VALUE"),
+        }
+    }
+}
+
+/// Parse a value given on the command line
+///
+/// Anything that is a TOML value (`60`, `true`, `2.5`, `["a", "b"]`) is
+/// taken as one; everything else is a string, so paths and durations like
+/// `30s` need no quotes.
+pub fn parse_value(value: &str) -> Value {
+    match value.parse::<Value>() {
+        Ok(mut parsed) => {
+            parsed.decor_mut().clear();
+            parsed
+        }
+        Err(_) => Value::from(value),
+    }
+}
+
+/// The item at `path` in `doc`, if there is one
+pub fn get<'a>(doc: &'a Document, path: &KeyPath) -> Option<&'a Item> {
+    let mut current = doc.as_item();
+    for segment in &path.segments {
+        current = match segment {
+            Segment::Key(key) => current.get(key.as_str())?,
+            Segment::Index(index) => current.get(*index)?,
+        };
+    }
+    Some(current).filter(|item| !item.is_none())
+}
+
+/// Change the value at `path` in `doc`
+///
+/// Missing sections are created for [`SetOp::Assign`] and
+/// [`SetOp::Append`], and appending to a missing key creates the array.
+/// Appending a value the array already has leaves it alone.
+pub fn set(doc: &mut Document, path: &KeyPath, op: SetOp, value: Value) -> Result<()> {
+    let op = match op {
+        SetOp::Assign if path.append => SetOp::Append,
+        op => op,
+    };
+    let Some((last, parents)) = path.segments.split_last() else {
+        bail!("Invalid key: key path cannot be empty");
+    };
+    if op == SetOp::Remove && get(doc, path).is_none() {
+        bail!("Key not found: {}", path);
+    }
+
+    let mut current = doc.as_item_mut();
+    let mut inline = false;
+    for segment in parents {
+        current = child_mut(current, segment, inline, path)?;
+        inline |= current.is_value();
+    }
+    let slot = child_mut(current, last, inline, path)?;
+
+    match op {
+        SetOp::Assign => assign(slot, value),
+        SetOp::Append => {
+            if slot.is_none() {
+                *slot = Item::Value(Value::Array(Array::new()));
+            }
+            let Some(array) = slot.as_array_mut() else {
+                bail!("{} is not an array", path);
+            };
+            if !array.iter().any(|item| same_value(item, &value)) {
+                array.push(value);
+            }
+        }
+        SetOp::Remove => {
+            let Some(array) = slot.as_array_mut() else {
+                bail!("{} is not an array", path);
+            };
+            let len = array.len();
+            let first_prefix = array
+                .get(0)
+                .and_then(|first| first.decor().prefix().cloned());
+            array.retain(|item| !same_value(item, &value));
+            if array.len() == len {
+                bail!("{} has no element {}", path, value);
+            }
+            // The new first element takes the old one's place in the layout
+            if let (Some(first), Some(prefix)) = (array.get_mut(0), first_prefix) {
+                first.decor_mut().set_prefix(prefix);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The child `segment` of `item`, creating `item` as a table if it is
+/// missing (an inline one if it is `inline`, i.e. inside a value)
+fn child_mut<'a>(
+    item: &'a mut Item,
+    segment: &Segment,
+    inline: bool,
+    path: &KeyPath,
+) -> Result<&'a mut Item> {
+    match segment {
+        Segment::Key(key) => {
+            if item.is_none() {
+                *item = if inline {
+                    Item::Value(Value::InlineTable(InlineTable::new()))
+                } else {
+                    let mut table = Table::new();
+                    table.set_implicit(true);
+                    Item::Table(table)
+                };
+            }
+            if !item.is_table_like() {
+                bail!("Cannot set {}: {:?} is not in a table", path, key);
+            }
+            item.get_mut(key.as_str())
+                .with_context(|| format!("Cannot navigate to key: {}", path))
+        }
+        Segment::Index(index) => item
+            .get_mut(*index)
+            .with_context(|| format!("Index {} out of range in {}", index, path)),
+    }
+}
+
+/// Assign `value` to `slot`, keeping the comment and spacing around the
+/// value it replaces
+fn assign(slot: &mut Item, mut value: Value) {
+    if let Some(old) = slot.as_value() {
+        *value.decor_mut() = old.decor().clone();
+    }
+    *slot = Item::Value(value);
+}
+
+/// Whether `a` and `b` are the same value, ignoring how they are written
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a.as_str(), b.as_str()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a.to_string().trim() == b.to_string().trim(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"# k-Terminus Configuration
+version = 2
+
+[orchestrator]
+# Address to bind SSH server
+bind_address = "0.0.0.0:2222"
+heartbeat_interval = 30 # seconds
+
+[orchestrator.backoff]
+# Maximum retry delay in seconds
+max = 60
+
+[orchestrator.groups]
+lab = ["gpu-1", "gpu-2"] # the lab machines
+
+[orchestrator.machines."gpu.lab"]
+alias = "gpu"
+
+[[orchestrator.webhook]]
+url = "https://example.com/hook"
+"#;
+
+    fn doc() -> Document {
+        CONFIG.parse().unwrap()
+    }
+
+    fn key(key: &str) -> KeyPath {
+        key.parse().unwrap()
+    }
+
+    fn set_value(doc: &mut Document, path: &str, op: SetOp, value: &str) -> Result<()> {
+        set(doc, &key(path), op, parse_value(value))
+    }
+
+    #[test]
+    fn test_key_paths() {
+        assert_eq!(key("backoff.max"), key("orchestrator.backoff.max"));
+        assert_eq!(key("version").to_string(), "version");
+        assert_eq!(
+            key("machines.\"gpu.lab\".alias").to_string(),
+            "orchestrator.machines.\"gpu.lab\".alias"
+        );
+        assert_eq!(
+            key("webhook[0].url").to_string(),
+            "orchestrator.webhook[0].url"
+        );
+        assert_eq!(key("groups.lab[]").to_string(), "orchestrator.groups.lab[]");
+
+        for invalid in ["", "a..b", "a.", "a[x]", "a[].b", ".a"] {
+            assert!(invalid.parse::<KeyPath>().is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_get_nested_and_array_keys() {
+        let doc = doc();
+        let get_str = |path: &str| get(&doc, &key(path)).and_then(|item| item.as_str());
+
+        assert_eq!(get_str("bind_address"), Some("0.0.0.0:2222"));
+        assert_eq!(get_str("machines.\"gpu.lab\".alias"), Some("gpu"));
+        assert_eq!(get_str("groups.lab[1]"), Some("gpu-2"));
+        assert_eq!(get_str("webhook[0].url"), Some("https://example.com/hook"));
+        assert_eq!(
+            get(&doc, &key("backoff.max")).and_then(|i| i.as_integer()),
+            Some(60)
+        );
+        assert!(get(&doc, &key("backoff.missing")).is_none());
+        assert!(get(&doc, &key("groups.lab[5]")).is_none());
+    }
+
+    #[test]
+    fn test_set_keeps_comments_and_formatting() {
+        let mut doc = doc();
+        set_value(&mut doc, "heartbeat_interval", SetOp::Assign, "45").unwrap();
+        set_value(&mut doc, "backoff.max", SetOp::Assign, "120").unwrap();
+
+        let expected = CONFIG
+            .replace(
+                "heartbeat_interval = 30 # seconds",
+                "heartbeat_interval = 45 # seconds",
+            )
+            .replace("max = 60", "max = 120");
+        assert_eq!(doc.to_string(), expected);
+    }
+
+    #[test]
+    fn test_set_creates_missing_sections() {
+        let mut doc = doc();
+        set_value(&mut doc, "log_rotation.keep", SetOp::Assign, "5").unwrap();
+        set_value(&mut doc, "tailnet_domain", SetOp::Assign, "vpn.example.com").unwrap();
+
+        let text = doc.to_string();
+        assert!(text.contains("tailnet_domain = \"vpn.example.com\""));
+        assert!(text.contains("[orchestrator.log_rotation]\nkeep = 5\n"));
+    }
+
+    #[test]
+    fn test_array_append_and_remove() {
+        let mut doc = doc();
+        set_value(&mut doc, "groups.lab", SetOp::Append, "gpu-3").unwrap();
+        // Already there, so not added twice
+        set_value(&mut doc, "groups.lab[]", SetOp::Assign, "gpu-3").unwrap();
+        set_value(&mut doc, "groups.lab", SetOp::Remove, "gpu-1").unwrap();
+        assert!(doc
+            .to_string()
+            .contains("lab = [\"gpu-2\", \"gpu-3\"] # the lab machines"));
+
+        // Appending to a missing key creates the array
+        set_value(&mut doc, "groups.build", SetOp::Append, "ci-1").unwrap();
+        assert!(doc.to_string().contains("build = [\"ci-1\"]"));
+
+        assert!(set_value(&mut doc, "groups.lab", SetOp::Remove, "gpu-9").is_err());
+        assert!(set_value(&mut doc, "groups.none", SetOp::Remove, "gpu-1").is_err());
+        assert!(set_value(&mut doc, "bind_address", SetOp::Append, "x").is_err());
+    }
+
+    #[test]
+    fn test_set_array_element_and_table_array() {
+        let mut doc = doc();
+        set_value(&mut doc, "groups.lab[0]", SetOp::Assign, "gpu-0").unwrap();
+        let url = "https://example.com/other";
+        set_value(&mut doc, "webhook[0].url", SetOp::Assign, url).unwrap();
+
+        let text = doc.to_string();
+        assert!(text.contains("lab = [\"gpu-0\", \"gpu-2\"]"));
+        assert!(text.contains("[[orchestrator.webhook]]\nurl = \"https://example.com/other\""));
+        assert!(set_value(&mut doc, "groups.lab[7]", SetOp::Assign, "x").is_err());
+    }
+
+    #[test]
+    fn test_parse_value_and_set_args() {
+        assert_eq!(parse_value("60").as_integer(), Some(60));
+        assert_eq!(parse_value("true").as_bool(), Some(true));
+        assert_eq!(parse_value("30s").as_str(), Some("30s"));
+        assert_eq!(parse_value("/bin/zsh").as_str(), Some("/bin/zsh"));
+        assert_eq!(
+            parse_value("[\"a\", \"b\"]").as_array().map(Array::len),
+            Some(2)
+        );
+
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let set_args = args(&["+=", "/bin/zsh"]);
+        assert_eq!(
+            SetOp::parse(&set_args).unwrap(),
+            (SetOp::Append, "/bin/zsh")
+        );
+        let set_args = args(&["-=", "x"]);
+        assert_eq!(SetOp::parse(&set_args).unwrap(), (SetOp::Remove, "x"));
+        assert_eq!(SetOp::parse(&args(&["60"])).unwrap(), (SetOp::Assign, "60"));
+        assert!(SetOp::parse(&args(&["*=", "x"])).is_err());
+    }
+}
@@ -1,23 +1,79 @@
 //! Connect command implementation
 
+use std::io::IsTerminal;
+use std::path::Path;
+
 use anyhow::Result;
 
-use crate::ipc::{OrchestratorClient, TerminalSession};
-use crate::output::{print_error, print_info, print_success};
+use super::select::resolve_session_args;
+use crate::ipc::{OrchestratorClient, SessionEnd, SessionLifetime, SessionLog, TerminalSession};
+use crate::output::{format_session_end, print_error, print_info, print_success};
 
 /// Execute the connect command - create new session and attach
+///
+/// When stdin isn't a terminal (`echo data | k-terminus connect box`), it is
+/// piped into the session instead: see [`TerminalSession::run_piped`].
+/// `close_on_eof` closes the session when the piped input ends. Without
+/// `allocate_pty` the shell runs on plain pipes, reading stdin until it ends:
+/// see [`TerminalSession::run_without_pty`]. `log_level` sets the agent's
+/// log level for this session only. `command` is typed into the new shell,
+/// followed by a newline, before handing the terminal to the user; it needs
+/// an interactive terminal. `multiplexer` does the same with the command
+/// attaching to a tmux or screen session (see [`Multiplexer`]). `lifetime` sets a TTL or keeps the session
+/// after its shell exits. Output received is also appended to `log`, if
+/// given.
+///
+/// Returns the exit code the CLI should exit with: the remote shell's exit
+/// code if the session ended, or 0 if the user detached or piped input ended.
+#[allow(clippy::too_many_arguments)]
 pub async fn connect_command(
     client: OrchestratorClient,
     machine: &str,
     shell: Option<&str>,
-) -> Result<()> {
+    close_on_eof: bool,
+    allocate_pty: bool,
+    log_level: Option<&str>,
+    command: Option<&str>,
+    multiplexer: Option<&Multiplexer>,
+    lifetime: SessionLifetime,
+    log: Option<SessionLog>,
+) -> Result<i32> {
     // Need a mutable client for the initial request
     let mut client = client;
 
-    print_info(&format!("Creating session on '{}'...", machine));
+    // Stdout may be carrying the session's output, so stay quiet when piped
+    // or capturing output without a PTY
+    let piped = !allocate_pty || !std::io::stdin().is_terminal();
+    if command.is_some() && piped {
+        anyhow::bail!(
+            "--command needs an interactive terminal; pipe the command into stdin instead"
+        );
+    }
+    if let (Some(multiplexer), true) = (multiplexer, piped) {
+        anyhow::bail!("--{} needs an interactive terminal", multiplexer.program());
+    }
+    let attach = multiplexer.map(Multiplexer::command);
+    let command = command.or(attach.as_deref());
+    if !piped {
+        print_info(&format!("Creating session on '{}'...", machine));
+    }
 
     // Create session
-    let session = match client.create_session(machine, shell).await {
+    let created = match log_level {
+        _ if lifetime != SessionLifetime::default() => {
+            client
+                .create_session_with_lifetime(machine, shell, allocate_pty, log_level, lifetime)
+                .await
+        }
+        Some(level) => {
+            client
+                .create_session_with_log_level(machine, shell, allocate_pty, level)
+                .await
+        }
+        None if allocate_pty => client.create_session(machine, shell).await,
+        None => client.create_pipe_session(machine, shell).await,
+    };
+    let session = match created {
         Ok(s) => s,
         Err(e) => {
             print_error(&format!("Failed to create session: {}", e));
@@ -25,6 +81,16 @@ pub async fn connect_command(
         }
     };
 
+    let terminal = with_log(TerminalSession::new(client, session.id.clone()).await?, log);
+    if !allocate_pty {
+        let end = terminal.run_without_pty().await?;
+        return report_piped_session_end(end);
+    }
+    if piped {
+        let end = terminal.run_piped(close_on_eof).await?;
+        return report_piped_session_end(end);
+    }
+
     print_success(&format!(
         "Session created: {} (PID: {})",
         session.id,
@@ -34,26 +100,221 @@ pub async fn connect_command(
             .unwrap_or_else(|| "pending".to_string())
     ));
 
+    // Type the initial command once the PTY is up, byte for byte
+    let terminal = match command {
+        Some(command) => {
+            let mut input = command.as_bytes().to_vec();
+            input.push(b'\n');
+            terminal.with_initial_input(input, session.pid.is_some())
+        }
+        None => terminal,
+    };
+
     // Attach to the session
     print_info("Attaching to session... (Press Ctrl+] to detach)");
+    let end = terminal.run().await?;
 
-    // Create terminal session and run it
-    let terminal = TerminalSession::new(client, session.id.clone()).await?;
-    terminal.run().await?;
-
-    print_success("Detached from session");
-    Ok(())
+    if let (Some(multiplexer), SessionEnd::Exited { exit_code, .. }) = (multiplexer, &end) {
+        if *exit_code == Some(MISSING_MULTIPLEXER) {
+            print_error(&format!(
+                "{} isn't installed on '{}'; install it there or connect without --{}",
+                multiplexer.program(),
+                machine,
+                multiplexer.program()
+            ));
+            return Ok(MISSING_MULTIPLEXER);
+        }
+    }
+    report_session_end(end)
 }
 
 /// Attach to an existing session
-pub async fn attach_command(client: OrchestratorClient, session_id: &str) -> Result<()> {
-    print_info(&format!("Attaching to session {}...", session_id));
-    print_info("Press Ctrl+] to detach");
+///
+/// `session` is a session ID or a machine selector such as `nas:last`.
+/// With `mirror`, it's watched without taking its input (see
+/// [`TerminalSession::mirror`]). Returns the exit code the CLI should exit
+/// with, as for `connect_command`.
+pub async fn attach_command(
+    mut client: OrchestratorClient,
+    session: &str,
+    log: Option<SessionLog>,
+    mirror: bool,
+) -> Result<i32> {
+    let session_id = resolve_session_args(&mut client, &[session.to_string()])
+        .await?
+        .remove(0);
+    if mirror {
+        print_info(&format!("Mirroring session {}...", session_id));
+        print_info("Press Ctrl+T to take the input, Ctrl+] to detach");
+    } else {
+        print_info(&format!("Attaching to session {}...", session_id));
+        print_info("Press Ctrl+] to detach");
+    }
 
     // Create terminal session and run it
-    let terminal = TerminalSession::new(client, session_id.to_string()).await?;
-    terminal.run().await?;
+    let terminal = if mirror {
+        TerminalSession::mirror(client, session_id).await?
+    } else {
+        TerminalSession::new(client, session_id).await?
+    };
+    let terminal = with_log(terminal, log);
+    let end = terminal.run().await?;
+
+    report_session_end(end)
+}
+
+/// Open the log for `--log`, if given, before attaching so a bad path fails
+/// early
+///
+/// The log keeps escape sequences as received unless `strip_ansi` is set.
+pub fn open_session_log(
+    path: Option<&Path>,
+    timestamps: bool,
+    strip_ansi: bool,
+) -> Result<Option<SessionLog>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let mut log = SessionLog::open(path)?;
+    if timestamps {
+        log = log.with_timestamps();
+    }
+    if strip_ansi {
+        log = log.with_ansi_stripped();
+    }
+    Ok(Some(log))
+}
+
+fn with_log(terminal: TerminalSession, log: Option<SessionLog>) -> TerminalSession {
+    match log {
+        Some(log) => terminal.with_log(log),
+        None => terminal,
+    }
+}
+
+/// Tell the user why the terminal session ended and pick the exit code
+fn report_session_end(end: SessionEnd) -> Result<i32> {
+    match end {
+        SessionEnd::Detached => {
+            print_success("Detached from session");
+            Ok(0)
+        }
+        SessionEnd::Exited { exit_code, reason } => {
+            print_info(&format_session_end(exit_code, reason.as_ref()));
+            Ok(exit_code.unwrap_or(0))
+        }
+        SessionEnd::ConnectionLost => {
+            print_error("Connection to orchestrator lost");
+            anyhow::bail!("Connection to orchestrator lost while attached to session")
+        }
+        SessionEnd::InputEnded => Ok(0),
+    }
+}
+
+/// Pick the exit code for a piped session, reporting only failures (on
+/// stderr) so they don't mix with the session's output
+fn report_piped_session_end(end: SessionEnd) -> Result<i32> {
+    match end {
+        SessionEnd::Exited { exit_code, .. } => Ok(exit_code.unwrap_or(0)),
+        SessionEnd::ConnectionLost => {
+            anyhow::bail!("Connection to orchestrator lost while piping into session")
+        }
+        SessionEnd::Detached | SessionEnd::InputEnded => Ok(0),
+    }
+}
+
+/// Default session name for `--tmux` and `--screen` without one
+pub const DEFAULT_MULTIPLEXER_SESSION: &str = "k-terminus";
+
+/// Exit code of the remote shell when the multiplexer isn't installed, as
+/// for a command not found
+const MISSING_MULTIPLEXER: i32 = 127;
+
+/// A terminal multiplexer session to attach to, or create, on the machine
+///
+/// Reconnecting with the same name lands in the same session, so work
+/// survives both detaching and losing the connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Multiplexer {
+    /// `tmux new -A -s <name>`: attach, or create the session
+    Tmux(String),
+    /// `screen -D -RR <name>`: attach, detaching it elsewhere first, or
+    /// create the session
+    Screen(String),
+}
+
+impl Multiplexer {
+    /// Name of the multiplexer's program
+    pub fn program(&self) -> &'static str {
+        match self {
+            Multiplexer::Tmux(_) => "tmux",
+            Multiplexer::Screen(_) => "screen",
+        }
+    }
+
+    /// Command typed into the new shell to replace it with the multiplexer
+    ///
+    /// It runs under `sh` so it works whatever the login shell is, and exits
+    /// with [`MISSING_MULTIPLEXER`] after saying so if the program isn't
+    /// installed, which ends the session.
+    fn command(&self) -> String {
+        let attach = match self {
+            Multiplexer::Tmux(name) => format!("tmux new -A -s {}", name),
+            Multiplexer::Screen(name) => format!("screen -D -RR {}", name),
+        };
+        format!(
+            "exec sh -c 'command -v {program} >/dev/null 2>&1 || {{ echo \"k-terminus: {program} is not installed on this machine\" >&2; exit {code}; }}; exec {attach}'",
+            program = self.program(),
+            code = MISSING_MULTIPLEXER,
+            attach = attach
+        )
+    }
+}
+
+/// Parse a tmux or screen session name
+///
+/// Only letters, digits, `-` and `_` are allowed, not leading `-`, so the
+/// name needs no quoting in the remote shell and means the same to both
+/// programs (tmux replaces `.` and `:`).
+pub fn parse_multiplexer_session(name: &str) -> std::result::Result<String, String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(format!(
+            "invalid session name '{}': use letters, digits, '-' and '_'",
+            name
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multiplexer_command() {
+        let tmux = Multiplexer::Tmux("work".to_string()).command();
+        assert!(tmux.starts_with("exec sh -c 'command -v tmux "));
+        assert!(tmux.contains("exit 127;"));
+        assert!(tmux.ends_with("; exec tmux new -A -s work'"));
+
+        let screen = Multiplexer::Screen(DEFAULT_MULTIPLEXER_SESSION.to_string()).command();
+        assert!(screen.contains("screen is not installed"));
+        assert!(screen.ends_with("; exec screen -D -RR k-terminus'"));
 
-    print_success("Detached from session");
-    Ok(())
+        assert_eq!(
+            parse_multiplexer_session("build_2-a"),
+            Ok("build_2-a".to_string())
+        );
+        assert!(parse_multiplexer_session("").is_err());
+        assert!(parse_multiplexer_session("-x").is_err());
+        assert!(parse_multiplexer_session("a b").is_err());
+        assert!(parse_multiplexer_session("it's").is_err());
+        assert!(parse_multiplexer_session("a.b").is_err());
+    }
 }
@@ -0,0 +1,149 @@
+//! Doctor command implementation
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use crate::ipc::OrchestratorClient;
+use crate::output::{print_error, print_info, print_success, print_warning};
+use kt_core::config::{self, AgentConfig, ConfigFile};
+use kt_core::ipc::RejectionInfo;
+use kt_core::permissions::{self, Repair};
+use kt_core::pidfile::{clean_stale_files, find_stale_files, StartupLock};
+
+/// Check the local installation for problems, repairing what it can
+///
+/// Checks that the config directory, IPC token, host key and agent key
+/// aren't accessible by other users, tightening their permissions if so.
+/// Also looks for the token and PID files of an orchestrator that is no
+/// longer running, which are only removed with `fix`, and lists agents a
+/// running orchestrator recently turned away.
+pub async fn doctor_command(
+    client: &mut OrchestratorClient,
+    config_path: Option<&PathBuf>,
+    fix: bool,
+) -> Result<()> {
+    let unresolved = check_permissions(config_path) + check_stale_files(fix)?;
+    check_rejections(client).await;
+    if unresolved > 0 {
+        anyhow::bail!("{} problem(s) need fixing", unresolved);
+    }
+    Ok(())
+}
+
+/// Check and tighten file permissions, returning the problems left
+fn check_permissions(config_path: Option<&PathBuf>) -> usize {
+    let host_key_path = load_or_default::<ConfigFile>(
+        config_path
+            .cloned()
+            .unwrap_or_else(config::default_config_path),
+    )
+    .orchestrator
+    .host_key_path;
+    let agent_key_path =
+        load_or_default::<AgentConfig>(config::default_config_dir().join("agent.toml"))
+            .private_key_path;
+
+    print_info("Checking file permissions...");
+    let findings = permissions::check_permissions(&[&host_key_path, &agent_key_path]);
+    if findings.is_empty() {
+        print_success("File permissions OK");
+        return 0;
+    }
+
+    let mut unresolved = 0;
+    for finding in &findings {
+        match finding.repair {
+            Repair::Fixed => print_warning(&finding.to_string()),
+            Repair::Failed(_) | Repair::Manual => {
+                unresolved += 1;
+                print_error(&finding.to_string());
+            }
+        }
+    }
+
+    if unresolved > 0 {
+        print_error(&format!(
+            "{} permission problem(s) need fixing by hand",
+            unresolved
+        ));
+    } else {
+        print_success("Fixed file permissions");
+    }
+    unresolved
+}
+
+/// Look for files left by a crashed orchestrator, removing them with `fix`
+///
+/// Returns the number of stale files left behind.
+fn check_stale_files(fix: bool) -> Result<usize> {
+    print_info("Checking for stale orchestrator files...");
+    let stale = find_stale_files()?;
+    if stale.is_empty() {
+        print_success("No stale orchestrator files");
+        return Ok(0);
+    }
+
+    if !fix {
+        for file in &stale {
+            print_warning(&format!("Stale {}", file));
+        }
+        print_info("Run 'k-terminus doctor --fix' to remove them");
+        return Ok(stale.len());
+    }
+
+    // Checked again under the lock: an orchestrator may have started since
+    let lock = StartupLock::acquire_default()?;
+    for file in clean_stale_files(&lock)? {
+        print_warning(&format!("Removed stale {}", file));
+    }
+    print_success("Removed stale orchestrator files");
+    Ok(0)
+}
+
+/// List agents the orchestrator rejected, if one is running
+///
+/// These are problems with the agents rather than this installation, so
+/// they aren't counted as unresolved.
+async fn check_rejections(client: &mut OrchestratorClient) {
+    print_info("Checking for rejected agents...");
+    if !client.ping().await.unwrap_or(false) {
+        print_info("Orchestrator not running, skipped");
+        return;
+    }
+    match client.list_rejections().await {
+        Ok(rejections) if rejections.is_empty() => print_success("No agents rejected"),
+        Ok(rejections) => {
+            for rejection in &rejections {
+                print_warning(&format_rejection(rejection));
+            }
+        }
+        Err(e) => print_warning(&format!("Failed to list rejected agents: {}", e)),
+    }
+}
+
+fn format_rejection(rejection: &RejectionInfo) -> String {
+    let agent = match &rejection.hostname {
+        Some(hostname) => format!("{} ({})", hostname, rejection.peer_addr),
+        None => rejection.peer_addr.clone(),
+    };
+    let times = match rejection.count {
+        1 => String::new(),
+        count => format!(", {} times since {}", count, rejection.first_seen),
+    };
+    format!(
+        "Rejected agent {}: {} (last at {}{})",
+        agent, rejection.reason, rejection.last_seen, times
+    )
+}
+
+/// Load a config file, falling back to defaults if it's missing or invalid
+fn load_or_default<T: config::VersionedConfig + Default>(path: PathBuf) -> T {
+    if !path.exists() {
+        return T::default();
+    }
+    config::load_config(&path).unwrap_or_else(|e| {
+        print_warning(&format!("Failed to load config from {:?}: {}", path, e));
+        T::default()
+    })
+}
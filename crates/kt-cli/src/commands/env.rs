@@ -0,0 +1,29 @@
+//! Env command implementation
+
+use anyhow::Result;
+
+use crate::ipc::OrchestratorClient;
+use crate::output::{format_session_env, print_error, print_info};
+
+/// Execute the env command - show the environment a session was created with
+pub async fn env_command(
+    client: &mut OrchestratorClient,
+    session_id: &str,
+    show_secrets: bool,
+) -> Result<()> {
+    let env = match client.get_session_env(session_id, show_secrets).await {
+        Ok(env) => env,
+        Err(e) => {
+            print_error(&format!("Failed to get session environment: {}", e));
+            return Err(e);
+        }
+    };
+
+    println!("{}", format_session_env(&env));
+
+    if env.iter().any(|var| var.redacted) {
+        print_info("Some values were redacted. Use --show-secrets to reveal them.");
+    }
+
+    Ok(())
+}
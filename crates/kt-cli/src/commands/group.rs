@@ -0,0 +1,50 @@
+//! Group command implementation
+
+use anyhow::Result;
+
+use crate::ipc::{GroupAction, OrchestratorClient};
+use crate::output::{format_groups, print_success, print_warning};
+
+/// Execute `group list`
+pub async fn group_list_command(client: &mut OrchestratorClient) -> Result<()> {
+    let groups = client.list_groups().await?;
+    println!("{}", format_groups(&groups));
+    Ok(())
+}
+
+/// Execute `group create`, `group add`, `group remove` or `group delete`
+///
+/// Members that match no known machine are accepted with a warning, since
+/// a group may name machines that haven't joined yet.
+pub async fn group_modify_command(
+    client: &mut OrchestratorClient,
+    group: &str,
+    action: GroupAction,
+    members: &[String],
+) -> Result<()> {
+    let changed = client.modify_group(group, action, members).await?;
+    let Some(info) = changed else {
+        print_success(&format!("Deleted group '@{}'", group.trim_start_matches('@')));
+        return Ok(());
+    };
+
+    let verb = match action {
+        GroupAction::Create => "Created",
+        GroupAction::Delete | GroupAction::Add | GroupAction::Remove => "Updated",
+    };
+    print_success(&format!("{} group '@{}'", verb, info.name));
+    println!("{}", format_groups(std::slice::from_ref(&info)));
+
+    let unknown: Vec<_> = members
+        .iter()
+        .filter(|m| info.unknown_members.iter().any(|u| u.eq_ignore_ascii_case(m)))
+        .map(String::as_str)
+        .collect();
+    if action != GroupAction::Remove && !unknown.is_empty() {
+        print_warning(&format!(
+            "No known machine named {}; it will match once it joins",
+            unknown.join(", ")
+        ));
+    }
+    Ok(())
+}
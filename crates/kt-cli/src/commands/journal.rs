@@ -0,0 +1,38 @@
+//! Journal command implementation
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use kt_orchestrator::journal::{format_journal_entry, parse_journal_line};
+
+use crate::output::print_warning;
+
+/// Execute `journal replay` - print an event journal one event per line
+///
+/// Lines that aren't journal entries, such as one cut short when the
+/// orchestrator died mid-write, are skipped with a warning.
+pub fn journal_replay_command(path: &Path) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+
+    let mut skipped = 0;
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_journal_line(&line) {
+            Some(entry) => println!("{}", format_journal_entry(&entry)),
+            None => {
+                print_warning(&format!("Line {} is not a journal entry", number + 1));
+                skipped += 1;
+            }
+        }
+    }
+    if skipped > 0 {
+        print_warning(&format!("Skipped {} unreadable lines", skipped));
+    }
+
+    Ok(())
+}
@@ -2,10 +2,16 @@
 
 use anyhow::Result;
 
-use crate::ipc::OrchestratorClient;
-use crate::output::{print_error, print_success, print_warning};
+use super::select::resolve_session_args;
+use crate::ipc::{OrchestratorClient, SessionInfo};
+use crate::output::{format_kill_targets, print_error, print_success, print_warning};
 
 /// Execute the kill command
+///
+/// Sessions can be given as IDs or as machine selectors (`gpu-box:last`,
+/// see [`resolve_session_args`]). Without `force`, the target sessions are looked up first and shown for
+/// confirmation. Session IDs that don't resolve are reported, and the user is
+/// asked whether to go ahead with the rest.
 pub async fn kill_command(
     client: &mut OrchestratorClient,
     sessions: &[String],
@@ -16,35 +22,47 @@ pub async fn kill_command(
         return Ok(());
     }
 
-    if !force && sessions.len() > 1 {
-        print_warning(&format!(
-            "About to kill {} sessions. Use --force to skip confirmation.",
-            sessions.len()
-        ));
+    let sessions = resolve_session_args(client, sessions).await?;
+    let mut errors = Vec::new();
+
+    let targets: Vec<String> = if force {
+        sessions
+    } else {
+        let known = client.list_sessions(None).await?;
+        let (found, missing) = resolve_sessions(&sessions, &known);
 
-        // In a full implementation, prompt for confirmation
-        print!("Continue? [y/N] ");
-        std::io::Write::flush(&mut std::io::stdout())?;
+        for session_id in &missing {
+            print_error(&format!("Session not found: {}", session_id));
+            errors.push(session_id.clone());
+        }
+        if found.is_empty() {
+            anyhow::bail!("No matching sessions to kill");
+        }
 
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
+        let machines = client.list_machines().await.unwrap_or_default();
+        println!("{}", format_kill_targets(&found, &machines));
 
-        if !input.trim().eq_ignore_ascii_case("y") {
+        let prompt = if missing.is_empty() {
+            format!("Kill {} session(s)?", found.len())
+        } else {
+            format!("Continue and kill the other {} session(s)?", found.len())
+        };
+        if !confirm(&prompt)? {
             print_warning("Aborted");
             return Ok(());
         }
-    }
 
-    let mut errors = Vec::new();
+        found.into_iter().map(|s| s.id).collect()
+    };
 
-    for session_id in sessions {
+    for session_id in &targets {
         match client.kill_session(session_id, force).await {
             Ok(()) => {
                 print_success(&format!("Killed session: {}", session_id));
             }
             Err(e) => {
                 print_error(&format!("Failed to kill session {}: {}", session_id, e));
-                errors.push((session_id.clone(), e));
+                errors.push(session_id.clone());
             }
         }
     }
@@ -55,3 +73,75 @@ pub async fn kill_command(
 
     Ok(())
 }
+
+/// Split the requested session IDs into known sessions and unknown IDs
+///
+/// Sessions are returned in the order they were requested; duplicates are
+/// only killed once.
+fn resolve_sessions(
+    requested: &[String],
+    known: &[SessionInfo],
+) -> (Vec<SessionInfo>, Vec<String>) {
+    let mut found: Vec<SessionInfo> = Vec::new();
+    let mut missing = Vec::new();
+
+    for session_id in requested {
+        if found.iter().any(|s| &s.id == session_id) || missing.contains(session_id) {
+            continue;
+        }
+        match known.iter().find(|s| &s.id == session_id) {
+            Some(session) => found.push(session.clone()),
+            None => missing.push(session_id.clone()),
+        }
+    }
+
+    (found, missing)
+}
+
+/// Ask a yes/no question on stdin, defaulting to no
+fn confirm(prompt: &str) -> Result<bool> {
+    print!("{} [y/N] ", prompt);
+    std::io::Write::flush(&mut std::io::stdout())?;
+
+    let mut input = String::new();
+    std::io::stdin().read_line(&mut input)?;
+
+    Ok(input.trim().eq_ignore_ascii_case("y"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            machine_id: "machine-1".to_string(),
+            shell: None,
+            created_at: "0Z".to_string(),
+            pid: None,
+            size: None,
+            name: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        }
+    }
+
+    #[test]
+    fn test_resolve_sessions_reports_missing_and_dedups() {
+        let known = vec![session("1"), session("2"), session("3")];
+        let requested: Vec<String> = ["3", "9", "1", "3", "9"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let (found, missing) = resolve_sessions(&requested, &known);
+
+        let found: Vec<_> = found.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(found, vec!["3", "1"]);
+        assert_eq!(missing, vec!["9"]);
+    }
+}
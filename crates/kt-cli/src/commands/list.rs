@@ -2,16 +2,117 @@
 
 use anyhow::Result;
 
+use super::select::resolve_machine_arg;
+use crate::exit_code;
 use crate::ipc::OrchestratorClient;
-use crate::output::{format_machines, format_sessions, print_error};
+use crate::output::{
+    format_columns, format_csv, format_machines, format_sessions, parse_column, parse_columns,
+    print_error, sort_rows, Column, MachineColumn, SessionColumn,
+};
+
+/// How `list` prints its table
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListOutput {
+    /// Tables for reading in a terminal
+    #[default]
+    Human,
+    /// CSV of just the table asked for, for spreadsheets (see
+    /// [`format_csv`])
+    Csv,
+}
+
+/// Which table `list` shows and how
+#[derive(Debug, Clone, Default)]
+pub struct ListView {
+    /// Show sessions instead of machines
+    pub sessions: bool,
+    /// Column to sort by
+    pub sort: Option<String>,
+    /// Sort in descending order
+    pub reverse: bool,
+    /// Columns to show, in this order
+    pub columns: Option<Vec<String>>,
+    /// How to print the table
+    pub output: ListOutput,
+}
+
+impl ListView {
+    /// Check the sort and column names against the table's columns
+    pub fn check(&self) -> Result<()> {
+        if self.sessions {
+            TableLayout::<SessionColumn>::parse(self).map(drop)
+        } else {
+            TableLayout::<MachineColumn>::parse(self).map(drop)
+        }
+    }
+}
+
+/// Sort column and columns to show, checked against the table's columns
+struct TableLayout<C> {
+    sort: Option<C>,
+    columns: Option<Vec<C>>,
+}
+
+impl<C: Column> TableLayout<C> {
+    fn parse(view: &ListView) -> Result<Self> {
+        Ok(Self {
+            sort: view.sort.as_deref().map(parse_column).transpose()?,
+            columns: view.columns.as_deref().map(parse_columns).transpose()?,
+        })
+    }
+
+    /// `rows` as CSV, in the columns asked for or those the table would
+    /// show
+    fn csv(&self, rows: &[C::Row], detailed: bool) -> String {
+        let columns = match &self.columns {
+            Some(columns) => columns.as_slice(),
+            None if detailed => C::DETAILED,
+            None => C::DEFAULT,
+        };
+        format_csv(rows, columns)
+    }
+}
+
+/// Exit code of `list` for what its filters found
+///
+/// `filtered` is whether `--machine` or `--tag` was given, `machine_filter`
+/// whether `--machine` was. `matched_machines` counts the machines it
+/// selected, `rows` the rows of the table asked for (machines, or sessions
+/// with `--sessions`).
+fn list_exit_code(
+    filtered: bool,
+    machine_filter: bool,
+    matched_machines: usize,
+    rows: usize,
+) -> i32 {
+    if machine_filter && matched_machines == 0 {
+        exit_code::NO_SUCH_MACHINE
+    } else if filtered && rows == 0 {
+        exit_code::NO_MATCH
+    } else {
+        exit_code::OK
+    }
+}
 
 /// Execute the list command
+///
+/// Returns the exit code (see [`list_exit_code`]); the tables are printed
+/// whatever it is.
 pub async fn list_command(
     client: &mut OrchestratorClient,
     machine: Option<&str>,
     tag: Option<&[String]>,
     long: bool,
-) -> Result<()> {
+    view: &ListView,
+) -> Result<i32> {
+    // Check the flags before listing anything
+    let session_layout = view
+        .sessions
+        .then(|| TableLayout::<SessionColumn>::parse(view));
+    let session_layout = session_layout.transpose()?;
+    let machine_layout = (!view.sessions).then(|| TableLayout::<MachineColumn>::parse(view));
+    let machine_layout = machine_layout.transpose()?;
+
     // List machines
     let machines = match client.list_machines().await {
         Ok(m) => m,
@@ -21,8 +122,26 @@ pub async fn list_command(
         }
     };
 
+    // A group selects exactly its members; a plain filter matches loosely
+    let group_members = match machine {
+        Some(arg) if arg.starts_with('@') => Some(resolve_machine_arg(client, arg).await?),
+        _ => None,
+    };
+
     // Filter by machine name if specified
-    let machines: Vec<_> = if let Some(filter) = machine {
+    let machines: Vec<_> = if let Some(members) = &group_members {
+        machines
+            .into_iter()
+            .filter(|m| {
+                members.iter().any(|name| {
+                    m.id.eq_ignore_ascii_case(name)
+                        || m.alias
+                            .as_deref()
+                            .is_some_and(|a| a.eq_ignore_ascii_case(name))
+                })
+            })
+            .collect()
+    } else if let Some(filter) = machine {
         machines
             .into_iter()
             .filter(|m| {
@@ -37,6 +156,15 @@ pub async fn list_command(
     } else {
         machines
     };
+    let matched_machines = machines.len();
+    let code_for = |rows: usize| {
+        list_exit_code(
+            machine.is_some() || tag.is_some(),
+            machine.is_some(),
+            matched_machines,
+            rows,
+        )
+    };
 
     // Filter by tag if specified
     let machines: Vec<_> = if let Some(_tags) = tag {
@@ -47,9 +175,76 @@ pub async fn list_command(
         machines
     };
 
+    // Sessions of the selected machines, or of all of them
+    if let Some(layout) = session_layout {
+        let mut sessions = Vec::new();
+        if machine.is_some() {
+            for m in &machines {
+                sessions.extend(list_sessions(client, Some(&m.id)).await?);
+            }
+        } else {
+            sessions = list_sessions(client, None).await?;
+        }
+        if let Some(column) = layout.sort {
+            sort_rows(&mut sessions, column, view.reverse);
+        }
+        if view.output == ListOutput::Csv {
+            print!("{}", layout.csv(&sessions, long));
+            return Ok(code_for(sessions.len()));
+        }
+
+        println!("Active Sessions:");
+        match &layout.columns {
+            Some(columns) => println!(
+                "{}",
+                format_columns(&sessions, columns, long, "No active sessions")
+            ),
+            None => println!("{}", format_sessions(&sessions, long)),
+        }
+        return Ok(code_for(sessions.len()));
+    }
+
+    let mut machines = machines;
+    if let Some(column) = machine_layout.as_ref().and_then(|l| l.sort) {
+        sort_rows(&mut machines, column, view.reverse);
+    }
+    if let (Some(layout), ListOutput::Csv) = (&machine_layout, view.output) {
+        print!("{}", layout.csv(&machines, long));
+        return Ok(code_for(machines.len()));
+    }
+
     // Print machine table
     println!("Connected Machines:");
-    println!("{}", format_machines(&machines, long));
+    match machine_layout.and_then(|l| l.columns) {
+        Some(columns) => println!(
+            "{}",
+            format_columns(&machines, &columns, long, "No machines connected")
+        ),
+        None => println!("{}", format_machines(&machines, long)),
+    }
+
+    // No machine to list the sessions of
+    if machine.is_some() && machines.is_empty() {
+        return Ok(code_for(0));
+    }
+
+    // List sessions of every connected group member
+    if group_members.is_some() {
+        let mut sessions = Vec::new();
+        for m in &machines {
+            match client.list_sessions(Some(&m.id)).await {
+                Ok(s) => sessions.extend(s),
+                Err(e) => {
+                    print_error(&format!("Failed to list sessions: {}", e));
+                    return Err(e);
+                }
+            }
+        }
+
+        println!("\nActive Sessions:");
+        println!("{}", format_sessions(&sessions, long));
+        return Ok(code_for(machines.len()));
+    }
 
     // List sessions if specific machine requested
     if machine.is_some() || machines.len() == 1 {
@@ -65,9 +260,36 @@ pub async fn list_command(
             };
 
             println!("\nActive Sessions:");
-            println!("{}", format_sessions(&sessions));
+            println!("{}", format_sessions(&sessions, long));
         }
     }
 
-    Ok(())
+    Ok(code_for(machines.len()))
+}
+
+async fn list_sessions(
+    client: &mut OrchestratorClient,
+    machine_id: Option<&str>,
+) -> Result<Vec<crate::ipc::SessionInfo>> {
+    client.list_sessions(machine_id).await.map_err(|e| {
+        print_error(&format!("Failed to list sessions: {}", e));
+        e
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_exit_code() {
+        // Without filters an empty list is fine
+        assert_eq!(list_exit_code(false, false, 0, 0), exit_code::OK);
+        assert_eq!(list_exit_code(true, true, 2, 2), exit_code::OK);
+        // `--machine` naming no machine...
+        assert_eq!(list_exit_code(true, true, 0, 0), exit_code::NO_SUCH_MACHINE);
+        // ...is told apart from a machine without sessions
+        assert_eq!(list_exit_code(true, true, 1, 0), exit_code::NO_MATCH);
+        assert_eq!(list_exit_code(true, false, 3, 0), exit_code::NO_MATCH);
+    }
 }
@@ -0,0 +1,26 @@
+//! Maintenance command implementation
+
+use anyhow::Result;
+
+use crate::ipc::OrchestratorClient;
+use crate::output::{print_error, print_success};
+
+/// Execute `maintenance on` (Some, with its optional message) or
+/// `maintenance off` (None)
+pub async fn maintenance_command(
+    client: &mut OrchestratorClient,
+    on: Option<Option<String>>,
+) -> Result<()> {
+    let enabled = on.is_some();
+    if let Err(e) = client.set_maintenance_mode(enabled, on.flatten()).await {
+        print_error(&format!("Failed to set maintenance mode: {}", e));
+        return Err(e);
+    }
+
+    if enabled {
+        print_success("Maintenance mode on: new sessions are refused, existing ones carry on");
+    } else {
+        print_success("Maintenance mode off: new sessions are taken again");
+    }
+    Ok(())
+}
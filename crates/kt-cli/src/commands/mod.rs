@@ -1,13 +1,40 @@
 //! CLI command implementations
 
+mod bench;
+mod bundle;
 mod config;
+mod config_key;
 mod connect;
+mod doctor;
+mod env;
+mod group;
+mod journal;
 mod kill;
 mod list;
+mod maintenance;
+mod select;
+mod session_info;
 mod status;
+mod token;
+mod verify;
 
-pub use config::{config_edit, config_get, config_init, config_set, config_show};
-pub use connect::{attach_command, connect_command};
+pub use bench::{bench_command, BenchOptions, BenchReport, DEFAULT_BENCH_COMMAND};
+pub use bundle::{export_command, import_command};
+pub use config::{
+    config_edit, config_get, config_init, config_set, config_show, config_show_origins,
+};
+pub use connect::{
+    attach_command, connect_command, open_session_log, parse_multiplexer_session, Multiplexer,
+    DEFAULT_MULTIPLEXER_SESSION,
+};
+pub use doctor::doctor_command;
+pub use env::env_command;
+pub use group::{group_list_command, group_modify_command};
+pub use journal::journal_replay_command;
 pub use kill::kill_command;
-pub use list::list_command;
+pub use list::{list_command, ListOutput, ListView};
+pub use maintenance::maintenance_command;
+pub use session_info::session_info_command;
 pub use status::status_command;
+pub use token::token_rotate_command;
+pub use verify::verify_command;
@@ -0,0 +1,342 @@
+//! Session and machine selectors
+//!
+//! Besides a session ID, `kill` and `attach` accept a machine (ID or alias)
+//! with an optional position among its sessions, oldest first:
+//!
+//! - `gpu-box:1` - the oldest session on `gpu-box`
+//! - `gpu-box:last` - the most recent one
+//! - `gpu-box` - the only session on `gpu-box`, if it has exactly one
+//!
+//! Commands that take a machine filter also accept `@group` for the members
+//! of a machine group.
+
+use anyhow::Result;
+
+use crate::ipc::{OrchestratorClient, SessionInfo};
+use crate::output::print_warning;
+
+/// Expand a machine argument into machine IDs or aliases
+///
+/// `@name` expands to the members of group `name`, with a warning about
+/// members that match no known machine; anything else stands for itself.
+pub async fn resolve_machine_arg(
+    client: &mut OrchestratorClient,
+    arg: &str,
+) -> Result<Vec<String>> {
+    let Some(name) = arg.strip_prefix('@') else {
+        return Ok(vec![arg.to_string()]);
+    };
+    let groups = client.list_groups().await?;
+    let Some(group) = groups
+        .into_iter()
+        .find(|g| g.name.eq_ignore_ascii_case(name))
+    else {
+        anyhow::bail!(
+            "Unknown group '@{}'. Run 'k-terminus group list' to see groups",
+            name
+        );
+    };
+    if !group.unknown_members.is_empty() {
+        print_warning(&format!(
+            "Group '@{}' names unknown machine(s): {}",
+            group.name,
+            group.unknown_members.join(", ")
+        ));
+    }
+    Ok(group.members)
+}
+
+/// Which of a machine's sessions a selector picks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionPick {
+    /// The single session; ambiguous if there are several
+    Only,
+    /// The most recently created session
+    Last,
+    /// The n-th oldest session, counting from 1
+    Nth(usize),
+}
+
+/// A parsed session argument
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionSelector {
+    /// A session ID, or a machine with a single session
+    Bare(String),
+    /// A session picked by position among a machine's sessions
+    OnMachine { machine: String, pick: SessionPick },
+}
+
+impl SessionSelector {
+    /// Parse a session argument
+    ///
+    /// Machine IDs can't contain `:`, so only a `:last` or `:<n>` suffix makes
+    /// a positional selector; anything else is taken as-is.
+    pub fn parse(arg: &str) -> Result<Self> {
+        let Some((machine, suffix)) = arg.rsplit_once(':') else {
+            return Ok(Self::Bare(arg.to_string()));
+        };
+        if machine.is_empty() {
+            anyhow::bail!("Missing machine before ':' in '{}'", arg);
+        }
+        let pick = if suffix.eq_ignore_ascii_case("last") {
+            SessionPick::Last
+        } else {
+            match suffix.parse::<usize>() {
+                Ok(0) => anyhow::bail!(
+                    "Session positions start at 1 (oldest): use '{}:1' or '{}:last'",
+                    machine,
+                    machine
+                ),
+                Ok(n) => SessionPick::Nth(n),
+                Err(_) => anyhow::bail!(
+                    "Invalid session position '{}' in '{}': expected a number from 1 or 'last'",
+                    suffix,
+                    arg
+                ),
+            }
+        };
+        Ok(Self::OnMachine {
+            machine: machine.to_string(),
+            pick,
+        })
+    }
+}
+
+/// Turn session arguments into session IDs
+///
+/// Bare arguments that are neither a session ID nor a machine are passed
+/// through unchanged, so the caller reports them as unknown sessions.
+pub async fn resolve_session_args(
+    client: &mut OrchestratorClient,
+    args: &[String],
+) -> Result<Vec<String>> {
+    let selectors = args
+        .iter()
+        .map(|arg| SessionSelector::parse(arg))
+        .collect::<Result<Vec<_>>>()?;
+    // Plain session IDs need no lookups
+    if selectors
+        .iter()
+        .all(|s| matches!(s, SessionSelector::Bare(arg) if is_session_id(arg)))
+    {
+        return Ok(args.to_vec());
+    }
+
+    let all_sessions = client.list_sessions(None).await?;
+    let machines = client.list_machines().await.unwrap_or_default();
+    let is_machine = |name: &str| {
+        machines
+            .iter()
+            .any(|m| m.id == name || m.alias.as_deref() == Some(name))
+            || all_sessions.iter().any(|s| s.machine_id == name)
+    };
+
+    let mut ids = Vec::with_capacity(args.len());
+    for selector in selectors {
+        let (machine, pick) = match selector {
+            SessionSelector::Bare(arg) => {
+                if all_sessions.iter().any(|s| s.id == arg) || !is_machine(&arg) {
+                    ids.push(arg);
+                    continue;
+                }
+                (arg, SessionPick::Only)
+            }
+            SessionSelector::OnMachine { machine, pick } => {
+                if !is_machine(&machine) {
+                    anyhow::bail!(
+                        "Unknown machine '{}'. Run 'k-terminus list' to see machines and sessions",
+                        machine
+                    );
+                }
+                (machine, pick)
+            }
+        };
+        let sessions = client.list_sessions(Some(&machine)).await?;
+        ids.push(pick_session(&machine, pick, sessions)?.id);
+    }
+    Ok(ids)
+}
+
+/// Whether `arg` looks like a session ID (`session-<n>`)
+fn is_session_id(arg: &str) -> bool {
+    arg.strip_prefix("session-")
+        .is_some_and(|n| n.parse::<u32>().is_ok())
+}
+
+/// Pick a session from a machine's sessions, oldest first
+fn pick_session(
+    machine: &str,
+    pick: SessionPick,
+    mut sessions: Vec<SessionInfo>,
+) -> Result<SessionInfo> {
+    if sessions.is_empty() {
+        anyhow::bail!("No sessions on machine '{}'", machine);
+    }
+    // Creation times have second precision; session numbers break ties
+    sessions.sort_by(|a, b| {
+        a.created_at
+            .cmp(&b.created_at)
+            .then_with(|| session_number(&a.id).cmp(&session_number(&b.id)))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let count = sessions.len();
+    let index = match pick {
+        SessionPick::Only if count == 1 => 0,
+        SessionPick::Only => anyhow::bail!(
+            "Machine '{}' has {} sessions; pick one with '{}:<n>' or '{}:last':\n{}",
+            machine,
+            count,
+            machine,
+            machine,
+            format_candidates(machine, &sessions)
+        ),
+        SessionPick::Last => count - 1,
+        SessionPick::Nth(n) if n <= count => n - 1,
+        SessionPick::Nth(n) => anyhow::bail!(
+            "Machine '{}' has only {} session(s), so '{}:{}' doesn't exist:\n{}",
+            machine,
+            count,
+            machine,
+            n,
+            format_candidates(machine, &sessions)
+        ),
+    };
+    Ok(sessions.swap_remove(index))
+}
+
+/// Numeric part of a `session-<n>` ID, for ordering
+fn session_number(id: &str) -> Option<u32> {
+    id.strip_prefix("session-")?.parse().ok()
+}
+
+/// One line per candidate session, with the selector that picks it
+fn format_candidates(machine: &str, sessions: &[SessionInfo]) -> String {
+    sessions
+        .iter()
+        .enumerate()
+        .map(|(i, session)| {
+            let mut line = format!(
+                "  {}:{}  {}  created {}",
+                machine,
+                i + 1,
+                session.id,
+                session.created_at
+            );
+            if let Some(name) = &session.name {
+                line.push_str(&format!("  ({})", name));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, created_at: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            machine_id: "gpu-box".to_string(),
+            shell: None,
+            created_at: created_at.to_string(),
+            pid: None,
+            size: None,
+            name: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        assert_eq!(
+            SessionSelector::parse("session-3").unwrap(),
+            SessionSelector::Bare("session-3".to_string())
+        );
+        assert_eq!(
+            SessionSelector::parse("gpu-box").unwrap(),
+            SessionSelector::Bare("gpu-box".to_string())
+        );
+        assert_eq!(
+            SessionSelector::parse("gpu-box:last").unwrap(),
+            SessionSelector::OnMachine {
+                machine: "gpu-box".to_string(),
+                pick: SessionPick::Last
+            }
+        );
+        assert_eq!(
+            SessionSelector::parse("nas:2").unwrap(),
+            SessionSelector::OnMachine {
+                machine: "nas".to_string(),
+                pick: SessionPick::Nth(2)
+            }
+        );
+
+        let err = SessionSelector::parse("nas:0").unwrap_err().to_string();
+        assert!(err.contains("nas:1"), "{}", err);
+        assert!(SessionSelector::parse("nas:first").is_err());
+        assert!(SessionSelector::parse(":last").is_err());
+    }
+
+    #[test]
+    fn test_pick_orders_by_creation_then_number() {
+        // session-10 and session-9 were created in the same second
+        let sessions = vec![
+            session("session-10", "2026-01-01T00:00:05Z"),
+            session("session-2", "2026-01-01T00:00:09Z"),
+            session("session-9", "2026-01-01T00:00:05Z"),
+            session("session-1", "2026-01-01T00:00:01Z"),
+        ];
+
+        let pick = |p| pick_session("gpu-box", p, sessions.clone()).unwrap().id;
+        assert_eq!(pick(SessionPick::Nth(1)), "session-1");
+        assert_eq!(pick(SessionPick::Nth(2)), "session-9");
+        assert_eq!(pick(SessionPick::Nth(3)), "session-10");
+        assert_eq!(pick(SessionPick::Last), "session-2");
+    }
+
+    #[test]
+    fn test_pick_errors_name_candidates() {
+        let single = vec![session("session-4", "2026-01-01T00:00:00Z")];
+        assert_eq!(
+            pick_session("gpu-box", SessionPick::Only, single)
+                .unwrap()
+                .id,
+            "session-4"
+        );
+
+        let two = vec![
+            session("session-6", "2026-01-01T00:00:02Z"),
+            session("session-5", "2026-01-01T00:00:01Z"),
+        ];
+        let err = pick_session("gpu-box", SessionPick::Only, two.clone())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("has 2 sessions"), "{}", err);
+        assert!(err.contains("gpu-box:1  session-5"), "{}", err);
+        assert!(err.contains("gpu-box:2  session-6"), "{}", err);
+
+        let err = pick_session("gpu-box", SessionPick::Nth(3), two)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("only 2 session(s)"), "{}", err);
+
+        let err = pick_session("gpu-box", SessionPick::Last, Vec::new())
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "No sessions on machine 'gpu-box'");
+    }
+
+    #[test]
+    fn test_session_id_detection() {
+        assert!(is_session_id("session-12"));
+        assert!(!is_session_id("session-"));
+        assert!(!is_session_id("gpu-box"));
+    }
+}
@@ -0,0 +1,39 @@
+//! Session info command implementation
+
+use anyhow::Result;
+
+use super::select::resolve_session_args;
+use crate::ipc::OrchestratorClient;
+use crate::output::{format_session_details, print_error};
+
+/// Execute the session info command - show everything known about a session
+///
+/// Orchestrators too old for `GetSession` only list open sessions, so what
+/// they can't tell is shown as unknown.
+pub async fn session_info_command(client: &mut OrchestratorClient, arg: &str) -> Result<()> {
+    let session_id = resolve_session_args(client, &[arg.to_string()])
+        .await?
+        .remove(0);
+
+    let details = match client.get_session(&session_id).await {
+        Ok(details) => details,
+        Err(e) => {
+            print_error(&format!("Failed to get session: {}", e));
+            return Err(e);
+        }
+    };
+    let output = match &details {
+        Some(details) => format_session_details(&details.session, Some(details)),
+        None => {
+            let sessions = client.list_sessions(None).await?;
+            let Some(session) = sessions.iter().find(|s| s.id == session_id) else {
+                print_error(&format!("Session not found: {}", session_id));
+                anyhow::bail!("Session not found: {}", session_id);
+            };
+            format_session_details(session, None)
+        }
+    };
+    print!("{}", output);
+
+    Ok(())
+}
@@ -2,21 +2,78 @@
 
 use anyhow::Result;
 
-use crate::ipc::OrchestratorClient;
-use crate::output::{format_status, print_error};
+use crate::exit_code;
+use crate::ipc::{OrchestratorClient, OrchestratorStatus};
+use crate::output::{format_capabilities, format_status, print_error};
 
 /// Execute the status command
-pub async fn status_command(client: &mut OrchestratorClient, detailed: bool) -> Result<()> {
+///
+/// Returns the exit code: [`exit_code::NOT_RUNNING`] if the orchestrator
+/// can't be reached, [`exit_code::UNHEALTHY`] if it's not healthy.
+pub async fn status_command(client: &mut OrchestratorClient, detailed: bool) -> Result<i32> {
     let status = match client.status().await {
         Ok(s) => s,
         Err(e) => {
             print_error(&format!("Failed to get orchestrator status: {}", e));
             print_error("Is the orchestrator running? Try: k-terminus start");
-            return Err(e);
+            return Ok(exit_code::NOT_RUNNING);
         }
     };
 
-    println!("{}", format_status(&status, detailed));
+    print!("{}", format_status(&status, detailed));
+    if detailed {
+        let capabilities = client.capabilities().await?;
+        print!("{}", format_capabilities(&capabilities));
+    }
+    println!();
 
-    Ok(())
+    Ok(status_exit_code(&status))
+}
+
+/// Exit code for a status: unhealthy while shutting down or in maintenance
+/// mode, as new sessions are refused then
+fn status_exit_code(status: &OrchestratorStatus) -> i32 {
+    if !status.running || status.maintenance.is_some() {
+        exit_code::UNHEALTHY
+    } else {
+        exit_code::OK
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_exit_code() {
+        let status = OrchestratorStatus {
+            running: true,
+            uptime_secs: 60,
+            machine_count: 0,
+            session_count: 0,
+            version: "0.1.0".to_string(),
+            protocol_version: None,
+            tailscale_hostname: None,
+            bind_address: "0.0.0.0:2222".to_string(),
+            listen_address: None,
+            pairing_code: None,
+            owner: Default::default(),
+            output_coalescing: Default::default(),
+            input_breaker: Default::default(),
+            maintenance: None,
+        };
+        assert_eq!(status_exit_code(&status), exit_code::OK);
+
+        let maintenance = OrchestratorStatus {
+            maintenance: Some("Upgrading".to_string()),
+            ..status.clone()
+        };
+        assert_eq!(status_exit_code(&maintenance), exit_code::UNHEALTHY);
+
+        let stopping = OrchestratorStatus {
+            running: false,
+            ..status
+        };
+        assert_eq!(status_exit_code(&stopping), exit_code::UNHEALTHY);
+    }
 }
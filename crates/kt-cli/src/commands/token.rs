@@ -0,0 +1,77 @@
+//! Token command implementation
+
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use kt_core::config::{self, ConfigFile, ConfigLoader};
+use kt_core::time::format_iso8601;
+
+use crate::ipc::OrchestratorClient;
+use crate::output::{print_success, print_warning};
+
+/// Execute `token rotate`
+///
+/// A running orchestrator generates the new token itself and keeps
+/// accepting the old one briefly, so connected clients aren't cut off. When
+/// no orchestrator is running, a leftover token file is rotated directly,
+/// with the lifetime from the orchestrator's config.
+pub async fn token_rotate_command(
+    client: &mut OrchestratorClient,
+    config_path: Option<&PathBuf>,
+) -> Result<()> {
+    if client.ping().await.unwrap_or(false) {
+        let (generation, expires_at, previous_valid_secs) = client.rotate_token().await?;
+        print_success(&format!(
+            "Rotated IPC token (generation {}{})",
+            generation,
+            format_expiry(expires_at)
+        ));
+        println!(
+            "The previous token is accepted for another {} seconds",
+            previous_valid_secs
+        );
+        return Ok(());
+    }
+
+    if !kt_core::ipc_token_exists() {
+        print_warning("Orchestrator is not running and there is no token to rotate");
+        return Ok(());
+    }
+
+    let lifetime = token_lifetime(config_path)?;
+    let info = kt_core::rotate_ipc_token(lifetime).context("Failed to rotate IPC token")?;
+    print_success(&format!(
+        "Rotated IPC token (generation {}); the orchestrator is not running",
+        info.generation
+    ));
+    Ok(())
+}
+
+/// `ipc_token_lifetime` from the orchestrator's config file and environment
+fn token_lifetime(config_path: Option<&PathBuf>) -> Result<Option<Duration>> {
+    let path = config_path
+        .cloned()
+        .unwrap_or_else(config::default_config_path);
+
+    let mut loader = ConfigLoader::<ConfigFile>::new();
+    if path.exists() {
+        loader
+            .with_file(&path)
+            .with_context(|| format!("Failed to load config from {:?}", path))?;
+    }
+    let config = loader.with_env().load()?;
+    Ok(config.orchestrator.ipc_token_lifetime.map(Duration::from))
+}
+
+/// ", expires <time>" for tokens that expire
+fn format_expiry(expires_at: Option<u64>) -> String {
+    expires_at
+        .map(|at| {
+            format!(
+                ", expires {}",
+                format_iso8601(UNIX_EPOCH + Duration::from_secs(at))
+            )
+        })
+        .unwrap_or_default()
+}
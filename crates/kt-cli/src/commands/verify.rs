@@ -0,0 +1,84 @@
+//! Verify command implementation
+
+use anyhow::Result;
+
+use crate::exit_code;
+use crate::ipc::OrchestratorClient;
+use crate::output::{print_error, print_success, print_warning};
+
+/// Execute the verify command
+///
+/// Prints the agent protocol version of this binary and the versions it can
+/// talk to. With `online`, also asks the running orchestrator for its
+/// version (without starting one) and returns [`exit_code::NOT_RUNNING`] if
+/// it can't be reached, [`exit_code::INCOMPATIBLE`] if its agents would be
+/// refused.
+pub async fn verify_command(client: &mut OrchestratorClient, online: bool) -> Result<i32> {
+    println!("k-Terminus {}", env!("CARGO_PKG_VERSION"));
+    println!(
+        "Protocol: v{} (compatible with {})",
+        kt_protocol::PROTOCOL_VERSION,
+        kt_protocol::COMPATIBLE_PROTOCOLS.join(", ")
+    );
+    if !online {
+        return Ok(exit_code::OK);
+    }
+
+    let status = match client.status().await {
+        Ok(s) => s,
+        Err(e) => {
+            print_error(&format!("Failed to get orchestrator status: {}", e));
+            print_error("Is the orchestrator running? Try: k-terminus start");
+            return Ok(exit_code::NOT_RUNNING);
+        }
+    };
+    let protocol = status.protocol_version.as_deref();
+    println!(
+        "Orchestrator: k-Terminus {}, protocol {}",
+        status.version,
+        protocol.map_or_else(|| "unknown".to_string(), |v| format!("v{}", v))
+    );
+
+    let code = verify_exit_code(protocol);
+    match (code, protocol) {
+        (exit_code::OK, _) => {
+            print_success("Compatible: this binary and the orchestrator can talk to each other")
+        }
+        (_, Some(version)) => print_error(&format!(
+            "Incompatible: the orchestrator speaks v{} and would refuse agents of this binary (v{})",
+            version,
+            kt_protocol::PROTOCOL_VERSION
+        )),
+        (_, None) => print_warning(
+            "The orchestrator predates reporting its protocol version; upgrade it to verify",
+        ),
+    }
+    Ok(code)
+}
+
+/// Exit code for the orchestrator's protocol version, None if it doesn't
+/// report one
+fn verify_exit_code(orchestrator_protocol: Option<&str>) -> i32 {
+    match orchestrator_protocol {
+        Some(version) if kt_protocol::is_compatible_protocol(version) => exit_code::OK,
+        Some(_) => exit_code::INCOMPATIBLE,
+        None => exit_code::ERROR,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_exit_code() {
+        assert_eq!(
+            verify_exit_code(Some(kt_protocol::PROTOCOL_VERSION)),
+            exit_code::OK
+        );
+        assert_eq!(verify_exit_code(Some("1.0")), exit_code::OK);
+        assert_eq!(verify_exit_code(Some("1.9")), exit_code::INCOMPATIBLE);
+        assert_eq!(verify_exit_code(Some("2.0")), exit_code::INCOMPATIBLE);
+        assert_eq!(verify_exit_code(None), exit_code::ERROR);
+    }
+}
@@ -0,0 +1,32 @@
+//! Exit codes for scripting
+//!
+//! `status`, `list` and `verify` exit with these codes to say what they found, after
+//! printing their output as usual. The values are stable: scripts and CI
+//! branch on them, so a code keeps its meaning once released. `connect` and
+//! `attach` exit with the code of the remote shell instead, and usage errors
+//! exit with 2 (from the argument parser).
+
+/// The command did what was asked and found what it looked for
+pub const OK: i32 = 0;
+
+/// Anything else went wrong (the error is printed)
+pub const ERROR: i32 = 1;
+
+/// The orchestrator isn't running, or can't be reached (`status`,
+/// `verify --online`)
+pub const NOT_RUNNING: i32 = 3;
+
+/// The orchestrator is running but shutting down or in maintenance mode
+/// (`status`)
+pub const UNHEALTHY: i32 = 4;
+
+/// `list --machine` matched no connected machine
+pub const NO_SUCH_MACHINE: i32 = 5;
+
+/// `list` with a filter matched machines but nothing to list, e.g.
+/// `--sessions` of a machine without sessions
+pub const NO_MATCH: i32 = 6;
+
+/// The orchestrator speaks an agent protocol this binary can't talk to
+/// (`verify --online`)
+pub const INCOMPATIBLE: i32 = 7;
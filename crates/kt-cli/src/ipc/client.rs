@@ -7,28 +7,60 @@
 //!
 //! Events from the orchestrator are wrapped in `IpcEventEnvelope` with monotonic
 //! sequence numbers. This enables gap detection and state recovery.
+//!
+//! ## Response Cache
+//!
+//! With [`OrchestratorClient::with_cache_ttl`], responses to read-only
+//! queries (status, machine, session and group listings) are reused for
+//! identical queries within the TTL. The cache lives in the client only, and
+//! any other request clears it, since it may change what the queries would
+//! return.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
 
 use anyhow::{Context, Result};
 use crossterm::event::{KeyCode, KeyModifiers};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 
 use kt_core::ipc::{
-    default_ipc_address, IpcEvent, IpcEventEnvelope, IpcRequest, IpcResponse, MachineInfo,
-    OrchestratorStatus, SessionInfo,
+    client_instance_id, default_ipc_address, validate_term, CloseReason, GroupAction, GroupInfo,
+    IpcEvent, IpcEventEnvelope, IpcFeature, IpcRequest, IpcResponse, MachineInfo,
+    OrchestratorCapabilities, OrchestratorStatus, OutputStream, RejectionInfo, SessionDetails,
+    SessionEnvVar, SessionInfo,
 };
 use kt_core::ipc_auth::read_token;
+use kt_core::time::{clock_offset_millis, current_time_millis};
+use kt_orchestrator::session::ORPHAN_GRACE_PERIOD;
+
+use super::SessionLog;
 
 /// Client for communicating with the orchestrator daemon
 pub struct OrchestratorClient {
     address: String,
     stream: Option<TcpStream>,
     authenticated: bool,
+    /// Logical client ID sent when authenticating. Reconnecting with the same
+    /// ID reclaims sessions the orchestrator orphaned when we dropped off.
+    client_id: String,
+    /// Take the client ID over from another process still connected with it
+    force_takeover: bool,
+    /// Have the orchestrator close our sessions when we disconnect instead
+    /// of orphaning them
+    close_on_disconnect: bool,
     /// Epoch ID from orchestrator (changes on restart)
     epoch_id: Option<String>,
     /// Last known sequence number for gap detection
     last_seq: u64,
+    /// Offset of the orchestrator's clock from ours in milliseconds,
+    /// measured each time we authenticate (None = not reported)
+    clock_offset_ms: Option<i64>,
+    /// Responses to read-only queries, reused within the cache TTL
+    cache: ResponseCache,
 }
 
 impl OrchestratorClient {
@@ -43,11 +75,50 @@ impl OrchestratorClient {
             address,
             stream: None,
             authenticated: false,
+            client_id: format!("cli-{}-{}", std::process::id(), current_time_millis()),
+            force_takeover: false,
+            close_on_disconnect: false,
             epoch_id: None,
             last_seq: 0,
+            clock_offset_ms: None,
+            cache: ResponseCache::default(),
         }
     }
 
+    /// Reuse responses to identical read-only queries for `ttl`
+    ///
+    /// Off by default, so nothing shown is older than the request that
+    /// fetched it. A zero TTL leaves it off.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache.ttl = Some(ttl).filter(|ttl| !ttl.is_zero());
+        self
+    }
+
+    /// Use a specific logical client ID instead of the generated one
+    pub fn with_client_id(mut self, client_id: String) -> Self {
+        self.client_id = client_id;
+        self
+    }
+
+    /// Take the client ID over if another process is still connected with
+    /// it, closing that process's connections (refused otherwise)
+    pub fn with_force_takeover(mut self, force_takeover: bool) -> Self {
+        self.force_takeover = force_takeover;
+        self
+    }
+
+    /// Have the orchestrator close this client's sessions when it
+    /// disconnects, instead of keeping them for it to reclaim
+    pub fn with_close_on_disconnect(mut self, close_on_disconnect: bool) -> Self {
+        self.close_on_disconnect = close_on_disconnect;
+        self
+    }
+
+    /// Get the logical client ID
+    pub fn client_id(&self) -> &str {
+        &self.client_id
+    }
+
     /// Get the epoch ID if authenticated
     pub fn epoch_id(&self) -> Option<&str> {
         self.epoch_id.as_deref()
@@ -58,6 +129,12 @@ impl OrchestratorClient {
         self.last_seq
     }
 
+    /// Get how far the orchestrator's clock is ahead of ours, in
+    /// milliseconds, if it reported its time when we authenticated
+    pub fn clock_offset_ms(&self) -> Option<i64> {
+        self.clock_offset_ms
+    }
+
     /// Get the address
     pub fn address(&self) -> &str {
         &self.address
@@ -77,25 +154,49 @@ impl OrchestratorClient {
     }
 
     /// Authenticate with the orchestrator using the token from the config directory
+    ///
+    /// If the token is rejected and the token file has changed since it was
+    /// read (the orchestrator rotated it), retries once with the new token.
     async fn authenticate(&mut self) -> Result<()> {
-        let token = read_token().with_context(|| {
-            "Failed to read IPC authentication token. Is the orchestrator running?"
-        })?;
+        let token = read_auth_token()?;
+        match self.send_authenticate(token.clone()).await {
+            Err(e) => match read_auth_token() {
+                Ok(fresh) if fresh != token => {
+                    tracing::debug!("IPC token changed, retrying authentication");
+                    self.send_authenticate(fresh).await
+                }
+                _ => Err(e),
+            },
+            ok => ok,
+        }
+    }
 
+    async fn send_authenticate(&mut self, token: String) -> Result<()> {
         let request = IpcRequest::Authenticate {
             token,
-            client_id: None,
+            client_id: Some(self.client_id.clone()),
+            instance_id: Some(client_instance_id().to_string()),
+            force_takeover: self.force_takeover,
+            close_on_disconnect: self.close_on_disconnect,
         };
+        let sent = SystemTime::now();
         match self.send_request_raw(request).await? {
             IpcResponse::Authenticated {
                 epoch_id,
                 current_seq,
+                server_time_ms,
             } => {
+                // Measured on every (re)connect, as either clock may have
+                // been adjusted in between
+                self.clock_offset_ms = (server_time_ms > 0)
+                    .then(|| clock_offset_millis(server_time_ms, sent, SystemTime::now()));
                 tracing::debug!(
                     epoch_id = %epoch_id,
                     current_seq = current_seq,
+                    clock_offset_ms = ?self.clock_offset_ms,
                     "Authenticated with orchestrator"
                 );
+                crate::output::set_clock_offset(self.clock_offset_ms.unwrap_or(0));
                 self.authenticated = true;
                 self.epoch_id = Some(epoch_id);
                 self.last_seq = current_seq;
@@ -145,13 +246,31 @@ impl OrchestratorClient {
     pub async fn status(&mut self) -> Result<OrchestratorStatus> {
         self.connect().await?;
 
-        match self.send_request(IpcRequest::GetStatus).await? {
+        match self
+            .send_request(IpcRequest::GetStatus {
+                include_pairing_code: false,
+            })
+            .await?
+        {
             IpcResponse::Status(status) => Ok(status),
             IpcResponse::Error { message } => anyhow::bail!("{}", message),
             other => anyhow::bail!("Unexpected response: {:?}", other),
         }
     }
 
+    /// Get the optional features the orchestrator supports
+    ///
+    /// An orchestrator too old to know the request supports none of them.
+    pub async fn capabilities(&mut self) -> Result<OrchestratorCapabilities> {
+        self.connect().await?;
+
+        match self.send_request(IpcRequest::GetCapabilities).await? {
+            IpcResponse::Capabilities(capabilities) => Ok(capabilities),
+            IpcResponse::Error { .. } => Ok(OrchestratorCapabilities::legacy()),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
     /// List connected machines
     pub async fn list_machines(&mut self) -> Result<Vec<MachineInfo>> {
         self.connect().await?;
@@ -163,16 +282,65 @@ impl OrchestratorClient {
         }
     }
 
+    /// List machine groups
+    pub async fn list_groups(&mut self) -> Result<Vec<GroupInfo>> {
+        self.connect().await?;
+
+        match self.send_request(IpcRequest::ListGroups).await? {
+            IpcResponse::Groups { groups } => Ok(groups),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
+    /// List recent agent registrations the orchestrator rejected
+    pub async fn list_rejections(&mut self) -> Result<Vec<RejectionInfo>> {
+        self.connect().await?;
+
+        match self.send_request(IpcRequest::ListRejections).await? {
+            IpcResponse::Rejections { rejections } => Ok(rejections),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
+    /// Create, change or delete a machine group
+    ///
+    /// Returns the group as changed, or `None` once deleted.
+    pub async fn modify_group(
+        &mut self,
+        group: &str,
+        action: GroupAction,
+        members: &[String],
+    ) -> Result<Option<GroupInfo>> {
+        self.connect().await?;
+
+        let request = IpcRequest::ModifyGroup {
+            group: group.to_string(),
+            action,
+            members: members.to_vec(),
+        };
+
+        match self.send_request(request).await? {
+            IpcResponse::Groups { groups } => Ok(groups.into_iter().next()),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
     /// List active sessions
     pub async fn list_sessions(&mut self, machine_id: Option<&str>) -> Result<Vec<SessionInfo>> {
         self.connect().await?;
 
         let request = IpcRequest::ListSessions {
             machine_id: machine_id.map(String::from),
+            cursor: None,
+            offset: None,
+            limit: None,
         };
 
         match self.send_request(request).await? {
-            IpcResponse::Sessions { sessions } => Ok(sessions),
+            IpcResponse::Sessions { sessions, .. } => Ok(sessions),
             IpcResponse::Error { message } => anyhow::bail!("{}", message),
             other => anyhow::bail!("Unexpected response: {:?}", other),
         }
@@ -183,17 +351,103 @@ impl OrchestratorClient {
         &mut self,
         machine_id: &str,
         shell: Option<&str>,
+    ) -> Result<SessionInfo> {
+        self.request_session(machine_id, shell, true, None, SessionLifetime::default())
+            .await
+    }
+
+    /// Create a new session whose shell runs on plain pipes instead of a
+    /// PTY, so its stdout and stderr arrive separately
+    pub async fn create_pipe_session(
+        &mut self,
+        machine_id: &str,
+        shell: Option<&str>,
+    ) -> Result<SessionInfo> {
+        self.request_session(machine_id, shell, false, None, SessionLifetime::default())
+            .await
+    }
+
+    /// Create a new session whose agent-side logs use `log_level` (`error`
+    /// through `trace`) instead of the agent's own level
+    ///
+    /// Agents that don't support per-session levels ignore it.
+    pub async fn create_session_with_log_level(
+        &mut self,
+        machine_id: &str,
+        shell: Option<&str>,
+        allocate_pty: bool,
+        log_level: &str,
+    ) -> Result<SessionInfo> {
+        self.request_session(
+            machine_id,
+            shell,
+            allocate_pty,
+            Some(log_level),
+            SessionLifetime::default(),
+        )
+        .await
+    }
+
+    /// Create a new session that lasts as `lifetime` says, not just until
+    /// its shell exits
+    pub async fn create_session_with_lifetime(
+        &mut self,
+        machine_id: &str,
+        shell: Option<&str>,
+        allocate_pty: bool,
+        log_level: Option<&str>,
+        lifetime: SessionLifetime,
+    ) -> Result<SessionInfo> {
+        // An older orchestrator would ignore it, leaving the session open
+        if !self.capabilities().await?.supports(IpcFeature::SessionTtl) {
+            anyhow::bail!(
+                "The orchestrator is too old for session TTLs or keeping sessions on exit; \
+                 upgrade it first"
+            );
+        }
+        self.request_session(machine_id, shell, allocate_pty, log_level, lifetime)
+            .await
+    }
+
+    async fn request_session(
+        &mut self,
+        machine_id: &str,
+        shell: Option<&str>,
+        allocate_pty: bool,
+        log_level: Option<&str>,
+        lifetime: SessionLifetime,
     ) -> Result<SessionInfo> {
         self.connect().await?;
 
+        // The remote shell should render for this terminal, not the agent's
+        let (term, truecolor) = if allocate_pty {
+            local_terminal(
+                std::env::var("TERM").ok().as_deref(),
+                std::env::var("COLORTERM").ok().as_deref(),
+            )
+        } else {
+            (None, false)
+        };
         let request = IpcRequest::CreateSession {
             machine_id: machine_id.to_string(),
             shell: shell.map(String::from),
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty,
+            log_level: log_level.map(String::from),
+            term,
+            truecolor,
+            idempotency_key: None,
+            ttl_secs: lifetime.ttl.map(|ttl| ttl.as_secs().max(1)),
+            close_on_exit: lifetime.keep_on_exit.then_some(false),
         };
 
         match self.send_request(request).await? {
             IpcResponse::SessionCreated(info) => Ok(info),
             IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            IpcResponse::CapacityExceeded { message, .. } => anyhow::bail!("{}", message),
             other => anyhow::bail!("Unexpected response: {:?}", other),
         }
     }
@@ -214,8 +468,58 @@ impl OrchestratorClient {
         }
     }
 
+    /// Get the environment a session was created with
+    ///
+    /// Sensitive values are redacted by the orchestrator unless `show_secrets` is set.
+    pub async fn get_session_env(
+        &mut self,
+        session_id: &str,
+        show_secrets: bool,
+    ) -> Result<Vec<SessionEnvVar>> {
+        self.connect().await?;
+
+        let request = IpcRequest::GetSessionEnv {
+            session_id: session_id.to_string(),
+            show_secrets,
+        };
+
+        match self.send_request(request).await? {
+            IpcResponse::SessionEnv { env, .. } => Ok(env),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
+    /// Get everything the orchestrator knows about a session, open or
+    /// recently closed
+    ///
+    /// Returns None if the orchestrator is too old to say more than
+    /// `list_sessions` does.
+    pub async fn get_session(&mut self, session_id: &str) -> Result<Option<SessionDetails>> {
+        if !self
+            .capabilities()
+            .await?
+            .supports(IpcFeature::SessionDetails)
+        {
+            return Ok(None);
+        }
+
+        let request = IpcRequest::GetSession {
+            session_id: session_id.to_string(),
+        };
+
+        match self.send_request(request).await? {
+            IpcResponse::Session(details) => Ok(Some(details)),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
     /// Subscribe to terminal output for a session
-    pub async fn subscribe(&mut self, session_id: &str) -> Result<()> {
+    ///
+    /// Returns the orchestrator's attach message, ready to write to the
+    /// terminal, if it has one.
+    pub async fn subscribe(&mut self, session_id: &str) -> Result<Option<String>> {
         self.connect().await?;
 
         let request = IpcRequest::Subscribe {
@@ -223,11 +527,44 @@ impl OrchestratorClient {
         };
 
         match self.send_request(request).await? {
-            IpcResponse::Ok => Ok(()),
-            IpcResponse::Subscribed { current_seq, .. } => {
+            IpcResponse::Ok => Ok(None),
+            IpcResponse::Subscribed {
+                current_seq, motd, ..
+            } => {
                 // Update last_seq from subscription response
                 self.last_seq = current_seq;
-                Ok(())
+                Ok(motd)
+            }
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
+    /// Subscribe to terminal output for a session as an observer
+    ///
+    /// Observers needn't own the session and can't type into it until they
+    /// claim its input (see `ClaimSession`). Returns the attach message like
+    /// [`Self::subscribe`].
+    pub async fn observe(&mut self, session_id: &str) -> Result<Option<String>> {
+        if !self
+            .capabilities()
+            .await?
+            .supports(IpcFeature::ReadOnlyAttach)
+        {
+            anyhow::bail!("The orchestrator is too old to mirror sessions; upgrade it first");
+        }
+
+        let request = IpcRequest::ObserveSession {
+            session_id: session_id.to_string(),
+        };
+
+        match self.send_request(request).await? {
+            IpcResponse::Ok => Ok(None),
+            IpcResponse::Subscribed {
+                current_seq, motd, ..
+            } => {
+                self.last_seq = current_seq;
+                Ok(motd)
             }
             IpcResponse::Error { message } => anyhow::bail!("{}", message),
             other => anyhow::bail!("Unexpected response: {:?}", other),
@@ -282,6 +619,48 @@ impl OrchestratorClient {
         }
     }
 
+    /// Replace the orchestrator's IPC token with a new one
+    ///
+    /// Returns the new token's generation, its expiry (Unix seconds) and how
+    /// long the previous token is still accepted.
+    pub async fn rotate_token(&mut self) -> Result<(u64, Option<u64>, u64)> {
+        self.connect().await?;
+
+        match self.send_request(IpcRequest::RotateIpcToken).await? {
+            IpcResponse::TokenRotated {
+                generation,
+                expires_at,
+                previous_valid_secs,
+            } => Ok((generation, expires_at, previous_valid_secs)),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
+    /// Turn maintenance mode on, refusing new sessions with `message`, or
+    /// off
+    pub async fn set_maintenance_mode(
+        &mut self,
+        enabled: bool,
+        message: Option<String>,
+    ) -> Result<()> {
+        if !self
+            .capabilities()
+            .await?
+            .supports(IpcFeature::MaintenanceMode)
+        {
+            anyhow::bail!("The orchestrator is too old for maintenance mode; upgrade it first");
+        }
+
+        let request = IpcRequest::SetMaintenanceMode { enabled, message };
+
+        match self.send_request(request).await? {
+            IpcResponse::Ok => Ok(()),
+            IpcResponse::Error { message } => anyhow::bail!("{}", message),
+            other => anyhow::bail!("Unexpected response: {:?}", other),
+        }
+    }
+
     /// Shutdown the orchestrator
     pub async fn shutdown(&mut self) -> Result<()> {
         self.connect().await?;
@@ -295,7 +674,28 @@ impl OrchestratorClient {
 
     /// Send a request and receive response (used by all public methods)
     async fn send_request(&mut self, request: IpcRequest) -> Result<IpcResponse> {
-        self.send_request_raw(request).await
+        let cache_key = self.cache.key(&request);
+        match &cache_key {
+            Some(key) => {
+                if let Some(response) = self.cache.get(key) {
+                    return Ok(response);
+                }
+            }
+            // Anything else may change what cached queries would return
+            None => self.cache.clear(),
+        }
+
+        let response = match self.send_request_raw(request).await? {
+            // Surface rate limiting as an error so every command reports it
+            IpcResponse::RateLimited { message, .. } => anyhow::bail!("{}", message),
+            response => response,
+        };
+        if let Some(key) = cache_key {
+            if !matches!(response, IpcResponse::Error { .. }) {
+                self.cache.insert(key, response.clone());
+            }
+        }
+        Ok(response)
     }
 
     /// Send a request without automatic authentication (used internally)
@@ -322,26 +722,294 @@ impl OrchestratorClient {
     }
 }
 
+/// Responses to read-only queries, kept for the client's lifetime only
+#[derive(Debug, Default)]
+struct ResponseCache {
+    /// How long a response is reused (None disables the cache)
+    ttl: Option<Duration>,
+    /// Responses by request JSON, so only identical queries share one
+    entries: HashMap<String, (Instant, IpcResponse)>,
+}
+
+impl ResponseCache {
+    /// Cache key for `request`, if caching is on and the request only reads
+    fn key(&self, request: &IpcRequest) -> Option<String> {
+        self.ttl?;
+        if !is_cacheable(request) {
+            return None;
+        }
+        serde_json::to_string(request).ok()
+    }
+
+    /// The response cached under `key`, if it is still within the TTL
+    fn get(&self, key: &str) -> Option<IpcResponse> {
+        let ttl = self.ttl?;
+        self.entries
+            .get(key)
+            .filter(|(fetched_at, _)| fetched_at.elapsed() < ttl)
+            .map(|(_, response)| response.clone())
+    }
+
+    fn insert(&mut self, key: String, response: IpcResponse) {
+        self.entries.insert(key, (Instant::now(), response));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// Whether a request only reads state, so its response may be reused
+///
+/// Everything that creates, changes or closes something is left out, and so
+/// is anything sequence-sensitive like state snapshots.
+fn is_cacheable(request: &IpcRequest) -> bool {
+    matches!(
+        request,
+        IpcRequest::GetStatus { .. }
+            | IpcRequest::ListMachines
+            | IpcRequest::GetMachine { .. }
+            | IpcRequest::ListSessions { .. }
+            | IpcRequest::ListGroups
+            | IpcRequest::GetCapabilities
+    )
+}
+
+/// Terminal type and 24-bit color support from the local `TERM` and
+/// `COLORTERM`
+///
+/// A `TERM` the orchestrator would reject is left out, so the session
+/// falls back to the agent's default instead of failing.
+fn local_terminal(term: Option<&str>, colorterm: Option<&str>) -> (Option<String>, bool) {
+    let term = term
+        .filter(|term| validate_term(term).is_ok())
+        .map(String::from);
+    let truecolor = matches!(colorterm, Some("truecolor" | "24bit"));
+    (term, truecolor)
+}
+
+/// Read the IPC token the orchestrator wrote
+fn read_auth_token() -> Result<String> {
+    read_token()
+        .with_context(|| "Failed to read IPC authentication token. Is the orchestrator running?")
+}
+
 impl Default for OrchestratorClient {
     fn default() -> Self {
         Self::new()
     }
 }
 
+/// How an interactive terminal session ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The user detached (Ctrl+]); the session keeps running
+    Detached,
+    /// The remote shell exited
+    Exited {
+        /// Exit code of the shell, if the agent reported one
+        exit_code: Option<i32>,
+        /// Why the orchestrator closed it, if it said
+        reason: Option<CloseReason>,
+    },
+    /// The connection to the orchestrator was lost
+    ConnectionLost,
+    /// Piped input ended and the session was closed (see
+    /// [`TerminalSession::run_piped`])
+    InputEnded,
+}
+
+/// How long a session lasts, beyond running until its shell exits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SessionLifetime {
+    /// Close it this long after it's created, whatever it's doing
+    pub ttl: Option<Duration>,
+    /// Keep it open after its shell exits, until it's closed
+    pub keep_on_exit: bool,
+}
+
+/// Puts the terminal into raw mode on the alternate screen and restores it
+/// when dropped.
+///
+/// Restoring in `Drop` means the user's shell is never left in raw mode,
+/// whether the session ends normally, returns early with an error, or panics.
+/// Job control is kept from suspending the CLI meanwhile (see
+/// [`SuspendGuard`]), and restored after the terminal.
+struct RawTerminalGuard {
+    _suspend: SuspendGuard,
+}
+
+impl RawTerminalGuard {
+    fn enter() -> Result<Self> {
+        use crossterm::{
+            terminal::{enable_raw_mode, EnterAlternateScreen},
+            ExecutableCommand,
+        };
+
+        let suspend = SuspendGuard::ignore();
+        enable_raw_mode()?;
+        // Construct the guard before anything else can fail so raw mode is undone
+        let guard = Self { _suspend: suspend };
+        std::io::stdout().execute(EnterAlternateScreen)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for RawTerminalGuard {
+    fn drop(&mut self) {
+        use crossterm::{
+            terminal::{disable_raw_mode, LeaveAlternateScreen},
+            ExecutableCommand,
+        };
+
+        let _ = std::io::stdout().execute(LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// Ignores SIGTSTP while attached, restoring its previous handling when
+/// dropped.
+///
+/// Raw mode turns Ctrl+Z into a key like any other, sent to the session to
+/// suspend the remote foreground process. SIGTSTP can still reach the CLI
+/// some other way (`kill -TSTP`, a terminal ignoring raw mode), and a
+/// suspended CLI would strand the terminal in raw mode with nothing reading
+/// it, so it's ignored until the user detaches.
+struct SuspendGuard {
+    /// Handling of SIGTSTP before, if it could be replaced
+    #[cfg(unix)]
+    previous: Option<libc::sighandler_t>,
+}
+
+impl SuspendGuard {
+    fn ignore() -> Self {
+        #[cfg(unix)]
+        {
+            // SAFETY: ignoring a signal installs no handler code to run
+            let previous = unsafe { libc::signal(libc::SIGTSTP, libc::SIG_IGN) };
+            Self {
+                previous: (previous != libc::SIG_ERR).then_some(previous),
+            }
+        }
+        #[cfg(not(unix))]
+        Self {}
+    }
+}
+
+impl Drop for SuspendGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(previous) = self.previous {
+            // SAFETY: puts back the handling the process had before
+            unsafe {
+                libc::signal(libc::SIGTSTP, previous);
+            }
+        }
+    }
+}
+
+/// First delay between reconnect attempts after the connection drops
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay between reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// Time allowed for a single reconnect attempt, including the replay
+const RECONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long to keep trying to reconnect. The orchestrator closes orphaned
+/// sessions after this, so there is nothing to come back to later.
+const RECONNECT_WINDOW: Duration = ORPHAN_GRACE_PERIOD;
+
+/// Most input bytes queued while disconnected; anything beyond is dropped
+const MAX_QUEUED_INPUT: usize = 4096;
+
+/// How long a notice stays on the status line after reconnecting
+const NOTICE_DURATION: Duration = Duration::from_secs(3);
+
+/// How long output must be quiet after piped input ends before the session is
+/// closed, so output for the last input isn't cut off
+const EOF_CLOSE_DELAY: Duration = Duration::from_millis(250);
+
+/// Largest chunk of piped input sent in one `SessionInput`
+const PIPED_INPUT_CHUNK: usize = 8192;
+
+/// Longest wait for a new session's PTY before sending its initial input
+/// anyway (the agent queues input for sessions still starting)
+const INITIAL_INPUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What a piped session does once its input ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputEnd {
+    /// Close the session once its output has gone quiet
+    CloseSession,
+    /// Leave the session running until the remote program exits
+    KeepRunning,
+    /// Close the remote shell's stdin (sessions without a PTY) and wait for
+    /// it to exit
+    CloseStdin,
+}
+
 /// Interactive terminal session handler
+///
+/// If the connection to the orchestrator drops while attached, the session
+/// reconnects with the same client ID (reclaiming the session before the
+/// orchestrator's orphan grace period runs out), subscribes again and replays
+/// the output it missed with `GetEventsSince`. A status line on the bottom
+/// row shows the outage, and input typed meanwhile is queued up to
+/// [`MAX_QUEUED_INPUT`] bytes and dropped beyond that.
+///
+/// Input given with [`Self::with_initial_input`] is sent before anything
+/// typed, once the session's PTY is ready. With [`Self::with_log`], all
+/// output received is also appended to a [`SessionLog`], which is flushed
+/// and closed when the session ends or is detached.
+///
+/// Ctrl+T claims the session's input from whichever attached client holds
+/// it, and gives it back when pressed again. A session attached with
+/// [`Self::mirror`] only watches until then: keys typed are dropped and the
+/// terminal size is left alone. Input refused because another client holds
+/// it is reported on the status line.
 pub struct TerminalSession {
     session_id: String,
     stream: TcpStream,
     /// Last seen sequence number for gap detection
     last_seq: u64,
+    /// Orchestrator address, for reconnecting
+    address: String,
+    /// Logical client ID, reused when reconnecting
+    client_id: String,
+    /// Epoch of the orchestrator we attached to
+    epoch_id: Option<String>,
+    /// Input to send first, and whether the PTY is known to be ready for it
+    initial_input: Option<(Vec<u8>, bool)>,
+    /// Where received output is logged, if anywhere
+    log: Option<SessionLog>,
+    /// Orchestrator's attach message, shown before the session's output
+    motd: Option<String>,
+    /// Attached as an observer (`attach --mirror`)
+    mirror: bool,
 }
 
 impl TerminalSession {
     /// Create a new terminal session from a connected client
     pub async fn new(mut client: OrchestratorClient, session_id: String) -> Result<Self> {
         // Subscribe to terminal output
-        client.subscribe(&session_id).await?;
+        let motd = client.subscribe(&session_id).await?;
+        Self::attached(client, session_id, motd, false)
+    }
 
+    /// Watch a session without taking its input, which needn't be ours
+    pub async fn mirror(mut client: OrchestratorClient, session_id: String) -> Result<Self> {
+        let motd = client.observe(&session_id).await?;
+        Self::attached(client, session_id, motd, true)
+    }
+
+    fn attached(
+        mut client: OrchestratorClient,
+        session_id: String,
+        motd: Option<String>,
+        mirror: bool,
+    ) -> Result<Self> {
         // Take the stream for interactive mode and inherit the sequence number
         let last_seq = client.last_seq();
         let stream = client
@@ -352,44 +1020,93 @@ impl TerminalSession {
             session_id,
             stream,
             last_seq,
+            address: client.address().to_string(),
+            client_id: client.client_id().to_string(),
+            epoch_id: client.epoch_id().map(str::to_string),
+            initial_input: None,
+            log: None,
+            motd,
+            mirror,
         })
     }
 
+    /// Send `data` as the session's first input when running interactively
+    ///
+    /// `pty_ready` says the session already has a PID. Otherwise the input
+    /// waits until the orchestrator reports the session ready or it produces
+    /// output, for at most [`INITIAL_INPUT_TIMEOUT`]. The bytes are sent
+    /// exactly as given.
+    pub fn with_initial_input(mut self, data: Vec<u8>, pty_ready: bool) -> Self {
+        self.initial_input = Some((data, pty_ready));
+        self
+    }
+
+    /// Append all output received for the session to `log`
+    pub fn with_log(mut self, log: SessionLog) -> Self {
+        self.log = Some(log);
+        self
+    }
+
     /// Run the interactive terminal session
     ///
-    /// Returns when the user detaches (Ctrl+]) or the session closes
-    pub async fn run(self) -> Result<()> {
+    /// Returns when the user detaches (Ctrl+]), the session closes, or the
+    /// connection to the orchestrator can't be restored. The terminal is
+    /// restored before this returns, including on error. Input claimed with
+    /// Ctrl+T goes back to its holder when the connection closes.
+    pub async fn run(self) -> Result<SessionEnd> {
         use crossterm::{
             event::{self, Event, KeyEvent},
-            terminal::{
-                disable_raw_mode, enable_raw_mode, size, EnterAlternateScreen, LeaveAlternateScreen,
-            },
-            ExecutableCommand,
+            terminal::size,
         };
-        use std::io::{stdout, Write};
+        use std::io::stdout;
 
-        let (reader, writer) = self.stream.into_split();
-        let mut reader = BufReader::new(reader);
-        let mut writer = BufWriter::new(writer);
         let session_id = self.session_id;
-        let mut last_seen_seq = self.last_seq;
-
-        // Enter raw mode
-        enable_raw_mode()?;
+        // Sequence number of the next event we haven't seen
+        let mut next_seq = self.last_seq;
+        let mut conn = Some(AttachedConnection::new(self.stream));
+        let mut outage: Option<Outage> = None;
+        let mut notice_until: Option<Instant> = None;
+        let mut terminal_size = size().ok();
+        let mut initial_input = self.initial_input;
+        let mut log = self.log;
+        let mirror = self.mirror;
+        // Whether we claimed the session's input with Ctrl+T
+        let mut claimed = false;
+
+        // Enter raw mode (restored when the guard is dropped)
+        let terminal_guard = RawTerminalGuard::enter()?;
         let mut stdout = stdout();
-        stdout.execute(EnterAlternateScreen)?;
-
-        // Send initial terminal size
-        if let Ok((cols, rows)) = size() {
-            let request = IpcRequest::SessionResize {
-                session_id: session_id.clone(),
-                cols,
-                rows,
-            };
-            let mut json = serde_json::to_string(&request)?;
-            json.push('\n');
-            writer.write_all(json.as_bytes()).await?;
-            writer.flush().await?;
+
+        // The attach message, once, ahead of anything the session sends; it
+        // isn't session output, so it stays out of the log
+        if let Some(motd) = self.motd {
+            let _ = std::io::Write::write_all(&mut stdout, motd.as_bytes());
+            let _ = std::io::Write::flush(&mut stdout);
+        }
+
+        // Send initial terminal size; a mirror leaves it to the session's owner
+        if let (Some(c), Some((cols, rows)), false) = (conn.as_mut(), terminal_size, mirror) {
+            if c.send(&resize_request(&session_id, cols, rows))
+                .await
+                .is_err()
+            {
+                conn = None;
+                outage = Some(Outage::begin(&mut stdout));
+            }
+        }
+
+        // Send the initial input now if the PTY is ready, after the resize so
+        // the command runs at the right size
+        let mut initial_input_deadline = None;
+        match initial_input.take() {
+            Some((data, true)) => {
+                send_or_queue_input(&mut conn, &mut outage, &mut stdout, &session_id, data).await;
+            }
+            Some((data, false)) => {
+                initial_input = Some((data, false));
+                initial_input_deadline = Some(Instant::now() + INITIAL_INPUT_TIMEOUT);
+            }
+            None => {}
         }
 
         // Create channel for terminal events
@@ -408,7 +1125,7 @@ impl TerminalSession {
 
         let mut line_buf = String::new();
 
-        loop {
+        let end = loop {
             tokio::select! {
                 // Handle terminal events (keyboard, resize)
                 Some(evt) = event_rx.recv() => {
@@ -418,86 +1135,1038 @@ impl TerminalSession {
                             if modifiers.contains(KeyModifiers::CONTROL)
                                 && code == KeyCode::Char(']')
                             {
-                                break;
+                                break SessionEnd::Detached;
                             }
 
-                            // Convert key to bytes and send
-                            let data = key_to_bytes(code, modifiers);
-                            if !data.is_empty() {
-                                let request = IpcRequest::SessionInput {
-                                    session_id: session_id.clone(),
-                                    data,
+                            // Ctrl+T to claim the input, or give it back
+                            if modifiers.contains(KeyModifiers::CONTROL)
+                                && code == KeyCode::Char('t')
+                            {
+                                let Some(c) = conn.as_mut() else {
+                                    continue;
+                                };
+                                let sent = if claimed {
+                                    c.send(&IpcRequest::ReleaseSession { session_id: session_id.clone() }).await
+                                } else {
+                                    // Size the session for us now that we type into it
+                                    let claim = c.send(&IpcRequest::ClaimSession { session_id: session_id.clone() }).await;
+                                    match (claim, terminal_size) {
+                                        (Ok(()), Some((cols, rows))) => c.send(&resize_request(&session_id, cols, rows)).await,
+                                        (claim, _) => claim,
+                                    }
                                 };
-                                let mut json = serde_json::to_string(&request)?;
-                                json.push('\n');
-                                writer.write_all(json.as_bytes()).await?;
-                                writer.flush().await?;
+                                if let Err(e) = sent {
+                                    tracing::warn!("Error writing to IPC: {}", e);
+                                    conn = None;
+                                    outage = Some(Outage::begin(&mut stdout));
+                                }
+                                continue;
+                            }
+
+                            // Convert key to bytes and send (or queue while disconnected)
+                            let data = key_to_bytes(code, modifiers);
+                            if data.is_empty() {
+                                continue;
+                            }
+                            if mirror && !claimed {
+                                if outage.is_none() {
+                                    draw_status_line(&mut stdout, "mirroring, Ctrl+T to take the input");
+                                    notice_until = Some(Instant::now() + NOTICE_DURATION);
+                                }
+                                continue;
+                            }
+                            // Anything typed goes after the initial input
+                            if let Some((initial, _)) = initial_input.take() {
+                                initial_input_deadline = None;
+                                send_or_queue_input(&mut conn, &mut outage, &mut stdout, &session_id, initial).await;
                             }
+                            send_or_queue_input(&mut conn, &mut outage, &mut stdout, &session_id, data).await;
                         }
                         Event::Resize(cols, rows) => {
-                            let request = IpcRequest::SessionResize {
-                                session_id: session_id.clone(),
-                                cols,
-                                rows,
-                            };
-                            let mut json = serde_json::to_string(&request)?;
-                            json.push('\n');
-                            writer.write_all(json.as_bytes()).await?;
-                            writer.flush().await?;
+                            // Sent again after reconnecting if we're disconnected
+                            terminal_size = Some((cols, rows));
+                            if let Some(o) = outage.as_ref() {
+                                o.draw(&mut stdout);
+                            } else if mirror && !claimed {
+                                // The session keeps its owner's size
+                            } else if let Some(c) = conn.as_mut() {
+                                if let Err(e) = c.send(&resize_request(&session_id, cols, rows)).await {
+                                    tracing::warn!("Error writing to IPC: {}", e);
+                                    conn = None;
+                                    outage = Some(Outage::begin(&mut stdout));
+                                }
+                            }
                         }
                         _ => {}
                     }
                 }
 
                 // Handle IPC events (terminal output)
-                result = reader.read_line(&mut line_buf) => {
-                    match result {
-                        Ok(0) => break, // EOF
+                result = read_event_line(conn.as_mut(), &mut line_buf) => {
+                    let envelope = match result {
+                        Ok(0) => None, // EOF
                         Ok(_) => {
-                            // Try to parse as IpcEventEnvelope (new protocol)
-                            if let Ok(envelope) = serde_json::from_str::<IpcEventEnvelope>(&line_buf) {
-                                // Check for sequence gaps (log warning but continue)
-                                if envelope.seq > last_seen_seq + 1 && last_seen_seq > 0 {
-                                    tracing::warn!(
-                                        expected = last_seen_seq + 1,
-                                        got = envelope.seq,
-                                        "Event sequence gap detected"
-                                    );
-                                }
-                                last_seen_seq = envelope.seq;
-
-                                // Extract the inner event and process it
-                                match envelope.event {
-                                    IpcEvent::TerminalOutput { data, .. } => {
-                                        stdout.write_all(&data)?;
-                                        stdout.flush()?;
-                                    }
-                                    IpcEvent::SessionClosed { session_id: sid } if sid == session_id => {
-                                        break;
+                            let parsed = serde_json::from_str::<IpcEventEnvelope>(&line_buf);
+                            // Anything else is the response to a request we sent
+                            let held_by = parsed.is_err().then(|| input_held_by(&line_buf)).flatten();
+                            line_buf.clear();
+                            match parsed {
+                                Ok(envelope) => Some(envelope),
+                                Err(_) => {
+                                    if let Some(holder) = held_by {
+                                        draw_status_line(&mut stdout, &format!("input held by {}, Ctrl+T to take it", holder));
+                                        notice_until = Some(Instant::now() + NOTICE_DURATION);
                                     }
-                                    _ => {}
+                                    continue;
                                 }
                             }
-                            line_buf.clear();
                         }
                         Err(e) => {
                             tracing::warn!("Error reading from IPC: {}", e);
-                            break;
+                            None
+                        }
+                    };
+
+                    let Some(envelope) = envelope else {
+                        line_buf.clear();
+                        conn = None;
+                        outage = Some(Outage::begin(&mut stdout));
+                        continue;
+                    };
+
+                    // Events replayed after a reconnect may arrive again live
+                    if envelope.seq < next_seq {
+                        continue;
+                    }
+                    // Check for sequence gaps (log warning but continue)
+                    if envelope.seq > next_seq && next_seq > 0 {
+                        tracing::warn!(
+                            expected = next_seq,
+                            got = envelope.seq,
+                            "Event sequence gap detected"
+                        );
+                    }
+                    next_seq = envelope.seq + 1;
+
+                    if initial_input.is_some() && shows_pty_ready(&envelope.event, &session_id) {
+                        if let Some((initial, _)) = initial_input.take() {
+                            initial_input_deadline = None;
+                            send_or_queue_input(&mut conn, &mut outage, &mut stdout, &session_id, initial).await;
+                        }
+                    }
+
+                    if let IpcEvent::SessionInputHolder { session_id: sid, holder } = &envelope.event {
+                        if *sid == session_id {
+                            claimed = holder.as_deref() == Some(self.client_id.as_str());
+                            let text = match holder {
+                                _ if claimed => "you have the input, Ctrl+T to give it back".to_string(),
+                                Some(holder) => format!("input taken by {}", holder),
+                                None => "input back with the session's owner".to_string(),
+                            };
+                            draw_status_line(&mut stdout, &text);
+                            notice_until = Some(Instant::now() + NOTICE_DURATION);
+                        }
+                    }
+
+                    // e.g. the shell of a session kept on exit exiting
+                    if let IpcEvent::Notice { message, session_id: Some(sid), .. } = &envelope.event {
+                        if *sid == session_id {
+                            draw_status_line(&mut stdout, message);
+                            notice_until = Some(Instant::now() + NOTICE_DURATION);
+                        }
+                    }
+
+                    if let Some(end) = apply_event(envelope.event, &session_id, &mut stdout, &mut log)? {
+                        break end;
+                    }
+                }
+
+                // Flush output that has sat in the log's buffer long enough
+                _ = sleep_until_opt(log.as_ref().and_then(SessionLog::flush_deadline)) => {
+                    flush_log(&mut log);
+                }
+
+                // Stop waiting for the PTY and send the initial input
+                _ = sleep_until_opt(initial_input_deadline) => {
+                    initial_input_deadline = None;
+                    if let Some((initial, _)) = initial_input.take() {
+                        tracing::debug!("No sign of the session being ready, sending initial input");
+                        send_or_queue_input(&mut conn, &mut outage, &mut stdout, &session_id, initial).await;
+                    }
+                }
+
+                // Try to reconnect while disconnected
+                _ = sleep_until_retry(outage.as_ref()) => {
+                    let attempt = tokio::time::timeout(
+                        RECONNECT_ATTEMPT_TIMEOUT,
+                        reconnect(
+                            &self.address,
+                            &self.client_id,
+                            self.epoch_id.as_deref(),
+                            &session_id,
+                            mirror,
+                            &mut next_seq,
+                            &mut stdout,
+                            &mut log,
+                        ),
+                    )
+                    .await;
+
+                    match attempt {
+                        Ok(Ok(Reconnected::Attached { conn: mut c, missed_output })) => {
+                            // A claim goes with the connection that made it
+                            claimed = false;
+                            let o = outage.take().expect("retrying without an outage");
+                            let size = if mirror { None } else { terminal_size };
+                            let sent = o.flush(&mut c, &session_id, size).await;
+                            if let Err(e) = sent {
+                                tracing::debug!("Reconnected connection failed again: {}", e);
+                                outage = Some(o.restart(&mut stdout));
+                                continue;
+                            }
+
+                            let notice = match (missed_output, o.dropped_input) {
+                                (true, 0) => Some("reconnected, some output was missed".to_string()),
+                                (true, n) => Some(format!(
+                                    "reconnected, some output was missed and {} bytes of input were dropped",
+                                    n
+                                )),
+                                (false, 0) => None,
+                                (false, n) => Some(format!("reconnected, {} bytes of input were dropped", n)),
+                            };
+                            match notice {
+                                Some(text) => {
+                                    draw_status_line(&mut stdout, &text);
+                                    notice_until = Some(Instant::now() + NOTICE_DURATION);
+                                }
+                                None => clear_status_line(&mut stdout),
+                            }
+                            conn = Some(c);
+                        }
+                        Ok(Ok(Reconnected::Ended(end))) => {
+                            clear_status_line(&mut stdout);
+                            break end;
+                        }
+                        Ok(Err(e)) => {
+                            tracing::debug!("Reconnect attempt failed: {}", e);
+                            if let Some(end) = retry_or_give_up(&mut outage, &mut stdout) {
+                                break end;
+                            }
                         }
+                        Err(_) => {
+                            tracing::debug!("Reconnect attempt timed out");
+                            if let Some(end) = retry_or_give_up(&mut outage, &mut stdout) {
+                                break end;
+                            }
+                        }
+                    }
+                }
+
+                // Take down the post-reconnect notice
+                _ = sleep_until_opt(notice_until) => {
+                    notice_until = None;
+                    if outage.is_none() {
+                        clear_status_line(&mut stdout);
                     }
                 }
             }
-        }
+        };
 
         // Cleanup
         event_handle.abort();
-        stdout.execute(LeaveAlternateScreen)?;
-        disable_raw_mode()?;
+        drop(terminal_guard);
+        finish_log(log);
+
+        Ok(end)
+    }
+
+    /// Run the session with local stdin piped in instead of a terminal
+    ///
+    /// Stdin is forwarded byte for byte as it's read, and the session's
+    /// output is written to stdout unchanged; the local terminal isn't put in
+    /// raw mode. When stdin ends the session is closed once its output has
+    /// been quiet for [`EOF_CLOSE_DELAY`], unless `close_on_eof` is false, in
+    /// which case it runs until the remote program exits. A dropped
+    /// connection isn't re-established.
+    pub async fn run_piped(self, close_on_eof: bool) -> Result<SessionEnd> {
+        let on_input_end = if close_on_eof {
+            InputEnd::CloseSession
+        } else {
+            InputEnd::KeepRunning
+        };
+        self.pipe(
+            std::io::stdin(),
+            std::io::stdout(),
+            std::io::stderr(),
+            on_input_end,
+        )
+        .await
+    }
+
+    /// Run a session created without a PTY (see
+    /// [`OrchestratorClient::create_pipe_session`])
+    ///
+    /// Like [`Self::run_piped`], but the session's stderr goes to local
+    /// stderr, and when stdin ends the remote shell's stdin is closed too.
+    /// Returns once the shell exits, with its exit code.
+    pub async fn run_without_pty(self) -> Result<SessionEnd> {
+        self.pipe(
+            std::io::stdin(),
+            std::io::stdout(),
+            std::io::stderr(),
+            InputEnd::CloseStdin,
+        )
+        .await
+    }
+
+    /// [`Self::run_piped`] with the input and output streams given
+    async fn pipe(
+        self,
+        mut input: impl std::io::Read + Send + 'static,
+        mut stdout: impl std::io::Write,
+        mut stderr: impl std::io::Write,
+        on_input_end: InputEnd,
+    ) -> Result<SessionEnd> {
+        let session_id = self.session_id;
+        let mut next_seq = self.last_seq;
+        let mut conn = AttachedConnection::new(self.stream);
+        let mut log = self.log;
+
+        // Blocking reads on a plain thread: one still waiting for input
+        // when the session ends doesn't hold up process exit
+        let (input_tx, mut input_rx) = mpsc::channel::<Vec<u8>>(16);
+        std::thread::spawn(move || {
+            let mut buf = vec![0u8; PIPED_INPUT_CHUNK];
+            loop {
+                match input.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if input_tx.blocking_send(buf[..n].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        tracing::warn!("Error reading stdin: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut stdin_open = true;
+        let mut close_at: Option<Instant> = None;
+        let mut closing = false;
+        let mut line_buf = String::new();
+
+        let end = loop {
+            tokio::select! {
+                chunk = input_rx.recv(), if stdin_open => match chunk {
+                    Some(data) => {
+                        let request = IpcRequest::SessionInput {
+                            session_id: session_id.clone(),
+                            data,
+                        };
+                        if let Err(e) = conn.send(&request).await {
+                            tracing::warn!("Error writing to IPC: {}", e);
+                            break SessionEnd::ConnectionLost;
+                        }
+                    }
+                    None => {
+                        stdin_open = false;
+                        match on_input_end {
+                            InputEnd::CloseSession => {
+                                close_at = Some(Instant::now() + EOF_CLOSE_DELAY);
+                            }
+                            InputEnd::KeepRunning => {}
+                            InputEnd::CloseStdin => {
+                                let request = IpcRequest::SessionInput {
+                                    session_id: session_id.clone(),
+                                    data: Vec::new(),
+                                };
+                                if let Err(e) = conn.send(&request).await {
+                                    tracing::warn!("Error writing to IPC: {}", e);
+                                    break SessionEnd::ConnectionLost;
+                                }
+                            }
+                        }
+                    }
+                },
 
+                result = conn.reader.read_line(&mut line_buf) => {
+                    match result {
+                        Ok(0) => break SessionEnd::ConnectionLost,
+                        Ok(_) => {}
+                        Err(e) => {
+                            tracing::warn!("Error reading from IPC: {}", e);
+                            break SessionEnd::ConnectionLost;
+                        }
+                    }
+                    let parsed = serde_json::from_str::<IpcEventEnvelope>(&line_buf);
+                    line_buf.clear();
+                    // Anything else is a response to our input
+                    let Ok(envelope) = parsed else {
+                        continue;
+                    };
+                    if envelope.seq < next_seq {
+                        continue;
+                    }
+                    next_seq = envelope.seq + 1;
+
+                    let is_output = matches!(
+                        &envelope.event,
+                        IpcEvent::TerminalOutput { session_id: sid, .. } if *sid == session_id
+                    );
+                    let end = match &envelope.event {
+                        IpcEvent::TerminalOutput {
+                            session_id: sid,
+                            data,
+                            stream: OutputStream::Stderr,
+                        } if *sid == session_id => {
+                            stderr.write_all(data)?;
+                            stderr.flush()?;
+                            log_output(&mut log, data);
+                            None
+                        }
+                        _ => apply_event(envelope.event, &session_id, &mut stdout, &mut log)?,
+                    };
+                    match end {
+                        Some(SessionEnd::Exited { .. }) if closing => break SessionEnd::InputEnded,
+                        Some(end) => break end,
+                        None => {}
+                    }
+                    if is_output {
+                        if let Some(at) = close_at.as_mut() {
+                            *at = Instant::now() + EOF_CLOSE_DELAY;
+                        }
+                    }
+                }
+
+                _ = sleep_until_opt(log.as_ref().and_then(SessionLog::flush_deadline)) => {
+                    flush_log(&mut log);
+                }
+
+                // Input ended and output has gone quiet
+                _ = sleep_until_opt(close_at) => {
+                    close_at = None;
+                    closing = true;
+                    let request = IpcRequest::CloseSession {
+                        session_id: session_id.clone(),
+                        force: false,
+                    };
+                    if let Err(e) = conn.send(&request).await {
+                        tracing::warn!("Error writing to IPC: {}", e);
+                        break SessionEnd::ConnectionLost;
+                    }
+                    // Keep reading until the session reports it closed
+                }
+            }
+        };
+        finish_log(log);
+
+        Ok(end)
+    }
+
+    /// Measure keystroke echo round trips and output throughput.
+    ///
+    /// Waits for the shell to go quiet, types `echo_samples` characters one
+    /// at a time, timing each until its echo arrives, then runs `command` and
+    /// counts its output for `duration` after discarding the first `warmup`.
+    /// The command is interrupted with Ctrl+C afterwards, but the session is
+    /// left open for the caller to close.
+    pub async fn bench(
+        self,
+        command: &str,
+        echo_samples: usize,
+        warmup: Duration,
+        duration: Duration,
+    ) -> Result<BenchSamples> {
+        let mut reader = BenchReader {
+            conn: AttachedConnection::new(self.stream),
+            session_id: self.session_id,
+            next_seq: self.last_seq,
+            line: String::new(),
+        };
+        let mut samples = BenchSamples::default();
+
+        // Let the shell print its prompt
+        reader.wait_quiet().await?;
+
+        for _ in 0..echo_samples {
+            reader.input(b"x").await?;
+            let sent = Instant::now();
+            reader
+                .next_output_until(sent + BENCH_STEP_TIMEOUT)
+                .await?
+                .context("No echo from the remote shell")?;
+            samples.echo.push(sent.elapsed());
+        }
+        // Erase what was typed (Ctrl+U)
+        reader.input(b"\x15").await?;
+        reader.wait_quiet().await?;
+
+        let mut line = command.as_bytes().to_vec();
+        line.push(b'\r');
+        reader.input(&line).await?;
+
+        let warm_until = Instant::now() + warmup;
+        while reader.next_output_until(warm_until).await?.is_some() {}
+
+        let start = Instant::now();
+        let until = start + duration;
+        let mut last_chunk = None;
+        while let Some(len) = reader.next_output_until(until).await? {
+            let now = Instant::now();
+            if let Some(last) = last_chunk {
+                samples.chunk_gaps.push(now - last);
+            }
+            last_chunk = Some(now);
+            samples.bytes += len as u64;
+        }
+        samples.elapsed = start.elapsed();
+
+        // Stop the command (Ctrl+C)
+        if let Err(e) = reader.input(b"\x03").await {
+            tracing::debug!("Failed to interrupt benchmark command: {}", e);
+        }
+        Ok(samples)
+    }
+}
+
+/// Timings collected by [`TerminalSession::bench`]
+#[derive(Debug, Clone, Default)]
+pub struct BenchSamples {
+    /// Keystroke echo round trips
+    pub echo: Vec<Duration>,
+    /// Gaps between consecutive output chunks while measuring throughput
+    pub chunk_gaps: Vec<Duration>,
+    /// Output bytes received while measuring throughput
+    pub bytes: u64,
+    /// How long throughput was measured for
+    pub elapsed: Duration,
+}
+
+/// How long output must stay quiet for the shell to count as idle
+const BENCH_QUIET: Duration = Duration::from_millis(300);
+
+/// Longest wait for one echo, or for the shell to go idle
+const BENCH_STEP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reads a benchmarked session's output off an attached connection
+struct BenchReader {
+    conn: AttachedConnection,
+    session_id: String,
+    next_seq: u64,
+    /// Partially read line, kept when a read times out
+    line: String,
+}
+
+impl BenchReader {
+    async fn input(&mut self, data: &[u8]) -> Result<()> {
+        self.conn
+            .send(&IpcRequest::SessionInput {
+                session_id: self.session_id.clone(),
+                data: data.to_vec(),
+            })
+            .await
+    }
+
+    /// Wait for the next output chunk and return its length, or `None` if
+    /// nothing arrives by `deadline`
+    async fn next_output_until(&mut self, deadline: Instant) -> Result<Option<usize>> {
+        loop {
+            let read =
+                tokio::time::timeout_at(deadline, self.conn.reader.read_line(&mut self.line));
+            let Ok(result) = read.await else {
+                return Ok(None);
+            };
+            if result? == 0 {
+                anyhow::bail!("Connection to orchestrator lost");
+            }
+            let parsed = serde_json::from_str::<IpcEventEnvelope>(&self.line);
+            self.line.clear();
+            // Anything else is a response to our input
+            let Ok(envelope) = parsed else {
+                continue;
+            };
+            if envelope.seq < self.next_seq {
+                continue;
+            }
+            self.next_seq = envelope.seq + 1;
+
+            match envelope.event {
+                IpcEvent::TerminalOutput {
+                    session_id, data, ..
+                } if session_id == self.session_id => {
+                    return Ok(Some(data.len()));
+                }
+                IpcEvent::SessionClosed {
+                    session_id, reason, ..
+                } if session_id == self.session_id => {
+                    let reason = reason.map(|r| format!(" ({})", r)).unwrap_or_default();
+                    anyhow::bail!("Session closed during the benchmark{}", reason);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Wait until output has been quiet for [`BENCH_QUIET`], giving up on
+    /// quiet after [`BENCH_STEP_TIMEOUT`]
+    async fn wait_quiet(&mut self) -> Result<()> {
+        let give_up = Instant::now() + BENCH_STEP_TIMEOUT;
+        while Instant::now() < give_up {
+            let quiet_until = (Instant::now() + BENCH_QUIET).min(give_up);
+            if self.next_output_until(quiet_until).await?.is_none() {
+                break;
+            }
+        }
         Ok(())
     }
 }
 
+/// The IPC connection of an attached terminal session
+struct AttachedConnection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: BufWriter<OwnedWriteHalf>,
+}
+
+impl AttachedConnection {
+    fn new(stream: TcpStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+        }
+    }
+
+    /// Send a request as a JSON line
+    async fn send(&mut self, request: &IpcRequest) -> Result<()> {
+        let mut json = serde_json::to_string(request)?;
+        json.push('\n');
+        self.writer.write_all(json.as_bytes()).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// Read the response to the last request.
+    ///
+    /// Events arriving first are collected into `events` instead.
+    async fn read_response(&mut self, events: &mut Vec<IpcEventEnvelope>) -> Result<IpcResponse> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.reader.read_line(&mut line).await? == 0 {
+                anyhow::bail!("Connection closed");
+            }
+            if let Ok(envelope) = serde_json::from_str::<IpcEventEnvelope>(&line) {
+                events.push(envelope);
+                continue;
+            }
+            return Ok(serde_json::from_str(&line)?);
+        }
+    }
+}
+
+/// State kept while the connection to the orchestrator is down
+struct Outage {
+    started: Instant,
+    next_attempt: Instant,
+    delay: Duration,
+    /// Input typed while disconnected, sent after reconnecting
+    queued_input: Vec<u8>,
+    /// Bytes of input that didn't fit in the queue
+    dropped_input: usize,
+}
+
+impl Outage {
+    /// Start an outage and show the status line
+    fn begin(out: &mut impl std::io::Write) -> Self {
+        let now = Instant::now();
+        let outage = Self {
+            started: now,
+            next_attempt: now,
+            delay: RECONNECT_INITIAL_DELAY,
+            queued_input: Vec::new(),
+            dropped_input: 0,
+        };
+        outage.draw(out);
+        outage
+    }
+
+    /// Continue an outage after a connection failed right after reconnecting
+    fn restart(mut self, out: &mut impl std::io::Write) -> Self {
+        self.schedule_retry();
+        self.draw(out);
+        self
+    }
+
+    /// Queue input typed while disconnected, dropping what doesn't fit
+    fn queue_input(&mut self, data: &[u8]) {
+        if self.queued_input.len() + data.len() <= MAX_QUEUED_INPUT {
+            self.queued_input.extend_from_slice(data);
+        } else {
+            self.dropped_input += data.len();
+        }
+    }
+
+    /// Schedule the next attempt with exponential backoff
+    fn schedule_retry(&mut self) {
+        self.next_attempt = Instant::now() + self.delay;
+        self.delay = (self.delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+
+    /// Check whether the reconnect window has run out
+    fn expired(&self) -> bool {
+        self.started.elapsed() >= RECONNECT_WINDOW
+    }
+
+    /// Show the outage on the status line
+    fn draw(&self, out: &mut impl std::io::Write) {
+        let text = if self.dropped_input > 0 {
+            format!(
+                "connection lost, reconnecting... (input dropped: {} bytes; Ctrl+] to detach)",
+                self.dropped_input
+            )
+        } else if !self.queued_input.is_empty() {
+            format!(
+                "connection lost, reconnecting... ({} bytes of input queued; Ctrl+] to detach)",
+                self.queued_input.len()
+            )
+        } else {
+            "connection lost, reconnecting... (Ctrl+] to detach)".to_string()
+        };
+        draw_status_line(out, &text);
+    }
+
+    /// Send the queued input and latest terminal size on a new connection
+    async fn flush(
+        &self,
+        conn: &mut AttachedConnection,
+        session_id: &str,
+        terminal_size: Option<(u16, u16)>,
+    ) -> Result<()> {
+        if let Some((cols, rows)) = terminal_size {
+            conn.send(&resize_request(session_id, cols, rows)).await?;
+        }
+        if !self.queued_input.is_empty() {
+            let request = IpcRequest::SessionInput {
+                session_id: session_id.to_string(),
+                data: self.queued_input.clone(),
+            };
+            conn.send(&request).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of a successful reconnect attempt
+enum Reconnected {
+    /// Attached again; `missed_output` is set if the replay was incomplete
+    Attached {
+        conn: AttachedConnection,
+        missed_output: bool,
+    },
+    /// The session ended (or became unreachable) while we were away
+    Ended(SessionEnd),
+}
+
+/// Reconnect, re-subscribe (as an observer if `mirror`) and replay the
+/// events missed since `next_seq`
+#[allow(clippy::too_many_arguments)]
+async fn reconnect(
+    address: &str,
+    client_id: &str,
+    epoch_id: Option<&str>,
+    session_id: &str,
+    mirror: bool,
+    next_seq: &mut u64,
+    stdout: &mut impl std::io::Write,
+    log: &mut Option<SessionLog>,
+) -> Result<Reconnected> {
+    let mut client =
+        OrchestratorClient::with_address(address.to_string()).with_client_id(client_id.to_string());
+    client.connect().await?;
+
+    // A new epoch means the orchestrator restarted and the session is gone
+    if epoch_id.is_some() && client.epoch_id() != epoch_id {
+        tracing::warn!("Orchestrator restarted while disconnected");
+        return Ok(Reconnected::Ended(SessionEnd::ConnectionLost));
+    }
+
+    let stream = client
+        .take_stream()
+        .ok_or_else(|| anyhow::anyhow!("No connection"))?;
+    let mut conn = AttachedConnection::new(stream);
+    let mut live = Vec::new();
+
+    let subscribe = if mirror {
+        IpcRequest::ObserveSession {
+            session_id: session_id.to_string(),
+        }
+    } else {
+        IpcRequest::Subscribe {
+            session_id: session_id.to_string(),
+        }
+    };
+    conn.send(&subscribe).await?;
+    match conn.read_response(&mut live).await? {
+        IpcResponse::Ok | IpcResponse::Subscribed { .. } => {}
+        IpcResponse::Error { message } if message.starts_with("Session not found") => {
+            // Closed while we were away and its close event already evicted
+            return Ok(Reconnected::Ended(SessionEnd::Exited {
+                exit_code: None,
+                reason: None,
+            }));
+        }
+        IpcResponse::Error { message } => anyhow::bail!("{}", message),
+        other => anyhow::bail!("Unexpected response: {:?}", other),
+    }
+
+    conn.send(&IpcRequest::GetEventsSince {
+        since_seq: next_seq.saturating_sub(1),
+    })
+    .await?;
+    let (replayed, truncated) = match conn.read_response(&mut live).await? {
+        IpcResponse::EventsSince {
+            events, truncated, ..
+        } => (events, truncated),
+        // Older orchestrators can't replay; carry on with live output
+        _ => (Vec::new(), true),
+    };
+
+    for envelope in replay_order(replayed, live, *next_seq) {
+        *next_seq = envelope.seq + 1;
+        if let Some(end) = apply_event(envelope.event, session_id, stdout, log)? {
+            return Ok(Reconnected::Ended(end));
+        }
+    }
+
+    Ok(Reconnected::Attached {
+        conn,
+        missed_output: truncated,
+    })
+}
+
+/// Merge replayed and live events into sequence order, without duplicates
+/// or events from before `next_seq`
+fn replay_order(
+    replayed: Vec<IpcEventEnvelope>,
+    live: Vec<IpcEventEnvelope>,
+    next_seq: u64,
+) -> Vec<IpcEventEnvelope> {
+    let mut events: Vec<_> = replayed
+        .into_iter()
+        .chain(live)
+        .filter(|e| e.seq >= next_seq)
+        .collect();
+    events.sort_by_key(|e| e.seq);
+    events.dedup_by_key(|e| e.seq);
+    events
+}
+
+/// Write terminal output for our session (and log it), or report that it
+/// ended
+fn apply_event(
+    event: IpcEvent,
+    session_id: &str,
+    stdout: &mut impl std::io::Write,
+    log: &mut Option<SessionLog>,
+) -> Result<Option<SessionEnd>> {
+    match event {
+        IpcEvent::TerminalOutput {
+            session_id: sid,
+            data,
+            ..
+        } if sid == session_id => {
+            stdout.write_all(&data)?;
+            stdout.flush()?;
+            log_output(log, &data);
+        }
+        IpcEvent::SessionClosed {
+            session_id: sid,
+            exit_code,
+            reason,
+        } if sid == session_id => {
+            return Ok(Some(SessionEnd::Exited { exit_code, reason }));
+        }
+        _ => {}
+    }
+    Ok(None)
+}
+
+/// Append output to the session log
+///
+/// A log that can't be written to is given up on rather than ending the
+/// session.
+fn log_output(log: &mut Option<SessionLog>, data: &[u8]) {
+    if let Some(l) = log.as_mut() {
+        if let Err(e) = l.write(data) {
+            tracing::warn!("Error writing session log, no longer logging: {}", e);
+            *log = None;
+        }
+    }
+}
+
+fn flush_log(log: &mut Option<SessionLog>) {
+    if let Some(l) = log.as_mut() {
+        if let Err(e) = l.flush() {
+            tracing::warn!("Error writing session log, no longer logging: {}", e);
+            *log = None;
+        }
+    }
+}
+
+fn finish_log(log: Option<SessionLog>) {
+    if let Some(Err(e)) = log.map(SessionLog::finish) {
+        tracing::warn!("Error writing session log: {}", e);
+    }
+}
+
+/// Whether `event` shows the session's PTY exists: the orchestrator
+/// reporting its PID, or output from it
+fn shows_pty_ready(event: &IpcEvent, session_id: &str) -> bool {
+    match event {
+        IpcEvent::SessionCreated(info) => info.id == session_id && info.pid.is_some(),
+        IpcEvent::TerminalOutput {
+            session_id: sid, ..
+        } => sid == session_id,
+        _ => false,
+    }
+}
+
+/// Send input to the session, or queue it while disconnected
+///
+/// A failed send starts an outage with the input queued for when the
+/// connection is back.
+async fn send_or_queue_input(
+    conn: &mut Option<AttachedConnection>,
+    outage: &mut Option<Outage>,
+    stdout: &mut impl std::io::Write,
+    session_id: &str,
+    data: Vec<u8>,
+) {
+    if let Some(o) = outage.as_mut() {
+        o.queue_input(&data);
+        o.draw(stdout);
+    } else if let Some(c) = conn.as_mut() {
+        let request = IpcRequest::SessionInput {
+            session_id: session_id.to_string(),
+            data: data.clone(),
+        };
+        if let Err(e) = c.send(&request).await {
+            tracing::warn!("Error writing to IPC: {}", e);
+            *conn = None;
+            let mut o = Outage::begin(stdout);
+            o.queue_input(&data);
+            *outage = Some(o);
+        }
+    }
+}
+
+/// Schedule another reconnect attempt, or give up once the window has passed
+fn retry_or_give_up(
+    outage: &mut Option<Outage>,
+    out: &mut impl std::io::Write,
+) -> Option<SessionEnd> {
+    let o = outage.as_mut()?;
+    if o.expired() {
+        clear_status_line(out);
+        return Some(SessionEnd::ConnectionLost);
+    }
+    o.schedule_retry();
+    o.draw(out);
+    None
+}
+
+/// Read the next line from the connection, or wait forever without one
+async fn read_event_line(
+    conn: Option<&mut AttachedConnection>,
+    buf: &mut String,
+) -> std::io::Result<usize> {
+    match conn {
+        Some(conn) => conn.reader.read_line(buf).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for the next reconnect attempt, or forever while connected
+async fn sleep_until_retry(outage: Option<&Outage>) {
+    sleep_until_opt(outage.map(|o| o.next_attempt)).await
+}
+
+/// Sleep until `deadline`, or forever without one
+async fn sleep_until_opt(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Holder named by an `InputHeld` response line, i.e. input we sent was
+/// refused because another client has the session's input
+fn input_held_by(line: &str) -> Option<String> {
+    match serde_json::from_str::<IpcResponse>(line) {
+        Ok(IpcResponse::InputHeld { holder, .. }) => Some(holder),
+        _ => None,
+    }
+}
+
+fn resize_request(session_id: &str, cols: u16, rows: u16) -> IpcRequest {
+    IpcRequest::SessionResize {
+        session_id: session_id.to_string(),
+        cols,
+        rows,
+    }
+}
+
+/// Show `text` dimmed on the terminal's bottom row, keeping the cursor where it was
+fn draw_status_line(out: &mut impl std::io::Write, text: &str) {
+    use crossterm::{
+        cursor::{MoveTo, RestorePosition, SavePosition},
+        style::{Attribute, Print, SetAttribute},
+        terminal::{size, Clear, ClearType},
+        QueueableCommand,
+    };
+
+    let Ok((cols, rows)) = size() else {
+        return;
+    };
+    let text: String = format!(" k-terminus: {}", text)
+        .chars()
+        .take(cols as usize)
+        .collect();
+    // Drawing is best effort; a failed write shows up as an IPC error soon enough
+    let _ = out
+        .queue(SavePosition)
+        .and_then(|o| o.queue(MoveTo(0, rows.saturating_sub(1))))
+        .and_then(|o| o.queue(Clear(ClearType::CurrentLine)))
+        .and_then(|o| o.queue(SetAttribute(Attribute::Dim)))
+        .and_then(|o| o.queue(Print(text)))
+        .and_then(|o| o.queue(SetAttribute(Attribute::Reset)))
+        .and_then(|o| o.queue(RestorePosition))
+        .and_then(|o| o.flush());
+}
+
+/// Erase the status line drawn by [`draw_status_line`]
+fn clear_status_line(out: &mut impl std::io::Write) {
+    use crossterm::{
+        cursor::{MoveTo, RestorePosition, SavePosition},
+        terminal::{size, Clear, ClearType},
+        QueueableCommand,
+    };
+
+    let Ok((_, rows)) = size() else {
+        return;
+    };
+    let _ = out
+        .queue(SavePosition)
+        .and_then(|o| o.queue(MoveTo(0, rows.saturating_sub(1))))
+        .and_then(|o| o.queue(Clear(ClearType::CurrentLine)))
+        .and_then(|o| o.queue(RestorePosition))
+        .and_then(|o| o.flush());
+}
+
 /// Convert a key event to bytes to send to the terminal
 fn key_to_bytes(code: KeyCode, modifiers: KeyModifiers) -> Vec<u8> {
     use KeyCode::*;
@@ -550,3 +2219,420 @@ fn key_to_bytes(code: KeyCode, modifiers: KeyModifiers) -> Vec<u8> {
         _ => vec![],
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(seq: u64, data: &str) -> IpcEventEnvelope {
+        IpcEventEnvelope {
+            seq,
+            timestamp: 0,
+            event: IpcEvent::TerminalOutput {
+                session_id: "s".to_string(),
+                data: data.as_bytes().to_vec(),
+                stream: OutputStream::Stdout,
+            },
+            session_seq: None,
+        }
+    }
+
+    #[test]
+    fn test_local_terminal() {
+        assert_eq!(
+            local_terminal(Some("xterm-256color"), Some("truecolor")),
+            (Some("xterm-256color".to_string()), true)
+        );
+        assert_eq!(
+            local_terminal(Some("tmux-256color"), Some("24bit")),
+            (Some("tmux-256color".to_string()), true)
+        );
+        assert_eq!(local_terminal(Some("bad term"), None), (None, false));
+        assert_eq!(local_terminal(None, Some("yes")), (None, false));
+    }
+
+    #[test]
+    fn test_replay_order_merges_and_dedups() {
+        let replayed = vec![output(4, "a"), output(5, "b"), output(6, "c")];
+        let live = vec![output(6, "c"), output(3, "old"), output(7, "d")];
+
+        let seqs: Vec<_> = replay_order(replayed, live, 4)
+            .iter()
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(seqs, vec![4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_outage_queues_then_drops_input() {
+        let mut outage = Outage::begin(&mut Vec::new());
+        outage.queue_input(&[b'x'; MAX_QUEUED_INPUT - 1]);
+        outage.queue_input(b"y");
+        outage.queue_input(b"zz");
+
+        assert_eq!(outage.queued_input.len(), MAX_QUEUED_INPUT);
+        assert_eq!(outage.dropped_input, 2);
+    }
+
+    #[test]
+    fn test_shows_pty_ready() {
+        let created = |id: &str, pid| {
+            IpcEvent::SessionCreated(SessionInfo {
+                id: id.to_string(),
+                machine_id: "m".to_string(),
+                shell: None,
+                created_at: String::new(),
+                pid,
+                size: None,
+                name: None,
+                bytes_in: 0,
+                bytes_out: 0,
+                detached: false,
+                input_bytes_per_min: 0,
+                input_requests_per_min: 0,
+            })
+        };
+        assert!(shows_pty_ready(&created("s", Some(42)), "s"));
+        assert!(!shows_pty_ready(&created("s", None), "s"));
+        assert!(!shows_pty_ready(&created("other", Some(42)), "s"));
+        assert!(shows_pty_ready(&output(1, "$ ").event, "s"));
+        assert!(!shows_pty_ready(&output(1, "$ ").event, "other"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_suspend_ignored_while_attached() {
+        fn sigtstp_handling() -> libc::sighandler_t {
+            // SAFETY: only reads the current action
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                libc::sigaction(libc::SIGTSTP, std::ptr::null(), &mut action);
+                action.sa_sigaction
+            }
+        }
+
+        let before = sigtstp_handling();
+        let guard = SuspendGuard::ignore();
+        assert_eq!(sigtstp_handling(), libc::SIG_IGN);
+        drop(guard);
+        assert_eq!(sigtstp_handling(), before);
+
+        // Ctrl+Z goes to the session instead
+        assert_eq!(
+            key_to_bytes(KeyCode::Char('z'), KeyModifiers::CONTROL),
+            vec![0x1a]
+        );
+    }
+
+    #[test]
+    fn test_input_held_by() {
+        let held = serde_json::to_string(&IpcResponse::InputHeld {
+            session_id: "s".to_string(),
+            holder: "laptop".to_string(),
+        })
+        .unwrap();
+        assert_eq!(input_held_by(&held), Some("laptop".to_string()));
+        let ok = serde_json::to_string(&IpcResponse::Ok).unwrap();
+        assert_eq!(input_held_by(&ok), None);
+        assert_eq!(input_held_by("not json"), None);
+    }
+
+    #[tokio::test]
+    async fn test_initial_input_sent_verbatim() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let command = "cd '/my project' && echo \"$HOME\" \\ | grep -E 'ü|\t'; ls *\n";
+
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(address).await.unwrap();
+            let mut conn = Some(AttachedConnection::new(stream));
+            let mut outage = None;
+            let data = command.as_bytes().to_vec();
+            send_or_queue_input(&mut conn, &mut outage, &mut Vec::new(), "s", data).await;
+            assert!(outage.is_none());
+        });
+
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut conn = AttachedConnection::new(stream);
+        let mut line = String::new();
+        conn.reader.read_line(&mut line).await.unwrap();
+        client.await.unwrap();
+        match serde_json::from_str(&line).unwrap() {
+            IpcRequest::SessionInput { session_id, data } => {
+                assert_eq!(session_id, "s");
+                assert_eq!(data, command.as_bytes());
+            }
+            other => panic!("unexpected request: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_piped_input_passes_through_and_closes_on_eof() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let input = b"line\r\n\x00\x03\xffbinary".to_vec();
+
+        let expected = input.clone();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = AttachedConnection::new(stream);
+            let mut received = Vec::new();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                conn.reader.read_line(&mut line).await.unwrap();
+                match serde_json::from_str(&line).unwrap() {
+                    IpcRequest::SessionInput { data, .. } => {
+                        received.extend_from_slice(&data);
+                        let echo = IpcEventEnvelope {
+                            seq: 1,
+                            timestamp: 0,
+                            event: IpcEvent::TerminalOutput {
+                                session_id: "s".to_string(),
+                                data,
+                                stream: OutputStream::Stdout,
+                            },
+                            session_seq: None,
+                        };
+                        let json = serde_json::to_string(&echo).unwrap() + "\n";
+                        conn.writer.write_all(json.as_bytes()).await.unwrap();
+                        conn.writer.flush().await.unwrap();
+                    }
+                    IpcRequest::CloseSession { session_id, .. } => {
+                        let closed = IpcEventEnvelope {
+                            seq: 2,
+                            timestamp: 0,
+                            event: IpcEvent::SessionClosed {
+                                session_id,
+                                exit_code: Some(137),
+                                reason: Some(CloseReason::UserRequested),
+                            },
+                            session_seq: None,
+                        };
+                        let json = serde_json::to_string(&closed).unwrap() + "\n";
+                        conn.writer.write_all(json.as_bytes()).await.unwrap();
+                        conn.writer.flush().await.unwrap();
+                        return received;
+                    }
+                    other => panic!("unexpected request {:?}", other),
+                }
+            }
+        });
+
+        let session = TerminalSession {
+            session_id: "s".to_string(),
+            stream: TcpStream::connect(&address).await.unwrap(),
+            last_seq: 0,
+            address,
+            client_id: "test".to_string(),
+            epoch_id: None,
+            initial_input: None,
+            log: None,
+            motd: None,
+            mirror: false,
+        };
+        let mut output = Vec::new();
+        let end = session
+            .pipe(
+                std::io::Cursor::new(input),
+                &mut output,
+                std::io::sink(),
+                InputEnd::CloseSession,
+            )
+            .await
+            .unwrap();
+
+        // Closing the session ourselves isn't a failure of the remote program
+        assert_eq!(end, SessionEnd::InputEnded);
+        assert_eq!(server.await.unwrap(), expected);
+        assert_eq!(output, expected);
+    }
+    #[tokio::test]
+    async fn test_pipe_without_pty_splits_streams_and_closes_stdin() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let reason = CloseReason::ProcessExited { code: Some(3) };
+
+        let server_reason = reason.clone();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = AttachedConnection::new(stream);
+            let mut line = String::new();
+            let mut seq = 0;
+            loop {
+                line.clear();
+                conn.reader.read_line(&mut line).await.unwrap();
+                let IpcRequest::SessionInput { session_id, data } =
+                    serde_json::from_str(&line).unwrap()
+                else {
+                    panic!("unexpected request {}", line);
+                };
+                // Empty input closes stdin; the program then exits
+                let done = data.is_empty();
+                let events = if done {
+                    vec![IpcEvent::SessionClosed {
+                        session_id,
+                        exit_code: Some(3),
+                        reason: Some(server_reason.clone()),
+                    }]
+                } else {
+                    [OutputStream::Stdout, OutputStream::Stderr]
+                        .into_iter()
+                        .map(|stream| IpcEvent::TerminalOutput {
+                            session_id: session_id.clone(),
+                            data: data.clone(),
+                            stream,
+                        })
+                        .collect()
+                };
+                for event in events {
+                    seq += 1;
+                    let envelope = IpcEventEnvelope {
+                        seq,
+                        timestamp: 0,
+                        event,
+                        session_seq: None,
+                    };
+                    let json = serde_json::to_string(&envelope).unwrap() + "\n";
+                    conn.writer.write_all(json.as_bytes()).await.unwrap();
+                }
+                conn.writer.flush().await.unwrap();
+                if done {
+                    return;
+                }
+            }
+        });
+
+        let session = TerminalSession {
+            session_id: "s".to_string(),
+            stream: TcpStream::connect(&address).await.unwrap(),
+            last_seq: 0,
+            address,
+            client_id: "test".to_string(),
+            epoch_id: None,
+            initial_input: None,
+            log: None,
+            motd: None,
+            mirror: false,
+        };
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let end = session
+            .pipe(
+                std::io::Cursor::new(b"data".to_vec()),
+                &mut stdout,
+                &mut stderr,
+                InputEnd::CloseStdin,
+            )
+            .await
+            .unwrap();
+        server.await.unwrap();
+
+        assert_eq!(
+            end,
+            SessionEnd::Exited {
+                exit_code: Some(3),
+                reason: Some(reason)
+            }
+        );
+        assert_eq!(stdout, b"data");
+        assert_eq!(stderr, b"data");
+    }
+
+    /// Client connected to a fake orchestrator that answers every request
+    /// and reports each request's type
+    async fn cache_test_client() -> (OrchestratorClient, mpsc::UnboundedReceiver<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (seen_tx, seen_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut conn = AttachedConnection::new(stream);
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if conn.reader.read_line(&mut line).await.unwrap() == 0 {
+                    return;
+                }
+                let request: serde_json::Value = serde_json::from_str(&line).unwrap();
+                let response = match request["type"].as_str().unwrap() {
+                    "list_machines" => IpcResponse::Machines { machines: vec![] },
+                    "list_sessions" => IpcResponse::Sessions {
+                        sessions: vec![],
+                        total: None,
+                        next_cursor: None,
+                    },
+                    _ => IpcResponse::Ok,
+                };
+                let _ = seen_tx.send(request["type"].as_str().unwrap().to_string());
+                let json = serde_json::to_string(&response).unwrap() + "\n";
+                conn.writer.write_all(json.as_bytes()).await.unwrap();
+                conn.writer.flush().await.unwrap();
+            }
+        });
+
+        let mut client = OrchestratorClient::with_address(address.clone());
+        client.stream = Some(TcpStream::connect(&address).await.unwrap());
+        client.authenticated = true;
+        (client, seen_rx)
+    }
+
+    fn drain(rx: &mut mpsc::UnboundedReceiver<String>) -> Vec<String> {
+        let mut seen = Vec::new();
+        while let Ok(request) = rx.try_recv() {
+            seen.push(request);
+        }
+        seen
+    }
+
+    #[tokio::test]
+    async fn test_cache_reuses_identical_queries_within_ttl() {
+        let (client, mut seen) = cache_test_client().await;
+        let mut client = client.with_cache_ttl(Duration::from_millis(200));
+
+        client.list_machines().await.unwrap();
+        client.list_machines().await.unwrap();
+        client.list_sessions(Some("a")).await.unwrap();
+        client.list_sessions(Some("a")).await.unwrap();
+        // Different parameters are a different query
+        client.list_sessions(Some("b")).await.unwrap();
+        assert_eq!(
+            drain(&mut seen),
+            ["list_machines", "list_sessions", "list_sessions"]
+        );
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        client.list_machines().await.unwrap();
+        assert_eq!(drain(&mut seen), ["list_machines"]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_never_holds_mutating_requests() {
+        let (client, mut seen) = cache_test_client().await;
+        let mut client = client.with_cache_ttl(Duration::from_secs(60));
+
+        client.list_machines().await.unwrap();
+        client.kill_session("s", false).await.unwrap();
+        client.kill_session("s", false).await.unwrap();
+        // The kill cleared the cache, so the listing is fetched again
+        client.list_machines().await.unwrap();
+        assert_eq!(
+            drain(&mut seen),
+            [
+                "list_machines",
+                "close_session",
+                "close_session",
+                "list_machines"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_off_by_default() {
+        let (mut client, mut seen) = cache_test_client().await;
+
+        client.list_machines().await.unwrap();
+        client.list_machines().await.unwrap();
+        assert_eq!(drain(&mut seen), ["list_machines", "list_machines"]);
+    }
+}
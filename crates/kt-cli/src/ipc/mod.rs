@@ -9,11 +9,14 @@
 //! numbers for gap detection.
 
 mod client;
+mod session_log;
 
-pub use client::{OrchestratorClient, TerminalSession};
+pub use client::{BenchSamples, OrchestratorClient, SessionEnd, SessionLifetime, TerminalSession};
+pub use session_log::SessionLog;
 
 // Re-export constants and types from kt_core
 pub use kt_core::ipc::{
-    default_ipc_address, IpcEventEnvelope, MachineInfo, MachineStatus, OrchestratorStatus,
-    SessionInfo, DEFAULT_IPC_PORT,
+    default_ipc_address, CloseReason, GroupAction, GroupInfo, IpcEventEnvelope, MachineInfo,
+    MachineStatus, OrchestratorCapabilities, OrchestratorStatus, SessionDetails, SessionEnvVar,
+    SessionInfo, SessionLifecycle, DEFAULT_IPC_PORT,
 };
@@ -0,0 +1,213 @@
+//! Local log of a session's output (`--log`)
+//!
+//! Every chunk of output received while attached is appended to the log
+//! file, stdout and stderr alike, including output replayed after a
+//! reconnect. By default the bytes are written exactly as received, escape
+//! sequences and all, so `cat`ing the file replays the session in a
+//! terminal. [`SessionLog::with_ansi_stripped`] keeps only the text instead,
+//! and [`SessionLog::with_timestamps`] starts each line with the time its
+//! first byte arrived.
+//!
+//! Writes are buffered and flushed at most [`LOG_FLUSH_INTERVAL`] after
+//! they were made; [`SessionLog::finish`] flushes the rest.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use tokio::time::Instant;
+
+use kt_core::ansi::{AnsiStripper, LineEndings};
+use kt_core::time::format_iso8601;
+
+/// Longest written output waits in the buffer before being flushed
+pub const LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends a session's output to a file
+pub struct SessionLog<W: Write = File> {
+    out: BufWriter<W>,
+    /// Set when escape sequences are stripped
+    stripper: Option<AnsiStripper>,
+    timestamps: bool,
+    /// The next byte written starts a line
+    at_line_start: bool,
+    /// When the oldest unflushed write was made
+    unflushed_since: Option<Instant>,
+}
+
+impl SessionLog {
+    /// Open `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open log file {}", path.display()))?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write> SessionLog<W> {
+    /// Log to `out`, writing output as received
+    pub fn new(out: W) -> Self {
+        Self {
+            out: BufWriter::new(out),
+            stripper: None,
+            timestamps: false,
+            at_line_start: true,
+            unflushed_since: None,
+        }
+    }
+
+    /// Start every line with an ISO 8601 timestamp in brackets
+    pub fn with_timestamps(mut self) -> Self {
+        self.timestamps = true;
+        self
+    }
+
+    /// Drop escape sequences and control characters, keeping plain text
+    /// with `\n` line endings
+    pub fn with_ansi_stripped(mut self) -> Self {
+        self.stripper = Some(AnsiStripper::new().with_line_endings(LineEndings::Lf));
+        self
+    }
+
+    /// Append a chunk of output, flushing if earlier writes have waited
+    /// [`LOG_FLUSH_INTERVAL`]
+    pub fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.write_at(data, SystemTime::now())?;
+        if self.flush_deadline().is_some_and(|at| at <= Instant::now()) {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// When buffered output is due to be flushed, if there is any
+    pub fn flush_deadline(&self) -> Option<Instant> {
+        self.unflushed_since.map(|since| since + LOG_FLUSH_INTERVAL)
+    }
+
+    /// Write buffered output to the file
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.unflushed_since = None;
+        self.out.flush()
+    }
+
+    /// Write anything the stripper held back and flush, closing the log
+    pub fn finish(mut self) -> std::io::Result<()> {
+        if let Some(text) = self.stripper.as_mut().map(AnsiStripper::finish) {
+            self.append(text.as_bytes(), SystemTime::now())?;
+        }
+        self.flush()
+    }
+
+    /// [`Self::write`] with the time the chunk arrived given
+    fn write_at(&mut self, data: &[u8], now: SystemTime) -> std::io::Result<()> {
+        match self.stripper.as_mut() {
+            Some(stripper) => {
+                let text = stripper.feed(data);
+                self.append(text.as_bytes(), now)
+            }
+            None => self.append(data, now),
+        }
+    }
+
+    fn append(&mut self, data: &[u8], now: SystemTime) -> std::io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        if self.unflushed_since.is_none() {
+            self.unflushed_since = Some(Instant::now());
+        }
+        if !self.timestamps {
+            return self.out.write_all(data);
+        }
+        for line in data.split_inclusive(|&b| b == b'\n') {
+            if self.at_line_start {
+                write!(self.out, "[{}] ", format_iso8601(now))?;
+            }
+            self.out.write_all(line)?;
+            self.at_line_start = line.ends_with(b"\n");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_714_579_200 + secs)
+    }
+
+    /// Feed `chunks`, one second apart, and finish the log
+    fn logged(log: SessionLog<&mut Vec<u8>>, chunks: &[&[u8]]) {
+        let mut log = log;
+        for (i, chunk) in chunks.iter().enumerate() {
+            log.write_at(chunk, at(i as u64)).unwrap();
+        }
+        log.finish().unwrap();
+    }
+
+    #[test]
+    fn test_raw_output_is_kept_as_is() {
+        let mut buf = Vec::new();
+        logged(
+            SessionLog::new(&mut buf),
+            &[b"\x1b[31mred\x1b[0m\r\n", b"50%\r100%"],
+        );
+        assert_eq!(buf, b"\x1b[31mred\x1b[0m\r\n50%\r100%");
+    }
+
+    #[test]
+    fn test_timestamps_frame_lines_across_chunks() {
+        let mut buf = Vec::new();
+        logged(
+            SessionLog::new(&mut buf).with_timestamps(),
+            &[b"one\r\ntw", b"o\r\n", b"\nthree"],
+        );
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[2024-05-01T16:00:00Z] one\r\n\
+             [2024-05-01T16:00:00Z] two\r\n\
+             [2024-05-01T16:00:02Z] \n\
+             [2024-05-01T16:00:02Z] three"
+        );
+    }
+
+    #[test]
+    fn test_strips_escape_sequences_split_across_chunks() {
+        let mut buf = Vec::new();
+        logged(
+            SessionLog::new(&mut buf)
+                .with_ansi_stripped()
+                .with_timestamps(),
+            &[b"\x1b[1mbold\x1b[", b"0m\r", b"\n\x1b]0;title\x07$ \r"],
+        );
+        // The trailing carriage return is only written on finish
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[2024-05-01T16:00:00Z] bold\n[2024-05-01T16:00:02Z] $ \r"
+        );
+    }
+
+    #[test]
+    fn test_flush_deadline() {
+        let mut buf = Vec::new();
+        let mut log = SessionLog::new(&mut buf);
+        assert!(log.flush_deadline().is_none());
+        log.write_at(b"", at(0)).unwrap();
+        assert!(log.flush_deadline().is_none());
+        log.write_at(b"x", at(0)).unwrap();
+        assert!(log.flush_deadline().is_some());
+        log.flush().unwrap();
+        assert!(log.flush_deadline().is_none());
+        drop(log);
+        assert_eq!(buf, b"x");
+    }
+}
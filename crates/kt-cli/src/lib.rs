@@ -4,5 +4,6 @@
 //! and interacting with remote sessions.
 
 pub mod commands;
+pub mod exit_code;
 pub mod ipc;
 pub mod output;
@@ -7,18 +7,23 @@
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use tokio::sync::{mpsc, Mutex};
 use tokio_util::sync::CancellationToken;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
 use k_terminus::commands;
-use k_terminus::ipc::OrchestratorClient;
-use k_terminus::output::{print_error, print_info, print_success, print_warning};
-use kt_core::config::{self, AgentConfig, ConfigFile, OrchestratorConfig};
-use kt_core::{auto_setup, is_initialized};
+use k_terminus::exit_code;
+use k_terminus::ipc::{OrchestratorClient, SessionLifetime};
+use k_terminus::output::{
+    print_connect_progress, print_error, print_info, print_success, print_warning,
+};
+use kt_core::config::{self, AgentConfig, ConfigFile, ConfigLoader, LogFormat, OrchestratorConfig};
+use kt_core::{auto_setup, is_initialized, TokenGuard};
+use kt_orchestrator::logging::{self, LogFileLayer, LogFileSettings, LogSource};
 
 #[derive(Parser)]
 #[command(name = "k-terminus")]
@@ -37,6 +42,30 @@ struct Cli {
     #[arg(short, long, global = true)]
     quiet: bool,
 
+    /// Fail instead of starting an orchestrator when none is running
+    #[arg(long, global = true)]
+    no_autostart: bool,
+
+    /// Reuse responses to identical status and list queries for this many
+    /// milliseconds (off by default)
+    #[arg(long, global = true, value_name = "MS")]
+    cache_ttl: Option<u64>,
+
+    /// Own sessions as this logical client ID instead of one per process,
+    /// reclaiming those an earlier process with the ID left behind
+    #[arg(long, global = true, value_name = "ID")]
+    client_id: Option<String>,
+
+    /// Take the client ID over from another process still connected with
+    /// it, disconnecting that process
+    #[arg(long, global = true, requires = "client_id")]
+    force_takeover: bool,
+
+    /// Close this process's sessions when it disconnects, instead of
+    /// keeping them for it to reclaim
+    #[arg(long, global = true)]
+    close_on_disconnect: bool,
+
     #[command(subcommand)]
     command: Option<Commands>,
 }
@@ -53,6 +82,12 @@ enum Commands {
         /// Bind address (overrides config)
         #[arg(short, long)]
         bind: Option<String>,
+        /// Log file format: text or json (overrides config)
+        #[arg(long, value_name = "FORMAT")]
+        log_format: Option<LogFormat>,
+        /// Serve IPC clients only, without the SSH listener (agents can't connect)
+        #[arg(long)]
+        ipc_only: bool,
     },
 
     /// Stop orchestrator
@@ -75,11 +110,15 @@ enum Commands {
         /// Run in foreground with verbose output
         #[arg(short, long)]
         foreground: bool,
+        /// Join even if the orchestrator is on this machine (for local
+        /// testing)
+        #[arg(long)]
+        allow_self: bool,
     },
 
     /// List connected machines and sessions
     List {
-        /// Filter by machine name/alias
+        /// Filter by machine name/alias, or `@group` for a group's members
         #[arg(short, long)]
         machine: Option<String>,
         /// Filter by tag
@@ -88,6 +127,24 @@ enum Commands {
         /// Show detailed information
         #[arg(short, long)]
         long: bool,
+        /// List sessions (of the selected machines) instead of machines
+        #[arg(long)]
+        sessions: bool,
+        /// Sort by this column (see --columns)
+        #[arg(long, value_name = "COLUMN")]
+        sort: Option<String>,
+        /// Sort in descending order
+        #[arg(short, long, requires = "sort")]
+        reverse: bool,
+        /// Show only these columns, in this order. Machines: id, alias,
+        /// label, hostname, os, arch, status, sessions, connected, reconnects,
+        /// heartbeat. Sessions: id, machine, shell, pid, name, created, traffic
+        #[arg(long, value_name = "COLUMNS", value_delimiter = ',')]
+        columns: Option<Vec<String>>,
+        /// Print tables for reading (`human`), or CSV of just the machines
+        /// or sessions for spreadsheets (`csv`)
+        #[arg(short, long, value_name = "FORMAT", value_parser = ["human", "csv"], default_value = "human")]
+        output: String,
     },
 
     /// Create new session on machine and attach
@@ -97,12 +154,84 @@ enum Commands {
         /// Shell to spawn (overrides machine default)
         #[arg(short, long)]
         shell: Option<String>,
+        /// When stdin is piped, keep the session running after the input
+        /// ends instead of closing it
+        #[arg(long)]
+        no_close_on_eof: bool,
+        /// Run the shell on plain pipes instead of a PTY: no terminal control
+        /// codes, stderr kept on stderr, and the shell's exit code returned
+        /// once stdin ends
+        #[arg(long, conflicts_with = "no_close_on_eof")]
+        no_pty: bool,
+        /// Agent log level for this session's lifecycle (create, resize,
+        /// close), independent of the agent's own level
+        #[arg(long, value_name = "LEVEL", value_parser = ["error", "warn", "info", "debug", "trace"])]
+        log_level: Option<String>,
+        /// Command to run in the new shell before handing it over, e.g.
+        /// `cd /project && source env`
+        #[arg(long, conflicts_with = "no_pty")]
+        command: Option<String>,
+        /// Attach to the tmux session of this name on the machine, creating
+        /// it if needed, instead of staying in a bare shell
+        #[arg(
+            long,
+            value_name = "SESSION",
+            num_args = 0..=1,
+            default_missing_value = commands::DEFAULT_MULTIPLEXER_SESSION,
+            value_parser = commands::parse_multiplexer_session,
+            conflicts_with_all = ["command", "no_pty", "screen"]
+        )]
+        tmux: Option<String>,
+        /// Attach to the GNU screen session of this name on the machine,
+        /// creating it if needed
+        #[arg(
+            long,
+            value_name = "SESSION",
+            num_args = 0..=1,
+            default_missing_value = commands::DEFAULT_MULTIPLEXER_SESSION,
+            value_parser = commands::parse_multiplexer_session,
+            conflicts_with_all = ["command", "no_pty"]
+        )]
+        screen: Option<String>,
+        /// Close the session this long after it's created, whatever it's
+        /// doing, e.g. `10m`
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration_arg)]
+        ttl: Option<Duration>,
+        /// Keep the session open after its shell exits, until it's killed
+        /// or its TTL runs out
+        #[arg(long)]
+        keep_on_exit: bool,
+        /// Append everything the session outputs to this file, escape
+        /// sequences included
+        #[arg(long, value_name = "PATH")]
+        log: Option<PathBuf>,
+        /// Start each line in the log with the time it arrived
+        #[arg(long, requires = "log")]
+        log_timestamps: bool,
+        /// Strip escape sequences from the log, keeping plain text
+        #[arg(long, requires = "log")]
+        strip_ansi: bool,
     },
 
     /// Attach to an existing session
     Attach {
-        /// Session ID to attach to
+        /// Session ID, or machine selector: `nas:last`, `nas:1` (oldest), or
+        /// `nas` for its only session
         session: String,
+        /// Append everything the session outputs to this file, escape
+        /// sequences included
+        #[arg(long, value_name = "PATH")]
+        log: Option<PathBuf>,
+        /// Start each line in the log with the time it arrived
+        #[arg(long, requires = "log")]
+        log_timestamps: bool,
+        /// Strip escape sequences from the log, keeping plain text
+        #[arg(long, requires = "log")]
+        strip_ansi: bool,
+        /// Watch without taking the input, until Ctrl+T claims it; the
+        /// session needn't be yours
+        #[arg(long)]
+        mirror: bool,
     },
 
     /// Show orchestrator status and health
@@ -112,9 +241,18 @@ enum Commands {
         detailed: bool,
     },
 
+    /// Show the protocol version of this binary, and with `--online`
+    /// check the running orchestrator can talk to it (e.g. before upgrading)
+    Verify {
+        /// Compare against the running orchestrator (doesn't start one)
+        #[arg(long)]
+        online: bool,
+    },
+
     /// Terminate a session
     Kill {
-        /// Session identifier(s) to kill
+        /// Session ID(s) or machine selectors (`gpu-box:last`, `gpu-box:1`,
+        /// or `gpu-box` for its only session)
         #[arg(required = true)]
         sessions: Vec<String>,
         /// Force kill without confirmation
@@ -122,27 +260,212 @@ enum Commands {
         force: bool,
     },
 
+    /// Show the environment a session was created with
+    Env {
+        /// Session ID to inspect
+        session: String,
+        /// Show values of sensitive-looking variables (tokens, keys, passwords)
+        #[arg(long)]
+        show_secrets: bool,
+    },
+
+    /// Inspect sessions
+    Session {
+        #[command(subcommand)]
+        action: SessionAction,
+    },
+
+    /// Check file permissions and leftovers of crashed orchestrators, and
+    /// repair what can be fixed
+    Doctor {
+        /// Remove token and PID files left by an orchestrator that is no
+        /// longer running
+        #[arg(long)]
+        fix: bool,
+    },
+
     /// Manage configuration
     Config {
         #[command(subcommand)]
         action: ConfigAction,
     },
+
+    /// Refuse new sessions while keeping existing ones, e.g. before
+    /// upgrading agents
+    Maintenance {
+        #[command(subcommand)]
+        action: MaintenanceAction,
+    },
+
+    /// Manage the IPC authentication token
+    Token {
+        #[command(subcommand)]
+        action: TokenAction,
+    },
+
+    /// Manage machine groups (selected as `@name`)
+    Group {
+        #[command(subcommand)]
+        action: GroupAction,
+    },
+
+    /// Read the orchestrator's event journal (see `event_journal_path`)
+    Journal {
+        #[command(subcommand)]
+        action: JournalAction,
+    },
+
+    /// Package the config, keys, machine profiles and groups into a bundle,
+    /// to move the orchestrator to another machine
+    Export {
+        /// Bundle file to write
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Encrypt the bundle with this passphrase
+        #[arg(long, env = "K_TERMINUS_BUNDLE_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+    },
+
+    /// Restore a bundle written by `export`
+    Import {
+        /// Bundle file to read
+        bundle: PathBuf,
+        /// Passphrase the bundle was encrypted with
+        #[arg(long, env = "K_TERMINUS_BUNDLE_PASSPHRASE", hide_env_values = true)]
+        passphrase: Option<String>,
+        /// Replace an existing setup
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Measure throughput and latency to a machine (for development)
+    #[command(hide = true)]
+    Bench {
+        /// Machine identifier (name, alias, or ID)
+        machine: String,
+        /// Shell to spawn (overrides machine default)
+        #[arg(short, long)]
+        shell: Option<String>,
+        /// Command whose output is measured
+        #[arg(long, default_value = commands::DEFAULT_BENCH_COMMAND)]
+        command: String,
+        /// How long to measure throughput for (e.g. "10s")
+        #[arg(long, default_value = "10s", value_parser = parse_duration_arg)]
+        duration: Duration,
+        /// Output discarded before measuring starts
+        #[arg(long, default_value = "2s", value_parser = parse_duration_arg)]
+        warmup: Duration,
+        /// Keystroke echo round trips to time
+        #[arg(long, default_value_t = 50)]
+        echo_samples: usize,
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Parse a duration argument like "10s", "1m30s" or plain seconds
+fn parse_duration_arg(value: &str) -> std::result::Result<Duration, String> {
+    kt_core::time::parse_duration(value).ok_or_else(|| format!("invalid duration: {}", value))
 }
 
 #[derive(Subcommand)]
 enum ConfigAction {
     /// Show current configuration
-    Show,
+    Show {
+        /// Show every effective value and where it came from (default,
+        /// file, environment)
+        #[arg(long)]
+        origins: bool,
+    },
     /// Get specific config value
     Get { key: String },
-    /// Set config value
-    Set { key: String, value: String },
+    /// Set config value, or add to or remove from an array with
+    /// `KEY += VALUE` / `KEY -= VALUE`
+    Set {
+        key: String,
+        /// VALUE, += VALUE or -= VALUE
+        #[arg(num_args = 1..=2, required = true, allow_hyphen_values = true)]
+        value: Vec<String>,
+    },
     /// Edit config in editor
     Edit,
     /// Show config directory path
     Path,
 }
 
+#[derive(Subcommand)]
+enum SessionAction {
+    /// Show everything the orchestrator knows about a session, including
+    /// one closed in the last few minutes
+    Info {
+        /// Session ID or machine selector (`gpu-box:last`, `gpu-box:1`, or
+        /// `gpu-box` for its only session)
+        session: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum JournalAction {
+    /// Print the events in a journal file, one per line
+    Replay {
+        /// Journal file (or one of its rotations, `<file>.1`...)
+        file: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum MaintenanceAction {
+    /// Refuse new sessions until turned off
+    On {
+        /// Reason shown to clients whose sessions are refused
+        #[arg(short, long)]
+        message: Option<String>,
+    },
+    /// Take new sessions again
+    Off,
+}
+
+#[derive(Subcommand)]
+enum TokenAction {
+    /// Replace the token with a new one without restarting the orchestrator
+    Rotate,
+}
+
+#[derive(Subcommand)]
+enum GroupAction {
+    /// List groups and their members
+    List,
+    /// Create a group
+    Create {
+        /// Group name
+        group: String,
+        /// Machine IDs or aliases
+        members: Vec<String>,
+    },
+    /// Add machines to a group
+    Add {
+        /// Group name
+        group: String,
+        /// Machine IDs or aliases
+        #[arg(required = true)]
+        members: Vec<String>,
+    },
+    /// Remove machines from a group
+    Remove {
+        /// Group name
+        group: String,
+        /// Machine IDs or aliases
+        #[arg(required = true)]
+        members: Vec<String>,
+    },
+    /// Delete a group
+    Delete {
+        /// Group name
+        group: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -156,11 +479,50 @@ async fn main() -> Result<()> {
         (false, _) => "trace",
     };
 
+    // A foreground orchestrator also logs to a file (at info unless RUST_LOG
+    // says otherwise) so IPC clients can tail and follow its logs
+    let log_capture = match &cli.command {
+        Some(Commands::Serve {
+            foreground: true,
+            log_format,
+            ..
+        }) => {
+            let mut settings =
+                LogFileSettings::load(cli.config.as_deref(), logging::default_log_path());
+            if let Some(format) = log_format {
+                settings.format = *format;
+            }
+            match LogFileLayer::open(&settings) {
+                Ok(layer) => Some((layer, LogSource::new(Some(settings.path)))),
+                Err(e) => {
+                    print_warning(&format!(
+                        "Failed to open log file {:?}: {}",
+                        settings.path, e
+                    ));
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let log_source = log_capture.as_ref().map(|(_, source)| source.clone());
+    let capture_layer = log_capture.map(|(file_layer, source)| {
+        file_layer.and_then(source.broadcast_layer()).with_filter(
+            tracing_subscriber::EnvFilter::new(
+                std::env::var("RUST_LOG").unwrap_or_else(|_| "info".into()),
+            ),
+        )
+    });
+
     tracing_subscriber::registry()
-        .with(tracing_subscriber::EnvFilter::new(
-            std::env::var("RUST_LOG").unwrap_or_else(|_| log_level.into()),
-        ))
-        .with(tracing_subscriber::fmt::layer().with_target(false))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_target(false)
+                .with_filter(tracing_subscriber::EnvFilter::new(
+                    std::env::var("RUST_LOG").unwrap_or_else(|_| log_level.into()),
+                )),
+        )
+        .with(capture_layer)
         .init();
 
     // Auto-setup on first run (for most commands)
@@ -197,13 +559,47 @@ async fn main() -> Result<()> {
 
     // Create IPC client for management commands
     let mut client = OrchestratorClient::new();
+    if let Some(ttl) = cli.cache_ttl {
+        client = client.with_cache_ttl(Duration::from_millis(ttl));
+    }
+    if let Some(client_id) = cli.client_id.clone() {
+        client = client
+            .with_client_id(client_id)
+            .with_force_takeover(cli.force_takeover);
+    }
+    client = client.with_close_on_disconnect(cli.close_on_disconnect);
+    let autostart = AutoStart {
+        disabled: cli.no_autostart,
+        config_path: cli.config.clone(),
+    };
 
     match command {
-        Commands::Serve { foreground, bind } => {
-            run_orchestrator(foreground, bind, cli.config.as_ref()).await?;
+        Commands::Serve {
+            foreground,
+            bind,
+            log_format,
+            ipc_only,
+        } => {
+            run_orchestrator(
+                foreground,
+                bind,
+                log_format,
+                ipc_only,
+                cli.config.as_ref(),
+                log_source,
+            )
+            .await?;
         }
 
         Commands::Stop => {
+            if let Ok(status) = client.status().await {
+                if status.owner == kt_core::OrchestratorOwner::DesktopEmbedded {
+                    print_warning(
+                        "This orchestrator is embedded in k-Terminus Desktop. Stopping it \
+                         disconnects the desktop app and ends its sessions.",
+                    );
+                }
+            }
             print_info("Stopping orchestrator...");
             match client.shutdown().await {
                 Ok(()) => {
@@ -227,37 +623,157 @@ async fn main() -> Result<()> {
             alias,
             key,
             foreground,
+            allow_self,
         } => {
-            run_join(target.as_deref(), alias.as_deref(), key, foreground).await?;
+            let verbose = cli.verbose > 0;
+            run_join(
+                target.as_deref(),
+                alias.as_deref(),
+                key,
+                foreground,
+                allow_self,
+                verbose,
+            )
+            .await?;
         }
 
-        Commands::List { machine, tag, long } => {
-            ensure_orchestrator_running().await?;
-            commands::list_command(&mut client, machine.as_deref(), tag.as_deref(), long).await?;
+        Commands::List {
+            machine,
+            tag,
+            long,
+            sessions,
+            sort,
+            reverse,
+            columns,
+            output,
+        } => {
+            let output = match output.as_str() {
+                "csv" => commands::ListOutput::Csv,
+                _ => commands::ListOutput::Human,
+            };
+            let view = commands::ListView {
+                sessions,
+                sort,
+                reverse,
+                columns,
+                output,
+            };
+            view.check()?;
+            ensure_orchestrator_running(&autostart).await?;
+            let code = commands::list_command(
+                &mut client,
+                machine.as_deref(),
+                tag.as_deref(),
+                long,
+                &view,
+            )
+            .await?;
+            if code != exit_code::OK {
+                std::process::exit(code);
+            }
         }
 
-        Commands::Connect { machine, shell } => {
-            ensure_orchestrator_running().await?;
-            commands::connect_command(client, &machine, shell.as_deref()).await?;
+        Commands::Connect {
+            machine,
+            shell,
+            no_close_on_eof,
+            no_pty,
+            log_level,
+            command,
+            tmux,
+            screen,
+            ttl,
+            keep_on_exit,
+            log,
+            log_timestamps,
+            strip_ansi,
+        } => {
+            ensure_orchestrator_running(&autostart).await?;
+            let log = commands::open_session_log(log.as_deref(), log_timestamps, strip_ansi)?;
+            let multiplexer = tmux
+                .map(commands::Multiplexer::Tmux)
+                .or(screen.map(commands::Multiplexer::Screen));
+            let code = commands::connect_command(
+                client,
+                &machine,
+                shell.as_deref(),
+                !no_close_on_eof,
+                !no_pty,
+                log_level.as_deref(),
+                command.as_deref(),
+                multiplexer.as_ref(),
+                SessionLifetime { ttl, keep_on_exit },
+                log,
+            )
+            .await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
 
-        Commands::Attach { session } => {
-            ensure_orchestrator_running().await?;
-            commands::attach_command(client, &session).await?;
+        Commands::Attach {
+            session,
+            log,
+            log_timestamps,
+            strip_ansi,
+            mirror,
+        } => {
+            ensure_orchestrator_running(&autostart).await?;
+            let log = commands::open_session_log(log.as_deref(), log_timestamps, strip_ansi)?;
+            let code = commands::attach_command(client, &session, log, mirror).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
 
         Commands::Status { detailed } => {
-            ensure_orchestrator_running().await?;
-            commands::status_command(&mut client, detailed).await?;
+            let code = match ensure_orchestrator_running(&autostart).await {
+                Ok(()) => commands::status_command(&mut client, detailed).await?,
+                Err(e) => {
+                    print_error(&e.to_string());
+                    exit_code::NOT_RUNNING
+                }
+            };
+            if code != exit_code::OK {
+                std::process::exit(code);
+            }
+        }
+
+        Commands::Verify { online } => {
+            let code = commands::verify_command(&mut client, online).await?;
+            if code != exit_code::OK {
+                std::process::exit(code);
+            }
         }
 
         Commands::Kill { sessions, force } => {
             commands::kill_command(&mut client, &sessions, force).await?;
         }
 
+        Commands::Env {
+            session,
+            show_secrets,
+        } => {
+            commands::env_command(&mut client, &session, show_secrets).await?;
+        }
+
+        Commands::Session { action } => match action {
+            SessionAction::Info { session } => {
+                commands::session_info_command(&mut client, &session).await?;
+            }
+        },
+
+        Commands::Doctor { fix } => {
+            commands::doctor_command(&mut client, cli.config.as_ref(), fix).await?;
+        }
+
         Commands::Config { action } => match action {
-            ConfigAction::Show => {
-                commands::config_show(cli.config.as_ref())?;
+            ConfigAction::Show { origins } => {
+                if origins {
+                    commands::config_show_origins(cli.config.as_ref())?;
+                } else {
+                    commands::config_show(cli.config.as_ref())?;
+                }
             }
             ConfigAction::Get { key } => {
                 commands::config_get(cli.config.as_ref(), &key)?;
@@ -273,6 +789,77 @@ async fn main() -> Result<()> {
                 println!("{}", path.display());
             }
         },
+
+        Commands::Maintenance { action } => {
+            let message = match action {
+                MaintenanceAction::On { message } => Some(message),
+                MaintenanceAction::Off => None,
+            };
+            commands::maintenance_command(&mut client, message).await?;
+        }
+
+        Commands::Token { action } => match action {
+            TokenAction::Rotate => {
+                commands::token_rotate_command(&mut client, cli.config.as_ref()).await?;
+            }
+        },
+
+        Commands::Journal { action } => match action {
+            JournalAction::Replay { file } => {
+                commands::journal_replay_command(&file)?;
+            }
+        },
+
+        Commands::Group { action } => {
+            use k_terminus::ipc::GroupAction as Change;
+
+            ensure_orchestrator_running(&autostart).await?;
+            let (group, change, members) = match action {
+                GroupAction::List => return commands::group_list_command(&mut client).await,
+                GroupAction::Create { group, members } => (group, Change::Create, members),
+                GroupAction::Add { group, members } => (group, Change::Add, members),
+                GroupAction::Remove { group, members } => (group, Change::Remove, members),
+                GroupAction::Delete { group } => (group, Change::Delete, Vec::new()),
+            };
+            commands::group_modify_command(&mut client, &group, change, &members).await?;
+        }
+
+        Commands::Export { out, passphrase } => {
+            let config_path = cli.config.as_deref();
+            commands::export_command(&mut client, config_path, &out, passphrase.as_deref())
+                .await?;
+        }
+
+        Commands::Import {
+            bundle,
+            passphrase,
+            force,
+        } => {
+            let config_path = cli.config.as_deref();
+            let passphrase = passphrase.as_deref();
+            commands::import_command(&mut client, config_path, &bundle, passphrase, force).await?;
+        }
+
+        Commands::Bench {
+            machine,
+            shell,
+            command,
+            duration,
+            warmup,
+            echo_samples,
+            json,
+        } => {
+            ensure_orchestrator_running(&autostart).await?;
+            let options = commands::BenchOptions {
+                shell,
+                command,
+                duration,
+                warmup,
+                echo_samples,
+                json,
+            };
+            commands::bench_command(client, &machine, &options).await?;
+        }
     }
 
     Ok(())
@@ -285,7 +872,10 @@ async fn main() -> Result<()> {
 async fn run_orchestrator(
     foreground: bool,
     bind_override: Option<String>,
+    log_format: Option<LogFormat>,
+    ipc_only: bool,
     config_path: Option<&PathBuf>,
+    log_source: Option<LogSource>,
 ) -> Result<()> {
     use kt_orchestrator::ipc::IpcServer;
     use kt_orchestrator::server::{load_or_generate_host_key, ConnectionEvent, SshServer};
@@ -299,6 +889,12 @@ async fn run_orchestrator(
         if let Some(bind) = &bind_override {
             cmd.arg("--bind").arg(bind);
         }
+        if let Some(format) = log_format {
+            cmd.arg("--log-format").arg(format.to_string());
+        }
+        if ipc_only {
+            cmd.arg("--ipc-only");
+        }
         if let Some(path) = config_path {
             cmd.arg("--config").arg(path);
         }
@@ -316,35 +912,8 @@ async fn run_orchestrator(
     // Foreground mode - run the orchestrator directly
     tracing::info!("k-Terminus Orchestrator starting...");
 
-    // Load configuration (wrapped in ConfigFile to handle [orchestrator] section)
-    let config: OrchestratorConfig = if let Some(config_path) = config_path {
-        let config_file: ConfigFile = config::load_config(config_path)
-            .with_context(|| format!("Failed to load config from {:?}", config_path))?;
-        config_file.orchestrator
-    } else {
-        let default_path = config::default_config_path();
-        if default_path.exists() {
-            let config_file: ConfigFile = config::load_config(&default_path).unwrap_or_else(|e| {
-                tracing::warn!("Failed to load config from {:?}: {}", default_path, e);
-                ConfigFile::default()
-            });
-            config_file.orchestrator
-        } else {
-            tracing::info!("Using default configuration");
-            OrchestratorConfig::default()
-        }
-    };
-
-    // Override bind address if specified
-    let bind_addr = bind_override.unwrap_or_else(|| config.bind_address.clone());
-
-    // Load or generate host key
-    let host_key = load_or_generate_host_key(&config.host_key_path).await?;
-    let public_key = host_key
-        .clone_public_key()
-        .context("Failed to extract public key from host key")?;
-    let host_key_fingerprint = public_key.fingerprint();
-    tracing::info!("Host key fingerprint: {}", host_key_fingerprint);
+    let config = load_orchestrator_config(config_path, bind_override.as_deref(), false)?;
+    let bind_addr = config.bind_address.clone();
 
     // Create orchestrator state
     let state = Arc::new(OrchestratorState::new(config.clone()));
@@ -385,11 +954,27 @@ async fn run_orchestrator(
 
     // Start IPC server for CLI/GUI communication
     let ipc_address = config.ipc_address();
-    let ipc_server = Arc::new(
-        IpcServer::new(ipc_address.clone(), Arc::clone(&state))?
-            .with_shutdown_token(cancel.clone()),
-    );
+    let mut ipc_server =
+        IpcServer::new(ipc_address, Arc::clone(&state))?.with_shutdown_token(cancel.clone());
+    // Remove the token we just acquired when shutting down (or panicking),
+    // so nothing takes it for a running orchestrator
+    let _token_guard = TokenGuard::for_default_path().context("Failed to locate IPC token file")?;
+    if let Some(log_source) = log_source {
+        ipc_server = ipc_server.with_log_source(log_source);
+    }
+    let ipc_server = Arc::new(ipc_server);
     let ipc_event_tx = ipc_server.event_sender();
+    let ipc_listener = ipc_server.bind().await?;
+    let ipc_local_addr = ipc_listener.local_addr()?;
+
+    // Reload the configuration on SIGHUP, the same way it was loaded
+    let config_path = config_path.cloned();
+    kt_orchestrator::reload::spawn_reload_on_hangup(
+        Arc::clone(&state),
+        move || load_orchestrator_config(config_path.as_ref(), bind_override.as_deref(), true),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    );
 
     // Spawn event handler that updates state and broadcasts IPC events
     let state_clone = Arc::clone(&state);
@@ -399,12 +984,26 @@ async fn run_orchestrator(
         }
     });
 
+    // Notify webhooks of lifecycle events
+    let _webhook_handle = kt_orchestrator::webhook::spawn_notifier(
+        config.webhook.clone(),
+        ipc_server.event_sender().subscribe(),
+        cancel.clone(),
+    );
+
+    // Journal events to disk, if configured
+    let _journal_handle = kt_orchestrator::journal::spawn_journal(
+        &config,
+        ipc_server.event_sender().subscribe(),
+        cancel.clone(),
+    );
+
     // Spawn IPC server task
     let ipc_server_clone = Arc::clone(&ipc_server);
     let cancel_ipc = cancel.clone();
     tokio::spawn(async move {
         tokio::select! {
-            result = ipc_server_clone.run() => {
+            result = ipc_server_clone.serve(ipc_listener) => {
                 if let Err(e) = result {
                     tracing::error!("IPC server error: {}", e);
                 }
@@ -414,35 +1013,92 @@ async fn run_orchestrator(
             }
         }
     });
-    tracing::info!("IPC server listening on {}", ipc_address);
 
     // Start health monitor
     let health_monitor = kt_orchestrator::connection::HealthMonitor::new(
-        config.heartbeat_interval,
-        config.heartbeat_timeout,
+        *config.heartbeat_interval,
+        *config.heartbeat_timeout,
+    );
+    let _health_handle = health_monitor.spawn(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
     );
-    let _health_handle = health_monitor.spawn(Arc::clone(&state), cancel.clone());
     tracing::info!(
-        "Health monitor started (interval={:?}, timeout={:?})",
+        "Health monitor started (interval={}, timeout={})",
         config.heartbeat_interval,
         config.heartbeat_timeout
     );
 
+    // Close orphaned sessions after their grace period, and sessions whose
+    // TTL ran out
+    tokio::spawn(kt_orchestrator::session::run_orphan_cleanup(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    ));
+
+    // Send the latest size of sessions resized in quick succession
+    tokio::spawn(kt_orchestrator::session::run_resize_flusher(
+        Arc::clone(&state),
+        cancel.clone(),
+    ));
+
+    // Remove machines that don't reconnect within their grace period
+    tokio::spawn(kt_orchestrator::connection::run_reconnect_grace(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    ));
+
+    if ipc_only {
+        tracing::warn!("IPC-only mode: SSH listener not started, agents can't connect");
+        println!();
+        println!("  \x1b[1;32mk-Terminus Orchestrator\x1b[0m (IPC only)");
+        println!();
+        println!("  IPC on: {}", ipc_local_addr);
+        println!("  SSH listener disabled, agents can't connect");
+        println!();
+
+        kt_orchestrator::readiness::notify_ready(None, ipc_local_addr, None);
+        cancel.cancelled().await;
+        kt_orchestrator::readiness::notify_stopping();
+
+        tracing::info!("Orchestrator shutdown complete");
+        return Ok(());
+    }
+
+    // Tighten permissions on secrets an older version may have left readable
+    kt_core::permissions::check_permissions(&[&config.host_key_path]);
+
+    // Load or generate host key
+    let host_key = load_or_generate_host_key(&config.host_key_path).await?;
+    let public_key = host_key
+        .clone_public_key()
+        .context("Failed to extract public key from host key")?;
+    let host_key_fingerprint = public_key.fingerprint();
+    tracing::info!("Host key fingerprint: {}", host_key_fingerprint);
+
     // Create and run SSH server
     let server = SshServer::new(host_key, Arc::clone(&state), cancel.clone(), event_tx);
 
+    tracing::info!("Starting SSH server on {}", bind_addr);
+    let ssh_listener = server.bind_configured().await?;
+    let ssh_local_addr = ssh_listener.local_addr()?;
+
     // Print connection info with pairing code
     let pairing_code = state.pairing_code();
-    if let Ok(Some(ts_info)) = kt_core::tailscale::get_tailscale_info() {
+    if let Ok(Some(ts_info)) = kt_core::tailscale::get_tailscale_info_async().await {
         if ts_info.logged_in {
-            let port = bind_addr.split(':').next_back().unwrap_or("2222");
+            let port = ssh_local_addr.port();
             println!();
             println!("  \x1b[1;32mk-Terminus Orchestrator\x1b[0m");
             println!();
-            println!(
-                "  Listening on: {}.{}:{}",
-                ts_info.device_name, ts_info.tailnet, port
+            let hostname = kt_core::tailscale::resolve_device_name(
+                &ts_info.device_name,
+                ts_info.tailnet_or(config.tailnet_domain.as_deref()),
             );
+            println!("  Listening on: {}:{}", hostname, port);
             println!();
             println!("  \x1b[1;36mPairing Code: {}\x1b[0m", pairing_code);
             println!();
@@ -456,25 +1112,64 @@ async fn run_orchestrator(
         println!();
         println!("  \x1b[1;32mk-Terminus Orchestrator\x1b[0m");
         println!();
-        println!("  Listening on: {}", bind_addr);
+        println!("  Listening on: {}", ssh_local_addr);
         println!();
         println!("  \x1b[1;36mPairing Code: {}\x1b[0m", pairing_code);
         println!();
     }
 
-    tracing::info!("Starting SSH server on {}", bind_addr);
-    server.run(&bind_addr).await?;
+    // Both listeners are bound; tell the supervisor we're ready
+    kt_orchestrator::readiness::notify_ready(Some(ssh_local_addr), ipc_local_addr, None);
+
+    server.serve(ssh_listener).await?;
+    kt_orchestrator::readiness::notify_stopping();
 
     tracing::info!("Orchestrator shutdown complete");
     Ok(())
 }
 
+/// Load the orchestrator configuration: defaults <- file <-
+/// KT_ORCHESTRATOR__* <- flags
+///
+/// A default config file that can't be read is skipped with a warning at
+/// startup, but fails a reload so a typo doesn't reset every setting.
+fn load_orchestrator_config(
+    config_path: Option<&PathBuf>,
+    bind_override: Option<&str>,
+    reloading: bool,
+) -> Result<OrchestratorConfig> {
+    let mut loader = ConfigLoader::<ConfigFile>::new();
+    if let Some(config_path) = config_path {
+        loader
+            .with_file(config_path)
+            .with_context(|| format!("Failed to load config from {:?}", config_path))?;
+    } else {
+        let default_path = config::default_config_path();
+        if default_path.exists() {
+            if let Err(e) = loader.with_file(&default_path) {
+                if reloading {
+                    return Err(e)
+                        .with_context(|| format!("Failed to load config from {:?}", default_path));
+                }
+                tracing::warn!("Failed to load config from {:?}: {}", default_path, e);
+            }
+        } else if !reloading {
+            tracing::info!("No config file, using defaults");
+        }
+    }
+    loader.with_env();
+    if let Some(bind) = bind_override {
+        loader.with_cli("orchestrator.bind_address", bind.to_string(), "--bind")?;
+    }
+    Ok(loader.load()?.orchestrator)
+}
+
 async fn handle_connection_event_with_ipc(
     state: &kt_orchestrator::OrchestratorState,
     event: kt_orchestrator::server::ConnectionEvent,
     ipc_event_tx: &tokio::sync::broadcast::Sender<kt_core::ipc::IpcEventEnvelope>,
 ) {
-    use kt_core::ipc::IpcEvent;
+    use kt_core::ipc::{CloseReason, IpcEvent};
     use kt_orchestrator::connection::TunnelConnection;
     use kt_orchestrator::server::ConnectionEvent;
 
@@ -482,64 +1177,95 @@ async fn handle_connection_event_with_ipc(
         ConnectionEvent::MachineConnected {
             machine_id,
             alias,
+            label,
             hostname,
             os,
             arch,
+            capabilities,
+            reconnect_count,
             command_tx,
             cancel,
         } => {
-            tracing::info!(
-                "Machine connected: {} (alias: {}, hostname: {}, os: {}, arch: {})",
-                machine_id,
-                alias,
-                hostname,
-                os,
-                arch
-            );
+            tracing::info!(%machine_id, %alias, %hostname, %os, %arch, "Machine connected");
 
+            // Back within its grace period, with its sessions
+            let reconnected = state.coordinator.connections.is_reconnecting(&machine_id);
             // Register in connection pool
-            state.coordinator.connections.insert(TunnelConnection::new(
-                machine_id.clone(),
-                Some(alias.clone()),
-                Some(hostname.clone()),
-                os.clone(),
-                arch.clone(),
-                command_tx,
-                cancel,
-            ));
-
-            // Broadcast to IPC clients (wrapped in envelope)
-            let event = IpcEvent::MachineConnected(kt_core::ipc::MachineInfo {
+            state.coordinator.connections.insert(
+                TunnelConnection::new(
+                    machine_id.clone(),
+                    Some(alias.clone()),
+                    Some(hostname.clone()),
+                    os.clone(),
+                    arch.clone(),
+                    command_tx,
+                    cancel,
+                )
+                .with_label(label.clone())
+                .with_capabilities(capabilities)
+                .with_reconnect_count(reconnect_count),
+            );
+            // The pool keeps counting across agent restarts
+            let reconnect_count = state
+                .coordinator
+                .connections
+                .get(&machine_id)
+                .map_or(reconnect_count, |conn| conn.reconnect_count);
+
+            // Broadcast to IPC clients (wrapped in envelope); clients still
+            // list a reconnecting machine, so they get an update instead
+            let sessions = state.coordinator.sessions.list_for_machine(&machine_id);
+            let info = kt_core::ipc::MachineInfo {
                 id: machine_id.to_string(),
                 alias: Some(alias),
+                label,
                 hostname,
                 os,
                 arch,
                 status: kt_core::ipc::MachineStatus::Connected,
-                connected_at: None,
+                connected_at: Some(kt_core::time::format_iso8601(std::time::SystemTime::now())),
                 last_heartbeat: None,
-                session_count: 0,
+                session_count: sessions.len(),
                 tags: vec![],
-            });
+                capabilities: capabilities.names(),
+                reconnect_count,
+            };
+            let event = if reconnected {
+                tracing::info!(%machine_id, "Machine reconnected within its grace period");
+                IpcEvent::MachineUpdated(info)
+            } else {
+                IpcEvent::MachineConnected(info)
+            };
             let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
         }
 
         ConnectionEvent::MachineDisconnected { machine_id } => {
-            tracing::info!("Machine disconnected: {}", machine_id);
+            tracing::info!(%machine_id, "Machine disconnected");
 
-            // Remove from connection pool
-            state.coordinator.connections.remove(&machine_id);
+            // Keep it and its sessions for a while in case it comes back
+            if kt_orchestrator::connection::begin_reconnect_grace(state, ipc_event_tx, &machine_id)
+                .await
+            {
+                return;
+            }
 
-            // Clean up all sessions for the disconnected machine
-            let removed_sessions = state.coordinator.sessions.remove_by_machine(&machine_id);
+            // Remove the connection and its sessions together
+            let (connection, removed_sessions) =
+                state.coordinator.atomic_disconnect(&machine_id).await;
             for session in &removed_sessions {
+                if !session.try_close() {
+                    continue;
+                }
                 tracing::info!(
-                    "Cleaned up orphaned session {} on machine disconnect",
-                    session.id
+                    %machine_id,
+                    session_id = %session.id,
+                    "Cleaned up orphaned session on machine disconnect"
                 );
                 // Notify IPC clients that the session was closed
                 let event = IpcEvent::SessionClosed {
                     session_id: session.id.to_string(),
+                    exit_code: None,
+                    reason: Some(CloseReason::MachineDisconnected),
                 };
                 let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
             }
@@ -551,9 +1277,26 @@ async fn handle_connection_event_with_ipc(
                 );
             }
 
-            // Broadcast machine disconnected to IPC clients
-            let event = IpcEvent::MachineDisconnected {
-                machine_id: machine_id.to_string(),
+            // Broadcast machine disconnected to IPC clients, unless the
+            // health monitor already removed and announced it
+            if connection.is_some() {
+                let event = IpcEvent::MachineDisconnected {
+                    machine_id: machine_id.to_string(),
+                };
+                let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
+            }
+        }
+
+        ConnectionEvent::MachineRejected {
+            hostname,
+            reason,
+            peer_addr,
+        } => {
+            // The handler has recorded it for ListRejections
+            let event = IpcEvent::MachineRejected {
+                hostname,
+                reason,
+                peer_addr: peer_addr.ip().to_string(),
             };
             let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
         }
@@ -563,12 +1306,7 @@ async fn handle_connection_event_with_ipc(
             session_id,
             pid,
         } => {
-            tracing::info!(
-                "Session {} ready on {} (pid={})",
-                session_id,
-                machine_id,
-                pid
-            );
+            tracing::info!(%machine_id, %session_id, pid, "Session ready");
 
             // Update session with PID
             state.coordinator.sessions.set_pid(session_id, pid);
@@ -581,6 +1319,12 @@ async fn handle_connection_event_with_ipc(
                 created_at: String::new(),
                 pid: Some(pid),
                 size: None,
+                name: None,
+                bytes_in: 0,
+                bytes_out: 0,
+                detached: false,
+                input_bytes_per_min: 0,
+                input_requests_per_min: 0,
             });
             let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
         }
@@ -588,28 +1332,45 @@ async fn handle_connection_event_with_ipc(
         ConnectionEvent::SessionClosed {
             machine_id,
             session_id,
+            exit_code,
+            reason,
         } => {
-            tracing::info!("Session {} closed on {}", session_id, machine_id);
+            tracing::info!(%machine_id, %session_id, ?exit_code, "Session closed");
 
-            // Remove session
-            state.coordinator.sessions.remove(session_id);
+            // Sessions created to outlive their shell stay until closed
+            if kt_orchestrator::session::keep_exited_session(
+                state,
+                ipc_event_tx,
+                session_id,
+                exit_code,
+                &reason,
+            ) {
+                return;
+            }
 
-            // Broadcast to IPC clients
-            let event = IpcEvent::SessionClosed {
-                session_id: session_id.to_string(),
-            };
-            let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
+            // Remove session; if it's already gone, whoever removed it
+            // (e.g. an explicit close) has announced it
+            if state.coordinator.sessions.remove(session_id).is_some() {
+                let event = IpcEvent::SessionClosed {
+                    session_id: session_id.to_string(),
+                    exit_code,
+                    reason: Some(reason),
+                };
+                let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
+            }
         }
 
         ConnectionEvent::SessionData {
             machine_id: _,
             session_id,
             data,
+            stream,
         } => {
             // Broadcast terminal output to IPC clients (wrapped in envelope)
             let event = IpcEvent::TerminalOutput {
                 session_id: session_id.to_string(),
                 data,
+                stream,
             };
             let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
         }
@@ -634,19 +1395,36 @@ fn looks_like_pairing_code(s: &str) -> bool {
         && s.chars().all(|c| PAIRING_CODE_CHARSET.contains(c))
 }
 
+/// Agent settings from agent.toml and `KT_AGENT__*`, e.g. `tailnet_domain`
+/// (used when Tailscale doesn't report a MagicDNS suffix) and the fallback
+/// orchestrators
+fn configured_agent() -> AgentConfig {
+    let mut loader = ConfigLoader::<AgentConfig>::new();
+    let path = config::default_config_dir().join("agent.toml");
+    if path.exists() {
+        if let Err(e) = loader.with_file(&path) {
+            tracing::warn!("Failed to load config from {:?}: {}", path, e);
+        }
+    }
+    loader.with_env().load().unwrap_or_default()
+}
+
 /// Connect to an orchestrator as an agent
 async fn run_join(
     target: Option<&str>,
     alias: Option<&str>,
     key_path: Option<PathBuf>,
     foreground: bool,
+    allow_self: bool,
+    verbose: bool,
 ) -> Result<()> {
     use kt_agent::pty::PtyManager;
     use kt_agent::tunnel::{ExponentialBackoff, TunnelConnector};
     use kt_core::tailscale;
 
     // Check Tailscale
-    let ts_info = tailscale::get_tailscale_info()
+    let ts_info = tailscale::get_tailscale_info_async()
+        .await
         .context("Failed to check Tailscale status")?
         .ok_or_else(|| {
             anyhow::anyhow!(
@@ -671,6 +1449,11 @@ async fn run_join(
             );
         }
 
+        // Refuse here, where the user sees it, rather than in the background
+        if let Some(t) = target.filter(|t| !looks_like_pairing_code(t)) {
+            check_join_self(&join_address(t, &ts_info), &ts_info, allow_self).await?;
+        }
+
         // Daemonize with the provided target
         let exe = std::env::current_exe()?;
         let mut cmd = std::process::Command::new(exe);
@@ -681,6 +1464,9 @@ async fn run_join(
         if let Some(k) = &key_path {
             cmd.arg("--key").arg(k);
         }
+        if allow_self {
+            cmd.arg("--allow-self");
+        }
 
         let child = cmd
             .stdin(std::process::Stdio::null())
@@ -703,15 +1489,8 @@ async fn run_join(
             print_success(&format!("Found orchestrator: {}", discovered.peer.device_name));
             discovered.ssh_address
         }
-        Some(t) => {
-            // It's a hostname/address - resolve it
-            let resolved = tailscale::resolve_device_name(t, &ts_info.tailnet);
-            if resolved.contains(':') {
-                resolved
-            } else {
-                format!("{}:2222", resolved)
-            }
-        }
+        // It's a hostname/address - resolve it
+        Some(t) => join_address(t, &ts_info),
         None => {
             // No target - prompt for pairing code (only in foreground mode)
             print_info("No orchestrator specified. Enter the pairing code shown on the orchestrator.");
@@ -725,37 +1504,52 @@ async fn run_join(
             discovered.ssh_address
         }
     };
+    check_join_self(&address, &ts_info, allow_self).await?;
 
-    print_info(&format!("Connecting to {} via Tailscale...", address));
-
-    // Build config
+    // Build config; failover and identity settings come from agent.toml
+    let configured = configured_agent();
     let config = AgentConfig {
         orchestrator_address: address,
+        fallback_orchestrators: configured.fallback_orchestrators,
+        failover_attempts: configured.failover_attempts,
+        failback_check_interval: configured.failback_check_interval,
         alias: alias.map(|a| a.to_string()),
+        label: configured.label,
+        hostname: configured.hostname,
+        os: configured.os,
+        arch: configured.arch,
+        state_dir: configured.state_dir,
+        metrics_interval_secs: configured.metrics_interval_secs,
         private_key_path: key_path.unwrap_or_else(|| AgentConfig::default().private_key_path),
         ..Default::default()
     };
+    if !config.fallback_orchestrators.is_empty() {
+        print_info(&format!(
+            "Fallback orchestrators: {}",
+            config.fallback_orchestrators.join(", ")
+        ));
+    }
 
     // Ensure SSH key exists
     ensure_ssh_key(&config.private_key_path).await?;
 
-    // Create connector
-    let connector =
-        TunnelConnector::new(config.clone()).context("Failed to create tunnel connector")?;
+    // Create connector, showing each connection attempt step by step
+    let connector = TunnelConnector::new(config.clone())
+        .context("Failed to create tunnel connector")?
+        .with_progress(move |progress| print_connect_progress(progress, verbose));
 
     // Create PTY manager
-    let pty_manager = Arc::new(Mutex::new(PtyManager::with_defaults(
-        config.default_shell.clone(),
-        config.default_env.clone(),
-    )));
+    let pty_manager = Arc::new(Mutex::new(
+        PtyManager::with_defaults(config.default_shell.clone(), config.default_env.clone())
+            .with_default_term(config.default_term.clone()),
+    ));
 
-    print_success(&format!("Connected as '{}'", config.machine_alias()));
+    print_info(&format!("Joining as '{}'", config.machine_alias()));
 
     // Main loop
+    let mut backoff = ExponentialBackoff::from_config(&config.backoff);
     loop {
-        let backoff = ExponentialBackoff::from_config(&config.backoff);
-
-        let mut tunnel = match connector.connect_with_retry(backoff).await {
+        let mut tunnel = match connector.connect_with_retry(&mut backoff).await {
             Ok(tunnel) => tunnel,
             Err(e) => {
                 tracing::error!("Connection failed: {}", e);
@@ -764,29 +1558,108 @@ async fn run_join(
             }
         };
 
-        tracing::info!("Connected to orchestrator");
-
-        let reason = run_agent_event_loop(&mut tunnel, Arc::clone(&pty_manager)).await;
+        tracing::info!("Connected to orchestrator at {}", tunnel.address());
+
+        // On a fallback, move back to the primary as soon as it returns
+        let on_primary = connector.is_primary(&tunnel);
+        let reason = tokio::select! {
+            reason = run_agent_event_loop(
+                &mut tunnel,
+                Arc::clone(&pty_manager),
+                config.metrics_interval(),
+            ) => reason,
+            _ = connector.wait_for_primary(), if !on_primary => {
+                "Primary orchestrator is back, failing back".to_string()
+            }
+        };
         tracing::warn!("Disconnected: {}", reason);
 
-        // Cleanup
+        // Cleanup; sessions can't move to another orchestrator
         {
             let mut manager = pty_manager.lock().await;
-            for sid in manager.list_sessions() {
-                manager.close(sid);
+            let lost = manager.list_sessions();
+            if !lost.is_empty() {
+                tracing::warn!("{} session(s) ended with the connection", lost.len());
+            }
+            for sid in lost {
+                let exit_code = manager.close(sid);
+                let _ = tunnel.send_session_close(sid, exit_code).await;
             }
         }
+        let _ = tunnel.close().await;
 
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
     }
 }
 
+/// Orchestrator address for a hostname or address given to `join`, with the
+/// tailnet domain and default port filled in
+fn join_address(target: &str, ts_info: &kt_core::tailscale::TailscaleInfo) -> String {
+    let configured = configured_agent().tailnet_domain;
+    let tailnet = ts_info.tailnet_or(configured.as_deref());
+    kt_core::net::orchestrator_dial_address(target, Some(tailnet))
+}
+
+/// Refuse to join an orchestrator on this machine unless `allow_self`
+///
+/// An agent connected to its own machine's orchestrator loops back on
+/// itself, which is rarely what was meant. With `--allow-self` (local
+/// testing against 127.0.0.1) this is only a warning.
+async fn check_join_self(
+    address: &str,
+    ts_info: &kt_core::tailscale::TailscaleInfo,
+    allow_self: bool,
+) -> Result<()> {
+    let Some(ip) = own_address(address, ts_info).await else {
+        return Ok(());
+    };
+    if allow_self {
+        print_warning(&format!(
+            "{} is this machine ({}); joining anyway because of --allow-self",
+            address, ip
+        ));
+        return Ok(());
+    }
+    anyhow::bail!(
+        "{} is this machine ({}), so the agent would connect to its own orchestrator.\n\
+         Point join at another machine's orchestrator, or use --allow-self to join \
+         anyway (e.g. for local testing).",
+        address,
+        ip
+    )
+}
+
+/// The address `address` resolves to if it is this machine
+///
+/// Names that don't resolve in time are assumed to be another machine.
+async fn own_address(
+    address: &str,
+    ts_info: &kt_core::tailscale::TailscaleInfo,
+) -> Option<std::net::IpAddr> {
+    let lookup = tokio::net::lookup_host(address);
+    let resolved = tokio::time::timeout(Duration::from_secs(3), lookup)
+        .await
+        .ok()?
+        .ok()?;
+    let mut own: Vec<std::net::IpAddr> = kt_core::net::list_interfaces()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|interface| interface.ip)
+        .collect();
+    own.extend(ts_info.ip.parse::<std::net::IpAddr>().ok());
+    resolved
+        .map(|addr| addr.ip())
+        .find(|ip| kt_core::net::is_own_address(*ip, &own))
+}
+
 async fn run_agent_event_loop(
     tunnel: &mut kt_agent::tunnel::ActiveTunnel,
     pty_manager: Arc<Mutex<kt_agent::pty::PtyManager>>,
+    metrics_interval: Option<Duration>,
 ) -> String {
     use kt_agent::tunnel::TunnelEvent;
 
+    let runtime_config = kt_agent::runtime_config::RuntimeConfig::start(metrics_interval);
     loop {
         let event = match tunnel.recv_event().await {
             Some(e) => e,
@@ -794,7 +1667,9 @@ async fn run_agent_event_loop(
         };
 
         match event {
-            TunnelEvent::Registered { accepted, reason } => {
+            TunnelEvent::Registered {
+                accepted, reason, ..
+            } => {
                 if !accepted {
                     return format!("Registration rejected: {:?}", reason);
                 }
@@ -802,11 +1677,19 @@ async fn run_agent_event_loop(
             TunnelEvent::CreateSession {
                 session_id,
                 shell,
-                env,
+                mut env,
                 size,
+                cwd,
+                allocate_pty,
+                term,
+                truecolor,
+                ..
             } => {
+                env.extend(kt_agent::pty::terminal_env(term.as_deref(), truecolor));
                 let mut manager = pty_manager.lock().await;
-                if let Ok(pid) = manager.create_session(session_id, shell, env, size) {
+                if let Ok(pid) =
+                    manager.create_session(session_id, shell, env, cwd, size, allocate_pty)
+                {
                     let _ = tunnel.send_session_ready(session_id, pid).await;
                 }
             }
@@ -826,6 +1709,10 @@ async fn run_agent_event_loop(
             TunnelEvent::Heartbeat { timestamp } => {
                 let _ = tunnel.send_heartbeat_ack(timestamp).await;
             }
+            TunnelEvent::ConfigUpdate { key, value } => {
+                let result = runtime_config.apply(&key, &value);
+                let _ = tunnel.send_config_ack(key, result).await;
+            }
             TunnelEvent::Disconnected => {
                 return "Disconnected by orchestrator".to_string();
             }
@@ -834,15 +1721,19 @@ async fn run_agent_event_loop(
 }
 
 /// Ensure SSH key exists, generate if needed
+///
+/// ssh-keygen creates the private key readable by the owner only; an existing
+/// key that other users can read is tightened.
 async fn ensure_ssh_key(path: &std::path::Path) -> Result<()> {
     if path.exists() {
+        kt_core::permissions::check_permissions(&[path]);
         return Ok(());
     }
 
     print_info("Generating SSH key...");
 
     if let Some(parent) = path.parent() {
-        tokio::fs::create_dir_all(parent).await?;
+        kt_core::permissions::create_private_dir_all(parent)?;
     }
 
     let status = tokio::process::Command::new("ssh-keygen")
@@ -879,7 +1770,7 @@ async fn show_quick_status() {
     println!();
 
     // Check Tailscale status
-    match tailscale::get_tailscale_info() {
+    match tailscale::get_tailscale_info_async().await {
         Ok(Some(info)) if info.logged_in => {
             println!(
                 "  Tailscale: \x1b[32m●\x1b[0m {} ({})",
@@ -890,23 +1781,30 @@ async fn show_quick_status() {
             println!("  Tailscale: \x1b[33m●\x1b[0m Not logged in");
             println!("             Run: sudo tailscale up");
         }
-        _ => {
+        Ok(None) => {
             println!("  Tailscale: \x1b[31m●\x1b[0m Not installed");
             println!("             Visit: https://tailscale.com/download");
         }
+        Err(e) => {
+            println!("  Tailscale: \x1b[33m●\x1b[0m Status unknown");
+            println!("             {:#}", e);
+        }
     }
 
     let mut client = OrchestratorClient::new();
 
     match client.ping().await {
-        Ok(true) => {
-            println!("  Orchestrator: \x1b[32m●\x1b[0m Running");
-
-            if let Ok(status) = client.status().await {
+        Ok(true) => match client.status().await {
+            Ok(status) => {
+                println!(
+                    "  Orchestrator: \x1b[32m●\x1b[0m Running ({})",
+                    status.owner.description()
+                );
                 println!("  Machines: {}", status.machine_count);
                 println!("  Sessions: {}", status.session_count);
             }
-        }
+            Err(_) => println!("  Orchestrator: \x1b[32m●\x1b[0m Running"),
+        },
         _ => {
             println!("  Orchestrator: \x1b[31m●\x1b[0m Not running");
         }
@@ -921,15 +1819,18 @@ async fn show_quick_status() {
     println!();
 }
 
-async fn ensure_orchestrator_running() -> Result<()> {
+async fn ensure_orchestrator_running(autostart: &AutoStart) -> Result<()> {
     let mut client = OrchestratorClient::new();
 
     if client.ping().await.unwrap_or(false) {
         return Ok(());
     }
+    if !autostart.enabled() {
+        anyhow::bail!("Orchestrator not running; start it with `k-terminus serve`");
+    }
 
     print_info("Orchestrator not running, starting...");
-    run_orchestrator(false, None, None).await?;
+    run_orchestrator(false, None, None, false, None, None).await?;
 
     // Wait for it to be ready
     for _ in 0..10 {
@@ -944,6 +1845,39 @@ async fn ensure_orchestrator_running() -> Result<()> {
     Ok(())
 }
 
+/// Whether management commands may start an orchestrator that isn't running
+struct AutoStart {
+    /// `--no-autostart` was given
+    disabled: bool,
+    /// `--config`, for reading `orchestrator.auto_start`
+    config_path: Option<PathBuf>,
+}
+
+impl AutoStart {
+    /// `--no-autostart`, else `orchestrator.auto_start` from the config file
+    /// or `KT_ORCHESTRATOR__AUTO_START`
+    fn enabled(&self) -> bool {
+        if self.disabled {
+            return false;
+        }
+        let path = self
+            .config_path
+            .clone()
+            .unwrap_or_else(config::default_config_path);
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        if path.exists() {
+            if let Err(e) = loader.with_file(&path) {
+                tracing::debug!("Failed to load config from {:?}: {}", path, e);
+            }
+        }
+        loader.with_env();
+        loader
+            .load()
+            .map(|file| file.orchestrator.auto_start)
+            .unwrap_or(true)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,641 @@
+//! Column selection and sorting for the `list` tables
+//!
+//! Sorting happens here, over what the orchestrator returned, so it works
+//! against any orchestrator version. It is stable, so rows that compare
+//! equal keep the orchestrator's order, also when reversed. Text is compared
+//! without regard to the locale: ASCII case is ignored and runs of digits
+//! compare as numbers, so `node-2` comes before `node-10`.
+
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+use anyhow::{bail, Result};
+use tabled::{builder::Builder, settings::Style};
+
+use kt_core::time::parse_iso8601;
+
+use super::{format_bytes, format_timestamp, truncate};
+use crate::ipc::{MachineInfo, SessionInfo};
+
+/// A column of a `list` table
+pub trait Column: Copy + Sized + 'static {
+    /// What a row of the table shows
+    type Row;
+
+    /// Every column, in the order the table shows them
+    const ALL: &'static [Self];
+
+    /// Columns of the table without `--columns`
+    const DEFAULT: &'static [Self];
+
+    /// Columns of the table without `--columns`, with `--long`
+    const DETAILED: &'static [Self];
+
+    /// Name used in `--sort` and `--columns`
+    fn name(self) -> &'static str;
+
+    /// Table header
+    fn header(self) -> &'static str;
+
+    /// Cell of `row` in this column; timestamps are absolute if `detailed`
+    fn cell(self, row: &Self::Row, detailed: bool) -> String;
+
+    /// Order of two rows by this column
+    fn compare(self, a: &Self::Row, b: &Self::Row) -> Ordering;
+
+    /// CSV header of this column: its name, or one per value for a column
+    /// that shows several
+    fn csv_headers(self) -> &'static [&'static str];
+
+    /// Values of `row` in this column for CSV, one per header: full IDs,
+    /// ISO-8601 timestamps and plain numbers, empty when unset
+    fn csv_cells(self, row: &Self::Row) -> Vec<String>;
+}
+
+/// Column of the machines table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineColumn {
+    Id,
+    Alias,
+    Label,
+    Hostname,
+    Os,
+    Arch,
+    Status,
+    Sessions,
+    Connected,
+    Reconnects,
+    Heartbeat,
+}
+
+impl Column for MachineColumn {
+    type Row = MachineInfo;
+
+    const ALL: &'static [Self] = &[
+        Self::Id,
+        Self::Alias,
+        Self::Label,
+        Self::Hostname,
+        Self::Os,
+        Self::Arch,
+        Self::Status,
+        Self::Sessions,
+        Self::Connected,
+        Self::Reconnects,
+        Self::Heartbeat,
+    ];
+
+    const DEFAULT: &'static [Self] = &[
+        Self::Id,
+        Self::Alias,
+        Self::Hostname,
+        Self::Os,
+        Self::Status,
+        Self::Sessions,
+        Self::Heartbeat,
+    ];
+
+    const DETAILED: &'static [Self] = Self::ALL;
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Alias => "alias",
+            Self::Label => "label",
+            Self::Hostname => "hostname",
+            Self::Os => "os",
+            Self::Arch => "arch",
+            Self::Status => "status",
+            Self::Sessions => "sessions",
+            Self::Connected => "connected",
+            Self::Reconnects => "reconnects",
+            Self::Heartbeat => "heartbeat",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Self::Id => "ID",
+            Self::Alias => "ALIAS",
+            Self::Label => "LABEL",
+            Self::Hostname => "HOSTNAME",
+            Self::Os => "OS",
+            Self::Arch => "ARCH",
+            Self::Status => "STATUS",
+            Self::Sessions => "SESSIONS",
+            Self::Connected => "CONNECTED",
+            Self::Reconnects => "RECONNECTS",
+            Self::Heartbeat => "LAST HEARTBEAT",
+        }
+    }
+
+    fn cell(self, m: &MachineInfo, detailed: bool) -> String {
+        match self {
+            Self::Id => truncate(&m.id, 12),
+            Self::Alias => m.alias.clone().unwrap_or_else(|| "-".to_string()),
+            Self::Label => m.label.clone().unwrap_or_else(|| "-".to_string()),
+            Self::Hostname => m.hostname.clone(),
+            Self::Os => m.os.clone(),
+            Self::Arch => m.arch.clone(),
+            Self::Status => m.status.to_string(),
+            Self::Sessions => m.session_count.to_string(),
+            Self::Connected => format_timestamp(m.connected_at.as_deref(), detailed),
+            Self::Reconnects => m.reconnect_count.to_string(),
+            Self::Heartbeat => format_timestamp(m.last_heartbeat.as_deref(), detailed),
+        }
+    }
+
+    fn compare(self, a: &MachineInfo, b: &MachineInfo) -> Ordering {
+        match self {
+            Self::Id => compare_text(&a.id, &b.id),
+            Self::Alias => compare_opt_text(a.alias.as_deref(), b.alias.as_deref()),
+            Self::Label => compare_opt_text(a.label.as_deref(), b.label.as_deref()),
+            Self::Hostname => compare_text(&a.hostname, &b.hostname),
+            Self::Os => compare_text(&a.os, &b.os),
+            Self::Arch => compare_text(&a.arch, &b.arch),
+            Self::Status => compare_text(&a.status.to_string(), &b.status.to_string()),
+            Self::Sessions => a.session_count.cmp(&b.session_count),
+            Self::Connected => {
+                timestamp(a.connected_at.as_deref()).cmp(&timestamp(b.connected_at.as_deref()))
+            }
+            Self::Reconnects => a.reconnect_count.cmp(&b.reconnect_count),
+            Self::Heartbeat => {
+                timestamp(a.last_heartbeat.as_deref()).cmp(&timestamp(b.last_heartbeat.as_deref()))
+            }
+        }
+    }
+
+    fn csv_headers(self) -> &'static [&'static str] {
+        match self {
+            Self::Id => &["id"],
+            Self::Alias => &["alias"],
+            Self::Label => &["label"],
+            Self::Hostname => &["hostname"],
+            Self::Os => &["os"],
+            Self::Arch => &["arch"],
+            Self::Status => &["status"],
+            Self::Sessions => &["sessions"],
+            Self::Connected => &["connected"],
+            Self::Reconnects => &["reconnects"],
+            Self::Heartbeat => &["heartbeat"],
+        }
+    }
+
+    fn csv_cells(self, m: &MachineInfo) -> Vec<String> {
+        let cell = match self {
+            Self::Id => m.id.clone(),
+            Self::Alias => m.alias.clone().unwrap_or_default(),
+            Self::Label => m.label.clone().unwrap_or_default(),
+            Self::Hostname => m.hostname.clone(),
+            Self::Os => m.os.clone(),
+            Self::Arch => m.arch.clone(),
+            Self::Status => m.status.to_string(),
+            Self::Sessions => m.session_count.to_string(),
+            Self::Connected => m.connected_at.clone().unwrap_or_default(),
+            Self::Reconnects => m.reconnect_count.to_string(),
+            Self::Heartbeat => m.last_heartbeat.clone().unwrap_or_default(),
+        };
+        vec![cell]
+    }
+}
+
+/// Column of the sessions table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionColumn {
+    Id,
+    Machine,
+    Shell,
+    Pid,
+    Name,
+    Created,
+    Traffic,
+    Input,
+}
+
+impl Column for SessionColumn {
+    type Row = SessionInfo;
+
+    const ALL: &'static [Self] = &[
+        Self::Id,
+        Self::Machine,
+        Self::Shell,
+        Self::Pid,
+        Self::Name,
+        Self::Created,
+        Self::Traffic,
+        Self::Input,
+    ];
+
+    const DEFAULT: &'static [Self] = &[
+        Self::Id,
+        Self::Machine,
+        Self::Shell,
+        Self::Pid,
+        Self::Created,
+    ];
+
+    const DETAILED: &'static [Self] = Self::DEFAULT;
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Id => "id",
+            Self::Machine => "machine",
+            Self::Shell => "shell",
+            Self::Pid => "pid",
+            Self::Name => "name",
+            Self::Created => "created",
+            Self::Traffic => "traffic",
+            Self::Input => "input",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            Self::Id => "SESSION ID",
+            Self::Machine => "MACHINE",
+            Self::Shell => "SHELL",
+            Self::Pid => "PID",
+            Self::Name => "NAME",
+            Self::Created => "CREATED",
+            Self::Traffic => "TRAFFIC (IN/OUT)",
+            Self::Input => "INPUT/MIN",
+        }
+    }
+
+    fn cell(self, s: &SessionInfo, detailed: bool) -> String {
+        match self {
+            Self::Id => s.id.clone(),
+            Self::Machine => truncate(&s.machine_id, 12),
+            Self::Shell => s.shell.clone().unwrap_or_else(|| "default".to_string()),
+            Self::Pid => s
+                .pid
+                .map(|p| p.to_string())
+                .unwrap_or_else(|| "-".to_string()),
+            Self::Name => s.name.clone().unwrap_or_else(|| "-".to_string()),
+            Self::Created => format_timestamp(Some(&s.created_at), detailed),
+            Self::Traffic => format!(
+                "{} / {}",
+                format_bytes(s.bytes_in),
+                format_bytes(s.bytes_out)
+            ),
+            Self::Input => format!(
+                "{} in {} writes",
+                format_bytes(s.input_bytes_per_min),
+                s.input_requests_per_min
+            ),
+        }
+    }
+
+    fn compare(self, a: &SessionInfo, b: &SessionInfo) -> Ordering {
+        match self {
+            Self::Id => compare_text(&a.id, &b.id),
+            Self::Machine => compare_text(&a.machine_id, &b.machine_id),
+            Self::Shell => compare_opt_text(a.shell.as_deref(), b.shell.as_deref()),
+            Self::Pid => a.pid.cmp(&b.pid),
+            Self::Name => compare_opt_text(a.name.as_deref(), b.name.as_deref()),
+            Self::Created => timestamp(Some(&a.created_at)).cmp(&timestamp(Some(&b.created_at))),
+            Self::Traffic => traffic(a).cmp(&traffic(b)),
+            Self::Input => a.input_bytes_per_min.cmp(&b.input_bytes_per_min),
+        }
+    }
+
+    fn csv_headers(self) -> &'static [&'static str] {
+        match self {
+            Self::Id => &["id"],
+            Self::Machine => &["machine"],
+            Self::Shell => &["shell"],
+            Self::Pid => &["pid"],
+            Self::Name => &["name"],
+            Self::Created => &["created"],
+            Self::Traffic => &["bytes_in", "bytes_out"],
+            Self::Input => &["input_bytes_per_min", "input_writes_per_min"],
+        }
+    }
+
+    fn csv_cells(self, s: &SessionInfo) -> Vec<String> {
+        let cell = match self {
+            Self::Id => s.id.clone(),
+            Self::Machine => s.machine_id.clone(),
+            Self::Shell => s.shell.clone().unwrap_or_default(),
+            Self::Pid => s.pid.map(|p| p.to_string()).unwrap_or_default(),
+            Self::Name => s.name.clone().unwrap_or_default(),
+            Self::Created => s.created_at.clone(),
+            Self::Traffic => return vec![s.bytes_in.to_string(), s.bytes_out.to_string()],
+            Self::Input => {
+                return vec![
+                    s.input_bytes_per_min.to_string(),
+                    s.input_requests_per_min.to_string(),
+                ]
+            }
+        };
+        vec![cell]
+    }
+}
+
+/// Look up a column by name
+///
+/// # Errors
+/// If there is no such column; the message lists the valid ones.
+pub fn parse_column<C: Column>(name: &str) -> Result<C> {
+    let name = name.trim();
+    match C::ALL.iter().find(|c| c.name().eq_ignore_ascii_case(name)) {
+        Some(column) => Ok(*column),
+        None => bail!(
+            "Unknown column '{}' (valid columns: {})",
+            name,
+            C::ALL
+                .iter()
+                .map(|c| c.name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+/// Look up each of `names`, as given to `--columns`
+pub fn parse_columns<C: Column>(names: &[String]) -> Result<Vec<C>> {
+    let columns = names
+        .iter()
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| parse_column(name))
+        .collect::<Result<Vec<C>>>()?;
+    if columns.is_empty() {
+        bail!("No columns given");
+    }
+    Ok(columns)
+}
+
+/// Sort `rows` by `column`, descending if `reverse`
+///
+/// Rows that compare equal keep their order either way.
+pub fn sort_rows<C: Column>(rows: &mut [C::Row], column: C, reverse: bool) {
+    rows.sort_by(|a, b| {
+        let order = column.compare(a, b);
+        if reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+}
+
+/// Format `rows` as a table of just `columns`
+///
+/// # Returns
+/// A formatted table, or `empty` if there are no rows.
+pub fn format_columns<C: Column>(
+    rows: &[C::Row],
+    columns: &[C],
+    detailed: bool,
+    empty: &str,
+) -> String {
+    if rows.is_empty() {
+        return empty.to_string();
+    }
+
+    let mut builder = Builder::default();
+    builder.push_record(columns.iter().map(|c| c.header()));
+    for row in rows {
+        builder.push_record(columns.iter().map(|c| c.cell(row, detailed)));
+    }
+    builder.build().with(Style::rounded()).to_string()
+}
+
+/// Compare text ignoring ASCII case, with runs of digits compared as numbers
+fn compare_text(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let (x, y) = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) => (*x, *y),
+        };
+        let order = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let x = take_digits(&mut a_chars);
+            let y = take_digits(&mut b_chars);
+            // Without leading zeros, a longer run is the larger number
+            let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+            x_trimmed
+                .len()
+                .cmp(&y_trimmed.len())
+                .then_with(|| x_trimmed.cmp(y_trimmed))
+        } else {
+            a_chars.next();
+            b_chars.next();
+            x.to_ascii_lowercase().cmp(&y.to_ascii_lowercase())
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    // Equal but for case or leading zeros: still give a fixed order
+    a.cmp(b)
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        digits.push(c);
+    }
+    digits
+}
+
+/// Compare optional text, with missing values first
+fn compare_opt_text(a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => compare_text(a, b),
+        _ => a.is_some().cmp(&b.is_some()),
+    }
+}
+
+/// A timestamp for ordering; missing or unparsable ones come first
+fn timestamp(timestamp: Option<&str>) -> Option<SystemTime> {
+    timestamp.and_then(parse_iso8601)
+}
+
+fn traffic(session: &SessionInfo) -> u64 {
+    session.bytes_in.saturating_add(session.bytes_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::MachineStatus;
+
+    fn machine(id: &str, alias: Option<&str>, sessions: usize, heartbeat: &str) -> MachineInfo {
+        MachineInfo {
+            id: id.to_string(),
+            alias: alias.map(String::from),
+            label: None,
+            hostname: format!("{}.lan", id),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: MachineStatus::Connected,
+            connected_at: Some("2024-01-01T00:00:00Z".to_string()),
+            last_heartbeat: Some(heartbeat.to_string()),
+            session_count: sessions,
+            tags: vec![],
+            capabilities: vec![],
+            reconnect_count: 0,
+        }
+    }
+
+    fn session(id: &str, machine_id: &str, created_at: &str, bytes: u64) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: created_at.to_string(),
+            pid: None,
+            size: None,
+            name: None,
+            bytes_in: 1,
+            bytes_out: bytes,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        }
+    }
+
+    fn fleet() -> Vec<MachineInfo> {
+        vec![
+            machine("node-10", Some("db"), 2, "2024-01-01T00:05:00Z"),
+            machine("node-2", None, 0, "2024-01-01T00:09:00Z"),
+            machine("Node-3", Some("App"), 2, "2024-01-01T00:01:00Z"),
+            machine("node-1", Some("cache"), 5, "2024-01-01T00:07:00Z"),
+        ]
+    }
+
+    fn sorted_ids(column: MachineColumn, reverse: bool) -> Vec<String> {
+        let mut machines = fleet();
+        sort_rows(&mut machines, column, reverse);
+        machines.into_iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_sort_machines() {
+        // Digits compare as numbers and case is ignored
+        assert_eq!(
+            sorted_ids(MachineColumn::Id, false),
+            ["node-1", "node-2", "Node-3", "node-10"]
+        );
+        // Machines without an alias come first
+        assert_eq!(
+            sorted_ids(MachineColumn::Alias, false),
+            ["node-2", "Node-3", "node-1", "node-10"]
+        );
+        assert_eq!(
+            sorted_ids(MachineColumn::Heartbeat, true),
+            ["node-2", "node-1", "node-10", "Node-3"]
+        );
+        assert_eq!(
+            sorted_ids(MachineColumn::Hostname, false),
+            ["node-1", "node-2", "Node-3", "node-10"]
+        );
+    }
+
+    #[test]
+    fn test_sort_is_stable() {
+        // node-10 and Node-3 tie on sessions and keep their order both ways
+        assert_eq!(
+            sorted_ids(MachineColumn::Sessions, false),
+            ["node-2", "node-10", "Node-3", "node-1"]
+        );
+        assert_eq!(
+            sorted_ids(MachineColumn::Sessions, true),
+            ["node-1", "node-10", "Node-3", "node-2"]
+        );
+        // Every machine has the same OS
+        assert_eq!(
+            sorted_ids(MachineColumn::Os, true),
+            ["node-10", "node-2", "Node-3", "node-1"]
+        );
+    }
+
+    #[test]
+    fn test_sort_sessions() {
+        let sessions = vec![
+            session("session-10", "b", "2024-01-01T00:02:00Z", 100),
+            session("session-2", "a", "2024-01-01T00:03:00Z", 5000),
+            session("session-1", "b", "2024-01-01T00:01:00Z", 10),
+        ];
+        let sorted = |column: SessionColumn, reverse: bool| {
+            let mut sessions = sessions.clone();
+            sort_rows(&mut sessions, column, reverse);
+            sessions.into_iter().map(|s| s.id).collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            sorted(SessionColumn::Id, false),
+            ["session-1", "session-2", "session-10"]
+        );
+        assert_eq!(
+            sorted(SessionColumn::Created, false),
+            ["session-1", "session-10", "session-2"]
+        );
+        assert_eq!(
+            sorted(SessionColumn::Machine, false),
+            ["session-2", "session-10", "session-1"]
+        );
+        assert_eq!(
+            sorted(SessionColumn::Traffic, true),
+            ["session-2", "session-10", "session-1"]
+        );
+    }
+
+    #[test]
+    fn test_compare_text() {
+        assert_eq!(compare_text("a2", "a10"), Ordering::Less);
+        assert_eq!(compare_text("a02", "a10"), Ordering::Less);
+        assert_eq!(compare_text("B", "a"), Ordering::Greater);
+        assert_eq!(compare_text("ab", "abc"), Ordering::Less);
+        // Equal ignoring case still orders the same way every time
+        assert_eq!(compare_text("Web", "web"), Ordering::Less);
+        assert_eq!(compare_text("web", "web"), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_parse_columns() {
+        let names = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_columns::<MachineColumn>(&names(&["alias", " OS ", "heartbeat"])).unwrap(),
+            [
+                MachineColumn::Alias,
+                MachineColumn::Os,
+                MachineColumn::Heartbeat
+            ]
+        );
+        assert_eq!(
+            parse_column::<SessionColumn>("traffic").unwrap(),
+            SessionColumn::Traffic
+        );
+
+        let err = parse_columns::<MachineColumn>(&names(&["alias", "uptime"])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown column 'uptime' (valid columns: id, alias, label, hostname, os, arch, \
+             status, sessions, connected, reconnects, heartbeat)"
+        );
+        assert!(parse_column::<SessionColumn>("sessions").is_err());
+        assert!(parse_columns::<MachineColumn>(&names(&[""])).is_err());
+    }
+
+    #[test]
+    fn test_format_selected_columns() {
+        let machines = fleet();
+        let columns = [MachineColumn::Alias, MachineColumn::Sessions];
+        let table = format_columns(&machines[..2], &columns, false, "No machines connected");
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[1].contains("ALIAS") && lines[1].contains("SESSIONS"));
+        assert!(!table.contains("HOSTNAME"));
+        assert!(lines[3].contains("db") && lines[3].contains('2'));
+        assert!(lines[4].contains('-'));
+
+        let none: &[MachineInfo] = &[];
+        assert_eq!(
+            format_columns(none, &columns, false, "No machines connected"),
+            "No machines connected"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! CSV output for the `list` tables
+//!
+//! Written as RFC 4180 describes: a header row, CRLF line endings, and
+//! fields that hold a comma, a quote or a line break quoted, with quotes
+//! doubled. Cells hold plain values rather than the table's display text
+//! (full IDs, ISO-8601 timestamps, byte counts, empty when unset), so
+//! spreadsheets can sort and sum them.
+
+use super::columns::Column;
+
+/// Format `rows` as CSV with a header row, in the order of `columns`
+///
+/// A column showing several values, such as `traffic`, becomes one CSV
+/// column per value. Without rows, just the header is written.
+pub fn format_csv<C: Column>(rows: &[C::Row], columns: &[C]) -> String {
+    let mut out = String::new();
+    push_record(
+        &mut out,
+        columns.iter().flat_map(|c| c.csv_headers().iter().copied()),
+    );
+    for row in rows {
+        push_record(&mut out, columns.iter().flat_map(|c| c.csv_cells(row)));
+    }
+    out
+}
+
+/// Append one record and its line ending
+fn push_record<S: AsRef<str>>(out: &mut String, fields: impl IntoIterator<Item = S>) {
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        push_field(out, field.as_ref());
+    }
+    out.push_str("\r\n");
+}
+
+/// Append one field, quoted if it needs to be
+fn push_field(out: &mut String, field: &str) {
+    if field.contains([',', '"', '\r', '\n']) {
+        out.push('"');
+        out.push_str(&field.replace('"', "\"\""));
+        out.push('"');
+    } else {
+        out.push_str(field);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::SessionInfo;
+    use crate::output::SessionColumn;
+
+    #[test]
+    fn test_format_csv() {
+        let session = SessionInfo {
+            id: "session-1".to_string(),
+            machine_id: "gpu-box".to_string(),
+            shell: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            pid: Some(42),
+            size: None,
+            name: Some("build, \"nightly\"\nlog".to_string()),
+            bytes_in: 10,
+            bytes_out: 2048,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        };
+        let columns = [
+            SessionColumn::Id,
+            SessionColumn::Shell,
+            SessionColumn::Pid,
+            SessionColumn::Name,
+            SessionColumn::Traffic,
+        ];
+
+        assert_eq!(
+            format_csv(&[session], &columns),
+            "id,shell,pid,name,bytes_in,bytes_out\r\n\
+             session-1,,42,\"build, \"\"nightly\"\"\nlog\",10,2048\r\n"
+        );
+        assert_eq!(
+            format_csv(&[], SessionColumn::DEFAULT),
+            "id,machine,shell,pid,created\r\n"
+        );
+    }
+}
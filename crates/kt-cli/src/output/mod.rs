@@ -4,21 +4,41 @@
 //! human-readable output for the terminal, including tables for machines
 //! and sessions, status displays, and colored status messages.
 
+mod columns;
+mod csv;
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::SystemTime;
+
 use tabled::{
     settings::{Style, Width},
     Table, Tabled,
 };
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::ipc::{MachineInfo, OrchestratorStatus, SessionInfo};
+use kt_agent::tunnel::ConnectProgress;
+use kt_core::time::{format_iso8601, format_relative_to, offset_time, parse_iso8601};
+
+pub use columns::{
+    format_columns, parse_column, parse_columns, sort_rows, Column, MachineColumn, SessionColumn,
+};
+pub use csv::format_csv;
+
+use crate::ipc::{
+    CloseReason, GroupInfo, MachineInfo, OrchestratorCapabilities, OrchestratorStatus,
+    SessionDetails, SessionEnvVar, SessionInfo,
+};
 
 /// Format a list of machines as an ASCII table
 ///
-/// Creates a formatted table displaying machine information with optional
-/// detailed view that includes connection timestamps and heartbeat info.
+/// Creates a formatted table displaying machine information. The last
+/// heartbeat is shown relative to now ("3m ago"); the detailed view shows
+/// absolute connection and heartbeat timestamps instead.
 ///
 /// # Arguments
 /// * `machines` - Slice of machine information to display
-/// * `detailed` - If true, includes additional columns for timestamps
+/// * `detailed` - If true, includes additional columns with absolute timestamps
 ///
 /// # Returns
 /// A formatted string suitable for terminal output, or "No machines connected"
@@ -42,6 +62,8 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
         status: String,
         #[tabled(rename = "SESSIONS")]
         sessions: usize,
+        #[tabled(rename = "LAST HEARTBEAT")]
+        heartbeat: String,
     }
 
     #[derive(Tabled)]
@@ -50,6 +72,8 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
         id: String,
         #[tabled(rename = "ALIAS")]
         alias: String,
+        #[tabled(rename = "LABEL")]
+        label: String,
         #[tabled(rename = "HOSTNAME")]
         hostname: String,
         #[tabled(rename = "OS/ARCH")]
@@ -60,6 +84,8 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
         sessions: usize,
         #[tabled(rename = "CONNECTED")]
         connected: String,
+        #[tabled(rename = "RECONNECTS")]
+        reconnects: u64,
         #[tabled(rename = "LAST HEARTBEAT")]
         heartbeat: String,
     }
@@ -70,12 +96,14 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
             .map(|m| MachineRowDetailed {
                 id: truncate(&m.id, 12),
                 alias: m.alias.clone().unwrap_or_else(|| "-".to_string()),
+                label: m.label.clone().unwrap_or_else(|| "-".to_string()),
                 hostname: m.hostname.clone(),
                 os_arch: format!("{}/{}", m.os, m.arch),
                 status: m.status.to_string(),
                 sessions: m.session_count,
-                connected: m.connected_at.clone().unwrap_or_else(|| "-".to_string()),
-                heartbeat: m.last_heartbeat.clone().unwrap_or_else(|| "-".to_string()),
+                connected: format_timestamp(m.connected_at.as_deref(), true),
+                reconnects: m.reconnect_count,
+                heartbeat: format_timestamp(m.last_heartbeat.as_deref(), true),
             })
             .collect();
 
@@ -93,6 +121,7 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
                 os: m.os.clone(),
                 status: m.status.to_string(),
                 sessions: m.session_count,
+                heartbeat: format_timestamp(m.last_heartbeat.as_deref(), false),
             })
             .collect();
 
@@ -103,15 +132,17 @@ pub fn format_machines(machines: &[MachineInfo], detailed: bool) -> String {
 /// Format a list of sessions as an ASCII table
 ///
 /// Creates a formatted table displaying session information including
-/// session ID, machine, shell, PID, and creation time.
+/// session ID, machine, shell, PID, and creation time. The creation time is
+/// relative to now ("3m ago") unless `detailed` is set.
 ///
 /// # Arguments
 /// * `sessions` - Slice of session information to display
+/// * `detailed` - If true, shows absolute creation timestamps
 ///
 /// # Returns
 /// A formatted string suitable for terminal output, or "No active sessions"
 /// if the list is empty.
-pub fn format_sessions(sessions: &[SessionInfo]) -> String {
+pub fn format_sessions(sessions: &[SessionInfo], detailed: bool) -> String {
     if sessions.is_empty() {
         return "No active sessions".to_string();
     }
@@ -140,13 +171,257 @@ pub fn format_sessions(sessions: &[SessionInfo]) -> String {
                 .pid
                 .map(|p| p.to_string())
                 .unwrap_or_else(|| "-".to_string()),
-            created: s.created_at.clone(),
+            created: format_timestamp(Some(&s.created_at), detailed),
+        })
+        .collect();
+
+    Table::new(rows).with(Style::rounded()).to_string()
+}
+
+/// Format the sessions about to be killed, for confirmation
+///
+/// Shows each session's machine (by alias or hostname when the machine is
+/// still connected), shell, label and uptime so the user can check they are
+/// killing the right ones.
+///
+/// # Arguments
+/// * `sessions` - Sessions that are about to be killed
+/// * `machines` - Connected machines, used to name each session's machine
+///
+/// # Returns
+/// A formatted table suitable for terminal output.
+pub fn format_kill_targets(sessions: &[SessionInfo], machines: &[MachineInfo]) -> String {
+    #[derive(Tabled)]
+    struct KillTargetRow {
+        #[tabled(rename = "SESSION ID")]
+        id: String,
+        #[tabled(rename = "MACHINE")]
+        machine: String,
+        #[tabled(rename = "SHELL")]
+        shell: String,
+        #[tabled(rename = "LABEL")]
+        label: String,
+        #[tabled(rename = "UPTIME")]
+        uptime: String,
+    }
+
+    let rows: Vec<KillTargetRow> = sessions
+        .iter()
+        .map(|s| {
+            let machine = machines.iter().find(|m| m.id == s.machine_id);
+            KillTargetRow {
+                id: s.id.clone(),
+                machine: machine
+                    .map(|m| m.alias.clone().unwrap_or_else(|| m.hostname.clone()))
+                    .unwrap_or_else(|| truncate(&s.machine_id, 12)),
+                shell: s.shell.clone().unwrap_or_else(|| "default".to_string()),
+                label: s.name.clone().unwrap_or_else(|| "-".to_string()),
+                uptime: parse_iso8601(&s.created_at)
+                    .map(|created| format_duration(created.elapsed().unwrap_or_default().as_secs()))
+                    .unwrap_or_else(|| "-".to_string()),
+            }
         })
         .collect();
 
     Table::new(rows).with(Style::rounded()).to_string()
 }
 
+/// Format machine groups, one per line
+///
+/// # Arguments
+/// * `groups` - Groups to display
+///
+/// # Returns
+/// Lines such as "@homelab  nas, pi1", with members that match no known
+/// machine marked, or "No groups defined" if the list is empty.
+pub fn format_groups(groups: &[GroupInfo]) -> String {
+    if groups.is_empty() {
+        return "No groups defined".to_string();
+    }
+
+    groups
+        .iter()
+        .map(|group| {
+            let members = if group.members.is_empty() {
+                "(no members)".to_string()
+            } else {
+                group
+                    .members
+                    .iter()
+                    .map(|m| {
+                        if group.unknown_members.contains(m) {
+                            format!("{} (unknown)", m)
+                        } else {
+                            m.clone()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            format!("@{}  {}", group.name, members)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format a session's environment as `NAME=value` lines
+///
+/// Redacted values are shown as the placeholder returned by the orchestrator.
+///
+/// # Arguments
+/// * `env` - Environment variables of the session
+///
+/// # Returns
+/// One line per variable, or "No environment variables set" if empty.
+pub fn format_session_env(env: &[SessionEnvVar]) -> String {
+    if env.is_empty() {
+        return "No environment variables set".to_string();
+    }
+
+    env.iter()
+        .map(|var| format!("{}={}", var.name, var.value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Format the notice shown when an attached session's shell exits
+///
+/// # Arguments
+/// * `exit_code` - Exit code of the remote shell, if known
+/// * `reason` - Why the orchestrator closed the session, if it said
+///
+/// # Returns
+/// A bracketed notice such as "[session ended, exit code 0]", or one naming
+/// the reason when the shell didn't simply exit, such as
+/// "[session ended: machine disconnected]".
+pub fn format_session_end(exit_code: Option<i32>, reason: Option<&CloseReason>) -> String {
+    match (exit_code, reason) {
+        (Some(code), _) => format!("[session ended, exit code {}]", code),
+        (None, None | Some(CloseReason::ProcessExited { .. })) => "[session ended]".to_string(),
+        (None, Some(reason)) => format!("[session ended: {}]", reason),
+    }
+}
+
+/// Shown for what an orchestrator too old for `GetSession` can't tell
+const UNKNOWN_TOO_OLD: &str = "unknown (orchestrator too old)";
+
+/// Format everything known about a session as "Label: value" lines
+///
+/// Without `details`, which older orchestrators can't give, only what
+/// `session` lists is shown, and the rest is labelled unknown.
+///
+/// # Arguments
+/// * `session` - The session as listed
+/// * `details` - What `GetSession` said about it, if the orchestrator knows it
+///
+/// # Returns
+/// A multi-line formatted string suitable for terminal output.
+pub fn format_session_details(session: &SessionInfo, details: Option<&SessionDetails>) -> String {
+    let mut output = String::new();
+    let mut line = |label: &str, value: String| {
+        output.push_str(&format!("{}: {}\n", label, value));
+    };
+
+    line("Session", session.id.clone());
+    if let Some(name) = &session.name {
+        line("Name", name.clone());
+    }
+    line(
+        "State",
+        details.map_or(UNKNOWN_TOO_OLD.to_string(), |d| d.state.to_string()),
+    );
+    line(
+        "Owner",
+        match details {
+            None => UNKNOWN_TOO_OLD.to_string(),
+            Some(d) if d.owned_by_you => "you".to_string(),
+            Some(d) => match &d.owner_client_id {
+                Some(owner) => format!("another client ({})", owner),
+                None => "none".to_string(),
+            },
+        },
+    );
+    line("Machine", session.machine_id.clone());
+    line(
+        "Shell",
+        session
+            .shell
+            .clone()
+            .unwrap_or_else(|| "default".to_string()),
+    );
+    line(
+        "PID",
+        session
+            .pid
+            .map_or_else(|| "unknown".to_string(), |pid| pid.to_string()),
+    );
+    line(
+        "Size",
+        session.size.as_ref().map_or_else(
+            || "unknown".to_string(),
+            |size| format!("{}x{}", size.cols, size.rows),
+        ),
+    );
+    line(
+        "Created",
+        format!(
+            "{} ({})",
+            format_timestamp(Some(&session.created_at), false),
+            format_timestamp(Some(&session.created_at), true)
+        ),
+    );
+    line(
+        "Idle",
+        match details {
+            None => UNKNOWN_TOO_OLD.to_string(),
+            Some(d) => d.idle_secs.map_or_else(|| "-".to_string(), format_duration),
+        },
+    );
+    if let Some(orphaned_at) = details.and_then(|d| d.orphaned_at.as_deref()) {
+        line("Orphaned", format_timestamp(Some(orphaned_at), false));
+    }
+    if let Some(secs) = details.and_then(|d| d.expires_in_secs) {
+        line("Expires", format!("in {} (TTL)", format_duration(secs)));
+    }
+    line(
+        "Subscribers",
+        details.map_or(UNKNOWN_TOO_OLD.to_string(), |d| d.subscribers.to_string()),
+    );
+    line(
+        "Traffic",
+        format!(
+            "{} in, {} out",
+            format_bytes(session.bytes_in),
+            format_bytes(session.bytes_out)
+        ),
+    );
+    // Its shell exited, but the session was kept open
+    if let Some(d) = details.filter(|d| d.state == crate::ipc::SessionLifecycle::Exited) {
+        line(
+            "Exit Code",
+            d.exit_code
+                .map_or_else(|| "unknown".to_string(), |code| code.to_string()),
+        );
+    }
+    if let Some(closed_at) = details.and_then(|d| d.closed_at.as_deref()) {
+        line("Closed", format_timestamp(Some(closed_at), false));
+    }
+    if let Some(d) = details.filter(|d| d.closed_at.is_some() || d.close_reason.is_some()) {
+        line(
+            "Close Reason",
+            match (&d.close_reason, d.exit_code) {
+                (Some(CloseReason::ProcessExited { code: None }), Some(code)) => {
+                    CloseReason::ProcessExited { code: Some(code) }.to_string()
+                }
+                (Some(reason), _) => reason.to_string(),
+                (None, _) => "unknown".to_string(),
+            },
+        );
+    }
+
+    output
+}
+
 /// Format orchestrator status as a human-readable string
 ///
 /// Displays the orchestrator's running state, version, uptime, and
@@ -161,26 +436,77 @@ pub fn format_sessions(sessions: &[SessionInfo]) -> String {
 pub fn format_status(status: &OrchestratorStatus, detailed: bool) -> String {
     let mut output = String::new();
 
-    output.push_str(&format!(
-        "Orchestrator Status: {}\n",
-        if status.running { "Running" } else { "Stopped" }
-    ));
+    if status.running {
+        output.push_str(&format!(
+            "Orchestrator Status: Running ({})\n",
+            status.owner.description()
+        ));
+    } else {
+        output.push_str("Orchestrator Status: Stopped\n");
+    }
+    if let Some(message) = &status.maintenance {
+        output.push_str(&format!(
+            "Maintenance Mode: ON, new sessions refused ({})\n",
+            message
+        ));
+    }
     output.push_str(&format!("Version: {}\n", status.version));
     output.push_str(&format!(
         "Uptime: {}\n",
         format_duration(status.uptime_secs)
     ));
+    match status.listen_address.as_deref() {
+        Some(listen) if listen != status.bind_address => output.push_str(&format!(
+            "SSH Address: {} (bind_address = {})\n",
+            listen, status.bind_address
+        )),
+        Some(listen) => output.push_str(&format!("SSH Address: {}\n", listen)),
+        None => {}
+    }
     output.push_str(&format!("Connected Machines: {}\n", status.machine_count));
     output.push_str(&format!("Active Sessions: {}\n", status.session_count));
 
     if detailed {
         output.push_str("\n--- Detailed Metrics ---\n");
-        // Additional metrics would be added here in a full implementation
+        let coalescing = &status.output_coalescing;
+        output.push_str(&format!(
+            "Output Coalescing: {} ({} recent client lags, engaged {} times, {} events merged)\n",
+            if coalescing.engaged { "engaged" } else { "off" },
+            coalescing.recent_lag_events,
+            coalescing.times_engaged,
+            coalescing.coalesced_events
+        ));
+        let breaker = &status.input_breaker;
+        if breaker.max_kib_per_min == 0 {
+            output.push_str("Input Breaker: off\n");
+        } else {
+            output.push_str(&format!(
+                "Input Breaker: {} KiB/min per session ({} sessions blocked, tripped {} times)\n",
+                breaker.max_kib_per_min, breaker.blocked_sessions, breaker.times_tripped
+            ));
+        }
     }
 
     output
 }
 
+/// Format the protocol version and optional features of the orchestrator
+pub fn format_capabilities(capabilities: &OrchestratorCapabilities) -> String {
+    if capabilities.protocol_version == 0 {
+        return "IPC Protocol: unknown (orchestrator predates feature detection)\n".to_string();
+    }
+    let features = capabilities.features.names();
+    format!(
+        "IPC Protocol: v{}\nFeatures: {}\n",
+        capabilities.protocol_version,
+        if features.is_empty() {
+            "none".to_string()
+        } else {
+            features.join(", ")
+        }
+    )
+}
+
 /// Format duration in human-readable form
 fn format_duration(secs: u64) -> String {
     if secs < 60 {
@@ -200,15 +526,74 @@ fn format_duration(secs: u64) -> String {
     }
 }
 
-/// Truncate a string with ellipsis if too long
-fn truncate(s: &str, max_len: usize) -> String {
-    if s.len() <= max_len {
-        s.to_string()
-    } else {
-        format!("{}...", &s[..max_len.saturating_sub(3)])
+/// Format a byte count in binary units, e.g. `512B` or `3.4KiB`
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{}B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+/// How far the orchestrator's clock is ahead of ours, in milliseconds
+static CLOCK_OFFSET_MS: AtomicI64 = AtomicI64::new(0);
+
+/// Measure relative times on a clock `offset_millis` ahead of ours
+///
+/// Timestamps come from the orchestrator's clock, so "3m ago" has to be
+/// measured against it too, or a skewed local clock shows everything as
+/// "just now" or hours old. The IPC client sets this each time it
+/// authenticates.
+pub fn set_clock_offset(offset_millis: i64) {
+    CLOCK_OFFSET_MS.store(offset_millis, Ordering::Relaxed);
+}
+
+/// Format a timestamp from the orchestrator for display
+///
+/// Relative ("3m ago") by default, or ISO-8601 as received when `absolute`
+/// is set. Timestamps that don't parse are shown as received.
+fn format_timestamp(timestamp: Option<&str>, absolute: bool) -> String {
+    let Some(timestamp) = timestamp.filter(|t| !t.is_empty()) else {
+        return "-".to_string();
+    };
+    match parse_iso8601(timestamp) {
+        Some(time) if absolute => format_iso8601(time),
+        Some(time) => {
+            let offset = CLOCK_OFFSET_MS.load(Ordering::Relaxed);
+            format_relative_to(time, offset_time(SystemTime::now(), offset))
+        }
+        None => timestamp.to_string(),
     }
 }
 
+/// Truncate a string with ellipsis if it is wider than `max_width` columns
+///
+/// Widths are display widths, so wide (e.g. CJK) characters count as two
+/// columns, and the string is only cut between grapheme clusters.
+fn truncate(s: &str, max_width: usize) -> String {
+    if s.width() <= max_width {
+        return s.to_string();
+    }
+    let budget = max_width.saturating_sub(3);
+    let mut width = 0;
+    let mut out = String::new();
+    for grapheme in s.graphemes(true) {
+        width += grapheme.width();
+        if width > budget {
+            break;
+        }
+        out.push_str(grapheme);
+    }
+    out.push_str("...");
+    out
+}
+
 /// Print a success message in green with a checkmark prefix
 ///
 /// Outputs to stdout with green coloring for positive feedback to the user.
@@ -276,3 +661,206 @@ pub fn print_info(msg: &str) {
         Print("\n")
     );
 }
+
+/// Print one step of connecting to an orchestrator as an agent
+///
+/// Finished stages get a checkmark with their timing, a failed stage a
+/// cross with the error; `verbose` adds the error's underlying causes.
+pub fn print_connect_progress(progress: &ConnectProgress<'_>, verbose: bool) {
+    match progress {
+        ConnectProgress::Attempt { address } => {
+            print_info(&format!("Connecting to {}...", address));
+        }
+        ConnectProgress::Started { .. } => {}
+        ConnectProgress::Finished {
+            stage,
+            elapsed,
+            detail,
+        } => {
+            let mut line = format!("{:<14} {:>6}", stage, format_elapsed(*elapsed));
+            if let Some(detail) = detail {
+                line.push_str(&format!("  {}", detail));
+            }
+            print_success(&line);
+        }
+        ConnectProgress::Failed {
+            stage,
+            elapsed,
+            error,
+        } => {
+            let mut chain = error.chain();
+            chain.dedup();
+            print_error(&format!(
+                "{:<14} {:>6}  {}",
+                stage,
+                format_elapsed(*elapsed),
+                chain.first().map(String::as_str).unwrap_or("failed")
+            ));
+            if verbose {
+                for cause in chain.iter().skip(1) {
+                    eprintln!("    caused by: {}", cause);
+                }
+            }
+        }
+        ConnectProgress::Ready { address, elapsed } => {
+            print_success(&format!(
+                "Ready: registered with {} in {}",
+                address,
+                format_elapsed(*elapsed)
+            ));
+        }
+    }
+}
+
+/// Format a short duration, e.g. `45ms` or `1.2s`
+fn format_elapsed(elapsed: std::time::Duration) -> String {
+    if elapsed.as_millis() < 1000 {
+        format!("{}ms", elapsed.as_millis())
+    } else {
+        format!("{:.1}s", elapsed.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0B");
+        assert_eq!(format_bytes(1023), "1023B");
+        assert_eq!(format_bytes(3482), "3.4KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0GiB");
+    }
+
+    fn session() -> SessionInfo {
+        SessionInfo {
+            id: "42".to_string(),
+            machine_id: "gpu-box".to_string(),
+            shell: Some("/bin/zsh".to_string()),
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            pid: None,
+            size: None,
+            name: None,
+            bytes_in: 2048,
+            bytes_out: 100,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        }
+    }
+
+    #[test]
+    fn test_format_session_details() {
+        let details = SessionDetails {
+            session: SessionInfo {
+                pid: Some(4321),
+                size: Some(kt_core::ipc::TerminalSize {
+                    cols: 120,
+                    rows: 40,
+                }),
+                ..session()
+            },
+            state: crate::ipc::SessionLifecycle::Closed,
+            owner_client_id: Some("desktop".to_string()),
+            owned_by_you: false,
+            subscribers: 0,
+            idle_secs: None,
+            orphaned_at: None,
+            closed_at: Some("2024-01-01T01:00:00Z".to_string()),
+            close_reason: Some(CloseReason::MachineDisconnected),
+            exit_code: None,
+            expires_in_secs: None,
+        };
+        let output = format_session_details(&details.session, Some(&details));
+        assert!(output.contains("State: closed\n"));
+        assert!(output.contains("Owner: another client (desktop)\n"));
+        assert!(output.contains("PID: 4321\n"));
+        assert!(output.contains("Size: 120x40\n"));
+        assert!(output.contains("Traffic: 2.0KiB in, 100B out\n"));
+        assert!(output.contains("Close Reason: machine disconnected\n"));
+        assert!(!output.contains("Expires"));
+
+        let expiring = SessionDetails {
+            state: crate::ipc::SessionLifecycle::Active,
+            closed_at: None,
+            close_reason: None,
+            expires_in_secs: Some(90),
+            ..details
+        };
+        let output = format_session_details(&expiring.session, Some(&expiring));
+        assert!(output.contains("Expires: in 1m 30s (TTL)\n"), "{}", output);
+    }
+
+    #[test]
+    fn test_format_session_details_from_old_orchestrator() {
+        let output = format_session_details(&session(), None);
+        assert!(output.contains("State: unknown (orchestrator too old)\n"));
+        assert!(output.contains("Owner: unknown (orchestrator too old)\n"));
+        assert!(output.contains("Subscribers: unknown (orchestrator too old)\n"));
+        assert!(output.contains("PID: unknown\n"));
+        assert!(output.contains("Size: unknown\n"));
+        assert!(!output.contains("Close Reason"));
+    }
+
+    #[test]
+    fn test_format_status_shows_maintenance() {
+        let mut status = OrchestratorStatus {
+            running: true,
+            uptime_secs: 90,
+            machine_count: 1,
+            session_count: 2,
+            version: "0.1.0".to_string(),
+            protocol_version: None,
+            tailscale_hostname: None,
+            bind_address: "0.0.0.0:2222".to_string(),
+            listen_address: None,
+            pairing_code: None,
+            owner: Default::default(),
+            output_coalescing: Default::default(),
+            input_breaker: Default::default(),
+            maintenance: None,
+        };
+        assert!(!format_status(&status, false).contains("Maintenance"));
+
+        status.maintenance = Some("Upgrading agents".to_string());
+        let output = format_status(&status, false);
+        let mut lines = output.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("Orchestrator Status: Running"));
+        assert_eq!(
+            lines.next(),
+            Some("Maintenance Mode: ON, new sessions refused (Upgrading agents)")
+        );
+    }
+
+    #[test]
+    fn test_truncate_ascii() {
+        assert_eq!(truncate("machine-1", 12), "machine-1");
+        assert_eq!(truncate("machine-123456", 12), "machine-1...");
+        assert_eq!(truncate("abcdef", 2), "...");
+    }
+
+    #[test]
+    fn test_truncate_counts_display_width() {
+        // Six CJK characters are 12 columns wide and 18 bytes long
+        assert_eq!(truncate("数据库服务器", 12), "数据库服务器");
+        assert_eq!(truncate("a数据库服务器", 12), "a数据库服...");
+        assert_eq!(truncate("a数据库服务器", 12).width(), 12);
+
+        // A wide character that would overhang the limit is left out
+        let cut = truncate("数据库服务器-01", 12);
+        assert_eq!(cut, "数据库服...");
+        assert_eq!(cut.width(), 11);
+    }
+
+    #[test]
+    fn test_truncate_keeps_graphemes_whole() {
+        // "e" followed by a combining acute accent is one column
+        let name = "cafe\u{301}s-serveur";
+        assert_eq!(truncate(name, 8), "cafe\u{301}s...");
+        assert_eq!(truncate(name, 7), "cafe\u{301}...");
+    }
+}
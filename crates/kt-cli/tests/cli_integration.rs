@@ -6,8 +6,7 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 
 fn k_terminus() -> Command {
-    Command::cargo_bin("k-terminus")
-        .expect("Failed to locate k-terminus binary - ensure it's built before running tests")
+    assert_cmd::cargo::cargo_bin_cmd!("k-terminus")
 }
 
 #[test]
@@ -129,3 +128,27 @@ fn test_cli_join_missing_host() {
     // Join requires a host argument
     k_terminus().arg("join").assert().failure();
 }
+
+#[test]
+fn test_cli_env_help() {
+    k_terminus()
+        .args(["env", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--show-secrets"));
+}
+
+#[test]
+fn test_cli_env_missing_session() {
+    // Env requires a session argument
+    k_terminus().arg("env").assert().failure();
+}
+
+#[test]
+fn test_cli_no_autostart_flag() {
+    k_terminus()
+        .args(["list", "--help"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("--no-autostart"));
+}
@@ -13,7 +13,6 @@
 
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpStream;
-use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
 use std::sync::atomic::{AtomicU16, Ordering};
 use std::time::Duration;
@@ -78,6 +77,11 @@ struct TestOrchestrator {
 
 impl TestOrchestrator {
     fn start() -> Self {
+        Self::start_with(&[])
+    }
+
+    /// Start with extra `serve` arguments
+    fn start_with(serve_args: &[&str]) -> Self {
         let (ssh_port, ipc_port) = get_test_ports();
         let config = TestConfig::new(ssh_port, ipc_port);
 
@@ -92,6 +96,7 @@ impl TestOrchestrator {
                 "serve",
                 "--foreground",
             ])
+            .args(serve_args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -140,7 +145,7 @@ impl TestAgent {
         eprintln!("Using key: {:?} (exists: {})", home_key, home_key.exists());
 
         let mut cmd = Command::new(env!("CARGO_BIN_EXE_k-terminus"));
-        cmd.args(["join", orchestrator_addr, "--foreground"]);
+        cmd.args(["join", orchestrator_addr, "--foreground", "--allow-self"]);
 
         if home_key.exists() {
             cmd.args([
@@ -175,16 +180,7 @@ impl Drop for TestAgent {
 
 /// Read the IPC authentication token from the default location
 fn read_auth_token() -> Result<String, std::io::Error> {
-    let token_path = get_default_token_path()?;
-    std::fs::read_to_string(token_path).map(|s| s.trim().to_string())
-}
-
-/// Get the default token path (matches kt_core::ipc_auth::default_token_path)
-fn get_default_token_path() -> Result<PathBuf, std::io::Error> {
-    let config_dir = dirs::config_dir()
-        .or_else(|| dirs::home_dir().map(|h| h.join(".config")))
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "Could not find config directory"))?;
-    Ok(config_dir.join("k-terminus").join("ipc_auth_token"))
+    kt_core::ipc_auth::read_token()
 }
 
 /// Send an IPC request and get response (without authentication)
@@ -445,6 +441,32 @@ fn test_e2e_orchestrator_list_sessions_empty() {
     );
 }
 
+#[test]
+fn test_e2e_orchestrator_ipc_only() {
+    let mut orchestrator = TestOrchestrator::start_with(&["--ipc-only"]);
+    assert!(orchestrator.is_running(), "Orchestrator should be running");
+
+    if !wait_for_ipc(orchestrator.ipc_port, Duration::from_secs(5)) {
+        panic!("IPC server did not start within timeout");
+    }
+
+    let response = ipc_request(orchestrator.ipc_port, r#"{"type":"list_machines"}"#)
+        .expect("Failed to list machines");
+    assert!(
+        response.contains("\"machines\":[]"),
+        "Expected empty machines list, got: {}",
+        response
+    );
+
+    // The SSH port is left free and no host key is generated
+    let ssh_addr = format!("127.0.0.1:{}", orchestrator.config.ssh_port);
+    assert!(
+        std::net::TcpListener::bind(&ssh_addr).is_ok(),
+        "SSH port should not be bound in IPC-only mode"
+    );
+    assert!(!orchestrator.config.dir.path().join("host_key").exists());
+}
+
 #[test]
 fn test_e2e_orchestrator_shutdown() {
     let mut orchestrator = TestOrchestrator::start();
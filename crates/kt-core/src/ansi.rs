@@ -0,0 +1,601 @@
+//! Streaming parser for terminal output
+//!
+//! Session output arrives in chunks that can end anywhere: in the middle of
+//! a multi-byte UTF-8 character, or halfway through an escape sequence.
+//! [`AnsiParser`] splits the stream into text, control characters and
+//! escape sequences, holding back an incomplete character or sequence until
+//! the chunk that completes it arrives. Anything that strips, sanitizes or
+//! transcribes output should be built on it (as [`AnsiStripper`] is) rather
+//! than scanning bytes itself.
+//!
+//! Text derived from output for transcripts or search can have its line
+//! endings normalized (see [`LineEndings`]), so output from Windows and Unix
+//! agents reads the same. The live stream sent to terminals is never
+//! normalized; it has to stay byte-exact.
+//!
+//! Invalid UTF-8 becomes U+FFFD. Escape sequences follow ECMA-48 as xterm
+//! reads it: CSI (`ESC [`), OSC (`ESC ]`, ended by BEL or ST), the other
+//! string sequences (DCS, SOS, PM, APC, ended by ST) and plain `ESC`
+//! sequences such as `ESC ( B`.
+
+use std::mem;
+
+/// Longest escape sequence kept in full; longer ones are truncated
+///
+/// Bounds what a stream that opens a sequence and never ends it can make
+/// the parser buffer.
+pub const MAX_SEQUENCE_LEN: usize = 4096;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+const DEL: u8 = 0x7f;
+
+/// A piece of terminal output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// Text, made of whole characters and free of control characters
+    Text(String),
+    /// A C0 control character other than ESC (e.g. `\n`, `\r`, BEL), or DEL
+    Control(u8),
+    /// A control sequence (`ESC [` ... final byte), as received
+    Csi(Vec<u8>),
+    /// An operating system command (`ESC ]` ... BEL or ST), as received
+    Osc(Vec<u8>),
+    /// Any other escape sequence, as received
+    ///
+    /// Also used for sequences that were cut short by the next one or by
+    /// the end of the stream.
+    Escape(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    /// After ESC, possibly with intermediate bytes
+    Escape,
+    Csi,
+    /// OSC, DCS, SOS, PM or APC payload
+    String {
+        osc: bool,
+    },
+    /// ESC inside a string sequence, which may start ST
+    StringEscape {
+        osc: bool,
+    },
+}
+
+/// Incremental terminal output parser
+#[derive(Debug)]
+pub struct AnsiParser {
+    state: State,
+    /// Text bytes not yet emitted, possibly ending in a partial character
+    text: Vec<u8>,
+    /// The escape sequence being read
+    sequence: Vec<u8>,
+    out: Vec<Segment>,
+}
+
+impl Default for AnsiParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnsiParser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            text: Vec::new(),
+            sequence: Vec::new(),
+            out: Vec::new(),
+        }
+    }
+
+    /// Parse the next chunk of output
+    ///
+    /// Returns the segments completed by it. A character or sequence that
+    /// is still incomplete at the end of `data` is held back for the next
+    /// call.
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Segment> {
+        for &byte in data {
+            self.advance(byte);
+        }
+        self.flush_text(false);
+        mem::take(&mut self.out)
+    }
+
+    /// End the stream, returning whatever was held back
+    ///
+    /// A trailing partial character becomes U+FFFD and an unfinished
+    /// sequence is returned as [`Segment::Escape`].
+    pub fn finish(&mut self) -> Vec<Segment> {
+        self.flush_text(true);
+        if let State::StringEscape { .. } = self.state {
+            self.push_sequence(ESC);
+        }
+        if self.state != State::Ground {
+            self.end_sequence(Segment::Escape);
+        }
+        mem::take(&mut self.out)
+    }
+
+    fn advance(&mut self, byte: u8) {
+        match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => match byte {
+                b'[' if self.sequence.len() == 1 => self.continue_sequence(State::Csi, byte),
+                b']' if self.sequence.len() == 1 => {
+                    self.continue_sequence(State::String { osc: true }, byte)
+                }
+                b'P' | b'X' | b'^' | b'_' if self.sequence.len() == 1 => {
+                    self.continue_sequence(State::String { osc: false }, byte)
+                }
+                0x20..=0x2f => self.push_sequence(byte),
+                0x30..=0x7e => {
+                    self.push_sequence(byte);
+                    self.end_sequence(Segment::Escape);
+                }
+                _ => self.abort_sequence(byte),
+            },
+            State::Csi => match byte {
+                0x20..=0x3f => self.push_sequence(byte),
+                0x40..=0x7e => {
+                    self.push_sequence(byte);
+                    self.end_sequence(Segment::Csi);
+                }
+                // Terminals act on controls inside a CSI without ending it
+                0x00..=0x1f if !matches!(byte, ESC | CAN | SUB) => {
+                    self.out.push(Segment::Control(byte))
+                }
+                _ => self.abort_sequence(byte),
+            },
+            State::String { osc } => match byte {
+                BEL if osc => {
+                    self.push_sequence(byte);
+                    self.end_sequence(Segment::Osc);
+                }
+                ESC => self.state = State::StringEscape { osc },
+                CAN | SUB => self.abort_sequence(byte),
+                _ => self.push_sequence(byte),
+            },
+            State::StringEscape { osc } => {
+                if byte == b'\\' {
+                    self.push_sequence(ESC);
+                    self.push_sequence(byte);
+                    self.end_sequence(if osc { Segment::Osc } else { Segment::Escape });
+                } else {
+                    // An ESC that doesn't form ST ends the string and
+                    // starts a sequence of its own
+                    self.end_sequence(if osc { Segment::Osc } else { Segment::Escape });
+                    self.ground(ESC);
+                    self.advance(byte);
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, byte: u8) {
+        match byte {
+            ESC => {
+                self.flush_text(true);
+                self.sequence.push(byte);
+                self.state = State::Escape;
+            }
+            0x00..=0x1f | DEL => {
+                self.flush_text(true);
+                self.out.push(Segment::Control(byte));
+            }
+            _ => self.text.push(byte),
+        }
+    }
+
+    fn push_sequence(&mut self, byte: u8) {
+        if self.sequence.len() < MAX_SEQUENCE_LEN {
+            self.sequence.push(byte);
+        }
+    }
+
+    fn continue_sequence(&mut self, state: State, byte: u8) {
+        self.push_sequence(byte);
+        self.state = state;
+    }
+
+    fn end_sequence(&mut self, kind: fn(Vec<u8>) -> Segment) {
+        self.out.push(kind(mem::take(&mut self.sequence)));
+        self.state = State::Ground;
+    }
+
+    /// End the current sequence early at `byte`, which is then read afresh
+    fn abort_sequence(&mut self, byte: u8) {
+        self.end_sequence(Segment::Escape);
+        match byte {
+            // CAN and SUB only cancel the sequence
+            CAN | SUB => {}
+            _ => self.ground(byte),
+        }
+    }
+
+    /// Emit buffered text; unless `all`, a trailing partial character is
+    /// kept for the next chunk
+    fn flush_text(&mut self, all: bool) {
+        let mut text = String::new();
+        let mut rest = &self.text[..];
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Validated just above
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None if all => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &[];
+                            break;
+                        }
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        let kept = rest.len();
+        let len = self.text.len();
+        self.text.drain(..len - kept);
+        if !text.is_empty() {
+            self.out.push(Segment::Text(text));
+        }
+    }
+}
+
+/// Line endings written by [`AnsiStripper`]
+///
+/// Normalizing treats a newline and any carriage returns right before it
+/// as one line ending. Carriage returns not followed by a newline (as
+/// progress bars use) are kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LineEndings {
+    /// As received; PTYs on both Unix and Windows usually send `\r\n`
+    #[default]
+    AsReceived,
+    /// `\n`
+    Lf,
+    /// `\r\n`
+    Crlf,
+}
+
+impl LineEndings {
+    fn newline(self) -> &'static str {
+        match self {
+            Self::Crlf => "\r\n",
+            _ => "\n",
+        }
+    }
+}
+
+/// Reduces terminal output to plain text
+///
+/// Keeps text, newlines, carriage returns and tabs; drops escape sequences
+/// and every other control character.
+#[derive(Debug, Default)]
+pub struct AnsiStripper {
+    parser: AnsiParser,
+    line_endings: LineEndings,
+    /// Carriage returns at the end of the last chunk, held back until it's
+    /// known whether a newline follows
+    pending_cr: usize,
+}
+
+impl AnsiStripper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write line endings as `line_endings`
+    pub fn with_line_endings(mut self, line_endings: LineEndings) -> Self {
+        self.line_endings = line_endings;
+        self
+    }
+
+    /// Strip the next chunk of output
+    pub fn feed(&mut self, data: &[u8]) -> String {
+        let text = plain_text(self.parser.feed(data));
+        self.normalize(&text, false)
+    }
+
+    /// End the stream, returning any text that was held back
+    pub fn finish(&mut self) -> String {
+        let text = plain_text(self.parser.finish());
+        self.normalize(&text, true)
+    }
+
+    fn normalize(&mut self, text: &str, finish: bool) -> String {
+        if self.line_endings == LineEndings::AsReceived {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\r' => self.pending_cr += 1,
+                '\n' => {
+                    self.pending_cr = 0;
+                    out.push_str(self.line_endings.newline());
+                }
+                c => {
+                    self.flush_cr(&mut out);
+                    out.push(c);
+                }
+            }
+        }
+        if finish {
+            self.flush_cr(&mut out);
+        }
+        out
+    }
+
+    fn flush_cr(&mut self, out: &mut String) {
+        for _ in 0..mem::take(&mut self.pending_cr) {
+            out.push('\r');
+        }
+    }
+}
+
+fn plain_text(segments: Vec<Segment>) -> String {
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            Segment::Text(s) => text.push_str(&s),
+            Segment::Control(c @ (b'\n' | b'\r' | b'\t')) => text.push(c as char),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// Make `text` fit to show in a terminal as a banner, such as the message
+/// shown on attaching to a session
+///
+/// Keeps text, tabs and SGR sequences (colors, bold...) for emphasis, and
+/// drops every other escape sequence and control character, so a banner
+/// can't move the cursor or clear the screen. Lines end in `\r\n`, which a
+/// terminal in raw mode needs, and attributes are reset at the end so they
+/// don't carry over into what follows.
+pub fn terminal_banner(text: &str) -> String {
+    let mut parser = AnsiParser::new();
+    let mut segments = parser.feed(text.as_bytes());
+    segments.extend(parser.finish());
+
+    let mut banner = String::with_capacity(text.len() + 8);
+    for segment in segments {
+        match segment {
+            Segment::Text(s) => banner.push_str(&s),
+            Segment::Control(b'\n') => banner.push_str("\r\n"),
+            Segment::Control(b'\t') => banner.push('\t'),
+            Segment::Csi(sequence) if sequence.last() == Some(&b'm') => {
+                banner.push_str(&String::from_utf8_lossy(&sequence));
+            }
+            _ => {}
+        }
+    }
+    let ends_line = banner.ends_with('\n');
+    banner.push_str("\u{1b}[0m");
+    if !ends_line {
+        banner.push_str("\r\n");
+    }
+    banner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output exercising every kind of segment, with multi-byte characters
+    /// next to and inside sequences
+    const SAMPLE: &[u8] = "héllo \u{1b}[1;31m日本\u{1b}[0m\r\n\
+        \u{1b}]0;tïtle 🦀\u{7}\u{1b}]2;other\u{1b}\\\
+        \u{1b}(B\u{1b}P1$r0m\u{1b}\\tab\there 🦀\u{7}end\n"
+        .as_bytes();
+
+    /// Parse `chunks` in order, merging adjacent text so results compare
+    /// equal however the stream was split
+    fn parse(chunks: &[&[u8]]) -> Vec<Segment> {
+        let mut parser = AnsiParser::new();
+        let mut segments = Vec::new();
+        for chunk in chunks {
+            segments.extend(parser.feed(chunk));
+        }
+        segments.extend(parser.finish());
+
+        let mut merged: Vec<Segment> = Vec::new();
+        for segment in segments {
+            match (merged.last_mut(), segment) {
+                (Some(Segment::Text(prev)), Segment::Text(s)) => prev.push_str(&s),
+                (_, segment) => merged.push(segment),
+            }
+        }
+        merged
+    }
+
+    #[test]
+    fn test_segments() {
+        assert_eq!(
+            parse(&[SAMPLE]),
+            vec![
+                Segment::Text("héllo ".into()),
+                Segment::Csi(b"\x1b[1;31m".to_vec()),
+                Segment::Text("日本".into()),
+                Segment::Csi(b"\x1b[0m".to_vec()),
+                Segment::Control(b'\r'),
+                Segment::Control(b'\n'),
+                Segment::Osc("\x1b]0;tïtle 🦀\x07".as_bytes().to_vec()),
+                Segment::Osc(b"\x1b]2;other\x1b\\".to_vec()),
+                Segment::Escape(b"\x1b(B".to_vec()),
+                Segment::Escape(b"\x1bP1$r0m\x1b\\".to_vec()),
+                Segment::Text("tab".into()),
+                Segment::Control(b'\t'),
+                Segment::Text("here 🦀".into()),
+                Segment::Control(BEL),
+                Segment::Text("end".into()),
+                Segment::Control(b'\n'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_at_every_offset() {
+        let whole = parse(&[SAMPLE]);
+        for i in 0..=SAMPLE.len() {
+            let (a, b) = SAMPLE.split_at(i);
+            assert_eq!(parse(&[a, b]), whole, "split at {}", i);
+            for j in i..=SAMPLE.len() {
+                let (b, c) = SAMPLE[i..].split_at(j - i);
+                assert_eq!(parse(&[a, b, c]), whole, "split at {} and {}", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_at_a_time_never_emits_partial_characters() {
+        let mut parser = AnsiParser::new();
+        let mut segments = Vec::new();
+        for byte in "日本🦀".as_bytes() {
+            let out = parser.feed(std::slice::from_ref(byte));
+            for segment in &out {
+                let Segment::Text(s) = segment else {
+                    panic!("unexpected {:?}", segment);
+                };
+                assert!(!s.contains(char::REPLACEMENT_CHARACTER));
+            }
+            segments.extend(out);
+        }
+        assert_eq!(
+            segments,
+            vec![
+                Segment::Text("日".into()),
+                Segment::Text("本".into()),
+                Segment::Text("🦀".into()),
+            ]
+        );
+        assert!(parser.finish().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        assert_eq!(
+            parse(&[b"a\xffb\xe6\x97\nc\xe6"]),
+            vec![
+                Segment::Text("a\u{fffd}b\u{fffd}".into()),
+                Segment::Control(b'\n'),
+                Segment::Text("c\u{fffd}".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_interrupted_and_unfinished_sequences() {
+        assert_eq!(
+            parse(&[b"\x1b[31\x1b[0mx\x1b[1\x18y\x1b]0;t"]),
+            vec![
+                Segment::Escape(b"\x1b[31".to_vec()),
+                Segment::Csi(b"\x1b[0m".to_vec()),
+                Segment::Text("x".into()),
+                Segment::Escape(b"\x1b[1".to_vec()),
+                Segment::Text("y".into()),
+                Segment::Escape(b"\x1b]0;t".to_vec()),
+            ]
+        );
+        // Controls inside a CSI are acted on without ending it
+        assert_eq!(
+            parse(&[b"\x1b[1\r;2H"]),
+            vec![Segment::Control(b'\r'), Segment::Csi(b"\x1b[1;2H".to_vec())]
+        );
+        // An ESC that isn't ST ends the string and starts a new sequence
+        assert_eq!(
+            parse(&[b"\x1b]0;t\x1b[Km"]),
+            vec![
+                Segment::Osc(b"\x1b]0;t".to_vec()),
+                Segment::Csi(b"\x1b[K".to_vec()),
+                Segment::Text("m".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_sequence_is_bounded() {
+        let mut parser = AnsiParser::new();
+        parser.feed(b"\x1b]0;");
+        for _ in 0..100 {
+            assert!(parser.feed(&[b'x'; 1024]).is_empty());
+        }
+        let out = parser.feed(b"\x07after");
+        let Segment::Osc(osc) = &out[0] else {
+            panic!("unexpected {:?}", out);
+        };
+        assert_eq!(osc.len(), MAX_SEQUENCE_LEN);
+        assert_eq!(out[1], Segment::Text("after".into()));
+    }
+
+    #[test]
+    fn test_stripper() {
+        let mut stripper = AnsiStripper::new();
+        let mut text = String::new();
+        for chunk in SAMPLE.chunks(3) {
+            text.push_str(&stripper.feed(chunk));
+        }
+        text.push_str(&stripper.finish());
+        assert_eq!(text, "héllo 日本\r\ntab\there 🦀end\n");
+    }
+
+    #[test]
+    fn test_stripper_normalizes_line_endings() {
+        let output = b"a\r\nb\nc\r\r\nbar 50%\rbar 100%\r\x1b[K\nend\r";
+        let strip = |line_endings, chunk_size| {
+            let mut stripper = AnsiStripper::new().with_line_endings(line_endings);
+            let mut text = String::new();
+            for chunk in output.chunks(chunk_size) {
+                text.push_str(&stripper.feed(chunk));
+            }
+            text + &stripper.finish()
+        };
+
+        for chunk_size in [1, 2, 3, output.len()] {
+            assert_eq!(
+                strip(LineEndings::Lf, chunk_size),
+                "a\nb\nc\nbar 50%\rbar 100%\nend\r"
+            );
+            assert_eq!(
+                strip(LineEndings::Crlf, chunk_size),
+                "a\r\nb\r\nc\r\nbar 50%\rbar 100%\r\nend\r"
+            );
+        }
+        assert_eq!(
+            strip(LineEndings::AsReceived, 2),
+            "a\r\nb\nc\r\r\nbar 50%\rbar 100%\r\nend\r"
+        );
+    }
+
+    #[test]
+    fn test_terminal_banner_keeps_only_emphasis() {
+        assert_eq!(
+            terminal_banner("\u{1b}[1mShared host\u{1b}[0m\nBe nice"),
+            "\u{1b}[1mShared host\u{1b}[0m\r\nBe nice\u{1b}[0m\r\n"
+        );
+        // No clearing the screen, moving the cursor or setting the title
+        assert_eq!(
+            terminal_banner("\u{1b}[2J\u{1b}[H\u{1b}]0;pwned\u{7}Hi\r\n\u{8}"),
+            "Hi\r\n\u{1b}[0m"
+        );
+    }
+}
@@ -4,13 +4,18 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::time::Duration;
 
+use kt_protocol::MAX_METRICS_INTERVAL_SECS;
+
 use super::orchestrator::BackoffConfig;
-use super::serde_utils::duration_secs;
+use super::{DurationString, VersionedConfig};
 
 /// Configuration for the client agent
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AgentConfig {
+    /// Schema version (see [`super::migration`])
+    pub version: u32,
+
     /// Orchestrator address to connect to.
     ///
     /// **Important**: Use a Tailscale hostname (e.g., `my-laptop.tailnet.ts.net:2222`),
@@ -18,6 +23,17 @@ pub struct AgentConfig {
     /// changes, while IP addresses may change when networks change.
     pub orchestrator_address: String,
 
+    /// Orchestrators to fail over to, in order, when the primary can't be
+    /// reached. The agent returns to the primary once it is back.
+    pub fallback_orchestrators: Vec<String>,
+
+    /// Failed connection attempts on one orchestrator before trying the next
+    pub failover_attempts: u32,
+
+    /// How often to check whether the primary is back while connected to a
+    /// fallback
+    pub failback_check_interval: DurationString,
+
     /// Path to the private key for authentication
     pub private_key_path: PathBuf,
 
@@ -30,6 +46,22 @@ pub struct AgentConfig {
     /// Machine alias (optional, defaults to hostname)
     pub alias: Option<String>,
 
+    /// Free-form label shown next to the machine, e.g. "CI runner"
+    pub label: Option<String>,
+
+    /// Hostname to report instead of the system's
+    pub hostname: Option<String>,
+
+    /// Operating system to report instead of the detected one
+    pub os: Option<String>,
+
+    /// CPU architecture to report instead of the detected one
+    pub arch: Option<String>,
+
+    /// Directory for agent state such as the install ID the machine ID is
+    /// derived from (defaults to the config directory)
+    pub state_dir: Option<PathBuf>,
+
     /// Tags for this machine
     pub tags: Vec<String>,
 
@@ -39,21 +71,42 @@ pub struct AgentConfig {
     /// Default environment variables for sessions
     pub default_env: Vec<(String, String)>,
 
+    /// `TERM` for sessions whose client doesn't report its terminal type
+    pub default_term: String,
+
     /// Backoff configuration for reconnections
     pub backoff: BackoffConfig,
 
     /// Connection timeout
-    #[serde(with = "duration_secs")]
-    pub connect_timeout: Duration,
+    pub connect_timeout: DurationString,
 
     /// Maximum number of concurrent sessions
     pub max_sessions: Option<u32>,
+
+    /// Tailnet domain to use when Tailscale doesn't report a MagicDNS suffix,
+    /// e.g. a Headscale `base_domain`
+    pub tailnet_domain: Option<String>,
+
+    /// On-disk journal of session output
+    pub session_journal: SessionJournalConfig,
+
+    /// Disconnect instead of just warning when the orchestrator's version
+    /// differs by more than a patch
+    pub strict_version: bool,
+
+    /// Seconds between reports of host metrics (None or 0 = no reports,
+    /// and no collection); the orchestrator can change it per connection
+    pub metrics_interval_secs: Option<u64>,
 }
 
 impl Default for AgentConfig {
     fn default() -> Self {
         Self {
+            version: <Self as VersionedConfig>::VERSION,
             orchestrator_address: "localhost:2222".to_string(),
+            fallback_orchestrators: vec![],
+            failover_attempts: 3,
+            failback_check_interval: DurationString::from_secs(60),
             private_key_path: dirs::home_dir()
                 .unwrap_or_default()
                 .join(".config")
@@ -62,12 +115,22 @@ impl Default for AgentConfig {
             orchestrator_host_key: None,
             username: whoami::username(),
             alias: None,
+            label: None,
+            hostname: None,
+            os: None,
+            arch: None,
+            state_dir: None,
             tags: vec![],
             default_shell: None,
-            default_env: vec![("TERM".to_string(), "xterm-256color".to_string())],
+            default_env: vec![],
+            default_term: "xterm-256color".to_string(),
             backoff: BackoffConfig::default(),
-            connect_timeout: Duration::from_secs(30),
+            connect_timeout: DurationString::from_secs(30),
             max_sessions: None,
+            tailnet_domain: None,
+            session_journal: SessionJournalConfig::default(),
+            strict_version: false,
+            metrics_interval_secs: None,
         }
     }
 }
@@ -76,7 +139,67 @@ impl AgentConfig {
     /// Get the machine alias, falling back to hostname
     pub fn machine_alias(&self) -> String {
         self.alias
+            .clone()
+            .unwrap_or_else(|| self.reported_hostname())
+    }
+
+    /// Hostname reported to the orchestrator
+    pub fn reported_hostname(&self) -> String {
+        self.hostname
             .clone()
             .unwrap_or_else(|| gethostname::gethostname().to_string_lossy().into_owned())
     }
+
+    /// Interval between host metrics reports, capped at a day (None = off)
+    pub fn metrics_interval(&self) -> Option<Duration> {
+        self.metrics_interval_secs
+            .filter(|&secs| secs > 0)
+            .map(|secs| Duration::from_secs(secs.min(MAX_METRICS_INTERVAL_SECS)))
+    }
+
+    /// Directory agent state is kept in
+    pub fn state_dir(&self) -> PathBuf {
+        self.state_dir
+            .clone()
+            .unwrap_or_else(super::default_config_dir)
+    }
+}
+
+/// Journal of recent session output kept on disk, so output from before an
+/// agent restart isn't lost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionJournalConfig {
+    /// Whether to journal session output
+    pub enabled: bool,
+
+    /// Maximum journal size per session, in KiB; older output is dropped
+    pub max_kib: u64,
+
+    /// Directory for journal files (defaults to `<config_dir>/journal`)
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for SessionJournalConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_kib: 256,
+            dir: None,
+        }
+    }
+}
+
+impl SessionJournalConfig {
+    /// Directory journal files are written to
+    pub fn journal_dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| super::default_config_dir().join("journal"))
+    }
+
+    /// Maximum journal size per session, in bytes
+    pub fn max_bytes(&self) -> u64 {
+        self.max_kib.saturating_mul(1024)
+    }
 }
@@ -0,0 +1,170 @@
+//! Durations in config files
+//!
+//! Config files used to give durations as a number of seconds. They are now
+//! written for humans, as `"500ms"`, `"30s"`, `"5m"` or `"1h"` (units may be
+//! combined, as in `"1h30m"`), and a bare number is still read as seconds so
+//! that older files keep working.
+
+use std::fmt;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::time::{format_duration, parse_duration};
+
+/// Formats a config duration is accepted in, for error messages
+pub const ACCEPTED_FORMATS: &str =
+    "a number of seconds or a duration like \"500ms\", \"30s\", \"5m\" or \"1h\"";
+
+/// A config duration, written like `"30s"` (see the [module docs](self))
+///
+/// Dereferences to the [`Duration`] it holds and is serialized in the
+/// largest unit that represents it exactly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DurationString(pub Duration);
+
+impl DurationString {
+    /// A duration of `secs` seconds
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(Duration::from_secs(secs))
+    }
+
+    /// A duration of `millis` milliseconds
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(Duration::from_millis(millis))
+    }
+
+    /// The duration held
+    pub const fn get(self) -> Duration {
+        self.0
+    }
+}
+
+impl Deref for DurationString {
+    type Target = Duration;
+
+    fn deref(&self) -> &Duration {
+        &self.0
+    }
+}
+
+impl From<Duration> for DurationString {
+    fn from(duration: Duration) -> Self {
+        Self(duration)
+    }
+}
+
+impl From<DurationString> for Duration {
+    fn from(duration: DurationString) -> Self {
+        duration.0
+    }
+}
+
+impl fmt::Display for DurationString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format_duration(self.0))
+    }
+}
+
+impl FromStr for DurationString {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_duration(s)
+            .map(Self)
+            .ok_or_else(|| format!("invalid duration {:?}, expected {}", s, ACCEPTED_FORMATS))
+    }
+}
+
+impl Serialize for DurationString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DurationString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = DurationString;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(ACCEPTED_FORMATS)
+    }
+
+    fn visit_u64<E: de::Error>(self, secs: u64) -> Result<DurationString, E> {
+        Ok(DurationString::from_secs(secs))
+    }
+
+    fn visit_i64<E: de::Error>(self, secs: i64) -> Result<DurationString, E> {
+        u64::try_from(secs)
+            .map(DurationString::from_secs)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(secs), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<DurationString, E> {
+        parse_duration(s)
+            .map(DurationString)
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestConfig {
+        timeout: DurationString,
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for (text, duration) in [
+            ("500ms", Duration::from_millis(500)),
+            ("45s", Duration::from_secs(45)),
+            ("2m", Duration::from_secs(120)),
+            ("1h", Duration::from_secs(3600)),
+        ] {
+            let toml = format!("timeout = \"{}\"\n", text);
+            let config: TestConfig = toml::from_str(&toml).unwrap();
+            assert_eq!(*config.timeout, duration);
+            assert_eq!(toml::to_string(&config).unwrap(), toml);
+        }
+
+        let config: TestConfig = serde_json::from_str(r#"{"timeout":"1h30m"}"#).unwrap();
+        assert_eq!(config.timeout, DurationString::from_secs(5400));
+        assert_eq!(
+            serde_json::to_string(&config).unwrap(),
+            r#"{"timeout":"90m"}"#
+        );
+    }
+
+    #[test]
+    fn test_bare_number_is_seconds() {
+        let config: TestConfig = toml::from_str("timeout = 90").unwrap();
+        assert_eq!(config.timeout, DurationString::from_secs(90));
+        let config: TestConfig = toml::from_str("timeout = \"90\"").unwrap();
+        assert_eq!(config.timeout, DurationString::from_secs(90));
+    }
+
+    #[test]
+    fn test_invalid_names_accepted_formats() {
+        for invalid in ["timeout = \"soon\"", "timeout = -1", "timeout = 1.5"] {
+            let error = toml::from_str::<TestConfig>(invalid)
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains(ACCEPTED_FORMATS), "{}", error);
+        }
+        let error = "soon".parse::<DurationString>().unwrap_err();
+        assert!(error.contains(ACCEPTED_FORMATS), "{}", error);
+    }
+}
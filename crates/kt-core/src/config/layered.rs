@@ -0,0 +1,540 @@
+//! Layered configuration: defaults ← file ← environment ← command line
+//!
+//! Containerized deployments configure k-Terminus without a config file by
+//! setting environment variables. Each config type has a prefix, and nested
+//! keys are separated by a double underscore:
+//!
+//! | Variable                               | Key                               |
+//! |----------------------------------------|-----------------------------------|
+//! | `KT_ORCHESTRATOR__BIND_ADDRESS`        | `orchestrator.bind_address`       |
+//! | `KT_ORCHESTRATOR__BACKOFF__MAX`        | `orchestrator.backoff.max`        |
+//! | `KT_AGENT__ORCHESTRATOR_ADDRESS`       | `orchestrator_address` (agent)    |
+//!
+//! Values are read as the type the key already has: booleans accept
+//! `true`/`false`, `1`/`0`, `yes`/`no` and `on`/`off`, durations accept
+//! seconds or strings like `"30s"`, and lists are comma-separated (or written
+//! as a TOML array). Keys without a default are parsed as TOML literals when
+//! they look like one and as strings otherwise.
+//!
+//! [`ConfigLoader`] applies the layers in order and remembers which layer
+//! each value came from ([`ConfigSources`]), for `config show --origins`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use super::migration::{unknown_keys, VersionedConfig};
+use super::{read_config_value, AgentConfig, ConfigFile};
+use crate::error::ConfigError;
+
+/// Separator between nested keys in environment variable names
+pub const ENV_SEPARATOR: &str = "__";
+
+/// A config type that can be overridden from the environment
+pub trait EnvConfig: VersionedConfig + Default {
+    /// Prefix of the environment variables for this config, e.g. `KT_AGENT__`
+    const ENV_PREFIX: &'static str;
+
+    /// Table the variables are applied to, e.g. `["orchestrator"]`
+    const ENV_TABLE: &'static [&'static str];
+}
+
+impl EnvConfig for ConfigFile {
+    const ENV_PREFIX: &'static str = "KT_ORCHESTRATOR__";
+    const ENV_TABLE: &'static [&'static str] = &["orchestrator"];
+}
+
+impl EnvConfig for AgentConfig {
+    const ENV_PREFIX: &'static str = "KT_AGENT__";
+    const ENV_TABLE: &'static [&'static str] = &[];
+}
+
+/// Where an effective config value came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    /// Built-in default
+    Default,
+    /// A config file
+    File(PathBuf),
+    /// An environment variable (its name)
+    Env(String),
+    /// A command-line flag (its name)
+    Cli(String),
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(path) => write!(f, "file {}", path.display()),
+            ConfigSource::Env(var) => write!(f, "env {}", var),
+            ConfigSource::Cli(flag) => write!(f, "flag {}", flag),
+        }
+    }
+}
+
+/// Which layer set each config value, keyed by dotted path
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSources {
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigSources {
+    /// Source of the value at `key` (e.g. `orchestrator.bind_address`)
+    pub fn get(&self, key: &str) -> &ConfigSource {
+        self.sources.get(key).unwrap_or(&ConfigSource::Default)
+    }
+
+    /// Values that didn't come from the defaults, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ConfigSource)> {
+        self.sources
+            .iter()
+            .map(|(key, source)| (key.as_str(), source))
+    }
+
+    /// Record `source` for `key`, replacing whatever set it or its children
+    fn set(&mut self, key: &str, source: ConfigSource) {
+        let children = format!("{}.", key);
+        self.sources.retain(|k, _| !k.starts_with(&children));
+        self.sources.insert(key.to_string(), source);
+    }
+}
+
+/// Builds a config from defaults, a file, the environment and flags
+pub struct ConfigLoader<T> {
+    value: toml::Value,
+    sources: ConfigSources,
+    _config: PhantomData<T>,
+}
+
+impl<T: EnvConfig> Default for ConfigLoader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: EnvConfig> ConfigLoader<T> {
+    /// Start from the config type's defaults
+    pub fn new() -> Self {
+        let value = toml::Value::try_from(T::default())
+            .unwrap_or_else(|_| toml::Value::Table(toml::Table::new()));
+        Self {
+            value,
+            sources: ConfigSources::default(),
+            _config: PhantomData,
+        }
+    }
+
+    /// Apply a config file, migrating it if needed (see [`super::load_config`])
+    pub fn with_file(&mut self, path: &Path) -> Result<&mut Self, ConfigError> {
+        let file = read_config_value::<T>(path)?;
+        for (key, _) in leaves(&file) {
+            self.sources
+                .set(&key, ConfigSource::File(path.to_path_buf()));
+        }
+        merge(&mut self.value, file);
+        Ok(self)
+    }
+
+    /// Apply the process environment
+    pub fn with_env(&mut self) -> &mut Self {
+        self.with_env_vars(std::env::vars())
+    }
+
+    /// Apply environment variables from `vars`, ignoring ones without the
+    /// config type's prefix
+    pub fn with_env_vars(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> &mut Self {
+        // Sorted so that the outcome doesn't depend on the environment's order
+        let mut vars: Vec<_> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(T::ENV_PREFIX))
+            .collect();
+        vars.sort();
+
+        for (name, raw) in vars {
+            let Some(path) = env_key_path::<T>(&name) else {
+                tracing::warn!("Ignoring malformed config variable {}", name);
+                continue;
+            };
+            let key = path.join(".");
+            let value = coerce_env_value(&raw, lookup(&self.value, &path));
+            if set_path(&mut self.value, &path, value) {
+                self.sources.set(&key, ConfigSource::Env(name));
+            } else {
+                tracing::warn!("Ignoring {}: {} is not a table", name, key);
+            }
+        }
+        self
+    }
+
+    /// Apply a command-line flag that sets the dotted `key`
+    pub fn with_cli(
+        &mut self,
+        key: &str,
+        value: impl Into<toml::Value>,
+        flag: &str,
+    ) -> Result<&mut Self, ConfigError> {
+        let path: Vec<String> = key.split('.').map(str::to_string).collect();
+        if !set_path(&mut self.value, &path, value.into()) {
+            return Err(ConfigError::Invalid(format!(
+                "Cannot set {} from {}",
+                key, flag
+            )));
+        }
+        self.sources.set(key, ConfigSource::Cli(flag.to_string()));
+        Ok(self)
+    }
+
+    /// Build the effective config.
+    ///
+    /// Unknown keys are logged as a warning, naming the layer that set them.
+    pub fn load(&self) -> Result<T, ConfigError> {
+        let config: T = self.value.clone().try_into().map_err(|e| {
+            // The error names the offending key; say which override set it
+            let message = e.to_string();
+            let culprit = self.sources.iter().find(|(key, source)| {
+                matches!(source, ConfigSource::Env(_) | ConfigSource::Cli(_))
+                    && message.contains(&format!("`{}`", key))
+            });
+            match culprit {
+                Some((_, source)) => {
+                    ConfigError::Invalid(format!("{} (set by {})", message.trim_end(), source))
+                }
+                None => ConfigError::Parse(e),
+            }
+        })?;
+
+        let unknown = unknown_keys(&self.value, &toml::Value::try_from(&config)?);
+        if !unknown.is_empty() {
+            let described: Vec<String> = unknown
+                .iter()
+                .map(|key| format!("{} ({})", key, self.source_of(key)))
+                .collect();
+            tracing::warn!("Ignoring unknown config keys: {}", described.join(", "));
+        }
+
+        Ok(config)
+    }
+
+    /// Which layer set each value
+    pub fn sources(&self) -> &ConfigSources {
+        &self.sources
+    }
+
+    /// Source of `key`, or of the table that was set as a whole around it
+    fn source_of(&self, key: &str) -> &ConfigSource {
+        let mut prefix = key;
+        loop {
+            let source = self.sources.get(prefix);
+            if *source != ConfigSource::Default {
+                return source;
+            }
+            match prefix.rsplit_once('.') {
+                Some((parent, _)) => prefix = parent,
+                None => return source,
+            }
+        }
+    }
+}
+
+/// Every non-table value in `value`, as (dotted path, value) in key order
+pub fn leaves(value: &toml::Value) -> Vec<(String, &toml::Value)> {
+    let mut out = Vec::new();
+    collect_leaves(value, "", &mut out);
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    out
+}
+
+fn collect_leaves<'a>(
+    value: &'a toml::Value,
+    prefix: &str,
+    out: &mut Vec<(String, &'a toml::Value)>,
+) {
+    match value {
+        toml::Value::Table(table) if !table.is_empty() || prefix.is_empty() => {
+            for (key, child) in table {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                collect_leaves(child, &path, out);
+            }
+        }
+        _ => out.push((prefix.to_string(), value)),
+    }
+}
+
+/// Deep-merge `overlay` into `base`; tables merge, anything else replaces
+fn merge(base: &mut toml::Value, overlay: toml::Value) {
+    match (base, overlay) {
+        (toml::Value::Table(base), toml::Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Key path for an environment variable, e.g. `KT_ORCHESTRATOR__BACKOFF__MAX`
+/// becomes `["orchestrator", "backoff", "max"]`
+fn env_key_path<T: EnvConfig>(name: &str) -> Option<Vec<String>> {
+    let rest = name.strip_prefix(T::ENV_PREFIX)?;
+    let mut path: Vec<String> = T::ENV_TABLE.iter().map(|s| s.to_string()).collect();
+    for segment in rest.split(ENV_SEPARATOR) {
+        if segment.is_empty() {
+            return None;
+        }
+        path.push(segment.to_ascii_lowercase());
+    }
+    Some(path)
+}
+
+fn lookup<'a>(value: &'a toml::Value, path: &[String]) -> Option<&'a toml::Value> {
+    path.iter().try_fold(value, |value, key| value.get(key))
+}
+
+/// Set the value at `path`, creating tables on the way.
+///
+/// Returns false if a non-table value is in the way.
+fn set_path(root: &mut toml::Value, path: &[String], value: toml::Value) -> bool {
+    let Some((last, parents)) = path.split_last() else {
+        return false;
+    };
+    let mut current = root;
+    for key in parents {
+        let Some(table) = current.as_table_mut() else {
+            return false;
+        };
+        current = table
+            .entry(key.clone())
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+    }
+    match current.as_table_mut() {
+        Some(table) => {
+            table.insert(last.clone(), value);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Turn an environment variable's text into a value of the same type as the
+/// one it replaces.
+///
+/// Text that doesn't fit the type is kept as a string, so deserializing the
+/// config reports the mismatch (and strings are how durations like `"30s"`
+/// are written anyway).
+fn coerce_env_value(raw: &str, current: Option<&toml::Value>) -> toml::Value {
+    let string = || toml::Value::String(raw.to_string());
+    match current {
+        Some(toml::Value::String(_)) => string(),
+        Some(toml::Value::Boolean(_)) => parse_bool(raw).map_or_else(string, toml::Value::Boolean),
+        Some(toml::Value::Integer(_)) => raw
+            .trim()
+            .parse()
+            .map_or_else(|_| string(), toml::Value::Integer),
+        Some(toml::Value::Float(_)) => raw
+            .trim()
+            .parse()
+            .map_or_else(|_| string(), toml::Value::Float),
+        Some(toml::Value::Array(_)) => match parse_literal(raw) {
+            Some(array @ toml::Value::Array(_)) => array,
+            _ => toml::Value::Array(
+                raw.split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| toml::Value::String(item.to_string()))
+                    .collect(),
+            ),
+        },
+        _ => match parse_literal(raw) {
+            Some(toml::Value::Datetime(_)) | None => string(),
+            Some(value) => value,
+        },
+    }
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse text as a TOML value (`42`, `true`, `["a", "b"]`, ...)
+fn parse_literal(raw: &str) -> Option<toml::Value> {
+    let mut table: toml::Table = toml::from_str(&format!("value = {}", raw)).ok()?;
+    table.remove("value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write_config(dir: &Path, content: &str) -> PathBuf {
+        let path = dir.join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_precedence_default_file_env_cli() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_config(
+            dir.path(),
+            r#"
+            version = 1
+            [orchestrator]
+            bind_address = "0.0.0.0:2222"
+            ipc_port = 3000
+            heartbeat_interval = 10
+            "#,
+        );
+
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        loader.with_file(&path).unwrap();
+        loader.with_env_vars(vars(&[
+            ("KT_ORCHESTRATOR__BIND_ADDRESS", "10.0.0.1:2222"),
+            ("KT_ORCHESTRATOR__HEARTBEAT_INTERVAL", "45s"),
+            ("KT_AGENT__ALIAS", "not-for-the-orchestrator"),
+            ("PATH", "/usr/bin"),
+        ]));
+        loader
+            .with_cli("orchestrator.bind_address", "127.0.0.1:4444", "--bind")
+            .unwrap();
+
+        let config = loader.load().unwrap().orchestrator;
+        assert_eq!(config.bind_address, "127.0.0.1:4444");
+        assert_eq!(config.ipc_port, 3000);
+        assert_eq!(*config.heartbeat_interval, Duration::from_secs(45));
+        assert_eq!(*config.heartbeat_timeout, Duration::from_secs(90));
+
+        let sources = loader.sources();
+        assert_eq!(
+            sources.get("orchestrator.bind_address"),
+            &ConfigSource::Cli("--bind".to_string())
+        );
+        assert_eq!(
+            sources.get("orchestrator.heartbeat_interval"),
+            &ConfigSource::Env("KT_ORCHESTRATOR__HEARTBEAT_INTERVAL".to_string())
+        );
+        assert_eq!(
+            sources.get("orchestrator.ipc_port"),
+            &ConfigSource::File(path.clone())
+        );
+        assert_eq!(
+            sources.get("orchestrator.heartbeat_timeout"),
+            &ConfigSource::Default
+        );
+    }
+
+    #[test]
+    fn test_env_without_file() {
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        loader.with_env_vars(vars(&[
+            ("KT_ORCHESTRATOR__BACKOFF__MAX", "2m"),
+            ("KT_ORCHESTRATOR__BACKOFF__JITTER", "0.5"),
+            ("KT_ORCHESTRATOR__MAX_CONNECTIONS", "8"),
+            ("KT_ORCHESTRATOR__TAILSCALE_HOSTNAME", "laptop"),
+            ("KT_ORCHESTRATOR__AUTO_START", "false"),
+        ]));
+
+        let config = loader.load().unwrap().orchestrator;
+        assert_eq!(*config.backoff.max, Duration::from_secs(120));
+        assert_eq!(*config.backoff.initial, Duration::from_secs(1));
+        assert_eq!(config.backoff.jitter, 0.5);
+        assert_eq!(config.max_connections, Some(8));
+        assert_eq!(config.tailscale_hostname.as_deref(), Some("laptop"));
+        assert!(!config.auto_start);
+        assert!(ConfigFile::default().orchestrator.auto_start);
+    }
+
+    #[test]
+    fn test_agent_env_booleans_lists_and_durations() {
+        #[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+        struct Flags {
+            #[serde(default)]
+            version: u32,
+            #[serde(default)]
+            verbose: bool,
+        }
+        impl VersionedConfig for Flags {
+            const VERSION: u32 = 0;
+            const MIGRATIONS: &'static [super::super::migration::Migration] = &[];
+        }
+        impl EnvConfig for Flags {
+            const ENV_PREFIX: &'static str = "KT_TEST__";
+            const ENV_TABLE: &'static [&'static str] = &[];
+        }
+
+        for (raw, expected) in [("true", true), ("1", true), ("off", false), ("No", false)] {
+            let mut loader = ConfigLoader::<Flags>::new();
+            loader.with_env_vars(vars(&[("KT_TEST__VERBOSE", raw)]));
+            assert_eq!(loader.load().unwrap().verbose, expected, "{}", raw);
+        }
+        let mut loader = ConfigLoader::<Flags>::new();
+        loader.with_env_vars(vars(&[("KT_TEST__VERBOSE", "maybe")]));
+        assert!(loader.load().is_err());
+
+        let mut loader = ConfigLoader::<AgentConfig>::new();
+        loader.with_env_vars(vars(&[
+            (
+                "KT_AGENT__ORCHESTRATOR_ADDRESS",
+                "laptop.tailnet.ts.net:2222",
+            ),
+            ("KT_AGENT__TAGS", "gpu, lab"),
+            ("KT_AGENT__CONNECT_TIMEOUT", "1m30s"),
+            ("KT_AGENT__METRICS_INTERVAL_SECS", "30"),
+        ]));
+        let config = loader.load().unwrap();
+        assert_eq!(config.orchestrator_address, "laptop.tailnet.ts.net:2222");
+        assert_eq!(config.tags, vec!["gpu", "lab"]);
+        assert_eq!(*config.connect_timeout, Duration::from_secs(90));
+        assert_eq!(config.metrics_interval(), Some(Duration::from_secs(30)));
+        assert_eq!(AgentConfig::default().metrics_interval(), None);
+    }
+
+    #[test]
+    fn test_invalid_env_value_names_the_variable() {
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        loader.with_env_vars(vars(&[("KT_ORCHESTRATOR__IPC_PORT", "not-a-port")]));
+        let err = loader.load().unwrap_err().to_string();
+        assert!(err.contains("KT_ORCHESTRATOR__IPC_PORT"), "{}", err);
+    }
+
+    #[test]
+    fn test_malformed_and_blocked_variables_ignored() {
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        loader.with_env_vars(vars(&[
+            ("KT_ORCHESTRATOR__", "x"),
+            ("KT_ORCHESTRATOR__BIND_ADDRESS__PORT", "1"),
+        ]));
+        let config = loader.load().unwrap().orchestrator;
+        assert_eq!(config.bind_address, "127.0.0.1:2222");
+        assert!(loader.sources().iter().next().is_none());
+    }
+
+    #[test]
+    fn test_leaves() {
+        let value: toml::Value = toml::from_str("a = 1\n[b]\nc = [1]\nempty = {}").unwrap();
+        let keys: Vec<_> = leaves(&value).into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec!["a", "b.c", "b.empty"]);
+    }
+}
@@ -0,0 +1,320 @@
+//! Config schema versions and migrations
+//!
+//! Config files carry a top-level `version` key. When a file is older than
+//! the schema this build writes, [`super::load_config`] upgrades the raw TOML
+//! by applying each migration step in order (`MIGRATIONS[n]` turns version
+//! `n` into `n + 1`), keeps a copy of the original next to it, and writes the
+//! upgraded file back. Files without a `version` key are version 0.
+//!
+//! ## History
+//!
+//! `config.toml` ([`ConfigFile`]):
+//! - **v0**: unversioned. Orchestrator settings could sit at the top level
+//!   (the layout the standalone orchestrator used to read) and machine
+//!   profiles were written as top-level `[machines.<id>]` tables or
+//!   `[[machines]]` entries.
+//! - **v1**: everything orchestrator-related lives under `[orchestrator]`,
+//!   with profiles in `[orchestrator.machines.<id>]`.
+//!
+//! `agent.toml` ([`AgentConfig`]):
+//! - **v0**: unversioned.
+//! - **v1**: adds `version`; no other changes.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{AgentConfig, ConfigFile};
+use crate::error::ConfigError;
+
+/// Key holding the schema version
+pub const VERSION_KEY: &str = "version";
+
+/// One migration step, upgrading raw TOML by one schema version
+pub type Migration = fn(toml::Value) -> toml::Value;
+
+/// A config file type with a versioned schema
+pub trait VersionedConfig: Serialize + DeserializeOwned {
+    /// Schema version written by this build
+    const VERSION: u32;
+
+    /// Upgrade steps, oldest first; `MIGRATIONS[n]` turns version `n` into `n + 1`
+    const MIGRATIONS: &'static [Migration];
+}
+
+impl VersionedConfig for ConfigFile {
+    const VERSION: u32 = 1;
+    const MIGRATIONS: &'static [Migration] = &[migrate_config_v0_to_v1];
+}
+
+impl VersionedConfig for AgentConfig {
+    const VERSION: u32 = 1;
+    const MIGRATIONS: &'static [Migration] = &[migrate_agent_v0_to_v1];
+}
+
+/// Read the schema version of a raw config (0 if it has none)
+pub fn schema_version(value: &toml::Value) -> Result<u32, ConfigError> {
+    match value.get(VERSION_KEY) {
+        None => Ok(0),
+        Some(toml::Value::Integer(v)) => u32::try_from(*v)
+            .map_err(|_| ConfigError::Invalid(format!("Invalid config version: {}", v))),
+        Some(other) => Err(ConfigError::Invalid(format!(
+            "Invalid config version: {}",
+            other
+        ))),
+    }
+}
+
+/// Upgrade a raw config to `T::VERSION`.
+///
+/// Returns the upgraded value and the version it started at. Values already
+/// at (or beyond) the current version are returned unchanged.
+pub fn migrate<T: VersionedConfig>(value: toml::Value) -> Result<(toml::Value, u32), ConfigError> {
+    let from = schema_version(&value)?;
+    let mut value = value;
+    for (version, step) in T::MIGRATIONS.iter().enumerate().skip(from as usize) {
+        value = step(value);
+        if let Some(table) = value.as_table_mut() {
+            table.insert(
+                VERSION_KEY.to_string(),
+                toml::Value::Integer(version as i64 + 1),
+            );
+        }
+    }
+    Ok((value, from))
+}
+
+/// Keys present in `input` that didn't survive deserializing into the config
+/// type, given `parsed` (the deserialized config serialized back to TOML).
+///
+/// Returned as dotted paths, e.g. `orchestrator.bind_adress`.
+pub fn unknown_keys(input: &toml::Value, parsed: &toml::Value) -> Vec<String> {
+    let mut unknown = Vec::new();
+    collect_unknown_keys(input, parsed, "", &mut unknown);
+    unknown.sort();
+    unknown
+}
+
+fn collect_unknown_keys(
+    input: &toml::Value,
+    parsed: &toml::Value,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    let (Some(input), Some(parsed)) = (input.as_table(), parsed.as_table()) else {
+        return;
+    };
+    for (key, value) in input {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        match parsed.get(key) {
+            Some(parsed_value) => collect_unknown_keys(value, parsed_value, &path, unknown),
+            None => unknown.push(path),
+        }
+    }
+}
+
+/// Top-level keys of a v0 `config.toml` that belong to the orchestrator
+const V0_ORCHESTRATOR_KEYS: &[&str] = &[
+    "bind_address",
+    "heartbeat_interval",
+    "heartbeat_timeout",
+    "host_key_path",
+    "backoff",
+    "machines",
+    "ipc_port",
+    "max_connections",
+    "max_sessions_per_machine",
+    "max_total_sessions",
+    "tailscale_hostname",
+];
+
+/// v0 → v1: move top-level orchestrator settings and machine profiles under
+/// `[orchestrator]`, converting `[[machines]]` entries into tables keyed by
+/// their alias. Settings already under `[orchestrator]` win.
+fn migrate_config_v0_to_v1(mut value: toml::Value) -> toml::Value {
+    let Some(root) = value.as_table_mut() else {
+        return value;
+    };
+
+    let mut orchestrator = match root.remove("orchestrator") {
+        Some(toml::Value::Table(table)) => table,
+        Some(other) => {
+            // Not a table; leave it for deserialization to reject
+            root.insert("orchestrator".to_string(), other);
+            return value;
+        }
+        None => toml::Table::new(),
+    };
+
+    for key in V0_ORCHESTRATOR_KEYS {
+        let Some(setting) = root.remove(*key) else {
+            continue;
+        };
+        let setting = if *key == "machines" {
+            machines_array_to_table(setting)
+        } else {
+            setting
+        };
+
+        match (orchestrator.get_mut(*key), setting) {
+            (Some(toml::Value::Table(existing)), toml::Value::Table(moved)) => {
+                for (k, v) in moved {
+                    existing.entry(k).or_insert(v);
+                }
+            }
+            (Some(_), _) => {}
+            (None, setting) => {
+                orchestrator.insert(key.to_string(), setting);
+            }
+        }
+    }
+
+    root.insert("orchestrator".to_string(), toml::Value::Table(orchestrator));
+    value
+}
+
+/// Turn `[[machines]]` entries into a table keyed by alias
+fn machines_array_to_table(machines: toml::Value) -> toml::Value {
+    let toml::Value::Array(entries) = machines else {
+        return machines;
+    };
+
+    let mut table = toml::Table::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let key = entry
+            .get("alias")
+            .and_then(|a| a.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("machine-{}", index + 1));
+        table.insert(key, entry);
+    }
+    toml::Value::Table(table)
+}
+
+/// v0 → v1: no structural changes; the file only gains `version`
+fn migrate_agent_v0_to_v1(value: toml::Value) -> toml::Value {
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const CONFIG_V0_FLAT: &str = include_str!("../../tests/fixtures/config/config_v0_flat.toml");
+    const CONFIG_V0: &str = include_str!("../../tests/fixtures/config/config_v0.toml");
+    const CONFIG_V1: &str = include_str!("../../tests/fixtures/config/config_v1.toml");
+    const AGENT_V0: &str = include_str!("../../tests/fixtures/config/agent_v0.toml");
+    const AGENT_V1: &str = include_str!("../../tests/fixtures/config/agent_v1.toml");
+
+    fn load<T: VersionedConfig>(fixture: &str) -> (T, u32) {
+        let (value, from) = migrate::<T>(toml::from_str(fixture).unwrap()).unwrap();
+        assert_eq!(schema_version(&value).unwrap(), T::VERSION);
+        (value.try_into().unwrap(), from)
+    }
+
+    #[test]
+    fn test_every_version_has_a_migration() {
+        assert_eq!(ConfigFile::MIGRATIONS.len() as u32, ConfigFile::VERSION);
+        assert_eq!(AgentConfig::MIGRATIONS.len() as u32, AgentConfig::VERSION);
+    }
+
+    #[test]
+    fn test_config_fixtures_reach_current_version() {
+        for (fixture, version) in [(CONFIG_V0_FLAT, 0), (CONFIG_V0, 0), (CONFIG_V1, 1)] {
+            let (config, from): (ConfigFile, _) = load(fixture);
+            assert_eq!(from, version);
+            assert_eq!(config.version, ConfigFile::VERSION);
+
+            let orchestrator = &config.orchestrator;
+            assert_eq!(orchestrator.bind_address, "0.0.0.0:2222");
+            assert_eq!(*orchestrator.heartbeat_interval, Duration::from_secs(15));
+            assert_eq!(*orchestrator.backoff.max, Duration::from_secs(120));
+            assert_eq!(orchestrator.machines["gpu-server"].alias, "gpu-server");
+            assert_eq!(orchestrator.machines["gpu-server"].tags, vec!["gpu"]);
+            assert_eq!(
+                orchestrator.machines["gpu-server"].env["CUDA_VISIBLE_DEVICES"],
+                "0,1"
+            );
+        }
+    }
+
+    #[test]
+    fn test_agent_fixtures_reach_current_version() {
+        for (fixture, version) in [(AGENT_V0, 0), (AGENT_V1, 1)] {
+            let (config, from): (AgentConfig, _) = load(fixture);
+            assert_eq!(from, version);
+            assert_eq!(config.version, AgentConfig::VERSION);
+            assert_eq!(config.orchestrator_address, "my-laptop.tailnet.ts.net:2222");
+            assert_eq!(config.alias.as_deref(), Some("lab-box"));
+            assert_eq!(*config.connect_timeout, Duration::from_secs(10));
+        }
+    }
+
+    #[test]
+    fn test_orchestrator_section_wins_over_top_level() {
+        let value: toml::Value = toml::from_str(
+            r#"
+            bind_address = "0.0.0.0:1111"
+            [orchestrator]
+            bind_address = "127.0.0.1:2222"
+            "#,
+        )
+        .unwrap();
+        let (value, _) = migrate::<ConfigFile>(value).unwrap();
+        assert_eq!(
+            value["orchestrator"]["bind_address"].as_str(),
+            Some("127.0.0.1:2222")
+        );
+        assert!(value.get("bind_address").is_none());
+    }
+
+    #[test]
+    fn test_newer_version_left_alone() {
+        let value: toml::Value = toml::from_str("version = 99\nfuture_key = 1").unwrap();
+        let (migrated, from) = migrate::<ConfigFile>(value.clone()).unwrap();
+        assert_eq!(from, 99);
+        assert_eq!(migrated, value);
+    }
+
+    #[test]
+    fn test_invalid_version() {
+        let value: toml::Value = toml::from_str("version = \"one\"").unwrap();
+        assert!(schema_version(&value).is_err());
+        let value: toml::Value = toml::from_str("version = -1").unwrap();
+        assert!(schema_version(&value).is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let input: toml::Value = toml::from_str(
+            r#"
+            version = 1
+            colour = "blue"
+            [orchestrator]
+            bind_adress = "0.0.0.0:2222"
+            [orchestrator.backoff]
+            initial = 1
+            max = 60
+            multiplier = 2.0
+            jitter = 0.25
+            factor = 3
+            "#,
+        )
+        .unwrap();
+        let config: ConfigFile = input.clone().try_into().unwrap();
+        let parsed = toml::Value::try_from(&config).unwrap();
+
+        assert_eq!(
+            unknown_keys(&input, &parsed),
+            vec![
+                "colour",
+                "orchestrator.backoff.factor",
+                "orchestrator.bind_adress"
+            ]
+        );
+    }
+}
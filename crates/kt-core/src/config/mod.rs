@@ -1,23 +1,57 @@
 //! Configuration management for k-Terminus
 
 mod agent;
+mod duration;
+pub mod layered;
 mod machine;
+pub mod migration;
 mod orchestrator;
 pub mod serde_utils;
+mod webhook;
 
-pub use agent::AgentConfig;
+pub use agent::{AgentConfig, SessionJournalConfig};
+pub use duration::DurationString;
+pub use layered::{ConfigLoader, ConfigSource, ConfigSources, EnvConfig};
 pub use machine::MachineProfile;
-pub use orchestrator::{BackoffConfig, OrchestratorConfig};
+pub use migration::VersionedConfig;
+pub use orchestrator::{
+    BackoffConfig, BindFallback, IpcRateLimitConfig, LogFormat, LogRotationConfig,
+    MemoryLimitConfig, OrchestratorConfig, DEFAULT_MAX_INPUT_RATE_KIB_PER_MIN,
+    DEFAULT_RESIZE_DEBOUNCE,
+};
+pub use webhook::{WebhookConfig, WebhookEvent};
 
 use crate::error::ConfigError;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 
 /// Wrapper for loading config files that have [orchestrator] section
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigFile {
+    /// Schema version (see [`migration`])
+    #[serde(default = "config_file_version")]
+    pub version: u32,
+
     #[serde(default)]
     pub orchestrator: OrchestratorConfig,
+
+    /// Agent defaults written by setup; kept as-is, not interpreted here
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<toml::Table>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        Self {
+            version: config_file_version(),
+            orchestrator: OrchestratorConfig::default(),
+            agent: None,
+        }
+    }
+}
+
+fn config_file_version() -> u32 {
+    <ConfigFile as VersionedConfig>::VERSION
 }
 
 /// Get the default configuration directory
@@ -33,7 +67,34 @@ pub fn default_config_path() -> PathBuf {
 }
 
 /// Load configuration from a file
-pub fn load_config<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+///
+/// Files with an older schema version are migrated: the original is copied to
+/// `<file>.v<N>.bak` and the upgraded file is written in its place. Keys the
+/// config type doesn't know are logged as a warning rather than rejected.
+///
+/// This reads the file only; use [`ConfigLoader`] to also apply environment
+/// variables and command-line flags.
+pub fn load_config<T: VersionedConfig>(path: &Path) -> Result<T, ConfigError> {
+    let value = read_config_value::<T>(path)?;
+    let config: T = value.clone().try_into()?;
+
+    let unknown = migration::unknown_keys(&value, &toml::Value::try_from(&config)?);
+    if !unknown.is_empty() {
+        tracing::warn!(
+            "Ignoring unknown keys in config {:?}: {}",
+            path,
+            unknown.join(", ")
+        );
+    }
+
+    Ok(config)
+}
+
+/// Read a config file as raw TOML, migrated to the current schema version
+/// (see [`load_config`])
+pub(crate) fn read_config_value<T: VersionedConfig>(
+    path: &Path,
+) -> Result<toml::Value, ConfigError> {
     if !path.exists() {
         return Err(ConfigError::NotFound(path.to_path_buf()));
     }
@@ -41,8 +102,55 @@ pub fn load_config<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, Con
     let content = std::fs::read_to_string(path)
         .map_err(|e| ConfigError::Invalid(format!("Failed to read config: {}", e)))?;
 
-    let config: T = toml::from_str(&content)?;
-    Ok(config)
+    let (value, from_version) = migration::migrate::<T>(toml::from_str(&content)?)?;
+    if from_version < T::VERSION {
+        match write_migrated(path, &content, &value, from_version) {
+            Ok(backup) => tracing::info!(
+                "Upgraded config {:?} from version {} to {} (original saved as {:?})",
+                path,
+                from_version,
+                T::VERSION,
+                backup
+            ),
+            Err(e) => tracing::warn!(
+                "Upgraded config {:?} from version {} to {} in memory only: {}",
+                path,
+                from_version,
+                T::VERSION,
+                e
+            ),
+        }
+    } else if from_version > T::VERSION {
+        tracing::warn!(
+            "Config {:?} has version {}, newer than the supported version {}",
+            path,
+            from_version,
+            T::VERSION
+        );
+    }
+
+    Ok(value)
+}
+
+/// Back up the original config and write the migrated one in its place.
+///
+/// Returns the backup path.
+fn write_migrated(
+    path: &Path,
+    original: &str,
+    migrated: &toml::Value,
+    from_version: u32,
+) -> Result<PathBuf, ConfigError> {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", from_version));
+    let backup = PathBuf::from(backup);
+
+    std::fs::write(&backup, original)
+        .map_err(|e| ConfigError::Invalid(format!("Failed to back up config: {}", e)))?;
+    std::fs::write(path, toml::to_string_pretty(migrated)?)
+        .map_err(|e| ConfigError::Invalid(format!("Failed to write config: {}", e)))?;
+
+    Ok(backup)
 }
 
 /// Save configuration to a file
@@ -60,3 +168,256 @@ pub fn save_config<T: serde::Serialize>(path: &Path, config: &T) -> Result<(), C
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_config_migrates_and_backs_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let original = include_str!("../../tests/fixtures/config/config_v0.toml");
+        std::fs::write(&path, original).unwrap();
+
+        let config: ConfigFile = load_config(&path).unwrap();
+        assert_eq!(config.version, ConfigFile::VERSION);
+        assert!(config.orchestrator.machines.contains_key("gpu-server"));
+
+        // Original kept, upgraded file written in its place
+        let backup = dir.path().join("config.toml.v0.bak");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), original);
+        let rewritten: toml::Value =
+            toml::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(migration::schema_version(&rewritten).unwrap(), 1);
+
+        // Already current: loads without another backup
+        let _: ConfigFile = load_config(&path).unwrap();
+        assert!(!dir.path().join("config.toml.v1.bak").exists());
+    }
+
+    #[test]
+    fn test_load_config_tolerates_unknown_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("agent.toml");
+        std::fs::write(
+            &path,
+            "version = 1\nalias = \"box\"\nretired_option = true\n",
+        )
+        .unwrap();
+
+        let config: AgentConfig = load_config(&path).unwrap();
+        assert_eq!(config.alias.as_deref(), Some("box"));
+    }
+
+    #[test]
+    fn test_changed_keys() {
+        let old = OrchestratorConfig::default();
+        assert!(old.changed_keys(&old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.max_total_sessions = Some(10);
+        new.memory_limit.resume_percent += 1;
+        new.groups
+            .insert("gpus".to_string(), vec!["gpu-box".to_string()]);
+        assert_eq!(
+            old.changed_keys(&new),
+            vec![
+                "orchestrator.groups.gpus",
+                "orchestrator.max_total_sessions",
+                "orchestrator.memory_limit.resume_percent",
+            ]
+        );
+        // Unsetting a key is a change too
+        assert_eq!(
+            new.changed_keys(&OrchestratorConfig {
+                max_total_sessions: None,
+                ..new.clone()
+            }),
+            vec!["orchestrator.max_total_sessions"]
+        );
+    }
+
+    #[test]
+    fn test_webhooks_parse_with_defaults() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            version = 1
+
+            [[orchestrator.webhook]]
+            url = "https://ntfy.sh/lab"
+            events = ["machine_disconnected", "session_failed"]
+            machines = ["gpu-box"]
+            secret = "hunter2"
+            debounce = "10m"
+
+            [[orchestrator.webhook]]
+            url = "https://hooks.slack.com/services/x"
+            "#,
+        )
+        .unwrap();
+
+        let [ntfy, slack] = config.orchestrator.webhook.as_slice() else {
+            panic!("expected two webhooks");
+        };
+        assert!(ntfy.wants_event(WebhookEvent::SessionFailed));
+        assert!(!ntfy.wants_event(WebhookEvent::SessionClosed));
+        assert!(ntfy.wants_machine("GPU-Box", None));
+        assert!(!ntfy.wants_machine("laptop", Some("mine")));
+        assert_eq!(ntfy.debounce, std::time::Duration::from_secs(600));
+
+        assert!(slack.wants_event(WebhookEvent::MachineConnected));
+        assert!(slack.wants_machine("anything", None));
+        assert_eq!(slack.secret, None);
+        assert_eq!(slack.debounce, std::time::Duration::from_secs(300));
+    }
+
+    #[test]
+    fn test_log_settings_parse_with_defaults() {
+        let config: ConfigFile = toml::from_str(
+            r#"
+            version = 1
+
+            [orchestrator]
+            log_file = "/var/log/k-terminus/orchestrator.log"
+            log_format = "json"
+            log_rotation = { size_mb = 5 }
+            "#,
+        )
+        .unwrap();
+
+        let orchestrator = config.orchestrator;
+        assert_eq!(
+            orchestrator.log_file.as_deref(),
+            Some(Path::new("/var/log/k-terminus/orchestrator.log"))
+        );
+        assert_eq!(orchestrator.log_format, LogFormat::Json);
+        assert_eq!(orchestrator.log_rotation.max_bytes(), 5 * 1024 * 1024);
+        assert_eq!(orchestrator.log_rotation.keep, 3);
+
+        assert_eq!(OrchestratorConfig::default().log_format, LogFormat::Text);
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert!("yaml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn test_memory_limit_is_off_by_default() {
+        let limit = OrchestratorConfig::default().memory_limit;
+        assert_eq!(limit.soft_limit_bytes(), None);
+        assert_eq!(limit.resume_bytes(), None);
+
+        let config: ConfigFile = toml::from_str(
+            r#"
+            version = 1
+
+            [orchestrator.memory_limit]
+            soft_limit_mb = 200
+            "#,
+        )
+        .unwrap();
+        let limit = config.orchestrator.memory_limit;
+        assert_eq!(limit.soft_limit_bytes(), Some(200 * 1024 * 1024));
+        assert_eq!(limit.resume_bytes(), Some(180 * 1024 * 1024));
+        assert_eq!(*limit.check_interval, std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_integer_durations_still_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            version = 1
+
+            [orchestrator]
+            heartbeat_interval = 15
+            heartbeat_timeout = 45
+            reconnect_grace = 0
+            resize_debounce = 100
+            ipc_token_lifetime = 3600
+
+            [orchestrator.backoff]
+            initial = 2
+            max = 120
+            multiplier = 2.0
+            jitter = 0.25
+            "#,
+        )
+        .unwrap();
+
+        let config: ConfigFile = load_config(&path).unwrap();
+        let orchestrator = config.orchestrator;
+        assert_eq!(
+            orchestrator.heartbeat_interval,
+            DurationString::from_secs(15)
+        );
+        assert_eq!(
+            orchestrator.heartbeat_timeout,
+            DurationString::from_secs(45)
+        );
+        assert_eq!(orchestrator.reconnect_grace, DurationString::from_secs(0));
+        assert_eq!(
+            orchestrator.resize_debounce,
+            std::time::Duration::from_millis(100)
+        );
+        assert_eq!(
+            orchestrator.ipc_token_lifetime,
+            Some(DurationString::from_secs(3600))
+        );
+        assert_eq!(orchestrator.backoff.initial, DurationString::from_secs(2));
+        assert_eq!(orchestrator.backoff.max, DurationString::from_secs(120));
+
+        let agent: AgentConfig =
+            toml::from_str("connect_timeout = 10\nfailback_check_interval = 300\n").unwrap();
+        assert_eq!(agent.connect_timeout, DurationString::from_secs(10));
+        assert_eq!(
+            agent.failback_check_interval,
+            DurationString::from_secs(300)
+        );
+    }
+
+    #[test]
+    fn test_durations_written_for_humans() {
+        let mut config = ConfigFile::default();
+        config.orchestrator.heartbeat_interval = DurationString::from_millis(1500);
+        config.orchestrator.backoff.max = DurationString::from_secs(120);
+        let text = toml::to_string(&config).unwrap();
+        assert!(text.contains("heartbeat_interval = \"1500ms\""), "{}", text);
+        assert!(text.contains("heartbeat_timeout = \"90s\""), "{}", text);
+        assert!(text.contains("max = \"2m\""), "{}", text);
+
+        let parsed: ConfigFile = toml::from_str(&text).unwrap();
+        assert_eq!(
+            parsed.orchestrator.heartbeat_interval,
+            config.orchestrator.heartbeat_interval
+        );
+        assert_eq!(
+            parsed.orchestrator.backoff.max,
+            config.orchestrator.backoff.max
+        );
+    }
+
+    #[test]
+    fn test_invalid_duration_names_field_and_formats() {
+        let error =
+            toml::from_str::<ConfigFile>("[orchestrator]\nheartbeat_interval = \"every minute\"\n")
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("heartbeat_interval"), "{}", error);
+        assert!(
+            error.contains("\"500ms\", \"30s\", \"5m\" or \"1h\""),
+            "{}",
+            error
+        );
+
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        loader.with_env_vars([(
+            "KT_ORCHESTRATOR__BACKOFF__MAX".to_string(),
+            "a while".to_string(),
+        )]);
+        let error = loader.load().unwrap_err().to_string();
+        assert!(error.contains("orchestrator.backoff.max"), "{}", error);
+        assert!(error.contains("KT_ORCHESTRATOR__BACKOFF__MAX"), "{}", error);
+    }
+}
@@ -1,27 +1,49 @@
 //! Orchestrator configuration
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::PathBuf;
 use std::time::Duration;
 
-use super::serde_utils::duration_secs;
-use super::MachineProfile;
+use super::layered::leaves;
+use super::serde_utils::duration_millis;
+use super::{DurationString, MachineProfile, WebhookConfig};
+
+/// Default for [`OrchestratorConfig::resize_debounce`], also used by IPC
+/// clients that debounce resizes before sending them
+pub const DEFAULT_RESIZE_DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Default for [`OrchestratorConfig::max_input_rate_kib_per_min`]: 8 MiB a
+/// minute, thousands of times what a fast typist sends
+pub const DEFAULT_MAX_INPUT_RATE_KIB_PER_MIN: u64 = 8 * 1024;
 
 /// Configuration for the orchestrator daemon
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct OrchestratorConfig {
-    /// Address to bind the SSH server to
+    /// Address to bind the SSH server to: a socket address, `tailnet` for
+    /// this machine's Tailscale IP, or an interface name such as
+    /// `tailscale0`, optionally followed by `:port` (see [`crate::net`])
     pub bind_address: String,
 
-    /// Heartbeat interval in seconds
-    #[serde(with = "duration_secs")]
-    pub heartbeat_interval: Duration,
+    /// What to do when `bind_address` needs Tailscale and it isn't running
+    pub bind_fallback: BindFallback,
+
+    /// Heartbeat interval
+    pub heartbeat_interval: DurationString,
 
     /// Heartbeat timeout (how long to wait before considering connection dead)
-    #[serde(with = "duration_secs")]
-    pub heartbeat_timeout: Duration,
+    pub heartbeat_timeout: DurationString,
+
+    /// How long a machine whose connection dropped is shown as reconnecting,
+    /// with its sessions kept, before it is removed (0 = remove right away)
+    pub reconnect_grace: DurationString,
+
+    /// Shortest time between resizes forwarded to an agent for one session;
+    /// resizes in between are merged into the latest (0 = forward every
+    /// resize). Unlike other durations, a bare number is milliseconds.
+    #[serde(with = "duration_millis")]
+    pub resize_debounce: Duration,
 
     /// Path to the host key file
     pub host_key_path: PathBuf,
@@ -33,6 +55,11 @@ pub struct OrchestratorConfig {
     #[serde(default)]
     pub machines: HashMap<String, MachineProfile>,
 
+    /// Machine groups: name -> member machine IDs or aliases, selected as
+    /// `@name`
+    #[serde(default)]
+    pub groups: HashMap<String, Vec<String>>,
+
     /// IPC port for CLI/desktop communication (localhost only)
     pub ipc_port: u16,
 
@@ -42,8 +69,69 @@ pub struct OrchestratorConfig {
     /// Maximum sessions per machine
     pub max_sessions_per_machine: Option<u32>,
 
+    /// Maximum sessions across all machines combined
+    pub max_total_sessions: Option<usize>,
+
     /// Tailscale hostname (auto-detected during setup)
     pub tailscale_hostname: Option<String>,
+
+    /// Tailnet domain to use when Tailscale doesn't report a MagicDNS suffix,
+    /// e.g. a Headscale `base_domain`
+    pub tailnet_domain: Option<String>,
+
+    /// How long an IPC token is valid before the orchestrator rotates it
+    /// (None = keep the same token until the orchestrator exits)
+    pub ipc_token_lifetime: Option<DurationString>,
+
+    /// Per-client IPC request rate limits
+    pub ipc_rate_limit: IpcRateLimitConfig,
+
+    /// Webhooks notified of machine and session lifecycle events
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub webhook: Vec<WebhookConfig>,
+
+    /// Log file (None = `orchestrator.log` in the config directory, or in
+    /// the desktop app's log directory for its embedded orchestrator)
+    pub log_file: Option<PathBuf>,
+
+    /// When the log file is rotated and how many old files are kept
+    pub log_rotation: LogRotationConfig,
+
+    /// Format of the lines written to the log file
+    pub log_format: LogFormat,
+
+    /// File every IPC event is appended to for post-mortem debugging
+    /// (None = no journal)
+    pub event_journal_path: Option<PathBuf>,
+
+    /// Whether the event journal records terminal output itself rather than
+    /// just its length
+    pub event_journal_include_output: bool,
+
+    /// When the event journal is rotated and how many old files are kept
+    pub event_journal_rotation: LogRotationConfig,
+
+    /// Whether CLI commands that need an orchestrator start one when none is
+    /// running (`--no-autostart` turns this off for one command)
+    pub auto_start: bool,
+
+    /// Memory use above which the orchestrator sheds load
+    pub memory_limit: MemoryLimitConfig,
+
+    /// Input in KiB per minute above which a session's input is refused for
+    /// a while (0 = no limit). The default is far beyond what anyone types
+    /// or pastes, so only runaway automation trips it.
+    pub max_input_rate_kib_per_min: u64,
+
+    /// Message shown to clients attaching to a session, before its output,
+    /// e.g. a usage policy for a shared orchestrator (None = nothing).
+    /// SGR escape sequences such as `\u001b[1m` are kept for emphasis.
+    pub attach_motd: Option<String>,
+
+    /// Whether a session's output waits for its owner when the owner's
+    /// attached client falls behind, instead of the client losing events.
+    /// Observers never hold it up.
+    pub owner_backpressure: bool,
 }
 
 impl Default for OrchestratorConfig {
@@ -53,15 +141,35 @@ impl Default for OrchestratorConfig {
         Self {
             // Default to localhost for security - use "0.0.0.0:2222" for network access
             bind_address: "127.0.0.1:2222".to_string(),
-            heartbeat_interval: Duration::from_secs(30),
-            heartbeat_timeout: Duration::from_secs(90),
+            bind_fallback: BindFallback::default(),
+            heartbeat_interval: DurationString::from_secs(30),
+            heartbeat_timeout: DurationString::from_secs(90),
+            reconnect_grace: DurationString::from_secs(5),
+            resize_debounce: DEFAULT_RESIZE_DEBOUNCE,
             host_key_path: config_dir.join("host_key"),
             backoff: BackoffConfig::default(),
             machines: HashMap::new(),
+            groups: HashMap::new(),
             ipc_port: 22230,
             max_connections: None,
             max_sessions_per_machine: None,
+            max_total_sessions: None,
             tailscale_hostname: None,
+            tailnet_domain: None,
+            ipc_token_lifetime: None,
+            ipc_rate_limit: IpcRateLimitConfig::default(),
+            webhook: Vec::new(),
+            log_file: None,
+            log_rotation: LogRotationConfig::default(),
+            log_format: LogFormat::default(),
+            event_journal_path: None,
+            event_journal_include_output: false,
+            event_journal_rotation: LogRotationConfig::default(),
+            auto_start: true,
+            memory_limit: MemoryLimitConfig::default(),
+            max_input_rate_kib_per_min: DEFAULT_MAX_INPUT_RATE_KIB_PER_MIN,
+            attach_motd: None,
+            owner_backpressure: false,
         }
     }
 }
@@ -71,18 +179,60 @@ impl OrchestratorConfig {
     pub fn ipc_address(&self) -> String {
         format!("127.0.0.1:{}", self.ipc_port)
     }
+
+    /// Per-session input limit in bytes per minute, if any
+    pub fn max_input_bytes_per_min(&self) -> Option<u64> {
+        match self.max_input_rate_kib_per_min {
+            0 => None,
+            kib => Some(kib.saturating_mul(1024)),
+        }
+    }
+
+    /// Keys whose values differ from `other`, as dotted config file paths
+    /// such as `orchestrator.memory_limit.resume_percent`, in key order
+    ///
+    /// Arrays count as one value; a key set on only one side counts as
+    /// changed.
+    pub fn changed_keys(&self, other: &Self) -> Vec<String> {
+        let (Ok(old), Ok(new)) = (toml::Value::try_from(self), toml::Value::try_from(other)) else {
+            return Vec::new();
+        };
+        // Empty tables are leaves too, but their entries say what changed
+        let values = |value| -> BTreeMap<String, &toml::Value> {
+            leaves(value)
+                .into_iter()
+                .filter(|(_, v)| !v.as_table().is_some_and(|t| t.is_empty()))
+                .collect()
+        };
+        let (old, new) = (values(&old), values(&new));
+        let keys: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+        keys.into_iter()
+            .filter(|key| old.get(*key) != new.get(*key))
+            .map(|key| format!("orchestrator.{}", key))
+            .collect()
+    }
+}
+
+/// What the orchestrator does when `bind_address` names Tailscale or an
+/// interface that has no address
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BindFallback {
+    /// Refuse to start
+    #[default]
+    None,
+    /// Listen on 127.0.0.1 until the address comes back
+    Loopback,
 }
 
 /// Exponential backoff configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BackoffConfig {
     /// Initial delay
-    #[serde(with = "duration_secs")]
-    pub initial: Duration,
+    pub initial: DurationString,
 
     /// Maximum delay
-    #[serde(with = "duration_secs")]
-    pub max: Duration,
+    pub max: DurationString,
 
     /// Multiplier for each retry
     pub multiplier: f64,
@@ -94,10 +244,129 @@ pub struct BackoffConfig {
 impl Default for BackoffConfig {
     fn default() -> Self {
         Self {
-            initial: Duration::from_secs(1),
-            max: Duration::from_secs(60),
+            initial: DurationString::from_secs(1),
+            max: DurationString::from_secs(60),
             multiplier: 2.0,
             jitter: 0.25,
         }
     }
 }
+
+/// Per-client IPC rate limits, counted separately so typing into a session
+/// doesn't use up the budget for control requests and vice versa
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IpcRateLimitConfig {
+    /// Maximum `SessionInput`/`SessionResize` requests per second
+    pub input_per_second: u32,
+
+    /// Maximum requests of every other kind per second
+    pub control_per_second: u32,
+}
+
+impl Default for IpcRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            input_per_second: 1000,
+            control_per_second: 1000,
+        }
+    }
+}
+
+/// Size-based rotation of the orchestrator log file or event journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogRotationConfig {
+    /// Size in MiB at which the file is rotated (0 = never rotate)
+    pub size_mb: u64,
+
+    /// Rotated files to keep (`<file>.1` is the most recent)
+    pub keep: u32,
+}
+
+impl LogRotationConfig {
+    /// Rotation size in bytes (0 = never rotate)
+    pub fn max_bytes(&self) -> u64 {
+        self.size_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+impl Default for LogRotationConfig {
+    fn default() -> Self {
+        Self {
+            size_mb: 10,
+            keep: 3,
+        }
+    }
+}
+
+/// Self-monitoring of the orchestrator's memory use, off unless
+/// `soft_limit_mb` is set
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryLimitConfig {
+    /// Resident memory in MiB above which load is shed (None = never)
+    pub soft_limit_mb: Option<u64>,
+
+    /// Percentage of the soft limit memory use must drop below before
+    /// shedding stops, so it doesn't flap around the limit
+    pub resume_percent: u8,
+
+    /// How often memory use is sampled
+    pub check_interval: DurationString,
+}
+
+impl MemoryLimitConfig {
+    /// Soft limit in bytes, if set
+    pub fn soft_limit_bytes(&self) -> Option<u64> {
+        self.soft_limit_mb.map(|mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Memory use in bytes below which shedding stops, if a limit is set
+    pub fn resume_bytes(&self) -> Option<u64> {
+        let percent = u64::from(self.resume_percent.min(100));
+        self.soft_limit_bytes().map(|limit| limit / 100 * percent)
+    }
+}
+
+impl Default for MemoryLimitConfig {
+    fn default() -> Self {
+        Self {
+            soft_limit_mb: None,
+            resume_percent: 90,
+            check_interval: DurationString::from_secs(5),
+        }
+    }
+}
+
+/// Format of the orchestrator log file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// `<millis> <LEVEL> <target>: <message> key=value...`
+    #[default]
+    Text,
+    /// One JSON object per line, with structured fields kept apart
+    Json,
+}
+
+impl std::fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Text => "text",
+            Self::Json => "json",
+        })
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Invalid log format '{}': expected text or json", s)),
+        }
+    }
+}
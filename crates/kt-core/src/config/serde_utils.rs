@@ -5,7 +5,10 @@
 /// Helper module for Duration serialization as seconds
 ///
 /// This module serializes `std::time::Duration` as a u64 representing seconds,
-/// which is more human-readable in TOML/JSON configuration files.
+/// which is more human-readable in TOML/JSON configuration files. When
+/// deserializing it also accepts strings such as `"45s"` or `"5m"` (see
+/// [`crate::time::parse_duration`]), which is what environment overrides
+/// produce.
 ///
 /// # Example
 ///
@@ -20,7 +23,9 @@
 /// }
 /// ```
 pub mod duration_secs {
-    use serde::{self, Deserialize, Deserializer, Serializer};
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
     use std::time::Duration;
 
     /// Serialize a Duration as seconds (u64)
@@ -31,13 +36,118 @@ pub mod duration_secs {
         serializer.serialize_u64(duration.as_secs())
     }
 
-    /// Deserialize a Duration from seconds (u64)
+    /// Deserialize a Duration from seconds (u64) or a duration string
     pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
     where
         D: Deserializer<'de>,
     {
-        let secs = u64::deserialize(deserializer)?;
-        Ok(Duration::from_secs(secs))
+        deserializer.deserialize_any(DurationVisitor)
+    }
+
+    struct DurationVisitor;
+
+    impl<'de> Visitor<'de> for DurationVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number of seconds or a duration like \"30s\", \"5m\" or \"1h\"")
+        }
+
+        fn visit_u64<E: de::Error>(self, secs: u64) -> Result<Duration, E> {
+            Ok(Duration::from_secs(secs))
+        }
+
+        fn visit_i64<E: de::Error>(self, secs: i64) -> Result<Duration, E> {
+            u64::try_from(secs)
+                .map(Duration::from_secs)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(secs), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Duration, E> {
+            crate::time::parse_duration(s)
+                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+        }
+    }
+}
+
+/// Like [`duration_secs`], for optional durations (absent = `None`)
+pub mod duration_secs_opt {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    #[derive(Deserialize)]
+    struct Secs(#[serde(with = "super::duration_secs")] Duration);
+
+    /// Serialize an optional Duration as seconds (u64)
+    pub fn serialize<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        duration.map(|d| d.as_secs()).serialize(serializer)
+    }
+
+    /// Deserialize an optional Duration from seconds (u64) or a duration string
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(Option::<Secs>::deserialize(deserializer)?.map(|s| s.0))
+    }
+}
+
+/// Helper module for Duration serialization as milliseconds, for settings
+/// too short to give in whole seconds
+///
+/// A bare number is milliseconds; strings are read like
+/// [`DurationString`](super::DurationString)s, and that is how the duration
+/// is written.
+pub mod duration_millis {
+    use serde::de::{self, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+    use std::time::Duration;
+
+    /// Serialize a Duration in the largest unit that represents it exactly
+    pub fn serialize<S>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(&crate::time::format_duration(*duration))
+    }
+
+    /// Deserialize a Duration from milliseconds (u64) or a duration string
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(MillisVisitor)
+    }
+
+    struct MillisVisitor;
+
+    impl<'de> Visitor<'de> for MillisVisitor {
+        type Value = Duration;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number of milliseconds or a duration like \"50ms\" or \"1s\"")
+        }
+
+        fn visit_u64<E: de::Error>(self, millis: u64) -> Result<Duration, E> {
+            Ok(Duration::from_millis(millis))
+        }
+
+        fn visit_i64<E: de::Error>(self, millis: i64) -> Result<Duration, E> {
+            u64::try_from(millis)
+                .map(Duration::from_millis)
+                .map_err(|_| E::invalid_value(de::Unexpected::Signed(millis), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Duration, E> {
+            let millis = s.trim().parse::<u64>().ok().map(Duration::from_millis);
+            millis
+                .or_else(|| crate::time::parse_duration(s))
+                .ok_or_else(|| E::invalid_value(de::Unexpected::Str(s), &self))
+        }
     }
 }
 
@@ -69,6 +179,14 @@ mod tests {
         assert_eq!(config.timeout, Duration::from_secs(60));
     }
 
+    #[test]
+    fn test_duration_secs_deserialize_string() {
+        let config: TestConfig = serde_json::from_str(r#"{"timeout":"2m"}"#).unwrap();
+        assert_eq!(config.timeout, Duration::from_secs(120));
+        assert!(serde_json::from_str::<TestConfig>(r#"{"timeout":"soon"}"#).is_err());
+        assert!(serde_json::from_str::<TestConfig>(r#"{"timeout":-1}"#).is_err());
+    }
+
     #[test]
     fn test_duration_secs_roundtrip() {
         let original = TestConfig {
@@ -78,4 +196,42 @@ mod tests {
         let parsed: TestConfig = serde_json::from_str(&json).unwrap();
         assert_eq!(original, parsed);
     }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct OptionalConfig {
+        #[serde(default, with = "duration_secs_opt")]
+        lifetime: Option<Duration>,
+    }
+
+    #[test]
+    fn test_duration_secs_opt() {
+        let config: OptionalConfig = serde_json::from_str(r#"{"lifetime":"1h"}"#).unwrap();
+        assert_eq!(config.lifetime, Some(Duration::from_secs(3600)));
+        assert_eq!(
+            serde_json::to_string(&config).unwrap(),
+            r#"{"lifetime":3600}"#
+        );
+
+        let config: OptionalConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.lifetime, None);
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct MillisConfig {
+        #[serde(with = "duration_millis")]
+        debounce: Duration,
+    }
+
+    #[test]
+    fn test_duration_millis() {
+        let config: MillisConfig = serde_json::from_str(r#"{"debounce":50}"#).unwrap();
+        assert_eq!(config.debounce, Duration::from_millis(50));
+        assert_eq!(
+            serde_json::to_string(&config).unwrap(),
+            r#"{"debounce":"50ms"}"#
+        );
+        let config: MillisConfig = serde_json::from_str(r#"{"debounce":"1s"}"#).unwrap();
+        assert_eq!(config.debounce, Duration::from_secs(1));
+        assert!(serde_json::from_str::<MillisConfig>(r#"{"debounce":-1}"#).is_err());
+    }
 }
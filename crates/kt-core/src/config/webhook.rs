@@ -0,0 +1,100 @@
+//! Webhook notification configuration
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+use super::serde_utils::duration_secs;
+
+/// An HTTP endpoint notified of machine and session lifecycle events
+///
+/// ```toml
+/// [[orchestrator.webhook]]
+/// url = "https://ntfy.sh/my-lab"
+/// events = ["machine_disconnected", "session_failed"]
+/// machines = ["gpu-box"]
+/// secret = "shared-signing-secret"
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// URL to POST notifications to
+    pub url: String,
+
+    /// Events to send (empty = all)
+    #[serde(default)]
+    pub events: Vec<WebhookEvent>,
+
+    /// Machine IDs or aliases to send events for (empty = all)
+    #[serde(default)]
+    pub machines: Vec<String>,
+
+    /// Secret for signing each request with HMAC-SHA256 (none = unsigned)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+
+    /// Minimum time between machine notifications for the same machine, so
+    /// a flapping connection sends one notification rather than dozens
+    #[serde(default = "default_debounce", with = "duration_secs")]
+    pub debounce: Duration,
+}
+
+fn default_debounce() -> Duration {
+    Duration::from_secs(5 * 60)
+}
+
+impl WebhookConfig {
+    /// Create a webhook for every event on every machine
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            events: Vec::new(),
+            machines: Vec::new(),
+            secret: None,
+            debounce: default_debounce(),
+        }
+    }
+
+    /// Whether this webhook wants `event`
+    pub fn wants_event(&self, event: WebhookEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Whether this webhook wants events for the machine with the given ID
+    /// and alias
+    pub fn wants_machine(&self, machine_id: &str, alias: Option<&str>) -> bool {
+        self.machines.is_empty()
+            || self.machines.iter().any(|m| {
+                m.eq_ignore_ascii_case(machine_id)
+                    || alias.is_some_and(|alias| m.eq_ignore_ascii_case(alias))
+            })
+    }
+}
+
+/// Lifecycle events a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    /// A machine connected
+    MachineConnected,
+    /// A machine disconnected
+    MachineDisconnected,
+    /// A session was created
+    SessionCreated,
+    /// A session ended normally (exit code 0 or unknown)
+    SessionClosed,
+    /// A session's shell exited with a non-zero code
+    SessionFailed,
+}
+
+impl fmt::Display for WebhookEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::MachineConnected => "machine_connected",
+            Self::MachineDisconnected => "machine_disconnected",
+            Self::SessionCreated => "session_created",
+            Self::SessionClosed => "session_closed",
+            Self::SessionFailed => "session_failed",
+        };
+        f.write_str(name)
+    }
+}
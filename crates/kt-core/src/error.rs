@@ -56,6 +56,26 @@ pub enum ConnectionError {
     HostKeyVerificationFailed,
 }
 
+/// Reasons a machine ID fails validation
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MachineIdError {
+    /// The ID is empty
+    #[error("machine ID is empty")]
+    Empty,
+
+    /// The ID exceeds the length limit
+    #[error("machine ID is {len} bytes, longer than the {max}-byte limit")]
+    TooLong { len: usize, max: usize },
+
+    /// The ID contains a character outside `[A-Za-z0-9._-]`
+    #[error("machine ID contains invalid character {0:?}")]
+    InvalidChar(char),
+
+    /// The ID doesn't start with a letter or digit
+    #[error("machine ID must start with a letter or digit")]
+    InvalidStart,
+}
+
 /// Session-related errors
 #[derive(Error, Debug)]
 pub enum SessionError {
@@ -103,3 +123,41 @@ pub enum ConfigError {
     #[error("Missing required field: {0}")]
     MissingField(String),
 }
+
+/// Reasons Tailscale's status couldn't be read
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum TailscaleError {
+    /// The `tailscale` CLI isn't there
+    #[error("Tailscale is not installed")]
+    NotInstalled,
+
+    /// Installed, but stopped or not logged in
+    #[error("Tailscale is not logged in; run `sudo tailscale up`")]
+    NotLoggedIn,
+
+    /// Neither the JSON nor the text of `tailscale status` made sense, most
+    /// likely because this Tailscale version prints it differently
+    #[error("Couldn't parse `tailscale status` output ({0}); this Tailscale version may format it differently, please report it with the output of `tailscale version`")]
+    Unparseable(String),
+}
+
+/// Reasons a bind address naming Tailscale or an interface can't be resolved
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum BindError {
+    /// Tailscale isn't running, so there is no tailnet address to bind to
+    #[error("Tailscale is not running, so there is no tailnet address to listen on")]
+    TailscaleDown,
+
+    /// Tailscale reports an IP no local interface has (userspace networking)
+    #[error("Tailscale IP {0} is not assigned to any network interface (is tailscaled using userspace networking?)")]
+    TailscaleIpNotLocal(std::net::IpAddr),
+
+    /// No interface with that name has a usable address
+    #[error("No network interface named {name} has an address (interfaces: {available})")]
+    NoSuchInterface {
+        /// Interface name from the config
+        name: String,
+        /// Names of the interfaces that do, comma-separated
+        available: String,
+    },
+}
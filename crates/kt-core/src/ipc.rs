@@ -13,6 +13,7 @@
 //! - Epoch tracking (detect orchestrator restarts)
 
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
@@ -123,6 +124,15 @@ pub fn default_ipc_address() -> String {
     format!("127.0.0.1:{}", DEFAULT_IPC_PORT)
 }
 
+/// Random ID of this process, sent as `instance_id` when authenticating
+///
+/// The same for every connection the process makes, so its connections
+/// can share a logical client ID while another process can't.
+pub fn client_instance_id() -> &'static str {
+    static INSTANCE_ID: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    INSTANCE_ID.get_or_init(|| Uuid::new_v4().to_string())
+}
+
 /// Check if an orchestrator is running by sending a Ping request
 ///
 /// Returns `Ok(true)` if the orchestrator responds with Pong,
@@ -186,10 +196,30 @@ pub enum IpcRequest {
         /// If not provided, sessions are owned by the connection ID (legacy behavior).
         #[serde(default, skip_serializing_if = "Option::is_none")]
         client_id: Option<String>,
+        /// ID of the client process, the same on all of its connections
+        /// (see [`client_instance_id`]). The orchestrator refuses a
+        /// `client_id` that a live connection of another instance holds.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        instance_id: Option<String>,
+        /// Take `client_id` over from another instance's live connections,
+        /// which are closed
+        #[serde(default, skip_serializing_if = "is_false")]
+        force_takeover: bool,
+        /// Close this client's sessions as soon as this connection drops,
+        /// instead of orphaning them for a reconnect to reclaim (for
+        /// throwaway clients such as CI jobs). Applies to this connection
+        /// only, and needs [`IpcFeature::CloseOnDisconnect`].
+        #[serde(default, skip_serializing_if = "is_false")]
+        close_on_disconnect: bool,
     },
 
     /// Get orchestrator status
-    GetStatus,
+    GetStatus {
+        /// Include the pairing code; left out by default so it doesn't end
+        /// up in logs and diagnostic dumps of the status
+        #[serde(default, skip_serializing_if = "is_false")]
+        include_pairing_code: bool,
+    },
 
     /// List connected machines
     ListMachines,
@@ -198,15 +228,82 @@ pub enum IpcRequest {
     GetMachine { machine_id: String },
 
     /// List sessions (optionally filtered by machine)
-    ListSessions { machine_id: Option<String> },
+    ///
+    /// Sessions are ordered by machine ID, then session ID. Many of them can
+    /// be fetched in pages of `limit`: pass the `next_cursor` of one page as
+    /// `cursor` to get the next, which returns every session open throughout
+    /// exactly once however many open or close meanwhile. `offset` skips
+    /// sessions instead, e.g. to jump to a page, but shifts as sessions
+    /// before it close. Without `limit`, every session is returned.
+    /// Orchestrators that predate paging ignore these fields.
+    ListSessions {
+        machine_id: Option<String>,
+        /// `next_cursor` of the previous page (None = from the first session)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cursor: Option<String>,
+        /// Sessions to skip (after `cursor`, if given)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        offset: Option<usize>,
+        /// Most sessions per page (None = all)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<usize>,
+    },
 
     /// Create a new session on a machine
+    ///
+    /// Everything except `machine_id` is optional so that older clients that
+    /// only send a shell keep working.
     CreateSession {
         machine_id: String,
         shell: Option<String>,
+        /// Working directory to start the shell in (None = agent default)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cwd: Option<String>,
+        /// Extra environment variables for the shell
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        env: Vec<(String, String)>,
+        /// Display name for the session
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        /// Initial terminal size (None = 80x24)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        size: Option<TerminalSize>,
+        /// Run the shell in a PTY; without one its stdout and stderr are
+        /// reported as separate streams
+        #[serde(default = "default_allocate_pty", skip_serializing_if = "is_true")]
+        allocate_pty: bool,
+        /// Log level for the agent's logs about this session (None = agent
+        /// default). Ignored by agents that don't support it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        log_level: Option<String>,
+        /// The client's terminal type, used as the session's `TERM`
+        /// (None = agent default)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        term: Option<String>,
+        /// The client renders 24-bit color; the session gets
+        /// `COLORTERM=truecolor`
+        #[serde(default, skip_serializing_if = "is_false")]
+        truecolor: bool,
+        /// Key for retrying safely: repeating a request with the same key
+        /// returns the session the first one created, as long as it is
+        /// still open. Keys are per client and remembered for a while.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<String>,
+        /// Close the session this many seconds after it was created,
+        /// whatever it's doing. Requires [`IpcFeature::SessionTtl`].
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ttl_secs: Option<u64>,
+        /// Close the session when its shell exits (None = yes). With
+        /// `false` it stays open, its output still there to read, until
+        /// closed, its TTL runs out or its machine goes.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        close_on_exit: Option<bool>,
     },
 
     /// Send input to a session
+    ///
+    /// For a session created without a PTY, empty `data` closes the shell's
+    /// stdin.
     SessionInput { session_id: String, data: Vec<u8> },
 
     /// Resize a session's terminal
@@ -219,15 +316,104 @@ pub enum IpcRequest {
     /// Close a session
     CloseSession { session_id: String, force: bool },
 
+    /// Get everything known about a session: its state, owner, activity
+    /// and subscribers, or why it closed if it closed recently
+    ///
+    /// Requires [`IpcFeature::SessionDetails`].
+    GetSession { session_id: String },
+
+    /// Get the environment a session was created with
+    ///
+    /// Values of sensitive-looking variables (see `is_sensitive_env_var`) are
+    /// redacted unless `show_secrets` is set.
+    GetSessionEnv {
+        session_id: String,
+        #[serde(default)]
+        show_secrets: bool,
+    },
+
+    /// Configure activity and silence notifications for a session
+    ///
+    /// Bells are always reported. With `activity`, output after a quiet
+    /// spell is reported; with `silence_secs`, that many seconds without
+    /// output are reported once. Each call replaces the previous settings.
+    SetSessionMonitor {
+        session_id: String,
+        #[serde(default)]
+        activity: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        silence_secs: Option<u64>,
+    },
+
     /// Subscribe to events for a session (terminal output)
+    ///
+    /// Reattaches the session if it was detached. Answered with `Ok`, or
+    /// with `Subscribed` carrying the attach message when the orchestrator
+    /// has one.
     Subscribe { session_id: String },
 
     /// Unsubscribe from session events
     Unsubscribe { session_id: String },
 
+    /// Subscribe to a session's events as an observer, e.g. to mirror it in
+    /// a second terminal
+    ///
+    /// Unlike `Subscribe` the session needn't be ours, and its input isn't
+    /// ours until claimed with `ClaimSession`. `Unsubscribe` ends it.
+    /// Requires [`IpcFeature::ReadOnlyAttach`].
+    ObserveSession { session_id: String },
+
+    /// Take a session's input: until released or claimed by another
+    /// client, only ours is accepted, and others get `InputHeld`
+    ///
+    /// Open to the session's owner and to its observers. Everyone is told
+    /// with `SessionInputHolder`. Requires [`IpcFeature::ReadOnlyAttach`].
+    ClaimSession { session_id: String },
+
+    /// Give a claimed session's input back to its owner (nothing happens
+    /// if we don't hold it)
+    ReleaseSession { session_id: String },
+
+    /// Unsubscribe from a session and leave it running, detached
+    ///
+    /// A detached session isn't cleaned up like one orphaned by a
+    /// disconnecting client: it stays listed until it is closed or its owner
+    /// subscribes to it again.
+    DetachSession { session_id: String },
+
     /// Disconnect a machine
     DisconnectMachine { machine_id: String },
 
+    /// Change a runtime setting of a machine's agent (keys in
+    /// `kt_protocol::AgentConfigKey`)
+    ///
+    /// Answered once the agent has acked the change: `Ok` if it applied it,
+    /// else an `Error` with its reason. Lasts until the agent reconnects.
+    SetAgentOption {
+        machine_id: String,
+        key: String,
+        value: String,
+    },
+
+    /// List machine groups
+    ListGroups,
+
+    /// Create, change or delete a machine group
+    ///
+    /// Members are machine IDs or aliases; ones that don't match a known
+    /// machine are accepted and reported back as unknown. Changes last until
+    /// the orchestrator restarts; `[orchestrator.groups]` in the config file
+    /// defines the groups it starts with.
+    ModifyGroup {
+        group: String,
+        action: GroupAction,
+        #[serde(default)]
+        members: Vec<String>,
+    },
+
+    /// List recent agent registrations that were rejected
+    ListRejections,
+
     /// Ping (for keepalive)
     Ping,
 
@@ -244,7 +430,28 @@ pub enum IpcRequest {
     ///
     /// Returns current epoch_id, sequence number, and all machines/sessions.
     /// Used for initial sync and recovery after event gaps.
-    GetStateSnapshot,
+    ///
+    /// Large fleets can be fetched in pages of `limit` machines, ordered by
+    /// machine ID, each with its sessions: pass the `next_cursor` of one page
+    /// as `cursor` to get the next. A machine that connects or disconnects
+    /// while paging may be missed or included, but every other machine is
+    /// returned exactly once; the events after the first page's
+    /// `current_seq` cover the changes. Orchestrators that predate paging
+    /// ignore these fields and return everything at once.
+    GetStateSnapshot {
+        /// Only the machine with this ID or alias (case-insensitive)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        machine_filter: Option<String>,
+        /// Include sessions (on by default)
+        #[serde(default = "default_true", skip_serializing_if = "is_true")]
+        include_sessions: bool,
+        /// `next_cursor` of the previous page (None = first page)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cursor: Option<String>,
+        /// Most machines per page (None = all)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limit: Option<usize>,
+    },
 
     /// Get events since a specific sequence number
     ///
@@ -254,6 +461,43 @@ pub enum IpcRequest {
         /// Sequence number to start from (exclusive)
         since_seq: u64,
     },
+
+    /// Read the orchestrator's recent log lines
+    ///
+    /// Returns the last `lines` lines (capped at `MAX_TAIL_LOG_LINES`). With
+    /// `follow`, the orchestrator keeps pushing new lines on this connection
+    /// as further `LogLines` responses, batched, until the client disconnects.
+    /// Followers should use a dedicated connection for this.
+    TailLogs {
+        #[serde(default)]
+        follow: bool,
+        #[serde(default)]
+        lines: usize,
+    },
+
+    /// Replace the IPC token with a new one
+    ///
+    /// The new token is written to the token file. The previous token keeps
+    /// working for a short overlap window so other connected clients can
+    /// re-read the file; this connection stays authenticated.
+    RotateIpcToken,
+
+    /// Turn maintenance mode on or off
+    ///
+    /// In maintenance mode `CreateSession` is refused with `message` (or a
+    /// default one), while existing sessions carry on. Any authenticated
+    /// client may toggle it. Requires [`IpcFeature::MaintenanceMode`].
+    SetMaintenanceMode {
+        enabled: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+
+    /// Ask which optional features the orchestrator supports
+    ///
+    /// Orchestrators older than this request answer with an `Error`; treat
+    /// that as [`OrchestratorCapabilities::legacy`].
+    GetCapabilities,
 }
 
 /// IPC response from orchestrator to client
@@ -266,6 +510,11 @@ pub enum IpcResponse {
         epoch_id: String,
         /// Current sequence number for gap detection
         current_seq: u64,
+        /// Orchestrator's clock when it answered (milliseconds since Unix
+        /// epoch), for clients to render relative times against its clock
+        /// rather than their own (0 = orchestrator predates this field)
+        #[serde(default)]
+        server_time_ms: u64,
     },
 
     /// Authentication required - client must authenticate before other requests
@@ -277,24 +526,73 @@ pub enum IpcResponse {
     /// List of machines
     Machines { machines: Vec<MachineInfo> },
 
+    /// Machine groups (for `ModifyGroup`, the changed group, or none once
+    /// deleted)
+    Groups { groups: Vec<GroupInfo> },
+
+    /// Rejected agent registrations, most recently seen first
+    Rejections { rejections: Vec<RejectionInfo> },
+
     /// Single machine details
     Machine(MachineInfo),
 
     /// List of sessions
-    Sessions { sessions: Vec<SessionInfo> },
+    Sessions {
+        sessions: Vec<SessionInfo>,
+        /// Sessions matching the request's machine, before paging (None
+        /// from orchestrators that predate paging)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        total: Option<usize>,
+        /// Cursor for the next page, if there are more sessions
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_cursor: Option<String>,
+    },
 
     /// Session created
     SessionCreated(SessionInfo),
 
+    /// Details of a single session (reply to `GetSession`)
+    Session(SessionDetails),
+
+    /// Environment variables of a session
+    SessionEnv {
+        session_id: String,
+        env: Vec<SessionEnvVar>,
+    },
+
     /// Generic success
     Ok,
 
     /// Error response
     Error { message: String },
 
+    /// Session creation rejected because the orchestrator-wide session cap
+    /// (`max_total_sessions`) has been reached
+    CapacityExceeded {
+        message: String,
+        /// Sessions currently open across all machines
+        current: usize,
+        /// Configured maximum
+        max: usize,
+    },
+
+    /// Request rejected because the client exceeded one of its IPC rate
+    /// limits, or because a session's input tripped its circuit breaker
+    RateLimited {
+        message: String,
+        /// Which limit was exceeded
+        kind: RateLimitKind,
+        /// Configured requests per second for that kind (KiB per minute
+        /// for `SessionInput`)
+        limit: u32,
+    },
+
     /// Pong response
     Pong,
 
+    /// Orchestrator log lines (reply to `TailLogs`, and pushed while following)
+    LogLines { lines: Vec<LogLine> },
+
     /// Pairing code response
     PairingCode { code: String },
 
@@ -311,6 +609,9 @@ pub enum IpcResponse {
         machines: Vec<MachineInfo>,
         /// All active sessions
         sessions: Vec<SessionInfo>,
+        /// Cursor for the next page, if there are more machines
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        next_cursor: Option<String>,
     },
 
     /// Events since requested sequence number
@@ -323,12 +624,38 @@ pub enum IpcResponse {
         oldest_available_seq: Option<u64>,
     },
 
+    /// Optional features the orchestrator supports (reply to
+    /// `GetCapabilities`)
+    Capabilities(OrchestratorCapabilities),
+
+    /// IPC token rotated (reply to `RotateIpcToken`)
+    TokenRotated {
+        /// Generation of the new token
+        generation: u64,
+        /// When the new token expires (Unix seconds, None = never)
+        expires_at: Option<u64>,
+        /// Seconds the previous token is still accepted
+        previous_valid_secs: u64,
+    },
+
+    /// Input refused because another client holds the session's input
+    /// (see `ClaimSession`)
+    InputHeld {
+        session_id: String,
+        /// Client holding it
+        holder: String,
+    },
+
     /// Subscription successful with current state
     Subscribed {
         /// Current sequence number at subscription time
         current_seq: u64,
         /// Session info at subscription time
         session: SessionInfo,
+        /// Message to show before the session's output, ready to write to
+        /// a terminal (see `attach_motd`); sent once per subscription
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motd: Option<String>,
     },
 }
 
@@ -345,14 +672,54 @@ pub enum IpcEvent {
     /// Machine status updated
     MachineUpdated(MachineInfo),
 
+    /// An agent was refused at authentication or registration
+    ///
+    /// A rejection that repeats (same address, hostname and reason) is only
+    /// announced again after a while; `ListRejections` counts every one.
+    MachineRejected {
+        /// Hostname the agent reported, if it got as far as registering
+        hostname: Option<String>,
+        reason: String,
+        /// IP address the agent connected from
+        peer_addr: String,
+    },
+
     /// Session created
     SessionCreated(SessionInfo),
 
     /// Session closed
-    SessionClosed { session_id: String },
+    SessionClosed {
+        session_id: String,
+        /// Exit code of the remote shell, if the session ended because it exited
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        /// Why the session ended (none from orchestrators that don't say)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<CloseReason>,
+    },
 
     /// Terminal output data
-    TerminalOutput { session_id: String, data: Vec<u8> },
+    TerminalOutput {
+        session_id: String,
+        data: Vec<u8>,
+        /// Which stream it came from (always stdout for PTY sessions)
+        #[serde(default, skip_serializing_if = "OutputStream::is_stdout")]
+        stream: OutputStream,
+    },
+
+    /// Bell, activity or silence detected in a session's output
+    SessionActivity {
+        session_id: String,
+        kind: ActivityKind,
+    },
+
+    /// Client whose input a session takes changed (see `ClaimSession`)
+    SessionInputHolder {
+        session_id: String,
+        /// Client holding it, None when it went back to the owner
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        holder: Option<String>,
+    },
 
     /// Orchestrator status changed
     StatusChanged(OrchestratorStatus),
@@ -363,6 +730,32 @@ pub enum IpcEvent {
     /// The client should refresh its state (re-fetch machines, sessions)
     /// to ensure it has accurate information.
     EventsDropped { count: u32 },
+
+    /// Something the orchestrator did that users should know about, e.g.
+    /// refusing a session's input for a while
+    Notice {
+        message: String,
+        /// Session concerned, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        /// IPC client concerned, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_id: Option<String>,
+    },
+
+    /// The orchestrator reloaded its configuration (on `SIGHUP`)
+    ConfigReloaded {
+        /// Keys that changed and now apply, as dotted config file paths
+        /// such as `orchestrator.max_total_sessions`; empty if none did
+        changed_keys: Vec<String>,
+        /// Keys that changed but keep their old value until the
+        /// orchestrator restarts
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        restart_required: Vec<String>,
+    },
+
+    /// Reloading the configuration failed; the previous one still applies
+    ConfigReloadFailed { error: String },
 }
 
 /// Orchestrator status information
@@ -379,12 +772,410 @@ pub struct OrchestratorStatus {
     pub session_count: usize,
     /// Orchestrator version
     pub version: String,
+    /// Agent protocol version it speaks (`kt_protocol::PROTOCOL_VERSION`);
+    /// None from orchestrators that predate reporting it
+    #[serde(default)]
+    pub protocol_version: Option<String>,
     /// Tailscale hostname (if available)
     pub tailscale_hostname: Option<String>,
-    /// Bind address
+    /// Bind address, as configured
     pub bind_address: String,
-    /// Pairing code for easy agent connection
+    /// Address the SSH server is listening on, which differs from
+    /// `bind_address` when that names Tailscale or an interface
+    #[serde(default)]
+    pub listen_address: Option<String>,
+    /// Pairing code for easy agent connection (only when asked for with
+    /// `include_pairing_code`)
     pub pairing_code: Option<String>,
+    /// What kind of process runs the orchestrator
+    #[serde(default)]
+    pub owner: OrchestratorOwner,
+    /// Adaptive coalescing of terminal output
+    #[serde(default)]
+    pub output_coalescing: CoalescingStatus,
+    /// Per-session input circuit breaker
+    #[serde(default)]
+    pub input_breaker: InputBreakerStatus,
+    /// Why new sessions are refused, while in maintenance mode
+    #[serde(default)]
+    pub maintenance: Option<String>,
+}
+
+/// State of adaptive output coalescing
+///
+/// The orchestrator merges terminal output into fewer events while IPC
+/// clients keep lagging behind the event stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoalescingStatus {
+    /// Whether output is being coalesced right now
+    pub engaged: bool,
+    /// Times an IPC client lagged in the last few seconds
+    pub recent_lag_events: u32,
+    /// Times coalescing has engaged since the orchestrator started
+    pub times_engaged: u64,
+    /// Output events merged into others since the orchestrator started
+    pub coalesced_events: u64,
+}
+
+/// State of the per-session input circuit breaker
+///
+/// A session whose input goes over `max_input_rate_kib_per_min` has further
+/// input refused for a while, so runaway automation can't swamp its agent.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InputBreakerStatus {
+    /// Configured limit in KiB per minute per session (0 = none)
+    pub max_kib_per_min: u64,
+    /// Sessions whose input is being refused right now
+    pub blocked_sessions: usize,
+    /// Times the breaker has tripped since the orchestrator started
+    pub times_tripped: u64,
+}
+
+/// Version of the IPC protocol this build speaks
+///
+/// Bumped when requests or responses change in a way that optional fields
+/// and [`IpcFeature`]s can't express. Orchestrators that predate
+/// `GetCapabilities` count as version 0.
+pub const IPC_PROTOCOL_VERSION: u32 = 1;
+
+/// An optional orchestrator feature that IPC clients can detect
+///
+/// New features are only ever added at the end, so a feature keeps its bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IpcFeature {
+    /// Length-prefixed binary frames instead of JSON lines
+    BinaryFraming,
+    /// Subscribing to machine and session metrics
+    MetricsSubscription,
+    /// Catching up on missed events with `GetEventsSince`
+    EventReplay,
+    /// File upload and download through the orchestrator
+    FileTransfer,
+    /// Delivering signals to a session's process
+    Signals,
+    /// Attaching to a session without being able to send input, and
+    /// handing its input over (`ObserveSession`, `ClaimSession`)
+    ReadOnlyAttach,
+    /// Sessions and machines partitioned into namespaces
+    Namespaces,
+    /// Reading and following the orchestrator's logs with `TailLogs`
+    LogTailing,
+    /// `CreateSession` honoring `idempotency_key`
+    IdempotentCreate,
+    /// `GetStateSnapshot` filtering and paging
+    SnapshotPaging,
+    /// `GetSession`
+    SessionDetails,
+    /// `SetMaintenanceMode`
+    MaintenanceMode,
+    /// `CreateSession` honoring `ttl_secs` and `close_on_exit`
+    SessionTtl,
+    /// `SetAgentOption`
+    AgentOptions,
+    /// `Authenticate` honoring `close_on_disconnect`
+    CloseOnDisconnect,
+    /// `ListSessions` ordering and paging
+    SessionPaging,
+}
+
+impl IpcFeature {
+    /// All known features, in bit order
+    pub const ALL: [IpcFeature; 16] = [
+        IpcFeature::BinaryFraming,
+        IpcFeature::MetricsSubscription,
+        IpcFeature::EventReplay,
+        IpcFeature::FileTransfer,
+        IpcFeature::Signals,
+        IpcFeature::ReadOnlyAttach,
+        IpcFeature::Namespaces,
+        IpcFeature::LogTailing,
+        IpcFeature::IdempotentCreate,
+        IpcFeature::SnapshotPaging,
+        IpcFeature::SessionDetails,
+        IpcFeature::MaintenanceMode,
+        IpcFeature::SessionTtl,
+        IpcFeature::AgentOptions,
+        IpcFeature::CloseOnDisconnect,
+        IpcFeature::SessionPaging,
+    ];
+
+    /// Bit used for this feature on the wire
+    fn bit(self) -> u64 {
+        1 << self as u64
+    }
+
+    /// Stable name used in logs and status output
+    pub fn name(self) -> &'static str {
+        match self {
+            IpcFeature::BinaryFraming => "binary_framing",
+            IpcFeature::MetricsSubscription => "metrics_subscription",
+            IpcFeature::EventReplay => "event_replay",
+            IpcFeature::FileTransfer => "file_transfer",
+            IpcFeature::Signals => "signals",
+            IpcFeature::ReadOnlyAttach => "read_only_attach",
+            IpcFeature::Namespaces => "namespaces",
+            IpcFeature::LogTailing => "log_tailing",
+            IpcFeature::IdempotentCreate => "idempotent_create",
+            IpcFeature::SnapshotPaging => "snapshot_paging",
+            IpcFeature::SessionDetails => "session_details",
+            IpcFeature::MaintenanceMode => "maintenance_mode",
+            IpcFeature::SessionTtl => "session_ttl",
+            IpcFeature::AgentOptions => "agent_options",
+            IpcFeature::CloseOnDisconnect => "close_on_disconnect",
+            IpcFeature::SessionPaging => "session_paging",
+        }
+    }
+}
+
+impl fmt::Display for IpcFeature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Set of features an orchestrator supports
+///
+/// Encoded as a bitset like agent capabilities. Bits the client doesn't
+/// know about are kept but ignored, so a newer orchestrator can advertise
+/// features an older client has never heard of.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct IpcFeatures(u64);
+
+impl IpcFeatures {
+    /// No optional features
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Check whether a feature is present
+    pub fn contains(&self, feature: IpcFeature) -> bool {
+        self.0 & feature.bit() != 0
+    }
+
+    /// Add a feature to the set
+    pub fn insert(&mut self, feature: IpcFeature) {
+        self.0 |= feature.bit();
+    }
+
+    /// Return the set with a feature added
+    pub fn with(mut self, feature: IpcFeature) -> Self {
+        self.insert(feature);
+        self
+    }
+
+    /// Iterate over the known features in this set
+    pub fn iter(&self) -> impl Iterator<Item = IpcFeature> + '_ {
+        IpcFeature::ALL.into_iter().filter(|f| self.contains(*f))
+    }
+
+    /// Names of the known features in this set
+    pub fn names(&self) -> Vec<String> {
+        self.iter().map(|f| f.name().to_string()).collect()
+    }
+}
+
+impl FromIterator<IpcFeature> for IpcFeatures {
+    fn from_iter<I: IntoIterator<Item = IpcFeature>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::empty(), |features, feature| features.with(feature))
+    }
+}
+
+/// What an orchestrator supports, as answered to `GetCapabilities`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrchestratorCapabilities {
+    /// IPC protocol version the orchestrator speaks
+    pub protocol_version: u32,
+    /// Optional features it supports
+    #[serde(default)]
+    pub features: IpcFeatures,
+}
+
+impl OrchestratorCapabilities {
+    /// What this build supports, given the features available at runtime
+    pub fn current(features: IpcFeatures) -> Self {
+        Self {
+            protocol_version: IPC_PROTOCOL_VERSION,
+            features,
+        }
+    }
+
+    /// Capabilities of an orchestrator that predates `GetCapabilities`
+    ///
+    /// Reports no optional features, so clients turn them off instead of
+    /// failing when they are first used.
+    pub fn legacy() -> Self {
+        Self::default()
+    }
+
+    /// Check whether the orchestrator supports a feature
+    pub fn supports(&self, feature: IpcFeature) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+/// What a [`IpcEvent::SessionActivity`] event reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    /// The session rang the terminal bell (BEL)
+    Bell,
+    /// Output resumed after a quiet spell
+    Activity,
+    /// No output for the configured silence period
+    Silence,
+}
+
+impl fmt::Display for ActivityKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bell => write!(f, "bell"),
+            Self::Activity => write!(f, "activity"),
+            Self::Silence => write!(f, "silence"),
+        }
+    }
+}
+
+/// Which IPC rate limit a request was rejected by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKind {
+    /// Per client: terminal input and resizes for an interactive session
+    Input,
+    /// Per client: every other request
+    Control,
+    /// Per session: input bytes per minute (`max_input_rate_kib_per_min`)
+    SessionInput,
+}
+
+impl fmt::Display for RateLimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Input => write!(f, "input"),
+            Self::Control => write!(f, "control"),
+            Self::SessionInput => write!(f, "session input"),
+        }
+    }
+}
+
+/// Output stream of a session
+///
+/// A PTY merges everything into stdout; sessions created without one keep
+/// stderr apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputStream {
+    #[default]
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    /// Check whether this is stdout
+    pub fn is_stdout(&self) -> bool {
+        *self == Self::Stdout
+    }
+}
+
+fn default_allocate_pty() -> bool {
+    true
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Why a session ended
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CloseReason {
+    /// A client asked for the session to be closed
+    UserRequested,
+    /// The remote shell exited
+    ProcessExited {
+        /// Exit code, if the agent reported one
+        code: Option<i32>,
+    },
+    /// The session's machine disconnected or stopped answering heartbeats
+    MachineDisconnected,
+    /// The owning client went away and didn't come back within the grace
+    /// period
+    OrphanTimeout,
+    /// Closed to keep within a session limit
+    LimitReaped,
+    /// Its time to live (`ttl_secs`) ran out
+    Ttl,
+    /// Its owner disconnected, having asked for its sessions to be closed
+    /// then (`close_on_disconnect`)
+    OwnerDisconnected,
+    /// The agent reported an error for the session
+    Error {
+        /// Error from the agent
+        message: String,
+    },
+}
+
+impl CloseReason {
+    /// Whether the session ended other than by request (directly or with
+    /// `close_on_disconnect`), a clean exit or its TTL running out
+    pub fn is_abnormal(&self) -> bool {
+        match self {
+            Self::UserRequested | Self::Ttl | Self::OwnerDisconnected => false,
+            Self::ProcessExited { code } => code.is_some_and(|code| code != 0),
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UserRequested => write!(f, "closed by request"),
+            Self::ProcessExited { code: Some(code) } => {
+                write!(f, "shell exited with code {}", code)
+            }
+            Self::ProcessExited { code: None } => write!(f, "shell exited"),
+            Self::MachineDisconnected => write!(f, "machine disconnected"),
+            Self::OrphanTimeout => write!(f, "no client reclaimed it in time"),
+            Self::LimitReaped => write!(f, "closed to stay within the session limit"),
+            Self::Ttl => write!(f, "its time to live ran out"),
+            Self::OwnerDisconnected => write!(f, "its client disconnected"),
+            Self::Error { message } => write!(f, "error: {}", message),
+        }
+    }
+}
+
+/// What kind of process runs an orchestrator
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OrchestratorOwner {
+    /// `k-terminus serve` or the `kt-orchestrator` binary
+    #[default]
+    Standalone,
+    /// Embedded in the k-Terminus desktop app, which stops with it
+    DesktopEmbedded,
+}
+
+impl OrchestratorOwner {
+    /// Human-readable description, e.g. for `k-terminus status`
+    pub fn description(&self) -> &'static str {
+        match self {
+            Self::Standalone => "standalone daemon",
+            Self::DesktopEmbedded => "embedded in k-Terminus Desktop",
+        }
+    }
 }
 
 /// Machine information
@@ -395,6 +1186,9 @@ pub struct MachineInfo {
     pub id: String,
     /// User-friendly alias
     pub alias: Option<String>,
+    /// Free-form label, e.g. "CI runner"
+    #[serde(default)]
+    pub label: Option<String>,
     /// Machine hostname
     pub hostname: String,
     /// Operating system
@@ -403,14 +1197,21 @@ pub struct MachineInfo {
     pub arch: String,
     /// Connection status
     pub status: MachineStatus,
-    /// When the machine connected
+    /// When the machine connected (ISO-8601 UTC)
     pub connected_at: Option<String>,
-    /// Last heartbeat timestamp
+    /// Last heartbeat timestamp (ISO-8601 UTC)
     pub last_heartbeat: Option<String>,
     /// Number of active sessions
     pub session_count: usize,
     /// Machine tags
     pub tags: Vec<String>,
+    /// Optional features the agent supports (e.g. "cwd", "signals")
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Times the machine has reconnected; a high count flags an unstable
+    /// machine
+    #[serde(default)]
+    pub reconnect_count: u64,
 }
 
 /// Machine connection status
@@ -421,6 +1222,9 @@ pub enum MachineStatus {
     Connected,
     /// Machine is connecting
     Connecting,
+    /// Machine's connection dropped and it hasn't come back yet; its
+    /// sessions are kept until it does or its grace period runs out
+    Reconnecting,
     /// Machine is disconnected
     Disconnected,
     /// Connection error
@@ -432,12 +1236,58 @@ impl std::fmt::Display for MachineStatus {
         match self {
             MachineStatus::Connected => write!(f, "connected"),
             MachineStatus::Connecting => write!(f, "connecting"),
+            MachineStatus::Reconnecting => write!(f, "reconnecting"),
             MachineStatus::Disconnected => write!(f, "disconnected"),
             MachineStatus::Error => write!(f, "error"),
         }
     }
 }
 
+/// How `ModifyGroup` changes a group
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupAction {
+    /// Create a new group with the given members
+    Create,
+    /// Add members to an existing group
+    Add,
+    /// Remove members from an existing group
+    Remove,
+    /// Delete the group
+    Delete,
+}
+
+/// A named set of machines, selected as `@name`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupInfo {
+    /// Group name (without the `@`)
+    pub name: String,
+    /// Machine IDs or aliases, in the order they were added
+    pub members: Vec<String>,
+    /// Members that match no connected or configured machine
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unknown_members: Vec<String>,
+}
+
+/// An agent registration the orchestrator rejected
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RejectionInfo {
+    /// Hostname the agent reported, if it got as far as registering
+    pub hostname: Option<String>,
+    /// Why it was rejected
+    pub reason: String,
+    /// IP address the agent connected from
+    pub peer_addr: String,
+    /// When it was first rejected (ISO 8601)
+    pub first_seen: String,
+    /// When it was last rejected (ISO 8601)
+    pub last_seen: String,
+    /// Times it was rejected since it was first seen
+    pub count: u64,
+}
+
 /// Session information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -448,21 +1298,359 @@ pub struct SessionInfo {
     pub machine_id: String,
     /// Shell being used
     pub shell: Option<String>,
-    /// When the session was created
+    /// When the session was created (ISO-8601 UTC)
     pub created_at: String,
     /// Process ID on remote machine
     pub pid: Option<u32>,
     /// Terminal dimensions
     pub size: Option<TerminalSize>,
+    /// Display name given at creation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Input bytes sent to the session so far
+    #[serde(default)]
+    pub bytes_in: u64,
+    /// Output bytes received from the session so far
+    #[serde(default)]
+    pub bytes_out: u64,
+    /// Whether the session is detached from its client
+    #[serde(default)]
+    pub detached: bool,
+    /// Input bytes sent to the session over the last minute
+    #[serde(default)]
+    pub input_bytes_per_min: u64,
+    /// `SessionInput` requests for the session over the last minute
+    #[serde(default)]
+    pub input_requests_per_min: u64,
+}
+
+/// Where a session is in its lifecycle, as reported by `GetSession`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLifecycle {
+    /// Waiting for the agent to start the shell
+    Creating,
+    /// Running, with its owner connected
+    Active,
+    /// Running, its owner disconnected; closed unless reclaimed in time
+    Orphaned,
+    /// Running, detached by its owner on purpose
+    Detached,
+    /// Its shell exited; kept open as created with `close_on_exit: false`
+    Exited,
+    /// Being closed
+    Closing,
+    /// Closed
+    Closed,
+}
+
+impl fmt::Display for SessionLifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Creating => "creating",
+            Self::Active => "active",
+            Self::Orphaned => "orphaned",
+            Self::Detached => "detached",
+            Self::Exited => "exited",
+            Self::Closing => "closing",
+            Self::Closed => "closed",
+        })
+    }
+}
+
+/// Everything the orchestrator knows about one session (reply to
+/// `GetSession`)
+///
+/// Sessions that closed a short while ago are still answered for, as they
+/// were when they closed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionDetails {
+    /// The session as listed
+    pub session: SessionInfo,
+    /// Where it is in its lifecycle
+    pub state: SessionLifecycle,
+    /// Client ID that owns it (None = created by the orchestrator)
+    pub owner_client_id: Option<String>,
+    /// Whether the client asking owns it
+    pub owned_by_you: bool,
+    /// Connections subscribed to its output
+    pub subscribers: usize,
+    /// Seconds since its last input or output (None once closed)
+    pub idle_secs: Option<u64>,
+    /// When its owner disconnected or detached it (ISO-8601 UTC)
+    pub orphaned_at: Option<String>,
+    /// When it closed (ISO-8601 UTC)
+    pub closed_at: Option<String>,
+    /// Why it closed, if the orchestrator knows
+    pub close_reason: Option<CloseReason>,
+    /// Exit code of its shell, if it exited
+    pub exit_code: Option<i32>,
+    /// Seconds until its TTL runs out and it's closed (None = no TTL)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Placeholder shown instead of the value of a redacted environment variable
+pub const REDACTED_ENV_VALUE: &str = "<redacted>";
+
+/// A single environment variable of a session
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEnvVar {
+    /// Variable name
+    pub name: String,
+    /// Variable value (`REDACTED_ENV_VALUE` if redacted)
+    pub value: String,
+    /// Whether the value was withheld by the orchestrator
+    pub redacted: bool,
+}
+
+impl SessionEnvVar {
+    /// Build an entry, redacting the value if `redact` is set and the name
+    /// looks sensitive
+    pub fn new(name: &str, value: &str, redact: bool) -> Self {
+        if redact && is_sensitive_env_var(name) {
+            Self {
+                name: name.to_string(),
+                value: REDACTED_ENV_VALUE.to_string(),
+                redacted: true,
+            }
+        } else {
+            Self {
+                name: name.to_string(),
+                value: value.to_string(),
+                redacted: false,
+            }
+        }
+    }
+}
+
+/// Check whether an environment variable name looks like it holds a secret.
+///
+/// Matches names ending in `_TOKEN`, `_KEY` or `_SECRET`, and names containing
+/// `PASSWORD` or `PASSWD` (case-insensitive). This is a heuristic meant to keep
+/// credentials off shared screens, not a guarantee.
+pub fn is_sensitive_env_var(name: &str) -> bool {
+    let upper = name.to_ascii_uppercase();
+    matches!(upper.as_str(), "TOKEN" | "SECRET")
+        || upper.ends_with("_TOKEN")
+        || upper.ends_with("_KEY")
+        || upper.ends_with("_SECRET")
+        || upper.contains("PASSWORD")
+        || upper.contains("PASSWD")
+}
+
+/// Maximum number of past log lines returned by `TailLogs`
+pub const MAX_TAIL_LOG_LINES: usize = 5000;
+
+/// A single orchestrator log record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogLine {
+    /// When the record was logged (Unix millis)
+    pub timestamp_ms: u64,
+    /// Level in upper case ("ERROR", "WARN", "INFO", "DEBUG", "TRACE")
+    pub level: String,
+    /// Module path the record came from
+    pub target: String,
+    /// Formatted message, including any structured fields
+    pub message: String,
 }
 
 /// Terminal dimensions
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TerminalSize {
     pub cols: u16,
     pub rows: u16,
 }
 
+// ============================================================================
+// Request Validation
+// ============================================================================
+
+/// Minimum terminal columns/rows for create and resize requests.
+///
+/// A terminal with 0 columns or rows would be unusable. The minimum of 1
+/// allows for edge cases while preventing completely invalid sizes.
+pub const MIN_TERMINAL_SIZE: u16 = 1;
+
+/// Maximum terminal columns/rows for create and resize requests.
+///
+/// 10,000 is far larger than any realistic terminal size (typical max is 300-500).
+/// This limit prevents resource exhaustion from extremely large buffers while
+/// still allowing for unusual but valid use cases like virtual terminals.
+pub const MAX_TERMINAL_SIZE: u16 = 10000;
+
+/// Validate terminal dimensions against `MIN_TERMINAL_SIZE`..=`MAX_TERMINAL_SIZE`.
+///
+/// Returns `Ok(())` if both dimensions are in range, or an error message
+/// naming the first dimension that is out of range.
+pub fn validate_terminal_size(cols: u16, rows: u16) -> Result<(), String> {
+    let valid_range = MIN_TERMINAL_SIZE..=MAX_TERMINAL_SIZE;
+    if !valid_range.contains(&cols) {
+        return Err(format!(
+            "Invalid terminal columns: {} (must be {}-{})",
+            cols, MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE
+        ));
+    }
+    if !valid_range.contains(&rows) {
+        return Err(format!(
+            "Invalid terminal rows: {} (must be {}-{})",
+            rows, MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE
+        ));
+    }
+    Ok(())
+}
+
+/// Validate an environment variable name.
+///
+/// Valid names must:
+/// - Start with a letter (a-z, A-Z) or underscore (_)
+/// - Contain only alphanumeric characters (a-z, A-Z, 0-9) and underscores
+/// - Not be empty
+///
+/// This prevents environment variable injection attacks where malicious
+/// variable names could affect shell behavior or security.
+pub fn is_valid_env_var_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    // First character must be letter or underscore
+    let first = match chars.next() {
+        Some(c) => c,
+        None => return false,
+    };
+
+    if !first.is_ascii_alphabetic() && first != '_' {
+        return false;
+    }
+
+    // Rest must be alphanumeric or underscore
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Validate a list of environment variables.
+///
+/// Returns `Ok(())` if all variables are valid, or an error message
+/// describing the first invalid variable found.
+pub fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
+    for (name, _value) in env {
+        if !is_valid_env_var_name(name) {
+            return Err(format!(
+                "Invalid environment variable name '{}': must start with letter or underscore, \
+                 and contain only alphanumeric characters and underscores",
+                name
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Maximum length of a session's terminal type (`TERM`).
+pub const MAX_TERM_LEN: usize = 64;
+
+/// Validate a terminal type for a session's `TERM`.
+///
+/// Terminfo names are short and made of letters, digits and `-._+`;
+/// anything else is rejected rather than passed into the shell's
+/// environment.
+pub fn validate_term(term: &str) -> Result<(), String> {
+    if term.is_empty() || term.len() > MAX_TERM_LEN {
+        return Err(format!(
+            "Invalid terminal type '{}': must be 1-{} characters",
+            term, MAX_TERM_LEN
+        ));
+    }
+    if !term
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '+'))
+    {
+        return Err(format!(
+            "Invalid terminal type '{}': only letters, digits and -._+ are allowed",
+            term
+        ));
+    }
+    Ok(())
+}
+
+/// Maximum length of a `CreateSession` idempotency key.
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+
+/// Validate a `CreateSession` idempotency key.
+pub fn validate_idempotency_key(key: &str) -> Result<(), String> {
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(format!(
+            "Invalid idempotency key: must be 1-{} bytes",
+            MAX_IDEMPOTENCY_KEY_LEN
+        ));
+    }
+    Ok(())
+}
+
+/// Longest TTL a session can be created with (30 days).
+pub const MAX_SESSION_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+/// Validate a session's `ttl_secs` against 1..=`MAX_SESSION_TTL_SECS`.
+pub fn validate_session_ttl(ttl_secs: u64) -> Result<(), String> {
+    if !(1..=MAX_SESSION_TTL_SECS).contains(&ttl_secs) {
+        return Err(format!(
+            "Invalid session TTL: {}s (must be 1-{}s)",
+            ttl_secs, MAX_SESSION_TTL_SECS
+        ));
+    }
+    Ok(())
+}
+
+/// Log levels accepted for a session's `log_level`, most to least severe.
+pub const SESSION_LOG_LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+
+/// Validate a session log level and return it in canonical (lowercase) form.
+pub fn validate_session_log_level(level: &str) -> Result<String, String> {
+    let canonical = level.to_ascii_lowercase();
+    if SESSION_LOG_LEVELS.contains(&canonical.as_str()) {
+        Ok(canonical)
+    } else {
+        Err(format!(
+            "Invalid log level '{}': must be one of {}",
+            level,
+            SESSION_LOG_LEVELS.join(", ")
+        ))
+    }
+}
+
+/// Longest key accepted by `SetAgentOption`.
+pub const MAX_AGENT_OPTION_KEY_LEN: usize = 64;
+
+/// Longest value accepted by `SetAgentOption`.
+pub const MAX_AGENT_OPTION_VALUE_LEN: usize = 1024;
+
+/// Validate the shape of a `SetAgentOption` key and value.
+///
+/// Keys are lowercase ASCII letters, digits and underscores. Whether the
+/// agent knows the key is for the agent to say.
+pub fn validate_agent_option(key: &str, value: &str) -> Result<(), String> {
+    let valid_key = !key.is_empty()
+        && key.len() <= MAX_AGENT_OPTION_KEY_LEN
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_');
+    if !valid_key {
+        return Err(format!(
+            "Invalid agent option '{}': must be 1-{} lowercase letters, digits or underscores",
+            key, MAX_AGENT_OPTION_KEY_LEN
+        ));
+    }
+    if value.len() > MAX_AGENT_OPTION_VALUE_LEN {
+        return Err(format!(
+            "Value of agent option '{}' too long: {} bytes (max {})",
+            key,
+            value.len(),
+            MAX_AGENT_OPTION_VALUE_LEN
+        ));
+    }
+    Ok(())
+}
+
 /// IPC message wrapper (for framing)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -513,15 +1701,30 @@ mod tests {
         let req = IpcRequest::CreateSession {
             machine_id: "machine-1".to_string(),
             shell: Some("/bin/bash".to_string()),
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
         };
 
         let json = serde_json::to_string(&req).unwrap();
         assert!(json.contains("create_session"));
+        assert!(!json.contains("log_level"));
+        assert!(!json.contains("term"));
         assert!(json.contains("machine_id"));
 
         let decoded: IpcRequest = serde_json::from_str(&json).unwrap();
         match decoded {
-            IpcRequest::CreateSession { machine_id, shell } => {
+            IpcRequest::CreateSession {
+                machine_id, shell, ..
+            } => {
                 assert_eq!(machine_id, "machine-1");
                 assert_eq!(shell, Some("/bin/bash".to_string()));
             }
@@ -529,6 +1732,111 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_tail_logs_defaults() {
+        let req: IpcRequest = serde_json::from_str(r#"{"type":"tail_logs"}"#).unwrap();
+        assert!(matches!(
+            req,
+            IpcRequest::TailLogs {
+                follow: false,
+                lines: 0
+            }
+        ));
+
+        let resp = IpcResponse::LogLines {
+            lines: vec![LogLine {
+                timestamp_ms: 1,
+                level: "INFO".to_string(),
+                target: "kt_orchestrator".to_string(),
+                message: "started".to_string(),
+            }],
+        };
+        let json = serde_json::to_string(&resp).unwrap();
+        assert!(json.contains("\"timestampMs\":1"), "{}", json);
+    }
+
+    #[test]
+    fn test_get_status_pairing_code_is_opt_in() {
+        let request: IpcRequest = serde_json::from_str(r#"{"type":"get_status"}"#).unwrap();
+        assert!(matches!(
+            request,
+            IpcRequest::GetStatus {
+                include_pairing_code: false
+            }
+        ));
+
+        let request = IpcRequest::GetStatus {
+            include_pairing_code: true,
+        };
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(json, r#"{"type":"get_status","include_pairing_code":true}"#);
+    }
+
+    #[test]
+    fn test_get_state_snapshot_paging_is_optional() {
+        // Older clients send no paging fields and get everything
+        let request: IpcRequest = serde_json::from_str(r#"{"type":"get_state_snapshot"}"#).unwrap();
+        assert!(matches!(
+            request,
+            IpcRequest::GetStateSnapshot {
+                machine_filter: None,
+                include_sessions: true,
+                cursor: None,
+                limit: None,
+            }
+        ));
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(json, r#"{"type":"get_state_snapshot"}"#);
+
+        // Responses from older orchestrators have no cursor
+        let response: IpcResponse = serde_json::from_str(
+            r#"{"type":"state_snapshot","epoch_id":"e","current_seq":3,"machines":[],"sessions":[]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            response,
+            IpcResponse::StateSnapshot {
+                next_cursor: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_capabilities_wire_format() {
+        let request = serde_json::to_string(&IpcRequest::GetCapabilities).unwrap();
+        assert_eq!(request, r#"{"type":"get_capabilities"}"#);
+
+        let features: IpcFeatures = [IpcFeature::EventReplay, IpcFeature::LogTailing]
+            .into_iter()
+            .collect();
+        let response = IpcResponse::Capabilities(OrchestratorCapabilities::current(features));
+        let json = serde_json::to_string(&response).unwrap();
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"type":"capabilities","protocolVersion":{},"features":132}}"#,
+                IPC_PROTOCOL_VERSION
+            )
+        );
+
+        // Features from a newer orchestrator are kept but not reported
+        let parsed: IpcResponse = serde_json::from_str(
+            r#"{"type":"capabilities","protocolVersion":7,"features":9223372036854775812}"#,
+        )
+        .unwrap();
+        let IpcResponse::Capabilities(caps) = parsed else {
+            panic!("expected capabilities, got {:?}", parsed);
+        };
+        assert_eq!(caps.protocol_version, 7);
+        assert!(caps.supports(IpcFeature::EventReplay));
+        assert_eq!(caps.features.names(), vec!["event_replay"]);
+
+        assert!(IpcFeature::ALL
+            .into_iter()
+            .all(|f| !OrchestratorCapabilities::legacy().supports(f)));
+    }
+
     #[test]
     fn test_response_serialization() {
         let resp = IpcResponse::Status(OrchestratorStatus {
@@ -537,9 +1845,15 @@ mod tests {
             machine_count: 2,
             session_count: 5,
             version: "0.1.0".to_string(),
+            protocol_version: Some("1.0".to_string()),
             tailscale_hostname: Some("my-laptop.ts.net".to_string()),
-            bind_address: "0.0.0.0:2222".to_string(),
+            bind_address: "tailnet".to_string(),
+            listen_address: Some("100.64.1.50:2222".to_string()),
             pairing_code: Some("ABC123".to_string()),
+            owner: OrchestratorOwner::DesktopEmbedded,
+            output_coalescing: CoalescingStatus::default(),
+            input_breaker: InputBreakerStatus::default(),
+            maintenance: Some("Upgrading agents".to_string()),
         });
 
         let json = serde_json::to_string(&resp).unwrap();
@@ -548,8 +1862,11 @@ mod tests {
         match decoded {
             IpcResponse::Status(status) => {
                 assert!(status.running);
+                assert_eq!(status.maintenance.as_deref(), Some("Upgrading agents"));
                 assert_eq!(status.machine_count, 2);
                 assert_eq!(status.pairing_code, Some("ABC123".to_string()));
+                assert_eq!(status.listen_address.as_deref(), Some("100.64.1.50:2222"));
+                assert_eq!(status.owner, OrchestratorOwner::DesktopEmbedded);
             }
             _ => panic!("Wrong variant"),
         }
@@ -565,6 +1882,7 @@ mod tests {
             machines: vec![MachineInfo {
                 id: "test".to_string(),
                 alias: None,
+                label: None,
                 hostname: "test.local".to_string(),
                 os: "linux".to_string(),
                 arch: "x86_64".to_string(),
@@ -573,6 +1891,8 @@ mod tests {
                 last_heartbeat: None,
                 session_count: 0,
                 tags: vec![],
+                capabilities: vec![],
+                reconnect_count: 0,
             }],
         };
         let json2 = serde_json::to_string(&resp2);
@@ -589,9 +1909,423 @@ mod tests {
 
     #[test]
     fn test_sessions_response_serialization() {
-        let resp = IpcResponse::Sessions { sessions: vec![] };
+        let resp = IpcResponse::Sessions {
+            sessions: vec![],
+            total: None,
+            next_cursor: None,
+        };
         let json = serde_json::to_string(&resp);
         println!("Sessions empty: {:?}", json);
         assert!(json.is_ok(), "Empty sessions should serialize: {:?}", json);
     }
+
+    #[test]
+    fn test_sensitive_env_var_names() {
+        assert!(is_sensitive_env_var("GITHUB_TOKEN"));
+        assert!(is_sensitive_env_var("aws_secret_access_key"));
+        assert!(is_sensitive_env_var("API_SECRET"));
+        assert!(is_sensitive_env_var("PGPASSWORD"));
+        assert!(is_sensitive_env_var("DB_PASSWD"));
+        assert!(is_sensitive_env_var("TOKEN"));
+
+        assert!(!is_sensitive_env_var("PATH"));
+        assert!(!is_sensitive_env_var("TERM"));
+        assert!(!is_sensitive_env_var("KEYBOARD_LAYOUT"));
+        assert!(!is_sensitive_env_var("TOKENIZER"));
+    }
+
+    #[test]
+    fn test_session_env_var_redaction() {
+        let redacted = SessionEnvVar::new("GITHUB_TOKEN", "ghp_abc", true);
+        assert!(redacted.redacted);
+        assert_eq!(redacted.value, REDACTED_ENV_VALUE);
+
+        let shown = SessionEnvVar::new("GITHUB_TOKEN", "ghp_abc", false);
+        assert!(!shown.redacted);
+        assert_eq!(shown.value, "ghp_abc");
+
+        let plain = SessionEnvVar::new("TERM", "xterm-256color", true);
+        assert!(!plain.redacted);
+        assert_eq!(plain.value, "xterm-256color");
+    }
+
+    #[test]
+    fn test_get_session_env_defaults_to_redacted() {
+        let req: IpcRequest =
+            serde_json::from_str(r#"{"type":"get_session_env","session_id":"session-1"}"#).unwrap();
+        match req {
+            IpcRequest::GetSessionEnv {
+                session_id,
+                show_secrets,
+            } => {
+                assert_eq!(session_id, "session-1");
+                assert!(!show_secrets);
+            }
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    #[test]
+    fn test_session_closed_exit_code() {
+        let event = IpcEvent::SessionClosed {
+            session_id: "session-1".to_string(),
+            exit_code: Some(3),
+            reason: Some(CloseReason::ProcessExited { code: Some(3) }),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        match serde_json::from_str::<IpcEvent>(&json).unwrap() {
+            IpcEvent::SessionClosed { exit_code, .. } => assert_eq!(exit_code, Some(3)),
+            _ => panic!("Wrong variant"),
+        }
+
+        // Events from orchestrators that don't report exit codes still parse
+        let legacy: IpcEvent =
+            serde_json::from_str(r#"{"type":"session_closed","session_id":"session-1"}"#).unwrap();
+        match legacy {
+            IpcEvent::SessionClosed {
+                exit_code, reason, ..
+            } => {
+                assert!(exit_code.is_none());
+                assert!(reason.is_none());
+            }
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    #[test]
+    fn test_session_closed_reason_wire_format() {
+        let event = IpcEvent::SessionClosed {
+            session_id: "session-1".to_string(),
+            exit_code: None,
+            reason: Some(CloseReason::Error {
+                message: "PTY allocation failed".to_string(),
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "type": "session_closed",
+                "session_id": "session-1",
+                "reason": { "kind": "error", "message": "PTY allocation failed" },
+            })
+        );
+
+        let reason: CloseReason =
+            serde_json::from_str(r#"{"kind":"process_exited","code":1}"#).unwrap();
+        assert_eq!(reason, CloseReason::ProcessExited { code: Some(1) });
+        assert!(reason.is_abnormal());
+        assert!(!CloseReason::ProcessExited { code: Some(0) }.is_abnormal());
+        assert!(!CloseReason::UserRequested.is_abnormal());
+        assert!(CloseReason::OrphanTimeout.is_abnormal());
+        assert!(!CloseReason::OwnerDisconnected.is_abnormal());
+    }
+
+    #[test]
+    fn test_terminal_output_stream_wire_format() {
+        let event = |stream| IpcEvent::TerminalOutput {
+            session_id: "session-1".to_string(),
+            data: b"x".to_vec(),
+            stream,
+        };
+        let stdout = serde_json::to_value(event(OutputStream::Stdout)).unwrap();
+        assert!(stdout.get("stream").is_none(), "{}", stdout);
+        let stderr = serde_json::to_value(event(OutputStream::Stderr)).unwrap();
+        assert_eq!(stderr["stream"], "stderr");
+
+        let legacy: IpcEvent = serde_json::from_str(
+            r#"{"type":"terminal_output","session_id":"session-1","data":[120]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            legacy,
+            IpcEvent::TerminalOutput {
+                stream: OutputStream::Stdout,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_session_activity_wire_format() {
+        let event = IpcEvent::SessionActivity {
+            session_id: "session-1".to_string(),
+            kind: ActivityKind::Bell,
+        };
+        let value = serde_json::to_value(&event).unwrap();
+        assert_eq!(value["type"], "session_activity");
+        assert_eq!(value["kind"], "bell");
+
+        let request: IpcRequest =
+            serde_json::from_str(r#"{"type":"set_session_monitor","session_id":"session-1"}"#)
+                .unwrap();
+        assert!(matches!(
+            request,
+            IpcRequest::SetSessionMonitor {
+                activity: false,
+                silence_secs: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_create_session_legacy_payload() {
+        // Clients that predate cwd/env/name/size only send machine_id and shell
+        let req: IpcRequest = serde_json::from_str(
+            r#"{"type":"create_session","machine_id":"machine-1","shell":null}"#,
+        )
+        .unwrap();
+        match req {
+            IpcRequest::CreateSession {
+                machine_id,
+                shell,
+                cwd,
+                env,
+                name,
+                size,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
+                idempotency_key,
+                ttl_secs,
+                close_on_exit,
+            } => {
+                assert_eq!(machine_id, "machine-1");
+                assert!(shell.is_none());
+                assert!(cwd.is_none());
+                assert!(env.is_empty());
+                assert!(name.is_none());
+                assert!(size.is_none());
+                assert!(allocate_pty);
+                assert!(log_level.is_none());
+                assert!(term.is_none());
+                assert!(!truecolor);
+                assert!(idempotency_key.is_none());
+                assert!(ttl_secs.is_none());
+                assert!(close_on_exit.is_none());
+            }
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    #[test]
+    fn test_create_session_options_roundtrip() {
+        let req = IpcRequest::CreateSession {
+            machine_id: "machine-1".to_string(),
+            shell: Some("/bin/zsh".to_string()),
+            cwd: Some("/srv/app".to_string()),
+            env: vec![("RUST_LOG".to_string(), "debug".to_string())],
+            name: Some("build".to_string()),
+            size: Some(TerminalSize {
+                cols: 132,
+                rows: 43,
+            }),
+            allocate_pty: false,
+            log_level: Some("debug".to_string()),
+            term: Some("xterm-kitty".to_string()),
+            truecolor: true,
+            idempotency_key: Some("retry-1".to_string()),
+            ttl_secs: Some(600),
+            close_on_exit: Some(false),
+        };
+
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""allocate_pty":false"#), "{}", json);
+        let decoded: IpcRequest = serde_json::from_str(&json).unwrap();
+        match decoded {
+            IpcRequest::CreateSession {
+                cwd,
+                env,
+                name,
+                size,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
+                idempotency_key,
+                ttl_secs,
+                close_on_exit,
+                ..
+            } => {
+                assert!(!allocate_pty);
+                assert_eq!(log_level.as_deref(), Some("debug"));
+                assert_eq!(term.as_deref(), Some("xterm-kitty"));
+                assert!(truecolor);
+                assert_eq!(idempotency_key.as_deref(), Some("retry-1"));
+                assert_eq!(ttl_secs, Some(600));
+                assert_eq!(close_on_exit, Some(false));
+                assert_eq!(cwd.as_deref(), Some("/srv/app"));
+                assert_eq!(env, vec![("RUST_LOG".to_string(), "debug".to_string())]);
+                assert_eq!(name.as_deref(), Some("build"));
+                assert_eq!(
+                    size,
+                    Some(TerminalSize {
+                        cols: 132,
+                        rows: 43
+                    })
+                );
+            }
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    #[test]
+    fn test_validate_terminal_size() {
+        assert!(validate_terminal_size(80, 24).is_ok());
+        assert!(validate_terminal_size(MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE).is_ok());
+
+        let err = validate_terminal_size(0, 24).unwrap_err();
+        assert!(err.contains("columns"));
+        let err = validate_terminal_size(80, MAX_TERMINAL_SIZE + 1).unwrap_err();
+        assert!(err.contains("rows"));
+    }
+
+    #[test]
+    fn test_valid_env_var_names() {
+        // Valid names
+        assert!(is_valid_env_var_name("PATH"));
+        assert!(is_valid_env_var_name("_PATH"));
+        assert!(is_valid_env_var_name("my_var"));
+        assert!(is_valid_env_var_name("MY_VAR_123"));
+        assert!(is_valid_env_var_name("_"));
+        assert!(is_valid_env_var_name("_123"));
+        assert!(is_valid_env_var_name("a"));
+        assert!(is_valid_env_var_name("TERM"));
+        assert!(is_valid_env_var_name("HOME"));
+        assert!(is_valid_env_var_name("LD_LIBRARY_PATH"));
+    }
+
+    #[test]
+    fn test_invalid_env_var_names() {
+        // Invalid names - empty
+        assert!(!is_valid_env_var_name(""));
+
+        // Invalid names - starts with number
+        assert!(!is_valid_env_var_name("123"));
+        assert!(!is_valid_env_var_name("1PATH"));
+
+        // Invalid names - contains invalid characters
+        assert!(!is_valid_env_var_name("MY-VAR"));
+        assert!(!is_valid_env_var_name("MY.VAR"));
+        assert!(!is_valid_env_var_name("MY VAR"));
+        assert!(!is_valid_env_var_name("MY=VAR"));
+        assert!(!is_valid_env_var_name("MY$VAR"));
+        assert!(!is_valid_env_var_name("MY@VAR"));
+        assert!(!is_valid_env_var_name("$(whoami)"));
+        assert!(!is_valid_env_var_name("`command`"));
+
+        // Invalid names - starts with invalid characters
+        assert!(!is_valid_env_var_name("-PATH"));
+        assert!(!is_valid_env_var_name(".PATH"));
+        assert!(!is_valid_env_var_name(" PATH"));
+    }
+
+    #[test]
+    fn test_validate_env_vars() {
+        // Empty list is valid
+        assert!(validate_env_vars(&[]).is_ok());
+
+        // Valid variables
+        assert!(validate_env_vars(&[
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("TERM".to_string(), "xterm-256color".to_string()),
+            ("_CUSTOM_VAR".to_string(), "value".to_string()),
+        ])
+        .is_ok());
+
+        // Invalid variable name
+        let result = validate_env_vars(&[
+            ("PATH".to_string(), "/usr/bin".to_string()),
+            ("INVALID-VAR".to_string(), "value".to_string()),
+        ]);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().contains("INVALID-VAR"));
+
+        // Injection attempt
+        let result = validate_env_vars(&[("$(whoami)".to_string(), "value".to_string())]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_term() {
+        assert!(validate_term("xterm-256color").is_ok());
+        assert!(validate_term("screen.xterm-256color").is_ok());
+        assert!(validate_term("rxvt-unicode+x").is_ok());
+        assert!(validate_term("").is_err());
+        assert!(validate_term("xterm; rm -rf /").is_err());
+        assert!(validate_term("xterm\n").is_err());
+        assert!(validate_term(&"x".repeat(MAX_TERM_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_validate_session_ttl() {
+        assert!(validate_session_ttl(1).is_ok());
+        assert!(validate_session_ttl(MAX_SESSION_TTL_SECS).is_ok());
+        assert!(validate_session_ttl(0).is_err());
+        assert!(validate_session_ttl(MAX_SESSION_TTL_SECS + 1).is_err());
+    }
+
+    #[test]
+    fn test_validate_idempotency_key() {
+        assert!(validate_idempotency_key("7f3c9a10-retry").is_ok());
+        assert!(validate_idempotency_key("").is_err());
+        let too_long = "k".repeat(MAX_IDEMPOTENCY_KEY_LEN + 1);
+        assert!(validate_idempotency_key(&too_long).is_err());
+    }
+
+    #[test]
+    fn test_validate_session_log_level() {
+        assert_eq!(validate_session_log_level("debug"), Ok("debug".to_string()));
+        assert_eq!(validate_session_log_level("TRACE"), Ok("trace".to_string()));
+        assert!(validate_session_log_level("verbose").is_err());
+        assert!(validate_session_log_level("").is_err());
+    }
+
+    #[test]
+    fn test_validate_agent_option() {
+        assert!(validate_agent_option("metrics_interval", "30").is_ok());
+        assert!(validate_agent_option("output_rate_cap2", "").is_ok());
+        assert!(validate_agent_option("", "30").is_err());
+        assert!(validate_agent_option("Metrics-Interval", "30").is_err());
+        let too_long = "x".repeat(MAX_AGENT_OPTION_VALUE_LEN + 1);
+        assert!(validate_agent_option("metrics_interval", &too_long).is_err());
+    }
+
+    #[test]
+    fn test_terminal_size_constants() {
+        // Verify constants are reasonable (checked at compile time)
+        const _: () = assert!(MIN_TERMINAL_SIZE >= 1, "Min size should be at least 1");
+        const _: () = assert!(
+            MAX_TERMINAL_SIZE <= 10000,
+            "Max size should be reasonable (<=10000)"
+        );
+        const _: () = assert!(
+            MIN_TERMINAL_SIZE < MAX_TERMINAL_SIZE,
+            "Min should be less than max"
+        );
+    }
+
+    #[test]
+    fn test_terminal_size_typical_values() {
+        // Typical terminal sizes should be within bounds
+        let typical_cols = [80, 120, 132, 200];
+        let typical_rows = [24, 25, 40, 50, 80];
+
+        for cols in typical_cols {
+            assert!(
+                (MIN_TERMINAL_SIZE..=MAX_TERMINAL_SIZE).contains(&cols),
+                "Typical cols {} should be valid",
+                cols
+            );
+        }
+
+        for rows in typical_rows {
+            assert!(
+                (MIN_TERMINAL_SIZE..=MAX_TERMINAL_SIZE).contains(&rows),
+                "Typical rows {} should be valid",
+                rows
+            );
+        }
+    }
 }
@@ -21,14 +21,34 @@
 //!
 //! This prevents unauthorized local processes from controlling the orchestrator,
 //! even if they can connect to the IPC port.
+//!
+//! # Rotation
+//!
+//! A running orchestrator can replace its token (see [`rotate_token`]), either
+//! on request or when the token's `expires_at` passes. Each rotation bumps the
+//! token's `generation`. The file is replaced atomically, so clients never read
+//! a half-written token; clients re-read it when authentication fails.
+//!
+//! # Cleanup
+//!
+//! Orchestrators hold a [`TokenGuard`] that removes the token file when they
+//! shut down, as [`crate::PidFileGuard`] does for the PID file. A process
+//! that is killed or aborts never drops it; the next orchestrator to start
+//! then removes both files, as their owner is no longer alive (see
+//! [`crate::clean_stale_files`]).
 
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
-use crate::pidfile::is_process_alive;
+use crate::ipc::OrchestratorOwner;
+use crate::permissions::{create_private_dir_all, write_private_file};
+use crate::pidfile::{clean_stale_files, ProcessStamp, StartupLock};
+use crate::secret::Secret;
+use crate::time::current_time_secs;
 
 /// Length of the authentication token in bytes (before hex encoding)
 const TOKEN_BYTES: usize = 32;
@@ -43,20 +63,55 @@ const LEGACY_TOKEN_FILENAME: &str = "ipc_auth_token";
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenInfo {
     /// The authentication token (hex-encoded)
-    pub token: String,
+    pub token: Secret<String>,
     /// PID of the process that owns this token
     pub pid: u32,
+    /// Start time of the owning process (see [`ProcessStamp`]), so a new
+    /// process reusing its PID isn't taken for the owner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<u64>,
     /// IPC address the orchestrator is listening on
     pub address: String,
+    /// When the token expires, in seconds since the Unix epoch (None = never)
+    ///
+    /// The owning orchestrator rotates the token when it expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Rotation counter, starting at 1 for the token an orchestrator starts with
+    #[serde(default)]
+    pub generation: u64,
+    /// What kind of process runs the orchestrator
+    #[serde(default)]
+    pub owner: OrchestratorOwner,
+}
+
+impl TokenInfo {
+    /// The process that owns this token
+    pub fn owner_process(&self) -> ProcessStamp {
+        ProcessStamp {
+            pid: self.pid,
+            start_time: self.start_time,
+        }
+    }
+
+    /// Time left until the token expires (None if it never does)
+    pub fn expires_in(&self) -> Option<Duration> {
+        self.expires_at
+            .map(|at| Duration::from_secs(at.saturating_sub(current_time_secs())))
+    }
 }
 
 /// Result of attempting to acquire token ownership
 #[derive(Debug)]
 pub enum TokenOwnership {
     /// We acquired ownership - use this token (we wrote it)
-    Acquired { token: String },
+    Acquired { info: TokenInfo },
     /// Another live process owns the token - use theirs
-    External { token: String, pid: u32, address: String },
+    External {
+        token: Secret<String>,
+        pid: u32,
+        address: String,
+    },
 }
 
 /// Get the default path for the IPC authentication token file
@@ -100,19 +155,35 @@ pub fn generate_token() -> String {
 /// 2. If a token file exists but the owner is dead, take over ownership
 /// 3. If a token file exists and the owner is alive, return their token info
 ///
+/// A token file written by this very process (e.g. an embedded orchestrator
+/// that was restarted) counts as dead and is taken over.
+///
+/// This runs under the [`StartupLock`], after removing the token and PID
+/// files of a dead orchestrator (see [`clean_stale_files`]).
+///
 /// # Arguments
 /// * `address` - The IPC address this orchestrator will listen on
+/// * `lifetime` - How long the token is valid before it is rotated (None = forever)
+/// * `owner` - What kind of process runs this orchestrator
 ///
 /// # Returns
 /// * `TokenOwnership::Acquired` - We now own the token, use it
 /// * `TokenOwnership::External` - Another orchestrator owns it, connect to them
-pub fn acquire_token_ownership(address: &str) -> io::Result<TokenOwnership> {
+pub fn acquire_token_ownership(
+    address: &str,
+    lifetime: Option<Duration>,
+    owner: OrchestratorOwner,
+) -> io::Result<TokenOwnership> {
     let our_pid = std::process::id();
 
+    let lock = StartupLock::acquire_default()?;
+    clean_stale_files(&lock)?;
+
     // Try to read existing token info
     if let Some(info) = read_token_info()? {
         // Check if the owning process is still alive
-        if is_process_alive(info.pid) {
+        let owner = info.owner_process();
+        if !owner.is_current() && owner.is_alive() {
             // Another orchestrator is running - use their token
             tracing::info!(
                 "Found existing orchestrator (PID {}) at {}, using external mode",
@@ -139,18 +210,71 @@ pub fn acquire_token_ownership(address: &str) -> io::Result<TokenOwnership> {
     }
 
     // Generate new token and claim ownership
-    let token = generate_token();
-    let info = TokenInfo {
-        token: token.clone(),
-        pid: our_pid,
-        address: address.to_string(),
-    };
-
+    let info = new_token_info(address, lifetime, 1, owner);
     write_token_info(&info)?;
 
     tracing::info!("Acquired IPC token ownership (PID {})", our_pid);
 
-    Ok(TokenOwnership::Acquired { token })
+    Ok(TokenOwnership::Acquired { info })
+}
+
+/// Replace the token with a new one, keeping the recorded address.
+///
+/// Used by the owning orchestrator to rotate its token without restarting;
+/// it must keep accepting the old token for a while so connected clients
+/// have time to re-read the file. Fails if another live process owns the
+/// token, or if there is no token file to rotate.
+///
+/// # Arguments
+/// * `lifetime` - How long the new token is valid (None = forever)
+pub fn rotate_token(lifetime: Option<Duration>) -> io::Result<TokenInfo> {
+    rotate_token_at(&default_token_path()?, lifetime)
+}
+
+fn rotate_token_at(path: &Path, lifetime: Option<Duration>) -> io::Result<TokenInfo> {
+    let current = read_token_info_at(path)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No token file to rotate"))?;
+
+    let owner = current.owner_process();
+    if !owner.is_current() && owner.is_alive() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "Token is owned by another orchestrator (PID {})",
+                current.pid
+            ),
+        ));
+    }
+
+    let info = new_token_info(
+        &current.address,
+        lifetime,
+        current.generation + 1,
+        current.owner,
+    );
+    write_token_info_at(path, &info)?;
+
+    tracing::info!("Rotated IPC token (generation {})", info.generation);
+    Ok(info)
+}
+
+/// Token info for a freshly generated token owned by this process
+pub(crate) fn new_token_info(
+    address: &str,
+    lifetime: Option<Duration>,
+    generation: u64,
+    owner: OrchestratorOwner,
+) -> TokenInfo {
+    let stamp = ProcessStamp::current();
+    TokenInfo {
+        token: Secret::new(generate_token()),
+        pid: stamp.pid,
+        start_time: stamp.start_time,
+        address: address.to_string(),
+        expires_at: lifetime.map(|l| current_time_secs().saturating_add(l.as_secs())),
+        generation,
+        owner,
+    }
 }
 
 /// Read the full token info from the token file
@@ -158,9 +282,11 @@ pub fn acquire_token_ownership(address: &str) -> io::Result<TokenOwnership> {
 /// Returns `Ok(None)` if the file doesn't exist.
 /// Handles migration from legacy plain-text token files.
 pub fn read_token_info() -> io::Result<Option<TokenInfo>> {
-    let path = default_token_path()?;
+    read_token_info_at(&default_token_path()?)
+}
 
-    match fs::read_to_string(&path) {
+pub(crate) fn read_token_info_at(path: &Path) -> io::Result<Option<TokenInfo>> {
+    match fs::read_to_string(path) {
         Ok(contents) => {
             // Try to parse as JSON (new format)
             match serde_json::from_str::<TokenInfo>(&contents) {
@@ -192,28 +318,29 @@ pub fn read_token_info() -> io::Result<Option<TokenInfo>> {
 /// Sets file permissions to 0600 (owner read/write only) on Unix.
 fn write_token_info(info: &TokenInfo) -> io::Result<PathBuf> {
     let path = default_token_path()?;
+    write_token_info_at(&path, info)?;
+    Ok(path)
+}
 
+pub(crate) fn write_token_info_at(path: &Path, info: &TokenInfo) -> io::Result<()> {
     // Create parent directory if needed
     if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+        create_private_dir_all(parent)?;
     }
 
     // Serialize to JSON
     let json = serde_json::to_string_pretty(info)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
 
-    // Write to file
-    fs::write(&path, json)?;
-
-    // Set restrictive permissions on Unix
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let permissions = fs::Permissions::from_mode(0o600);
-        fs::set_permissions(&path, permissions)?;
+    // Write a restricted temporary file and rename it over the old one, so
+    // readers see either the old token or the new one, never a partial file
+    let tmp_path = path.with_extension(format!("json.{}.tmp", std::process::id()));
+    write_private_file(&tmp_path, json.as_bytes())?;
+    let result = fs::rename(&tmp_path, path);
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
     }
-
-    Ok(path)
+    result
 }
 
 /// Write an authentication token to the token file (legacy API)
@@ -226,10 +353,15 @@ fn write_token_info(info: &TokenInfo) -> io::Result<PathBuf> {
 /// Sets file permissions to 0600 (owner read/write only) on Unix.
 pub fn write_token(token: &str) -> io::Result<PathBuf> {
     // Convert to new format with current process info
+    let stamp = ProcessStamp::current();
     let info = TokenInfo {
-        token: token.to_string(),
-        pid: std::process::id(),
+        token: Secret::new(token.to_string()),
+        pid: stamp.pid,
+        start_time: stamp.start_time,
         address: format!("127.0.0.1:{}", crate::ipc::DEFAULT_IPC_PORT),
+        expires_at: None,
+        generation: 1,
+        owner: OrchestratorOwner::Standalone,
     };
     write_token_info(&info)
 }
@@ -241,7 +373,7 @@ pub fn write_token(token: &str) -> io::Result<PathBuf> {
 pub fn read_token() -> io::Result<String> {
     // First try the new JSON format
     if let Some(info) = read_token_info()? {
-        return Ok(info.token);
+        return Ok(info.token.expose().clone());
     }
 
     // Fall back to legacy format
@@ -265,11 +397,14 @@ pub fn read_token() -> io::Result<String> {
 /// orchestrator's token).
 /// Ignores errors if the file doesn't exist.
 pub fn remove_token() -> io::Result<()> {
-    let path = default_token_path()?;
+    remove_token_at(&default_token_path()?)
+}
+
+fn remove_token_at(path: &Path) -> io::Result<()> {
     let our_pid = std::process::id();
 
     // Check if we own the token before removing
-    if let Some(info) = read_token_info()? {
+    if let Some(info) = read_token_info_at(path)? {
         if info.pid != our_pid {
             tracing::debug!(
                 "Not removing token file - owned by PID {}, we are PID {}",
@@ -280,7 +415,7 @@ pub fn remove_token() -> io::Result<()> {
         }
     }
 
-    match fs::remove_file(&path) {
+    match fs::remove_file(path) {
         Ok(()) => {
             tracing::debug!("Removed token file");
             Ok(())
@@ -290,6 +425,34 @@ pub fn remove_token() -> io::Result<()> {
     }
 }
 
+/// Guard that removes the token file when dropped, if this process still
+/// owns it
+///
+/// Useful for ensuring the token file is cleaned up even on panic.
+pub struct TokenGuard {
+    path: PathBuf,
+}
+
+impl TokenGuard {
+    /// Create a guard for the token file at `path`
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Create a guard for the default path
+    pub fn for_default_path() -> io::Result<Self> {
+        Ok(Self::new(default_token_path()?))
+    }
+}
+
+impl Drop for TokenGuard {
+    fn drop(&mut self) {
+        if let Err(e) = remove_token_at(&self.path) {
+            tracing::warn!("Failed to remove token file {:?}: {}", self.path, e);
+        }
+    }
+}
+
 /// Check if a token file exists
 pub fn token_exists() -> bool {
     default_token_path()
@@ -300,18 +463,20 @@ pub fn token_exists() -> bool {
 /// Validate a token against the stored token
 ///
 /// Returns true if the provided token matches the stored token.
-/// Uses constant-time comparison to prevent timing attacks.
+/// Uses constant-time comparison to prevent timing attacks: the time taken
+/// depends only on the length of `expected`, not on where (or whether) the
+/// tokens differ, including when their lengths differ.
 pub fn validate_token(provided: &str, expected: &str) -> bool {
-    // Use constant-time comparison to prevent timing attacks
-    if provided.len() != expected.len() {
-        return false;
+    let provided = provided.as_bytes();
+    let expected = expected.as_bytes();
+
+    // Fold the length difference in rather than returning early
+    let mut result = provided.len() ^ expected.len();
+    for (i, b) in expected.iter().enumerate() {
+        let a = provided.get(i).copied().unwrap_or(0);
+        result |= usize::from(a ^ b);
     }
-
-    let mut result = 0u8;
-    for (a, b) in provided.bytes().zip(expected.bytes()) {
-        result |= a ^ b;
-    }
-    result == 0
+    std::hint::black_box(result) == 0
 }
 
 #[cfg(test)]
@@ -348,14 +513,22 @@ mod tests {
         let short = "abc";
         let long = "abcdef";
         assert!(!validate_token(short, long));
+        assert!(!validate_token(long, short));
+        assert!(!validate_token("", short));
+        // A prefix padded with NULs must not match
+        assert!(!validate_token("abc\0\0\0", long));
     }
 
     #[test]
     fn test_token_info_serialization() {
         let info = TokenInfo {
-            token: "abc123".to_string(),
+            token: Secret::new("abc123".to_string()),
             pid: 12345,
+            start_time: Some(42),
             address: "127.0.0.1:22230".to_string(),
+            expires_at: Some(1_700_000_000),
+            generation: 3,
+            owner: OrchestratorOwner::DesktopEmbedded,
         };
 
         let json = serde_json::to_string(&info).expect("Failed to serialize");
@@ -363,7 +536,93 @@ mod tests {
 
         assert_eq!(parsed.token, info.token);
         assert_eq!(parsed.pid, info.pid);
+        assert_eq!(parsed.start_time, info.start_time);
         assert_eq!(parsed.address, info.address);
+        assert_eq!(parsed.expires_at, info.expires_at);
+        assert_eq!(parsed.generation, info.generation);
+        assert_eq!(parsed.owner, OrchestratorOwner::DesktopEmbedded);
+        assert!(json.contains(r#""owner":"desktop-embedded""#), "{}", json);
+        assert!(!format!("{:?}", info).contains("abc123"));
+    }
+
+    #[test]
+    fn test_token_info_without_rotation_fields() {
+        // Files written before rotation existed
+        let json = r#"{"token":"abc123","pid":12345,"address":"127.0.0.1:22230"}"#;
+        let parsed: TokenInfo = serde_json::from_str(json).expect("Failed to deserialize");
+        assert_eq!(parsed.expires_at, None);
+        assert_eq!(parsed.generation, 0);
+        assert_eq!(parsed.expires_in(), None);
+        assert_eq!(parsed.owner, OrchestratorOwner::Standalone);
+    }
+
+    #[test]
+    fn test_rotate_token() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join(TOKEN_FILENAME);
+        assert_eq!(
+            rotate_token_at(&path, None).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        let first = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
+        write_token_info_at(&path, &first).unwrap();
+
+        let rotated = rotate_token_at(&path, Some(Duration::from_secs(3600))).unwrap();
+        assert_ne!(rotated.token, first.token);
+        assert_eq!(rotated.generation, 2);
+        assert_eq!(rotated.address, first.address);
+        let expires_in = rotated.expires_in().unwrap();
+        assert!(expires_in > Duration::from_secs(3590) && expires_in <= Duration::from_secs(3600));
+
+        let on_disk = read_token_info_at(&path).unwrap().unwrap();
+        assert_eq!(on_disk.token, rotated.token);
+        assert_eq!(on_disk.generation, 2);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rotate_token_refuses_live_owner() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join(TOKEN_FILENAME);
+
+        // PID 1 is always alive and never us
+        let mut info = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
+        info.pid = 1;
+        info.start_time = ProcessStamp::of(1).start_time;
+        write_token_info_at(&path, &info).unwrap();
+
+        assert_eq!(
+            rotate_token_at(&path, None).unwrap_err().kind(),
+            io::ErrorKind::PermissionDenied
+        );
+        assert_eq!(
+            read_token_info_at(&path).unwrap().unwrap().token,
+            info.token
+        );
+    }
+
+    #[test]
+    fn test_token_guard_removes_only_own_token() {
+        let dir = tempdir().expect("Failed to create temp dir");
+        let path = dir.path().join(TOKEN_FILENAME);
+
+        let info = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
+        write_token_info_at(&path, &info).unwrap();
+        drop(TokenGuard::new(path.clone()));
+        assert!(!path.exists());
+
+        // Taken over by another orchestrator in the meantime
+        let mut info = info;
+        info.pid = 1;
+        write_token_info_at(&path, &info).unwrap();
+        drop(TokenGuard::new(path.clone()));
+        assert!(path.exists());
+
+        // Already gone
+        fs::remove_file(&path).unwrap();
+        drop(TokenGuard::new(path));
     }
 
     #[test]
@@ -371,11 +630,7 @@ mod tests {
         let dir = tempdir().expect("Failed to create temp dir");
         let path = dir.path().join("test_token.json");
 
-        let info = TokenInfo {
-            token: generate_token(),
-            pid: std::process::id(),
-            address: "127.0.0.1:22230".to_string(),
-        };
+        let info = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
 
         // Write token info
         let json = serde_json::to_string_pretty(&info).expect("Failed to serialize");
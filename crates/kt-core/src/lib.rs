@@ -3,33 +3,46 @@
 //! This crate provides shared types, traits, and configuration structures
 //! used by the orchestrator, agent, and CLI components.
 
+pub mod ansi;
 pub mod config;
 pub mod error;
 pub mod ipc;
 pub mod ipc_auth;
+pub mod net;
+pub mod permissions;
 pub mod pidfile;
+pub mod secret;
 pub mod setup;
 pub mod tailscale;
 pub mod time;
 pub mod traits;
 pub mod types;
 
-pub use error::KtError;
+pub use error::{BindError, KtError, MachineIdError};
 pub use ipc::{
-    default_ipc_address, is_orchestrator_running, try_ipc_ping, try_ipc_ping_with_timeout,
-    IpcEvent, IpcMessage, IpcRequest, IpcResponse, MachineInfo, MachineStatus, OrchestratorStatus,
-    SessionInfo, TerminalSize, DEFAULT_IPC_PORT,
+    client_instance_id, default_ipc_address, is_orchestrator_running, is_sensitive_env_var,
+    is_valid_env_var_name, try_ipc_ping, try_ipc_ping_with_timeout, validate_agent_option,
+    validate_env_vars, validate_idempotency_key, validate_session_log_level, validate_session_ttl,
+    validate_term, validate_terminal_size, ActivityKind, CloseReason, CoalescingStatus,
+    GroupAction, GroupInfo, InputBreakerStatus, IpcEvent, IpcFeature, IpcFeatures, IpcMessage,
+    IpcRequest, IpcResponse, LogLine, MachineInfo, MachineStatus, OrchestratorCapabilities,
+    OrchestratorOwner, OrchestratorStatus, OutputStream, RateLimitKind, RejectionInfo,
+    SessionDetails, SessionEnvVar, SessionInfo, SessionLifecycle, TerminalSize, DEFAULT_IPC_PORT,
+    IPC_PROTOCOL_VERSION, MAX_IDEMPOTENCY_KEY_LEN, MAX_TAIL_LOG_LINES, MAX_TERMINAL_SIZE,
+    MAX_TERM_LEN, MIN_TERMINAL_SIZE,
 };
 pub use ipc_auth::{
     acquire_token_ownership, default_token_path, generate_token as generate_ipc_token,
     read_token as read_ipc_token, read_token_info, remove_token as remove_ipc_token,
-    token_exists as ipc_token_exists, validate_token as validate_ipc_token,
-    write_token as write_ipc_token, TokenInfo, TokenOwnership,
+    rotate_token as rotate_ipc_token, token_exists as ipc_token_exists,
+    validate_token as validate_ipc_token, write_token as write_ipc_token, TokenGuard, TokenInfo,
+    TokenOwnership,
 };
 pub use pidfile::{
-    default_pid_path, is_process_alive, read_pid_file, remove_pid_file, write_pid_file,
-    PidFileGuard,
+    clean_stale_files, default_pid_path, find_stale_files, is_process_alive, read_pid_file,
+    remove_pid_file, write_pid_file, PidFileGuard, ProcessStamp, StaleFile, StartupLock,
 };
+pub use secret::{fingerprint, Secret};
 pub use setup::{auto_setup, is_initialized, SetupResult};
 pub use tailscale::TailscaleInfo;
-pub use types::{Capability, MachineId};
+pub use types::{Capability, MachineId, MAX_MACHINE_ID_LEN};
@@ -0,0 +1,553 @@
+//! Network interfaces and SSH bind address resolution
+//!
+//! `orchestrator.bind_address` is usually a socket address such as
+//! `127.0.0.1:2222`, but it can also say where to listen without naming an
+//! IP, so the SSH server never ends up on a public interface by accident:
+//!
+//! - `tailnet` (or `tailnet:2222`): this machine's Tailscale IP
+//! - an interface name such as `tailscale0` (or `tailscale0:2222`): that
+//!   interface's address
+//!
+//! [`resolve_bind_address`] turns these into a concrete address. The
+//! orchestrator resolves at startup and again whenever it checks whether the
+//! Tailscale IP has changed, e.g. after tailscaled restarts.
+//!
+//! Agents are given the orchestrator as a device name, hostname or IP
+//! literal, with or without a port; [`orchestrator_dial_address`] turns that
+//! into the `host:port` they dial. IPv6 literals are bracketed, so a bare
+//! `fd7a::1` isn't mistaken for a host with a port.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use crate::config::BindFallback;
+use crate::error::BindError;
+use crate::tailscale::TailscaleClient;
+
+/// SSH port used when a tailnet or interface bind address has none
+pub const DEFAULT_SSH_PORT: u16 = 2222;
+
+/// Bind address shorthand for this machine's Tailscale IP
+pub const TAILNET_BIND: &str = "tailnet";
+
+/// Interface name prefix used by Tailscale on Linux and the BSDs
+const TAILSCALE_INTERFACE_PREFIX: &str = "tailscale";
+
+/// What `bind_address` asks the SSH server to listen on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindTarget {
+    /// A socket address or `host:port`, bound as given
+    Address(String),
+    /// This machine's Tailscale IP
+    Tailnet {
+        /// Port to listen on
+        port: u16,
+    },
+    /// The address of a network interface
+    Interface {
+        /// Interface name, e.g. `tailscale0`
+        name: String,
+        /// Port to listen on
+        port: u16,
+    },
+}
+
+impl BindTarget {
+    /// Parse a `bind_address` value.
+    ///
+    /// A host that isn't an IP address, `localhost` or a dotted hostname is
+    /// taken to be an interface name.
+    pub fn parse(bind_address: &str) -> Self {
+        let bind_address = bind_address.trim();
+        if bind_address.parse::<SocketAddr>().is_ok() {
+            return Self::Address(bind_address.to_string());
+        }
+
+        let (host, port) = match bind_address.rsplit_once(':') {
+            Some((host, port)) => match port.parse() {
+                Ok(port) => (host, port),
+                Err(_) => return Self::Address(bind_address.to_string()),
+            },
+            None => (bind_address, DEFAULT_SSH_PORT),
+        };
+
+        if host.eq_ignore_ascii_case(TAILNET_BIND) {
+            Self::Tailnet { port }
+        } else if is_interface_name(host) {
+            Self::Interface {
+                name: host.to_string(),
+                port,
+            }
+        } else {
+            Self::Address(bind_address.to_string())
+        }
+    }
+
+    /// Whether the address can change while the orchestrator runs and
+    /// should be re-resolved now and then
+    pub fn is_dynamic(&self) -> bool {
+        !matches!(self, Self::Address(_))
+    }
+
+    fn port(&self) -> Option<u16> {
+        match self {
+            Self::Address(_) => None,
+            Self::Tailnet { port } | Self::Interface { port, .. } => Some(*port),
+        }
+    }
+}
+
+fn is_interface_name(host: &str) -> bool {
+    !host.is_empty()
+        && !host.eq_ignore_ascii_case("localhost")
+        && host.parse::<IpAddr>().is_err()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// An address assigned to a network interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetInterface {
+    /// Interface name, e.g. `tailscale0` or `en0`
+    pub name: String,
+    /// One of the interface's addresses
+    pub ip: IpAddr,
+}
+
+/// A bind address ready to pass to the SSH listener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedBind {
+    /// Address to bind
+    pub address: String,
+    /// Why the loopback fallback is used instead of what the config asked
+    /// for, if it is
+    pub fallback_reason: Option<BindError>,
+}
+
+/// Resolve a bind target against the Tailscale IP and the local interfaces.
+///
+/// An empty `interfaces` list means they couldn't be listed, in which case
+/// the Tailscale IP is trusted as is. When the target can't be resolved,
+/// `fallback` decides between an error and listening on loopback.
+pub fn resolve_bind_address(
+    target: &BindTarget,
+    fallback: BindFallback,
+    tailscale_ip: Option<IpAddr>,
+    interfaces: &[NetInterface],
+) -> Result<ResolvedBind, BindError> {
+    let resolved = match target {
+        BindTarget::Address(address) => {
+            return Ok(ResolvedBind {
+                address: address.clone(),
+                fallback_reason: None,
+            })
+        }
+        BindTarget::Tailnet { .. } => resolve_tailnet(tailscale_ip, interfaces),
+        BindTarget::Interface { name, .. } => resolve_interface(name, interfaces),
+    };
+    let port = target.port().unwrap_or(DEFAULT_SSH_PORT);
+
+    match (resolved, fallback) {
+        (Ok(ip), _) => Ok(ResolvedBind {
+            address: SocketAddr::new(ip, port).to_string(),
+            fallback_reason: None,
+        }),
+        (Err(e), BindFallback::Loopback) => Ok(ResolvedBind {
+            address: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port).to_string(),
+            fallback_reason: Some(e),
+        }),
+        (Err(e), BindFallback::None) => Err(e),
+    }
+}
+
+/// Resolve `bind_address` using the current Tailscale status and interfaces
+pub async fn resolve_bind_address_now(
+    bind_address: &str,
+    fallback: BindFallback,
+) -> Result<ResolvedBind, BindError> {
+    let target = BindTarget::parse(bind_address);
+    if !target.is_dynamic() {
+        return resolve_bind_address(&target, fallback, None, &[]);
+    }
+
+    let tailscale_ip = match &target {
+        BindTarget::Tailnet { .. } => {
+            // Ask again rather than trusting a status cached before a restart
+            let client = TailscaleClient::shared();
+            client.invalidate();
+            match client.info().await {
+                Ok(info) => info.and_then(|info| info.ip.parse().ok()),
+                Err(e) => {
+                    tracing::debug!("Failed to get Tailscale status: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    let interfaces = list_interfaces().unwrap_or_else(|e| {
+        tracing::debug!("Failed to list network interfaces: {}", e);
+        Vec::new()
+    });
+    resolve_bind_address(&target, fallback, tailscale_ip, &interfaces)
+}
+
+fn resolve_tailnet(
+    tailscale_ip: Option<IpAddr>,
+    interfaces: &[NetInterface],
+) -> Result<IpAddr, BindError> {
+    match tailscale_ip {
+        Some(ip) if interfaces.is_empty() || interfaces.iter().any(|i| i.ip == ip) => Ok(ip),
+        Some(ip) => Err(BindError::TailscaleIpNotLocal(ip)),
+        // The CLI may be missing while the interface is up
+        None => interfaces
+            .iter()
+            .find(|i| i.name.starts_with(TAILSCALE_INTERFACE_PREFIX) && is_tailscale_ip(&i.ip))
+            .map(|i| i.ip)
+            .ok_or(BindError::TailscaleDown),
+    }
+}
+
+fn resolve_interface(name: &str, interfaces: &[NetInterface]) -> Result<IpAddr, BindError> {
+    let usable: Vec<IpAddr> = interfaces
+        .iter()
+        .filter(|i| i.name == name && !is_link_local(&i.ip))
+        .map(|i| i.ip)
+        .collect();
+    if let Some(ip) = usable.iter().find(|ip| ip.is_ipv4()).or(usable.first()) {
+        return Ok(*ip);
+    }
+
+    // tailscaled removes its interface when it stops
+    if name.starts_with(TAILSCALE_INTERFACE_PREFIX) {
+        return Err(BindError::TailscaleDown);
+    }
+    let mut available: Vec<&str> = interfaces.iter().map(|i| i.name.as_str()).collect();
+    available.dedup();
+    Err(BindError::NoSuchInterface {
+        name: name.to_string(),
+        available: if available.is_empty() {
+            "none found".to_string()
+        } else {
+            available.join(", ")
+        },
+    })
+}
+
+/// Whether `ip` is in the range Tailscale assigns IPv4 addresses from
+/// (100.64.0.0/10)
+fn is_tailscale_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            a == 100 && (64..128).contains(&b)
+        }
+        IpAddr::V6(_) => false,
+    }
+}
+
+/// Link-local IPv6 addresses need a scope ID to bind, which a socket address
+/// string can't carry portably
+fn is_link_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(_) => false,
+        IpAddr::V6(ip) => (ip.segments()[0] & 0xffc0) == 0xfe80,
+    }
+}
+
+/// `address` with [`DEFAULT_SSH_PORT`] added if it names no port
+///
+/// Bare IPv6 literals (`fd7a::1`) always get the default port, and are
+/// bracketed to carry it; bracketed ones (`[fd7a::1]:2200`) keep theirs.
+/// Hostnames are left as given.
+pub fn with_default_port(address: &str) -> String {
+    let address = address.trim();
+    if address.parse::<SocketAddr>().is_ok() {
+        return address.to_string();
+    }
+    let literal = address
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(address);
+    if let Ok(ip) = literal.parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_SSH_PORT).to_string();
+    }
+    match address.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+            address.to_string()
+        }
+        _ => format!("{}:{}", address, DEFAULT_SSH_PORT),
+    }
+}
+
+/// Address an agent dials for an orchestrator given as `name`
+///
+/// With Tailscale, `own_tailnet` is appended to bare device names as in
+/// [`resolve_device_name`](crate::tailscale::resolve_device_name); without
+/// (None) `name` is taken as given. The default port is added if `name` has
+/// none.
+pub fn orchestrator_dial_address(name: &str, own_tailnet: Option<&str>) -> String {
+    match own_tailnet {
+        Some(tailnet) => with_default_port(&crate::tailscale::resolve_device_name(name, tailnet)),
+        None => with_default_port(name),
+    }
+}
+
+/// Whether `ip` is this machine: a loopback or unspecified address, or one
+/// of `own` (its interfaces' addresses, including its Tailscale IP)
+pub fn is_own_address(ip: IpAddr, own: &[IpAddr]) -> bool {
+    let ip = ip.to_canonical();
+    ip.is_loopback() || ip.is_unspecified() || own.contains(&ip)
+}
+
+/// Addresses of this machine's network interfaces
+#[cfg(unix)]
+pub fn list_interfaces() -> io::Result<Vec<NetInterface>> {
+    use std::ffi::CStr;
+    use std::net::Ipv6Addr;
+
+    let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: on success getifaddrs points `addrs` at a list that is freed
+    // below and not used afterwards
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut interfaces = Vec::new();
+    let mut cursor = addrs;
+    while !cursor.is_null() {
+        // SAFETY: `cursor` is a node of the list from getifaddrs, whose
+        // address (when present) matches its `sa_family`
+        unsafe {
+            let ifa = &*cursor;
+            cursor = ifa.ifa_next;
+            if ifa.ifa_addr.is_null() || ifa.ifa_name.is_null() {
+                continue;
+            }
+            let ip = match i32::from((*ifa.ifa_addr).sa_family) {
+                libc::AF_INET => {
+                    let sin = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+                    IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr)))
+                }
+                libc::AF_INET6 => {
+                    let sin6 = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr))
+                }
+                _ => continue,
+            };
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned();
+            interfaces.push(NetInterface { name, ip });
+        }
+    }
+
+    // SAFETY: `addrs` came from getifaddrs and nothing borrows from it now
+    unsafe { libc::freeifaddrs(addrs) };
+    Ok(interfaces)
+}
+
+/// Addresses of this machine's network interfaces
+#[cfg(not(unix))]
+pub fn list_interfaces() -> io::Result<Vec<NetInterface>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "listing network interfaces is not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn interface(name: &str, ip: &str) -> NetInterface {
+        NetInterface {
+            name: name.to_string(),
+            ip: ip.parse().unwrap(),
+        }
+    }
+
+    fn interfaces() -> Vec<NetInterface> {
+        vec![
+            interface("lo", "127.0.0.1"),
+            interface("eth0", "203.0.113.7"),
+            interface("eth0", "fe80::1"),
+            interface("tailscale0", "fd7a:115c:a1e0::1"),
+            interface("tailscale0", "100.101.102.103"),
+        ]
+    }
+
+    #[test]
+    fn test_parse_bind_target() {
+        assert_eq!(
+            BindTarget::parse("0.0.0.0:2222"),
+            BindTarget::Address("0.0.0.0:2222".to_string())
+        );
+        assert_eq!(
+            BindTarget::parse("[::1]:2222"),
+            BindTarget::Address("[::1]:2222".to_string())
+        );
+        assert_eq!(
+            BindTarget::parse("localhost:2222"),
+            BindTarget::Address("localhost:2222".to_string())
+        );
+        assert_eq!(
+            BindTarget::parse("pi.local:2222"),
+            BindTarget::Address("pi.local:2222".to_string())
+        );
+        assert_eq!(
+            BindTarget::parse("tailnet"),
+            BindTarget::Tailnet {
+                port: DEFAULT_SSH_PORT
+            }
+        );
+        assert_eq!(
+            BindTarget::parse("tailnet:3333"),
+            BindTarget::Tailnet { port: 3333 }
+        );
+        assert_eq!(
+            BindTarget::parse("tailscale0:2222"),
+            BindTarget::Interface {
+                name: "tailscale0".to_string(),
+                port: 2222
+            }
+        );
+        assert!(!BindTarget::parse("127.0.0.1:2222").is_dynamic());
+        assert!(BindTarget::parse("utun4").is_dynamic());
+    }
+
+    #[test]
+    fn test_resolve_tailnet() {
+        let target = BindTarget::Tailnet { port: 2222 };
+        let tailscale_ip: IpAddr = "100.101.102.103".parse().unwrap();
+        let ip = Some(tailscale_ip);
+
+        let resolved = resolve_bind_address(&target, BindFallback::None, ip, &interfaces());
+        assert_eq!(resolved.unwrap().address, "100.101.102.103:2222");
+
+        // Without the CLI, the tailscale interface is used
+        let resolved = resolve_bind_address(&target, BindFallback::None, None, &interfaces());
+        assert_eq!(resolved.unwrap().address, "100.101.102.103:2222");
+
+        // If interfaces can't be listed, the Tailscale IP is trusted
+        let resolved = resolve_bind_address(&target, BindFallback::None, ip, &[]);
+        assert_eq!(resolved.unwrap().address, "100.101.102.103:2222");
+
+        // Userspace networking: the IP isn't on any interface
+        let local = vec![interface("eth0", "203.0.113.7")];
+        assert_eq!(
+            resolve_bind_address(&target, BindFallback::None, ip, &local),
+            Err(BindError::TailscaleIpNotLocal(tailscale_ip))
+        );
+    }
+
+    #[test]
+    fn test_tailscale_down_fails_or_falls_back() {
+        let target = BindTarget::Tailnet { port: 2222 };
+        let local = vec![interface("eth0", "203.0.113.7")];
+
+        assert_eq!(
+            resolve_bind_address(&target, BindFallback::None, None, &local),
+            Err(BindError::TailscaleDown)
+        );
+        assert_eq!(
+            resolve_bind_address(&target, BindFallback::Loopback, None, &local),
+            Ok(ResolvedBind {
+                address: "127.0.0.1:2222".to_string(),
+                fallback_reason: Some(BindError::TailscaleDown),
+            })
+        );
+
+        let target = BindTarget::parse("tailscale0");
+        assert_eq!(
+            resolve_bind_address(&target, BindFallback::None, None, &local),
+            Err(BindError::TailscaleDown)
+        );
+    }
+
+    #[test]
+    fn test_resolve_interface() {
+        // IPv4 is preferred, link-local IPv6 skipped
+        let target = BindTarget::parse("eth0:2200");
+        let resolved = resolve_bind_address(&target, BindFallback::None, None, &interfaces());
+        assert_eq!(resolved.unwrap().address, "203.0.113.7:2200");
+
+        let v6_only = vec![interface("wg0", "fe80::2"), interface("wg0", "fd00::2")];
+        let target = BindTarget::parse("wg0");
+        let resolved = resolve_bind_address(&target, BindFallback::None, None, &v6_only);
+        assert_eq!(resolved.unwrap().address, "[fd00::2]:2222");
+
+        let target = BindTarget::parse("wlan0");
+        let Err(BindError::NoSuchInterface { name, available }) =
+            resolve_bind_address(&target, BindFallback::None, None, &interfaces())
+        else {
+            panic!("Expected NoSuchInterface");
+        };
+        assert_eq!(name, "wlan0");
+        assert_eq!(available, "lo, eth0, tailscale0");
+    }
+
+    #[test]
+    fn test_address_passes_through() {
+        let target = BindTarget::parse("0.0.0.0:2222");
+        assert_eq!(
+            resolve_bind_address(&target, BindFallback::None, None, &[]),
+            Ok(ResolvedBind {
+                address: "0.0.0.0:2222".to_string(),
+                fallback_reason: None,
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_interfaces_includes_loopback() {
+        let interfaces = list_interfaces().unwrap();
+        assert!(interfaces.iter().any(|i| i.ip.is_loopback()));
+    }
+
+    #[test]
+    fn test_orchestrator_dial_address() {
+        let tailnet = Some("tailnet-abc.ts.net");
+        for (name, tailnet, expected) in [
+            ("fd7a:115c::1234", tailnet, "[fd7a:115c::1234]:2222"),
+            ("fd7a:115c::1234", None, "[fd7a:115c::1234]:2222"),
+            ("[fd7a::1]", None, "[fd7a::1]:2222"),
+            ("[fd7a::1]:2200", tailnet, "[fd7a::1]:2200"),
+            ("100.64.0.1", tailnet, "100.64.0.1:2222"),
+            ("100.64.0.1:2200", None, "100.64.0.1:2200"),
+            ("my-laptop", tailnet, "my-laptop.tailnet-abc.ts.net:2222"),
+            ("my-laptop", None, "my-laptop:2222"),
+            (
+                "my-laptop:2200",
+                tailnet,
+                "my-laptop.tailnet-abc.ts.net:2200",
+            ),
+            ("box.example.com:2200", None, "box.example.com:2200"),
+            ("localhost", None, "localhost:2222"),
+        ] {
+            assert_eq!(
+                orchestrator_dial_address(name, tailnet),
+                expected,
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_own_address() {
+        let own: Vec<IpAddr> = interfaces().into_iter().map(|i| i.ip).collect();
+        for ip in [
+            "127.0.0.1",
+            "::1",
+            "0.0.0.0",
+            "::ffff:127.0.0.1",
+            "100.101.102.103",
+        ] {
+            assert!(is_own_address(ip.parse().unwrap(), &own), "{}", ip);
+        }
+        for ip in ["100.101.102.104", "198.51.100.1"] {
+            assert!(!is_own_address(ip.parse().unwrap(), &own), "{}", ip);
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Permissions for secret files
+//!
+//! The IPC token, host key and agent key grant control over the orchestrator
+//! and its machines, so they must only be readable by their owner. New files
+//! are created with mode 0600 (directories 0700) rather than relying on the
+//! umask, and [`check_permissions`] finds and tightens existing files that are
+//! more permissive, e.g. because an older version wrote them.
+//!
+//! Windows has no mode bits; there we only check that the files live under
+//! the user's profile, whose ACLs already restrict access to the user.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::config::default_config_dir;
+use crate::ipc_auth::default_token_path;
+
+/// Mode for secret files (owner read/write only)
+pub const PRIVATE_FILE_MODE: u32 = 0o600;
+
+/// Mode for directories holding secret files (owner only)
+pub const PRIVATE_DIR_MODE: u32 = 0o700;
+
+/// Whether a checked path should be a file or a directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathKind {
+    File,
+    Directory,
+}
+
+/// A problem found with a secret path
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionProblem {
+    /// Group or other users had access (Unix mode bits)
+    TooPermissive {
+        /// Mode found
+        mode: u32,
+        /// Mode it was (or should be) tightened to
+        restricted: u32,
+    },
+    /// The file is outside the user's profile directory (Windows)
+    OutsideUserProfile,
+}
+
+/// What was done about a problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Repair {
+    /// The permissions were tightened
+    Fixed,
+    /// Tightening the permissions failed
+    Failed(String),
+    /// The problem can't be repaired automatically
+    Manual,
+}
+
+/// A secret path with a permission problem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PermissionFinding {
+    pub path: PathBuf,
+    pub problem: PermissionProblem,
+    pub repair: Repair,
+}
+
+impl fmt::Display for PermissionFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.problem {
+            PermissionProblem::TooPermissive { mode, restricted } => {
+                write!(
+                    f,
+                    "{} was accessible by other users (mode {:o})",
+                    self.path.display(),
+                    mode
+                )?;
+                match &self.repair {
+                    Repair::Fixed => write!(f, "; changed to {:o}", restricted),
+                    Repair::Failed(e) => {
+                        write!(f, "; failed to change it to {:o}: {}", restricted, e)
+                    }
+                    Repair::Manual => write!(f, "; change it to {:o}", restricted),
+                }
+            }
+            PermissionProblem::OutsideUserProfile => write!(
+                f,
+                "{} is outside your user profile, so other users may be able to read it",
+                self.path.display()
+            ),
+        }
+    }
+}
+
+/// Create (or truncate) a file only its owner can read and write.
+///
+/// An existing file is tightened to [`PRIVATE_FILE_MODE`] before anything is
+/// written to it.
+pub fn create_private_file(path: &Path) -> io::Result<File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(PRIVATE_FILE_MODE);
+    }
+    let file = options.open(path)?;
+
+    // The mode only applies to newly created files
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(PRIVATE_FILE_MODE))?;
+    }
+
+    Ok(file)
+}
+
+/// Write `contents` to a file only its owner can read and write
+pub fn write_private_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = create_private_file(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// Create a directory and its missing parents, only accessible by the owner.
+///
+/// Directories that already exist are left alone.
+pub fn create_private_dir_all(path: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(PRIVATE_DIR_MODE);
+    }
+    builder.create(path)
+}
+
+/// Check the config directory, the IPC token and the given key files, and
+/// tighten any that other users can access.
+///
+/// Each finding is also logged as a warning. Missing paths are skipped.
+pub fn check_permissions(key_paths: &[&Path]) -> Vec<PermissionFinding> {
+    let mut targets = vec![(default_config_dir(), PathKind::Directory)];
+    if let Ok(token_path) = default_token_path() {
+        targets.push((token_path, PathKind::File));
+    }
+    targets.extend(key_paths.iter().map(|p| (p.to_path_buf(), PathKind::File)));
+
+    let findings = check_paths(&targets);
+    for finding in &findings {
+        tracing::warn!("{}", finding);
+    }
+    findings
+}
+
+/// Check and repair specific paths (see [`check_permissions`])
+pub fn check_paths(targets: &[(PathBuf, PathKind)]) -> Vec<PermissionFinding> {
+    targets
+        .iter()
+        .filter_map(|(path, kind)| check_path(path, *kind))
+        .collect()
+}
+
+#[cfg(unix)]
+fn check_path(path: &Path, kind: PathKind) -> Option<PermissionFinding> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path).ok()?.permissions().mode() & 0o777;
+    if mode & 0o077 == 0 {
+        return None;
+    }
+
+    let restricted = match kind {
+        PathKind::File => mode & PRIVATE_FILE_MODE,
+        PathKind::Directory => mode & PRIVATE_DIR_MODE,
+    };
+    let repair = match fs::set_permissions(path, fs::Permissions::from_mode(restricted)) {
+        Ok(()) => Repair::Fixed,
+        Err(e) => Repair::Failed(e.to_string()),
+    };
+
+    Some(PermissionFinding {
+        path: path.to_path_buf(),
+        problem: PermissionProblem::TooPermissive { mode, restricted },
+        repair,
+    })
+}
+
+#[cfg(not(unix))]
+fn check_path(path: &Path, _kind: PathKind) -> Option<PermissionFinding> {
+    if !path.exists() {
+        return None;
+    }
+    let home = dirs::home_dir()?;
+    if path.starts_with(&home) {
+        return None;
+    }
+
+    Some(PermissionFinding {
+        path: path.to_path_buf(),
+        problem: PermissionProblem::OutsideUserProfile,
+        repair: Repair::Manual,
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o777
+    }
+
+    #[test]
+    fn test_permissive_file_is_tightened_and_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ipc_auth_token.json");
+        fs::write(&path, "secret").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let findings = check_paths(&[(path.clone(), PathKind::File)]);
+        assert_eq!(
+            findings,
+            vec![PermissionFinding {
+                path: path.clone(),
+                problem: PermissionProblem::TooPermissive {
+                    mode: 0o644,
+                    restricted: 0o600,
+                },
+                repair: Repair::Fixed,
+            }]
+        );
+        assert_eq!(mode(&path), 0o600);
+
+        // Nothing left to report
+        assert!(check_paths(&[(path, PathKind::File)]).is_empty());
+    }
+
+    #[test]
+    fn test_permissive_directory_is_tightened() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("k-terminus");
+        fs::create_dir(&config_dir).unwrap();
+        fs::set_permissions(&config_dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let findings = check_paths(&[(config_dir.clone(), PathKind::Directory)]);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].repair, Repair::Fixed);
+        assert_eq!(mode(&config_dir), 0o700);
+    }
+
+    #[test]
+    fn test_missing_paths_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(check_paths(&[(dir.path().join("missing"), PathKind::File)]).is_empty());
+    }
+
+    #[test]
+    fn test_private_file_overrides_umask_and_existing_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("host_key");
+
+        write_private_file(&path, b"key").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        write_private_file(&path, b"new key").unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new key");
+    }
+
+    #[test]
+    fn test_private_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a").join("b");
+        create_private_dir_all(&nested).unwrap();
+        assert_eq!(mode(&nested), 0o700);
+    }
+}
@@ -2,52 +2,166 @@
 //!
 //! Provides utilities for managing a PID file to detect and prevent
 //! multiple orchestrator instances from running simultaneously.
+//!
+//! # Stale files
+//!
+//! An orchestrator that crashes leaves its PID file and IPC token file
+//! behind, naming a process that no longer exists. [`clean_stale_files`]
+//! removes them. It runs under the [`StartupLock`], which orchestrators hold
+//! while claiming the token and writing the PID file, so a file that belongs
+//! to an orchestrator that is just starting is never mistaken for a stale one.
+//!
+//! # PID reuse
+//!
+//! Once a process exits its PID can be given to an unrelated one, which
+//! would make the files of a dead orchestrator look alive. PID files, the
+//! startup lock and the token file therefore record a [`ProcessStamp`]: the
+//! PID together with the process's start time. A process running under that
+//! PID but started at another time is a different one, and the files are
+//! treated as stale.
 
+use std::fmt;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::config;
+use crate::ipc_auth::{default_token_path, read_token_info_at};
 
 /// Default PID file name
 const PID_FILE_NAME: &str = "orchestrator.pid";
 
+/// Startup lock file name
+const STARTUP_LOCK_NAME: &str = "startup.lock";
+
+/// How long to wait for another process to release the startup lock
+pub const STARTUP_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often to check whether the startup lock was released
+const STARTUP_LOCK_POLL: Duration = Duration::from_millis(50);
+
+/// Age after which a startup lock without a readable PID is considered
+/// abandoned (its holder died between creating and writing it)
+const STARTUP_LOCK_UNREADABLE_GRACE: Duration = Duration::from_secs(2);
+
+/// Startup locks currently held by this process, so a lock file with our
+/// own PID can be told apart from one left by an earlier process that had it
+static STARTUP_LOCKS_HELD: AtomicUsize = AtomicUsize::new(0);
+
 /// Get the default PID file path
 pub fn default_pid_path() -> PathBuf {
     config::default_config_dir().join(PID_FILE_NAME)
 }
 
-/// Read the PID from the PID file
+/// Get the default startup lock path
+pub fn default_startup_lock_path() -> PathBuf {
+    config::default_config_dir().join(STARTUP_LOCK_NAME)
+}
+
+/// A process, identified by its PID and when it started
+///
+/// `start_time` is an opaque, platform-specific value that is only ever
+/// compared with another read on the same machine: clock ticks since boot on
+/// Linux, microseconds since the Unix epoch on macOS and the creation
+/// `FILETIME` on Windows. It is `None` where it can't be read (or in files
+/// written by older versions), in which case only the PID is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessStamp {
+    /// Process ID
+    pub pid: u32,
+    /// When the process started
+    pub start_time: Option<u64>,
+}
+
+impl ProcessStamp {
+    /// Stamp of the current process
+    pub fn current() -> Self {
+        Self::of(std::process::id())
+    }
+
+    /// Stamp of the process now running as `pid`
+    pub fn of(pid: u32) -> Self {
+        Self {
+            pid,
+            start_time: process_start_time(pid),
+        }
+    }
+
+    /// Whether this is the current process
+    pub fn is_current(&self) -> bool {
+        self.pid == std::process::id()
+    }
+
+    /// Whether the process this stamp names is still running
+    ///
+    /// False if its PID is free, or now belongs to a process that started
+    /// at a different time.
+    pub fn is_alive(&self) -> bool {
+        if !is_process_alive(self.pid) {
+            return false;
+        }
+        match (self.start_time, process_start_time(self.pid)) {
+            (Some(recorded), Some(actual)) => recorded == actual,
+            _ => true,
+        }
+    }
+}
+
+impl fmt::Display for ProcessStamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.start_time {
+            Some(start_time) => write!(f, "{} {}", self.pid, start_time),
+            None => write!(f, "{}", self.pid),
+        }
+    }
+}
+
+impl std::str::FromStr for ProcessStamp {
+    type Err = std::num::ParseIntError;
+
+    /// Parse `<pid>` or `<pid> <start time>`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let pid = parts.next().unwrap_or_default().parse()?;
+        let start_time = parts.next().map(str::parse).transpose()?;
+        Ok(Self { pid, start_time })
+    }
+}
+
+/// Read the process stamp from the PID file
 ///
-/// Returns `Ok(Some(pid))` if the file exists and contains a valid PID,
+/// Returns `Ok(Some(stamp))` if the file exists and contains a valid PID,
 /// `Ok(None)` if the file doesn't exist, or an error if the file is malformed.
-pub fn read_pid_file(path: &Path) -> io::Result<Option<u32>> {
+/// Files holding only a PID give a stamp without a start time.
+pub fn read_pid_file(path: &Path) -> io::Result<Option<ProcessStamp>> {
     match fs::File::open(path) {
         Ok(mut file) => {
             let mut contents = String::new();
             file.read_to_string(&mut contents)?;
-            let pid = contents
+            let stamp = contents
                 .trim()
-                .parse::<u32>()
+                .parse::<ProcessStamp>()
                 .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-            Ok(Some(pid))
+            Ok(Some(stamp))
         }
         Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
         Err(e) => Err(e),
     }
 }
 
-/// Write the current process PID to the PID file
+/// Write a process stamp to the PID file
 ///
 /// Creates parent directories if they don't exist.
-pub fn write_pid_file(path: &Path, pid: u32) -> io::Result<()> {
+pub fn write_pid_file(path: &Path, stamp: ProcessStamp) -> io::Result<()> {
     // Ensure parent directory exists
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent)?;
     }
 
     let mut file = fs::File::create(path)?;
-    writeln!(file, "{}", pid)?;
+    writeln!(file, "{}", stamp)?;
     Ok(())
 }
 
@@ -99,6 +213,69 @@ pub fn is_process_alive(pid: u32) -> bool {
     }
 }
 
+/// Start time of the process running as `pid`, if it can be read
+///
+/// See [`ProcessStamp::start_time`] for what the value means.
+#[cfg(target_os = "linux")]
+fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // The command name (field 2) is in parentheses and may contain spaces
+    // or parentheses itself; the start time is field 22
+    let after_comm = &stat[stat.rfind(')')? + 1..];
+    after_comm.split_whitespace().nth(19)?.parse().ok()
+}
+
+#[cfg(target_os = "macos")]
+fn process_start_time(pid: u32) -> Option<u64> {
+    let mut info: libc::proc_bsdinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    let written = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDTBSDINFO,
+            0,
+            &mut info as *mut _ as *mut libc::c_void,
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+    Some(info.pbi_start_tvsec * 1_000_000 + info.pbi_start_tvusec)
+}
+
+#[cfg(windows)]
+fn process_start_time(pid: u32) -> Option<u64> {
+    use std::ptr;
+    use windows_sys::Win32::Foundation::{CloseHandle, FILETIME, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Threading::{
+        GetProcessTimes, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    let empty = || FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle == INVALID_HANDLE_VALUE || handle == ptr::null_mut() {
+            return None;
+        }
+        let (mut created, mut exited, mut kernel, mut user) = (empty(), empty(), empty(), empty());
+        let ok = GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user);
+        CloseHandle(handle);
+        if ok == 0 {
+            return None;
+        }
+        Some((u64::from(created.dwHighDateTime) << 32) | u64::from(created.dwLowDateTime))
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn process_start_time(_pid: u32) -> Option<u64> {
+    None
+}
+
 /// Guard that removes the PID file when dropped
 ///
 /// Useful for ensuring the PID file is cleaned up even on panic.
@@ -108,14 +285,14 @@ pub struct PidFileGuard {
 
 impl PidFileGuard {
     /// Create a new guard and write the PID file
-    pub fn new(path: PathBuf, pid: u32) -> io::Result<Self> {
-        write_pid_file(&path, pid)?;
+    pub fn new(path: PathBuf, stamp: ProcessStamp) -> io::Result<Self> {
+        write_pid_file(&path, stamp)?;
         Ok(Self { path })
     }
 
     /// Create a guard for the default path
-    pub fn default(pid: u32) -> io::Result<Self> {
-        Self::new(default_pid_path(), pid)
+    pub fn default(stamp: ProcessStamp) -> io::Result<Self> {
+        Self::new(default_pid_path(), stamp)
     }
 }
 
@@ -127,11 +304,203 @@ impl Drop for PidFileGuard {
     }
 }
 
+/// Lock held while an orchestrator starts up or stale files are cleaned
+///
+/// The lock is a file created exclusively, holding the holder's PID. A lock
+/// left behind by a process that died is broken. The file is removed when
+/// the lock is dropped.
+#[derive(Debug)]
+pub struct StartupLock {
+    path: PathBuf,
+}
+
+impl StartupLock {
+    /// Take the lock at `path`, waiting up to `timeout` for its holder
+    ///
+    /// Fails with `WouldBlock` if a live process still holds it by then.
+    pub fn acquire(path: PathBuf, timeout: Duration) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    STARTUP_LOCKS_HELD.fetch_add(1, Ordering::SeqCst);
+                    let lock = Self { path };
+                    writeln!(file, "{}", ProcessStamp::current())?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+
+            match lock_holder(&path) {
+                LockHolder::Alive(pid) if Instant::now() >= deadline => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        format!("Startup lock {:?} is held by PID {}", path, pid),
+                    ));
+                }
+                LockHolder::Alive(_) => std::thread::sleep(STARTUP_LOCK_POLL),
+                LockHolder::Abandoned(pid) => {
+                    tracing::info!("Breaking startup lock {:?} left by PID {:?}", path, pid);
+                    remove_pid_file(&path)?;
+                }
+            }
+        }
+    }
+
+    /// Take the lock at the default path
+    pub fn acquire_default() -> io::Result<Self> {
+        Self::acquire(default_startup_lock_path(), STARTUP_LOCK_TIMEOUT)
+    }
+}
+
+impl Drop for StartupLock {
+    fn drop(&mut self) {
+        if let Err(e) = remove_pid_file(&self.path) {
+            tracing::warn!("Failed to remove startup lock {:?}: {}", self.path, e);
+        }
+        STARTUP_LOCKS_HELD.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Who holds an existing startup lock
+enum LockHolder {
+    /// A live process (or one still writing its PID)
+    Alive(u32),
+    /// Nobody: the holder died, or never wrote its PID
+    Abandoned(Option<u32>),
+}
+
+fn lock_holder(path: &Path) -> LockHolder {
+    match read_pid_file(path) {
+        Ok(Some(stamp)) if stamp.is_current() => {
+            if STARTUP_LOCKS_HELD.load(Ordering::SeqCst) > 0 {
+                LockHolder::Alive(stamp.pid)
+            } else {
+                LockHolder::Abandoned(Some(stamp.pid))
+            }
+        }
+        Ok(Some(stamp)) if stamp.is_alive() => LockHolder::Alive(stamp.pid),
+        Ok(Some(stamp)) => LockHolder::Abandoned(Some(stamp.pid)),
+        // Already released
+        Ok(None) => LockHolder::Abandoned(None),
+        Err(_) => {
+            let age = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+            match age {
+                Some(age) if age >= STARTUP_LOCK_UNREADABLE_GRACE => LockHolder::Abandoned(None),
+                _ => LockHolder::Alive(0),
+            }
+        }
+    }
+}
+
+/// A file naming an orchestrator that is no longer running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleFile {
+    /// Path of the file
+    pub path: PathBuf,
+    /// PID the file names (None if the PID file couldn't be parsed)
+    pub pid: Option<u32>,
+}
+
+impl fmt::Display for StaleFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.pid {
+            Some(pid) => write!(f, "{} (PID {} is not running)", self.path.display(), pid),
+            None => write!(f, "{} (unreadable)", self.path.display()),
+        }
+    }
+}
+
+/// Find the token and PID files left by orchestrators that are gone
+///
+/// Only reports; the answer may be out of date by the time it is acted on,
+/// so removal goes through [`clean_stale_files`].
+pub fn find_stale_files() -> io::Result<Vec<StaleFile>> {
+    find_stale_files_at(&default_token_path()?, &default_pid_path())
+}
+
+/// Remove the token and PID files left by orchestrators that are gone
+///
+/// Taking `lock` means no orchestrator can be claiming these files while
+/// they are checked and removed. Returns what was removed.
+pub fn clean_stale_files(lock: &StartupLock) -> io::Result<Vec<StaleFile>> {
+    clean_stale_files_at(lock, &default_token_path()?, &default_pid_path())
+}
+
+fn find_stale_files_at(token_path: &Path, pid_path: &Path) -> io::Result<Vec<StaleFile>> {
+    let mut stale = Vec::new();
+
+    if let Some(info) = read_token_info_at(token_path)? {
+        let owner = info.owner_process();
+        if !owner.is_current() && !owner.is_alive() {
+            stale.push(StaleFile {
+                path: token_path.to_path_buf(),
+                pid: Some(info.pid),
+            });
+        }
+    }
+
+    match read_pid_file(pid_path) {
+        Ok(Some(stamp)) if stamp.is_current() || stamp.is_alive() => {}
+        Ok(Some(stamp)) => stale.push(StaleFile {
+            path: pid_path.to_path_buf(),
+            pid: Some(stamp.pid),
+        }),
+        Ok(None) => {}
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => stale.push(StaleFile {
+            path: pid_path.to_path_buf(),
+            pid: None,
+        }),
+        Err(e) => return Err(e),
+    }
+
+    Ok(stale)
+}
+
+fn clean_stale_files_at(
+    _lock: &StartupLock,
+    token_path: &Path,
+    pid_path: &Path,
+) -> io::Result<Vec<StaleFile>> {
+    let stale = find_stale_files_at(token_path, pid_path)?;
+    for file in &stale {
+        remove_pid_file(&file.path)?;
+        tracing::info!("Removed stale {}", file);
+    }
+    Ok(stale)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::TempDir;
 
+    use crate::ipc::OrchestratorOwner;
+    use crate::ipc_auth::{new_token_info, write_token_info_at};
+
+    /// A PID that's very unlikely to be a running process
+    const DEAD_PID: u32 = 999999999;
+
+    /// Stamp with only a PID, as written by older versions
+    fn pid_only(pid: u32) -> ProcessStamp {
+        ProcessStamp {
+            pid,
+            start_time: None,
+        }
+    }
+
     #[test]
     fn test_read_nonexistent_pid_file() {
         let dir = TempDir::new().unwrap();
@@ -144,8 +513,16 @@ mod tests {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("test.pid");
 
-        write_pid_file(&path, 12345).unwrap();
-        assert_eq!(read_pid_file(&path).unwrap(), Some(12345));
+        write_pid_file(&path, pid_only(12345)).unwrap();
+        assert_eq!(read_pid_file(&path).unwrap(), Some(pid_only(12345)));
+
+        let stamp = ProcessStamp::current();
+        write_pid_file(&path, stamp).unwrap();
+        assert_eq!(read_pid_file(&path).unwrap(), Some(stamp));
+
+        fs::write(&path, "12345 abc\n").unwrap();
+        let err = read_pid_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
     }
 
     #[test]
@@ -153,7 +530,7 @@ mod tests {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("test.pid");
 
-        write_pid_file(&path, 12345).unwrap();
+        write_pid_file(&path, pid_only(12345)).unwrap();
         remove_pid_file(&path).unwrap();
         assert!(read_pid_file(&path).unwrap().is_none());
     }
@@ -179,17 +556,134 @@ mod tests {
         assert!(!is_process_alive(999999999));
     }
 
+    #[test]
+    fn test_process_stamp_detects_pid_reuse() {
+        let stamp = ProcessStamp::current();
+        assert!(stamp.is_current());
+        assert!(stamp.is_alive());
+        assert!(pid_only(stamp.pid).is_alive());
+
+        // Same PID, different start time: another process got the PID
+        if let Some(start_time) = stamp.start_time {
+            let reused = ProcessStamp {
+                pid: stamp.pid,
+                start_time: Some(start_time + 1),
+            };
+            assert!(!reused.is_alive());
+        }
+        assert!(!pid_only(DEAD_PID).is_alive());
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+    #[test]
+    fn test_process_start_time_is_known() {
+        let stamp = ProcessStamp::current();
+        assert!(stamp.start_time.is_some());
+        assert_eq!(ProcessStamp::of(stamp.pid), stamp);
+    }
+
     #[test]
     fn test_pid_file_guard() {
         let dir = TempDir::new().unwrap();
         let path = dir.path().join("guard.pid");
 
         {
-            let _guard = PidFileGuard::new(path.clone(), 12345).unwrap();
+            let _guard = PidFileGuard::new(path.clone(), pid_only(12345)).unwrap();
             assert!(path.exists());
         }
 
         // Guard dropped, file should be removed
         assert!(!path.exists());
     }
+
+    #[test]
+    fn test_startup_lock_excludes_and_breaks_abandoned() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("startup.lock");
+
+        // Held by a live process: times out
+        write_pid_file(&path, ProcessStamp::of(1)).unwrap();
+        let err = StartupLock::acquire(path.clone(), Duration::from_millis(100)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        // Left by a dead process: broken
+        write_pid_file(&path, pid_only(DEAD_PID)).unwrap();
+        {
+            let _lock = StartupLock::acquire(path.clone(), Duration::ZERO).unwrap();
+            let holder = read_pid_file(&path).unwrap();
+            assert_eq!(holder, Some(ProcessStamp::current()));
+        }
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_clean_stale_files() {
+        let dir = TempDir::new().unwrap();
+        let token_path = dir.path().join("ipc_auth_token.json");
+        let pid_path = dir.path().join("orchestrator.pid");
+        let lock = StartupLock::acquire(dir.path().join("startup.lock"), Duration::ZERO).unwrap();
+
+        // Nothing to clean
+        assert!(clean_stale_files_at(&lock, &token_path, &pid_path)
+            .unwrap()
+            .is_empty());
+
+        // Files of a live orchestrator are kept
+        let mut info = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
+        let init = ProcessStamp::of(1);
+        info.pid = 1;
+        info.start_time = init.start_time;
+        write_token_info_at(&token_path, &info).unwrap();
+        write_pid_file(&pid_path, init).unwrap();
+        assert!(clean_stale_files_at(&lock, &token_path, &pid_path)
+            .unwrap()
+            .is_empty());
+        assert!(token_path.exists() && pid_path.exists());
+
+        // Files of a dead one are removed
+        info.pid = DEAD_PID;
+        info.start_time = None;
+        write_token_info_at(&token_path, &info).unwrap();
+        write_pid_file(&pid_path, pid_only(DEAD_PID)).unwrap();
+        let cleaned = clean_stale_files_at(&lock, &token_path, &pid_path).unwrap();
+        assert_eq!(
+            cleaned,
+            vec![
+                StaleFile {
+                    path: token_path.clone(),
+                    pid: Some(DEAD_PID),
+                },
+                StaleFile {
+                    path: pid_path.clone(),
+                    pid: Some(DEAD_PID),
+                },
+            ]
+        );
+        assert!(!token_path.exists() && !pid_path.exists());
+    }
+
+    #[test]
+    fn test_files_of_reused_pid_are_stale() {
+        let dir = TempDir::new().unwrap();
+        let token_path = dir.path().join("ipc_auth_token.json");
+        let pid_path = dir.path().join("orchestrator.pid");
+
+        // PID 1 is alive, but was not started when these files say
+        let Some(start_time) = ProcessStamp::of(1).start_time else {
+            return;
+        };
+        let reused = ProcessStamp {
+            pid: 1,
+            start_time: Some(start_time + 1),
+        };
+        let mut info = new_token_info("127.0.0.1:22230", None, 1, OrchestratorOwner::Standalone);
+        info.pid = reused.pid;
+        info.start_time = reused.start_time;
+        write_token_info_at(&token_path, &info).unwrap();
+        write_pid_file(&pid_path, reused).unwrap();
+
+        let stale = find_stale_files_at(&token_path, &pid_path).unwrap();
+        assert_eq!(stale.len(), 2);
+        assert!(stale.iter().all(|file| file.pid == Some(1)));
+    }
 }
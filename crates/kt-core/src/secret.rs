@@ -0,0 +1,127 @@
+//! Wrapper for values that must not end up in logs
+//!
+//! [`Secret`] holds things like the IPC auth token and the pairing code.
+//! Its `Debug` and `Display` print `[REDACTED]`, so a secret inside a struct
+//! that gets logged or dumped into a diagnostic bundle stays hidden; code
+//! that really needs the value calls [`Secret::expose`]. The value is wiped
+//! from memory when the secret is dropped.
+//!
+//! To tell log lines about the same secret apart without revealing it, log
+//! its [`fingerprint`].
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+/// Hex digits of the SHA-256 hash shown by [`fingerprint`]
+const FINGERPRINT_LEN: usize = 8;
+
+/// A value that is redacted when formatted and zeroized on drop
+///
+/// Serializing a secret writes the value itself; that is only meant for
+/// files that exist to hold it, like the token file.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// The value itself, for when it is actually needed
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]>> Secret<T> {
+    /// Non-reversible identifier for this value (see [`fingerprint`])
+    pub fn fingerprint(&self) -> String {
+        fingerprint(self.0.as_ref())
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T: Zeroize> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl<T: Zeroize + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+/// Short hash of a secret, for correlating log lines
+///
+/// The first few hex digits of its SHA-256: the same value always gives the
+/// same fingerprint, but the value can't be recovered from it.
+pub fn fingerprint(value: &[u8]) -> String {
+    let mut hash = hex::encode(Sha256::digest(value));
+    hash.truncate(FINGERPRINT_LEN);
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formatting_never_shows_value() {
+        let secret = Secret::new("hunter2-0123456789abcdef".to_string());
+        let debug = format!("{:?}", secret);
+        let display = format!("{}", secret);
+        let nested = format!("{:?}", Some(&secret));
+        for s in [debug, display, nested] {
+            assert!(!s.contains("hunter2"), "{}", s);
+            assert!(s.contains("[REDACTED]"), "{}", s);
+        }
+        assert_eq!(secret.expose(), "hunter2-0123456789abcdef");
+    }
+
+    #[test]
+    fn test_serializes_as_plain_value() {
+        let secret = Secret::new("abc123".to_string());
+        let json = serde_json::to_string(&secret).unwrap();
+        assert_eq!(json, r#""abc123""#);
+        let parsed: Secret<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, secret);
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let secret = Secret::new("abc123".to_string());
+        let print = secret.fingerprint();
+        assert_eq!(print.len(), FINGERPRINT_LEN);
+        assert_eq!(print, fingerprint(b"abc123"));
+        assert_ne!(print, fingerprint(b"abc124"));
+        assert!(!print.contains("abc123"));
+    }
+}
@@ -8,7 +8,8 @@ use std::process::Command;
 
 use anyhow::{Context, Result};
 
-use crate::config::default_config_dir;
+use crate::config::{default_config_dir, ConfigFile, VersionedConfig};
+use crate::permissions::create_private_dir_all;
 use crate::tailscale::{self, TailscaleInfo};
 
 /// Setup result containing paths to generated files
@@ -40,7 +41,10 @@ pub fn auto_setup() -> Result<SetupResult> {
     // Check if already initialized
     if is_initialized() {
         // Still try to get Tailscale info
-        let tailscale = tailscale::get_tailscale_info().ok().flatten();
+        let tailscale = tailscale::get_tailscale_info().unwrap_or_else(|e| {
+            tracing::warn!("Couldn't read Tailscale status: {:#}", e);
+            None
+        });
         return Ok(SetupResult {
             config_dir: config_dir.clone(),
             host_key_path: config_dir.join("host_key"),
@@ -58,7 +62,7 @@ pub fn auto_setup() -> Result<SetupResult> {
     let tailscale_info = setup_tailscale()?;
 
     // Create config directory
-    fs::create_dir_all(&config_dir)
+    create_private_dir_all(&config_dir)
         .with_context(|| format!("Failed to create config directory: {:?}", config_dir))?;
 
     // Generate host key (for orchestrator identity)
@@ -245,11 +249,15 @@ tailscale_hostname = "{}"
     } else {
         String::new()
     };
+    let version = <ConfigFile as VersionedConfig>::VERSION;
 
     format!(
         r#"# k-Terminus Configuration
 # Auto-generated on first run
 
+# Config schema version (upgraded automatically when it changes)
+version = {version}
+
 [orchestrator]
 # Address to bind SSH server (agents connect here)
 bind_address = "0.0.0.0:2222"
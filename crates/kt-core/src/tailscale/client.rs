@@ -0,0 +1,518 @@
+//! Async Tailscale status client with a short-lived cache
+//!
+//! [`TailscaleClient`] asks tailscaled's local API over its Unix socket when
+//! the socket is there, and falls back to running `tailscale status --json`
+//! otherwise (e.g. on macOS and Windows, or when tailscaled lives elsewhere).
+//! Both return the same JSON; if it doesn't parse, the plain
+//! `tailscale status` table is tried before giving up. Successful answers,
+//! including "not installed", are cached for [`STATUS_CACHE_TTL`]; errors are
+//! not, so the next call tries again.
+//!
+//! How the status is fetched is behind [`TailscaleRunner`], so tests can
+//! stub out the socket and the CLI.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use super::{
+    combined_output, info_from_status, is_logged_out_error, parse_status, peers_from_status,
+    status_from_text, TailscaleInfo, TailscalePeer, TailscaleStatus,
+};
+use crate::error::TailscaleError;
+
+/// How long a status answer is reused
+pub const STATUS_CACHE_TTL: Duration = Duration::from_secs(5);
+
+/// Where tailscaled listens for local API requests on Linux
+pub const DEFAULT_SOCKET_PATH: &str = "/var/run/tailscale/tailscaled.sock";
+
+/// Local API path returning the same JSON as `tailscale status --json`
+const STATUS_PATH: &str = "/localapi/v0/status";
+
+/// Host header tailscaled expects on local API requests
+const LOCAL_API_HOST: &str = "local-tailscaled.sock";
+
+/// How long to wait for the local API before falling back to the CLI
+const LOCAL_API_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Output of a finished `tailscale` command
+#[derive(Debug, Clone, Default)]
+pub struct CommandOutput {
+    /// Whether the command exited successfully
+    pub success: bool,
+    pub stdout: Vec<u8>,
+    pub stderr: String,
+}
+
+/// How a [`TailscaleClient`] reaches tailscaled
+#[async_trait]
+pub trait TailscaleRunner: Send + Sync {
+    /// GET `path` from the tailscaled local API.
+    ///
+    /// Returns `Ok(None)` if the socket isn't available.
+    async fn local_api_get(&self, path: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Run the `tailscale` CLI with `args`.
+    ///
+    /// Returns `Ok(None)` if the binary isn't installed.
+    async fn run_cli(&self, args: &[&str]) -> Result<Option<CommandOutput>>;
+}
+
+/// Talks to the real tailscaled socket and `tailscale` binary
+#[derive(Debug, Clone)]
+pub struct SystemRunner {
+    socket_path: PathBuf,
+    binary: String,
+}
+
+impl SystemRunner {
+    pub fn new(socket_path: impl Into<PathBuf>, binary: impl Into<String>) -> Self {
+        Self {
+            socket_path: socket_path.into(),
+            binary: binary.into(),
+        }
+    }
+}
+
+impl Default for SystemRunner {
+    fn default() -> Self {
+        Self::new(DEFAULT_SOCKET_PATH, "tailscale")
+    }
+}
+
+#[async_trait]
+impl TailscaleRunner for SystemRunner {
+    #[cfg(unix)]
+    async fn local_api_get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        use std::io::ErrorKind;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::UnixStream;
+
+        let mut stream = match UnixStream::connect(&self.socket_path).await {
+            Ok(stream) => stream,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    ErrorKind::NotFound
+                        | ErrorKind::ConnectionRefused
+                        | ErrorKind::PermissionDenied
+                ) =>
+            {
+                return Ok(None);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to connect to {}", self.socket_path.display())
+                })
+            }
+        };
+
+        // HTTP/1.0 so tailscaled closes the connection instead of chunking
+        let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, LOCAL_API_HOST);
+        let exchange = async {
+            stream.write_all(request.as_bytes()).await?;
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).await?;
+            Ok::<_, std::io::Error>(response)
+        };
+        let response = tokio::time::timeout(LOCAL_API_TIMEOUT, exchange)
+            .await
+            .context("Timed out waiting for the tailscaled local API")?
+            .context("Failed to query the tailscaled local API")?;
+
+        parse_http_response(&response).map(Some)
+    }
+
+    #[cfg(not(unix))]
+    async fn local_api_get(&self, _path: &str) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    async fn run_cli(&self, args: &[&str]) -> Result<Option<CommandOutput>> {
+        let output = match tokio::process::Command::new(&self.binary)
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).context("Failed to run tailscale status"),
+        };
+
+        Ok(Some(CommandOutput {
+            success: output.status.success(),
+            stdout: output.stdout,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }))
+    }
+}
+
+/// Return the body of a successful HTTP response
+fn parse_http_response(response: &[u8]) -> Result<Vec<u8>> {
+    let header_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .context("Malformed response from the tailscaled local API")?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let body = &response[header_end + 4..];
+
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .context("Malformed response from the tailscaled local API")?;
+    if status != "200" {
+        anyhow::bail!(
+            "Tailscale local API returned {}: {}",
+            status,
+            String::from_utf8_lossy(body).trim()
+        );
+    }
+
+    Ok(body.to_vec())
+}
+
+/// What tailscaled reported
+#[derive(Debug, Clone)]
+enum Status {
+    NotInstalled,
+    LoggedOut,
+    Running(Arc<TailscaleStatus>),
+}
+
+/// Tailscale status client (see the module docs)
+pub struct TailscaleClient {
+    runner: Box<dyn TailscaleRunner>,
+    ttl: Duration,
+    cache: Mutex<Option<(Instant, Status)>>,
+}
+
+impl TailscaleClient {
+    /// Create a client that caches answers for `ttl`
+    pub fn new(runner: impl TailscaleRunner + 'static, ttl: Duration) -> Self {
+        Self {
+            runner: Box::new(runner),
+            ttl,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Process-wide client using the system socket and CLI
+    pub fn shared() -> &'static TailscaleClient {
+        static SHARED: OnceLock<TailscaleClient> = OnceLock::new();
+        SHARED.get_or_init(|| TailscaleClient::new(SystemRunner::default(), STATUS_CACHE_TTL))
+    }
+
+    /// Our own device's Tailscale info, or `None` if Tailscale isn't installed
+    pub async fn info(&self) -> Result<Option<TailscaleInfo>> {
+        match self.status().await? {
+            Status::NotInstalled => Ok(None),
+            Status::LoggedOut => Ok(Some(TailscaleInfo::logged_out())),
+            Status::Running(status) => info_from_status((*status).clone()).map(Some),
+        }
+    }
+
+    /// All peers in the current tailnet
+    pub async fn peers(&self) -> Result<Vec<TailscalePeer>> {
+        match self.status().await? {
+            Status::NotInstalled => Err(TailscaleError::NotInstalled.into()),
+            Status::LoggedOut => Ok(Vec::new()),
+            Status::Running(status) => Ok(peers_from_status((*status).clone())),
+        }
+    }
+
+    /// Forget the cached status, e.g. after running `tailscale up`
+    pub fn invalidate(&self) {
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    async fn status(&self) -> Result<Status> {
+        if let Some((fetched_at, status)) = &*self.cache.lock().unwrap_or_else(|e| e.into_inner()) {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(status.clone());
+            }
+        }
+
+        let status = self.fetch().await?;
+        *self.cache.lock().unwrap_or_else(|e| e.into_inner()) =
+            Some((Instant::now(), status.clone()));
+        Ok(status)
+    }
+
+    /// Ask the local API, then the CLI
+    async fn fetch(&self) -> Result<Status> {
+        match self.runner.local_api_get(STATUS_PATH).await {
+            Ok(Some(body)) => match parse_status(&body) {
+                Ok(status) => return Ok(Status::from(status)),
+                Err(e) => tracing::debug!("Tailscale local API answer not understood: {:#}", e),
+            },
+            Ok(None) => {}
+            Err(e) => tracing::debug!("Tailscale local API unavailable, using the CLI: {:#}", e),
+        }
+
+        let Some(output) = self.runner.run_cli(&["status", "--json"]).await? else {
+            return Ok(Status::NotInstalled);
+        };
+        if !output.success {
+            if is_logged_out_error(&output.stderr) {
+                return Ok(Status::LoggedOut);
+            }
+            anyhow::bail!("Tailscale status failed: {}", output.stderr);
+        }
+        let status = match parse_status(&output.stdout) {
+            Ok(status) => status,
+            Err(json_error) => {
+                let text = self
+                    .runner
+                    .run_cli(&["status"])
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|output| combined_output(&output.stdout, &output.stderr));
+                status_from_text(json_error, text.as_deref())?
+            }
+        };
+        Ok(Status::from(status))
+    }
+}
+
+impl From<TailscaleStatus> for Status {
+    fn from(status: TailscaleStatus) -> Self {
+        if status.backend_state == "Running" {
+            Self::Running(Arc::new(status))
+        } else {
+            Self::LoggedOut
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const STATUS_JSON: &str = r#"{
+        "BackendState": "Running",
+        "Self": {
+            "DNSName": "my-laptop.tail1234.ts.net.",
+            "TailscaleIPs": ["100.64.1.50", "fd7a:115c:a1e0::1"]
+        },
+        "CurrentTailnet": { "Name": "me@example.com", "MagicDNSSuffix": "tail1234.ts.net" },
+        "Peer": {
+            "nodekey:abc": {
+                "DNSName": "lab-server.tail1234.ts.net.",
+                "TailscaleIPs": ["100.64.1.51"],
+                "Online": true
+            }
+        }
+    }"#;
+
+    /// What a stub source answers with
+    #[derive(Clone)]
+    enum Answer {
+        Unavailable,
+        Fail,
+        Json(&'static str),
+        LoggedOut,
+    }
+
+    #[derive(Clone)]
+    struct StubRunner {
+        local_api: Answer,
+        cli: Answer,
+        /// What plain `tailscale status` prints
+        cli_text: Option<&'static str>,
+        /// Sources queried, in order
+        calls: Arc<Mutex<Vec<&'static str>>>,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl StubRunner {
+        fn new(local_api: Answer, cli: Answer) -> Self {
+            Self {
+                local_api,
+                cli,
+                cli_text: None,
+                calls: Arc::default(),
+                fetches: Arc::default(),
+            }
+        }
+
+        fn calls(&self) -> Vec<&'static str> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl TailscaleRunner for StubRunner {
+        async fn local_api_get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+            assert_eq!(path, STATUS_PATH);
+            self.calls.lock().unwrap().push("local_api");
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            match self.local_api {
+                Answer::Unavailable => Ok(None),
+                Answer::Fail => anyhow::bail!("connection reset"),
+                Answer::Json(json) => Ok(Some(json.as_bytes().to_vec())),
+                Answer::LoggedOut => unreachable!("the local API answers with JSON"),
+            }
+        }
+
+        async fn run_cli(&self, args: &[&str]) -> Result<Option<CommandOutput>> {
+            if args == ["status"] {
+                self.calls.lock().unwrap().push("cli_text");
+                return Ok(self.cli_text.map(|text| CommandOutput {
+                    success: true,
+                    stdout: text.as_bytes().to_vec(),
+                    ..Default::default()
+                }));
+            }
+            assert_eq!(args, ["status", "--json"]);
+            self.calls.lock().unwrap().push("cli");
+            match self.cli {
+                Answer::Unavailable => Ok(None),
+                Answer::Fail => Ok(Some(CommandOutput {
+                    success: false,
+                    stderr: "failed to connect to local tailscaled".to_string(),
+                    ..Default::default()
+                })),
+                Answer::Json(json) => Ok(Some(CommandOutput {
+                    success: true,
+                    stdout: json.as_bytes().to_vec(),
+                    ..Default::default()
+                })),
+                Answer::LoggedOut => Ok(Some(CommandOutput {
+                    success: false,
+                    stderr: "Tailscale is stopped.".to_string(),
+                    ..Default::default()
+                })),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_api_preferred_over_cli() {
+        let runner = StubRunner::new(Answer::Json(STATUS_JSON), Answer::Json(STATUS_JSON));
+        let client = TailscaleClient::new(runner.clone(), STATUS_CACHE_TTL);
+
+        let info = client.info().await.unwrap().unwrap();
+        assert!(info.logged_in);
+        assert_eq!(info.device_name, "my-laptop");
+        assert_eq!(info.hostname, "my-laptop.tail1234.ts.net");
+        assert_eq!(info.ip, "100.64.1.50");
+        assert_eq!(runner.calls(), ["local_api"]);
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_cli() {
+        for local_api in [Answer::Unavailable, Answer::Fail] {
+            let runner = StubRunner::new(local_api, Answer::Json(STATUS_JSON));
+            let client = TailscaleClient::new(runner.clone(), STATUS_CACHE_TTL);
+
+            let peers = client.peers().await.unwrap();
+            assert_eq!(peers.len(), 1);
+            assert_eq!(peers[0].device_name, "lab-server");
+            assert_eq!(runner.calls(), ["local_api", "cli"]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_not_installed_and_logged_out() {
+        let client = TailscaleClient::new(
+            StubRunner::new(Answer::Unavailable, Answer::Unavailable),
+            STATUS_CACHE_TTL,
+        );
+        assert!(client.info().await.unwrap().is_none());
+        assert!(client.peers().await.is_err());
+
+        let client = TailscaleClient::new(
+            StubRunner::new(Answer::Unavailable, Answer::LoggedOut),
+            STATUS_CACHE_TTL,
+        );
+        assert!(!client.info().await.unwrap().unwrap().logged_in);
+
+        let stopped = r#"{"BackendState": "Stopped", "Self": null, "Peer": null}"#;
+        let client = TailscaleClient::new(
+            StubRunner::new(Answer::Json(stopped), Answer::Unavailable),
+            STATUS_CACHE_TTL,
+        );
+        assert!(!client.info().await.unwrap().unwrap().logged_in);
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_json_falls_back_to_text() {
+        // A future version that renamed the fields we rely on
+        let changed = r#"{"Version": "9.0", "State": "Running"}"#;
+        let mut runner = StubRunner::new(Answer::Json(changed), Answer::Json(changed));
+        runner.cli_text = Some(
+            "100.64.1.50     my-laptop    me@   linux  -\n\
+             100.64.1.51     lab-server   me@   linux  active; direct\n",
+        );
+        let client = TailscaleClient::new(runner.clone(), STATUS_CACHE_TTL);
+
+        let info = client.info().await.unwrap().unwrap();
+        assert_eq!(info.device_name, "my-laptop");
+        assert_eq!(info.ip, "100.64.1.50");
+        assert_eq!(info.tailnet, "");
+        assert_eq!(runner.calls(), ["local_api", "cli", "cli_text"]);
+
+        // With nothing usable either way, the error says so
+        runner.cli_text = Some("unexpected output\n");
+        let client = TailscaleClient::new(runner, STATUS_CACHE_TTL);
+        let err = client.info().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TailscaleError>(),
+            Some(TailscaleError::Unparseable(_))
+        ));
+        assert!(err.to_string().contains("tailscale version"), "{}", err);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_status_cached_for_ttl() {
+        let runner = StubRunner::new(Answer::Json(STATUS_JSON), Answer::Unavailable);
+        let client = TailscaleClient::new(runner.clone(), STATUS_CACHE_TTL);
+
+        client.info().await.unwrap();
+        client.peers().await.unwrap();
+        client.info().await.unwrap();
+        assert_eq!(runner.fetches.load(Ordering::SeqCst), 1);
+
+        tokio::time::advance(STATUS_CACHE_TTL).await;
+        client.info().await.unwrap();
+        assert_eq!(runner.fetches.load(Ordering::SeqCst), 2);
+
+        client.invalidate();
+        client.info().await.unwrap();
+        assert_eq!(runner.fetches.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_errors_not_cached() {
+        let runner = StubRunner::new(Answer::Unavailable, Answer::Fail);
+        let client = TailscaleClient::new(runner.clone(), STATUS_CACHE_TTL);
+
+        assert!(client.info().await.is_err());
+        assert!(client.info().await.is_err());
+        assert_eq!(runner.calls(), ["local_api", "cli", "local_api", "cli"]);
+    }
+
+    #[test]
+    fn test_parse_http_response() {
+        let body =
+            parse_http_response(b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}")
+                .unwrap();
+        assert_eq!(body, b"{}");
+
+        let err =
+            parse_http_response(b"HTTP/1.0 403 Forbidden\r\n\r\naccess denied\n").unwrap_err();
+        assert!(err.to_string().contains("403"));
+        assert!(err.to_string().contains("access denied"));
+
+        assert!(parse_http_response(b"HTTP/1.0 200 OK\r\n").is_err());
+    }
+}
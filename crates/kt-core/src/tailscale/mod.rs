@@ -0,0 +1,773 @@
+//! Tailscale integration for k-Terminus
+//!
+//! Provides detection, installation, and configuration of Tailscale
+//! for seamless networking across NAT/firewalls.
+//!
+//! The functions here shell out to the `tailscale` CLI synchronously. Hot
+//! paths should use [`get_tailscale_info_async`] or a [`TailscaleClient`],
+//! which ask tailscaled directly and cache the answer for a few seconds.
+//!
+//! Status is read from `tailscale status --json`, the stable format. If a
+//! Tailscale version prints JSON we can't parse, the plain `tailscale status`
+//! table is tried instead (without the tailnet domain, which it doesn't
+//! show), and if that fails too the error says so with
+//! [`TailscaleError::Unparseable`] rather than reporting Tailscale absent.
+
+mod client;
+
+pub use client::{
+    CommandOutput, SystemRunner, TailscaleClient, TailscaleRunner, DEFAULT_SOCKET_PATH,
+    STATUS_CACHE_TTL,
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::process::Command;
+
+use crate::error::TailscaleError;
+
+/// Information about the local Tailscale installation
+#[derive(Debug, Clone)]
+pub struct TailscaleInfo {
+    /// Device name (e.g., "adams-macbook")
+    pub device_name: String,
+    /// Tailnet domain (e.g., "tailnet-abc.ts.net" or "tail1234.ts.net"), or
+    /// a Headscale base domain. Empty if MagicDNS is off.
+    pub tailnet: String,
+    /// Tailscale IP address (e.g., "100.64.1.50")
+    pub ip: String,
+    /// Full hostname (e.g., "adams-macbook.tailnet-abc.ts.net"; just the
+    /// device name if there is no tailnet domain)
+    pub hostname: String,
+    /// Whether logged in to Tailscale
+    pub logged_in: bool,
+}
+
+/// Status response from `tailscale status --json`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct TailscaleStatus {
+    backend_state: String,
+    #[serde(rename = "Self")]
+    self_node: Option<SelfNode>,
+    current_tailnet: Option<CurrentTailnet>,
+    /// MagicDNS suffix (also reported by Headscale, which has no `CurrentTailnet`)
+    #[serde(default, rename = "MagicDNSSuffix")]
+    magic_dns_suffix: String,
+    #[serde(default, deserialize_with = "deserialize_null_as_empty_map")]
+    peer: std::collections::HashMap<String, PeerNode>,
+}
+
+/// Deserialize null as an empty HashMap
+fn deserialize_null_as_empty_map<'de, D, K, V>(
+    deserializer: D,
+) -> Result<std::collections::HashMap<K, V>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    K: std::cmp::Eq + std::hash::Hash + Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    Option::<std::collections::HashMap<K, V>>::deserialize(deserializer)
+        .map(|opt| opt.unwrap_or_default())
+}
+
+/// Information about a peer in the tailnet
+#[derive(Debug, Clone)]
+pub struct TailscalePeer {
+    /// Device name (e.g., "lab-server")
+    pub device_name: String,
+    /// Full DNS name (e.g., "lab-server.tailnet-abc.ts.net")
+    pub dns_name: String,
+    /// Tailscale IP addresses
+    pub ips: Vec<String>,
+    /// Whether the peer is currently online
+    pub online: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PeerNode {
+    #[serde(default)]
+    host_name: String,
+    #[serde(default, rename = "DNSName")]
+    dns_name: String,
+    #[serde(default, rename = "TailscaleIPs")]
+    tailscale_ips: Vec<String>,
+    #[serde(default)]
+    online: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SelfNode {
+    #[serde(default)]
+    host_name: String,
+    #[serde(default, rename = "DNSName")]
+    dns_name: String,
+    #[serde(default, rename = "TailscaleIPs")]
+    tailscale_ips: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CurrentTailnet {
+    #[serde(default, rename = "MagicDNSSuffix")]
+    magic_dns_suffix: String,
+}
+
+/// Check if Tailscale is installed
+pub fn is_tailscale_installed() -> bool {
+    Command::new("tailscale")
+        .arg("--version")
+        .output()
+        .map(|o| o.status.success())
+        .unwrap_or(false)
+}
+
+/// Get Tailscale status and information
+///
+/// `None` if Tailscale isn't installed. Output that can't be parsed is a
+/// [`TailscaleError::Unparseable`] error.
+pub fn get_tailscale_info() -> Result<Option<TailscaleInfo>> {
+    if !is_tailscale_installed() {
+        return Ok(None);
+    }
+
+    let output = Command::new("tailscale")
+        .args(["status", "--json"])
+        .output()
+        .context("Failed to run tailscale status")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Not logged in or not running
+        if is_logged_out_error(&stderr) {
+            return Ok(Some(TailscaleInfo::logged_out()));
+        }
+        anyhow::bail!("Tailscale status failed: {}", stderr);
+    }
+
+    info_from_status(read_status(&output.stdout)?).map(Some)
+}
+
+/// Parse `tailscale status --json` output, falling back to the plain
+/// `tailscale status` table if the JSON doesn't parse
+fn read_status(json: &[u8]) -> Result<TailscaleStatus> {
+    parse_status(json).or_else(|json_error| {
+        let text = Command::new("tailscale")
+            .arg("status")
+            .output()
+            .ok()
+            .map(|output| {
+                combined_output(&output.stdout, &String::from_utf8_lossy(&output.stderr))
+            });
+        status_from_text(json_error, text.as_deref())
+    })
+}
+
+/// Get Tailscale status and information, asking tailscaled without blocking.
+///
+/// Answers are shared through [`TailscaleClient::shared`] and reused for
+/// [`STATUS_CACHE_TTL`], so this is cheap enough for startup banners and
+/// status output.
+pub async fn get_tailscale_info_async() -> Result<Option<TailscaleInfo>> {
+    TailscaleClient::shared().info().await
+}
+
+impl TailscaleInfo {
+    /// The tailnet domain, or `configured` (the `tailnet_domain` option) if
+    /// Tailscale didn't report one
+    pub fn tailnet_or<'a>(&'a self, configured: Option<&'a str>) -> &'a str {
+        match configured {
+            Some(domain) if self.tailnet.is_empty() => domain,
+            _ => &self.tailnet,
+        }
+    }
+
+    /// Info for an installation that is stopped or not logged in
+    fn logged_out() -> Self {
+        Self {
+            device_name: String::new(),
+            tailnet: String::new(),
+            ip: String::new(),
+            hostname: String::new(),
+            logged_in: false,
+        }
+    }
+}
+
+/// Whether `tailscale status` failed because Tailscale isn't up
+fn is_logged_out_error(stderr: &str) -> bool {
+    stderr.contains("not logged in") || stderr.contains("stopped")
+}
+
+/// Parse the JSON printed by `tailscale status --json`
+fn parse_status(json: &[u8]) -> Result<TailscaleStatus> {
+    serde_json::from_slice(json).context("Failed to parse tailscale status JSON")
+}
+
+/// Parse the table printed by `tailscale status`
+///
+/// Each line is `<ip> <name> <user> <os> <state>`, our own device first;
+/// `#` lines are health warnings. Returns `None` if it holds no device.
+fn parse_status_text(text: &str) -> Option<TailscaleStatus> {
+    if text.contains("Logged out") || text.contains("NeedsLogin") || is_logged_out_error(text) {
+        return Some(TailscaleStatus {
+            backend_state: "Stopped".to_string(),
+            self_node: None,
+            current_tailnet: None,
+            magic_dns_suffix: String::new(),
+            peer: std::collections::HashMap::new(),
+        });
+    }
+
+    let mut devices = text.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let ip = fields.next()?;
+        ip.parse::<std::net::IpAddr>().ok()?;
+        let name = fields.next()?;
+        Some((ip.to_string(), name.to_string(), !line.contains("offline")))
+    });
+
+    let (ip, host_name, _) = devices.next()?;
+    let peer = devices
+        .map(|(ip, host_name, online)| {
+            let node = PeerNode {
+                host_name,
+                dns_name: String::new(),
+                tailscale_ips: vec![ip.clone()],
+                online,
+            };
+            (ip, node)
+        })
+        .collect();
+    Some(TailscaleStatus {
+        backend_state: "Running".to_string(),
+        self_node: Some(SelfNode {
+            host_name,
+            dns_name: String::new(),
+            tailscale_ips: vec![ip],
+        }),
+        current_tailnet: None,
+        magic_dns_suffix: String::new(),
+        peer,
+    })
+}
+
+/// Status from the `tailscale status` table, for when its JSON didn't parse
+/// with `json_error`
+fn status_from_text(json_error: anyhow::Error, text: Option<&str>) -> Result<TailscaleStatus> {
+    if let Some(status) = text.and_then(parse_status_text) {
+        tracing::warn!(
+            "Tailscale status JSON not understood ({:#}), using its text output",
+            json_error
+        );
+        return Ok(status);
+    }
+    Err(TailscaleError::Unparseable(format!("{:#}", json_error)).into())
+}
+
+/// Stdout and stderr of a command, as text
+fn combined_output(stdout: &[u8], stderr: &str) -> String {
+    format!("{}{}", String::from_utf8_lossy(stdout), stderr)
+}
+
+/// Extract our own device's info from a status response
+fn info_from_status(status: TailscaleStatus) -> Result<TailscaleInfo> {
+    // Check if logged in
+    if status.backend_state != "Running" {
+        return Ok(TailscaleInfo::logged_out());
+    }
+
+    let self_node = status
+        .self_node
+        .as_ref()
+        .ok_or_else(|| TailscaleError::Unparseable("no entry for this device".to_string()))?;
+    if self_node.tailscale_ips.is_empty() {
+        return Err(
+            TailscaleError::Unparseable("no Tailscale IP for this device".to_string()).into(),
+        );
+    }
+
+    // "adams-macbook.tailnet-abc.ts.net." -> ("adams-macbook", "tailnet-abc.ts.net")
+    let (device_name, own_suffix) = split_dns_name(&self_node.dns_name, &self_node.host_name);
+    let tailnet = magic_dns_suffix(&status, own_suffix);
+
+    // Get IPv4 address (prefer over IPv6)
+    let ip = self_node
+        .tailscale_ips
+        .iter()
+        .find(|ip: &&String| !ip.contains(':'))
+        .or(self_node.tailscale_ips.first())
+        .cloned()
+        .unwrap_or_default();
+
+    Ok(TailscaleInfo {
+        hostname: resolve_device_name(&device_name, &tailnet),
+        device_name,
+        tailnet,
+        ip,
+        logged_in: true,
+    })
+}
+
+/// Split a MagicDNS name into the device name and the tailnet domain.
+///
+/// Falls back to `host_name` when there's no DNS name (MagicDNS off).
+fn split_dns_name<'a>(dns_name: &'a str, host_name: &'a str) -> (String, &'a str) {
+    let dns_name = dns_name.trim_end_matches('.');
+    match dns_name.split_once('.') {
+        Some((device, suffix)) => (device.to_string(), suffix),
+        None if dns_name.is_empty() => (host_name.to_string(), ""),
+        None => (dns_name.to_string(), ""),
+    }
+}
+
+/// The MagicDNS suffix for this tailnet.
+///
+/// Prefers the domain our own DNS name actually has, since Headscale
+/// deployments use their own base domain (sometimes with the user name in
+/// front) rather than `*.ts.net`.
+fn magic_dns_suffix(status: &TailscaleStatus, own_suffix: &str) -> String {
+    let reported = status
+        .current_tailnet
+        .as_ref()
+        .map(|t| t.magic_dns_suffix.as_str())
+        .unwrap_or(&status.magic_dns_suffix);
+    let suffix = if own_suffix.is_empty() {
+        reported
+    } else {
+        own_suffix
+    };
+    suffix.trim_matches('.').to_string()
+}
+
+/// Extract the peers from a status response
+fn peers_from_status(status: TailscaleStatus) -> Vec<TailscalePeer> {
+    status
+        .peer
+        .into_values()
+        .map(|node| {
+            let (device_name, _) = split_dns_name(&node.dns_name, &node.host_name);
+            let dns_name = match node.dns_name.trim_end_matches('.') {
+                "" => device_name.clone(),
+                dns_name => dns_name.to_string(),
+            };
+
+            TailscalePeer {
+                device_name,
+                dns_name,
+                ips: node.tailscale_ips,
+                online: node.online,
+            }
+        })
+        .collect()
+}
+
+/// Get platform-specific Tailscale installation instructions
+pub fn get_install_instructions() -> String {
+    #[cfg(target_os = "macos")]
+    {
+        r#"Tailscale is not installed. Install it with:
+
+    brew install tailscale
+
+Or download from: https://tailscale.com/download/mac
+
+After installing, run:
+    sudo tailscale up"#
+            .to_string()
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        r#"Tailscale is not installed. Install it with:
+
+    curl -fsSL https://tailscale.com/install.sh | sh
+
+After installing, run:
+    sudo tailscale up"#
+            .to_string()
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        r#"Tailscale is not installed. Download it from:
+
+    https://tailscale.com/download/windows
+
+After installing, run Tailscale from the Start menu."#
+            .to_string()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        r#"Tailscale is not installed. Visit https://tailscale.com/download for installation instructions."#.to_string()
+    }
+}
+
+/// Attempt to auto-install Tailscale
+/// Returns Ok(true) if installation was attempted, Ok(false) if user needs to install manually
+pub fn auto_install_tailscale() -> Result<bool> {
+    #[cfg(target_os = "macos")]
+    {
+        // Check if Homebrew is available
+        if Command::new("brew")
+            .arg("--version")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false)
+        {
+            println!("Installing Tailscale via Homebrew...");
+            let status = Command::new("brew")
+                .args(["install", "tailscale"])
+                .status()
+                .context("Failed to run brew install")?;
+
+            if status.success() {
+                println!("Tailscale installed successfully!");
+                return Ok(true);
+            }
+        }
+        // Fall back to manual installation
+        Ok(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        println!("Installing Tailscale...");
+        let status = Command::new("sh")
+            .args(["-c", "curl -fsSL https://tailscale.com/install.sh | sh"])
+            .status()
+            .context("Failed to run Tailscale install script")?;
+
+        if status.success() {
+            println!("Tailscale installed successfully!");
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        // On Windows, we can't easily auto-install, prompt user
+        Ok(false)
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
+    {
+        Ok(false)
+    }
+}
+
+/// Start Tailscale authentication
+/// Returns the auth URL if authentication is needed
+pub fn start_tailscale_auth() -> Result<Option<String>> {
+    // Run `tailscale up` which will print an auth URL if needed
+    let output = Command::new("tailscale")
+        .arg("up")
+        .output()
+        .context("Failed to run tailscale up")?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let combined = format!("{}{}", stdout, stderr);
+
+    // Look for auth URL in output
+    for line in combined.lines() {
+        if line.contains("https://login.tailscale.com/") || line.contains("https://tailscale.com/")
+        {
+            // Extract URL
+            if let Some(url_start) = line.find("https://") {
+                let url = line[url_start..]
+                    .split_whitespace()
+                    .next()
+                    .unwrap_or(&line[url_start..]);
+                return Ok(Some(url.to_string()));
+            }
+        }
+    }
+
+    // If no URL found, either already authenticated or there was an error
+    if output.status.success() {
+        Ok(None) // Already authenticated
+    } else {
+        anyhow::bail!("Tailscale authentication failed: {}", stderr)
+    }
+}
+
+/// Wait for Tailscale to be authenticated and return info
+/// This polls until Tailscale is in a running state
+pub async fn wait_for_tailscale_auth(timeout_secs: u64) -> Result<TailscaleInfo> {
+    use std::time::{Duration, Instant};
+    use tokio::time::sleep;
+
+    let start = Instant::now();
+    let timeout = Duration::from_secs(timeout_secs);
+
+    loop {
+        if start.elapsed() > timeout {
+            anyhow::bail!("Timeout waiting for Tailscale authentication");
+        }
+
+        if let Some(info) = get_tailscale_info()? {
+            if info.logged_in {
+                return Ok(info);
+            }
+        }
+
+        sleep(Duration::from_secs(2)).await;
+    }
+}
+
+/// Resolve a device name to its full Tailscale hostname
+///
+/// `name` may carry a port (`my-laptop:2222`), which is kept. The tailnet
+/// domain is only appended to bare device names: names that already contain
+/// a dot are taken to be fully qualified, and IP literals are left alone. An
+/// empty `own_tailnet` (MagicDNS off) leaves the name as given.
+pub fn resolve_device_name(name: &str, own_tailnet: &str) -> String {
+    let (host, port) = split_host_port(name);
+    let own_tailnet = own_tailnet.trim_matches('.');
+
+    if host.contains('.') || host.parse::<std::net::IpAddr>().is_ok() || own_tailnet.is_empty() {
+        return name.to_string();
+    }
+
+    match port {
+        Some(port) => format!("{}.{}:{}", host, own_tailnet, port),
+        None => format!("{}.{}", host, own_tailnet),
+    }
+}
+
+/// Split `host:port`, leaving IPv6 literals (bare or bracketed) intact
+fn split_host_port(name: &str) -> (&str, Option<&str>) {
+    if let Some(rest) = name.strip_prefix('[') {
+        if let Some((host, after)) = rest.split_once(']') {
+            return (host, after.strip_prefix(':'));
+        }
+    }
+    match name.rsplit_once(':') {
+        // More than one colon: an IPv6 address without a port
+        Some((host, _)) if host.contains(':') => (name, None),
+        Some((host, port)) => (host, Some(port)),
+        None => (name, None),
+    }
+}
+
+/// Get all peers in the current tailnet
+pub fn get_tailscale_peers() -> Result<Vec<TailscalePeer>> {
+    if !is_tailscale_installed() {
+        return Err(TailscaleError::NotInstalled.into());
+    }
+
+    let output = Command::new("tailscale")
+        .args(["status", "--json"])
+        .output()
+        .context("Failed to run tailscale status")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if is_logged_out_error(&stderr) {
+            return Err(TailscaleError::NotLoggedIn.into());
+        }
+        anyhow::bail!("Tailscale status failed: {}", stderr);
+    }
+
+    Ok(peers_from_status(read_status(&output.stdout)?))
+}
+
+/// Look up a peer by their IP address
+/// Returns the peer info if the IP belongs to a device in our tailnet
+pub fn lookup_peer_by_ip(ip: &std::net::IpAddr) -> Result<Option<TailscalePeer>> {
+    let ip_str = ip.to_string();
+    let peers = get_tailscale_peers()?;
+
+    Ok(peers.into_iter().find(|peer| peer.ips.contains(&ip_str)))
+}
+
+/// Check if an IP address belongs to a peer in our tailnet
+pub fn is_tailscale_peer(ip: &std::net::IpAddr) -> bool {
+    lookup_peer_by_ip(ip).ok().flatten().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_device_name() {
+        let tailnet = "tailnet-abc.ts.net";
+
+        // Short name should get tailnet appended
+        assert_eq!(
+            resolve_device_name("my-laptop", tailnet),
+            "my-laptop.tailnet-abc.ts.net"
+        );
+
+        // Full hostname should be returned as-is
+        assert_eq!(
+            resolve_device_name("other-device.different-tailnet.ts.net", tailnet),
+            "other-device.different-tailnet.ts.net"
+        );
+    }
+
+    const TAILSCALE_STATUS: &str =
+        include_str!("../../tests/fixtures/tailscale/tailscale_status.json");
+    const HEADSCALE_STATUS: &str =
+        include_str!("../../tests/fixtures/tailscale/headscale_status.json");
+
+    fn sorted_peers(json: &str) -> Vec<TailscalePeer> {
+        let mut peers = peers_from_status(parse_status(json.as_bytes()).unwrap());
+        peers.sort_by(|a, b| a.device_name.cmp(&b.device_name));
+        peers
+    }
+
+    #[test]
+    fn test_resolve_device_name_keeps_ports_and_ip_literals() {
+        let tailnet = "alice.vpn.example.com";
+
+        assert_eq!(
+            resolve_device_name("lab-box:2222", tailnet),
+            "lab-box.alice.vpn.example.com:2222"
+        );
+        assert_eq!(resolve_device_name("100.64.0.1", tailnet), "100.64.0.1");
+        assert_eq!(
+            resolve_device_name("100.64.0.1:2222", tailnet),
+            "100.64.0.1:2222"
+        );
+        assert_eq!(
+            resolve_device_name("fd7a:115c:a1e0::1", tailnet),
+            "fd7a:115c:a1e0::1"
+        );
+        assert_eq!(
+            resolve_device_name("[fd7a:115c:a1e0::1]:2222", tailnet),
+            "[fd7a:115c:a1e0::1]:2222"
+        );
+        assert_eq!(
+            resolve_device_name("box.internal.example.com:2222", tailnet),
+            "box.internal.example.com:2222"
+        );
+
+        // Without a tailnet domain there's nothing to append
+        assert_eq!(resolve_device_name("lab-box", ""), "lab-box");
+        assert_eq!(
+            resolve_device_name("lab-box", ".example.com."),
+            "lab-box.example.com"
+        );
+    }
+
+    #[test]
+    fn test_tailscale_status_fixture() {
+        let info = info_from_status(parse_status(TAILSCALE_STATUS.as_bytes()).unwrap()).unwrap();
+        assert!(info.logged_in);
+        assert_eq!(info.device_name, "adams-macbook");
+        assert_eq!(info.tailnet, "tail1234.ts.net");
+        assert_eq!(info.hostname, "adams-macbook.tail1234.ts.net");
+        assert_eq!(info.ip, "100.101.102.103");
+
+        let peers = sorted_peers(TAILSCALE_STATUS);
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].device_name, "lab-server");
+        assert_eq!(peers[0].dns_name, "lab-server.tail1234.ts.net");
+        assert!(peers[0].online);
+        assert_eq!(peers[1].device_name, "old-desktop");
+        assert!(!peers[1].online);
+    }
+
+    #[test]
+    fn test_headscale_status_fixture() {
+        // No CurrentTailnet, and a custom base domain with the user in front
+        let info = info_from_status(parse_status(HEADSCALE_STATUS.as_bytes()).unwrap()).unwrap();
+        assert!(info.logged_in);
+        assert_eq!(info.device_name, "lab-box");
+        assert_eq!(info.tailnet, "alice.vpn.example.com");
+        assert_eq!(info.hostname, "lab-box.alice.vpn.example.com");
+        assert_eq!(
+            resolve_device_name("workstation", &info.tailnet),
+            "workstation.alice.vpn.example.com"
+        );
+
+        let peers = sorted_peers(HEADSCALE_STATUS);
+        assert_eq!(peers[0].device_name, "printer");
+        assert_eq!(peers[0].dns_name, "printer");
+        assert_eq!(peers[1].device_name, "workstation");
+        assert_eq!(peers[1].dns_name, "workstation.alice.vpn.example.com");
+    }
+
+    #[test]
+    fn test_reported_suffix_used_without_dns_name() {
+        let json = r#"{
+            "BackendState": "Running",
+            "Self": {"HostName": "lab-box", "DNSName": "", "TailscaleIPs": ["100.64.0.2"]},
+            "MagicDNSSuffix": "vpn.example.com.",
+            "CurrentTailnet": null,
+            "Peer": null
+        }"#;
+        let info = info_from_status(parse_status(json.as_bytes()).unwrap()).unwrap();
+        assert_eq!(info.device_name, "lab-box");
+        assert_eq!(info.tailnet, "vpn.example.com");
+        assert_eq!(info.hostname, "lab-box.vpn.example.com");
+    }
+
+    #[test]
+    fn test_parse_status_text() {
+        let text = "\
+100.101.102.103  adams-macbook  adam@  macOS   -
+100.64.0.5       lab-server     adam@  linux   active; direct 192.168.1.5:41641
+100.64.0.6       old-desktop    adam@  windows offline
+
+# Health check:
+#     - Some peers are advertising routes but --accept-routes is false
+";
+        let status = parse_status_text(text).unwrap();
+        let info = info_from_status(status.clone()).unwrap();
+        assert!(info.logged_in);
+        assert_eq!(info.device_name, "adams-macbook");
+        assert_eq!(info.ip, "100.101.102.103");
+        assert_eq!(info.hostname, "adams-macbook");
+
+        let mut peers = peers_from_status(status);
+        peers.sort_by(|a, b| a.device_name.cmp(&b.device_name));
+        assert_eq!(peers.len(), 2);
+        assert!(peers[0].online);
+        assert_eq!(peers[1].device_name, "old-desktop");
+        assert!(!peers[1].online);
+
+        let logged_out =
+            parse_status_text("Logged out.\nLog in at: https://login.tailscale.com/a/abc\n");
+        assert!(!info_from_status(logged_out.unwrap()).unwrap().logged_in);
+        assert!(parse_status_text("").is_none());
+        assert!(parse_status_text("something new entirely\n").is_none());
+    }
+
+    #[test]
+    fn test_running_without_own_ip_is_an_error() {
+        let json = r#"{"BackendState": "Running", "Self": {"HostName": "lab-box"}, "Peer": null}"#;
+        let err = info_from_status(parse_status(json.as_bytes()).unwrap()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TailscaleError>(),
+            Some(TailscaleError::Unparseable(_))
+        ));
+    }
+
+    #[test]
+    fn test_configured_tailnet_domain_is_a_fallback() {
+        let json = r#"{
+            "BackendState": "Running",
+            "Self": {"HostName": "lab-box", "DNSName": "", "TailscaleIPs": ["100.64.0.2"]},
+            "Peer": null
+        }"#;
+        let info = info_from_status(parse_status(json.as_bytes()).unwrap()).unwrap();
+        assert_eq!(info.tailnet, "");
+        assert_eq!(info.hostname, "lab-box");
+        assert_eq!(info.tailnet_or(Some("vpn.example.com")), "vpn.example.com");
+        assert_eq!(info.tailnet_or(None), "");
+
+        let info = info_from_status(parse_status(HEADSCALE_STATUS.as_bytes()).unwrap()).unwrap();
+        assert_eq!(
+            info.tailnet_or(Some("other.example.com")),
+            "alice.vpn.example.com"
+        );
+    }
+}
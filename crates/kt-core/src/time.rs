@@ -1,6 +1,8 @@
 //! Time utilities for k-Terminus
 //!
-//! Provides common time-related operations used across crates.
+//! Provides common time-related operations used across crates, including
+//! the timestamp formats shown to users: ISO-8601 on the wire and in `--long`
+//! output, and short relative forms ("3m ago") in tables.
 
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -49,6 +51,219 @@ pub fn elapsed_duration(since_millis: u64) -> Duration {
     Duration::from_millis(elapsed_millis(since_millis))
 }
 
+/// Seconds in a day, for calendar conversion
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Format a time as an ISO-8601 UTC timestamp, e.g. `2024-05-01T16:00:00Z`.
+///
+/// Sub-second precision is dropped. Times before the Unix epoch are clamped
+/// to the epoch.
+///
+/// # Examples
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use kt_core::time::format_iso8601;
+///
+/// let time = UNIX_EPOCH + Duration::from_secs(1_714_579_200);
+/// assert_eq!(format_iso8601(time), "2024-05-01T16:00:00Z");
+/// ```
+pub fn format_iso8601(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+    let secs_of_day = secs % SECS_PER_DAY;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day % 3600) / 60,
+        secs_of_day % 60
+    )
+}
+
+/// Parse a timestamp written by [`format_iso8601`].
+///
+/// Also accepts the `<unix seconds>Z` form sent by older orchestrators.
+/// Returns `None` for anything else, including timestamps with offsets or
+/// fractional seconds.
+pub fn parse_iso8601(s: &str) -> Option<SystemTime> {
+    let body = s.strip_suffix('Z')?;
+
+    if let Ok(secs) = body.parse::<u64>() {
+        return Some(UNIX_EPOCH + Duration::from_secs(secs));
+    }
+
+    let (date, clock) = body.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-');
+    let year: u64 = date_parts.next()?.parse().ok()?;
+    let month: u64 = date_parts.next()?.parse().ok()?;
+    let day: u64 = date_parts.next()?.parse().ok()?;
+
+    let mut clock_parts = clock.splitn(3, ':');
+    let hour: u64 = clock_parts.next()?.parse().ok()?;
+    let minute: u64 = clock_parts.next()?.parse().ok()?;
+    let second: u64 = clock_parts.next()?.parse().ok()?;
+
+    if year < 1970
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    // Reject dates like February 30th that roll over into the next month
+    if civil_from_days(days) != (year, month, day) {
+        return None;
+    }
+
+    let secs = days * SECS_PER_DAY + hour * 3600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Format how long ago a time was, e.g. "3m ago", "2h ago" or "yesterday".
+///
+/// Times less than a minute ago, and times in the future (clock skew between
+/// machines), are "just now". Anything older than a month is shown as a date.
+pub fn format_relative(time: SystemTime) -> String {
+    format_relative_to(time, SystemTime::now())
+}
+
+/// Offset of another machine's clock from ours, in milliseconds.
+///
+/// `remote_millis` is the other clock's reading (Unix milliseconds), taken
+/// somewhere between `sent` and `received` on our clock. It is taken to be
+/// half way, so the error is at most half the round trip. Positive when the
+/// other clock is ahead of ours.
+pub fn clock_offset_millis(remote_millis: u64, sent: SystemTime, received: SystemTime) -> i64 {
+    let round_trip = received.duration_since(sent).unwrap_or_default();
+    let midpoint = (sent + round_trip / 2)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as i64;
+    remote_millis as i64 - midpoint
+}
+
+/// `time` moved by `offset_millis`, e.g. our clock's reading moved onto a
+/// clock that is `offset_millis` ahead (see [`clock_offset_millis`]).
+pub fn offset_time(time: SystemTime, offset_millis: i64) -> SystemTime {
+    let offset = Duration::from_millis(offset_millis.unsigned_abs());
+    if offset_millis >= 0 {
+        time + offset
+    } else {
+        time.checked_sub(offset).unwrap_or(time)
+    }
+}
+
+/// Format how long before `now` a time was; see [`format_relative`].
+///
+/// Based purely on elapsed seconds, so the result doesn't depend on time
+/// zones or daylight saving: "yesterday" means 24 to 48 hours ago.
+pub fn format_relative_to(time: SystemTime, now: SystemTime) -> String {
+    let secs = match now.duration_since(time) {
+        Ok(elapsed) => elapsed.as_secs(),
+        Err(_) => return "just now".to_string(),
+    };
+
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86_399 => format!("{}h ago", secs / 3600),
+        86_400..=172_799 => "yesterday".to_string(),
+        172_800..=2_591_999 => format!("{}d ago", secs / SECS_PER_DAY),
+        _ => format_iso8601(time)[..10].to_string(),
+    }
+}
+
+/// Parse a duration written for humans, e.g. "30", "45s", "500ms", "5m",
+/// "1h30m" or "2d".
+///
+/// A bare number is seconds. Units are `ms`, `s`, `m`, `h` and `d`; several
+/// may be combined, largest first or not. Returns `None` for anything else.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if let Ok(secs) = s.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    if s.is_empty() {
+        return None;
+    }
+
+    let mut total_ms: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        if digits == 0 {
+            return None;
+        }
+        let value: u64 = rest[..digits].parse().ok()?;
+        let (unit_ms, unit_len) = match &rest.as_bytes()[digits..] {
+            [b'm', b's', ..] => (1, 2),
+            [b's', ..] => (1000, 1),
+            [b'm', ..] => (60 * 1000, 1),
+            [b'h', ..] => (3600 * 1000, 1),
+            [b'd', ..] => (SECS_PER_DAY * 1000, 1),
+            _ => return None,
+        };
+        total_ms = total_ms.checked_add(value.checked_mul(unit_ms)?)?;
+        rest = &rest[digits + unit_len..];
+    }
+    Some(Duration::from_millis(total_ms))
+}
+
+/// Format a duration the way [`parse_duration`] reads it, in the largest
+/// unit that represents it exactly, e.g. "500ms", "90s", "5m" or "1h".
+///
+/// Anything below a millisecond is dropped.
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_millis() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let secs = duration.as_secs();
+    match (secs % 3600, secs % 60) {
+        _ if secs == 0 => "0s".to_string(),
+        (0, _) => format!("{}h", secs / 3600),
+        (_, 0) => format!("{}m", secs / 60),
+        _ => format!("{}s", secs),
+    }
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) civil date.
+///
+/// Howard Hinnant's `civil_from_days`, restricted to dates after the epoch.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Convert a civil date (from 1970 on) to days since the Unix epoch
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    (era * 146_097 + doe).saturating_sub(719_468)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -79,4 +294,161 @@ mod tests {
         let elapsed = elapsed_millis(future);
         assert_eq!(elapsed, 0);
     }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_format_iso8601_known_dates() {
+        assert_eq!(format_iso8601(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(format_iso8601(at(1_714_579_200)), "2024-05-01T16:00:00Z");
+        // Leap day and the last second of a leap year
+        assert_eq!(format_iso8601(at(951_782_400)), "2000-02-29T00:00:00Z");
+        assert_eq!(format_iso8601(at(1_735_689_599)), "2024-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_iso8601_roundtrip() {
+        // Step through ~60 years at an interval that hits every time of day
+        for secs in (0..2_000_000_000u64).step_by(7_919_993) {
+            let time = at(secs);
+            assert_eq!(parse_iso8601(&format_iso8601(time)), Some(time), "{}", secs);
+        }
+    }
+
+    #[test]
+    fn test_parse_iso8601() {
+        assert_eq!(parse_iso8601("1714579200Z"), Some(at(1_714_579_200)));
+        assert_eq!(
+            parse_iso8601("2024-05-01T16:00:00Z"),
+            Some(at(1_714_579_200))
+        );
+
+        assert_eq!(parse_iso8601(""), None);
+        assert_eq!(parse_iso8601("2024-05-01T16:00:00"), None);
+        assert_eq!(parse_iso8601("2024-02-30T00:00:00Z"), None);
+        assert_eq!(parse_iso8601("2023-02-29T00:00:00Z"), None);
+        assert_eq!(parse_iso8601("2024-05-01T24:00:00Z"), None);
+        assert_eq!(parse_iso8601("1969-12-31T23:59:59Z"), None);
+    }
+
+    #[test]
+    fn test_format_relative_boundaries() {
+        let now = at(1_714_579_200);
+        let ago = |secs: u64| format_relative_to(now - Duration::from_secs(secs), now);
+
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(59), "just now");
+        assert_eq!(ago(60), "1m ago");
+        assert_eq!(ago(3599), "59m ago");
+        assert_eq!(ago(3600), "1h ago");
+        assert_eq!(ago(86_399), "23h ago");
+        assert_eq!(ago(86_400), "yesterday");
+        assert_eq!(ago(172_799), "yesterday");
+        assert_eq!(ago(172_800), "2d ago");
+        assert_eq!(ago(2_591_999), "29d ago");
+        assert_eq!(ago(2_592_000), "2024-04-01");
+    }
+
+    #[test]
+    fn test_format_relative_future_is_just_now() {
+        let now = at(1_714_579_200);
+        for skew in [1, 59, 60, 3600, 86_400 * 365] {
+            assert_eq!(
+                format_relative_to(now + Duration::from_secs(skew), now),
+                "just now"
+            );
+        }
+    }
+
+    #[test]
+    fn test_clock_offset() {
+        let sent = at(1_714_579_200);
+        let received = sent + Duration::from_millis(200);
+
+        // Remote clock 5 minutes ahead, read half way through the round trip
+        let remote = 1_714_579_200_000 + 100 + 300_000;
+        let offset = clock_offset_millis(remote, sent, received);
+        assert_eq!(offset, 300_000);
+        assert_eq!(
+            offset_time(received, offset),
+            received + Duration::from_secs(300)
+        );
+        assert_eq!(
+            format_relative_to(at(1_714_579_200 - 300), offset_time(received, offset)),
+            "10m ago"
+        );
+
+        // Remote clock behind
+        let offset = clock_offset_millis(1_714_579_200_100 - 90_000, sent, received);
+        assert_eq!(offset, -90_000);
+        assert_eq!(
+            format_relative_to(at(1_714_579_200 - 120), offset_time(received, offset)),
+            "just now"
+        );
+    }
+
+    #[test]
+    fn test_format_relative_is_monotonic() {
+        // Older times never render as more recent than newer ones
+        let now = at(1_714_579_200);
+        let rank = |s: &str| -> u64 {
+            if s == "just now" {
+                0
+            } else if s == "yesterday" {
+                86_400
+            } else if let Some(n) = s.strip_suffix("m ago") {
+                n.parse::<u64>().unwrap() * 60
+            } else if let Some(n) = s.strip_suffix("h ago") {
+                n.parse::<u64>().unwrap() * 3600
+            } else if let Some(n) = s.strip_suffix("d ago") {
+                n.parse::<u64>().unwrap() * 86_400
+            } else {
+                u64::MAX
+            }
+        };
+
+        let mut previous = 0;
+        for secs in (0..3_000_000u64).step_by(997) {
+            let formatted = format_relative_to(now - Duration::from_secs(secs), now);
+            let current = rank(&formatted);
+            assert!(current >= previous, "{} after {}", formatted, previous);
+            previous = current;
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90"), Some(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("5m"), Some(Duration::from_secs(300)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::from_secs(5400)));
+        assert_eq!(parse_duration("2d"), Some(Duration::from_secs(172_800)));
+        assert_eq!(parse_duration(" 10s "), Some(Duration::from_secs(10)));
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("1s250ms"), Some(Duration::from_millis(1250)));
+
+        for invalid in ["", "s", "5x", "1.5h", "-5s", "5 m", "m5"] {
+            assert_eq!(parse_duration(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_format_duration() {
+        for (secs, expected) in [
+            (0, "0s"),
+            (45, "45s"),
+            (90, "90s"),
+            (300, "5m"),
+            (7200, "2h"),
+        ] {
+            assert_eq!(format_duration(Duration::from_secs(secs)), expected);
+        }
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+        for secs in [45, 300, 5400, 172_800] {
+            let duration = Duration::from_secs(secs);
+            assert_eq!(parse_duration(&format_duration(duration)), Some(duration));
+        }
+    }
 }
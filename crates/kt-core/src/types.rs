@@ -2,15 +2,61 @@
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::str::FromStr;
+
+use crate::error::MachineIdError;
+
+/// Maximum length of a machine ID in bytes
+pub const MAX_MACHINE_ID_LEN: usize = 128;
 
 /// Unique identifier for a machine
+///
+/// Canonical IDs are lowercase ASCII letters, digits, `-`, `_` and `.`,
+/// starting with a letter or digit, at most [`MAX_MACHINE_ID_LEN`] bytes.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct MachineId(pub String);
 
 impl MachineId {
-    /// Create a new machine ID
+    /// Create a new machine ID from a trusted, already-canonical string
+    ///
+    /// Use [`MachineId::parse`] for anything that came from an agent or a
+    /// user. Debug builds assert that `id` is valid and canonical.
     pub fn new(id: impl Into<String>) -> Self {
-        Self(id.into())
+        let id = id.into();
+        debug_assert!(
+            Self::parse(&id).is_ok_and(|parsed| parsed.0 == id),
+            "non-canonical machine ID: {:?}",
+            id
+        );
+        Self(id)
+    }
+
+    /// Validate and canonicalize an untrusted machine ID
+    ///
+    /// ASCII letters are lowercased, so `GPU-Box` and `gpu-box` name the
+    /// same machine. Anything outside ASCII is rejected rather than
+    /// normalized, so look-alike characters (e.g. the Kelvin sign or
+    /// Cyrillic letters) can't impersonate an existing ID.
+    pub fn parse(id: &str) -> Result<Self, MachineIdError> {
+        if id.is_empty() {
+            return Err(MachineIdError::Empty);
+        }
+        if id.len() > MAX_MACHINE_ID_LEN {
+            return Err(MachineIdError::TooLong {
+                len: id.len(),
+                max: MAX_MACHINE_ID_LEN,
+            });
+        }
+        if let Some(c) = id
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')))
+        {
+            return Err(MachineIdError::InvalidChar(c));
+        }
+        if !id.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+            return Err(MachineIdError::InvalidStart);
+        }
+        Ok(Self(id.to_ascii_lowercase()))
     }
 
     /// Create a machine ID from a public key fingerprint
@@ -18,10 +64,10 @@ impl MachineId {
         // Use first 16 chars of fingerprint as ID
         let id = fingerprint
             .chars()
-            .filter(|c| c.is_alphanumeric())
+            .filter(|c| c.is_ascii_alphanumeric())
             .take(16)
             .collect::<String>()
-            .to_lowercase();
+            .to_ascii_lowercase();
         Self(id)
     }
 
@@ -37,15 +83,23 @@ impl fmt::Display for MachineId {
     }
 }
 
+impl FromStr for MachineId {
+    type Err = MachineIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
 impl From<String> for MachineId {
     fn from(s: String) -> Self {
-        Self(s)
+        Self::new(s)
     }
 }
 
 impl From<&str> for MachineId {
     fn from(s: &str) -> Self {
-        Self(s.to_string())
+        Self::new(s)
     }
 }
 
@@ -110,6 +164,93 @@ mod tests {
         assert!(id.as_str().chars().all(|c| c.is_alphanumeric()));
     }
 
+    #[test]
+    fn test_machine_id_parse_canonicalizes_case() {
+        let id = MachineId::parse("GPU-Box").unwrap();
+        assert_eq!(id.as_str(), "gpu-box");
+        assert_eq!(id, MachineId::parse("gpu-box").unwrap());
+        assert_eq!(
+            "Lab_01.Local".parse::<MachineId>().unwrap().as_str(),
+            "lab_01.local"
+        );
+    }
+
+    #[test]
+    fn test_machine_id_parse_rejects_malformed() {
+        assert_eq!(MachineId::parse(""), Err(MachineIdError::Empty));
+        assert_eq!(MachineId::parse("-box"), Err(MachineIdError::InvalidStart));
+        assert_eq!(
+            MachineId::parse(".hidden"),
+            Err(MachineIdError::InvalidStart)
+        );
+        assert_eq!(
+            MachineId::parse("a/b"),
+            Err(MachineIdError::InvalidChar('/'))
+        );
+        assert_eq!(
+            MachineId::parse("gpu box"),
+            Err(MachineIdError::InvalidChar(' '))
+        );
+        assert_eq!(
+            MachineId::parse("box\n"),
+            Err(MachineIdError::InvalidChar('\n'))
+        );
+        assert_eq!(
+            MachineId::parse("box\u{1b}[31m"),
+            Err(MachineIdError::InvalidChar('\u{1b}'))
+        );
+
+        let long = "a".repeat(MAX_MACHINE_ID_LEN + 1);
+        assert_eq!(
+            MachineId::parse(&long),
+            Err(MachineIdError::TooLong {
+                len: MAX_MACHINE_ID_LEN + 1,
+                max: MAX_MACHINE_ID_LEN
+            })
+        );
+        assert!(MachineId::parse(&"a".repeat(MAX_MACHINE_ID_LEN)).is_ok());
+    }
+
+    #[test]
+    fn test_machine_id_parse_rejects_unicode_lookalikes() {
+        // KELVIN SIGN lowercases to an ASCII 'k' under Unicode rules
+        assert_eq!(
+            MachineId::parse("\u{212A}ube"),
+            Err(MachineIdError::InvalidChar('\u{212A}'))
+        );
+        // Cyrillic 'а' in place of Latin 'a'
+        assert_eq!(
+            MachineId::parse("gpu-b\u{0430}x"),
+            Err(MachineIdError::InvalidChar('\u{0430}'))
+        );
+        // Fullwidth letters NFKC-normalize to ASCII
+        assert_eq!(
+            MachineId::parse("\u{FF27}PU"),
+            Err(MachineIdError::InvalidChar('\u{FF27}'))
+        );
+        // Decomposed 'é' and invisible zero-width joiners
+        assert_eq!(
+            MachineId::parse("cafe\u{0301}"),
+            Err(MachineIdError::InvalidChar('\u{0301}'))
+        );
+        assert_eq!(
+            MachineId::parse("gpu\u{200B}box"),
+            Err(MachineIdError::InvalidChar('\u{200B}'))
+        );
+        // A long run of multi-byte characters is measured in bytes
+        assert!(matches!(
+            MachineId::parse(&"\u{00e9}".repeat(MAX_MACHINE_ID_LEN)),
+            Err(MachineIdError::TooLong { .. })
+        ));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "non-canonical machine ID")]
+    fn test_machine_id_new_asserts_canonical() {
+        let _ = MachineId::new("GPU-Box");
+    }
+
     #[test]
     fn test_connection_status_display() {
         assert_eq!(format!("{}", ConnectionStatus::Connected), "connected");
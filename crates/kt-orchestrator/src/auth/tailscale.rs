@@ -85,11 +85,13 @@ impl TailscaleVerifier {
 
     /// Check if Tailscale is available and we're logged in
     pub fn is_available() -> bool {
-        tailscale::get_tailscale_info()
-            .ok()
-            .flatten()
-            .map(|info| info.logged_in)
-            .unwrap_or(false)
+        match tailscale::get_tailscale_info() {
+            Ok(info) => info.is_some_and(|info| info.logged_in),
+            Err(e) => {
+                tracing::warn!("Couldn't read Tailscale status: {:#}", e);
+                false
+            }
+        }
     }
 }
 
@@ -107,7 +109,6 @@ mod tests {
     fn test_verifier_creation() {
         let verifier = TailscaleVerifier::new();
         // Just verify it can be created without panicking
-        assert!(true);
         drop(verifier);
     }
 }
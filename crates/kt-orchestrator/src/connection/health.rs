@@ -3,8 +3,10 @@
 use std::sync::Arc;
 use std::time::Duration;
 
+use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope};
 use kt_core::time::current_time_millis;
 
 use super::pool::AgentCommand;
@@ -29,10 +31,12 @@ impl HealthMonitor {
     /// This spawns a background task that:
     /// - Sends periodic heartbeats to all connected agents
     /// - Checks for agents that haven't responded within the timeout
-    /// - Disconnects unresponsive agents
+    /// - Disconnects unresponsive agents, announcing them and their sessions
+    ///   on `events`
     pub fn spawn(
         &self,
         state: Arc<OrchestratorState>,
+        events: broadcast::Sender<IpcEventEnvelope>,
         cancel: CancellationToken,
     ) -> tokio::task::JoinHandle<()> {
         let interval = self.interval;
@@ -59,10 +63,11 @@ impl HealthMonitor {
                                     timeout
                                 );
 
-                                // Clean up all sessions for this machine BEFORE removing the connection
-                                // to prevent orphaned sessions (atomic cleanup)
-                                // Use coordinator.sessions for proper state management
-                                let sessions = state.coordinator.sessions.list_for_machine(&conn.machine_id);
+                                // Remove the connection and its sessions together, the same
+                                // way the SSH handler's disconnect does; whichever removes
+                                // the connection first announces it
+                                let (removed, sessions) =
+                                    state.coordinator.atomic_disconnect(&conn.machine_id).await;
                                 for session in sessions {
                                     // Use try_close() CAS to ensure only one cleanup path wins
                                     // This prevents races between health monitor, cleanup task, and disconnect handler
@@ -72,13 +77,27 @@ impl HealthMonitor {
                                             session.id,
                                             conn.machine_id
                                         );
-                                        state.coordinator.sessions.remove(session.id);
+                                        session.emit(
+                                            &events,
+                                            &state.epoch,
+                                            IpcEvent::SessionClosed {
+                                                session_id: session.id.to_string(),
+                                                exit_code: None,
+                                                reason: Some(CloseReason::MachineDisconnected),
+                                            },
+                                        );
                                     }
                                     // If try_close() returns false, another cleanup path already claimed this session
                                 }
 
                                 conn.disconnect();
-                                state.coordinator.connections.remove(&conn.machine_id);
+                                if removed.is_some() {
+                                    let _ = events.send(state.epoch.wrap_event(
+                                        IpcEvent::MachineDisconnected {
+                                            machine_id: conn.machine_id.to_string(),
+                                        },
+                                    ));
+                                }
                                 continue;
                             }
 
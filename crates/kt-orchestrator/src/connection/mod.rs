@@ -2,6 +2,8 @@
 
 mod health;
 mod pool;
+mod reconnect;
 
 pub use health::HealthMonitor;
 pub use pool::{AgentCommand, ConnectionLimitExceeded, ConnectionPool, TunnelConnection};
+pub use reconnect::{begin_reconnect_grace, run_reconnect_grace};
@@ -2,16 +2,17 @@
 
 use dashmap::DashMap;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use bytes::Bytes;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::sync::CancellationToken;
 
-use kt_core::time::current_time_millis;
+use kt_core::ipc::{MachineInfo, MachineStatus};
+use kt_core::time::{current_time_millis, format_iso8601};
 use kt_core::types::MachineId;
-use kt_protocol::{Message, SessionId, TerminalSize};
+use kt_protocol::{AgentCapabilities, Capability, Message, SessionId, TerminalSize};
 
 /// Error returned when connection limit is exceeded
 #[derive(Debug, Clone)]
@@ -43,6 +44,15 @@ pub enum AgentCommand {
         shell: Option<String>,
         env: Vec<(String, String)>,
         size: TerminalSize,
+        cwd: Option<String>,
+        /// Plain pipes instead of a PTY when false
+        allocate_pty: bool,
+        /// Log level for the agent's logs about this session
+        log_level: Option<String>,
+        /// The client's terminal type, for the session's `TERM`
+        term: Option<String>,
+        /// The client renders 24-bit color
+        truecolor: bool,
     },
     /// Send input data to a session
     SessionInput { session_id: SessionId, data: Bytes },
@@ -53,6 +63,9 @@ pub enum AgentCommand {
     },
     /// Close a session
     CloseSession { session_id: SessionId },
+    /// Change a runtime setting of the agent (see
+    /// [`TunnelConnection::update_config`])
+    UpdateConfig { key: String, value: String },
     /// Send a heartbeat
     Heartbeat { timestamp: u64 },
 }
@@ -76,12 +89,22 @@ impl AgentCommand {
                 shell,
                 env,
                 size,
+                cwd,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
             } => (
                 session_id,
                 Message::SessionCreate {
                     shell,
                     env,
                     initial_size: size,
+                    cwd,
+                    allocate_pty,
+                    log_level,
+                    term,
+                    truecolor,
                 },
             ),
             AgentCommand::SessionInput { session_id, data } => (session_id, Message::Data(data)),
@@ -89,6 +112,10 @@ impl AgentCommand {
             AgentCommand::CloseSession { session_id } => {
                 (session_id, Message::SessionClose { exit_code: None })
             }
+            AgentCommand::UpdateConfig { key, value } => (
+                SessionId::CONTROL,
+                Message::AgentConfigUpdate { key, value },
+            ),
             AgentCommand::Heartbeat { timestamp } => {
                 (SessionId::CONTROL, Message::Heartbeat { timestamp })
             }
@@ -103,34 +130,57 @@ impl AgentCommand {
 /// making it suitable for high-concurrency scenarios where multiple sessions
 /// may be accessing different machines simultaneously.
 ///
+/// A machine whose connection dropped is kept aside as *reconnecting* (see
+/// [`Self::mark_reconnecting`]) rather than forgotten: lookups like
+/// [`Self::get`] no longer find it, but [`Self::list_reconnecting`] does,
+/// until it connects again or is removed when its grace period runs out.
+///
 /// # Thread Safety
 /// All methods on `ConnectionPool` are thread-safe and can be called from
 /// multiple tasks without external synchronization.
 pub struct ConnectionPool {
     /// Connections indexed by machine ID
     connections: DashMap<MachineId, Arc<TunnelConnection>>,
+    /// Dropped connections waiting for their machine to come back, with
+    /// when they dropped
+    reconnecting: DashMap<MachineId, (Arc<TunnelConnection>, Instant)>,
 }
 
+/// Where the agent's ack of a setting change goes (`Err` with its reason if
+/// it didn't apply the change)
+type ConfigAckSender = oneshot::Sender<Result<(), String>>;
+
 /// A connection to a remote machine
 pub struct TunnelConnection {
     /// Machine identifier
     pub machine_id: MachineId,
     /// Machine alias
     pub alias: Option<String>,
+    /// Free-form label the agent reported
+    pub label: Option<String>,
     /// Hostname
     pub hostname: Option<String>,
     /// Operating system
     pub os: String,
     /// CPU architecture
     pub arch: String,
+    /// Optional features the agent advertised at registration
+    pub capabilities: AgentCapabilities,
+    /// Times this machine has reconnected (see [`ConnectionPool::insert`])
+    pub reconnect_count: u64,
     /// Channel for sending commands to this agent
     pub command_tx: mpsc::Sender<AgentCommand>,
     /// Cancellation token to disconnect this specific connection
     pub cancel: CancellationToken,
+    /// Setting changes sent and waiting for the agent's ack, oldest first,
+    /// each with where the ack goes
+    pending_config: Mutex<Vec<(String, ConfigAckSender)>>,
     /// Last heartbeat received (epoch millis)
     last_heartbeat_millis: AtomicU64,
     /// When the connection was established
     connected_at: Instant,
+    /// System time when the connection was established (for display)
+    connected_at_system: SystemTime,
 }
 
 impl TunnelConnection {
@@ -147,21 +197,98 @@ impl TunnelConnection {
         Self {
             machine_id,
             alias,
+            label: None,
             hostname,
             os,
             arch,
+            capabilities: AgentCapabilities::empty(),
+            reconnect_count: 0,
             command_tx,
             cancel,
+            pending_config: Mutex::new(Vec::new()),
             last_heartbeat_millis: AtomicU64::new(current_time_millis()),
             connected_at: Instant::now(),
+            connected_at_system: SystemTime::now(),
         }
     }
 
+    /// Set the label the agent reported at registration
+    pub fn with_label(mut self, label: Option<String>) -> Self {
+        self.label = label;
+        self
+    }
+
+    /// Set the capabilities the agent advertised at registration
+    pub fn with_capabilities(mut self, capabilities: AgentCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the reconnect count the agent reported at registration
+    pub fn with_reconnect_count(mut self, reconnect_count: u64) -> Self {
+        self.reconnect_count = reconnect_count;
+        self
+    }
+
+    /// Check whether the agent supports an optional feature
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(capability)
+    }
+
     /// Signal this connection to disconnect
     pub fn disconnect(&self) {
         self.cancel.cancel();
     }
 
+    /// Send a change of runtime setting `key` to the agent
+    ///
+    /// The receiver gets the agent's answer: `Err` with its reason if it
+    /// didn't apply the change. Only for agents with
+    /// [`Capability::AgentConfig`]; others drop the connection on it.
+    pub async fn update_config(
+        &self,
+        key: String,
+        value: String,
+    ) -> Result<oneshot::Receiver<Result<(), String>>, String> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        self.pending_config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((key.clone(), ack_tx));
+        let command = AgentCommand::UpdateConfig { key, value };
+        if let Err(e) = self.command_tx.send(command).await {
+            // Dropping the receiver closes the entry pushed above
+            drop(ack_rx);
+            self.pending_config
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain(|(_, ack_tx)| !ack_tx.is_closed());
+            return Err(e.to_string());
+        }
+        Ok(ack_rx)
+    }
+
+    /// Pass the agent's ack of a change of `key` to the oldest change of it
+    /// waiting for one
+    ///
+    /// Returns false if none was waiting.
+    pub fn ack_config(&self, key: &str, result: Result<(), String>) -> bool {
+        let mut pending = self
+            .pending_config
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let Some(index) = pending
+            .iter()
+            .position(|(pending_key, _)| pending_key == key)
+        else {
+            return false;
+        };
+        let (_, ack_tx) = pending.remove(index);
+        // The requester may have given up waiting
+        let _ = ack_tx.send(result);
+        true
+    }
+
     /// Update the last heartbeat timestamp
     pub fn record_heartbeat(&self) {
         self.last_heartbeat_millis
@@ -184,6 +311,48 @@ impl TunnelConnection {
     pub fn uptime(&self) -> Duration {
         self.connected_at.elapsed()
     }
+
+    /// Get connection time as an ISO-8601 UTC timestamp
+    pub fn connected_at_iso(&self) -> String {
+        format_iso8601(self.connected_at_system)
+    }
+
+    /// Get the last heartbeat time as an ISO-8601 UTC timestamp
+    pub fn last_heartbeat_iso(&self) -> String {
+        format_iso8601(UNIX_EPOCH + Duration::from_millis(self.last_heartbeat_millis()))
+    }
+
+    /// Describe this machine for IPC clients
+    pub fn machine_info(&self, status: MachineStatus, session_count: usize) -> MachineInfo {
+        MachineInfo {
+            id: self.machine_id.to_string(),
+            alias: self.alias.clone(),
+            label: self.label.clone(),
+            hostname: self
+                .hostname
+                .clone()
+                .unwrap_or_else(|| self.machine_id.to_string()),
+            os: self.os.clone(),
+            arch: self.arch.clone(),
+            status,
+            connected_at: Some(self.connected_at_iso()),
+            last_heartbeat: Some(self.last_heartbeat_iso()),
+            session_count,
+            tags: vec![],
+            capabilities: self.capabilities.names(),
+            reconnect_count: self.reconnect_count,
+        }
+    }
+
+    /// Whether this is the machine `id_or_alias` names, by ID or alias,
+    /// ignoring ASCII case
+    fn is_named(&self, id_or_alias: &str) -> bool {
+        self.machine_id.as_str().eq_ignore_ascii_case(id_or_alias)
+            || self
+                .alias
+                .as_deref()
+                .is_some_and(|alias| alias.eq_ignore_ascii_case(id_or_alias))
+    }
 }
 
 impl ConnectionPool {
@@ -191,6 +360,7 @@ impl ConnectionPool {
     pub fn new() -> Self {
         Self {
             connections: DashMap::new(),
+            reconnecting: DashMap::new(),
         }
     }
 
@@ -204,19 +374,20 @@ impl ConnectionPool {
 
     /// Get a connection by machine ID or alias
     ///
-    /// First tries an exact match by machine ID, then falls back to searching
-    /// by alias. Returns `None` if no connection matches.
+    /// First tries a match by canonical machine ID, then falls back to searching
+    /// by alias. Both ignore ASCII case. Returns `None` if no connection matches.
     pub fn get_by_id_or_alias(&self, id_or_alias: &str) -> Option<Arc<TunnelConnection>> {
-        // First try exact machine ID match
-        let machine_id = MachineId::new(id_or_alias);
-        if let Some(conn) = self.get(&machine_id) {
-            return Some(conn);
+        // First try machine ID match
+        if let Ok(machine_id) = MachineId::parse(id_or_alias) {
+            if let Some(conn) = self.get(&machine_id) {
+                return Some(conn);
+            }
         }
 
         // Fall back to alias search
         for entry in self.connections.iter() {
             if let Some(ref alias) = entry.alias {
-                if alias == id_or_alias {
+                if alias.eq_ignore_ascii_case(id_or_alias) {
                     return Some(Arc::clone(&entry));
                 }
             }
@@ -248,9 +419,23 @@ impl ConnectionPool {
     ///
     /// If a connection with the same machine ID already exists, it will be
     /// replaced. The old connection (if any) is not explicitly disconnected;
-    /// callers should handle cleanup before calling `insert`.
-    pub fn insert(&self, connection: TunnelConnection) {
+    /// callers should handle cleanup before calling `insert`. A machine that
+    /// was reconnecting is connected again.
+    ///
+    /// The reconnect count stays tied to the machine ID: replacing a known
+    /// machine's connection counts as a reconnect even if the agent restarted
+    /// and reports a lower count.
+    pub fn insert(&self, mut connection: TunnelConnection) {
         let machine_id = connection.machine_id.clone();
+        let previous = self
+            .reconnecting
+            .remove(&machine_id)
+            .map(|(_, (previous, _))| previous)
+            .or_else(|| self.get(&machine_id));
+        if let Some(previous) = previous {
+            let count = previous.reconnect_count + 1;
+            connection.reconnect_count = connection.reconnect_count.max(count);
+        }
         self.connections.insert(machine_id, Arc::new(connection));
     }
 
@@ -262,7 +447,8 @@ impl ConnectionPool {
     ///
     /// # Connection Replacement Policy
     ///
-    /// Replacement connections (same machine_id) are always allowed, even when at the limit.
+    /// Replacement connections (same machine_id, including a machine that is
+    /// reconnecting) are always allowed, even when at the limit.
     /// This is **intentional behavior** for the following reasons:
     ///
     /// 1. **Network resilience**: Agents may reconnect after network interruptions. Blocking
@@ -293,16 +479,18 @@ impl ConnectionPool {
         // Check limit only if this is a new connection (not replacing existing)
         // Replacement connections are always allowed - see docstring for rationale
         if let Some(max) = max_connections {
-            if !self.connections.contains_key(&machine_id) && self.connections.len() >= max as usize
-            {
+            let known = self.connections.contains_key(&machine_id)
+                || self.reconnecting.contains_key(&machine_id);
+            let current = self.connections.len() + self.reconnecting.len();
+            if !known && current >= max as usize {
                 return Err(ConnectionLimitExceeded {
-                    current: self.connections.len(),
+                    current,
                     max: max as usize,
                 });
             }
         }
 
-        self.connections.insert(machine_id, Arc::new(connection));
+        self.insert(connection);
         Ok(())
     }
 
@@ -315,6 +503,60 @@ impl ConnectionPool {
     pub fn remove(&self, machine_id: &MachineId) -> Option<Arc<TunnelConnection>> {
         self.connections.remove(machine_id).map(|(_, v)| v)
     }
+
+    /// Set a dropped connection aside until its machine connects again
+    ///
+    /// Returns the connection, or `None` if the machine had none (e.g. the
+    /// health monitor already removed it).
+    pub fn mark_reconnecting(&self, machine_id: &MachineId) -> Option<Arc<TunnelConnection>> {
+        let connection = self.remove(machine_id)?;
+        self.reconnecting.insert(
+            machine_id.clone(),
+            (Arc::clone(&connection), Instant::now()),
+        );
+        Some(connection)
+    }
+
+    /// Whether the machine's connection dropped and it hasn't come back yet
+    pub fn is_reconnecting(&self, machine_id: &MachineId) -> bool {
+        self.reconnecting.contains_key(machine_id)
+    }
+
+    /// Find a reconnecting machine by ID or alias, ignoring ASCII case
+    pub fn get_reconnecting(&self, id_or_alias: &str) -> Option<Arc<TunnelConnection>> {
+        self.reconnecting
+            .iter()
+            .find(|entry| entry.0.is_named(id_or_alias))
+            .map(|entry| Arc::clone(&entry.0))
+    }
+
+    /// The last connections of machines that are reconnecting
+    pub fn list_reconnecting(&self) -> Vec<Arc<TunnelConnection>> {
+        self.reconnecting
+            .iter()
+            .map(|entry| Arc::clone(&entry.0))
+            .collect()
+    }
+
+    /// Stop waiting for machines that have been reconnecting for `grace` as
+    /// of `now`, returning their last connections
+    pub fn remove_expired_reconnecting(
+        &self,
+        grace: Duration,
+        now: Instant,
+    ) -> Vec<Arc<TunnelConnection>> {
+        let expired: Vec<MachineId> = self
+            .reconnecting
+            .iter()
+            .filter(|entry| now.saturating_duration_since(entry.1) >= grace)
+            .map(|entry| entry.key().clone())
+            .collect();
+        expired
+            .iter()
+            .filter_map(|id| self.reconnecting.remove(id))
+            .map(|(_, (connection, _))| connection)
+            .collect()
+    }
 }
 
 impl Default for ConnectionPool {
@@ -441,6 +683,33 @@ mod tests {
         assert_eq!(result.unwrap().machine_id.as_str(), "foo-alias");
     }
 
+    #[test]
+    fn test_connection_pool_get_by_id_or_alias_ignores_case() {
+        let pool = ConnectionPool::new();
+        let (tx, _rx) = mpsc::channel(1);
+        pool.insert(TunnelConnection::new(
+            MachineId::parse("GPU-Box").unwrap(),
+            Some("Lab-GPU".to_string()),
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            tx,
+            CancellationToken::new(),
+        ));
+
+        for query in ["GPU-Box", "gpu-box", "GPU-BOX", "lab-gpu", "LAB-GPU"] {
+            let conn = pool.get_by_id_or_alias(query);
+            assert_eq!(
+                conn.map(|c| c.machine_id.to_string()).as_deref(),
+                Some("gpu-box"),
+                "lookup of {query:?}"
+            );
+        }
+
+        // Look-alikes must not resolve to the ASCII ID
+        assert!(pool.get_by_id_or_alias("gpu-b\u{0430}x").is_none());
+    }
+
     #[test]
     fn test_connection_pool_list() {
         let pool = ConnectionPool::new();
@@ -468,6 +737,30 @@ mod tests {
         assert_eq!(conn.arch, "x86_64");
     }
 
+    #[test]
+    fn test_reconnect_count_is_kept_per_machine() {
+        let pool = ConnectionPool::new();
+        let id = MachineId::new("flaky");
+        pool.insert(create_test_connection("flaky").with_reconnect_count(4));
+        assert_eq!(pool.get(&id).unwrap().reconnect_count, 4);
+
+        // The agent restarted and counts from zero again
+        pool.mark_reconnecting(&id);
+        pool.insert(create_test_connection("flaky"));
+        assert_eq!(pool.get(&id).unwrap().reconnect_count, 5);
+
+        // A higher count from the agent wins
+        pool.insert(create_test_connection("flaky").with_reconnect_count(9));
+        let conn = pool.get(&id).unwrap();
+        let info = conn.machine_info(MachineStatus::Connected, 0);
+        assert_eq!(info.reconnect_count, 9);
+
+        // Other machines start out at their own count
+        pool.insert(create_test_connection("steady"));
+        let steady = pool.get(&MachineId::new("steady")).unwrap();
+        assert_eq!(steady.reconnect_count, 0);
+    }
+
     #[test]
     fn test_tunnel_connection_heartbeat() {
         let conn = create_test_connection("test-machine");
@@ -511,6 +804,11 @@ mod tests {
             shell: Some("/bin/bash".to_string()),
             env: vec![("TERM".to_string(), "xterm".to_string())],
             size: TerminalSize { cols: 80, rows: 24 },
+            cwd: Some("/tmp".to_string()),
+            allocate_pty: false,
+            log_level: Some("debug".to_string()),
+            term: Some("xterm-256color".to_string()),
+            truecolor: true,
         };
 
         let (session_id, msg) = cmd.to_message();
@@ -521,8 +819,18 @@ mod tests {
                 shell,
                 env,
                 initial_size,
+                cwd,
+                allocate_pty,
+                log_level,
+                term,
+                truecolor,
             } => {
+                assert!(!allocate_pty);
+                assert_eq!(log_level, Some("debug".to_string()));
+                assert_eq!(term, Some("xterm-256color".to_string()));
+                assert!(truecolor);
                 assert_eq!(shell, Some("/bin/bash".to_string()));
+                assert_eq!(cwd, Some("/tmp".to_string()));
                 assert_eq!(env, vec![("TERM".to_string(), "xterm".to_string())]);
                 assert_eq!(initial_size.cols, 80);
                 assert_eq!(initial_size.rows, 24);
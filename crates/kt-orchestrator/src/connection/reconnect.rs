@@ -0,0 +1,204 @@
+//! Grace period for machines whose connection drops
+//!
+//! Agents usually reconnect within a second or two of losing their
+//! connection. Rather than removing such a machine and closing its sessions
+//! right away, which makes it flap in `list`, the orchestrator keeps it for
+//! `reconnect_grace` (see [`OrchestratorConfig`]) as
+//! [`MachineStatus::Reconnecting`], together with its sessions. If it
+//! connects again in time it is `Connected` once more and keeps its
+//! sessions; otherwise [`run_reconnect_grace`] removes it and its sessions
+//! just as an immediate disconnect would have.
+//!
+//! An agent that lost sessions along with the connection reports them when
+//! it comes back, and they are closed then.
+//!
+//! [`OrchestratorConfig`]: kt_core::config::OrchestratorConfig
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope, MachineStatus};
+use kt_core::types::MachineId;
+
+use crate::state::OrchestratorState;
+
+/// Interval between checks for machines that didn't come back in time
+const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Keep a machine whose connection dropped as reconnecting, announcing it
+/// with [`IpcEvent::MachineUpdated`]
+///
+/// Returns `false` if the grace period is off or the machine wasn't
+/// connected; the caller then removes it and its sessions right away.
+pub async fn begin_reconnect_grace(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    machine_id: &MachineId,
+) -> bool {
+    let grace = *state.config().reconnect_grace;
+    if grace.is_zero() {
+        return false;
+    }
+    let Some(connection) = state.coordinator.begin_reconnect(machine_id).await else {
+        return false;
+    };
+
+    let sessions = state.coordinator.sessions.list_for_machine(machine_id);
+    let session_count = sessions.len();
+    tracing::info!(
+        %machine_id,
+        session_count,
+        "Machine connection dropped, waiting {:?} for it to reconnect",
+        grace
+    );
+    let info = connection.machine_info(MachineStatus::Reconnecting, session_count);
+    let _ = events.send(state.epoch.wrap_event(IpcEvent::MachineUpdated(info)));
+    true
+}
+
+/// Run the task removing machines that don't reconnect within their grace
+/// period.
+///
+/// # Arguments
+///
+/// * `state` - The orchestrator state containing the connection pool
+/// * `events` - IPC event channel the removals are announced on
+/// * `cancel` - Cancellation token for graceful shutdown
+pub async fn run_reconnect_grace(
+    state: Arc<OrchestratorState>,
+    events: broadcast::Sender<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                expire_reconnecting(&state, &events, Instant::now()).await;
+            }
+            _ = cancel.cancelled() => {
+                tracing::debug!("Reconnect grace task shutting down");
+                break;
+            }
+        }
+    }
+}
+
+/// Remove machines that have been reconnecting for the whole grace period
+/// as of `now`, closing their sessions
+async fn expire_reconnecting(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    now: Instant,
+) {
+    let grace = *state.config().reconnect_grace;
+    for (connection, sessions) in state.coordinator.expire_reconnecting(grace, now).await {
+        let machine_id = &connection.machine_id;
+        tracing::info!(
+            %machine_id,
+            "Machine didn't reconnect within {:?}, removing it and {} session(s)",
+            grace,
+            sessions.len()
+        );
+        for session in &sessions {
+            if session.try_close() {
+                session.emit(
+                    events,
+                    &state.epoch,
+                    IpcEvent::SessionClosed {
+                        session_id: session.id.to_string(),
+                        exit_code: None,
+                        reason: Some(CloseReason::MachineDisconnected),
+                    },
+                );
+            }
+        }
+        let _ = events.send(state.epoch.wrap_event(IpcEvent::MachineDisconnected {
+            machine_id: machine_id.to_string(),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::connection::TunnelConnection;
+
+    fn connection(id: &str) -> TunnelConnection {
+        let (tx, _rx) = mpsc::channel(1);
+        TunnelConnection::new(
+            MachineId::new(id),
+            Some("nas".to_string()),
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            tx,
+            CancellationToken::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_machine_kept_until_grace_runs_out() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let grace = *state.config().reconnect_grace;
+        let (events, mut event_rx) = broadcast::channel(16);
+        let machine_id = MachineId::new("machine-1");
+        let pool = &state.coordinator.connections;
+        pool.insert(connection("machine-1"));
+        let session_id = state.coordinator.sessions.create(machine_id.clone(), None);
+
+        assert!(begin_reconnect_grace(&state, &events, &machine_id).await);
+        assert!(pool.get(&machine_id).is_none());
+        assert!(pool.get_reconnecting("NAS").is_some());
+        let IpcEvent::MachineUpdated(info) = event_rx.try_recv().unwrap().event else {
+            panic!("expected MachineUpdated");
+        };
+        assert_eq!(info.status, MachineStatus::Reconnecting);
+        assert_eq!(info.session_count, 1);
+
+        // Coming back in time keeps the machine and its session
+        pool.insert(connection("machine-1"));
+        assert!(!pool.is_reconnecting(&machine_id));
+        expire_reconnecting(&state, &events, Instant::now() + grace).await;
+        assert!(pool.get(&machine_id).is_some());
+        assert!(state.coordinator.sessions.get(session_id).is_some());
+
+        // Not coming back removes both once the grace period is over
+        assert!(begin_reconnect_grace(&state, &events, &machine_id).await);
+        let _ = event_rx.try_recv();
+        expire_reconnecting(&state, &events, Instant::now()).await;
+        assert!(pool.is_reconnecting(&machine_id));
+        expire_reconnecting(&state, &events, Instant::now() + grace).await;
+        assert!(pool.list_reconnecting().is_empty());
+        assert!(state.coordinator.sessions.get(session_id).is_none());
+
+        let IpcEvent::SessionClosed { reason, .. } = event_rx.try_recv().unwrap().event else {
+            panic!("expected SessionClosed");
+        };
+        assert_eq!(reason, Some(CloseReason::MachineDisconnected));
+        assert!(matches!(
+            event_rx.try_recv().unwrap().event,
+            IpcEvent::MachineDisconnected { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_no_grace_when_disabled() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig {
+            reconnect_grace: Duration::ZERO.into(),
+            ..Default::default()
+        });
+        let (events, _event_rx) = broadcast::channel(16);
+        let machine_id = MachineId::new("machine-1");
+        let pool = &state.coordinator.connections;
+        pool.insert(connection("machine-1"));
+
+        assert!(!begin_reconnect_grace(&state, &events, &machine_id).await);
+        assert!(pool.get(&machine_id).is_some());
+    }
+}
@@ -18,6 +18,7 @@
 //! - Multiple cleanup paths (health monitor, disconnect handler) racing to clean up
 
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use kt_core::types::MachineId;
@@ -154,7 +155,7 @@ impl StateCoordinator {
     ///
     /// // Send events for all removed sessions
     /// for session in sessions {
-    ///     event_tx.send(IpcEvent::SessionClosed { session_id: session.id.to_string() });
+    ///     event_tx.send(IpcEvent::SessionClosed { session_id: session.id.to_string(), exit_code: None });
     /// }
     ///
     /// if conn.is_some() {
@@ -176,6 +177,36 @@ impl StateCoordinator {
 
         (connection, sessions)
     }
+
+    /// Keep a machine whose connection dropped, with its sessions, while it
+    /// has a chance to reconnect
+    ///
+    /// Returns the dropped connection, or `None` if the machine wasn't
+    /// connected.
+    pub async fn begin_reconnect(&self, machine_id: &MachineId) -> Option<Arc<TunnelConnection>> {
+        let _lock = self.write().await;
+        self.connections.mark_reconnecting(machine_id)
+    }
+
+    /// Give up on machines that have been reconnecting for `grace`,
+    /// removing them and their sessions together
+    ///
+    /// Returns each machine's last connection with the sessions removed.
+    pub async fn expire_reconnecting(
+        &self,
+        grace: Duration,
+        now: Instant,
+    ) -> Vec<(Arc<TunnelConnection>, Vec<Arc<SessionHandle>>)> {
+        let _lock = self.write().await;
+        self.connections
+            .remove_expired_reconnecting(grace, now)
+            .into_iter()
+            .map(|connection| {
+                let sessions = self.sessions.remove_by_machine(&connection.machine_id);
+                (connection, sessions)
+            })
+            .collect()
+    }
 }
 
 impl Default for StateCoordinator {
@@ -0,0 +1,196 @@
+//! Machine groups
+//!
+//! A group is a named list of machines (IDs or aliases) that clients can
+//! select as `@name`. Groups start out as defined in `[orchestrator.groups]`
+//! and can be changed at runtime with `ModifyGroup`; runtime changes last
+//! until the orchestrator restarts.
+//!
+//! Members don't have to be connected, or known at all: a group can name
+//! machines that haven't joined yet. Clients are told which members match
+//! no known machine so they can warn about typos.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use kt_core::ipc::GroupAction;
+use kt_core::types::MachineId;
+
+/// Named machine groups, ordered by name
+#[derive(Debug, Default)]
+pub struct MachineGroups {
+    groups: RwLock<BTreeMap<String, Vec<String>>>,
+}
+
+impl MachineGroups {
+    /// Groups as defined in the config file
+    ///
+    /// Groups with an invalid name are skipped with a warning, as are
+    /// invalid members.
+    pub fn from_config(groups: &HashMap<String, Vec<String>>) -> Self {
+        let mut parsed = BTreeMap::new();
+        for (name, members) in groups {
+            let name = match parse_group_name(name) {
+                Ok(name) => name,
+                Err(e) => {
+                    tracing::warn!("Ignoring machine group {:?}: {}", name, e);
+                    continue;
+                }
+            };
+            let mut list = Vec::new();
+            for member in members {
+                match MachineId::parse(member) {
+                    Ok(member) => push_unique(&mut list, member.0),
+                    Err(e) => {
+                        tracing::warn!("Ignoring member {:?} of group {}: {}", member, name, e)
+                    }
+                }
+            }
+            parsed.insert(name, list);
+        }
+        Self {
+            groups: RwLock::new(parsed),
+        }
+    }
+
+    /// All groups with their members
+    pub fn list(&self) -> Vec<(String, Vec<String>)> {
+        self.groups
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, members)| (name.clone(), members.clone()))
+            .collect()
+    }
+
+    /// Apply a change to a group
+    ///
+    /// Returns the group's name and members afterwards, or `None` once
+    /// deleted. Adding a member twice or removing a non-member is a no-op.
+    pub fn modify(
+        &self,
+        group: &str,
+        action: GroupAction,
+        members: &[String],
+    ) -> Result<Option<(String, Vec<String>)>, String> {
+        let name = parse_group_name(group)?;
+        let members = members
+            .iter()
+            .map(|m| {
+                MachineId::parse(m)
+                    .map(|id| id.0)
+                    .map_err(|e| format!("Invalid member '{}': {}", m, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut groups = self.groups.write().unwrap_or_else(|e| e.into_inner());
+        match action {
+            GroupAction::Create => {
+                if groups.contains_key(&name) {
+                    return Err(format!("Group already exists: {}", name));
+                }
+                let mut list = Vec::new();
+                for member in members {
+                    push_unique(&mut list, member);
+                }
+                groups.insert(name.clone(), list.clone());
+                Ok(Some((name, list)))
+            }
+            GroupAction::Add | GroupAction::Remove => {
+                let Some(list) = groups.get_mut(&name) else {
+                    return Err(format!("Group not found: {}", name));
+                };
+                if action == GroupAction::Add {
+                    for member in members {
+                        push_unique(list, member);
+                    }
+                } else {
+                    list.retain(|m| !members.contains(m));
+                }
+                Ok(Some((name, list.clone())))
+            }
+            GroupAction::Delete => {
+                if groups.remove(&name).is_none() {
+                    return Err(format!("Group not found: {}", name));
+                }
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Validate and canonicalize a group name (same rules as machine IDs; a
+/// leading `@` is accepted and dropped)
+fn parse_group_name(name: &str) -> Result<String, String> {
+    let name = name.strip_prefix('@').unwrap_or(name);
+    MachineId::parse(name)
+        .map(|id| id.0)
+        .map_err(|e| format!("Invalid group name '{}': {}", name, e))
+}
+
+fn push_unique(list: &mut Vec<String>, member: String) {
+    if !list.contains(&member) {
+        list.push(member);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(members: &[&str]) -> Vec<String> {
+        members.iter().map(|m| m.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_config_skips_invalid_entries() {
+        let config = HashMap::from([
+            ("HomeLab".to_string(), names(&["nas", "pi1", "NAS", "bad id"])),
+            ("bad name".to_string(), names(&["nas"])),
+        ]);
+        let groups = MachineGroups::from_config(&config);
+        assert_eq!(
+            groups.list(),
+            vec![("homelab".to_string(), names(&["nas", "pi1"]))]
+        );
+    }
+
+    #[test]
+    fn test_modify_lifecycle() {
+        let groups = MachineGroups::default();
+        let created = groups
+            .modify("@homelab", GroupAction::Create, &names(&["nas", "pi1"]))
+            .unwrap();
+        assert_eq!(created, Some(("homelab".to_string(), names(&["nas", "pi1"]))));
+        assert!(groups
+            .modify("homelab", GroupAction::Create, &[])
+            .unwrap_err()
+            .contains("already exists"));
+
+        let added = groups
+            .modify("homelab", GroupAction::Add, &names(&["pi2", "nas"]))
+            .unwrap();
+        assert_eq!(added.unwrap().1, names(&["nas", "pi1", "pi2"]));
+
+        let removed = groups
+            .modify("homelab", GroupAction::Remove, &names(&["pi1", "ghost"]))
+            .unwrap();
+        assert_eq!(removed.unwrap().1, names(&["nas", "pi2"]));
+
+        assert_eq!(groups.modify("homelab", GroupAction::Delete, &[]), Ok(None));
+        assert!(groups.list().is_empty());
+        assert!(groups
+            .modify("homelab", GroupAction::Add, &names(&["nas"]))
+            .unwrap_err()
+            .contains("not found"));
+    }
+
+    #[test]
+    fn test_modify_rejects_invalid_names() {
+        let groups = MachineGroups::default();
+        assert!(groups.modify("", GroupAction::Create, &[]).is_err());
+        assert!(groups
+            .modify("homelab", GroupAction::Create, &names(&["no spaces"]))
+            .is_err());
+        assert!(groups.list().is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+//! Logical client IDs held by live IPC connections
+//!
+//! A client authenticating with a logical client ID takes over the sessions
+//! that ID owns, so a second process using the same ID would take the first
+//! one's sessions from under it. Clients send a random instance ID, shared by
+//! all connections of one process (the desktop app keeps two, and the CLI
+//! reconnects while attached), and a logical ID held by a live connection of
+//! another instance is refused unless the client asks to take it over, which
+//! closes the other instance's connections.
+//!
+//! A connection holds its ID until its handler sees it close. A process that
+//! just died holds it for the moment that takes, so a conflicting claim waits
+//! up to [`STALE_CONNECTION_GRACE`] for the holder to let go before it is
+//! refused. Clients that send no instance ID predate this and never conflict.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+/// How long a claim waits for a conflicting connection to turn out stale
+pub const STALE_CONNECTION_GRACE: Duration = Duration::from_secs(1);
+
+/// How often a waiting claim checks again
+const CLAIM_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug)]
+struct Holder {
+    connection_id: String,
+    instance_id: Option<String>,
+    /// Cancelled to close the connection when its ID is taken over
+    closer: CancellationToken,
+}
+
+impl Holder {
+    fn conflicts_with(&self, instance_id: Option<&str>) -> bool {
+        match (self.instance_id.as_deref(), instance_id) {
+            (Some(held), Some(claimed)) => held != claimed,
+            _ => false,
+        }
+    }
+}
+
+type Holders = Arc<Mutex<HashMap<String, Vec<Holder>>>>;
+
+/// Logical client IDs and the live connections holding them
+#[derive(Debug, Default)]
+pub struct ActiveClients {
+    holders: Holders,
+}
+
+/// A connection's hold on a logical client ID, released when dropped
+#[derive(Debug)]
+pub struct ClientClaim {
+    holders: Holders,
+    client_id: String,
+    connection_id: String,
+}
+
+impl Drop for ClientClaim {
+    fn drop(&mut self) {
+        let mut holders = self.holders.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(connections) = holders.get_mut(&self.client_id) {
+            connections.retain(|holder| holder.connection_id != self.connection_id);
+            if connections.is_empty() {
+                holders.remove(&self.client_id);
+            }
+        }
+    }
+}
+
+impl ActiveClients {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `client_id` for connection `connection_id` of `instance_id`
+    ///
+    /// Connections of other instances holding it are given
+    /// [`STALE_CONNECTION_GRACE`] to close, and with `takeover` are told to
+    /// close through their `closer`. Returns how many are still holding it
+    /// if they don't.
+    pub async fn claim(
+        &self,
+        client_id: &str,
+        connection_id: &str,
+        instance_id: Option<&str>,
+        takeover: bool,
+        closer: CancellationToken,
+    ) -> Result<ClientClaim, usize> {
+        let deadline = Instant::now() + STALE_CONNECTION_GRACE;
+        loop {
+            let conflicting = {
+                let mut holders = self.holders.lock().unwrap_or_else(|e| e.into_inner());
+                let connections = holders.entry(client_id.to_string()).or_default();
+                let conflicting: Vec<CancellationToken> = connections
+                    .iter()
+                    .filter(|holder| holder.conflicts_with(instance_id))
+                    .map(|holder| holder.closer.clone())
+                    .collect();
+                if conflicting.is_empty() {
+                    connections.push(Holder {
+                        connection_id: connection_id.to_string(),
+                        instance_id: instance_id.map(String::from),
+                        closer,
+                    });
+                    return Ok(ClientClaim {
+                        holders: Arc::clone(&self.holders),
+                        client_id: client_id.to_string(),
+                        connection_id: connection_id.to_string(),
+                    });
+                }
+                conflicting
+            };
+
+            if takeover {
+                conflicting.iter().for_each(CancellationToken::cancel);
+            }
+            if Instant::now() >= deadline {
+                return Err(conflicting.len());
+            }
+            tokio::time::sleep(CLAIM_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Number of connections holding `client_id`
+    pub fn holders(&self, client_id: &str) -> usize {
+        let holders = self.holders.lock().unwrap_or_else(|e| e.into_inner());
+        holders.get(client_id).map_or(0, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn claim(
+        clients: &ActiveClients,
+        connection_id: &str,
+        instance_id: Option<&str>,
+        takeover: bool,
+    ) -> Result<(ClientClaim, CancellationToken), usize> {
+        let closer = CancellationToken::new();
+        clients
+            .claim(
+                "desktop",
+                connection_id,
+                instance_id,
+                takeover,
+                closer.clone(),
+            )
+            .await
+            .map(|claim| (claim, closer))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_live_holder_of_other_instance_refuses_claim() {
+        let clients = ActiveClients::new();
+        let first = claim(&clients, "conn-1", Some("a"), false).await.unwrap();
+        // Another connection of the same process is fine
+        let sibling = claim(&clients, "conn-2", Some("a"), false).await.unwrap();
+        assert_eq!(clients.holders("desktop"), 2);
+
+        let started = Instant::now();
+        assert_eq!(
+            claim(&clients, "conn-3", Some("b"), false)
+                .await
+                .unwrap_err(),
+            2
+        );
+        assert!(started.elapsed() >= STALE_CONNECTION_GRACE);
+        assert!(!first.1.is_cancelled());
+
+        drop((first, sibling));
+        assert_eq!(clients.holders("desktop"), 0);
+        assert!(claim(&clients, "conn-3", Some("b"), false).await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_stale_holder_does_not_block_reconnect() {
+        let clients = ActiveClients::new();
+        let (old, _) = claim(&clients, "conn-1", Some("a"), false).await.unwrap();
+
+        // The old process died; its handler notices a moment later
+        tokio::spawn(async move {
+            tokio::time::sleep(STALE_CONNECTION_GRACE / 4).await;
+            drop(old);
+        });
+        assert!(claim(&clients, "conn-2", Some("b"), false).await.is_ok());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_takeover_closes_holder() {
+        let clients = ActiveClients::new();
+        let (old, closer) = claim(&clients, "conn-1", Some("a"), false).await.unwrap();
+
+        // The old connection's handler closes it when told to
+        let handler = tokio::spawn(async move {
+            closer.cancelled().await;
+            drop(old);
+        });
+        let (_new, _) = claim(&clients, "conn-2", Some("b"), true).await.unwrap();
+        handler.await.unwrap();
+        assert_eq!(clients.holders("desktop"), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_clients_without_instance_never_conflict() {
+        let clients = ActiveClients::new();
+        let _legacy = claim(&clients, "conn-1", None, false).await.unwrap();
+        let _current = claim(&clients, "conn-2", Some("a"), false).await.unwrap();
+        let _other = claim(&clients, "conn-3", None, false).await.unwrap();
+        assert_eq!(clients.holders("desktop"), 3);
+    }
+}
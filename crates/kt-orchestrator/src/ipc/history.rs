@@ -0,0 +1,271 @@
+//! Recent event history for `GetEventsSince`
+//!
+//! Clients that lose their IPC connection briefly reconnect and ask for the
+//! events they missed by sequence number instead of re-syncing everything.
+//! The history keeps the most recent events, bounded both by count and by
+//! the amount of terminal output held, and remembers the newest sequence
+//! number it had to drop so it can tell a client when it can't fill a gap.
+//!
+//! While the orchestrator sheds load (see [`crate::memory`]), the history
+//! keeps much less terminal output. Only output is dropped for this, so a
+//! client catching up still gets every state change, and is told that its
+//! replay is truncated.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast;
+
+use kt_core::ipc::{IpcEvent, IpcEventEnvelope};
+
+/// Most events kept for replay
+pub const EVENT_HISTORY_CAPACITY: usize = 2048;
+
+/// Most terminal output bytes kept for replay (1 MiB)
+pub const EVENT_HISTORY_MAX_OUTPUT_BYTES: usize = 1024 * 1024;
+
+/// Most terminal output bytes kept for replay while shedding load (64 KiB)
+pub const SHED_HISTORY_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Events a client missed, as answered to `GetEventsSince`
+#[derive(Debug, Clone)]
+pub struct MissedEvents {
+    /// Events after the requested sequence number, oldest first
+    pub events: Vec<IpcEventEnvelope>,
+    /// True if some events after the requested sequence number were dropped
+    pub truncated: bool,
+    /// Oldest sequence number still held (None if the history is empty)
+    pub oldest_available_seq: Option<u64>,
+}
+
+/// Bounded history of recently broadcast events
+#[derive(Debug, Default)]
+pub struct EventHistory {
+    inner: Mutex<HistoryInner>,
+}
+
+#[derive(Debug, Default)]
+struct HistoryInner {
+    events: VecDeque<IpcEventEnvelope>,
+    output_bytes: usize,
+    /// Newest sequence number that was dropped or never recorded
+    dropped_through: Option<u64>,
+    /// Keep only [`SHED_HISTORY_MAX_OUTPUT_BYTES`] of output
+    shedding: bool,
+}
+
+impl HistoryInner {
+    /// Drop the oldest terminal output, and nothing else, until at most
+    /// `max_bytes` of it is left
+    fn trim_output(&mut self, max_bytes: usize) {
+        let mut excess = self.output_bytes.saturating_sub(max_bytes);
+        if excess == 0 {
+            return;
+        }
+        let mut freed = 0;
+        let mut dropped_through = self.dropped_through;
+        self.events.retain(|envelope| {
+            let len = output_len(envelope);
+            if excess == 0 || len == 0 {
+                return true;
+            }
+            excess = excess.saturating_sub(len);
+            freed += len;
+            dropped_through = dropped_through.max(Some(envelope.seq));
+            false
+        });
+        self.output_bytes -= freed;
+        self.dropped_through = dropped_through;
+    }
+}
+
+impl EventHistory {
+    /// Create an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a broadcast event, dropping the oldest ones past the limits
+    pub fn record(&self, envelope: IpcEventEnvelope) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.output_bytes += output_len(&envelope);
+        inner.events.push_back(envelope);
+
+        while inner.events.len() > EVENT_HISTORY_CAPACITY
+            || inner.output_bytes > EVENT_HISTORY_MAX_OUTPUT_BYTES
+        {
+            let Some(dropped) = inner.events.pop_front() else {
+                break;
+            };
+            inner.output_bytes -= output_len(&dropped);
+            inner.dropped_through = Some(dropped.seq);
+        }
+        if inner.shedding {
+            inner.trim_output(SHED_HISTORY_MAX_OUTPUT_BYTES);
+        }
+    }
+
+    /// Start or stop keeping less terminal output to save memory
+    pub fn set_shedding(&self, shedding: bool) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.shedding = shedding;
+        if shedding {
+            inner.trim_output(SHED_HISTORY_MAX_OUTPUT_BYTES);
+        }
+    }
+
+    /// Note that events up to `seq` were missed by the recorder
+    pub fn mark_dropped_through(&self, seq: u64) {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.dropped_through = Some(inner.dropped_through.map_or(seq, |s| s.max(seq)));
+    }
+
+    /// Events after `since_seq` that pass `filter`
+    pub fn since(
+        &self,
+        since_seq: u64,
+        filter: impl Fn(&IpcEventEnvelope) -> bool,
+    ) -> MissedEvents {
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        MissedEvents {
+            events: inner
+                .events
+                .iter()
+                .filter(|e| e.seq > since_seq && filter(e))
+                .cloned()
+                .collect(),
+            truncated: inner.dropped_through.is_some_and(|s| s > since_seq),
+            oldest_available_seq: inner.events.front().map(|e| e.seq),
+        }
+    }
+
+    /// Record every event sent on `event_tx` until the channel closes
+    pub async fn record_from(&self, mut event_rx: broadcast::Receiver<IpcEventEnvelope>) {
+        let mut lagged = false;
+        loop {
+            match event_rx.recv().await {
+                Ok(envelope) => {
+                    if lagged {
+                        // Everything before this event may be missing
+                        self.mark_dropped_through(envelope.seq.saturating_sub(1));
+                        lagged = false;
+                    }
+                    self.record(envelope);
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Event history lagged, {} events not recorded", n);
+                    lagged = true;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Terminal output bytes carried by an event
+fn output_len(envelope: &IpcEventEnvelope) -> usize {
+    match &envelope.event {
+        IpcEvent::TerminalOutput { data, .. } => data.len(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(seq: u64, session_id: &str, len: usize) -> IpcEventEnvelope {
+        IpcEventEnvelope {
+            seq,
+            timestamp: 0,
+            event: IpcEvent::TerminalOutput {
+                session_id: session_id.to_string(),
+                data: vec![b'x'; len],
+                stream: kt_core::ipc::OutputStream::Stdout,
+            },
+            session_seq: None,
+        }
+    }
+
+    #[test]
+    fn test_since_filters_and_orders() {
+        let history = EventHistory::new();
+        for seq in 1..=5 {
+            history.record(output(seq, if seq % 2 == 0 { "a" } else { "b" }, 1));
+        }
+
+        let missed = history.since(1, |e| {
+            matches!(&e.event, IpcEvent::TerminalOutput { session_id, .. } if session_id == "a")
+        });
+        let seqs: Vec<_> = missed.events.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![2, 4]);
+        assert!(!missed.truncated);
+        assert_eq!(missed.oldest_available_seq, Some(1));
+    }
+
+    #[test]
+    fn test_truncated_after_eviction() {
+        let history = EventHistory::new();
+        let big = EVENT_HISTORY_MAX_OUTPUT_BYTES / 2 + 1;
+        history.record(output(1, "a", big));
+        history.record(output(2, "a", big));
+
+        // Seq 1 was evicted to stay within the output budget
+        let missed = history.since(0, |_| true);
+        assert!(missed.truncated);
+        assert_eq!(missed.oldest_available_seq, Some(2));
+
+        // A client that already saw seq 1 missed nothing
+        assert!(!history.since(1, |_| true).truncated);
+    }
+
+    #[test]
+    fn test_mark_dropped_through() {
+        let history = EventHistory::new();
+        history.mark_dropped_through(10);
+        history.record(output(11, "a", 1));
+
+        assert!(history.since(5, |_| true).truncated);
+        assert!(!history.since(10, |_| true).truncated);
+    }
+
+    #[test]
+    fn test_shedding_drops_only_output() {
+        let history = EventHistory::new();
+        let chunk = SHED_HISTORY_MAX_OUTPUT_BYTES / 2;
+        history.record(output(1, "a", chunk));
+        history.record(IpcEventEnvelope {
+            seq: 2,
+            timestamp: 0,
+            event: IpcEvent::SessionClosed {
+                session_id: "b".to_string(),
+                exit_code: Some(0),
+                reason: None,
+            },
+            session_seq: None,
+        });
+        for seq in 3..=5 {
+            history.record(output(seq, "a", chunk));
+        }
+
+        history.set_shedding(true);
+        let seqs = |since| -> Vec<u64> {
+            let missed = history.since(since, |_| true);
+            missed.events.iter().map(|e| e.seq).collect()
+        };
+        // The oldest output went, the close stayed
+        assert_eq!(seqs(0), vec![2, 4, 5]);
+        assert!(history.since(0, |_| true).truncated);
+        assert!(history.since(2, |_| true).truncated);
+        assert!(!history.since(3, |_| true).truncated);
+
+        // New output keeps within the smaller budget
+        history.record(output(6, "a", chunk));
+        assert_eq!(seqs(0), vec![2, 5, 6]);
+
+        // Once load is no longer shed, the full budget is used again
+        history.set_shedding(false);
+        history.record(output(7, "a", chunk));
+        assert_eq!(seqs(0), vec![2, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,288 @@
+//! Idempotency keys for `CreateSession`
+//!
+//! A client that times out waiting for `CreateSession` can't tell whether
+//! the session was created. By sending the same `idempotency_key` with the
+//! retry it gets the session the first request created instead of a second
+//! one. Keys are scoped to the logical client ID, so two clients using the
+//! same key still get separate sessions.
+//!
+//! Keys are forgotten after [`IDEMPOTENCY_KEY_TTL`], and each client keeps at
+//! most [`MAX_KEYS_PER_CLIENT`], oldest dropped first.
+//!
+//! A request reserves its key before creating the session
+//! ([`IdempotencyKeys::reserve`]), in the same step as looking it up, so two
+//! retries arriving at once on different connections can't both miss the
+//! lookup and create two sessions: the second is told the first is still in
+//! progress.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use kt_protocol::SessionId;
+
+/// How long a key is remembered after the session was created
+pub const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Most keys remembered per client
+pub const MAX_KEYS_PER_CLIENT: usize = 256;
+
+#[derive(Debug)]
+struct KeyEntry {
+    key: String,
+    /// Session created with the key (None while it is being created)
+    session_id: Option<SessionId>,
+    created: Instant,
+}
+
+/// What [`IdempotencyKeys::reserve`] found for a key
+#[derive(Debug)]
+pub enum KeyLookup<'a, T> {
+    /// Nobody used the key: create the session and
+    /// [`complete`](KeyReservation::complete) the reservation
+    Reserved(KeyReservation<'a>),
+    /// The session created earlier with the key
+    Created(T),
+    /// Another request with the key is still creating its session
+    InProgress,
+}
+
+/// A key reserved for a session being created, released when dropped
+/// without being completed (e.g. the request failed)
+#[derive(Debug)]
+pub struct KeyReservation<'a> {
+    keys: &'a IdempotencyKeys,
+    client_id: String,
+    key: String,
+    completed: bool,
+}
+
+impl KeyReservation<'_> {
+    /// Remember that the key created `session_id` at `now`
+    pub fn complete(mut self, session_id: SessionId, now: Instant) {
+        self.completed = true;
+        let mut clients = self.keys.lock();
+        let entries = clients.entry(self.client_id.clone()).or_default();
+        match entries.iter_mut().find(|entry| entry.key == self.key) {
+            Some(entry) => {
+                entry.session_id = Some(session_id);
+                entry.created = now;
+            }
+            // Dropped to make room while the session was created
+            None => push_entry(entries, &self.key, Some(session_id), now),
+        }
+    }
+}
+
+impl Drop for KeyReservation<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.keys.remove(&self.client_id, &self.key);
+        }
+    }
+}
+
+/// Recently used `CreateSession` idempotency keys, per client
+#[derive(Debug, Default)]
+pub struct IdempotencyKeys {
+    /// Client ID -> its keys, oldest first
+    clients: Mutex<HashMap<String, VecDeque<KeyEntry>>>,
+}
+
+impl IdempotencyKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, VecDeque<KeyEntry>>> {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Look up `key` for `client_id` at `now`, reserving it if it is unused
+    ///
+    /// `open` turns the ID of the session created earlier with the key into
+    /// that session, or None if it is gone, in which case the key is
+    /// reserved again. It runs under the store's lock, so it must not use
+    /// the store. Also drops every client's expired keys, so clients that
+    /// went away don't keep theirs.
+    pub fn reserve<T>(
+        &self,
+        client_id: &str,
+        key: &str,
+        now: Instant,
+        open: impl FnOnce(SessionId) -> Option<T>,
+    ) -> KeyLookup<'_, T> {
+        let mut clients = self.lock();
+        clients.retain(|_, entries| {
+            while entries.front().is_some_and(|entry| is_expired(entry, now)) {
+                entries.pop_front();
+            }
+            !entries.is_empty()
+        });
+
+        let entries = clients.entry(client_id.to_string()).or_default();
+        if let Some(entry) = entries.iter().find(|entry| entry.key == key) {
+            match entry.session_id {
+                None => return KeyLookup::InProgress,
+                Some(session_id) => {
+                    if let Some(session) = open(session_id) {
+                        return KeyLookup::Created(session);
+                    }
+                    // Closed since: the key no longer stands for a session
+                }
+            }
+        }
+        entries.retain(|entry| entry.key != key);
+        push_entry(entries, key, None, now);
+        KeyLookup::Reserved(KeyReservation {
+            keys: self,
+            client_id: client_id.to_string(),
+            key: key.to_string(),
+            completed: false,
+        })
+    }
+
+    /// Forget `key`, e.g. because the session it named is gone
+    pub fn remove(&self, client_id: &str, key: &str) {
+        let mut clients = self.lock();
+        if let Some(entries) = clients.get_mut(client_id) {
+            entries.retain(|entry| entry.key != key);
+            if entries.is_empty() {
+                clients.remove(client_id);
+            }
+        }
+    }
+
+    /// Number of clients with remembered keys
+    #[cfg(test)]
+    fn client_count(&self) -> usize {
+        self.lock().len()
+    }
+}
+
+/// Add a key to a client's keys, dropping its oldest if it has too many
+fn push_entry(
+    entries: &mut VecDeque<KeyEntry>,
+    key: &str,
+    session_id: Option<SessionId>,
+    now: Instant,
+) {
+    if entries.len() >= MAX_KEYS_PER_CLIENT {
+        entries.pop_front();
+    }
+    entries.push_back(KeyEntry {
+        key: key.to_string(),
+        session_id,
+        created: now,
+    });
+}
+
+fn is_expired(entry: &KeyEntry, now: Instant) -> bool {
+    now.saturating_duration_since(entry.created) >= IDEMPOTENCY_KEY_TTL
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reserve `key` and complete it with `session_id`
+    fn create(keys: &IdempotencyKeys, client_id: &str, key: &str, session_id: u32, now: Instant) {
+        match keys.reserve(client_id, key, now, Some) {
+            KeyLookup::Reserved(reservation) => {
+                reservation.complete(SessionId::new(session_id), now)
+            }
+            other => panic!("Expected a reservation, got {:?}", other),
+        }
+    }
+
+    /// Session created earlier with `key`, without reserving it
+    fn get(keys: &IdempotencyKeys, client_id: &str, key: &str, now: Instant) -> Option<SessionId> {
+        match keys.reserve(client_id, key, now, Some) {
+            KeyLookup::Created(session_id) => Some(session_id),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_keys_are_client_scoped() {
+        let keys = IdempotencyKeys::new();
+        let now = Instant::now();
+        create(&keys, "client-a", "retry-1", 1, now);
+
+        assert_eq!(
+            get(&keys, "client-a", "retry-1", now),
+            Some(SessionId::new(1))
+        );
+        assert_eq!(get(&keys, "client-b", "retry-1", now), None);
+        assert_eq!(get(&keys, "client-a", "retry-2", now), None);
+
+        keys.remove("client-a", "retry-1");
+        assert_eq!(get(&keys, "client-a", "retry-1", now), None);
+        assert_eq!(keys.client_count(), 0);
+    }
+
+    #[test]
+    fn test_reserved_key_is_in_progress_until_completed_or_dropped() {
+        let keys = IdempotencyKeys::new();
+        let now = Instant::now();
+
+        let KeyLookup::Reserved(reservation) = keys.reserve("client-a", "retry-1", now, Some)
+        else {
+            panic!("Expected a reservation");
+        };
+        assert!(matches!(
+            keys.reserve("client-a", "retry-1", now, Some),
+            KeyLookup::InProgress
+        ));
+
+        // A failed create releases the key for the next retry
+        drop(reservation);
+        assert_eq!(keys.client_count(), 0);
+        create(&keys, "client-a", "retry-1", 7, now);
+        assert_eq!(
+            get(&keys, "client-a", "retry-1", now),
+            Some(SessionId::new(7))
+        );
+
+        // A key whose session is gone is reserved again
+        assert!(matches!(
+            keys.reserve("client-a", "retry-1", now, |_| None::<SessionId>),
+            KeyLookup::Reserved(_)
+        ));
+    }
+
+    #[test]
+    fn test_keys_expire_and_are_pruned() {
+        let keys = IdempotencyKeys::new();
+        let start = Instant::now();
+        create(&keys, "client-a", "retry-1", 1, start);
+
+        create(&keys, "client-b", "retry-1", 2, start);
+
+        let later = start + IDEMPOTENCY_KEY_TTL;
+        assert_eq!(get(&keys, "client-a", "retry-1", later), None);
+
+        // Looking up one client's key drops every client's expired keys
+        assert_eq!(keys.client_count(), 0);
+    }
+
+    #[test]
+    fn test_keys_per_client_are_bounded() {
+        let keys = IdempotencyKeys::new();
+        let now = Instant::now();
+        for i in 0..=MAX_KEYS_PER_CLIENT as u32 {
+            create(&keys, "client-a", &format!("key-{}", i), i, now);
+        }
+
+        // The oldest key made room for the newest (checked last, as a miss
+        // reserves the key and so drops another)
+        let newest = format!("key-{}", MAX_KEYS_PER_CLIENT);
+        let newest_id = SessionId::new(MAX_KEYS_PER_CLIENT as u32);
+        assert_eq!(get(&keys, "client-a", &newest, now), Some(newest_id));
+        assert_eq!(
+            get(&keys, "client-a", "key-1", now),
+            Some(SessionId::new(1))
+        );
+        assert_eq!(get(&keys, "client-a", "key-0", now), None);
+    }
+}
@@ -3,6 +3,20 @@
 //! Provides a Unix socket server that the desktop app and CLI
 //! use to communicate with the running orchestrator daemon.
 
+mod clients;
+mod history;
+mod idempotency;
 mod server;
+mod snapshot;
+mod tokens;
 
+pub use clients::{ActiveClients, ClientClaim, STALE_CONNECTION_GRACE};
+pub use history::{
+    EventHistory, MissedEvents, EVENT_HISTORY_CAPACITY, SHED_HISTORY_MAX_OUTPUT_BYTES,
+};
+pub use idempotency::{
+    IdempotencyKeys, KeyLookup, KeyReservation, IDEMPOTENCY_KEY_TTL, MAX_KEYS_PER_CLIENT,
+};
 pub use server::IpcServer;
+pub use snapshot::{session_page, snapshot_page, SessionPage, SnapshotPage, SnapshotQuery};
+pub use tokens::{IpcTokens, TOKEN_ROTATION_OVERLAP};
@@ -11,6 +11,8 @@
 //! - **Input validation**: Session input is limited to 64KB to prevent memory exhaustion
 //! - **Session ownership**: Sessions are tracked by creating client, with access control
 //! - **Request validation**: All JSON requests are validated before processing
+//! - **Token rotation**: The auth token can be replaced without a restart
+//!   (see [`IpcTokens`])
 //!
 //! # Input Size Limits
 //!
@@ -29,10 +31,15 @@
 //! - Only the creating client can subscribe to session output
 //! - Session cleanup when the owning client disconnects
 //! - Audit logging of session operations
+//!
+//! A logical client ID held by a live connection of another process is
+//! refused unless the client asks to take it over, so one process can't
+//! take another's sessions by reusing its ID (see
+//! [`ActiveClients`](super::ActiveClients)).
 
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
 use bytes::Bytes;
@@ -41,63 +48,31 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
+use kt_core::ansi::terminal_banner;
+use kt_core::config::IpcRateLimitConfig;
 use kt_core::ipc::{
-    IpcEvent, IpcEventEnvelope, IpcRequest, IpcResponse, MachineInfo, MachineStatus,
-    OrchestratorStatus, SessionInfo,
+    validate_agent_option, validate_env_vars, validate_idempotency_key, validate_session_log_level,
+    validate_session_ttl, validate_term, validate_terminal_size, CloseReason, GroupInfo, IpcEvent,
+    IpcEventEnvelope, IpcFeature, IpcFeatures, IpcRequest, IpcResponse, LogLine, MachineInfo,
+    MachineStatus, OrchestratorCapabilities, OrchestratorStatus, RateLimitKind, SessionDetails,
+    SessionEnvVar, SessionInfo, SessionLifecycle, MAX_TAIL_LOG_LINES,
+};
+use kt_protocol::{AgentConfigKey, Capability, TerminalSize};
+
+use super::clients::{ClientClaim, STALE_CONNECTION_GRACE};
+use super::history::EventHistory;
+use super::idempotency::KeyLookup;
+use super::snapshot::{session_page, snapshot_page, SnapshotQuery};
+use super::tokens::{IpcTokens, TOKEN_ROTATION_OVERLAP};
+use crate::connection::{AgentCommand, TunnelConnection};
+use crate::logging::{LogBatcher, LogSource};
+use crate::memory::run_memory_monitor;
+use crate::session::{
+    close_session, InputBreaker, InputVerdict, MonitorSettings, SessionHandle, SessionOptions,
+    SessionState, SubscriberGuard, INPUT_BLOCK_PERIOD,
 };
-use kt_protocol::TerminalSize;
-
-use crate::connection::AgentCommand;
-use crate::session::SessionState;
 use crate::state::OrchestratorState;
 
-/// Validate an environment variable name.
-///
-/// Valid names must:
-/// - Start with a letter (a-z, A-Z) or underscore (_)
-/// - Contain only alphanumeric characters (a-z, A-Z, 0-9) and underscores
-/// - Not be empty
-///
-/// This prevents environment variable injection attacks where malicious
-/// variable names could affect shell behavior or security.
-fn is_valid_env_var_name(name: &str) -> bool {
-    if name.is_empty() {
-        return false;
-    }
-
-    let mut chars = name.chars();
-
-    // First character must be letter or underscore
-    let first = match chars.next() {
-        Some(c) => c,
-        None => return false,
-    };
-
-    if !first.is_ascii_alphabetic() && first != '_' {
-        return false;
-    }
-
-    // Rest must be alphanumeric or underscore
-    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
-}
-
-/// Validate a list of environment variables.
-///
-/// Returns `Ok(())` if all variables are valid, or an error message
-/// describing the first invalid variable found.
-fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
-    for (name, _value) in env {
-        if !is_valid_env_var_name(name) {
-            return Err(format!(
-                "Invalid environment variable name '{}': must start with letter or underscore, \
-                 and contain only alphanumeric characters and underscores",
-                name
-            ));
-        }
-    }
-    Ok(())
-}
-
 /// Validate that a client has permission to access a session.
 ///
 /// Returns `Ok(())` if the client is allowed to access the session:
@@ -105,6 +80,8 @@ fn validate_env_vars(env: &[(String, String)]) -> Result<(), String> {
 /// - The session has no owner (public session)
 ///
 /// Returns `Err(IpcResponse::Error)` if the session is owned by another client.
+// The error is returned straight to the client, so boxing it would only add noise
+#[allow(clippy::result_large_err)]
 fn validate_ownership(
     session: &crate::session::SessionHandle,
     client_id: &str,
@@ -117,6 +94,91 @@ fn validate_ownership(
     }
 }
 
+/// Check that the agent behind a connection advertised a capability.
+///
+/// `feature` names what the client asked for, so the error says exactly
+/// which part of the request the agent can't honour.
+#[allow(clippy::result_large_err)]
+fn require_capability(
+    conn: &TunnelConnection,
+    capability: Capability,
+    feature: &str,
+) -> Result<(), IpcResponse> {
+    if conn.supports(capability) {
+        Ok(())
+    } else {
+        Err(IpcResponse::Error {
+            message: format!(
+                "Agent {} does not support {} (missing capability: {})",
+                conn.machine_id, feature, capability
+            ),
+        })
+    }
+}
+
+/// Change a runtime setting of the agent behind `conn` and wait for its ack.
+///
+/// Values of keys this build knows are checked here; unknown keys go to the
+/// agent anyway, which may be newer, and come back refused if it isn't.
+async fn set_agent_option(conn: &TunnelConnection, key: String, value: String) -> IpcResponse {
+    if let Err(response) = require_capability(conn, Capability::AgentConfig, "runtime options") {
+        return response;
+    }
+    let known = AgentConfigKey::from_name(&key);
+    if let Err(message) = validate_agent_option(&key, &value)
+        .and_then(|()| known.map_or(Ok(()), |known| known.validate(&value)))
+    {
+        return IpcResponse::Error { message };
+    }
+
+    let ack = match conn.update_config(key.clone(), value.clone()).await {
+        Ok(ack) => ack,
+        Err(e) => {
+            return IpcResponse::Error {
+                message: format!("Failed to send option to agent: {}", e),
+            }
+        }
+    };
+    match tokio::time::timeout(AGENT_OPTION_ACK_TIMEOUT, ack).await {
+        Ok(Ok(Ok(()))) => {
+            tracing::info!("Set {}={} on agent {}", key, value, conn.machine_id);
+            IpcResponse::Ok
+        }
+        Ok(Ok(Err(reason))) => IpcResponse::Error {
+            message: format!("Agent {} didn't apply {}: {}", conn.machine_id, key, reason),
+        },
+        Ok(Err(_)) | Err(_) => IpcResponse::Error {
+            message: format!(
+                "Agent {} didn't answer the change of {} (it may still apply it)",
+                conn.machine_id, key
+            ),
+        },
+    }
+}
+
+/// Pass the client's terminal type on to an agent.
+///
+/// Agents with [`Capability::Term`] get it as is. Older ones get `TERM` and
+/// `COLORTERM` added to the session environment instead, which they apply
+/// the same way.
+fn term_for_agent(
+    conn: &TunnelConnection,
+    mut env: Vec<(String, String)>,
+    term: Option<String>,
+    truecolor: bool,
+) -> (Vec<(String, String)>, Option<String>, bool) {
+    if conn.supports(Capability::Term) {
+        return (env, term, truecolor);
+    }
+    if let Some(term) = term {
+        env.push(("TERM".to_string(), term));
+    }
+    if truecolor {
+        env.push(("COLORTERM".to_string(), "truecolor".to_string()));
+    }
+    (env, None, false)
+}
+
 /// Maximum size for session input data (64KB).
 ///
 /// This limit prevents memory exhaustion attacks and protects against:
@@ -149,13 +211,6 @@ const MAX_SESSION_INPUT_SIZE: usize = 65536;
 /// but prevents runaway connections from bugs or attacks.
 const MAX_IPC_CONNECTIONS: u32 = 100;
 
-/// Rate limit: maximum requests per second per client.
-///
-/// This prevents individual clients from overwhelming the server.
-/// The limit is high enough for interactive terminal use but
-/// low enough to prevent DoS from malicious clients.
-const RATE_LIMIT_REQUESTS_PER_SECOND: u32 = 1000;
-
 /// Authentication rate limit: maximum failed auth attempts per minute.
 ///
 /// This is stricter than the general rate limit to prevent brute-force
@@ -166,18 +221,8 @@ const AUTH_RATE_LIMIT_FAILURES_PER_MINUTE: u32 = 10;
 /// Lockout duration after exceeding auth failure limit.
 const AUTH_LOCKOUT_DURATION_SECS: u64 = 60;
 
-/// Minimum terminal columns/rows for resize requests.
-///
-/// A terminal with 0 columns or rows would be unusable. The minimum of 1
-/// allows for edge cases while preventing completely invalid sizes.
-const MIN_TERMINAL_SIZE: u16 = 1;
-
-/// Maximum terminal columns/rows for resize requests.
-///
-/// 10,000 is far larger than any realistic terminal size (typical max is 300-500).
-/// This limit prevents resource exhaustion from extremely large buffers while
-/// still allowing for unusual but valid use cases like virtual terminals.
-const MAX_TERMINAL_SIZE: u16 = 10000;
+/// How long `SetAgentOption` waits for the agent to ack the change.
+const AGENT_OPTION_ACK_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// Broadcast channel capacity for IPC events.
 ///
@@ -201,6 +246,27 @@ const MAX_TERMINAL_SIZE: u16 = 10000;
 /// have significantly different workload characteristics.
 const IPC_EVENT_CHANNEL_CAPACITY: usize = 1024;
 
+/// Events queued for a client above which the output of the sessions it
+/// owns and is attached to is paused, with `owner_backpressure` on
+const OWNER_PAUSE_BACKLOG: usize = IPC_EVENT_CHANNEL_CAPACITY / 2;
+
+/// Events queued for a client at or below which the paused output resumes
+const OWNER_RESUME_BACKLOG: usize = IPC_EVENT_CHANNEL_CAPACITY / 8;
+
+/// Events queued for a client, for [`ClientState::apply_backpressure`]; 0
+/// with `owner_backpressure` off, which also lifts pauses from before a
+/// reload turned it off
+fn owner_backlog(
+    state: &OrchestratorState,
+    event_rx: &broadcast::Receiver<IpcEventEnvelope>,
+) -> usize {
+    if state.config().owner_backpressure {
+        event_rx.len()
+    } else {
+        0
+    }
+}
+
 /// IPC server for CLI/GUI communication
 ///
 /// Listens on localhost (127.0.0.1) only - not accessible from network.
@@ -217,8 +283,12 @@ pub struct IpcServer {
     shutdown_token: Option<CancellationToken>,
     /// Current number of active connections (for rate limiting)
     active_connections: Arc<AtomicU32>,
-    /// Authentication token for IPC clients
-    auth_token: String,
+    /// Authentication tokens accepted from IPC clients
+    tokens: Arc<IpcTokens>,
+    /// Captured logs served to `TailLogs` (None = log streaming unavailable)
+    log_source: Option<LogSource>,
+    /// Recent events served to `GetEventsSince`
+    event_history: Arc<EventHistory>,
 }
 
 impl IpcServer {
@@ -228,16 +298,20 @@ impl IpcServer {
     /// is already running and owns the token, returns an error.
     ///
     /// Clients must read this token and authenticate before making requests.
+    /// If `ipc_token_lifetime` is configured, the token expires and is
+    /// rotated while the server runs. The state's owner is recorded in the
+    /// token file.
     pub fn new(address: String, state: Arc<OrchestratorState>) -> Result<Self> {
         let (event_tx, _) = broadcast::channel(IPC_EVENT_CHANNEL_CAPACITY);
+        let lifetime = state.config().ipc_token_lifetime.map(Duration::from);
 
         // Acquire token ownership - this ensures we don't overwrite a running orchestrator's token
-        let auth_token = match kt_core::acquire_token_ownership(&address)
+        let token_info = match kt_core::acquire_token_ownership(&address, lifetime, state.owner)
             .context("Failed to acquire IPC token ownership")?
         {
-            kt_core::TokenOwnership::Acquired { token } => {
+            kt_core::TokenOwnership::Acquired { info } => {
                 tracing::info!("Acquired IPC token ownership");
-                token
+                info
             }
             kt_core::TokenOwnership::External { pid, address: ext_addr, .. } => {
                 // Another orchestrator is running - we should not start
@@ -257,7 +331,9 @@ impl IpcServer {
             event_tx,
             shutdown_token: None,
             active_connections: Arc::new(AtomicU32::new(0)),
-            auth_token,
+            tokens: Arc::new(IpcTokens::new(token_info, lifetime)),
+            log_source: None,
+            event_history: Arc::new(EventHistory::new()),
         })
     }
 
@@ -267,27 +343,70 @@ impl IpcServer {
         self
     }
 
+    /// Serve `TailLogs` requests from captured logs (call before run)
+    pub fn with_log_source(mut self, log_source: LogSource) -> Self {
+        self.log_source = Some(log_source);
+        self
+    }
+
     /// Get a sender for broadcasting events
     pub fn event_sender(&self) -> broadcast::Sender<IpcEventEnvelope> {
         self.event_tx.clone()
     }
 
-    /// Get the authentication token
+    /// Get the current authentication token
     ///
     /// This is primarily for testing purposes. In production, clients
     /// should read the token from the token file.
-    pub fn auth_token(&self) -> &str {
-        &self.auth_token
+    pub fn auth_token(&self) -> String {
+        self.tokens.current().token.expose().clone()
+    }
+
+    /// Rotate the authentication token (see [`IpcRequest::RotateIpcToken`])
+    pub fn rotate_token(&self) -> Result<kt_core::TokenInfo> {
+        self.tokens.rotate().context("Failed to rotate IPC token")
     }
 
     /// Start the IPC server
     pub async fn run(&self) -> Result<()> {
+        let listener = self.bind().await?;
+        self.serve(listener).await
+    }
+
+    /// Bind the IPC listener without accepting clients yet, so callers can
+    /// tell when the socket is ready (see [`IpcServer::serve`])
+    pub async fn bind(&self) -> Result<TcpListener> {
         let listener = TcpListener::bind(&self.address)
             .await
             .with_context(|| format!("Failed to bind IPC server to {}", self.address))?;
 
         tracing::info!("IPC server listening on {}", self.address);
+        Ok(listener)
+    }
+
+    /// Serve clients on a listener from [`IpcServer::bind`]
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        // Keep recent events so reconnecting clients can catch up
+        let event_history = Arc::clone(&self.event_history);
+        let history_rx = self.event_tx.subscribe();
+        tokio::spawn(async move { event_history.record_from(history_rx).await });
+
+        // Shed load past the memory soft limit, if one is configured
+        tokio::spawn(run_memory_monitor(
+            Arc::clone(&self.state),
+            Arc::clone(&self.event_history),
+            self.shutdown_token.clone().unwrap_or_default(),
+        ));
+
+        // Replace the token whenever it expires, for as long as we serve
+        tokio::select! {
+            result = self.accept_loop(listener) => result,
+            _ = self.tokens.rotate_on_expiry() => Ok(()),
+        }
+    }
 
+    /// Accept and serve clients until the listener fails
+    async fn accept_loop(&self, listener: TcpListener) -> Result<()> {
         loop {
             match listener.accept().await {
                 Ok((stream, peer_addr)) => {
@@ -319,11 +438,22 @@ impl IpcServer {
                     let event_tx = self.event_tx.clone();
                     let shutdown_token = self.shutdown_token.clone();
                     let active_connections = Arc::clone(&self.active_connections);
-                    let auth_token = self.auth_token.clone();
+                    let tokens = Arc::clone(&self.tokens);
+                    let log_source = self.log_source.clone();
+                    let event_history = Arc::clone(&self.event_history);
 
                     tokio::spawn(async move {
-                        let result =
-                            handle_client(stream, state, start_time, event_tx, shutdown_token, auth_token).await;
+                        let result = handle_client(
+                            stream,
+                            state,
+                            start_time,
+                            event_tx,
+                            shutdown_token,
+                            tokens,
+                            log_source,
+                            event_history,
+                        )
+                        .await;
 
                         // Decrement connection counter on disconnect
                         active_connections.fetch_sub(1, Ordering::SeqCst);
@@ -353,18 +483,29 @@ struct ClientState {
     authenticated: bool,
     /// Session IDs this client has subscribed to for terminal output
     subscribed_sessions: std::collections::HashSet<String>,
+    /// Counts this connection among the subscribers of each session in
+    /// `subscribed_sessions` until it unsubscribes or closes
+    subscriptions: std::collections::HashMap<String, SubscriberGuard>,
+    /// Sessions in `subscribed_sessions` this client observes rather than
+    /// owns (see `ObserveSession`)
+    observed_sessions: std::collections::HashSet<String>,
     /// Session IDs this client has created (for ownership tracking)
     owned_sessions: std::collections::HashSet<String>,
-    /// Rate limiter state: request count in current window
-    request_count: u32,
-    /// Rate limiter state: start of current window
-    rate_window_start: Instant,
+    /// Rate limiter state for session input and resizes
+    input_window: RateWindow,
+    /// Rate limiter state for all other requests
+    control_window: RateWindow,
     /// Auth rate limiter state: failed auth attempts in current window
     auth_failure_count: u32,
     /// Auth rate limiter state: start of current auth window
     auth_window_start: Instant,
     /// Auth lockout: time until which this client is locked out from auth
     auth_lockout_until: Option<Instant>,
+    /// Hold on `logical_client_id`, released when the connection closes
+    client_claim: Option<ClientClaim>,
+    /// Close this client's sessions when this connection closes instead of
+    /// orphaning them (`close_on_disconnect` at authentication)
+    close_on_disconnect: bool,
 }
 
 impl ClientState {
@@ -387,12 +528,16 @@ impl ClientState {
             logical_client_id: None, // Set during authentication if client provides one
             authenticated: false,
             subscribed_sessions: std::collections::HashSet::new(),
+            subscriptions: std::collections::HashMap::new(),
+            observed_sessions: std::collections::HashSet::new(),
             owned_sessions: std::collections::HashSet::new(),
-            request_count: 0,
-            rate_window_start: now,
+            input_window: RateWindow::new(now),
+            control_window: RateWindow::new(now),
             auth_failure_count: 0,
             auth_window_start: now,
             auth_lockout_until: None,
+            client_claim: None,
+            close_on_disconnect: false,
         }
     }
 
@@ -402,26 +547,16 @@ impl ClientState {
         self.logical_client_id.as_deref().unwrap_or(&self.connection_id)
     }
 
-    /// Check if the request is allowed under rate limiting.
+    /// Check if a request of the given kind is allowed under rate limiting.
     /// Returns true if allowed, false if rate limited.
-    fn check_rate_limit(&mut self) -> bool {
+    fn check_rate_limit(&mut self, kind: RateLimitKind, limits: &IpcRateLimitConfig) -> bool {
         let now = Instant::now();
-        let elapsed = now.duration_since(self.rate_window_start);
-
-        // Reset window if more than 1 second has passed
-        if elapsed.as_secs() >= 1 {
-            self.request_count = 0;
-            self.rate_window_start = now;
-        }
-
-        // Check if under limit
-        if self.request_count >= RATE_LIMIT_REQUESTS_PER_SECOND {
-            return false;
+        match kind {
+            RateLimitKind::Input => self.input_window.allow(now, limits.input_per_second),
+            RateLimitKind::Control => self.control_window.allow(now, limits.control_per_second),
+            // Metered per session by the input breaker, not per client
+            RateLimitKind::SessionInput => true,
         }
-
-        // Increment and allow
-        self.request_count += 1;
-        true
     }
 
     /// Check if authentication is allowed (not locked out due to too many failures).
@@ -479,6 +614,26 @@ impl ClientState {
         }
     }
 
+    /// Pause the output of the sessions this client owns and is attached to
+    /// while `backlog` events wait for it, and resume it once it has caught
+    /// up; sessions it only observes are left alone
+    fn apply_backpressure(&mut self, backlog: usize) {
+        let paused = match backlog {
+            n if n >= OWNER_PAUSE_BACKLOG => true,
+            n if n <= OWNER_RESUME_BACKLOG => false,
+            _ => return,
+        };
+        let client_id = self
+            .logical_client_id
+            .as_deref()
+            .unwrap_or(&self.connection_id);
+        for (session_id, guard) in self.subscriptions.iter_mut() {
+            let owned = !self.observed_sessions.contains(session_id)
+                && guard.session().owner_client_id.as_deref() == Some(client_id);
+            guard.pause_output(paused && owned);
+        }
+    }
+
     /// Check if this client should receive the given event envelope
     fn should_receive_event(&self, envelope: &IpcEventEnvelope) -> bool {
         match &envelope.event {
@@ -492,13 +647,94 @@ impl ClientState {
     }
 }
 
+/// One-second fixed window for a per-client rate limit
+struct RateWindow {
+    /// Requests counted in the current window
+    count: u32,
+    /// Start of the current window
+    start: Instant,
+}
+
+impl RateWindow {
+    fn new(now: Instant) -> Self {
+        Self {
+            count: 0,
+            start: now,
+        }
+    }
+
+    /// Count a request, returning false if the window is already full
+    fn allow(&mut self, now: Instant, limit: u32) -> bool {
+        // Reset window if more than 1 second has passed
+        if now.duration_since(self.start).as_secs() >= 1 {
+            self.count = 0;
+            self.start = now;
+        }
+
+        if self.count >= limit {
+            return false;
+        }
+
+        self.count += 1;
+        true
+    }
+}
+
+/// Which rate limit a request counts against.
+///
+/// Terminal input and resizes arrive in bursts while typing, pasting or
+/// dragging a window, so they get their own budget. Everything else, and
+/// lines that fail to parse, count as control requests.
+fn rate_limit_kind(request: &IpcRequest) -> RateLimitKind {
+    match request {
+        IpcRequest::SessionInput { .. } | IpcRequest::SessionResize { .. } => RateLimitKind::Input,
+        _ => RateLimitKind::Control,
+    }
+}
+
+/// Response for a request rejected by the rate limiter
+fn rate_limited_response(kind: RateLimitKind, limits: &IpcRateLimitConfig) -> IpcResponse {
+    let limit = match kind {
+        RateLimitKind::Input => limits.input_per_second,
+        RateLimitKind::Control => limits.control_per_second,
+        // Not a per-client limit (see `session_input_refused`)
+        RateLimitKind::SessionInput => 0,
+    };
+    IpcResponse::RateLimited {
+        message: format!(
+            "Rate limit exceeded: max {} {} requests per second",
+            limit, kind
+        ),
+        kind,
+        limit,
+    }
+}
+
+/// Response for input refused by the input circuit breaker
+fn session_input_refused(session_id: &str, breaker: &InputBreaker) -> IpcResponse {
+    let limit = breaker.max_kib_per_min();
+    IpcResponse::RateLimited {
+        message: format!(
+            "Input to session {} went over {} KiB per minute and is refused for up to {}s",
+            session_id,
+            limit,
+            INPUT_BLOCK_PERIOD.as_secs()
+        ),
+        kind: RateLimitKind::SessionInput,
+        limit: u32::try_from(limit).unwrap_or(u32::MAX),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn handle_client(
     stream: TcpStream,
     state: Arc<OrchestratorState>,
     start_time: Instant,
     event_tx: broadcast::Sender<IpcEventEnvelope>,
     shutdown_token: Option<CancellationToken>,
-    auth_token: String,
+    tokens: Arc<IpcTokens>,
+    log_source: Option<LogSource>,
+    event_history: Arc<EventHistory>,
 ) -> Result<()> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
@@ -508,6 +744,13 @@ async fn handle_client(
     // Subscribe to events
     let mut event_rx = event_tx.subscribe();
 
+    // Cancelled when another process takes over our logical client ID
+    let closer = CancellationToken::new();
+
+    // Live log lines, once the client follows logs via TailLogs
+    let mut log_rx: Option<broadcast::Receiver<LogLine>> = None;
+    let mut log_batch = LogBatcher::new();
+
     loop {
         tokio::select! {
             // Handle incoming requests
@@ -522,24 +765,28 @@ async fn handle_client(
                         }
 
                         // Check rate limit before processing request
-                        let response = if !client_state.check_rate_limit() {
+                        let parsed = serde_json::from_str::<IpcRequest>(trimmed);
+                        let kind = parsed.as_ref().map_or(RateLimitKind::Control, rate_limit_kind);
+                        let limits = &state.config().ipc_rate_limit;
+                        let response = if !client_state.check_rate_limit(kind, limits) {
                             tracing::warn!(
-                                "Rate limit exceeded for connection {} ({} req/s)",
+                                "Rate limit exceeded for connection {} ({} requests)",
                                 client_state.connection_id,
-                                RATE_LIMIT_REQUESTS_PER_SECOND
+                                kind
                             );
-                            IpcResponse::Error {
-                                message: format!(
-                                    "Rate limit exceeded: max {} requests per second",
-                                    RATE_LIMIT_REQUESTS_PER_SECOND
-                                ),
-                            }
+                            rate_limited_response(kind, limits)
                         } else {
-                            match serde_json::from_str::<IpcRequest>(trimmed) {
+                            match parsed {
                                 Ok(request) => {
                                     // Handle authentication
                                     match &request {
-                                        IpcRequest::Authenticate { token, client_id } => {
+                                        IpcRequest::Authenticate {
+                                            token,
+                                            client_id,
+                                            instance_id,
+                                            force_takeover,
+                                            close_on_disconnect,
+                                        } => {
                                             // Check auth-specific rate limit first
                                             if !client_state.check_auth_rate_limit() {
                                                 tracing::warn!(
@@ -552,37 +799,48 @@ async fn handle_client(
                                                         AUTH_LOCKOUT_DURATION_SECS
                                                     ),
                                                 }
-                                            } else if kt_core::validate_ipc_token(token, &auth_token) {
-                                                client_state.authenticated = true;
-
+                                            } else if let Some(generation) = tokens.validate(token) {
                                                 // Set logical client ID if provided (for session ownership)
-                                                if let Some(id) = client_id {
-                                                    client_state.logical_client_id = Some(id.clone());
-                                                    // Reclaim any orphaned sessions for this client
-                                                    reclaim_orphaned_sessions(&state, id, &mut client_state);
-                                                }
-
-                                                tracing::debug!(
-                                                    "Connection {} authenticated (logical_client: {:?})",
-                                                    client_state.connection_id,
-                                                    client_state.logical_client_id
-                                                );
-                                                // Return epoch info for client synchronization
-                                                IpcResponse::Authenticated {
-                                                    epoch_id: state.epoch.epoch_id_string(),
-                                                    current_seq: state.epoch.current_sequence(),
+                                                let claimed = match client_id {
+                                                    Some(id) => claim_client_id(
+                                                        &state,
+                                                        id,
+                                                        instance_id.as_deref(),
+                                                        *force_takeover,
+                                                        &closer,
+                                                        &mut client_state,
+                                                    )
+                                                    .await,
+                                                    None => Ok(()),
+                                                };
+                                                match claimed {
+                                                    Ok(()) => {
+                                                        client_state.authenticated = true;
+                                                        client_state.close_on_disconnect = *close_on_disconnect;
+                                                        tracing::debug!(
+                                                            "Connection {} authenticated (logical_client: {:?}, token generation {}, close_on_disconnect: {})",
+                                                            client_state.connection_id,
+                                                            client_state.logical_client_id,
+                                                            generation,
+                                                            client_state.close_on_disconnect
+                                                        );
+                                                        // Return epoch info for client synchronization
+                                                        IpcResponse::Authenticated {
+                                                            epoch_id: state.epoch.epoch_id_string(),
+                                                            current_seq: state.epoch.current_sequence(),
+                                                            server_time_ms: kt_core::time::current_time_millis(),
+                                                        }
+                                                    }
+                                                    Err(response) => response,
                                                 }
                                             } else {
                                                 // Record the failed attempt for auth rate limiting
                                                 client_state.record_auth_failure();
                                                 tracing::warn!(
-                                                    "Connection {} authentication failed ({} failures). Expected: {}...{}, got: {}...{}",
+                                                    "Connection {} authentication failed ({} failures, token fingerprint {})",
                                                     client_state.connection_id,
                                                     client_state.auth_failure_count,
-                                                    &auth_token[..8],
-                                                    &auth_token[auth_token.len()-8..],
-                                                    &token[..std::cmp::min(8, token.len())],
-                                                    if token.len() > 8 { &token[token.len()-8..] } else { "" }
+                                                    kt_core::fingerprint(token.as_bytes())
                                                 );
                                                 IpcResponse::Error {
                                                     message: "Invalid authentication token".to_string(),
@@ -601,12 +859,27 @@ async fn handle_client(
                                         _ if !client_state.authenticated => {
                                             IpcResponse::AuthenticationRequired
                                         }
+                                        // Following needs this connection's log receiver
+                                        IpcRequest::TailLogs { follow, lines } => {
+                                            tail_logs(log_source.as_ref(), *follow, *lines, &mut log_rx)
+                                        }
+                                        // Replay is filtered by this connection's subscriptions
+                                        IpcRequest::GetEventsSince { since_seq } => {
+                                            events_since(&event_history, *since_seq, &client_state)
+                                        }
+                                        // Rotation swaps the tokens this server accepts
+                                        IpcRequest::RotateIpcToken => rotate_token(&tokens),
+                                        // Log tailing depends on this server's log source
+                                        IpcRequest::GetCapabilities => {
+                                            IpcResponse::Capabilities(capabilities(log_source.as_ref()))
+                                        }
                                         // Authenticated - process normally
                                         _ => handle_request_with_state(
                                             request,
                                             &state,
                                             start_time,
                                             &mut client_state,
+                                            &event_tx,
                                             shutdown_token.as_ref(),
                                         ).await,
                                     }
@@ -625,7 +898,8 @@ async fn handle_client(
                     }
                     Err(e) => {
                         // Clean up owned sessions before returning error
-                        cleanup_owned_sessions(&state, &client_state);
+                        cleanup_owned_sessions(&state, &client_state, &event_tx);
+                        release_input_claims(&state, &client_state, &event_tx);
                         return Err(e.into());
                     }
                 }
@@ -635,6 +909,7 @@ async fn handle_client(
             result = event_rx.recv() => {
                 match result {
                     Ok(envelope) => {
+                        client_state.apply_backpressure(owner_backlog(&state, &event_rx));
                         // Only send events to authenticated clients
                         if client_state.authenticated && client_state.should_receive_event(&envelope) {
                             let mut event_json = serde_json::to_string(&envelope)?;
@@ -649,6 +924,9 @@ async fn handle_client(
                             client_state.connection_id,
                             n
                         );
+                        // Repeated lag makes sessions coalesce their output
+                        state.coalescing.record_lag(Instant::now());
+                        client_state.apply_backpressure(owner_backlog(&state, &event_rx));
                         // Send a sync notification to the client so they know to refresh state
                         // Wrap in IpcEventEnvelope for consistency
                         if client_state.authenticated {
@@ -665,15 +943,308 @@ async fn handle_client(
                     }
                 }
             }
+
+            // Our logical client ID was taken over by another process
+            _ = closer.cancelled() => {
+                tracing::info!(
+                    "Closing connection {}: client {} was taken over",
+                    client_state.connection_id,
+                    client_state.effective_client_id()
+                );
+                break;
+            }
+
+            // Collect followed log lines into the current batch
+            result = recv_log_line(&mut log_rx), if log_rx.is_some() => {
+                match result {
+                    Ok(log_line) => log_batch.push(log_line),
+                    Err(broadcast::error::RecvError::Lagged(n)) => log_batch.record_dropped(n),
+                    Err(broadcast::error::RecvError::Closed) => log_rx = None,
+                }
+            }
+
+            // Send the batch once its interval has passed
+            _ = tokio::time::sleep_until(
+                log_batch.deadline().unwrap_or_else(tokio::time::Instant::now)
+            ), if log_batch.deadline().is_some() => {
+                let response = IpcResponse::LogLines { lines: log_batch.take() };
+                let mut response_json = serde_json::to_string(&response)?;
+                response_json.push('\n');
+                writer.write_all(response_json.as_bytes()).await?;
+            }
         }
     }
 
     // Issue #10: Clean up sessions owned by this client when they disconnect
-    cleanup_owned_sessions(&state, &client_state);
+    cleanup_owned_sessions(&state, &client_state, &event_tx);
+    release_input_claims(&state, &client_state, &event_tx);
 
     Ok(())
 }
 
+/// Answer a `TailLogs` request, starting to follow if asked.
+///
+/// The follower is subscribed before the history is read, so a line logged
+/// in between may show up twice but is never lost.
+fn tail_logs(
+    log_source: Option<&LogSource>,
+    follow: bool,
+    lines: usize,
+    log_rx: &mut Option<broadcast::Receiver<LogLine>>,
+) -> IpcResponse {
+    let Some(log_source) = log_source else {
+        return IpcResponse::Error {
+            message: "Log streaming is not available on this orchestrator".to_string(),
+        };
+    };
+
+    if follow && log_rx.is_none() {
+        *log_rx = Some(log_source.subscribe());
+    }
+
+    match log_source.tail(lines.min(MAX_TAIL_LOG_LINES)) {
+        Ok(lines) => IpcResponse::LogLines { lines },
+        Err(e) => IpcResponse::Error {
+            message: format!("Failed to read log file: {}", e),
+        },
+    }
+}
+
+/// Answer a `GetEventsSince` request from the event history.
+///
+/// Only events the client would have received live are returned, so
+/// terminal output is limited to the sessions it is subscribed to.
+fn events_since(history: &EventHistory, since_seq: u64, client_state: &ClientState) -> IpcResponse {
+    let missed = history.since(since_seq, |envelope| {
+        client_state.should_receive_event(envelope)
+    });
+    IpcResponse::EventsSince {
+        events: missed.events,
+        truncated: missed.truncated,
+        oldest_available_seq: missed.oldest_available_seq,
+    }
+}
+
+/// Answer to a `Subscribe` or `ObserveSession` of `session`
+///
+/// The attach message goes in the response, so it comes before any output
+/// of the session, and only when the connection wasn't subscribed already.
+fn subscribed(state: &OrchestratorState, session: &SessionHandle, newly: bool) -> IpcResponse {
+    match state.config().attach_motd.as_deref() {
+        Some(motd) if newly => IpcResponse::Subscribed {
+            current_seq: state.epoch.current_sequence(),
+            session: session_info(session, Instant::now()),
+            motd: Some(terminal_banner(motd)),
+        },
+        _ => IpcResponse::Ok,
+    }
+}
+
+/// Refusal for new sessions when maintenance mode is turned on without one
+const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "The orchestrator is in maintenance mode and not taking new sessions";
+
+/// Current status of the orchestrator, for `GetStatus` and `StatusChanged`
+fn orchestrator_status(
+    state: &OrchestratorState,
+    start_time: Instant,
+    include_pairing_code: bool,
+) -> OrchestratorStatus {
+    let machines = state.coordinator.connections.list();
+    let sessions = state.coordinator.sessions.list();
+
+    OrchestratorStatus {
+        running: true,
+        uptime_secs: start_time.elapsed().as_secs(),
+        machine_count: machines.len(),
+        session_count: sessions.len(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: Some(kt_protocol::PROTOCOL_VERSION.to_string()),
+        tailscale_hostname: state.config().tailscale_hostname.clone(),
+        bind_address: state.config().bind_address.clone(),
+        listen_address: state.ssh_address().map(|addr| addr.to_string()),
+        pairing_code: include_pairing_code.then(|| state.pairing_code().to_string()),
+        owner: state.owner,
+        output_coalescing: state.coalescing.status(Instant::now()),
+        input_breaker: state
+            .input_breaker
+            .status(sessions.iter().map(|s| s.as_ref()), Instant::now()),
+        maintenance: state.maintenance(),
+    }
+}
+
+/// What this orchestrator supports, for `GetCapabilities`
+///
+/// Features are only listed once they are implemented, so clients keep
+/// them turned off until then.
+fn capabilities(log_source: Option<&LogSource>) -> OrchestratorCapabilities {
+    let mut features: IpcFeatures = [
+        IpcFeature::EventReplay,
+        IpcFeature::IdempotentCreate,
+        IpcFeature::SnapshotPaging,
+        IpcFeature::SessionDetails,
+        IpcFeature::MaintenanceMode,
+        IpcFeature::SessionTtl,
+        IpcFeature::ReadOnlyAttach,
+        IpcFeature::AgentOptions,
+        IpcFeature::CloseOnDisconnect,
+        IpcFeature::SessionPaging,
+    ]
+    .into_iter()
+    .collect();
+    if log_source.is_some() {
+        features.insert(IpcFeature::LogTailing);
+    }
+    OrchestratorCapabilities::current(features)
+}
+
+/// A session as listed to clients
+fn session_info(session: &SessionHandle, now: Instant) -> SessionInfo {
+    let (input_bytes_per_min, input_requests_per_min) = session.input_rate().per_minute(now);
+    SessionInfo {
+        id: session.id.to_string(),
+        machine_id: session.machine_id.to_string(),
+        shell: session.shell.clone(),
+        created_at: session.created_at_iso(),
+        pid: session.pid(),
+        size: session.size(),
+        name: session.name.clone(),
+        bytes_in: session.bytes_in(),
+        bytes_out: session.bytes_out(),
+        detached: session.is_detached(),
+        input_bytes_per_min,
+        input_requests_per_min,
+    }
+}
+
+/// Answer `GetSession` for `client_id`: an open session, or else one that
+/// closed recently
+///
+/// Anyone may look at a session, as they can list it; `owned_by_you` says
+/// whether they could also use it.
+fn get_session(state: &OrchestratorState, session_id: &str, client_id: &str) -> IpcResponse {
+    let sessions = &state.coordinator.sessions;
+    let Some(session) = sessions
+        .get_by_string_id(session_id)
+        .or_else(|| sessions.recently_closed(session_id))
+    else {
+        return IpcResponse::Error {
+            message: format!("Session not found: {}", session_id),
+        };
+    };
+
+    let now = Instant::now();
+    let close = session.close();
+    // A removed session whose close wasn't announced still counts as closed
+    let removed = !sessions
+        .get(session.id)
+        .is_some_and(|open| Arc::ptr_eq(&open, &session));
+    let lifecycle = match session.state() {
+        _ if close.is_some() || removed => SessionLifecycle::Closed,
+        // Kept open after its shell exited
+        SessionState::Active | SessionState::Orphaned if session.has_exited() => {
+            SessionLifecycle::Exited
+        }
+        SessionState::Creating => SessionLifecycle::Creating,
+        SessionState::Active => SessionLifecycle::Active,
+        SessionState::Orphaned if session.is_detached() => SessionLifecycle::Detached,
+        SessionState::Orphaned => SessionLifecycle::Orphaned,
+        SessionState::Closing => SessionLifecycle::Closing,
+    };
+    let closed = lifecycle == SessionLifecycle::Closed;
+    let orphaned_at = session
+        .orphaned_at()
+        .map(|millis| kt_core::time::format_iso8601(UNIX_EPOCH + Duration::from_millis(millis)));
+
+    IpcResponse::Session(SessionDetails {
+        session: session_info(&session, now),
+        state: lifecycle,
+        owner_client_id: session.owner_client_id.clone(),
+        owned_by_you: session.owner_client_id.as_deref() == Some(client_id),
+        subscribers: if closed { 0 } else { session.subscribers() },
+        idle_secs: (!closed).then(|| session.idle_for(now).as_secs()),
+        orphaned_at,
+        closed_at: close
+            .as_ref()
+            .map(|close| kt_core::time::format_iso8601(close.at)),
+        exit_code: match &close {
+            Some(close) => close.exit_code,
+            None => session.exit_code(),
+        },
+        close_reason: close.and_then(|close| close.reason),
+        expires_in_secs: session
+            .expires_at()
+            .filter(|_| !closed)
+            .map(|at| at.saturating_duration_since(now).as_secs()),
+    })
+}
+
+/// Answer `RotateIpcToken`: switch to a new token, keeping the old one
+/// usable for the overlap window
+fn rotate_token(tokens: &IpcTokens) -> IpcResponse {
+    match tokens.rotate() {
+        Ok(info) => IpcResponse::TokenRotated {
+            generation: info.generation,
+            expires_at: info.expires_at,
+            previous_valid_secs: TOKEN_ROTATION_OVERLAP.as_secs(),
+        },
+        Err(e) => {
+            tracing::error!("Failed to rotate IPC token: {}", e);
+            IpcResponse::Error {
+                message: format!("Failed to rotate IPC token: {}", e),
+            }
+        }
+    }
+}
+
+/// Describe connected machines, then those reconnecting
+fn list_machines(state: &OrchestratorState) -> Vec<MachineInfo> {
+    let connections = &state.coordinator.connections;
+    let connected = connections
+        .list()
+        .into_iter()
+        .map(|conn| (conn, MachineStatus::Connected));
+    let reconnecting = connections
+        .list_reconnecting()
+        .into_iter()
+        .map(|conn| (conn, MachineStatus::Reconnecting));
+    connected
+        .chain(reconnecting)
+        .map(|(conn, status)| {
+            let session_count = state.coordinator.sessions.list_for_machine(&conn.machine_id).len();
+            conn.machine_info(status, session_count)
+        })
+        .collect()
+}
+
+/// Describe a group, flagging members that match no connected machine or
+/// configured machine profile
+fn group_info(state: &OrchestratorState, name: String, members: Vec<String>) -> GroupInfo {
+    let is_known = |member: &str| {
+        state.coordinator.connections.get_by_id_or_alias(member).is_some()
+            || state.coordinator.connections.get_reconnecting(member).is_some()
+            || state.config().machines.iter().any(|(id, profile)| {
+                id.eq_ignore_ascii_case(member) || profile.alias.eq_ignore_ascii_case(member)
+            })
+    };
+    let unknown_members = members.iter().filter(|m| !is_known(m)).cloned().collect();
+    GroupInfo {
+        name,
+        members,
+        unknown_members,
+    }
+}
+
+/// Receive the next followed log line (pending forever when not following)
+async fn recv_log_line(
+    log_rx: &mut Option<broadcast::Receiver<LogLine>>,
+) -> Result<LogLine, broadcast::error::RecvError> {
+    match log_rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
 /// Get current time in milliseconds since UNIX epoch.
 fn current_time_millis() -> u64 {
     std::time::SystemTime::now()
@@ -687,7 +1258,51 @@ fn current_time_millis() -> u64 {
 /// Instead of immediately deleting sessions, we mark them as orphaned with a timestamp.
 /// This allows sessions to be reclaimed if the client reconnects within the grace period.
 /// Sessions that remain orphaned after the grace period are cleaned up by the cleanup task.
-fn cleanup_owned_sessions(state: &OrchestratorState, client_state: &ClientState) {
+/// Give the input of sessions this connection claimed back to their owners
+/// when it closes or unsubscribes
+fn release_input_claims(
+    state: &OrchestratorState,
+    client_state: &ClientState,
+    event_tx: &broadcast::Sender<IpcEventEnvelope>,
+) {
+    let client_id = client_state.effective_client_id();
+    for session_id in &client_state.subscribed_sessions {
+        if let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) {
+            if session.release_input(client_id) {
+                tracing::info!(%session_id, client_id, "Released session input");
+                announce_input_holder(state, event_tx, &session, None);
+            }
+        }
+    }
+}
+
+/// Tell clients who holds a session's input now (None = its owner)
+fn announce_input_holder(
+    state: &OrchestratorState,
+    event_tx: &broadcast::Sender<IpcEventEnvelope>,
+    session: &SessionHandle,
+    holder: Option<String>,
+) {
+    session.emit(
+        event_tx,
+        &state.epoch,
+        IpcEvent::SessionInputHolder {
+            session_id: session.id.to_string(),
+            holder,
+        },
+    );
+}
+
+/// Orphan the sessions of a disconnecting client, for it to reclaim within
+/// the grace period
+///
+/// Clients that authenticated with `close_on_disconnect` have their sessions
+/// closed instead, except those they detached.
+fn cleanup_owned_sessions(
+    state: &OrchestratorState,
+    client_state: &ClientState,
+    event_tx: &broadcast::Sender<IpcEventEnvelope>,
+) {
     if client_state.owned_sessions.is_empty() {
         return;
     }
@@ -695,6 +1310,30 @@ fn cleanup_owned_sessions(state: &OrchestratorState, client_state: &ClientState)
     let effective_id = client_state.effective_client_id();
     let now = current_time_millis();
 
+    if client_state.close_on_disconnect {
+        tracing::info!(
+            "Closing sessions of disconnecting client {} (connection: {})",
+            effective_id,
+            client_state.connection_id
+        );
+        for session in state.coordinator.sessions.list() {
+            if session.owner_client_id.as_deref() != Some(effective_id) || session.is_detached() {
+                continue;
+            }
+            // try_close() CAS, as for orphans reaching the end of the grace
+            // period
+            if session.try_close() {
+                tracing::debug!(
+                    "Closing session {} with its owner {}",
+                    session.id,
+                    effective_id
+                );
+                close_session(state, event_tx, &session, CloseReason::OwnerDisconnected);
+            }
+        }
+        return;
+    }
+
     tracing::info!(
         "Marking {} sessions as orphaned for disconnecting client {} (connection: {})",
         client_state.owned_sessions.len(),
@@ -719,6 +1358,65 @@ fn cleanup_owned_sessions(state: &OrchestratorState, client_state: &ClientState)
     }
 }
 
+/// Hold logical client ID `client_id` for this connection and reclaim its
+/// orphaned sessions
+///
+/// Refused if live connections of another instance hold it, unless
+/// `force_takeover` is set (see [`ActiveClients`](super::ActiveClients)).
+async fn claim_client_id(
+    state: &OrchestratorState,
+    client_id: &str,
+    instance_id: Option<&str>,
+    force_takeover: bool,
+    closer: &CancellationToken,
+    client_state: &mut ClientState,
+) -> Result<(), IpcResponse> {
+    let claim = state
+        .active_clients
+        .claim(
+            client_id,
+            &client_state.connection_id,
+            instance_id,
+            force_takeover,
+            closer.clone(),
+        )
+        .await;
+    let claim = match claim {
+        Ok(claim) => claim,
+        Err(holders) => {
+            tracing::warn!(
+                "Connection {} refused client ID {}: held by {} live connection(s) of another process{}",
+                client_state.connection_id,
+                client_id,
+                holders,
+                if force_takeover { " that didn't close" } else { "" }
+            );
+            return Err(IpcResponse::Error {
+                message: format!(
+                    "Client ID {} is in use by another process that is still connected \
+                     (waited {}s for it to go away). Use --force-takeover to take it over \
+                     and disconnect that process.",
+                    client_id,
+                    STALE_CONNECTION_GRACE.as_secs()
+                ),
+            });
+        }
+    };
+    if force_takeover {
+        tracing::info!(
+            "Connection {} took over client ID {}",
+            client_state.connection_id,
+            client_id
+        );
+    }
+
+    client_state.client_claim = Some(claim);
+    client_state.logical_client_id = Some(client_id.to_string());
+    // Reclaim any orphaned sessions for this client
+    reclaim_orphaned_sessions(state, client_id, client_state);
+    Ok(())
+}
+
 /// Reclaim orphaned sessions when a client reconnects.
 ///
 /// Called during authentication when a client provides a logical client ID.
@@ -733,16 +1431,15 @@ fn reclaim_orphaned_sessions(
     // Use coordinator.sessions for proper state management
     for session in state.coordinator.sessions.list() {
         if session.owner_client_id.as_deref() == Some(client_id) {
-            // Use try_reclaim for CAS-based state transition
-            if session.is_orphaned() {
-                if session.try_reclaim() {
-                    tracing::info!(
-                        "Session {} reclaimed by reconnected client {}",
-                        session.id,
-                        client_id
-                    );
-                    reclaimed_count += 1;
-                }
+            // Use try_reclaim for CAS-based state transition; detached
+            // sessions stay detached until subscribed to
+            if session.is_orphaned() && !session.is_detached() && session.try_reclaim() {
+                tracing::info!(
+                    "Session {} reclaimed by reconnected client {}",
+                    session.id,
+                    client_id
+                );
+                reclaimed_count += 1;
             }
             // Track in this connection's owned_sessions
             client_state.owned_sessions.insert(session.id.to_string());
@@ -763,6 +1460,7 @@ async fn handle_request_with_state(
     state: &OrchestratorState,
     start_time: Instant,
     client_state: &mut ClientState,
+    event_tx: &broadcast::Sender<IpcEventEnvelope>,
     shutdown_token: Option<&CancellationToken>,
 ) -> IpcResponse {
     // Handle subscription requests that modify client state
@@ -788,43 +1486,176 @@ async fn handle_request_with_state(
                 return err;
             }
 
-            client_state.subscribed_sessions.insert(session_id.clone());
+            if session.is_detached() && session.try_reclaim() {
+                tracing::info!(%session_id, "Reattached session");
+            }
+            let newly_subscribed = client_state.subscribed_sessions.insert(session_id.clone());
+            client_state
+                .subscriptions
+                .insert(session_id.clone(), session.subscribe());
             tracing::debug!(
                 "Connection {} subscribed to session {}",
                 client_state.connection_id,
                 session_id
             );
-            return IpcResponse::Ok;
+
+            client_state.observed_sessions.remove(session_id);
+            return subscribed(state, &session, newly_subscribed);
         }
-        IpcRequest::Unsubscribe { session_id } => {
-            client_state.subscribed_sessions.remove(session_id);
-            tracing::debug!(
-                "Connection {} unsubscribed from session {}",
-                client_state.connection_id,
+        IpcRequest::ObserveSession { session_id } => {
+            let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+                return IpcResponse::Error {
+                    message: format!("Session not found: {}", session_id),
+                };
+            };
+
+            // Observers only watch: a detached session stays detached
+            let newly_subscribed = client_state.subscribed_sessions.insert(session_id.clone());
+            client_state
+                .subscriptions
+                .insert(session_id.clone(), session.subscribe());
+            client_state.observed_sessions.insert(session_id.clone());
+            tracing::debug!(
+                "Connection {} (client: {}) observing session {}",
+                client_state.connection_id,
+                client_state.effective_client_id(),
+                session_id
+            );
+            return subscribed(state, &session, newly_subscribed);
+        }
+        IpcRequest::Unsubscribe { session_id } => {
+            if let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) {
+                if session.release_input(client_state.effective_client_id()) {
+                    announce_input_holder(state, event_tx, &session, None);
+                }
+            }
+            client_state.subscribed_sessions.remove(session_id);
+            client_state.subscriptions.remove(session_id);
+            client_state.observed_sessions.remove(session_id);
+            tracing::debug!(
+                "Connection {} unsubscribed from session {}",
+                client_state.connection_id,
                 session_id
             );
             return IpcResponse::Ok;
         }
+        IpcRequest::DetachSession { session_id } => {
+            let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+                return IpcResponse::Error {
+                    message: format!("Session not found: {}", session_id),
+                };
+            };
+            if let Err(err) = validate_ownership(&session, client_state.effective_client_id()) {
+                return err;
+            }
+
+            client_state.subscribed_sessions.remove(session_id);
+            client_state.subscriptions.remove(session_id);
+            // Detaching twice is fine
+            if !session.is_detached() && !session.try_detach(current_time_millis()) {
+                return IpcResponse::Error {
+                    message: format!(
+                        "Session {} is not running, so can't be detached",
+                        session_id
+                    ),
+                };
+            }
+            tracing::info!(%session_id, "Detached session");
+            return IpcResponse::Ok;
+        }
         _ => {}
     }
 
     // Handle all other requests with client state for ownership tracking
-    handle_request_with_client(request, state, start_time, client_state, shutdown_token).await
+    handle_request_with_client(
+        request,
+        state,
+        start_time,
+        client_state,
+        event_tx,
+        shutdown_token,
+    )
+    .await
 }
 
 /// Handle requests that need client state for ownership tracking
+///
+/// Sessions closed here are announced on `event_tx`.
 async fn handle_request_with_client(
     request: IpcRequest,
     state: &OrchestratorState,
     start_time: Instant,
     client_state: &mut ClientState,
+    event_tx: &broadcast::Sender<IpcEventEnvelope>,
     shutdown_token: Option<&CancellationToken>,
 ) -> IpcResponse {
     // Handle CreateSession specially to track ownership
     // Use coordinator.connections and coordinator.sessions for proper state management
-    if let IpcRequest::CreateSession { machine_id, shell } = request {
+    if let IpcRequest::CreateSession {
+        machine_id,
+        shell,
+        cwd,
+        env,
+        name,
+        size,
+        allocate_pty,
+        log_level,
+        term,
+        truecolor,
+        idempotency_key,
+        ttl_secs,
+        close_on_exit,
+    } = request
+    {
+        // Use effective_client_id (logical ID if set, otherwise connection ID)
+        let owner_id = client_state.effective_client_id().to_string();
+
+        // A retry of a request that already created a session gets that
+        // session; otherwise the key stays reserved until this one has created
+        // its session, and is released if it fails
+        let mut reservation = None;
+        if let Some(key) = &idempotency_key {
+            if let Err(e) = validate_idempotency_key(key) {
+                return IpcResponse::Error { message: e };
+            }
+            let open = |session_id| {
+                state
+                    .coordinator
+                    .sessions
+                    .get(session_id)
+                    .filter(|session| session.state() != SessionState::Closing)
+            };
+            match state
+                .idempotency_keys
+                .reserve(&owner_id, key, Instant::now(), open)
+            {
+                KeyLookup::Reserved(reserved) => reservation = Some(reserved),
+                KeyLookup::Created(session) => {
+                    tracing::info!(
+                        %session.id,
+                        owner = %owner_id,
+                        "Returning session created earlier with the same idempotency key"
+                    );
+                    client_state.owned_sessions.insert(session.id.to_string());
+                    return IpcResponse::SessionCreated(session_info(&session, Instant::now()));
+                }
+                KeyLookup::InProgress => {
+                    return IpcResponse::Error {
+                        message: "A request with this idempotency key is still creating its \
+                                  session; retry shortly"
+                            .to_string(),
+                    };
+                }
+            }
+        }
+
         // Look up by machine ID or alias
         let Some(conn) = state.coordinator.connections.get_by_id_or_alias(&machine_id) else {
+            if state.coordinator.connections.get_reconnecting(&machine_id).is_some() {
+                return IpcResponse::Error {
+                    message: format!("Machine is reconnecting: {}", machine_id),
+                };
+            }
             return IpcResponse::Error {
                 message: format!("Machine not found: {}", machine_id),
             };
@@ -833,34 +1664,127 @@ async fn handle_request_with_client(
         // Use the actual machine ID from the connection (in case lookup was by alias)
         let machine_id_parsed = conn.machine_id.clone();
 
-        // Environment variables to pass to the session.
-        // Currently empty, but this is where custom env vars would be added.
-        // They must be validated before being sent to the agent.
-        let env: Vec<(String, String)> = vec![];
-
         // Validate environment variable names to prevent injection attacks
         if let Err(e) = validate_env_vars(&env) {
             return IpcResponse::Error { message: e };
         }
 
+        // An agent without cwd support would start the shell in the wrong directory
+        if cwd.is_some() {
+            if let Err(err) =
+                require_capability(&conn, Capability::Cwd, "CreateSession with cwd")
+            {
+                return err;
+            }
+        }
+
+        // An agent without pipe support would give the shell a PTY anyway
+        if !allocate_pty {
+            if let Err(err) =
+                require_capability(&conn, Capability::Pipes, "CreateSession without a PTY")
+            {
+                return err;
+            }
+        }
+
+        if let Some(term) = &term {
+            if let Err(e) = validate_term(term) {
+                return IpcResponse::Error { message: e };
+            }
+        }
+
+        if let Some(ttl_secs) = ttl_secs {
+            if let Err(e) = validate_session_ttl(ttl_secs) {
+                return IpcResponse::Error { message: e };
+            }
+        }
+
+        // Only a hint: agents without support just log at their own level
+        let log_level = match log_level.as_deref().map(validate_session_log_level) {
+            Some(Err(e)) => return IpcResponse::Error { message: e },
+            Some(Ok(level)) if conn.supports(Capability::SessionLogLevel) => Some(level),
+            Some(Ok(level)) => {
+                tracing::debug!(
+                    %machine_id,
+                    "Agent doesn't support session log levels; ignoring {}",
+                    level
+                );
+                None
+            }
+            None => None,
+        };
+
+        // Start the PTY at the client's size so it doesn't have to resize right away
+        let initial_size = match size {
+            Some(size) => {
+                if let Err(e) = validate_terminal_size(size.cols, size.rows) {
+                    return IpcResponse::Error { message: e };
+                }
+                TerminalSize::new(size.rows, size.cols)
+            }
+            None => TerminalSize::default(),
+        };
+
+        // In maintenance: existing sessions carry on, new ones wait
+        if let Some(message) = state.maintenance() {
+            tracing::info!(%machine_id, "Rejected session: in maintenance mode");
+            return IpcResponse::Error { message };
+        }
+
+        // Low on memory: take no more sessions until use drops
+        if state.memory.is_shedding() {
+            let message = state.memory.refusal_message(&state.config().memory_limit);
+            tracing::warn!(%machine_id, "Rejected session: {}", message);
+            let current = state.coordinator.sessions.len();
+            return IpcResponse::CapacityExceeded {
+                message,
+                current,
+                max: current,
+            };
+        }
+
         // Create a new session with this client as owner
-        // Use effective_client_id (logical ID if set, otherwise connection ID)
-        let owner_id = client_state.effective_client_id().to_string();
-        let session_id = state.coordinator.sessions.create_with_owner(
+        let options = SessionOptions {
+            shell: shell.clone(),
+            env: env.clone(),
+            cwd: cwd.clone(),
+            name: name.clone(),
+            ttl: ttl_secs.map(Duration::from_secs),
+            keep_on_exit: close_on_exit == Some(false),
+        };
+        // Reserve against the global cap atomically so concurrent creates can't overshoot
+        let session_id = match state.coordinator.sessions.try_create_with_options(
             machine_id_parsed.clone(),
-            shell.clone(),
+            options,
             Some(owner_id.clone()),
-        );
+            state.config().max_total_sessions,
+        ) {
+            Ok(id) => id,
+            Err(e) => {
+                tracing::warn!(%machine_id, "Rejected session: {}", e);
+                return IpcResponse::CapacityExceeded {
+                    message: e.to_string(),
+                    current: e.current,
+                    max: e.max,
+                };
+            }
+        };
 
         // Track ownership in client state
         client_state.owned_sessions.insert(session_id.to_string());
 
         // Send create session command to the agent
+        let (env, term, truecolor) = term_for_agent(&conn, env, term, truecolor);
         let command = AgentCommand::CreateSession {
             session_id,
-            shell,
+            shell: shell.clone(),
             env,
-            size: TerminalSize::default(),
+            size: initial_size,
+            cwd,
+            allocate_pty,
+            log_level,
+            term,
+            truecolor,
         };
 
         if let Err(e) = conn.command_tx.send(command).await {
@@ -871,30 +1795,46 @@ async fn handle_request_with_client(
                 message: format!("Failed to send command to agent: {}", e),
             };
         }
+        if let Some(reservation) = reservation {
+            reservation.complete(session_id, Instant::now());
+        }
 
         tracing::info!(
-            "Created session {} on machine {} (owner: {}, connection: {})",
-            session_id,
-            machine_id,
-            owner_id,
-            client_state.connection_id
+            machine_id = %machine_id_parsed,
+            %session_id,
+            owner = %owner_id,
+            connection = %client_state.connection_id,
+            "Created session"
         );
 
         // Get the session to retrieve created_at
-        let created_at = state
-            .coordinator
-            .sessions
-            .get(session_id)
-            .map(|s| s.created_at_iso())
-            .unwrap_or_default();
+        let created_at = match state.coordinator.sessions.get(session_id) {
+            Some(session) => {
+                session.set_size(kt_core::ipc::TerminalSize {
+                    cols: initial_size.cols,
+                    rows: initial_size.rows,
+                });
+                session.created_at_iso()
+            }
+            None => String::new(),
+        };
 
         return IpcResponse::SessionCreated(SessionInfo {
             id: session_id.to_string(),
             machine_id,
-            shell: None,
+            shell,
             created_at,
             pid: None,
-            size: None,
+            size: Some(kt_core::ipc::TerminalSize {
+                cols: initial_size.cols,
+                rows: initial_size.rows,
+            }),
+            name,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
         });
     }
 
@@ -918,9 +1858,16 @@ async fn handle_request_with_client(
             };
         };
 
-        // Validate ownership
-        if let Err(err) = validate_ownership(&session, client_state.effective_client_id()) {
-            return err;
+        // Only the client holding the input may type: the owner, unless
+        // another client claimed it
+        match session.input_holder() {
+            Some(holder) if holder != client_state.effective_client_id() => {
+                return IpcResponse::InputHeld {
+                    session_id: session_id.clone(),
+                    holder,
+                };
+            }
+            _ => {}
         }
 
         // Check session state
@@ -929,6 +1876,44 @@ async fn handle_request_with_client(
                 message: "Session is closing".into(),
             };
         }
+        if session.has_exited() {
+            return IpcResponse::Error {
+                message: "Session's shell has exited".into(),
+            };
+        }
+
+        // Refuse input from runaway automation for a while
+        match state
+            .input_breaker
+            .check(&session, data.len(), Instant::now())
+        {
+            InputVerdict::Allowed => {}
+            InputVerdict::Tripped => {
+                let owner = session.owner_client_id.as_deref().unwrap_or("none");
+                let message = format!(
+                    "Input to session {} (owned by client {}) went over {} KiB per minute; \
+                     refusing it for {}s",
+                    session_id,
+                    owner,
+                    state.input_breaker.max_kib_per_min(),
+                    INPUT_BLOCK_PERIOD.as_secs()
+                );
+                tracing::warn!("{}", message);
+                session.emit(
+                    event_tx,
+                    &state.epoch,
+                    IpcEvent::Notice {
+                        message,
+                        session_id: Some(session_id.clone()),
+                        client_id: session.owner_client_id.clone(),
+                    },
+                );
+                return session_input_refused(session_id, &state.input_breaker);
+            }
+            InputVerdict::Blocked => {
+                return session_input_refused(session_id, &state.input_breaker);
+            }
+        }
 
         // Get the connection for this machine
         let Some(conn) = state.coordinator.connections.get(&session.machine_id) else {
@@ -948,6 +1933,7 @@ async fn handle_request_with_client(
                 message: format!("Failed to send input to agent: {}", e),
             };
         }
+        session.record_input(data.len());
 
         return IpcResponse::Ok;
     }
@@ -960,22 +1946,8 @@ async fn handle_request_with_client(
     } = &request
     {
         // Issue #13: Validate terminal resize dimensions
-        let valid_range = MIN_TERMINAL_SIZE..=MAX_TERMINAL_SIZE;
-        if !valid_range.contains(cols) {
-            return IpcResponse::Error {
-                message: format!(
-                    "Invalid terminal columns: {} (must be {}-{})",
-                    cols, MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE
-                ),
-            };
-        }
-        if !valid_range.contains(rows) {
-            return IpcResponse::Error {
-                message: format!(
-                    "Invalid terminal rows: {} (must be {}-{})",
-                    rows, MIN_TERMINAL_SIZE, MAX_TERMINAL_SIZE
-                ),
-            };
+        if let Err(e) = validate_terminal_size(*cols, *rows) {
+            return IpcResponse::Error { message: e };
         }
 
         // Look up the session to find which machine it belongs to
@@ -985,9 +1957,13 @@ async fn handle_request_with_client(
             };
         };
 
-        // Validate ownership
-        if let Err(err) = validate_ownership(&session, client_state.effective_client_id()) {
-            return err;
+        // Validate ownership; a client that claimed the input may resize too,
+        // so the session fits whoever is typing
+        let client_id = client_state.effective_client_id();
+        if session.input_holder().as_deref() != Some(client_id) {
+            if let Err(err) = validate_ownership(&session, client_id) {
+                return err;
+            }
         }
 
         // Check session state
@@ -1004,10 +1980,23 @@ async fn handle_request_with_client(
             };
         };
 
+        session.set_size(kt_core::ipc::TerminalSize {
+            cols: *cols,
+            rows: *rows,
+        });
+
+        // Resizes arriving in quick succession are merged, the latest size
+        // being sent by the resize flusher
+        let size = TerminalSize::new(*rows, *cols);
+        let Some(size) = state.resizes.submit(session.id, size, Instant::now()) else {
+            tracing::trace!("Holding back resize of session {}", session_id);
+            return IpcResponse::Ok;
+        };
+
         // Send resize command to the agent
         let command = AgentCommand::SessionResize {
             session_id: session.id,
-            size: TerminalSize::new(*rows, *cols),
+            size,
         };
 
         if let Err(e) = conn.command_tx.send(command).await {
@@ -1020,6 +2009,129 @@ async fn handle_request_with_client(
         return IpcResponse::Ok;
     }
 
+    // Handle GetSessionEnv with ownership validation
+    if let IpcRequest::GetSessionEnv {
+        session_id,
+        show_secrets,
+    } = &request
+    {
+        let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+            return IpcResponse::Error {
+                message: format!("Session not found: {}", session_id),
+            };
+        };
+
+        // Validate ownership
+        if let Err(err) = validate_ownership(&session, client_state.effective_client_id()) {
+            return err;
+        }
+
+        // Redact on the server side so secret values never leave the orchestrator
+        // unless explicitly requested
+        let env = session
+            .env
+            .iter()
+            .map(|(name, value)| SessionEnvVar::new(name, value, !show_secrets))
+            .collect();
+
+        return IpcResponse::SessionEnv {
+            session_id: session.id.to_string(),
+            env,
+        };
+    }
+
+    if let IpcRequest::GetSession { session_id } = &request {
+        return get_session(state, session_id, client_state.effective_client_id());
+    }
+
+    if let IpcRequest::ClaimSession { session_id } = &request {
+        let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+            return IpcResponse::Error {
+                message: format!("Session not found: {}", session_id),
+            };
+        };
+        let client_id = client_state.effective_client_id();
+        if !client_state.observed_sessions.contains(session_id) {
+            if let Err(err) = validate_ownership(&session, client_id) {
+                return err;
+            }
+        }
+
+        if session.set_input_holder(Some(client_id.to_string())) {
+            tracing::info!(%session_id, client_id, "Claimed session input");
+            announce_input_holder(state, event_tx, &session, Some(client_id.to_string()));
+        }
+        return IpcResponse::Ok;
+    }
+
+    if let IpcRequest::ReleaseSession { session_id } = &request {
+        let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+            return IpcResponse::Error {
+                message: format!("Session not found: {}", session_id),
+            };
+        };
+        if session.release_input(client_state.effective_client_id()) {
+            tracing::info!(%session_id, "Released session input");
+            announce_input_holder(state, event_tx, &session, None);
+        }
+        return IpcResponse::Ok;
+    }
+
+    if let IpcRequest::SetMaintenanceMode { enabled, message } = request {
+        let message = enabled.then(|| {
+            message
+                .filter(|message| !message.trim().is_empty())
+                .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string())
+        });
+        tracing::warn!(
+            client_id = client_state.effective_client_id(),
+            "Maintenance mode {}",
+            if enabled { "on" } else { "off" }
+        );
+        state.set_maintenance(message);
+        let status = orchestrator_status(state, start_time, false);
+        let _ = event_tx.send(state.epoch.wrap_event(IpcEvent::StatusChanged(status)));
+        return IpcResponse::Ok;
+    }
+
+    // Handle SetSessionMonitor with ownership validation
+    if let IpcRequest::SetSessionMonitor {
+        session_id,
+        activity,
+        silence_secs,
+    } = &request
+    {
+        let Some(session) = state.coordinator.sessions.get_by_string_id(session_id) else {
+            return IpcResponse::Error {
+                message: format!("Session not found: {}", session_id),
+            };
+        };
+
+        // Validate ownership
+        if let Err(err) = validate_ownership(&session, client_state.effective_client_id()) {
+            return err;
+        }
+
+        if *silence_secs == Some(0) {
+            return IpcResponse::Error {
+                message: "silence_secs must be at least 1".into(),
+            };
+        }
+
+        session.monitor().set_settings(MonitorSettings {
+            activity: *activity,
+            silence: silence_secs.map(Duration::from_secs),
+        });
+
+        tracing::debug!(
+            %session_id,
+            activity,
+            ?silence_secs,
+            "Updated session monitor"
+        );
+        return IpcResponse::Ok;
+    }
+
     // Handle CloseSession with ownership validation
     if let IpcRequest::CloseSession { session_id, force: _ } = &request {
         // Look up the session to find which machine it belongs to
@@ -1039,14 +2151,30 @@ async fn handle_request_with_client(
             return IpcResponse::Ok;
         }
 
-        // Transition to Closing state
-        session.try_close();
+        // Transition to Closing state; if another cleanup path got there
+        // first, it announces the close
+        let announce = session.try_close();
+        let announce_closed = || {
+            if announce {
+                session.emit(
+                    event_tx,
+                    &state.epoch,
+                    IpcEvent::SessionClosed {
+                        session_id: session_id.clone(),
+                        // Set if its shell exited while kept open
+                        exit_code: session.exit_code(),
+                        reason: Some(CloseReason::UserRequested),
+                    },
+                );
+            }
+        };
 
         // Get the connection for this machine
         let Some(conn) = state.coordinator.connections.get(&session.machine_id) else {
             // Machine disconnected - just remove the session
             state.coordinator.sessions.remove(session.id);
             client_state.owned_sessions.remove(session_id);
+            announce_closed();
             return IpcResponse::Ok;
         };
 
@@ -1062,8 +2190,9 @@ async fn handle_request_with_client(
         // Remove from session manager
         state.coordinator.sessions.remove(session.id);
         client_state.owned_sessions.remove(session_id);
+        announce_closed();
 
-        tracing::info!("Closed session {}", session_id);
+        tracing::info!(%session_id, "Closed session");
         return IpcResponse::Ok;
     }
 
@@ -1079,69 +2208,29 @@ async fn handle_request(
 ) -> IpcResponse {
     // Use coordinator.connections and coordinator.sessions for proper state management
     match request {
-        IpcRequest::GetStatus => {
-            let machines = state.coordinator.connections.list();
-            let sessions = state.coordinator.sessions.list();
-
-            IpcResponse::Status(OrchestratorStatus {
-                running: true,
-                uptime_secs: start_time.elapsed().as_secs(),
-                machine_count: machines.len(),
-                session_count: sessions.len(),
-                version: env!("CARGO_PKG_VERSION").to_string(),
-                tailscale_hostname: state.config.tailscale_hostname.clone(),
-                bind_address: state.config.bind_address.clone(),
-                pairing_code: Some(state.pairing_code().to_string()),
-            })
-        }
+        IpcRequest::GetStatus {
+            include_pairing_code,
+        } => IpcResponse::Status(orchestrator_status(state, start_time, include_pairing_code)),
 
         IpcRequest::ListMachines => {
-            let connections = state.coordinator.connections.list();
-            let machines: Vec<MachineInfo> = connections
-                .iter()
-                .map(|conn| {
-                    let session_count = state.coordinator.sessions.list_for_machine(&conn.machine_id).len();
-                    MachineInfo {
-                        id: conn.machine_id.to_string(),
-                        alias: conn.alias.clone(),
-                        hostname: conn
-                            .hostname
-                            .clone()
-                            .unwrap_or_else(|| conn.machine_id.to_string()),
-                        os: conn.os.clone(),
-                        arch: conn.arch.clone(),
-                        status: MachineStatus::Connected,
-                        connected_at: None,
-                        last_heartbeat: None,
-                        session_count,
-                        tags: vec![],
-                    }
-                })
-                .collect();
+            let machines = list_machines(state);
 
             IpcResponse::Machines { machines }
         }
 
         IpcRequest::GetMachine { machine_id } => {
             // Look up by machine ID or alias
-            match state.coordinator.connections.get_by_id_or_alias(&machine_id) {
-                Some(conn) => {
+            let connections = &state.coordinator.connections;
+            let found = match connections.get_by_id_or_alias(&machine_id) {
+                Some(conn) => Some((conn, MachineStatus::Connected)),
+                None => connections
+                    .get_reconnecting(&machine_id)
+                    .map(|conn| (conn, MachineStatus::Reconnecting)),
+            };
+            match found {
+                Some((conn, status)) => {
                     let session_count = state.coordinator.sessions.list_for_machine(&conn.machine_id).len();
-                    IpcResponse::Machine(MachineInfo {
-                        id: conn.machine_id.to_string(),
-                        alias: conn.alias.clone(),
-                        hostname: conn
-                            .hostname
-                            .clone()
-                            .unwrap_or_else(|| conn.machine_id.to_string()),
-                        os: conn.os.clone(),
-                        arch: conn.arch.clone(),
-                        status: MachineStatus::Connected,
-                        connected_at: None,
-                        last_heartbeat: None,
-                        session_count,
-                        tags: vec![],
-                    })
+                    IpcResponse::Machine(conn.machine_info(status, session_count))
                 }
                 None => IpcResponse::Error {
                     message: format!("Machine not found: {}", machine_id),
@@ -1149,7 +2238,12 @@ async fn handle_request(
             }
         }
 
-        IpcRequest::ListSessions { machine_id } => {
+        IpcRequest::ListSessions {
+            machine_id,
+            cursor,
+            offset,
+            limit,
+        } => {
             let sessions = if let Some(mid) = machine_id {
                 // Resolve alias to actual machine ID if needed
                 let actual_machine_id = state
@@ -1157,26 +2251,24 @@ async fn handle_request(
                     .connections
                     .get_by_id_or_alias(&mid)
                     .map(|conn| conn.machine_id.clone())
-                    .unwrap_or_else(|| kt_core::MachineId::new(mid));
-                state.coordinator.sessions.list_for_machine(&actual_machine_id)
+                    .or_else(|| kt_core::MachineId::parse(&mid).ok());
+                match actual_machine_id {
+                    Some(id) => state.coordinator.sessions.list_for_machine(&id),
+                    None => Vec::new(),
+                }
             } else {
                 state.coordinator.sessions.list()
             };
 
-            let session_infos: Vec<SessionInfo> = sessions
-                .iter()
-                .map(|s| SessionInfo {
-                    id: s.id.to_string(),
-                    machine_id: s.machine_id.to_string(),
-                    shell: s.shell.clone(),
-                    created_at: s.created_at_iso(),
-                    pid: s.pid(),
-                    size: None,
-                })
-                .collect();
+            let now = Instant::now();
+            let session_infos: Vec<SessionInfo> =
+                sessions.iter().map(|s| session_info(s, now)).collect();
 
+            let page = session_page(session_infos, cursor.as_deref(), offset, limit);
             IpcResponse::Sessions {
-                sessions: session_infos,
+                sessions: page.sessions,
+                total: Some(page.total),
+                next_cursor: page.next_cursor,
             }
         }
 
@@ -1204,6 +2296,36 @@ async fn handle_request(
             }
         }
 
+        // GetSessionEnv is handled in handle_request_with_client for ownership validation
+        // GetSession is handled in handle_request_with_client, which knows the client
+        IpcRequest::GetSession { .. } => IpcResponse::Error {
+            message: "Internal error: GetSession should be handled with client state".to_string(),
+        },
+
+        IpcRequest::ClaimSession { .. } | IpcRequest::ReleaseSession { .. } => IpcResponse::Error {
+            message: "Internal error: ClaimSession should be handled with client state".to_string(),
+        },
+
+        IpcRequest::SetMaintenanceMode { .. } => IpcResponse::Error {
+            message: "Internal error: SetMaintenanceMode should be handled with client state"
+                .to_string(),
+        },
+
+        IpcRequest::GetSessionEnv { .. } => {
+            // This branch should not be reached - GetSessionEnv goes through handle_request_with_client
+            IpcResponse::Error {
+                message: "Internal error: GetSessionEnv should be handled with client state".to_string(),
+            }
+        }
+
+        // SetSessionMonitor is handled in handle_request_with_client for ownership validation
+        IpcRequest::SetSessionMonitor { .. } => {
+            // This branch should not be reached - SetSessionMonitor goes through handle_request_with_client
+            IpcResponse::Error {
+                message: "Internal error: SetSessionMonitor should be handled with client state".to_string(),
+            }
+        }
+
         // CloseSession is handled in handle_request_with_client for ownership validation
         IpcRequest::CloseSession { .. } => {
             // This branch should not be reached - CloseSession goes through handle_request_with_client
@@ -1212,12 +2334,20 @@ async fn handle_request(
             }
         }
 
-        // Subscribe/Unsubscribe are handled in handle_request_with_state
-        IpcRequest::Subscribe { .. } | IpcRequest::Unsubscribe { .. } => {
+        // Subscribe/Unsubscribe/DetachSession are handled in handle_request_with_state
+        IpcRequest::Subscribe { .. }
+        | IpcRequest::ObserveSession { .. }
+        | IpcRequest::Unsubscribe { .. }
+        | IpcRequest::DetachSession { .. } => {
             // This shouldn't be reached - handled by handle_request_with_state
             IpcResponse::Ok
         }
 
+        // TailLogs is handled in handle_client, which owns the log receiver
+        IpcRequest::TailLogs { .. } => IpcResponse::Error {
+            message: "Internal error: TailLogs should be handled by the connection loop".to_string(),
+        },
+
         IpcRequest::DisconnectMachine { machine_id } => {
             // Look up by machine ID or alias
             let Some(conn) = state.coordinator.connections.get_by_id_or_alias(&machine_id) else {
@@ -1239,6 +2369,53 @@ async fn handle_request(
             IpcResponse::Ok
         }
 
+        IpcRequest::SetAgentOption {
+            machine_id,
+            key,
+            value,
+        } => {
+            let Some(conn) = state
+                .coordinator
+                .connections
+                .get_by_id_or_alias(&machine_id)
+            else {
+                return IpcResponse::Error {
+                    message: format!("Machine not found: {}", machine_id),
+                };
+            };
+            set_agent_option(&conn, key, value).await
+        }
+
+        IpcRequest::ListGroups => IpcResponse::Groups {
+            groups: state
+                .groups
+                .list()
+                .into_iter()
+                .map(|(name, members)| group_info(state, name, members))
+                .collect(),
+        },
+
+        IpcRequest::ModifyGroup {
+            group,
+            action,
+            members,
+        } => match state.groups.modify(&group, action, &members) {
+            Ok(changed) => {
+                tracing::info!(?action, %group, ?members, "Modified machine group");
+                IpcResponse::Groups {
+                    groups: changed
+                        .map(|(name, members)| group_info(state, name, members))
+                        .into_iter()
+                        .collect(),
+                }
+            }
+            Err(message) => IpcResponse::Error { message },
+        },
+
+        IpcRequest::ListRejections => IpcResponse::Rejections {
+            rejections: state.rejections.list(),
+        },
+
         IpcRequest::Ping => IpcResponse::Pong,
 
         // Authenticate is handled in handle_client before this function is called
@@ -1271,62 +2448,52 @@ async fn handle_request(
             }
         }
 
-        IpcRequest::GetStateSnapshot => {
+        IpcRequest::GetStateSnapshot {
+            machine_filter,
+            include_sessions,
+            cursor,
+            limit,
+        } => {
             // Get all machines
-            let connections = state.coordinator.connections.list();
-            let machines: Vec<MachineInfo> = connections
-                .iter()
-                .map(|conn| {
-                    let session_count = state.coordinator.sessions.list_for_machine(&conn.machine_id).len();
-                    MachineInfo {
-                        id: conn.machine_id.to_string(),
-                        alias: conn.alias.clone(),
-                        hostname: conn
-                            .hostname
-                            .clone()
-                            .unwrap_or_else(|| conn.machine_id.to_string()),
-                        os: conn.os.clone(),
-                        arch: conn.arch.clone(),
-                        status: MachineStatus::Connected,
-                        connected_at: None,
-                        last_heartbeat: None,
-                        session_count,
-                        tags: vec![],
-                    }
-                })
-                .collect();
+            let machines = list_machines(state);
 
             // Get all sessions
             let all_sessions = state.coordinator.sessions.list();
-            let sessions: Vec<SessionInfo> = all_sessions
-                .iter()
-                .map(|s| SessionInfo {
-                    id: s.id.to_string(),
-                    machine_id: s.machine_id.to_string(),
-                    shell: s.shell.clone(),
-                    created_at: s.created_at_iso(),
-                    pid: s.pid(),
-                    size: None,
-                })
-                .collect();
-
+            let now = Instant::now();
+            let sessions: Vec<SessionInfo> =
+                all_sessions.iter().map(|s| session_info(s, now)).collect();
+
+            let query = SnapshotQuery {
+                machine_filter: machine_filter.as_deref(),
+                include_sessions,
+                cursor: cursor.as_deref(),
+                limit,
+            };
+            let page = snapshot_page(machines, sessions, &query);
             IpcResponse::StateSnapshot {
                 epoch_id: state.epoch.epoch_id_string(),
                 current_seq: state.epoch.current_sequence(),
-                machines,
-                sessions,
+                machines: page.machines,
+                sessions: page.sessions,
+                next_cursor: page.next_cursor,
             }
         }
 
-        IpcRequest::GetEventsSince { since_seq: _ } => {
-            // TODO: Implement event buffer for replay
-            // For now, return empty with truncated=true to force full resync
-            IpcResponse::EventsSince {
-                events: vec![],
-                truncated: true,
-                oldest_available_seq: Some(state.epoch.current_sequence()),
-            }
-        }
+        // GetEventsSince is handled in handle_client, which owns the event history
+        IpcRequest::GetEventsSince { .. } => IpcResponse::Error {
+            message: "Internal error: GetEventsSince should be handled by the connection loop"
+                .to_string(),
+        },
+
+        IpcRequest::RotateIpcToken => IpcResponse::Error {
+            message: "Internal error: RotateIpcToken should be handled by the connection loop"
+                .to_string(),
+        },
+
+        IpcRequest::GetCapabilities => IpcResponse::Error {
+            message: "Internal error: GetCapabilities should be handled by the connection loop"
+                .to_string(),
+        },
     }
 }
 
@@ -1335,69 +2502,69 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_valid_env_var_names() {
-        // Valid names
-        assert!(is_valid_env_var_name("PATH"));
-        assert!(is_valid_env_var_name("_PATH"));
-        assert!(is_valid_env_var_name("my_var"));
-        assert!(is_valid_env_var_name("MY_VAR_123"));
-        assert!(is_valid_env_var_name("_"));
-        assert!(is_valid_env_var_name("_123"));
-        assert!(is_valid_env_var_name("a"));
-        assert!(is_valid_env_var_name("TERM"));
-        assert!(is_valid_env_var_name("HOME"));
-        assert!(is_valid_env_var_name("LD_LIBRARY_PATH"));
+    fn test_rate_limit_kind_classifies_requests() {
+        let input = IpcRequest::SessionInput {
+            session_id: "s1".to_string(),
+            data: b"ls\n".to_vec(),
+        };
+        let resize = IpcRequest::SessionResize {
+            session_id: "s1".to_string(),
+            cols: 80,
+            rows: 24,
+        };
+        assert_eq!(rate_limit_kind(&input), RateLimitKind::Input);
+        assert_eq!(rate_limit_kind(&resize), RateLimitKind::Input);
+        assert_eq!(
+            rate_limit_kind(&IpcRequest::ListMachines),
+            RateLimitKind::Control
+        );
+        assert_eq!(rate_limit_kind(&IpcRequest::Ping), RateLimitKind::Control);
     }
 
     #[test]
-    fn test_invalid_env_var_names() {
-        // Invalid names - empty
-        assert!(!is_valid_env_var_name(""));
-
-        // Invalid names - starts with number
-        assert!(!is_valid_env_var_name("123"));
-        assert!(!is_valid_env_var_name("1PATH"));
-
-        // Invalid names - contains invalid characters
-        assert!(!is_valid_env_var_name("MY-VAR"));
-        assert!(!is_valid_env_var_name("MY.VAR"));
-        assert!(!is_valid_env_var_name("MY VAR"));
-        assert!(!is_valid_env_var_name("MY=VAR"));
-        assert!(!is_valid_env_var_name("MY$VAR"));
-        assert!(!is_valid_env_var_name("MY@VAR"));
-        assert!(!is_valid_env_var_name("$(whoami)"));
-        assert!(!is_valid_env_var_name("`command`"));
-
-        // Invalid names - starts with invalid characters
-        assert!(!is_valid_env_var_name("-PATH"));
-        assert!(!is_valid_env_var_name(".PATH"));
-        assert!(!is_valid_env_var_name(" PATH"));
+    fn test_rate_limits_are_independent() {
+        let mut state = ClientState::new();
+        let limits = IpcRateLimitConfig {
+            input_per_second: 5,
+            control_per_second: 2,
+        };
+
+        // Exhaust the input budget
+        for _ in 0..5 {
+            assert!(state.check_rate_limit(RateLimitKind::Input, &limits));
+        }
+        assert!(!state.check_rate_limit(RateLimitKind::Input, &limits));
+
+        // Control requests still have their own budget
+        assert!(state.check_rate_limit(RateLimitKind::Control, &limits));
+        assert!(state.check_rate_limit(RateLimitKind::Control, &limits));
+        assert!(!state.check_rate_limit(RateLimitKind::Control, &limits));
     }
 
     #[test]
-    fn test_validate_env_vars() {
-        // Empty list is valid
-        assert!(validate_env_vars(&[]).is_ok());
-
-        // Valid variables
-        assert!(validate_env_vars(&[
-            ("PATH".to_string(), "/usr/bin".to_string()),
-            ("TERM".to_string(), "xterm-256color".to_string()),
-            ("_CUSTOM_VAR".to_string(), "value".to_string()),
-        ])
-        .is_ok());
-
-        // Invalid variable name
-        let result = validate_env_vars(&[
-            ("PATH".to_string(), "/usr/bin".to_string()),
-            ("INVALID-VAR".to_string(), "value".to_string()),
-        ]);
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("INVALID-VAR"));
-
-        // Injection attempt
-        let result = validate_env_vars(&[("$(whoami)".to_string(), "value".to_string())]);
-        assert!(result.is_err());
+    fn test_rate_limited_response_names_the_limit() {
+        let limits = IpcRateLimitConfig {
+            input_per_second: 500,
+            control_per_second: 20,
+        };
+
+        let IpcResponse::RateLimited { kind, limit, .. } =
+            rate_limited_response(RateLimitKind::Input, &limits)
+        else {
+            panic!("Expected RateLimited");
+        };
+        assert_eq!((kind, limit), (RateLimitKind::Input, 500));
+
+        let IpcResponse::RateLimited {
+            kind,
+            limit,
+            message,
+        } = rate_limited_response(RateLimitKind::Control, &limits)
+        else {
+            panic!("Expected RateLimited");
+        };
+        assert_eq!((kind, limit), (RateLimitKind::Control, 20));
+        assert!(message.contains("20 control requests"));
     }
 
     #[test]
@@ -1449,43 +2616,6 @@ mod tests {
         assert!(state.auth_lockout_until.is_none());
     }
 
-    #[test]
-    fn test_terminal_size_constants() {
-        // Verify constants are reasonable
-        assert!(MIN_TERMINAL_SIZE >= 1, "Min size should be at least 1");
-        assert!(
-            MAX_TERMINAL_SIZE <= 10000,
-            "Max size should be reasonable (<=10000)"
-        );
-        assert!(
-            MIN_TERMINAL_SIZE < MAX_TERMINAL_SIZE,
-            "Min should be less than max"
-        );
-    }
-
-    #[test]
-    fn test_terminal_size_typical_values() {
-        // Typical terminal sizes should be within bounds
-        let typical_cols = [80, 120, 132, 200];
-        let typical_rows = [24, 25, 40, 50, 80];
-
-        for cols in typical_cols {
-            assert!(
-                cols >= MIN_TERMINAL_SIZE && cols <= MAX_TERMINAL_SIZE,
-                "Typical cols {} should be valid",
-                cols
-            );
-        }
-
-        for rows in typical_rows {
-            assert!(
-                rows >= MIN_TERMINAL_SIZE && rows <= MAX_TERMINAL_SIZE,
-                "Typical rows {} should be valid",
-                rows
-            );
-        }
-    }
-
     #[test]
     fn test_client_state_owned_sessions_tracking() {
         let mut state = ClientState::new();
@@ -1510,4 +2640,1530 @@ mod tests {
         assert_eq!(state.owned_sessions.len(), 1);
         assert!(!state.owned_sessions.contains("session-1"));
     }
+
+    #[tokio::test]
+    async fn test_get_session_env_enforces_ownership_and_redacts() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![
+                ("EDITOR".to_string(), "vim".to_string()),
+                ("GITHUB_TOKEN".to_string(), "ghp_secret".to_string()),
+            ],
+            Some("owner".to_string()),
+        );
+
+        let request = |show_secrets| IpcRequest::GetSessionEnv {
+            session_id: session_id.to_string(),
+            show_secrets,
+        };
+
+        // Another client must not see the environment
+        let mut other = ClientState::new();
+        other.logical_client_id = Some("intruder".to_string());
+        let response = handle_request_with_client(
+            request(true),
+            &state,
+            Instant::now(),
+            &mut other,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Error { .. }));
+
+        // The owner gets redacted values by default
+        let mut owner = ClientState::new();
+        owner.logical_client_id = Some("owner".to_string());
+        let response = handle_request_with_client(
+            request(false),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::SessionEnv { env, .. } = response else {
+            panic!("Expected SessionEnv, got {:?}", response);
+        };
+        assert_eq!(env[0].value, "vim");
+        assert!(env[1].redacted);
+        assert_ne!(env[1].value, "ghp_secret");
+
+        // ...and the real values when explicitly asked
+        let response = handle_request_with_client(
+            request(true),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::SessionEnv { env, .. } = response else {
+            panic!("Expected SessionEnv, got {:?}", response);
+        };
+        assert_eq!(env[1].value, "ghp_secret");
+    }
+
+    #[tokio::test]
+    async fn test_attach_motd_sent_once_per_subscription() {
+        let config = kt_core::config::OrchestratorConfig {
+            attach_motd: Some("\u{1b}[1mShared host\u{1b}[0m\nNo crypto mining".to_string()),
+            ..Default::default()
+        };
+        let state = OrchestratorState::new(config);
+        let (event_tx, _) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            None,
+        );
+        let subscribe = || IpcRequest::Subscribe {
+            session_id: session_id.to_string(),
+        };
+
+        let mut client = ClientState::new();
+        let response = handle_request_with_state(
+            subscribe(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Subscribed { session, motd, .. } = response else {
+            panic!("Expected Subscribed, got {:?}", response);
+        };
+        assert_eq!(session.id, session_id.to_string());
+        assert_eq!(
+            motd.as_deref(),
+            Some("\u{1b}[1mShared host\u{1b}[0m\r\nNo crypto mining\u{1b}[0m\r\n")
+        );
+
+        // Subscribing again on the same connection doesn't repeat it
+        let response = handle_request_with_state(
+            subscribe(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+
+        // Without a message, subscribing is answered as before
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            None,
+        );
+        let response = handle_request_with_state(
+            IpcRequest::Subscribe {
+                session_id: session_id.to_string(),
+            },
+            &state,
+            Instant::now(),
+            &mut ClientState::new(),
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+    }
+
+    #[tokio::test]
+    async fn test_input_handoff_between_attached_clients() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, mut event_rx) = broadcast::channel::<IpcEventEnvelope>(16);
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("alice".to_string()),
+        );
+        let session_id = session_id.to_string();
+
+        let mut alice = ClientState::new();
+        alice.logical_client_id = Some("alice".to_string());
+        let mut bob = ClientState::new();
+        bob.logical_client_id = Some("bob".to_string());
+        let mut carol = ClientState::new();
+        carol.logical_client_id = Some("carol".to_string());
+
+        macro_rules! send {
+            ($client:expr, $request:expr) => {
+                handle_request_with_state(
+                    $request,
+                    &state,
+                    Instant::now(),
+                    &mut $client,
+                    &event_tx,
+                    None,
+                )
+                .await
+            };
+        }
+        let input = || IpcRequest::SessionInput {
+            session_id: session_id.clone(),
+            data: b"ls\n".to_vec(),
+        };
+        let claim = || IpcRequest::ClaimSession {
+            session_id: session_id.clone(),
+        };
+        let mut holder_events = || {
+            let mut holders = Vec::new();
+            while let Ok(envelope) = event_rx.try_recv() {
+                if let IpcEvent::SessionInputHolder { holder, .. } = envelope.event {
+                    holders.push(holder);
+                }
+            }
+            holders
+        };
+
+        // An observer watches without input until it claims it
+        let response = send!(
+            bob,
+            IpcRequest::ObserveSession {
+                session_id: session_id.clone(),
+            }
+        );
+        assert!(matches!(response, IpcResponse::Ok));
+        let IpcResponse::InputHeld { holder, .. } = send!(bob, input()) else {
+            panic!("Expected InputHeld");
+        };
+        assert_eq!(holder, "alice");
+        assert!(matches!(send!(alice, input()), IpcResponse::Ok));
+        assert!(command_rx.try_recv().is_ok());
+
+        // Claiming hands the input over, and everyone is told
+        assert!(matches!(send!(bob, claim()), IpcResponse::Ok));
+        assert_eq!(holder_events(), vec![Some("bob".to_string())]);
+        let IpcResponse::InputHeld { holder, .. } = send!(alice, input()) else {
+            panic!("Expected InputHeld");
+        };
+        assert_eq!(holder, "bob");
+        assert!(matches!(send!(bob, input()), IpcResponse::Ok));
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(AgentCommand::SessionInput { .. })
+        ));
+
+        // The owner can take it back; strangers can't claim it at all
+        assert!(matches!(send!(alice, claim()), IpcResponse::Ok));
+        assert!(matches!(send!(carol, claim()), IpcResponse::Error { .. }));
+        assert!(matches!(send!(bob, input()), IpcResponse::InputHeld { .. }));
+
+        // Releasing, or an observer leaving, gives it back to the owner
+        assert!(matches!(send!(bob, claim()), IpcResponse::Ok));
+        let response = send!(
+            bob,
+            IpcRequest::Unsubscribe {
+                session_id: session_id.clone(),
+            }
+        );
+        assert!(matches!(response, IpcResponse::Ok));
+        assert_eq!(
+            holder_events(),
+            vec![Some("alice".to_string()), Some("bob".to_string()), None]
+        );
+        assert!(matches!(send!(alice, input()), IpcResponse::Ok));
+    }
+
+    #[tokio::test]
+    async fn test_detach_and_reattach_session() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+        let session = state.coordinator.sessions.get(session_id).unwrap();
+        let detach = || IpcRequest::DetachSession {
+            session_id: session_id.to_string(),
+        };
+
+        let mut other = ClientState::new();
+        other.logical_client_id = Some("intruder".to_string());
+        let response = handle_request_with_state(
+            detach(),
+            &state,
+            Instant::now(),
+            &mut other,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Error { .. }));
+        assert!(!session.is_detached());
+
+        let mut owner = ClientState::new();
+        owner.logical_client_id = Some("owner".to_string());
+        owner.subscribed_sessions.insert(session_id.to_string());
+        let response = handle_request_with_state(
+            detach(),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert!(session.is_detached());
+        assert!(owner.subscribed_sessions.is_empty());
+
+        // Still listed, marked as detached
+        let response = handle_request_with_client(
+            IpcRequest::ListSessions {
+                machine_id: None,
+                cursor: None,
+                offset: None,
+                limit: None,
+            },
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Sessions { sessions, .. } = response else {
+            panic!("Expected Sessions, got {:?}", response);
+        };
+        assert!(sessions[0].detached);
+
+        // The owner reconnecting doesn't reattach it...
+        reclaim_orphaned_sessions(&state, "owner", &mut owner);
+        assert!(session.is_detached());
+
+        // ...subscribing does
+        let response = handle_request_with_state(
+            IpcRequest::Subscribe {
+                session_id: session_id.to_string(),
+            },
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert_eq!(session.state(), SessionState::Active);
+        assert!(!session.is_detached());
+    }
+
+    #[tokio::test]
+    async fn test_set_session_monitor_enforces_ownership() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+        let request = |silence_secs| IpcRequest::SetSessionMonitor {
+            session_id: session_id.to_string(),
+            activity: true,
+            silence_secs,
+        };
+        let session = state.coordinator.sessions.get(session_id).unwrap();
+
+        let mut other = ClientState::new();
+        other.logical_client_id = Some("intruder".to_string());
+        let response = handle_request_with_client(
+            request(Some(30)),
+            &state,
+            Instant::now(),
+            &mut other,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Error { .. }));
+        assert_eq!(session.monitor().settings(), MonitorSettings::default());
+
+        let mut owner = ClientState::new();
+        owner.logical_client_id = Some("owner".to_string());
+        let response = handle_request_with_client(
+            request(Some(0)),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Error { .. }));
+
+        let response = handle_request_with_client(
+            request(Some(30)),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert_eq!(
+            session.monitor().settings(),
+            MonitorSettings {
+                activity: true,
+                silence: Some(Duration::from_secs(30)),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close_session_announces_user_requested() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, mut event_rx) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+
+        let mut owner = ClientState::new();
+        owner.logical_client_id = Some("owner".to_string());
+        let request = || IpcRequest::CloseSession {
+            session_id: session_id.to_string(),
+            force: false,
+        };
+        let response = handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+
+        let envelope = event_rx.try_recv().unwrap();
+        let IpcEvent::SessionClosed { reason, .. } = envelope.event else {
+            panic!("Expected SessionClosed, got {:?}", envelope.event);
+        };
+        assert_eq!(reason, Some(CloseReason::UserRequested));
+
+        // The session is gone, so closing again announces nothing
+        handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_session_details_live_and_closed() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _event_rx) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+
+        let mut owner = ClientState::new();
+        owner.logical_client_id = Some("owner".to_string());
+        let mut viewer = ClientState::new();
+        viewer.logical_client_id = Some("viewer".to_string());
+        handle_request_with_state(
+            IpcRequest::Subscribe {
+                session_id: session_id.to_string(),
+            },
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+
+        let get = || IpcRequest::GetSession {
+            session_id: session_id.to_string(),
+        };
+        let response =
+            handle_request_with_client(get(), &state, Instant::now(), &mut viewer, &event_tx, None)
+                .await;
+        let IpcResponse::Session(details) = response else {
+            panic!("Expected Session, got {:?}", response);
+        };
+        assert_eq!(details.state, SessionLifecycle::Active);
+        assert_eq!(details.owner_client_id.as_deref(), Some("owner"));
+        assert!(!details.owned_by_you);
+        assert_eq!(details.subscribers, 1);
+        assert!(details.idle_secs.is_some());
+        assert!(details.close_reason.is_none());
+
+        handle_request_with_client(
+            IpcRequest::CloseSession {
+                session_id: session_id.to_string(),
+                force: false,
+            },
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        let response =
+            handle_request_with_client(get(), &state, Instant::now(), &mut owner, &event_tx, None)
+                .await;
+        let IpcResponse::Session(details) = response else {
+            panic!("Expected Session, got {:?}", response);
+        };
+        assert_eq!(details.state, SessionLifecycle::Closed);
+        assert!(details.owned_by_you);
+        assert_eq!(details.close_reason, Some(CloseReason::UserRequested));
+        assert!(details.closed_at.is_some());
+        assert!(details.idle_secs.is_none());
+
+        let response = handle_request_with_client(
+            IpcRequest::GetSession {
+                session_id: "999".to_string(),
+            },
+            &state,
+            Instant::now(),
+            &mut owner,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Error { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_create_session_rejected_at_total_capacity() {
+        let config = kt_core::config::OrchestratorConfig {
+            max_total_sessions: Some(1),
+            ..Default::default()
+        };
+        let state = OrchestratorState::new(config);
+        let (event_tx, _) = broadcast::channel(16);
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+
+        let request = || IpcRequest::CreateSession {
+            machine_id: "machine-1".to_string(),
+            shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
+        };
+        let mut client = ClientState::new();
+
+        let response = handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+
+        let response = handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::CapacityExceeded { current, max, .. } = response else {
+            panic!("Expected CapacityExceeded, got {:?}", response);
+        };
+        assert_eq!((current, max), (1, 1));
+
+        // Only the first session reached the agent
+        assert_eq!(state.coordinator.sessions.len(), 1);
+        assert!(command_rx.try_recv().is_ok());
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_session_rejected_while_shedding_memory() {
+        let mut config = kt_core::config::OrchestratorConfig::default();
+        config.memory_limit.soft_limit_mb = Some(100);
+        let state = OrchestratorState::new(config);
+        let (event_tx, _) = broadcast::channel(16);
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let request = || IpcRequest::CreateSession {
+            machine_id: "machine-1".to_string(),
+            shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
+        };
+        let mut client = ClientState::new();
+
+        let limits = &state.config().memory_limit;
+        assert!(state.memory.update(200 * 1024 * 1024, limits));
+        let response = handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::CapacityExceeded { message, .. } = response else {
+            panic!("Expected CapacityExceeded, got {:?}", response);
+        };
+        assert!(message.contains("200 MiB"), "{}", message);
+        assert!(state.coordinator.sessions.is_empty());
+        assert!(command_rx.try_recv().is_err());
+
+        // Sessions are taken again once memory use has dropped
+        assert!(state.memory.update(10 * 1024 * 1024, limits));
+        let response = handle_request_with_client(
+            request(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+        assert_eq!(state.coordinator.sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_refuses_only_new_sessions() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, mut event_rx) = broadcast::channel(64);
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let create = || IpcRequest::CreateSession {
+            machine_id: "machine-1".to_string(),
+            shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
+        };
+        let mut client = ClientState::new();
+
+        let IpcResponse::SessionCreated(existing) = handle_request_with_client(
+            create(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await
+        else {
+            panic!("Expected SessionCreated");
+        };
+        while command_rx.try_recv().is_ok() {}
+
+        let response = handle_request_with_client(
+            IpcRequest::SetMaintenanceMode {
+                enabled: true,
+                message: Some("Upgrading agents until 14:00".to_string()),
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        let announced = std::iter::from_fn(|| event_rx.try_recv().ok()).find_map(|envelope| {
+            match envelope.event {
+                IpcEvent::StatusChanged(status) => status.maintenance,
+                _ => None,
+            }
+        });
+        assert_eq!(announced.as_deref(), Some("Upgrading agents until 14:00"));
+
+        let response = handle_request_with_client(
+            create(),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert_eq!(message, "Upgrading agents until 14:00");
+        assert_eq!(state.coordinator.sessions.len(), 1);
+        assert!(command_rx.try_recv().is_err());
+
+        // The existing session keeps taking input and streaming output
+        let response = handle_request_with_client(
+            IpcRequest::SessionInput {
+                session_id: existing.id.clone(),
+                data: b"ls\n".to_vec(),
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(AgentCommand::SessionInput { .. })
+        ));
+        let session = state
+            .coordinator
+            .sessions
+            .get_by_string_id(&existing.id)
+            .unwrap();
+        assert!(session.emit_output(
+            &event_tx,
+            &state.epoch,
+            &state.coalescing,
+            b"file.txt\n".to_vec(),
+            kt_core::ipc::OutputStream::Stdout,
+        ));
+        assert!(std::iter::from_fn(|| event_rx.try_recv().ok())
+            .any(|envelope| matches!(envelope.event, IpcEvent::TerminalOutput { .. })));
+
+        let IpcResponse::Status(status) = handle_request_with_client(
+            IpcRequest::GetStatus {
+                include_pairing_code: false,
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await
+        else {
+            panic!("Expected Status");
+        };
+        assert_eq!(
+            status.maintenance.as_deref(),
+            Some("Upgrading agents until 14:00")
+        );
+
+        let response = handle_request_with_client(
+            IpcRequest::SetMaintenanceMode {
+                enabled: false,
+                message: None,
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert!(matches!(
+            handle_request_with_client(
+                create(),
+                &state,
+                Instant::now(),
+                &mut client,
+                &event_tx,
+                None,
+            )
+            .await,
+            IpcResponse::SessionCreated(_)
+        ));
+        assert_eq!(state.coordinator.sessions.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_idempotency_key() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+
+        async fn create(state: &OrchestratorState, client: &mut ClientState, key: &str) -> String {
+            let request = IpcRequest::CreateSession {
+                machine_id: "machine-1".to_string(),
+                shell: None,
+                cwd: None,
+                env: vec![],
+                name: None,
+                size: None,
+                allocate_pty: true,
+                log_level: None,
+                term: None,
+                truecolor: false,
+                idempotency_key: Some(key.to_string()),
+                ttl_secs: None,
+                close_on_exit: None,
+            };
+            let (event_tx, _) = broadcast::channel(16);
+            let response =
+                handle_request_with_client(request, state, Instant::now(), client, &event_tx, None)
+                    .await;
+            let IpcResponse::SessionCreated(info) = response else {
+                panic!("Expected SessionCreated, got {:?}", response);
+            };
+            info.id
+        }
+        let client = |id: &str| ClientState {
+            logical_client_id: Some(id.to_string()),
+            ..ClientState::new()
+        };
+
+        let mut client_a = client("client-a");
+        let first = create(&state, &mut client_a, "retry-1").await;
+        assert_eq!(create(&state, &mut client_a, "retry-1").await, first);
+
+        // Keys belong to the logical client, so a new connection finds them
+        let mut reconnected = client("client-a");
+        assert_eq!(create(&state, &mut reconnected, "retry-1").await, first);
+        assert!(reconnected.owned_sessions.contains(&first));
+
+        // The same key from another client is another session
+        let other = create(&state, &mut client("client-b"), "retry-1").await;
+        assert_ne!(other, first);
+
+        assert_eq!(state.coordinator.sessions.len(), 2);
+        assert!(command_rx.try_recv().is_ok());
+        assert!(command_rx.try_recv().is_ok());
+        assert!(command_rx.try_recv().is_err());
+
+        // Once the session is gone the key creates a new one
+        let sessions = &state.coordinator.sessions;
+        sessions.remove(sessions.get_by_string_id(&first).unwrap().id);
+        let recreated = create(&state, &mut client_a, "retry-1").await;
+        assert_ne!(recreated, first);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_creates_with_same_idempotency_key() {
+        let state = Arc::new(OrchestratorState::new(
+            kt_core::config::OrchestratorConfig::default(),
+        ));
+        let (command_tx, mut command_rx) = tokio::sync::mpsc::channel(64);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+
+        // Retries on separate connections of the same logical client
+        let barrier = Arc::new(tokio::sync::Barrier::new(32));
+        let tasks: Vec<_> = (0..32)
+            .map(|_| {
+                let state = state.clone();
+                let barrier = barrier.clone();
+                tokio::spawn(async move {
+                    let mut client = ClientState {
+                        logical_client_id: Some("client-a".to_string()),
+                        ..ClientState::new()
+                    };
+                    let request = IpcRequest::CreateSession {
+                        machine_id: "machine-1".to_string(),
+                        shell: None,
+                        cwd: None,
+                        env: vec![],
+                        name: None,
+                        size: None,
+                        allocate_pty: true,
+                        log_level: None,
+                        term: None,
+                        truecolor: false,
+                        idempotency_key: Some("retry-1".to_string()),
+                        ttl_secs: None,
+                        close_on_exit: None,
+                    };
+                    let (event_tx, _) = broadcast::channel(16);
+                    barrier.wait().await;
+                    handle_request_with_client(
+                        request,
+                        &state,
+                        Instant::now(),
+                        &mut client,
+                        &event_tx,
+                        None,
+                    )
+                    .await
+                })
+            })
+            .collect();
+
+        let mut created = std::collections::HashSet::new();
+        for task in tasks {
+            match task.await.unwrap() {
+                IpcResponse::SessionCreated(info) => {
+                    created.insert(info.id);
+                }
+                IpcResponse::Error { message } => {
+                    assert!(message.contains("still creating"), "{}", message)
+                }
+                other => panic!("Unexpected response: {:?}", other),
+            }
+        }
+
+        // Every request either got the one session or was told to retry
+        assert_eq!(created.len(), 1);
+        assert_eq!(state.coordinator.sessions.len(), 1);
+        assert!(command_rx.try_recv().is_ok());
+        assert!(command_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_session_with_ttl() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let (command_tx, _command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let request = |ttl_secs: u64| IpcRequest::CreateSession {
+            machine_id: "machine-1".to_string(),
+            shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: Some(ttl_secs),
+            close_on_exit: Some(false),
+        };
+        let mut client = ClientState::new();
+
+        let response = handle_request_with_client(
+            request(0),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(
+            matches!(response, IpcResponse::Error { .. }),
+            "{:?}",
+            response
+        );
+        assert!(state.coordinator.sessions.is_empty());
+
+        let response = handle_request_with_client(
+            request(600),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::SessionCreated(created) = response else {
+            panic!("Expected SessionCreated, got {:?}", response);
+        };
+        let session = state
+            .coordinator
+            .sessions
+            .get_by_string_id(&created.id)
+            .unwrap();
+        assert!(session.keeps_on_exit());
+
+        let IpcResponse::Session(details) =
+            get_session(&state, &created.id, client.effective_client_id())
+        else {
+            panic!("Expected Session");
+        };
+        assert!(
+            matches!(details.expires_in_secs, Some(595..=600)),
+            "{:?}",
+            details
+        );
+
+        // Once its shell exits it's kept, refusing input
+        session.mark_exited(Some(0));
+        let IpcResponse::Session(details) =
+            get_session(&state, &created.id, client.effective_client_id())
+        else {
+            panic!("Expected Session");
+        };
+        assert_eq!(details.state, SessionLifecycle::Exited);
+        assert_eq!(details.exit_code, Some(0));
+        let input = IpcRequest::SessionInput {
+            session_id: created.id.clone(),
+            data: b"ls\n".to_vec(),
+        };
+        let response =
+            handle_request_with_client(input, &state, Instant::now(), &mut client, &event_tx, None)
+                .await;
+        assert!(
+            matches!(response, IpcResponse::Error { .. }),
+            "{:?}",
+            response
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_session_checks_agent_capabilities() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let (command_tx, _command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("old-agent"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let (command_tx, _command_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(
+            TunnelConnection::new(
+                kt_core::MachineId::new("new-agent"),
+                None,
+                None,
+                "linux".to_string(),
+                "x86_64".to_string(),
+                command_tx,
+                CancellationToken::new(),
+            )
+            .with_capabilities(kt_protocol::AgentCapabilities::empty().with(Capability::Cwd)),
+        );
+
+        let request = |machine_id: &str, cwd: Option<&str>| IpcRequest::CreateSession {
+            machine_id: machine_id.to_string(),
+            shell: None,
+            cwd: cwd.map(String::from),
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
+        };
+        let mut client = ClientState::new();
+
+        // No capabilities is fine for a plain session
+        let response = handle_request_with_client(
+            request("old-agent", None),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+
+        // ...but a working directory needs the agent to support it
+        let response = handle_request_with_client(
+            request("old-agent", Some("/tmp")),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert!(message.contains("does not support"), "{}", message);
+        assert!(message.contains("cwd"), "{}", message);
+
+        let response = handle_request_with_client(
+            request("new-agent", Some("/tmp")),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+
+        // Sessions without a PTY need pipe support too
+        let response = handle_request_with_client(
+            IpcRequest::CreateSession {
+                machine_id: "new-agent".to_string(),
+                shell: None,
+                cwd: None,
+                env: vec![],
+                name: None,
+                size: None,
+                allocate_pty: false,
+                log_level: None,
+                term: None,
+                truecolor: false,
+                idempotency_key: None,
+                ttl_secs: None,
+                close_on_exit: None,
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert!(message.contains("pipes"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_set_agent_option_waits_for_ack() {
+        let connection = |capabilities| {
+            let (command_tx, command_rx) = tokio::sync::mpsc::channel(8);
+            let conn = TunnelConnection::new(
+                kt_core::MachineId::new("web"),
+                None,
+                None,
+                "linux".to_string(),
+                "x86_64".to_string(),
+                command_tx,
+                CancellationToken::new(),
+            )
+            .with_capabilities(capabilities);
+            (Arc::new(conn), command_rx)
+        };
+        let option = |value: &str| ("metrics_interval".to_string(), value.to_string());
+
+        // Older agents would drop the connection on the message
+        let (conn, _command_rx) = connection(kt_protocol::AgentCapabilities::empty());
+        let (key, value) = option("30");
+        let response = set_agent_option(&conn, key, value).await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert!(message.contains("agent_config"), "{}", message);
+
+        // Invalid values of known keys never reach the agent
+        let capabilities = kt_protocol::AgentCapabilities::empty().with(Capability::AgentConfig);
+        let (conn, mut command_rx) = connection(capabilities);
+        let (key, value) = option("soon");
+        let response = set_agent_option(&conn, key, value).await;
+        assert!(
+            matches!(response, IpcResponse::Error { .. }),
+            "{:?}",
+            response
+        );
+        assert!(command_rx.try_recv().is_err());
+
+        // The answer is the agent's ack
+        let acker = Arc::clone(&conn);
+        tokio::spawn(async move {
+            while let Some(command) = command_rx.recv().await {
+                if let AgentCommand::UpdateConfig { key, value } = command {
+                    let result = if key == "metrics_interval" {
+                        Ok(())
+                    } else {
+                        Err(format!("Unknown setting '{}'", key))
+                    };
+                    assert!(acker.ack_config(&key, result), "{}={}", key, value);
+                }
+            }
+        });
+        let (key, value) = option("30");
+        assert!(matches!(
+            set_agent_option(&conn, key, value).await,
+            IpcResponse::Ok
+        ));
+        let response =
+            set_agent_option(&conn, "output_rate_cap".to_string(), "64".to_string()).await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert!(message.contains("Unknown setting"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_create_session_log_level_only_reaches_capable_agents() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let (command_tx, mut old_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(TunnelConnection::new(
+            kt_core::MachineId::new("old-agent"),
+            None,
+            None,
+            "linux".to_string(),
+            "x86_64".to_string(),
+            command_tx,
+            CancellationToken::new(),
+        ));
+        let (command_tx, mut new_rx) = tokio::sync::mpsc::channel(8);
+        state.coordinator.connections.insert(
+            TunnelConnection::new(
+                kt_core::MachineId::new("new-agent"),
+                None,
+                None,
+                "linux".to_string(),
+                "x86_64".to_string(),
+                command_tx,
+                CancellationToken::new(),
+            )
+            .with_capabilities(
+                kt_protocol::AgentCapabilities::empty().with(Capability::SessionLogLevel),
+            ),
+        );
+
+        let request = |machine_id: &str, level: &str| IpcRequest::CreateSession {
+            machine_id: machine_id.to_string(),
+            shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: Some(level.to_string()),
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
+        };
+        let mut client = ClientState::new();
+        let sent_level = |command| match command {
+            AgentCommand::CreateSession { log_level, .. } => log_level,
+            other => panic!("Expected CreateSession, got {:?}", other),
+        };
+
+        // Older agents still get the session, just without the level
+        let response = handle_request_with_client(
+            request("old-agent", "debug"),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+        assert_eq!(sent_level(old_rx.try_recv().unwrap()), None);
+
+        let response = handle_request_with_client(
+            request("new-agent", "TRACE"),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::SessionCreated(_)));
+        assert_eq!(
+            sent_level(new_rx.try_recv().unwrap()),
+            Some("trace".to_string())
+        );
+
+        let response = handle_request_with_client(
+            request("new-agent", "loud"),
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        let IpcResponse::Error { message } = response else {
+            panic!("Expected Error, got {:?}", response);
+        };
+        assert!(message.contains("Invalid log level"), "{}", message);
+        assert!(new_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_term_for_agent_falls_back_to_env() {
+        let connection = || {
+            let (command_tx, _) = tokio::sync::mpsc::channel(1);
+            TunnelConnection::new(
+                kt_core::MachineId::new("agent"),
+                None,
+                None,
+                "linux".to_string(),
+                "x86_64".to_string(),
+                command_tx,
+                CancellationToken::new(),
+            )
+        };
+        let env = vec![("LANG".to_string(), "C.UTF-8".to_string())];
+        let term = Some("xterm-kitty".to_string());
+
+        let capable = connection()
+            .with_capabilities(kt_protocol::AgentCapabilities::empty().with(Capability::Term));
+        assert_eq!(
+            term_for_agent(&capable, env.clone(), term.clone(), true),
+            (env.clone(), term.clone(), true)
+        );
+
+        let (old_env, old_term, old_truecolor) =
+            term_for_agent(&connection(), env.clone(), term, true);
+        assert_eq!(old_term, None);
+        assert!(!old_truecolor);
+        assert_eq!(
+            old_env,
+            vec![
+                ("LANG".to_string(), "C.UTF-8".to_string()),
+                ("TERM".to_string(), "xterm-kitty".to_string()),
+                ("COLORTERM".to_string(), "truecolor".to_string()),
+            ]
+        );
+
+        // Nothing to add when the client didn't say
+        assert_eq!(
+            term_for_agent(&connection(), env.clone(), None, false),
+            (env, None, false)
+        );
+    }
+
+    #[test]
+    fn test_tail_logs() {
+        let mut log_rx = None;
+
+        let response = tail_logs(None, true, 10, &mut log_rx);
+        assert!(matches!(response, IpcResponse::Error { .. }));
+        assert!(log_rx.is_none());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(crate::logging::LOG_FILE_NAME);
+        let line = LogLine {
+            timestamp_ms: 1,
+            level: "INFO".to_string(),
+            target: "kt_orchestrator".to_string(),
+            message: "started".to_string(),
+        };
+        std::fs::write(&path, crate::logging::format_log_line(&line) + "\n").unwrap();
+        let source = LogSource::new(Some(path));
+
+        // History only
+        let response = tail_logs(Some(&source), false, 10, &mut log_rx);
+        let IpcResponse::LogLines { lines } = response else {
+            panic!("Expected LogLines, got {:?}", response);
+        };
+        assert_eq!(lines, vec![line]);
+        assert!(log_rx.is_none());
+
+        // Following subscribes to live lines
+        tail_logs(Some(&source), true, 0, &mut log_rx);
+        assert!(log_rx.is_some());
+    }
+
+    #[test]
+    fn test_capabilities_follow_log_source() {
+        let caps = capabilities(None);
+        assert_eq!(caps.protocol_version, kt_core::ipc::IPC_PROTOCOL_VERSION);
+        assert!(caps.supports(IpcFeature::EventReplay));
+        assert!(caps.supports(IpcFeature::SnapshotPaging));
+        assert!(caps.supports(IpcFeature::SessionPaging));
+        assert!(!caps.supports(IpcFeature::LogTailing));
+        assert!(!caps.supports(IpcFeature::FileTransfer));
+
+        let caps = capabilities(Some(&LogSource::new(None)));
+        assert!(caps.supports(IpcFeature::LogTailing));
+    }
+
+    #[test]
+    fn test_events_since_filters_unsubscribed_output() {
+        let history = EventHistory::new();
+        for (seq, session_id) in [(1, "session-1"), (2, "session-2"), (3, "session-1")] {
+            history.record(IpcEventEnvelope {
+                seq,
+                timestamp: 0,
+                event: IpcEvent::TerminalOutput {
+                    session_id: session_id.to_string(),
+                    data: b"x".to_vec(),
+                    stream: kt_core::ipc::OutputStream::Stdout,
+                },
+                session_seq: None,
+            });
+        }
+
+        let mut client_state = ClientState::new();
+        client_state
+            .subscribed_sessions
+            .insert("session-1".to_string());
+
+        let response = events_since(&history, 1, &client_state);
+        let IpcResponse::EventsSince {
+            events, truncated, ..
+        } = response
+        else {
+            panic!("Expected EventsSince, got {:?}", response);
+        };
+        assert_eq!(events.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![3]);
+        assert!(!truncated);
+    }
+
+    #[tokio::test]
+    async fn test_backpressure_pauses_only_owned_attached_sessions() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (event_tx, _) = broadcast::channel(16);
+        let create = |owner: &str| {
+            state.coordinator.sessions.create_with_env(
+                kt_core::MachineId::new("machine-1"),
+                None,
+                vec![],
+                Some(owner.to_string()),
+            )
+        };
+        let (owned_id, observed_id) = (create("owner"), create("other"));
+        let owned = state.coordinator.sessions.get(owned_id).unwrap();
+        let observed = state.coordinator.sessions.get(observed_id).unwrap();
+
+        let mut client = ClientState::new();
+        client.logical_client_id = Some("owner".to_string());
+        for request in [
+            IpcRequest::Subscribe {
+                session_id: owned_id.to_string(),
+            },
+            IpcRequest::ObserveSession {
+                session_id: observed_id.to_string(),
+            },
+        ] {
+            let response = handle_request_with_state(
+                request,
+                &state,
+                Instant::now(),
+                &mut client,
+                &event_tx,
+                None,
+            )
+            .await;
+            assert!(matches!(response, IpcResponse::Ok));
+        }
+
+        client.apply_backpressure(OWNER_PAUSE_BACKLOG);
+        assert!(owned.output_paused());
+        assert!(!observed.output_paused());
+        // Between the marks nothing changes
+        client.apply_backpressure(OWNER_RESUME_BACKLOG + 1);
+        assert!(owned.output_paused());
+        client.apply_backpressure(OWNER_RESUME_BACKLOG);
+        assert!(!owned.output_paused());
+
+        // Unsubscribing while paused lifts the pause
+        client.apply_backpressure(IPC_EVENT_CHANNEL_CAPACITY);
+        let response = handle_request_with_state(
+            IpcRequest::Unsubscribe {
+                session_id: owned_id.to_string(),
+            },
+            &state,
+            Instant::now(),
+            &mut client,
+            &event_tx,
+            None,
+        )
+        .await;
+        assert!(matches!(response, IpcResponse::Ok));
+        assert!(!owned.output_paused());
+    }
 }
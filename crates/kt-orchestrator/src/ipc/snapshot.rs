@@ -0,0 +1,360 @@
+//! Filtering and paging of `GetStateSnapshot` and `ListSessions`
+//!
+//! With many machines the full snapshot is one JSON line of several
+//! megabytes, which stalls the connection while it is written and the
+//! client while it parses it. Clients can instead ask for one machine, leave
+//! out sessions, or page through the machines `limit` at a time.
+//!
+//! Pages are ordered by machine ID, and the cursor is the last ID of the
+//! previous page rather than an offset, so machines connecting or
+//! disconnecting while a client pages don't shift the pages: every machine
+//! that stays connected throughout is returned exactly once. A machine that
+//! connects behind the cursor, or disconnects ahead of it, is missed; the
+//! client learns of it from the events after the first page's sequence
+//! number.
+//!
+//! `ListSessions` pages the same way through sessions ordered by machine ID,
+//! then session ID, with the last pair of the previous page as the cursor.
+
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+use kt_core::ipc::{MachineInfo, SessionInfo};
+
+/// Options of a `GetStateSnapshot` request
+#[derive(Debug, Clone, Copy)]
+pub struct SnapshotQuery<'a> {
+    /// Only the machine with this ID or alias (case-insensitive)
+    pub machine_filter: Option<&'a str>,
+    /// Include sessions
+    pub include_sessions: bool,
+    /// Last machine ID of the previous page
+    pub cursor: Option<&'a str>,
+    /// Most machines per page (None = all)
+    pub limit: Option<usize>,
+}
+
+impl Default for SnapshotQuery<'_> {
+    fn default() -> Self {
+        Self {
+            machine_filter: None,
+            include_sessions: true,
+            cursor: None,
+            limit: None,
+        }
+    }
+}
+
+/// One page of a state snapshot
+#[derive(Debug, Default)]
+pub struct SnapshotPage {
+    /// Machines of this page, by ID
+    pub machines: Vec<MachineInfo>,
+    /// Sessions of those machines, by machine ID
+    pub sessions: Vec<SessionInfo>,
+    /// Cursor for the next page, if there are more machines
+    pub next_cursor: Option<String>,
+}
+
+/// Select the page `query` asks for from all `machines` and `sessions`
+///
+/// A page holds at most `limit` machine IDs (at least one), counting
+/// machines only known from their sessions, together with all of their
+/// sessions.
+pub fn snapshot_page(
+    mut machines: Vec<MachineInfo>,
+    mut sessions: Vec<SessionInfo>,
+    query: &SnapshotQuery<'_>,
+) -> SnapshotPage {
+    if let Some(filter) = query.machine_filter {
+        machines.retain(|machine| matches_filter(machine, filter));
+        sessions.retain(|session| {
+            session.machine_id.eq_ignore_ascii_case(filter)
+                || machines
+                    .iter()
+                    .any(|machine| machine.id == session.machine_id)
+        });
+    }
+    if !query.include_sessions {
+        sessions.clear();
+    }
+
+    let ids: BTreeSet<String> = machines
+        .iter()
+        .map(|machine| machine.id.clone())
+        .chain(sessions.iter().map(|session| session.machine_id.clone()))
+        .collect();
+    let start = match query.cursor {
+        Some(cursor) => Bound::Excluded(cursor),
+        None => Bound::Unbounded,
+    };
+    let mut remaining = ids.range::<str, _>((start, Bound::Unbounded));
+    let limit = query.limit.unwrap_or(usize::MAX).max(1);
+    let page: Vec<&String> = remaining.by_ref().take(limit).collect();
+    let (Some(first), Some(last)) = (page.first(), page.last()) else {
+        return SnapshotPage::default();
+    };
+
+    // The page's IDs are contiguous in the sorted set
+    let in_page = |id: &String| id >= *first && id <= *last;
+    machines.retain(|machine| in_page(&machine.id));
+    machines.sort_by(|a, b| a.id.cmp(&b.id));
+    sessions.retain(|session| in_page(&session.machine_id));
+    sessions.sort_by(|a, b| {
+        a.machine_id
+            .cmp(&b.machine_id)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+
+    let next_cursor = remaining.next().map(|_| last.to_string());
+    SnapshotPage {
+        machines,
+        sessions,
+        next_cursor,
+    }
+}
+
+/// One page of `ListSessions`
+#[derive(Debug, Default)]
+pub struct SessionPage {
+    /// Sessions of this page, by machine ID, then session ID
+    pub sessions: Vec<SessionInfo>,
+    /// Sessions there are in all
+    pub total: usize,
+    /// Cursor for the next page, if there are more sessions
+    pub next_cursor: Option<String>,
+}
+
+/// Select a page of `sessions`: those after `cursor`, less the first
+/// `offset`, at most `limit` of them (at least one)
+///
+/// Without any of them, every session is returned, still in order.
+pub fn session_page(
+    mut sessions: Vec<SessionInfo>,
+    cursor: Option<&str>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+) -> SessionPage {
+    let total = sessions.len();
+    sessions.sort_by(|a, b| {
+        a.machine_id
+            .cmp(&b.machine_id)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    if let Some(cursor) = cursor {
+        let after = session_cursor_key(cursor);
+        sessions.retain(|session| (session.machine_id.as_str(), session.id.as_str()) > after);
+    }
+    sessions.drain(..offset.unwrap_or(0).min(sessions.len()));
+
+    let limit = limit.unwrap_or(usize::MAX).max(1);
+    let next_cursor = match sessions.get(limit) {
+        Some(_) => {
+            sessions.truncate(limit);
+            sessions.last().map(session_cursor)
+        }
+        None => None,
+    };
+    SessionPage {
+        sessions,
+        total,
+        next_cursor,
+    }
+}
+
+/// Cursor after `session`: its machine ID and session ID
+fn session_cursor(session: &SessionInfo) -> String {
+    format!("{}/{}", session.machine_id, session.id)
+}
+
+/// Sort key a cursor names (machine IDs have no `/`)
+fn session_cursor_key(cursor: &str) -> (&str, &str) {
+    cursor.split_once('/').unwrap_or((cursor, ""))
+}
+
+fn matches_filter(machine: &MachineInfo, filter: &str) -> bool {
+    machine.id.eq_ignore_ascii_case(filter)
+        || machine
+            .alias
+            .as_deref()
+            .is_some_and(|alias| alias.eq_ignore_ascii_case(filter))
+}
+
+#[cfg(test)]
+mod tests {
+    use kt_core::ipc::MachineStatus;
+
+    use super::*;
+
+    fn machine(id: &str) -> MachineInfo {
+        MachineInfo {
+            id: id.to_string(),
+            alias: Some(format!("{}-alias", id)),
+            label: None,
+            hostname: id.to_string(),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: MachineStatus::Connected,
+            connected_at: None,
+            last_heartbeat: None,
+            session_count: 1,
+            tags: vec![],
+            capabilities: vec![],
+            reconnect_count: 0,
+        }
+    }
+
+    fn session(machine_id: &str) -> SessionInfo {
+        SessionInfo {
+            id: format!("{}-session", machine_id),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: "2024-01-01T00:00:00Z".to_string(),
+            pid: None,
+            size: None,
+            name: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        }
+    }
+
+    /// Fleet of machines with one session each
+    fn fleet(ids: &[&str]) -> (Vec<MachineInfo>, Vec<SessionInfo>) {
+        (
+            ids.iter().map(|id| machine(id)).collect(),
+            ids.iter().map(|id| session(id)).collect(),
+        )
+    }
+
+    fn machine_ids(page: &SnapshotPage) -> Vec<&str> {
+        page.machines.iter().map(|m| m.id.as_str()).collect()
+    }
+
+    fn paged(cursor: Option<&str>, limit: usize) -> SnapshotQuery<'_> {
+        SnapshotQuery {
+            cursor,
+            limit: Some(limit),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_pages_are_ordered_by_machine_id() {
+        let (machines, sessions) = fleet(&["c", "a", "e", "b", "d"]);
+
+        let first = snapshot_page(machines.clone(), sessions.clone(), &paged(None, 2));
+        assert_eq!(machine_ids(&first), ["a", "b"]);
+        assert_eq!(first.sessions.len(), 2);
+        assert_eq!(first.next_cursor.as_deref(), Some("b"));
+
+        let cursor = first.next_cursor.as_deref();
+        let second = snapshot_page(machines.clone(), sessions.clone(), &paged(cursor, 2));
+        assert_eq!(machine_ids(&second), ["c", "d"]);
+
+        let cursor = second.next_cursor.as_deref();
+        let last = snapshot_page(machines, sessions, &paged(cursor, 2));
+        assert_eq!(machine_ids(&last), ["e"]);
+        assert_eq!(last.sessions[0].machine_id, "e");
+        assert_eq!(last.next_cursor, None);
+    }
+
+    #[test]
+    fn test_paging_under_concurrent_changes() {
+        let (machines, sessions) = fleet(&["a", "b", "c", "d", "e"]);
+        let first = snapshot_page(machines, sessions, &paged(None, 2));
+        assert_eq!(machine_ids(&first), ["a", "b"]);
+
+        // Between pages "aa" connects behind the cursor, "c" disconnects
+        // and "f" connects ahead of it
+        let (machines, sessions) = fleet(&["a", "aa", "b", "d", "e", "f"]);
+        let mut seen = machine_ids(&first)
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let mut cursor = first.next_cursor.clone();
+        while let Some(current) = cursor {
+            let query = paged(Some(&current), 2);
+            let page = snapshot_page(machines.clone(), sessions.clone(), &query);
+            seen.extend(machine_ids(&page).into_iter().map(String::from));
+            cursor = page.next_cursor;
+        }
+
+        // Machines there throughout come exactly once, in order
+        assert_eq!(seen, ["a", "b", "d", "e", "f"]);
+    }
+
+    #[test]
+    fn test_filter_and_sessions_left_out() {
+        let (machines, mut sessions) = fleet(&["a", "b"]);
+        // A session whose machine is reconnecting and not listed
+        sessions.push(session("z"));
+
+        let query = SnapshotQuery {
+            machine_filter: Some("B-ALIAS"),
+            ..Default::default()
+        };
+        let page = snapshot_page(machines.clone(), sessions.clone(), &query);
+        assert_eq!(machine_ids(&page), ["b"]);
+        assert_eq!(page.sessions.len(), 1);
+        assert_eq!(page.sessions[0].machine_id, "b");
+
+        let query = SnapshotQuery {
+            machine_filter: Some("z"),
+            ..Default::default()
+        };
+        let page = snapshot_page(machines.clone(), sessions.clone(), &query);
+        assert!(page.machines.is_empty());
+        assert_eq!(page.sessions.len(), 1);
+
+        let query = SnapshotQuery {
+            include_sessions: false,
+            ..Default::default()
+        };
+        let page = snapshot_page(machines, sessions, &query);
+        assert_eq!(machine_ids(&page), ["a", "b"]);
+        assert!(page.sessions.is_empty());
+        assert_eq!(page.next_cursor, None);
+    }
+
+    fn session_ids(page: &SessionPage) -> Vec<&str> {
+        page.sessions.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_session_pages_stay_consistent() {
+        let on = |machine_id: &str, id: &str| SessionInfo {
+            id: id.to_string(),
+            ..session(machine_id)
+        };
+        let mut sessions = vec![on("b", "s1"), on("a", "s2"), on("b", "s0"), on("a", "s1")];
+
+        // Unpaged: everything, in order
+        let all = session_page(sessions.clone(), None, None, None);
+        assert_eq!(session_ids(&all), ["s1", "s2", "s0", "s1"]);
+        assert_eq!(all.total, 4);
+        assert_eq!(all.next_cursor, None);
+
+        let first = session_page(sessions.clone(), None, None, Some(2));
+        assert_eq!(session_ids(&first), ["s1", "s2"]);
+        assert_eq!(first.next_cursor.as_deref(), Some("a/s2"));
+
+        // Sessions opening behind the cursor and closing ahead of it don't
+        // shift the next page
+        sessions.retain(|s| s.id != "s2");
+        sessions.push(on("a", "s0"));
+        let cursor = first.next_cursor.as_deref();
+        let second = session_page(sessions.clone(), cursor, None, Some(2));
+        assert_eq!(session_ids(&second), ["s0", "s1"]);
+        assert_eq!(second.sessions[0].machine_id, "b");
+        assert_eq!(second.total, 4);
+        assert_eq!(second.next_cursor, None);
+
+        // Offsets count from the cursor
+        let skipped = session_page(sessions, Some("a/s1"), Some(1), Some(5));
+        assert_eq!(session_ids(&skipped), ["s1"]);
+        assert_eq!(skipped.next_cursor, None);
+    }
+}
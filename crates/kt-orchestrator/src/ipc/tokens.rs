@@ -0,0 +1,159 @@
+//! IPC token validation and rotation
+//!
+//! The orchestrator keeps the token it owns in memory and checks every
+//! `Authenticate` request against it. When the token is rotated, either on
+//! request or because it expired, the new token is written to the token file
+//! and the previous one is still accepted for [`TOKEN_ROTATION_OVERLAP`], so
+//! clients that read the file just before the rotation can still get in.
+//! Connections that are already authenticated are unaffected.
+
+use std::io;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+use kt_core::{Secret, TokenInfo};
+
+/// How long the previous token is still accepted after a rotation
+pub const TOKEN_ROTATION_OVERLAP: Duration = Duration::from_secs(60);
+
+/// Tokens accepted by the IPC server
+#[derive(Debug)]
+pub struct IpcTokens {
+    inner: RwLock<TokensInner>,
+    /// Lifetime given to rotated tokens (None = never expire)
+    lifetime: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct TokensInner {
+    current: TokenInfo,
+    /// Previous token and when it stops being accepted
+    previous: Option<(Secret<String>, Instant)>,
+}
+
+impl IpcTokens {
+    /// Accept `current`, rotating to tokens valid for `lifetime`
+    pub fn new(current: TokenInfo, lifetime: Option<Duration>) -> Self {
+        Self {
+            inner: RwLock::new(TokensInner {
+                current,
+                previous: None,
+            }),
+            lifetime,
+        }
+    }
+
+    /// The current token
+    pub fn current(&self) -> TokenInfo {
+        self.inner
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .current
+            .clone()
+    }
+
+    /// Check a token presented by a client.
+    ///
+    /// Returns the generation of the token it matched: the current one, or
+    /// the previous one during the overlap window.
+    pub fn validate(&self, provided: &str) -> Option<u64> {
+        let inner = self.inner.read().unwrap_or_else(|e| e.into_inner());
+        // Compare against both so the time taken doesn't reveal which matched
+        let current = kt_core::validate_ipc_token(provided, inner.current.token.expose());
+        let previous = inner.previous.as_ref().is_some_and(|(token, until)| {
+            kt_core::validate_ipc_token(provided, token.expose()) && Instant::now() < *until
+        });
+
+        if current {
+            Some(inner.current.generation)
+        } else if previous {
+            Some(inner.current.generation.saturating_sub(1))
+        } else {
+            None
+        }
+    }
+
+    /// Generate a new token, write it to the token file and start accepting it
+    pub fn rotate(&self) -> io::Result<TokenInfo> {
+        let info = kt_core::rotate_ipc_token(self.lifetime)?;
+        self.install(info.clone(), Instant::now());
+        Ok(info)
+    }
+
+    /// Make `info` the current token, accepting the old one until
+    /// `now + TOKEN_ROTATION_OVERLAP`
+    fn install(&self, info: TokenInfo, now: Instant) {
+        let mut inner = self.inner.write().unwrap_or_else(|e| e.into_inner());
+        let old = std::mem::replace(&mut inner.current, info);
+        inner.previous = Some((old.token, now + TOKEN_ROTATION_OVERLAP));
+    }
+
+    /// Rotate the token each time it expires. Never returns.
+    pub async fn rotate_on_expiry(&self) {
+        loop {
+            let Some(expires_in) = self.current().expires_in() else {
+                return std::future::pending().await;
+            };
+            tokio::time::sleep(expires_in).await;
+
+            if let Err(e) = self.rotate() {
+                // Keep the expired token rather than locking everyone out
+                tracing::error!("Failed to rotate expired IPC token: {}", e);
+                tokio::time::sleep(self.lifetime.unwrap_or(TOKEN_ROTATION_OVERLAP)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(token: &str, generation: u64) -> TokenInfo {
+        TokenInfo {
+            token: Secret::new(token.to_string()),
+            pid: std::process::id(),
+            start_time: None,
+            address: "127.0.0.1:22230".to_string(),
+            expires_at: None,
+            generation,
+            owner: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_previous_token_accepted_during_overlap() {
+        let tokens = IpcTokens::new(token("first", 1), None);
+        assert_eq!(tokens.validate("first"), Some(1));
+        assert_eq!(tokens.validate("second"), None);
+
+        let now = Instant::now();
+        tokens.install(token("second", 2), now);
+        assert_eq!(tokens.current().token.expose(), "second");
+        assert_eq!(tokens.validate("second"), Some(2));
+        assert_eq!(tokens.validate("first"), Some(1));
+        assert_eq!(tokens.validate("other"), None);
+    }
+
+    #[test]
+    fn test_previous_token_rejected_after_overlap() {
+        let tokens = IpcTokens::new(token("first", 1), None);
+        let past = Instant::now()
+            .checked_sub(TOKEN_ROTATION_OVERLAP + Duration::from_secs(1))
+            .unwrap();
+        tokens.install(token("second", 2), past);
+        assert_eq!(tokens.validate("first"), None);
+        assert_eq!(tokens.validate("second"), Some(2));
+    }
+
+    #[test]
+    fn test_only_one_previous_token_kept() {
+        let tokens = IpcTokens::new(token("first", 1), None);
+        let now = Instant::now();
+        tokens.install(token("second", 2), now);
+        tokens.install(token("third", 3), now);
+        assert_eq!(tokens.validate("first"), None);
+        assert_eq!(tokens.validate("second"), Some(2));
+        assert_eq!(tokens.validate("third"), Some(3));
+    }
+}
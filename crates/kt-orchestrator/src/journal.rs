@@ -0,0 +1,276 @@
+//! Event journal for post-mortem debugging
+//!
+//! With `event_journal_path` set, every event broadcast to IPC clients is
+//! appended to that file as one JSON object per line: the
+//! [`IpcEventEnvelope`] as sent, plus `outputLen` for terminal output. The
+//! output bytes themselves are left out unless
+//! `event_journal_include_output` is set, since they can hold passwords and
+//! anything else typed or printed in a session. The file is rotated by size
+//! like the log file ([`RotatingFile`]).
+//!
+//! The journal listens on the event broadcast like IPC clients do, from a
+//! thread of its own, so a slow or failing disk never holds up events: the
+//! journal falls behind and skips what it missed, and write errors are
+//! logged, not returned. `k-terminus journal replay` reads it back.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::thread::JoinHandle;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use kt_core::config::OrchestratorConfig;
+use kt_core::ipc::{IpcEvent, IpcEventEnvelope};
+use kt_core::time::format_iso8601;
+
+use crate::logging::RotatingFile;
+
+/// Where and what the event journal records
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalSettings {
+    /// Journal file path
+    pub path: PathBuf,
+    /// Size in bytes at which the file is rotated (0 = never)
+    pub max_size: u64,
+    /// Rotated files to keep
+    pub keep: u32,
+    /// Record terminal output bytes, not just their length
+    pub include_output: bool,
+}
+
+impl JournalSettings {
+    /// Journal settings of `config` (None = no journal)
+    pub fn from_config(config: &OrchestratorConfig) -> Option<Self> {
+        Some(Self {
+            path: config.event_journal_path.clone()?,
+            max_size: config.event_journal_rotation.max_bytes(),
+            keep: config.event_journal_rotation.keep,
+            include_output: config.event_journal_include_output,
+        })
+    }
+}
+
+/// One line of the event journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JournalEntry {
+    /// The event as broadcast, its terminal output emptied unless the
+    /// journal includes output
+    #[serde(flatten)]
+    pub envelope: IpcEventEnvelope,
+    /// Bytes of terminal output the event carried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_len: Option<usize>,
+}
+
+impl JournalEntry {
+    /// Entry for `envelope`, dropping its terminal output unless
+    /// `include_output`
+    pub fn new(mut envelope: IpcEventEnvelope, include_output: bool) -> Self {
+        let output_len = match &mut envelope.event {
+            IpcEvent::TerminalOutput { data, .. } => {
+                let len = data.len();
+                if !include_output {
+                    *data = Vec::new();
+                }
+                Some(len)
+            }
+            _ => None,
+        };
+        Self {
+            envelope,
+            output_len,
+        }
+    }
+}
+
+/// Parse a journal line (None if it isn't one, e.g. cut short by a crash)
+pub fn parse_journal_line(line: &str) -> Option<JournalEntry> {
+    serde_json::from_str(line).ok()
+}
+
+/// Format a journal entry for reading
+///
+/// `<timestamp> #<seq> <event type> key=value...`, values as JSON. Terminal
+/// output shows its length, and its text if the journal included it.
+pub fn format_journal_entry(entry: &JournalEntry) -> String {
+    let envelope = &entry.envelope;
+    // Events come many to a second, so keep the milliseconds
+    let time = format_iso8601(UNIX_EPOCH + Duration::from_secs(envelope.timestamp / 1000));
+    let mut line = format!(
+        "{}.{:03}Z #{}",
+        time.trim_end_matches('Z'),
+        envelope.timestamp % 1000,
+        envelope.seq
+    );
+
+    let mut fields = match serde_json::to_value(&envelope.event) {
+        Ok(serde_json::Value::Object(fields)) => fields,
+        _ => return format!("{} {:?}", line, envelope.event),
+    };
+    if let Some(serde_json::Value::String(kind)) = fields.remove("type") {
+        let _ = write!(line, " {}", kind);
+    }
+    let output = match &envelope.event {
+        IpcEvent::TerminalOutput { data, .. } => {
+            fields.remove("data");
+            Some(data)
+        }
+        _ => None,
+    };
+    for (key, value) in &fields {
+        let _ = write!(line, " {}={}", key, value);
+    }
+    if let Some(data) = output {
+        let len = entry.output_len.unwrap_or(data.len());
+        let _ = write!(line, " bytes={}", len);
+        if !data.is_empty() {
+            let _ = write!(line, " data={:?}", String::from_utf8_lossy(data));
+        }
+    }
+    if let Some(session_seq) = envelope.session_seq {
+        let _ = write!(line, " session_seq={}", session_seq);
+    }
+    line
+}
+
+/// Append events from `event_rx` to the journal of `config`, if it has
+/// one, until the channel closes or `cancel` fires
+///
+/// Returns `None` when there's no journal, or it can't be opened.
+pub fn spawn_journal(
+    config: &OrchestratorConfig,
+    event_rx: broadcast::Receiver<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) -> Option<JoinHandle<()>> {
+    let settings = JournalSettings::from_config(config)?;
+    let file = match RotatingFile::open(&settings.path, settings.max_size, settings.keep) {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!(
+                "Event journal disabled: failed to open {:?}: {}",
+                settings.path,
+                e
+            );
+            return None;
+        }
+    };
+
+    tracing::info!("Journaling events to {:?}", settings.path);
+    let spawned = std::thread::Builder::new()
+        .name("event-journal".to_string())
+        .spawn(move || run_journal(file, settings, event_rx, cancel));
+    match spawned {
+        Ok(handle) => Some(handle),
+        Err(e) => {
+            tracing::error!("Event journal disabled: failed to start: {}", e);
+            None
+        }
+    }
+}
+
+fn run_journal(
+    mut file: RotatingFile,
+    settings: JournalSettings,
+    mut event_rx: broadcast::Receiver<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
+    let mut failing = false;
+    while !cancel.is_cancelled() {
+        let envelope = match event_rx.blocking_recv() {
+            Ok(envelope) => envelope,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                tracing::warn!("Event journal fell behind, {} events not recorded", n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let entry = JournalEntry::new(envelope, settings.include_output);
+        let mut line = match serde_json::to_vec(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("Failed to serialize event for the journal: {}", e);
+                continue;
+            }
+        };
+        line.push(b'\n');
+
+        // Logged once per run of failures, so a full disk doesn't flood the log
+        match file.write_line(&line) {
+            Ok(()) if failing => {
+                tracing::info!("Event journal {:?} writable again", settings.path);
+                failing = false;
+            }
+            Ok(()) => {}
+            Err(e) if !failing => {
+                tracing::warn!("Failed to write event journal {:?}: {}", settings.path, e);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output(seq: u64) -> IpcEventEnvelope {
+        IpcEventEnvelope {
+            seq,
+            timestamp: 1_700_000_000_042,
+            event: IpcEvent::TerminalOutput {
+                session_id: "7".to_string(),
+                data: b"hunter2\n".to_vec(),
+                stream: kt_core::ipc::OutputStream::Stdout,
+            },
+            session_seq: Some(3),
+        }
+    }
+
+    #[test]
+    fn test_entry_leaves_out_output_by_default() {
+        let line = serde_json::to_string(&JournalEntry::new(output(1), false)).unwrap();
+        assert!(line.contains("\"data\":[]"), "{}", line);
+        let entry = parse_journal_line(&line).unwrap();
+        assert_eq!(entry.output_len, Some(8));
+        assert_eq!(
+            format_journal_entry(&entry),
+            "2023-11-14T22:13:20.042Z #1 terminal_output session_id=\"7\" bytes=8 session_seq=3"
+        );
+
+        let included = JournalEntry::new(output(2), true);
+        assert!(
+            format_journal_entry(&included).ends_with("bytes=8 data=\"hunter2\\n\" session_seq=3")
+        );
+        assert!(parse_journal_line("{\"seq\":3,\"timest").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_journal_records_every_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let config = OrchestratorConfig {
+            event_journal_path: Some(path.clone()),
+            ..Default::default()
+        };
+        let (event_tx, event_rx) = broadcast::channel(16);
+        let handle = spawn_journal(&config, event_rx, CancellationToken::new()).unwrap();
+
+        for seq in 1..=3 {
+            event_tx.send(output(seq)).unwrap();
+        }
+        drop(event_tx);
+        handle.join().unwrap();
+
+        let journal = std::fs::read_to_string(&path).unwrap();
+        let seqs: Vec<u64> = journal
+            .lines()
+            .map(|line| parse_journal_line(line).unwrap().envelope.seq)
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3]);
+    }
+}
@@ -8,10 +8,18 @@
 pub mod auth;
 pub mod connection;
 pub mod coordinator;
+pub mod groups;
 pub mod ipc;
+pub mod journal;
+pub mod logging;
+pub mod memory;
+pub mod readiness;
+pub mod rejections;
+pub mod reload;
 pub mod server;
 pub mod session;
 pub mod state;
+pub mod webhook;
 
 pub use coordinator::StateCoordinator;
 pub use state::OrchestratorState;
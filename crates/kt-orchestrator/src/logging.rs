@@ -0,0 +1,712 @@
+//! Orchestrator log capture
+//!
+//! Two tracing layers turn log events into [`LogLine`]s:
+//!
+//! - [`LogFileLayer`] appends them to the orchestrator's log file, which
+//!   backs the history returned by `IpcRequest::TailLogs`. The file is
+//!   rotated by size ([`RotatingFile`]) and written as text or JSON lines
+//!   (see [`LogFileSettings`]); both keep the fields of the enclosing spans,
+//!   such as a connection's `machine_id`.
+//! - [`LogBroadcastLayer`] publishes them to live followers (`TailLogs` with
+//!   `follow`, or the desktop app's log panel for an embedded orchestrator).
+//!
+//! Followers receive lines through a [`LogBatcher`], which groups everything
+//! logged within [`LOG_BATCH_INTERVAL`] into one message so a debug-level
+//! flood turns into a handful of writes instead of thousands.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write as _};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use kt_core::config::{self, ConfigFile, ConfigLoader, LogFormat, OrchestratorConfig};
+use kt_core::ipc::LogLine;
+use kt_core::time::{current_time_millis, format_iso8601};
+
+/// File name of the orchestrator log inside the config directory
+pub const LOG_FILE_NAME: &str = "orchestrator.log";
+
+/// How many lines live followers can fall behind before lines are dropped
+pub const LOG_CHANNEL_CAPACITY: usize = 1024;
+
+/// How long lines are collected before a batch is sent to followers
+pub const LOG_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Most lines sent in one batch; older lines beyond this are dropped
+pub const MAX_LOG_BATCH_LINES: usize = 500;
+
+/// Default log file path (`~/.config/k-terminus/orchestrator.log`)
+pub fn default_log_path() -> PathBuf {
+    kt_core::config::default_config_dir().join(LOG_FILE_NAME)
+}
+
+/// Where the IPC server reads logs from.
+///
+/// Cheap to clone; all clones share the same broadcast channel.
+#[derive(Clone)]
+pub struct LogSource {
+    /// Log file for history (None = live lines only)
+    path: Option<PathBuf>,
+    /// Live log lines
+    tx: broadcast::Sender<LogLine>,
+}
+
+impl LogSource {
+    /// Create a log source, optionally backed by a log file for history
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let (tx, _) = broadcast::channel(LOG_CHANNEL_CAPACITY);
+        Self { path, tx }
+    }
+
+    /// Layer that publishes every log event to this source's followers
+    pub fn broadcast_layer(&self) -> LogBroadcastLayer {
+        LogBroadcastLayer {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Follow live log lines
+    pub fn subscribe(&self) -> broadcast::Receiver<LogLine> {
+        self.tx.subscribe()
+    }
+
+    /// Read up to `lines` of the most recent lines from the log file.
+    ///
+    /// Returns nothing if this source has no log file.
+    pub fn tail(&self, lines: usize) -> io::Result<Vec<LogLine>> {
+        match &self.path {
+            Some(path) => tail_log_file(path, lines),
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Tracing layer that publishes log events to a broadcast channel.
+///
+/// Events are only formatted while someone is following.
+pub struct LogBroadcastLayer {
+    tx: broadcast::Sender<LogLine>,
+}
+
+impl<S: Subscriber> Layer<S> for LogBroadcastLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        let _ = self.tx.send(log_line_from_event(event));
+    }
+}
+
+/// Where and how the orchestrator writes its log file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogFileSettings {
+    /// Log file path
+    pub path: PathBuf,
+    /// Format of each line
+    pub format: LogFormat,
+    /// Size in bytes at which the file is rotated (0 = never)
+    pub max_size: u64,
+    /// Rotated files to keep
+    pub keep: u32,
+}
+
+impl LogFileSettings {
+    /// Settings from the orchestrator config; `default_path` is used unless
+    /// the config names a log file
+    pub fn from_config(config: &OrchestratorConfig, default_path: PathBuf) -> Self {
+        Self {
+            path: config.log_file.clone().unwrap_or(default_path),
+            format: config.log_format,
+            max_size: config.log_rotation.max_bytes(),
+            keep: config.log_rotation.keep,
+        }
+    }
+
+    /// Read the settings from the config file (`config_path`, or the
+    /// default) and environment.
+    ///
+    /// This runs before tracing is set up, so a config that fails to load
+    /// gives the defaults here; the orchestrator reports the problem when it
+    /// loads the config itself.
+    pub fn load(config_path: Option<&Path>, default_path: PathBuf) -> Self {
+        let path = config_path
+            .map(Path::to_path_buf)
+            .unwrap_or_else(config::default_config_path);
+        let mut loader = ConfigLoader::<ConfigFile>::new();
+        if path.exists() {
+            let _ = loader.with_file(&path);
+        }
+        let config = loader
+            .with_env()
+            .load()
+            .map(|file| file.orchestrator)
+            .unwrap_or_default();
+        Self::from_config(&config, default_path)
+    }
+}
+
+/// A log file that is rotated once it reaches a size limit.
+///
+/// Rotating moves the file to `<name>.1`, shifting older rotations up to
+/// `<name>.<keep>`; anything older is deleted.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+    max_size: u64,
+    keep: u32,
+}
+
+impl RotatingFile {
+    /// Open (or create) the file for appending.
+    ///
+    /// A file already at the size limit is rotated first.
+    pub fn open(path: &Path, max_size: u64, keep: u32) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut rotating = Self {
+            path: path.to_path_buf(),
+            size: file.metadata()?.len(),
+            file: Some(file),
+            max_size,
+            keep,
+        };
+        if max_size > 0 && rotating.size >= max_size {
+            rotating.rotate()?;
+        }
+        Ok(rotating)
+    }
+
+    /// Append a line, first rotating if it would take the file past the limit
+    pub fn write_line(&mut self, line: &[u8]) -> io::Result<()> {
+        let len = line.len() as u64;
+        if self.max_size > 0 && self.size > 0 && self.size + len > self.max_size {
+            self.rotate()?;
+        }
+        let file = match &mut self.file {
+            Some(file) => file,
+            // A failed rotation left no file open; try again
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            ),
+        };
+        file.write_all(line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        // Closed first: Windows can't rename an open file
+        self.file = None;
+
+        // Rotations beyond `keep`, e.g. left over from a larger setting
+        let mut n = self.keep + 1;
+        while self.rotated_path(n).exists() {
+            std::fs::remove_file(self.rotated_path(n))?;
+            n += 1;
+        }
+        for n in (1..self.keep).rev() {
+            match std::fs::rename(self.rotated_path(n), self.rotated_path(n + 1)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        if self.keep > 0 {
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+        } else {
+            std::fs::remove_file(&self.path)?;
+        }
+
+        self.file = Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?,
+        );
+        self.size = 0;
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let mut rotated = self.path.as_os_str().to_owned();
+        rotated.push(format!(".{}", n));
+        PathBuf::from(rotated)
+    }
+}
+
+/// Tracing layer that appends log events to a file, one line per event
+pub struct LogFileLayer {
+    file: Mutex<RotatingFile>,
+    format: LogFormat,
+}
+
+impl LogFileLayer {
+    /// Open (or create) the log file for appending
+    pub fn open(settings: &LogFileSettings) -> io::Result<Self> {
+        let file = RotatingFile::open(&settings.path, settings.max_size, settings.keep)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            format: settings.format,
+        })
+    }
+}
+
+impl<S> Layer<S> for LogFileLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut fields = EventFields::default();
+        attrs.record(&mut fields);
+        span.extensions_mut().insert(SpanFields(fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(SpanFields(fields)) = extensions.get_mut::<SpanFields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Span fields first, outermost span first, so the event's own
+        // fields win on a clash
+        let mut fields = EventFields::default();
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                    fields.merge(span_fields);
+                }
+            }
+        }
+        event.record(&mut fields);
+
+        let mut line = match self.format {
+            LogFormat::Text => format_log_line(&fields.into_log_line(event.metadata())),
+            LogFormat::Json => format_json_log_line(fields, event.metadata()),
+        };
+        line.push('\n');
+        if let Ok(mut file) = self.file.lock() {
+            // Nowhere to report a failed write without logging recursively
+            let _ = file.write_line(line.as_bytes());
+        }
+    }
+}
+
+/// Fields recorded on a span, kept in its extensions
+struct SpanFields(EventFields);
+
+/// Format a log line for the log file: `<millis> <LEVEL> <target>: <message>`.
+///
+/// Newlines in the message are escaped so every record stays on one line.
+pub fn format_log_line(line: &LogLine) -> String {
+    format!(
+        "{} {} {}: {}",
+        line.timestamp_ms,
+        line.level,
+        line.target,
+        line.message.replace('\n', "\\n")
+    )
+}
+
+/// A log file line in JSON format
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonLogLine {
+    /// ISO 8601, for log shippers
+    timestamp: String,
+    timestamp_ms: u64,
+    level: String,
+    target: String,
+    message: String,
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Format an event as a JSON log line, with its structured fields (and
+/// those of its spans) under `fields`.
+fn format_json_log_line(fields: EventFields, metadata: &Metadata<'_>) -> String {
+    let timestamp_ms = current_time_millis();
+    let line = JsonLogLine {
+        timestamp: format_iso8601(UNIX_EPOCH + Duration::from_millis(timestamp_ms)),
+        timestamp_ms,
+        level: metadata.level().to_string(),
+        target: metadata.target().to_string(),
+        message: fields.message,
+        fields: fields
+            .fields
+            .into_iter()
+            .map(|field| (field.name, field.json))
+            .collect(),
+    };
+    serde_json::to_string(&line).unwrap_or_default()
+}
+
+/// Parse a line written by [`format_log_line`] or in JSON format.
+///
+/// JSON fields are appended to the message as `key=value`, as in text lines.
+/// Returns `None` for lines in any other format.
+pub fn parse_log_line(line: &str) -> Option<LogLine> {
+    if line.starts_with('{') {
+        let json: JsonLogLine = serde_json::from_str(line).ok()?;
+        let mut message = json.message;
+        for (name, value) in json.fields {
+            let value = match value {
+                serde_json::Value::String(value) => value,
+                value => value.to_string(),
+            };
+            let _ = write!(message, " {}={}", name, value);
+        }
+        return Some(LogLine {
+            timestamp_ms: json.timestamp_ms,
+            level: json.level,
+            target: json.target,
+            message,
+        });
+    }
+
+    let (timestamp, rest) = line.split_once(' ')?;
+    let (level, rest) = rest.split_once(' ')?;
+    let (target, message) = rest.split_once(": ")?;
+    Some(LogLine {
+        timestamp_ms: timestamp.parse().ok()?,
+        level: level.to_string(),
+        target: target.to_string(),
+        message: message.replace("\\n", "\n"),
+    })
+}
+
+/// Read the last `lines` records from a log file.
+///
+/// A missing file counts as empty. Lines that don't parse are skipped.
+pub fn tail_log_file(path: &Path, lines: usize) -> io::Result<Vec<LogLine>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut tail: Vec<LogLine> = contents
+        .lines()
+        .rev()
+        .filter_map(parse_log_line)
+        .take(lines)
+        .collect();
+    tail.reverse();
+    Ok(tail)
+}
+
+/// Build a log line from a tracing event
+fn log_line_from_event(event: &Event<'_>) -> LogLine {
+    let mut fields = EventFields::default();
+    event.record(&mut fields);
+    fields.into_log_line(event.metadata())
+}
+
+/// An event's (or span's) message and structured fields, in order
+#[derive(Default)]
+struct EventFields {
+    message: String,
+    fields: Vec<EventField>,
+}
+
+/// A structured field, formatted for text and for JSON
+struct EventField {
+    name: String,
+    /// `{:?}` of the value, as text lines show it
+    text: String,
+    json: serde_json::Value,
+}
+
+impl EventFields {
+    /// Set a field, replacing an earlier value of the same name
+    fn set(&mut self, name: &str, text: String, json: serde_json::Value) {
+        match self.fields.iter_mut().find(|field| field.name == name) {
+            Some(field) => {
+                field.text = text;
+                field.json = json;
+            }
+            None => self.fields.push(EventField {
+                name: name.to_string(),
+                text,
+                json,
+            }),
+        }
+    }
+
+    /// Add the fields of an enclosing span
+    fn merge(&mut self, other: &EventFields) {
+        for field in &other.fields {
+            self.set(&field.name, field.text.clone(), field.json.clone());
+        }
+    }
+
+    /// A log line with the fields appended to the message as `key=value`
+    fn into_log_line(self, metadata: &Metadata<'_>) -> LogLine {
+        let mut message = self.message;
+        for field in self.fields {
+            if !message.is_empty() {
+                message.push(' ');
+            }
+            let _ = write!(message, "{}={}", field.name, field.text);
+        }
+        LogLine {
+            timestamp_ms: current_time_millis(),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message,
+        }
+    }
+}
+
+impl Visit for EventFields {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        let text = format!("{:?}", value);
+        if field.name() == "message" {
+            self.message = text;
+        } else {
+            self.set(field.name(), text.clone(), serde_json::Value::String(text));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.set(field.name(), format!("{:?}", value), value.into());
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.set(field.name(), value.to_string(), value.into());
+    }
+}
+
+/// Groups log lines into rate-limited batches.
+///
+/// The first line pushed into an empty batch starts a [`LOG_BATCH_INTERVAL`]
+/// timer; the batch is due when it expires. At most [`MAX_LOG_BATCH_LINES`]
+/// are kept per batch, and a note about dropped lines is prepended when lines
+/// were discarded (here or because the follower lagged).
+#[derive(Debug, Default)]
+pub struct LogBatcher {
+    pending: VecDeque<LogLine>,
+    dropped: u64,
+    deadline: Option<Instant>,
+}
+
+impl LogBatcher {
+    /// Create an empty batcher
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a line to the current batch
+    pub fn push(&mut self, line: LogLine) {
+        self.start_timer();
+        if self.pending.len() >= MAX_LOG_BATCH_LINES {
+            self.pending.pop_front();
+            self.dropped += 1;
+        }
+        self.pending.push_back(line);
+    }
+
+    /// Record lines that were lost before reaching the batcher
+    pub fn record_dropped(&mut self, count: u64) {
+        self.start_timer();
+        self.dropped += count;
+    }
+
+    /// When the current batch is due, if anything is pending
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Take the current batch and reset the timer
+    pub fn take(&mut self) -> Vec<LogLine> {
+        self.deadline = None;
+        let mut lines = std::mem::take(&mut self.pending);
+        if self.dropped > 0 {
+            lines.push_front(LogLine {
+                timestamp_ms: current_time_millis(),
+                level: "WARN".to_string(),
+                target: module_path!().to_string(),
+                message: format!("{} log lines dropped", self.dropped),
+            });
+            self.dropped = 0;
+        }
+        lines.into()
+    }
+
+    fn start_timer(&mut self) {
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + LOG_BATCH_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(message: &str) -> LogLine {
+        LogLine {
+            timestamp_ms: 1_700_000_000_000,
+            level: "INFO".to_string(),
+            target: "kt_orchestrator::ipc".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_log_line_format_roundtrip() {
+        let original = line("client connected\nsecond line: with colon");
+        let formatted = format_log_line(&original);
+        assert!(!formatted.contains('\n'));
+        assert_eq!(parse_log_line(&formatted), Some(original));
+
+        assert_eq!(parse_log_line("not a log line"), None);
+    }
+
+    #[test]
+    fn test_tail_log_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOG_FILE_NAME);
+
+        // Missing file is empty
+        assert!(tail_log_file(&path, 10).unwrap().is_empty());
+
+        let contents: String = (0..5)
+            .map(|i| format_log_line(&line(&format!("line {}", i))) + "\n")
+            .collect();
+        std::fs::write(&path, contents + "garbage\n").unwrap();
+
+        let tail = tail_log_file(&path, 2).unwrap();
+        let messages: Vec<_> = tail.iter().map(|l| l.message.as_str()).collect();
+        assert_eq!(messages, vec!["line 3", "line 4"]);
+    }
+
+    #[test]
+    fn test_rotating_file_rotates_and_prunes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOG_FILE_NAME);
+        let rotated = |n: u32| dir.path().join(format!("{}.{}", LOG_FILE_NAME, n));
+        // Left over from a larger `keep`
+        std::fs::write(rotated(3), "ancient\n").unwrap();
+
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        file.write_line(b"first\n").unwrap();
+        file.write_line(b"second\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second\n");
+        assert_eq!(std::fs::read_to_string(rotated(1)).unwrap(), "first\n");
+        assert!(!rotated(3).exists());
+
+        file.write_line(b"third\n").unwrap();
+        file.write_line(b"fourth\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(std::fs::read_to_string(rotated(1)).unwrap(), "third\n");
+        assert_eq!(std::fs::read_to_string(rotated(2)).unwrap(), "second\n");
+        assert!(!rotated(3).exists());
+
+        // Reopening a full file starts a new one
+        drop(file);
+        std::fs::write(&path, "0123456789\n").unwrap();
+        let _file = RotatingFile::open(&path, 10, 2).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        assert_eq!(std::fs::read_to_string(rotated(1)).unwrap(), "0123456789\n");
+    }
+
+    #[test]
+    fn test_log_file_layer_formats_with_span_fields() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for format in [LogFormat::Text, LogFormat::Json] {
+            let settings = LogFileSettings {
+                path: dir.path().join(format!("{}.log", format)),
+                format,
+                max_size: 0,
+                keep: 0,
+            };
+            let subscriber =
+                tracing_subscriber::registry().with(LogFileLayer::open(&settings).unwrap());
+            tracing::subscriber::with_default(subscriber, || {
+                let span = tracing::info_span!("connection", machine_id = tracing::field::Empty);
+                span.record("machine_id", "gpu-box");
+                let _entered = span.enter();
+                tracing::info!(session_id = %"session-7", pid = 42, "Session ready");
+            });
+
+            let contents = std::fs::read_to_string(&settings.path).unwrap();
+            let tail = tail_log_file(&settings.path, 10).unwrap();
+            assert_eq!(tail.len(), 1, "{}", contents);
+            match format {
+                LogFormat::Text => assert_eq!(
+                    tail[0].message,
+                    "Session ready machine_id=\"gpu-box\" session_id=session-7 pid=42"
+                ),
+                LogFormat::Json => {
+                    let json: serde_json::Value = serde_json::from_str(contents.trim()).unwrap();
+                    assert_eq!(json["message"], "Session ready");
+                    assert_eq!(json["level"], "INFO");
+                    assert_eq!(json["fields"]["machine_id"], "gpu-box");
+                    assert_eq!(json["fields"]["session_id"], "session-7");
+                    assert_eq!(json["fields"]["pid"], 42);
+                    assert_eq!(
+                        tail[0].message,
+                        "Session ready machine_id=gpu-box pid=42 session_id=session-7"
+                    );
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_batcher_caps_and_reports_drops() {
+        let mut batcher = LogBatcher::new();
+        assert!(batcher.deadline().is_none());
+
+        for i in 0..MAX_LOG_BATCH_LINES + 3 {
+            batcher.push(line(&format!("line {}", i)));
+        }
+        batcher.record_dropped(2);
+        assert!(batcher.deadline().is_some());
+
+        let batch = batcher.take();
+        assert_eq!(batch.len(), MAX_LOG_BATCH_LINES + 1);
+        assert_eq!(batch[0].message, "5 log lines dropped");
+        assert_eq!(batch[1].message, "line 3");
+        assert!(batcher.deadline().is_none());
+        assert!(batcher.take().is_empty());
+    }
+}
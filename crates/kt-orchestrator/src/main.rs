@@ -3,22 +3,29 @@
 //! The orchestrator runs on the local machine and accepts incoming
 //! reverse SSH connections from remote agents.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use clap::Parser;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use kt_core::ipc::{IpcEvent, IpcEventEnvelope};
-use kt_core::pidfile::{self, PidFileGuard};
+use kt_core::pidfile::{self, PidFileGuard, ProcessStamp, StartupLock};
+use kt_core::TokenGuard;
 
-use kt_core::config::{self, OrchestratorConfig};
+use kt_core::config::{self, ConfigFile, ConfigLoader, LogFormat, OrchestratorConfig};
+use kt_orchestrator::connection::run_reconnect_grace;
 use kt_orchestrator::ipc::IpcServer;
-use kt_orchestrator::server::{load_or_generate_host_key, ConnectionEvent, SshServer};
-use kt_orchestrator::session::run_orphan_cleanup;
+use kt_orchestrator::logging::{self, LogFileLayer, LogFileSettings, LogSource};
+use kt_orchestrator::readiness::{self, ReadyFile};
+use kt_orchestrator::server::{
+    handle_connection_event, load_or_generate_host_key, ConnectionEvent, SshServer,
+};
+use kt_orchestrator::session::{
+    run_orphan_cleanup, run_output_flusher, run_resize_flusher, run_silence_monitor,
+};
 use kt_orchestrator::OrchestratorState;
 
 #[derive(Parser)]
@@ -41,6 +48,14 @@ struct Args {
     /// Log level (error, warn, info, debug, trace)
     #[arg(long, default_value = "info")]
     log_level: String,
+
+    /// Log file format: text or json (overrides config)
+    #[arg(long, value_name = "FORMAT")]
+    log_format: Option<LogFormat>,
+
+    /// Write this file once SSH and IPC are both listening (removed on exit)
+    #[arg(long, value_name = "PATH")]
+    ready_file: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -53,11 +68,30 @@ async fn main() -> Result<()> {
     } else {
         &args.log_level
     };
+    // Log to a file as well so IPC clients can tail and follow the logs
+    let mut log_settings =
+        LogFileSettings::load(args.config.as_deref(), logging::default_log_path());
+    if let Some(format) = args.log_format {
+        log_settings.format = format;
+    }
+    let log_file = match LogFileLayer::open(&log_settings) {
+        Ok(layer) => Some(layer),
+        Err(e) => {
+            eprintln!(
+                "Warning: failed to open log file {:?}: {}",
+                log_settings.path, e
+            );
+            None
+        }
+    };
+    let log_source = LogSource::new(log_file.is_some().then_some(log_settings.path));
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG").unwrap_or_else(|_| log_level.into()),
         ))
         .with(tracing_subscriber::fmt::layer())
+        .with(log_file)
+        .with(log_source.broadcast_layer())
         .init();
 
     tracing::info!("k-Terminus Orchestrator starting...");
@@ -69,25 +103,11 @@ async fn main() -> Result<()> {
         return Err(e);
     }
 
-    // Load configuration
-    let config = if let Some(config_path) = &args.config {
-        config::load_config(config_path)
-            .with_context(|| format!("Failed to load config from {:?}", config_path))?
-    } else {
-        let default_path = config::default_config_path();
-        if default_path.exists() {
-            config::load_config(&default_path).unwrap_or_else(|e| {
-                tracing::warn!("Failed to load config from {:?}: {}", default_path, e);
-                OrchestratorConfig::default()
-            })
-        } else {
-            tracing::info!("Using default configuration");
-            OrchestratorConfig::default()
-        }
-    };
+    let config = load_config(args.config.as_deref(), args.bind.as_deref(), false)?;
+    let bind_addr = config.bind_address.clone();
 
-    // Override bind address if specified
-    let bind_addr = args.bind.unwrap_or_else(|| config.bind_address.clone());
+    // Tighten permissions on secrets an older version may have left readable
+    kt_core::permissions::check_permissions(&[&config.host_key_path]);
 
     // Load or generate host key
     let host_key = load_or_generate_host_key(&config.host_key_path).await?;
@@ -96,6 +116,11 @@ async fn main() -> Result<()> {
         .context("Failed to extract public key from host key")?;
     tracing::info!("Host key fingerprint: {}", public_key.fingerprint());
 
+    // Don't let a marker from an earlier run look like readiness
+    if let Some(path) = &args.ready_file {
+        ReadyFile::remove_stale(path);
+    }
+
     // Create orchestrator state
     let state = Arc::new(OrchestratorState::new(config.clone()));
 
@@ -137,10 +162,32 @@ async fn main() -> Result<()> {
     // (Create early so we can get the event sender for the event handler)
     let ipc_address = config.ipc_address();
     let ipc_server = Arc::new(
-        IpcServer::new(ipc_address.clone(), Arc::clone(&state))?
-            .with_shutdown_token(cancel.clone()),
+        IpcServer::new(ipc_address, Arc::clone(&state))?
+            .with_shutdown_token(cancel.clone())
+            .with_log_source(log_source),
     );
+    // Remove the token we just acquired and the PID file when shutting down
+    // (or panicking), so nothing takes them for a running orchestrator. Write
+    // the PID file under the startup lock so it can't be mistaken for a
+    // stale one while being written
+    let _token_guard = TokenGuard::for_default_path().context("Failed to locate IPC token file")?;
+    let _pid_guard = {
+        let _lock = StartupLock::acquire_default().context("Failed to take startup lock")?;
+        PidFileGuard::new(pid_path, ProcessStamp::current()).context("Failed to write PID file")?
+    };
+    tracing::debug!("PID file written");
     let ipc_event_tx = ipc_server.event_sender();
+    let ipc_listener = ipc_server.bind().await?;
+    let ipc_local_addr = ipc_listener.local_addr()?;
+
+    // Reload the configuration on SIGHUP, the same way it was loaded
+    let (config_path, bind) = (args.config.clone(), args.bind.clone());
+    kt_orchestrator::reload::spawn_reload_on_hangup(
+        Arc::clone(&state),
+        move || load_config(config_path.as_deref(), bind.as_deref(), true),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    );
 
     // Spawn event handler
     let state_clone = Arc::clone(&state);
@@ -150,12 +197,26 @@ async fn main() -> Result<()> {
         }
     });
 
+    // Notify webhooks of lifecycle events
+    let _webhook_handle = kt_orchestrator::webhook::spawn_notifier(
+        config.webhook.clone(),
+        ipc_server.event_sender().subscribe(),
+        cancel.clone(),
+    );
+
+    // Journal events to disk, if configured
+    let _journal_handle = kt_orchestrator::journal::spawn_journal(
+        &config,
+        ipc_server.event_sender().subscribe(),
+        cancel.clone(),
+    );
+
     // Spawn IPC server task
     let ipc_server_clone = Arc::clone(&ipc_server);
     let cancel_ipc = cancel.clone();
     tokio::spawn(async move {
         tokio::select! {
-            result = ipc_server_clone.run() => {
+            result = ipc_server_clone.serve(ipc_listener) => {
                 if let Err(e) = result {
                     tracing::error!("IPC server error: {}", e);
                 }
@@ -165,205 +226,108 @@ async fn main() -> Result<()> {
             }
         }
     });
-    tracing::info!("IPC server listening on {}", ipc_address);
-
-    // Write PID file (guard will remove it on shutdown)
-    let _pid_guard = PidFileGuard::new(pid_path, std::process::id())
-        .context("Failed to write PID file")?;
-    tracing::debug!("PID file written");
 
     // Start health monitor
     let health_monitor = kt_orchestrator::connection::HealthMonitor::new(
-        config.heartbeat_interval,
-        config.heartbeat_timeout,
+        *config.heartbeat_interval,
+        *config.heartbeat_timeout,
+    );
+    let _health_handle = health_monitor.spawn(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
     );
-    let _health_handle = health_monitor.spawn(Arc::clone(&state), cancel.clone());
     tracing::info!(
-        "Health monitor started (interval={:?}, timeout={:?})",
+        "Health monitor started (interval={}, timeout={})",
         config.heartbeat_interval,
         config.heartbeat_timeout
     );
 
     // Start orphan cleanup task
     let state_orphan = Arc::clone(&state);
+    let events_orphan = ipc_server.event_sender();
     let cancel_orphan = cancel.clone();
     tokio::spawn(async move {
-        run_orphan_cleanup(state_orphan, cancel_orphan).await;
+        run_orphan_cleanup(state_orphan, events_orphan, cancel_orphan).await;
     });
 
+    // Start silence monitor for sessions that asked for silence events
+    tokio::spawn(run_silence_monitor(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    ));
+
+    // Send output held back while coalescing for lagging clients
+    tokio::spawn(run_output_flusher(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    ));
+
+    // Send the latest size of sessions resized in quick succession
+    tokio::spawn(run_resize_flusher(Arc::clone(&state), cancel.clone()));
+
+    // Remove machines that don't reconnect within their grace period
+    tokio::spawn(run_reconnect_grace(
+        Arc::clone(&state),
+        ipc_server.event_sender(),
+        cancel.clone(),
+    ));
+
     // Create and run SSH server
     let server = SshServer::new(host_key, Arc::clone(&state), cancel.clone(), event_tx);
 
     tracing::info!("Starting SSH server on {}", bind_addr);
-    server.run(&bind_addr).await?;
+    let ssh_listener = server.bind_configured().await?;
+
+    // Both listeners are bound; tell the supervisor we're ready
+    let _ready_file = readiness::notify_ready(
+        Some(ssh_listener.local_addr()?),
+        ipc_local_addr,
+        args.ready_file.as_deref(),
+    );
+
+    server.serve(ssh_listener).await?;
+    readiness::notify_stopping();
 
     tracing::info!("Orchestrator shutdown complete");
     Ok(())
 }
 
-/// Handle connection events from SSH handlers
-async fn handle_connection_event(
-    state: &OrchestratorState,
-    event: ConnectionEvent,
-    ipc_event_tx: &broadcast::Sender<IpcEventEnvelope>,
-) {
-    use kt_orchestrator::connection::TunnelConnection;
-
-    match event {
-        ConnectionEvent::MachineConnected {
-            machine_id,
-            alias,
-            hostname,
-            os,
-            arch,
-            command_tx,
-            cancel,
-        } => {
-            tracing::info!(
-                "Machine connected: {} (alias: {}, hostname: {}, os: {}, arch: {})",
-                machine_id,
-                alias,
-                hostname,
-                os,
-                arch
-            );
-            // Register in connection pool with command channel
-            state.coordinator.connections.insert(TunnelConnection::new(
-                machine_id.clone(),
-                Some(alias.clone()),
-                Some(hostname.clone()),
-                os.clone(),
-                arch.clone(),
-                command_tx,
-                cancel,
-            ));
-
-            // Broadcast to IPC clients with sequence number
-            let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::MachineConnected(
-                kt_core::ipc::MachineInfo {
-                    id: machine_id.to_string(),
-                    alias: Some(alias),
-                    hostname,
-                    os,
-                    arch,
-                    status: kt_core::ipc::MachineStatus::Connected,
-                    connected_at: None,
-                    last_heartbeat: None,
-                    session_count: 0,
-                    tags: vec![],
-                },
-            )));
-        }
-
-        ConnectionEvent::MachineDisconnected { machine_id } => {
-            tracing::info!("Machine disconnected: {}", machine_id);
-
-            // Atomic operation - removes connection AND all sessions atomically
-            let (_, removed_sessions) = state.coordinator.atomic_disconnect(&machine_id).await;
-
-            // Emit session closed events with sequence numbers
-            // Use try_close() CAS to ensure only this cleanup path emits events
-            for session in &removed_sessions {
-                if session.try_close() {
-                    tracing::info!(
-                        "Cleaned up orphaned session {} on machine disconnect",
-                        session.id
-                    );
-                    // Notify IPC clients that the session was closed
-                    let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::SessionClosed {
-                        session_id: session.id.to_string(),
-                    }));
-                }
-            }
-            if !removed_sessions.is_empty() {
-                tracing::info!(
-                    "Cleaned up {} orphaned sessions for disconnected machine {}",
-                    removed_sessions.len(),
-                    machine_id
-                );
-            }
-
-            // Broadcast machine disconnected to IPC clients with sequence number
-            let _ = ipc_event_tx.send(
-                state
-                    .epoch
-                    .wrap_event(IpcEvent::MachineDisconnected {
-                        machine_id: machine_id.to_string(),
-                    }),
-            );
-        }
-
-        ConnectionEvent::SessionCreated {
-            machine_id,
-            session_id,
-            pid,
-        } => {
-            tracing::info!(
-                "Session {} ready on {} (pid={})",
-                session_id,
-                machine_id,
-                pid
-            );
-            // Update session with PID from agent
-            state.coordinator.sessions.set_pid(session_id, pid);
-
-            // Broadcast to IPC clients with sequence number
-            let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::SessionCreated(
-                kt_core::ipc::SessionInfo {
-                    id: session_id.to_string(),
-                    machine_id: machine_id.to_string(),
-                    shell: None,
-                    created_at: String::new(),
-                    pid: Some(pid),
-                    size: None,
-                },
-            )));
-        }
-
-        ConnectionEvent::SessionClosed {
-            machine_id,
-            session_id,
-        } => {
-            tracing::info!("Session {} closed on {}", session_id, machine_id);
-
-            // Get the session first to use CAS
-            if let Some(session) = state.coordinator.sessions.get(session_id) {
-                // Use try_close() CAS to ensure only one cleanup path emits events
-                if session.try_close() {
-                    // Remove session from session manager
-                    state.coordinator.sessions.remove(session_id);
-
-                    // Broadcast to IPC clients with sequence number
-                    let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::SessionClosed {
-                        session_id: session_id.to_string(),
-                    }));
+/// Load the configuration: defaults <- file <- KT_ORCHESTRATOR__* <- flags
+///
+/// A default config file that can't be read is skipped with a warning at
+/// startup, but fails a reload so a typo doesn't reset every setting.
+fn load_config(
+    config_path: Option<&Path>,
+    bind: Option<&str>,
+    reloading: bool,
+) -> Result<OrchestratorConfig> {
+    let mut loader = ConfigLoader::<ConfigFile>::new();
+    if let Some(config_path) = config_path {
+        loader
+            .with_file(config_path)
+            .with_context(|| format!("Failed to load config from {:?}", config_path))?;
+    } else {
+        let default_path = config::default_config_path();
+        if default_path.exists() {
+            if let Err(e) = loader.with_file(&default_path) {
+                if reloading {
+                    return Err(e)
+                        .with_context(|| format!("Failed to load config from {:?}", default_path));
                 }
-            } else {
-                // Session already removed (possibly by atomic_disconnect)
-                tracing::debug!("Session {} already removed", session_id);
+                tracing::warn!("Failed to load config from {:?}: {}", default_path, e);
             }
+        } else if !reloading {
+            tracing::info!("No config file, using defaults");
         }
-
-        ConnectionEvent::SessionData {
-            machine_id,
-            session_id,
-            data,
-        } => {
-            tracing::trace!(
-                "Session data: {} bytes from {} on {}",
-                data.len(),
-                session_id,
-                machine_id
-            );
-            // Broadcast to IPC clients with sequence number
-            let envelope = state.epoch.wrap_event(IpcEvent::TerminalOutput {
-                session_id: session_id.to_string(),
-                data,
-            });
-            // Ignore send errors (no subscribers is fine)
-            let _ = ipc_event_tx.send(envelope);
-        }
     }
+    loader.with_env();
+    if let Some(bind) = bind {
+        loader.with_cli("orchestrator.bind_address", bind.to_string(), "--bind")?;
+    }
+    Ok(loader.load()?.orchestrator)
 }
 
 /// Check if another orchestrator instance is already running
@@ -371,8 +335,8 @@ async fn handle_connection_event(
 /// Returns Ok(()) if we can proceed, or an error if another instance is running.
 async fn check_existing_instance(pid_path: &std::path::Path) -> Result<()> {
     // Check for existing PID file
-    let existing_pid = match pidfile::read_pid_file(pid_path) {
-        Ok(Some(pid)) => pid,
+    let existing = match pidfile::read_pid_file(pid_path) {
+        Ok(Some(stamp)) => stamp,
         Ok(None) => {
             tracing::debug!("No existing PID file found");
             return Ok(());
@@ -385,15 +349,20 @@ async fn check_existing_instance(pid_path: &std::path::Path) -> Result<()> {
         }
     };
 
+    let existing_pid = existing.pid;
     tracing::debug!("Found PID file with PID {}", existing_pid);
 
-    // Check if the process is still alive
-    if !pidfile::is_process_alive(existing_pid) {
+    // Check if the process is still alive, and not a new one reusing its PID
+    if !existing.is_alive() {
         tracing::info!(
             "Stale PID file found (process {} not running), cleaning up",
             existing_pid
         );
         pidfile::remove_pid_file(pid_path)?;
+        // Its token file too, if it left one, so clients don't take it for
+        // a running orchestrator
+        let lock = StartupLock::acquire_default()?;
+        pidfile::clean_stale_files(&lock)?;
         return Ok(());
     }
 
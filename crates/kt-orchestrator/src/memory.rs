@@ -0,0 +1,208 @@
+//! Memory self-monitoring and load shedding
+//!
+//! On a constrained machine the orchestrator can watch its own memory use.
+//! With `memory_limit.soft_limit_mb` set, it samples its resident memory
+//! every `check_interval`. Once that goes over the limit it sheds load until
+//! use drops below `resume_percent` of the limit:
+//!
+//! - the event history served to `GetEventsSince` keeps only the last
+//!   [`SHED_HISTORY_MAX_OUTPUT_BYTES`] of terminal output
+//! - new sessions are refused with `CapacityExceeded`
+//!
+//! Shedding never touches live broadcasts, existing sessions, or events
+//! other than terminal output, so clients and session tracking stay
+//! consistent. The event broadcast itself has a fixed capacity.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tokio_util::sync::CancellationToken;
+
+use kt_core::config::MemoryLimitConfig;
+
+use crate::ipc::{EventHistory, SHED_HISTORY_MAX_OUTPUT_BYTES};
+use crate::state::OrchestratorState;
+
+/// Shortest interval between memory samples
+const MIN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+const MIB: u64 = 1024 * 1024;
+
+/// Whether the orchestrator is shedding load to save memory
+#[derive(Debug, Default)]
+pub struct MemoryPressure {
+    shedding: AtomicBool,
+    /// Resident memory at the last sample, in bytes (0 = not sampled)
+    resident_bytes: AtomicU64,
+}
+
+impl MemoryPressure {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether load is being shed
+    pub fn is_shedding(&self) -> bool {
+        self.shedding.load(Ordering::Acquire)
+    }
+
+    /// Resident memory at the last sample, in bytes
+    pub fn resident_bytes(&self) -> Option<u64> {
+        match self.resident_bytes.load(Ordering::Relaxed) {
+            0 => None,
+            bytes => Some(bytes),
+        }
+    }
+
+    /// Record a sample of `resident` bytes against `config`
+    ///
+    /// Returns true if shedding started or stopped.
+    pub fn update(&self, resident: u64, config: &MemoryLimitConfig) -> bool {
+        self.resident_bytes.store(resident, Ordering::Relaxed);
+        let shedding = self.is_shedding();
+        let shed = match (config.soft_limit_bytes(), config.resume_bytes()) {
+            (Some(_), Some(resume)) if shedding => resident >= resume,
+            (Some(limit), _) => resident > limit,
+            (None, _) => false,
+        };
+        self.shedding.store(shed, Ordering::Release);
+        shed != shedding
+    }
+
+    /// Why a new session was refused
+    pub fn refusal_message(&self, config: &MemoryLimitConfig) -> String {
+        format!(
+            "Orchestrator is using {} MiB of memory, over its soft limit of {} MiB; \
+             new sessions are refused until that drops",
+            self.resident_bytes().unwrap_or(0) / MIB,
+            config.soft_limit_mb.unwrap_or(0)
+        )
+    }
+}
+
+/// Resident memory of the process `pid`, in bytes
+fn resident_memory(system: &mut System, pid: Pid) -> Option<u64> {
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::new().with_memory(),
+    );
+    system.process(pid).map(|process| process.memory())
+}
+
+/// Run the memory monitor task.
+///
+/// Samples the orchestrator's memory use and starts or stops shedding load
+/// as it crosses the configured limits. Returns right away if no soft limit
+/// is configured.
+///
+/// # Arguments
+///
+/// * `state` - The orchestrator state, whose [`MemoryPressure`] is updated
+/// * `history` - Event history to trim while shedding
+/// * `cancel` - Cancellation token for graceful shutdown
+pub async fn run_memory_monitor(
+    state: Arc<OrchestratorState>,
+    history: Arc<EventHistory>,
+    cancel: CancellationToken,
+) {
+    let config = &state.config().memory_limit;
+    let Some(limit_mb) = config.soft_limit_mb else {
+        return;
+    };
+    let pid = match sysinfo::get_current_pid() {
+        Ok(pid) => pid,
+        Err(e) => {
+            tracing::warn!("Can't monitor memory use: {}", e);
+            return;
+        }
+    };
+    tracing::info!(
+        "Shedding load above {} MiB of memory (resuming below {}%)",
+        limit_mb,
+        config.resume_percent.min(100)
+    );
+
+    let mut system = System::new();
+    let mut interval = tokio::time::interval(config.check_interval.get().max(MIN_CHECK_INTERVAL));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let Some(resident) = resident_memory(&mut system, pid) else {
+                    continue;
+                };
+                if !state.memory.update(resident, config) {
+                    continue;
+                }
+                let shedding = state.memory.is_shedding();
+                history.set_shedding(shedding);
+                if shedding {
+                    tracing::warn!(
+                        "Memory use {} MiB is over the soft limit of {} MiB; shedding load: \
+                         keeping {} KiB of output for replay and refusing new sessions",
+                        resident / MIB,
+                        limit_mb,
+                        SHED_HISTORY_MAX_OUTPUT_BYTES / 1024
+                    );
+                } else {
+                    tracing::info!(
+                        "Memory use down to {} MiB, no longer shedding load",
+                        resident / MIB
+                    );
+                }
+            }
+            _ = cancel.cancelled() => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(soft_limit_mb: Option<u64>) -> MemoryLimitConfig {
+        MemoryLimitConfig {
+            soft_limit_mb,
+            resume_percent: 90,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_never_sheds_without_a_limit() {
+        let pressure = MemoryPressure::new();
+        assert!(!pressure.update(u64::MAX, &config(None)));
+        assert!(!pressure.is_shedding());
+        assert_eq!(pressure.resident_bytes(), Some(u64::MAX));
+    }
+
+    #[test]
+    fn test_sheds_above_limit_until_below_resume() {
+        let pressure = MemoryPressure::new();
+        let config = config(Some(100));
+
+        assert!(!pressure.update(100 * MIB, &config));
+        assert!(!pressure.is_shedding());
+
+        assert!(pressure.update(100 * MIB + 1, &config));
+        assert!(pressure.is_shedding());
+        let message = pressure.refusal_message(&config);
+        assert!(message.contains("soft limit of 100 MiB"), "{}", message);
+
+        // Below the limit but not below 90% of it: keep shedding
+        assert!(!pressure.update(95 * MIB, &config));
+        assert!(pressure.is_shedding());
+
+        assert!(pressure.update(89 * MIB, &config));
+        assert!(!pressure.is_shedding());
+    }
+
+    #[test]
+    fn test_samples_own_memory() {
+        let pid = sysinfo::get_current_pid().unwrap();
+        let resident = resident_memory(&mut System::new(), pid);
+        assert!(resident.is_some_and(|bytes| bytes > 0));
+    }
+}
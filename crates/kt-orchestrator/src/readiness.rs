@@ -0,0 +1,215 @@
+//! Startup readiness signal for process supervisors
+//!
+//! A supervisor starting the orchestrator wants to know when it can take
+//! connections, not just that the process exists. Once both the SSH and the
+//! IPC listener are bound (just the IPC one when serving IPC only),
+//! [`notify_ready`]:
+//!
+//! - sends `READY=1` to systemd when `NOTIFY_SOCKET` is set, so units can use
+//!   `Type=notify`
+//! - writes the marker file, if one was requested (e.g. for launchd, which
+//!   has no readiness protocol of its own)
+//! - logs a line starting with [`READY_LOG_PREFIX`]
+//!
+//! Nothing is signalled if either bind fails, so a supervisor never races a
+//! socket that isn't there.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+/// Start of the log line emitted when the orchestrator is ready
+pub const READY_LOG_PREFIX: &str = "Orchestrator ready";
+
+/// Announce that both listeners are bound and accepting connections
+///
+/// `ssh_addr` is `None` when serving IPC only, without an SSH listener.
+///
+/// Returns a guard for the marker file at `ready_file`, if given; the file
+/// is removed when the guard is dropped.
+pub fn notify_ready(
+    ssh_addr: Option<SocketAddr>,
+    ipc_addr: SocketAddr,
+    ready_file: Option<&Path>,
+) -> Option<ReadyFile> {
+    let status = match ssh_addr {
+        Some(ssh_addr) => format!("SSH on {}, IPC on {}", ssh_addr, ipc_addr),
+        None => format!("IPC only on {}", ipc_addr),
+    };
+
+    let marker = ready_file.and_then(|path| match ReadyFile::create(path) {
+        Ok(marker) => Some(marker),
+        Err(e) => {
+            tracing::warn!("Failed to write readiness file {:?}: {}", path, e);
+            None
+        }
+    });
+
+    if let Err(e) = sd_notify(&format!(
+        "READY=1\nSTATUS={}\nMAINPID={}",
+        status,
+        std::process::id()
+    )) {
+        tracing::warn!("Failed to notify systemd of readiness: {}", e);
+    }
+
+    tracing::info!("{}: {}", READY_LOG_PREFIX, status);
+    marker
+}
+
+/// Tell systemd the orchestrator is shutting down
+pub fn notify_stopping() {
+    if let Err(e) = sd_notify("STOPPING=1") {
+        tracing::debug!("Failed to notify systemd of shutdown: {}", e);
+    }
+}
+
+/// Send `state` to the socket in `NOTIFY_SOCKET`, if set.
+///
+/// Returns whether a notification was sent.
+pub fn sd_notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) if !socket.is_empty() => {
+            send_notify(&socket, state)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Send one notification datagram to `socket`, which is a filesystem path
+/// or, on Linux, an abstract socket name starting with `@`
+#[cfg(unix)]
+fn send_notify(socket: &std::ffi::OsStr, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::net::UnixDatagram;
+
+    let sender = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sender.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract notify sockets are only supported on Linux",
+            ));
+        }
+        None => {
+            sender.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notify(_socket: &std::ffi::OsStr, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "NOTIFY_SOCKET is only supported on Unix",
+    ))
+}
+
+/// Marker file that exists while the orchestrator is ready
+///
+/// Holds the orchestrator's PID. Removed on drop.
+#[derive(Debug)]
+pub struct ReadyFile {
+    path: PathBuf,
+}
+
+impl ReadyFile {
+    /// Write the marker file, replacing any stale one atomically
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        std::fs::write(&tmp, format!("{}\n", std::process::id()))?;
+        std::fs::rename(&tmp, path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Remove a marker left behind by an earlier run, so it can't be
+    /// mistaken for this one being ready
+    pub fn remove_stale(path: &Path) {
+        match std::fs::remove_file(path) {
+            Ok(()) => tracing::debug!("Removed stale readiness file {:?}", path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => tracing::warn!("Failed to remove readiness file {:?}: {}", path, e),
+        }
+    }
+
+    /// Path of the marker file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ReadyFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_file_written_and_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ready");
+        std::fs::write(&path, "stale").unwrap();
+
+        let marker = ReadyFile::create(&path).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(marker.path()).unwrap().trim(),
+            std::process::id().to_string()
+        );
+
+        drop(marker);
+        assert!(!path.exists());
+
+        // Removing a missing marker is fine
+        ReadyFile::remove_stale(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_send_notify_to_path_socket() {
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("notify.sock");
+        let receiver = UnixDatagram::bind(&socket_path).unwrap();
+
+        send_notify(socket_path.as_os_str(), "READY=1\nSTATUS=test").unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=test");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_send_notify_to_abstract_socket() {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+        let name = format!("kt-notify-test-{}", std::process::id());
+        let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let receiver = UnixDatagram::bind_addr(&addr).unwrap();
+
+        send_notify(std::ffi::OsStr::new(&format!("@{}", name)), "READY=1").unwrap();
+
+        let mut buf = [0u8; 16];
+        let n = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+    }
+}
@@ -0,0 +1,147 @@
+//! Recently rejected agent registrations
+//!
+//! An agent the orchestrator turns away (incompatible protocol version, not
+//! in the tailnet, invalid machine ID) otherwise only shows up in the log,
+//! so the agent's owner is left wondering why it never appears. The last
+//! [`MAX_REJECTIONS`] rejections are kept for `ListRejections`, and IPC
+//! clients are told of each with a `MachineRejected` event.
+//!
+//! Agents retry, so the same rejection repeats every few seconds. A repeat
+//! (same address, hostname and reason) only bumps the count of the one
+//! already recorded, and is announced again at most once per
+//! [`ANNOUNCE_INTERVAL`].
+
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use kt_core::ipc::RejectionInfo;
+
+/// Most rejections remembered
+pub const MAX_REJECTIONS: usize = 50;
+
+/// Shortest time between announcements of the same rejection
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Entry {
+    info: RejectionInfo,
+    announced: Instant,
+}
+
+impl Entry {
+    fn matches(&self, hostname: Option<&str>, reason: &str, peer_addr: &str) -> bool {
+        self.info.hostname.as_deref() == hostname
+            && self.info.reason == reason
+            && self.info.peer_addr == peer_addr
+    }
+}
+
+/// Recently rejected agent registrations
+#[derive(Debug, Default)]
+pub struct Rejections {
+    /// Most recently seen first
+    entries: Mutex<VecDeque<Entry>>,
+}
+
+impl Rejections {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that the agent at `peer` was rejected at `now`
+    ///
+    /// Returns whether to announce it, which is false for a repeat of a
+    /// rejection announced less than [`ANNOUNCE_INTERVAL`] ago.
+    pub fn record(&self, hostname: Option<&str>, reason: &str, peer: IpAddr, now: Instant) -> bool {
+        let peer_addr = peer.to_string();
+        let seen = kt_core::time::format_iso8601(SystemTime::now());
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        let existing = entries
+            .iter()
+            .position(|entry| entry.matches(hostname, reason, &peer_addr));
+        if let Some(mut entry) = existing.and_then(|index| entries.remove(index)) {
+            entry.info.count += 1;
+            entry.info.last_seen = seen;
+            let announce = now.duration_since(entry.announced) >= ANNOUNCE_INTERVAL;
+            if announce {
+                entry.announced = now;
+            }
+            entries.push_front(entry);
+            return announce;
+        }
+
+        if entries.len() >= MAX_REJECTIONS {
+            entries.pop_back();
+        }
+        entries.push_front(Entry {
+            info: RejectionInfo {
+                hostname: hostname.map(String::from),
+                reason: reason.to_string(),
+                peer_addr,
+                first_seen: seen.clone(),
+                last_seen: seen,
+                count: 1,
+            },
+            announced: now,
+        });
+        true
+    }
+
+    /// Recorded rejections, most recently seen first
+    pub fn list(&self) -> Vec<RejectionInfo> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().map(|entry| entry.info.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PEER: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+
+    #[test]
+    fn test_repeats_are_counted_and_rate_limited() {
+        let rejections = Rejections::new();
+        let start = Instant::now();
+        let record = |reason: &str, after: Duration| {
+            rejections.record(Some("build-box"), reason, PEER, start + after)
+        };
+
+        assert!(record("Protocol version mismatch", Duration::ZERO));
+        assert!(!record("Protocol version mismatch", Duration::from_secs(5)));
+        assert!(record("Not in Tailscale network", Duration::from_secs(6)));
+        assert!(record("Protocol version mismatch", ANNOUNCE_INTERVAL));
+
+        let list = rejections.list();
+        assert_eq!(list.len(), 2);
+        assert_eq!(list[0].reason, "Protocol version mismatch");
+        assert_eq!(list[0].count, 3);
+        assert_eq!(list[0].peer_addr, "192.0.2.1");
+        assert_eq!(list[1].count, 1);
+    }
+
+    #[test]
+    fn test_oldest_dropped_when_full() {
+        let rejections = Rejections::new();
+        let now = Instant::now();
+        for i in 0..=MAX_REJECTIONS {
+            let hostname = format!("agent-{}", i);
+            rejections.record(Some(&hostname), "rejected", PEER, now);
+        }
+
+        let list = rejections.list();
+        assert_eq!(list.len(), MAX_REJECTIONS);
+        assert_eq!(
+            list[0].hostname.as_deref(),
+            Some(format!("agent-{}", MAX_REJECTIONS).as_str())
+        );
+        assert_eq!(
+            list[MAX_REJECTIONS - 1].hostname.as_deref(),
+            Some("agent-1")
+        );
+    }
+}
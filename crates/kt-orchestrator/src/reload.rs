@@ -0,0 +1,187 @@
+//! Configuration reload on `SIGHUP`
+//!
+//! Sending the orchestrator `SIGHUP` makes it load its configuration again,
+//! the same way as at startup (file, `KT_ORCHESTRATOR__*`, flags). Settings
+//! read each time they're used take effect right away:
+//!
+//! - `attach_motd`
+//! - `ipc_rate_limit` (for the next request)
+//! - `machines`
+//! - `max_total_sessions`
+//! - `owner_backpressure`
+//! - `reconnect_grace`
+//!
+//! Anything else that changed, such as `bind_address`, keeps its old value
+//! until the orchestrator restarts, with a warning. Clients are told with a
+//! `ConfigReloaded` event listing the keys that changed, or with a
+//! `ConfigReloadFailed` event when the configuration can't be loaded, in
+//! which case nothing changes.
+
+use std::sync::Arc;
+
+use kt_core::config::OrchestratorConfig;
+use kt_core::ipc::{IpcEvent, IpcEventEnvelope};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::state::OrchestratorState;
+
+/// Apply a configuration loaded again and tell clients what changed
+///
+/// Returns the keys that changed and now apply, or None if `loaded` is an
+/// error.
+pub fn reload_config(
+    state: &OrchestratorState,
+    loaded: anyhow::Result<OrchestratorConfig>,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+) -> Option<Vec<String>> {
+    let loaded = match loaded {
+        Ok(config) => config,
+        Err(e) => {
+            let error = format!("{:#}", e);
+            tracing::error!(
+                "Failed to reload configuration, keeping the current one: {}",
+                error
+            );
+            let _ = events.send(
+                state
+                    .epoch
+                    .wrap_event(IpcEvent::ConfigReloadFailed { error }),
+            );
+            return None;
+        }
+    };
+
+    let current = state.config();
+    let changed = current.changed_keys(&loaded);
+    let reloaded = reloadable(&current, loaded);
+    let changed_keys = current.changed_keys(&reloaded);
+    let restart_required: Vec<String> = changed
+        .into_iter()
+        .filter(|key| !changed_keys.contains(key))
+        .collect();
+    state.set_config(reloaded);
+
+    if changed_keys.is_empty() {
+        tracing::info!("Reloaded configuration, nothing that applies changed");
+    } else {
+        tracing::info!("Reloaded configuration: {}", changed_keys.join(", "));
+    }
+    if !restart_required.is_empty() {
+        tracing::warn!(
+            "Configuration changes that need a restart: {}",
+            restart_required.join(", ")
+        );
+    }
+    let _ = events.send(state.epoch.wrap_event(IpcEvent::ConfigReloaded {
+        changed_keys: changed_keys.clone(),
+        restart_required,
+    }));
+    Some(changed_keys)
+}
+
+/// `current` with the settings that can change while running taken from
+/// `loaded`
+fn reloadable(current: &OrchestratorConfig, loaded: OrchestratorConfig) -> OrchestratorConfig {
+    OrchestratorConfig {
+        attach_motd: loaded.attach_motd,
+        ipc_rate_limit: loaded.ipc_rate_limit,
+        machines: loaded.machines,
+        max_total_sessions: loaded.max_total_sessions,
+        owner_backpressure: loaded.owner_backpressure,
+        reconnect_grace: loaded.reconnect_grace,
+        ..current.clone()
+    }
+}
+
+/// Reload the configuration with `load` on each `SIGHUP` until cancelled
+///
+/// Does nothing on platforms without signals.
+pub fn spawn_reload_on_hangup<F>(
+    state: Arc<OrchestratorState>,
+    load: F,
+    events: broadcast::Sender<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) -> JoinHandle<()>
+where
+    F: Fn() -> anyhow::Result<OrchestratorConfig> + Send + 'static,
+{
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        {
+            use tokio::signal::unix::{signal, SignalKind};
+
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    tracing::warn!("Can't reload configuration on SIGHUP: {}", e);
+                    return;
+                }
+            };
+            loop {
+                tokio::select! {
+                    Some(()) = hangup.recv() => {
+                        tracing::info!("Received SIGHUP, reloading configuration...");
+                        reload_config(&state, load(), &events);
+                    }
+                    _ = cancel.cancelled() => break,
+                }
+            }
+        }
+
+        #[cfg(not(unix))]
+        {
+            let _ = (state, load, events, cancel);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_reload_applies_reloadable_keys_and_reports_them() {
+        let state = OrchestratorState::new(OrchestratorConfig::default());
+        let (events, mut rx) = broadcast::channel(8);
+
+        let loaded = OrchestratorConfig {
+            max_total_sessions: Some(4),
+            attach_motd: Some("Be nice".to_string()),
+            bind_address: "0.0.0.0:2222".to_string(),
+            ..Default::default()
+        };
+        let changed = reload_config(&state, Ok(loaded), &events).unwrap();
+
+        assert_eq!(
+            changed,
+            vec![
+                "orchestrator.attach_motd",
+                "orchestrator.max_total_sessions"
+            ]
+        );
+        let config = state.config();
+        assert_eq!(config.max_total_sessions, Some(4));
+        assert_eq!(config.bind_address, "127.0.0.1:2222");
+        match rx.recv().await.unwrap().event {
+            IpcEvent::ConfigReloaded {
+                changed_keys,
+                restart_required,
+            } => {
+                assert_eq!(changed_keys, changed);
+                assert_eq!(restart_required, vec!["orchestrator.bind_address"]);
+            }
+            other => panic!("expected ConfigReloaded, got {:?}", other),
+        }
+
+        // A failed reload changes nothing and says so
+        let failed = reload_config(&state, Err(anyhow::anyhow!("bad TOML")), &events);
+        assert!(failed.is_none());
+        assert_eq!(state.config().max_total_sessions, Some(4));
+        match rx.recv().await.unwrap().event {
+            IpcEvent::ConfigReloadFailed { error } => assert_eq!(error, "bad TOML"),
+            other => panic!("expected ConfigReloadFailed, got {:?}", other),
+        }
+    }
+}
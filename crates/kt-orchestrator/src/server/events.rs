@@ -0,0 +1,257 @@
+//! Handling of events from agent connections
+
+use std::time::Instant;
+
+use tokio::sync::broadcast;
+
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope};
+
+use super::ConnectionEvent;
+use crate::connection::{begin_reconnect_grace, TunnelConnection};
+use crate::session::keep_exited_session;
+use crate::state::OrchestratorState;
+
+/// Handle connection events from SSH handlers
+///
+/// Keeps the connection pool and sessions in step with what agents report,
+/// and announces the changes to IPC clients on `ipc_event_tx`.
+pub async fn handle_connection_event(
+    state: &OrchestratorState,
+    event: ConnectionEvent,
+    ipc_event_tx: &broadcast::Sender<IpcEventEnvelope>,
+) {
+    match event {
+        ConnectionEvent::MachineConnected {
+            machine_id,
+            alias,
+            label,
+            hostname,
+            os,
+            arch,
+            capabilities,
+            reconnect_count,
+            command_tx,
+            cancel,
+        } => {
+            tracing::info!(%machine_id, %alias, %hostname, %os, %arch, "Machine connected");
+            // Back within its grace period, with its sessions
+            let reconnected = state.coordinator.connections.is_reconnecting(&machine_id);
+            // Register in connection pool with command channel
+            state.coordinator.connections.insert(
+                TunnelConnection::new(
+                    machine_id.clone(),
+                    Some(alias.clone()),
+                    Some(hostname.clone()),
+                    os.clone(),
+                    arch.clone(),
+                    command_tx,
+                    cancel,
+                )
+                .with_label(label.clone())
+                .with_capabilities(capabilities)
+                .with_reconnect_count(reconnect_count),
+            );
+            // The pool keeps counting across agent restarts
+            let reconnect_count = state
+                .coordinator
+                .connections
+                .get(&machine_id)
+                .map_or(reconnect_count, |conn| conn.reconnect_count);
+
+            let sessions = state.coordinator.sessions.list_for_machine(&machine_id);
+            let info = kt_core::ipc::MachineInfo {
+                id: machine_id.to_string(),
+                alias: Some(alias),
+                label,
+                hostname,
+                os,
+                arch,
+                status: kt_core::ipc::MachineStatus::Connected,
+                connected_at: Some(kt_core::time::format_iso8601(std::time::SystemTime::now())),
+                last_heartbeat: None,
+                session_count: sessions.len(),
+                tags: vec![],
+                capabilities: capabilities.names(),
+                reconnect_count,
+            };
+            // Broadcast to IPC clients with sequence number; clients still
+            // list a reconnecting machine, so they get an update instead
+            let event = if reconnected {
+                tracing::info!(%machine_id, "Machine reconnected within its grace period");
+                IpcEvent::MachineUpdated(info)
+            } else {
+                IpcEvent::MachineConnected(info)
+            };
+            let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
+        }
+
+        ConnectionEvent::MachineDisconnected { machine_id } => {
+            tracing::info!(%machine_id, "Machine disconnected");
+
+            // Keep it and its sessions for a while in case it comes back
+            if begin_reconnect_grace(state, ipc_event_tx, &machine_id).await {
+                return;
+            }
+
+            // Atomic operation - removes connection AND all sessions atomically
+            let (connection, removed_sessions) =
+                state.coordinator.atomic_disconnect(&machine_id).await;
+
+            // Emit session closed events with sequence numbers
+            // Use try_close() CAS to ensure only this cleanup path emits events
+            for session in &removed_sessions {
+                if session.try_close() {
+                    tracing::info!(
+                        %machine_id,
+                        session_id = %session.id,
+                        "Cleaned up orphaned session on machine disconnect"
+                    );
+                    // Notify IPC clients that the session was closed
+                    session.emit(
+                        ipc_event_tx,
+                        &state.epoch,
+                        IpcEvent::SessionClosed {
+                            session_id: session.id.to_string(),
+                            exit_code: None,
+                            reason: Some(CloseReason::MachineDisconnected),
+                        },
+                    );
+                }
+            }
+            if !removed_sessions.is_empty() {
+                tracing::info!(
+                    "Cleaned up {} orphaned sessions for disconnected machine {}",
+                    removed_sessions.len(),
+                    machine_id
+                );
+            }
+
+            // Broadcast machine disconnected to IPC clients with sequence number,
+            // unless the health monitor already removed and announced it
+            if connection.is_some() {
+                let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::MachineDisconnected {
+                    machine_id: machine_id.to_string(),
+                }));
+            }
+        }
+
+        ConnectionEvent::MachineRejected {
+            hostname,
+            reason,
+            peer_addr,
+        } => {
+            // The handler has recorded it for ListRejections
+            let event = IpcEvent::MachineRejected {
+                hostname,
+                reason,
+                peer_addr: peer_addr.ip().to_string(),
+            };
+            let _ = ipc_event_tx.send(state.epoch.wrap_event(event));
+        }
+
+        ConnectionEvent::SessionCreated {
+            machine_id,
+            session_id,
+            pid,
+        } => {
+            tracing::info!(%machine_id, %session_id, pid, "Session ready");
+            // Update session with PID from agent
+            state.coordinator.sessions.set_pid(session_id, pid);
+
+            // Broadcast to IPC clients with sequence number
+            let _ = ipc_event_tx.send(state.epoch.wrap_event(IpcEvent::SessionCreated(
+                kt_core::ipc::SessionInfo {
+                    id: session_id.to_string(),
+                    machine_id: machine_id.to_string(),
+                    shell: None,
+                    created_at: String::new(),
+                    pid: Some(pid),
+                    size: None,
+                    name: None,
+                    bytes_in: 0,
+                    bytes_out: 0,
+                    detached: false,
+                    input_bytes_per_min: 0,
+                    input_requests_per_min: 0,
+                },
+            )));
+        }
+
+        ConnectionEvent::SessionClosed {
+            machine_id,
+            session_id,
+            exit_code,
+            reason,
+        } => {
+            tracing::info!(%machine_id, %session_id, ?exit_code, "Session closed");
+
+            // Sessions created to outlive their shell stay until closed
+            if keep_exited_session(state, ipc_event_tx, session_id, exit_code, &reason) {
+                return;
+            }
+
+            // Get the session first to use CAS
+            if let Some(session) = state.coordinator.sessions.get(session_id) {
+                // Use try_close() CAS to ensure only one cleanup path emits events
+                if session.try_close() {
+                    // Remove session from session manager
+                    state.coordinator.sessions.remove(session_id);
+
+                    // Broadcast to IPC clients with sequence number
+                    session.emit(
+                        ipc_event_tx,
+                        &state.epoch,
+                        IpcEvent::SessionClosed {
+                            session_id: session_id.to_string(),
+                            exit_code,
+                            reason: Some(reason),
+                        },
+                    );
+                }
+            } else {
+                // Session already removed (possibly by atomic_disconnect)
+                tracing::debug!("Session {} already removed", session_id);
+            }
+        }
+
+        ConnectionEvent::SessionData {
+            machine_id,
+            session_id,
+            data,
+            stream,
+        } => {
+            tracing::trace!(
+                "Session data: {} bytes from {} on {}",
+                data.len(),
+                session_id,
+                machine_id
+            );
+            // Output that arrives after the session was closed and removed
+            // (the agent hadn't seen the close yet) must not follow the
+            // SessionClosed event
+            let Some(session) = state.coordinator.sessions.get(session_id) else {
+                tracing::trace!("Dropping output for closed session {}", session_id);
+                return;
+            };
+            let activity = session.monitor().on_output(&data, Instant::now());
+            // Broadcast to IPC clients with sequence number, ordered with
+            // the session's close
+            let emitted =
+                session.emit_output(ipc_event_tx, &state.epoch, &state.coalescing, data, stream);
+            if !emitted {
+                tracing::trace!("Dropping output for closed session {}", session_id);
+                return;
+            }
+            for kind in activity {
+                session.emit(
+                    ipc_event_tx,
+                    &state.epoch,
+                    IpcEvent::SessionActivity {
+                        session_id: session_id.to_string(),
+                        kind,
+                    },
+                );
+            }
+        }
+    }
+}
@@ -14,9 +14,11 @@ use russh::{Channel, ChannelId, CryptoVec};
 use russh_keys::key::PublicKey;
 use tokio::sync::mpsc;
 use tokio_util::codec::{Decoder, Encoder};
+use tracing::Instrument;
 
+use kt_core::ipc::{CloseReason, OutputStream};
 use kt_core::types::MachineId;
-use kt_protocol::{Frame, FrameCodec, Message, SessionId};
+use kt_protocol::{AgentCapabilities, ErrorCode, Frame, FrameCodec, Message, SessionId};
 
 use crate::connection::AgentCommand;
 use crate::state::OrchestratorState;
@@ -27,11 +29,17 @@ pub enum ConnectionEvent {
     MachineConnected {
         machine_id: MachineId,
         alias: String,
+        /// Free-form label the agent reported
+        label: Option<String>,
         hostname: String,
         /// Operating system
         os: String,
         /// CPU architecture
         arch: String,
+        /// Optional features the agent advertised at registration
+        capabilities: AgentCapabilities,
+        /// Times the agent reported having reconnected
+        reconnect_count: u64,
         /// Channel for sending commands to this agent
         command_tx: mpsc::Sender<AgentCommand>,
         /// Token to cancel/disconnect this connection
@@ -39,6 +47,14 @@ pub enum ConnectionEvent {
     },
     /// A machine has disconnected
     MachineDisconnected { machine_id: MachineId },
+    /// An agent was rejected at authentication or registration (already
+    /// recorded in the orchestrator state)
+    MachineRejected {
+        /// Hostname the agent reported, if it got as far as registering
+        hostname: Option<String>,
+        reason: String,
+        peer_addr: SocketAddr,
+    },
     /// A new session was created (agent confirmed with PID)
     SessionCreated {
         machine_id: MachineId,
@@ -49,12 +65,18 @@ pub enum ConnectionEvent {
     SessionClosed {
         machine_id: MachineId,
         session_id: SessionId,
+        /// Exit code reported by the agent, if the shell exited
+        exit_code: Option<i32>,
+        /// Why the session ended
+        reason: CloseReason,
     },
     /// Data received from a session
     SessionData {
         machine_id: MachineId,
         session_id: SessionId,
         data: Vec<u8>,
+        /// Stderr only for sessions without a PTY
+        stream: OutputStream,
     },
 }
 
@@ -86,6 +108,10 @@ pub struct ClientHandler {
     command_processor_handle: Option<tokio::task::JoinHandle<()>>,
     /// Cancellation token for this connection (to allow external disconnect)
     cancel: tokio_util::sync::CancellationToken,
+    /// Whether `MachineDisconnected` has been sent for this connection
+    disconnect_sent: bool,
+    /// Tracing span for this connection, with the machine ID once known
+    span: tracing::Span,
 }
 
 impl ClientHandler {
@@ -118,14 +144,107 @@ impl ClientHandler {
             command_tx: Some(command_tx),
             command_processor_handle: None,
             cancel,
+            disconnect_sent: false,
+            span: tracing::Span::current(),
         }
     }
 
+    /// Set the machine ID, recording it on the connection's span
+    fn set_machine_id(&mut self, machine_id: MachineId) {
+        self.span
+            .record("machine_id", tracing::field::display(&machine_id));
+        self.machine_id = Some(machine_id);
+    }
+
     /// Get the machine ID if authenticated
     fn machine_id(&self) -> Option<&MachineId> {
         self.machine_id.as_ref()
     }
 
+    /// Validate and canonicalize an ID the agent reported at registration,
+    /// rejecting the registration if it is invalid
+    fn parse_reported_id(
+        &mut self,
+        session: &mut Session,
+        hostname: &str,
+        what: &str,
+        id: &str,
+    ) -> Option<String> {
+        match MachineId::parse(id) {
+            Ok(id) => Some(id.0),
+            Err(e) => {
+                tracing::warn!(
+                    "Rejecting agent {} with invalid {} {:?}: {}",
+                    self.peer_addr,
+                    what,
+                    id,
+                    e
+                );
+                let reason = format!("Invalid {}: {}", what, e);
+                self.reject_registration(session, hostname, reason);
+                None
+            }
+        }
+    }
+
+    /// Refuse the agent's registration, telling it why
+    fn reject_registration(&mut self, session: &mut Session, hostname: &str, reason: String) {
+        let ack = Message::RegisterAck {
+            accepted: false,
+            reason: Some(reason.clone()),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        };
+        self.send_message(session, SessionId::CONTROL, ack);
+        self.rejected(Some(hostname.to_string()), reason);
+    }
+
+    /// Record that the agent was rejected, announcing it unless the same
+    /// rejection was announced recently
+    fn rejected(&self, hostname: Option<String>, reason: String) {
+        let announce = self.state.rejections.record(
+            hostname.as_deref(),
+            &reason,
+            self.peer_addr.ip(),
+            std::time::Instant::now(),
+        );
+        if !announce {
+            return;
+        }
+        let event = ConnectionEvent::MachineRejected {
+            hostname,
+            reason,
+            peer_addr: self.peer_addr,
+        };
+        if let Err(e) = self.event_tx.try_send(event) {
+            tracing::warn!("Failed to send rejection event: {}", e);
+        }
+    }
+
+    /// The `MachineDisconnected` event for this connection, the first time
+    /// it is asked for
+    ///
+    /// The tunnel ending (EOF or close) and the handler being dropped all
+    /// announce the disconnect, so whichever comes first does it without
+    /// waiting for heartbeats to time out.
+    fn disconnect_event(&mut self) -> Option<ConnectionEvent> {
+        if self.disconnect_sent {
+            return None;
+        }
+        let machine_id = self.machine_id.clone()?;
+        self.disconnect_sent = true;
+        Some(ConnectionEvent::MachineDisconnected { machine_id })
+    }
+
+    /// Forget a channel, announcing the disconnect once none are left
+    async fn channel_finished(&mut self, channel: ChannelId) {
+        self.channels.remove(&channel);
+        if self.channels.is_empty() {
+            if let Some(event) = self.disconnect_event() {
+                let _ = self.event_tx.send(event).await;
+            }
+        }
+    }
+
     /// Process a decoded frame
     async fn handle_frame(&mut self, frame: Frame, session: &mut Session) {
         let Some(machine_id) = self.machine_id().cloned() else {
@@ -140,61 +259,88 @@ impl ClientHandler {
                 os,
                 arch,
                 version,
+                capabilities,
+                reconnect_count,
+                alias,
+                label,
             } => {
                 // Validate protocol version
                 let agent_version = version.as_deref().unwrap_or("unknown");
-                let expected_major = kt_protocol::PROTOCOL_VERSION
-                    .split('.')
-                    .next()
-                    .unwrap_or("1");
-
-                // Check major version compatibility (reject if major version differs)
-                let agent_major = agent_version.split('.').next().unwrap_or("1");
-                if agent_major != expected_major && agent_version != "unknown" {
+
+                // Reject versions whose frames this build can't decode
+                if agent_version != "unknown" && !kt_protocol::is_compatible_protocol(agent_version)
+                {
                     tracing::warn!(
                         "Rejecting agent {} with incompatible protocol version {} (expected {})",
                         reported_id,
                         agent_version,
                         kt_protocol::PROTOCOL_VERSION
                     );
-                    let ack = Message::RegisterAck {
-                        accepted: false,
-                        reason: Some(format!(
-                            "Protocol version mismatch: agent v{}, orchestrator v{}",
-                            agent_version,
-                            kt_protocol::PROTOCOL_VERSION
-                        )),
-                    };
-                    self.send_message(session, SessionId::CONTROL, ack);
+                    let reason = format!(
+                        "Protocol version mismatch: agent v{}, orchestrator v{}",
+                        agent_version,
+                        kt_protocol::PROTOCOL_VERSION
+                    );
+                    self.reject_registration(session, &hostname, reason);
                     return;
                 }
 
-                // For loopback connections, use the reported alias as part of the machine ID
-                // This allows multiple local agents with different aliases
+                // The reported ID is part of the machine ID for loopback
+                // connections and the alias too for agents that don't send
+                // one, so both have to pass the same validation as any other ID
+                let Some(reported_id) =
+                    self.parse_reported_id(session, &hostname, "machine ID", &reported_id)
+                else {
+                    return;
+                };
+                let alias = match alias {
+                    Some(alias) => {
+                        match self.parse_reported_id(session, &hostname, "alias", &alias) {
+                            Some(alias) => alias,
+                            None => return,
+                        }
+                    }
+                    None => reported_id.clone(),
+                };
+                let label = label.and_then(sanitize_label);
+
+                // For loopback connections, use the reported ID as part of the machine ID.
+                // It is derived from the agent's install ID, so an agent restarted with a
+                // new hostname (e.g. in a container) replaces its old entry; several local
+                // agents need a state directory each
                 let effective_machine_id = if self.peer_addr.ip().is_loopback() {
-                    // Use "local-{alias}" for local connections to support multiple agents
+                    // Use "local-{id}" for local connections to support multiple agents
                     let new_id = MachineId::new(format!("local-{}", reported_id));
-                    self.machine_id = Some(new_id.clone());
+                    self.set_machine_id(new_id.clone());
                     new_id
                 } else {
                     machine_id
                 };
 
                 tracing::info!(
-                    "Machine registered: {} ({}) - {} {} (protocol v{})",
+                    "Machine registered: {} ({}) - {} {} (protocol v{}, capabilities: [{}])",
                     effective_machine_id,
                     hostname,
                     os,
                     arch,
-                    agent_version
+                    agent_version,
+                    capabilities.names().join(", ")
                 );
+                if reconnect_count > 0 {
+                    tracing::info!(
+                        "Machine {} reconnected (reconnect #{})",
+                        effective_machine_id,
+                        reconnect_count
+                    );
+                }
 
-                self.alias = Some(reported_id.clone());
+                self.alias = Some(alias.clone());
 
                 // Send registration acknowledgment
                 let ack = Message::RegisterAck {
                     accepted: true,
                     reason: None,
+                    version: Some(env!("CARGO_PKG_VERSION").to_string()),
                 };
                 self.send_message(session, SessionId::CONTROL, ack);
 
@@ -210,10 +356,13 @@ impl ClientHandler {
                     .event_tx
                     .send(ConnectionEvent::MachineConnected {
                         machine_id: effective_machine_id,
-                        alias: reported_id,
+                        alias,
+                        label,
                         hostname,
                         os,
                         arch,
+                        capabilities,
+                        reconnect_count,
                         command_tx,
                         cancel: self.cancel.clone(),
                     })
@@ -248,6 +397,19 @@ impl ClientHandler {
                         machine_id,
                         session_id: frame.session_id,
                         data: data.to_vec(),
+                        stream: OutputStream::Stdout,
+                    })
+                    .await;
+            }
+
+            Message::Stderr(data) => {
+                let _ = self
+                    .event_tx
+                    .send(ConnectionEvent::SessionData {
+                        machine_id,
+                        session_id: frame.session_id,
+                        data: data.to_vec(),
+                        stream: OutputStream::Stderr,
                     })
                     .await;
             }
@@ -265,6 +427,55 @@ impl ClientHandler {
                     .send(ConnectionEvent::SessionClosed {
                         machine_id,
                         session_id: frame.session_id,
+                        exit_code,
+                        reason: CloseReason::ProcessExited { code: exit_code },
+                    })
+                    .await;
+            }
+
+            // The agent couldn't start the session's shell
+            Message::Error {
+                code: ErrorCode::PtyAllocationFailed,
+                message,
+            } if frame.session_id != SessionId::CONTROL => {
+                tracing::warn!(
+                    "Session {} failed on {}: {}",
+                    frame.session_id,
+                    machine_id,
+                    message
+                );
+
+                let _ = self
+                    .event_tx
+                    .send(ConnectionEvent::SessionClosed {
+                        machine_id,
+                        session_id: frame.session_id,
+                        exit_code: None,
+                        reason: CloseReason::Error { message },
+                    })
+                    .await;
+            }
+
+            // The agent lost the session with its previous connection, which
+            // was kept while waiting for it to reconnect
+            Message::Error {
+                code: ErrorCode::SessionNotFound,
+                message,
+            } if frame.session_id != SessionId::CONTROL => {
+                tracing::info!(
+                    "Session {} on {} ended with the previous connection: {}",
+                    frame.session_id,
+                    machine_id,
+                    message
+                );
+
+                let _ = self
+                    .event_tx
+                    .send(ConnectionEvent::SessionClosed {
+                        machine_id,
+                        session_id: frame.session_id,
+                        exit_code: None,
+                        reason: CloseReason::MachineDisconnected,
                     })
                     .await;
             }
@@ -283,6 +494,36 @@ impl ClientHandler {
                 }
             }
 
+            Message::AgentConfigAck {
+                key,
+                applied,
+                reason,
+            } => {
+                let result = if applied {
+                    Ok(())
+                } else {
+                    Err(reason.unwrap_or_else(|| "Refused by the agent".to_string()))
+                };
+                match &result {
+                    Ok(()) => tracing::debug!("Agent {} applied setting '{}'", machine_id, key),
+                    Err(reason) => tracing::debug!(
+                        "Agent {} didn't apply setting '{}': {}",
+                        machine_id,
+                        key,
+                        reason
+                    ),
+                }
+                let acked = self
+                    .state
+                    .coordinator
+                    .connections
+                    .get(&machine_id)
+                    .is_some_and(|conn| conn.ack_config(&key, result));
+                if !acked {
+                    tracing::warn!("Unexpected ack of setting '{}' from {}", key, machine_id);
+                }
+            }
+
             _ => {
                 tracing::warn!(
                     "Unexpected message type from {}: {:?}",
@@ -333,7 +574,7 @@ impl ClientHandler {
             return;
         };
 
-        let task_handle = tokio::spawn(async move {
+        let processor = async move {
             tracing::debug!("Command processor started for {}", machine_id);
 
             while let Some(command) = command_rx.recv().await {
@@ -359,7 +600,8 @@ impl ClientHandler {
             }
 
             tracing::debug!("Command processor stopped for {}", machine_id);
-        });
+        };
+        let task_handle = tokio::spawn(processor.instrument(self.span.clone()));
 
         self.command_processor_handle = Some(task_handle);
     }
@@ -367,20 +609,23 @@ impl ClientHandler {
 
 impl Drop for ClientHandler {
     fn drop(&mut self) {
+        let span = self.span.clone();
+        let _span = span.enter();
         // Abort the command processor task if it's running
         if let Some(handle) = self.command_processor_handle.take() {
             handle.abort();
             tracing::debug!("Aborted command processor task on handler drop");
         }
 
-        // Send disconnect event if this was a registered machine
-        if let Some(machine_id) = self.machine_id.take() {
-            tracing::info!("Machine {} disconnected (handler dropped)", machine_id);
+        // Send disconnect event if this was a registered machine and the
+        // tunnel didn't already end cleanly
+        if let Some(event) = self.disconnect_event() {
+            if let Some(machine_id) = self.machine_id() {
+                tracing::info!("Machine {} disconnected (handler dropped)", machine_id);
+            }
             // Use try_send since Drop is synchronous - if channel is full, the
             // health monitor will eventually clean up the stale connection
-            if let Err(e) = self.event_tx.try_send(ConnectionEvent::MachineDisconnected {
-                machine_id,
-            }) {
+            if let Err(e) = self.event_tx.try_send(event) {
                 tracing::warn!("Failed to send disconnect event: {}", e);
             }
         }
@@ -415,7 +660,13 @@ impl Handler for ClientHandler {
         if peer_ip.is_loopback() {
             tracing::info!("Loopback connection accepted from {}", peer_ip);
             // Use fingerprint-based ID for local connections
-            self.machine_id = Some(MachineId::new(format!("local-{}", &fingerprint[..8])));
+            self.set_machine_id(MachineId::new(format!(
+                "local-{}",
+                MachineId::from_fingerprint(&fingerprint)
+                    .as_str()
+                    .get(..8)
+                    .unwrap_or("agent")
+            )));
             return Ok(Auth::Accept);
         }
 
@@ -427,8 +678,30 @@ impl Handler for ClientHandler {
                 peer_ip
             );
             // Use the Tailscale device name as the machine ID
-            self.machine_id = Some(MachineId::new(&peer_info.device_name));
-            return Ok(Auth::Accept);
+            match MachineId::parse(&peer_info.device_name) {
+                Ok(id) => {
+                    self.set_machine_id(id);
+                    return Ok(Auth::Accept);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Authentication REJECTED for {}: Tailscale device name {:?} is not a valid machine ID: {}",
+                        peer_ip,
+                        peer_info.device_name,
+                        e
+                    );
+                    self.rejected(
+                        Some(peer_info.device_name.clone()),
+                        format!(
+                            "Tailscale device name {:?} is not a valid machine ID: {}",
+                            peer_info.device_name, e
+                        ),
+                    );
+                    return Ok(Auth::Reject {
+                        proceed_with_methods: None,
+                    });
+                }
+            }
         }
 
         // Reject: Not in tailnet and not loopback
@@ -437,6 +710,7 @@ impl Handler for ClientHandler {
             peer_ip,
             fingerprint
         );
+        self.rejected(None, "Not in Tailscale network".to_string());
         Ok(Auth::Reject {
             proceed_with_methods: None,
         })
@@ -477,7 +751,10 @@ impl Handler for ClientHandler {
         loop {
             match self.codec.decode(&mut self.buffer) {
                 Ok(Some(frame)) => {
-                    self.handle_frame(frame, session).await;
+                    // russh runs the handler on its own task, outside the
+                    // span the listener opened
+                    let span = self.span.clone();
+                    self.handle_frame(frame, session).instrument(span).await;
                 }
                 Ok(None) => {
                     // Need more data
@@ -502,20 +779,8 @@ impl Handler for ClientHandler {
         _session: &mut Session,
     ) -> Result<(), Self::Error> {
         tracing::debug!("Channel closed: {:?}", channel);
-        self.channels.remove(&channel);
-
         // If all channels closed, the connection is done
-        if self.channels.is_empty() {
-            if let Some(machine_id) = &self.machine_id {
-                let _ = self
-                    .event_tx
-                    .send(ConnectionEvent::MachineDisconnected {
-                        machine_id: machine_id.clone(),
-                    })
-                    .await;
-            }
-        }
-
+        self.channel_finished(channel).await;
         Ok(())
     }
 
@@ -526,10 +791,37 @@ impl Handler for ClientHandler {
         _session: &mut Session,
     ) -> Result<(), Self::Error> {
         tracing::debug!("Channel EOF: {:?}", channel);
+        // The agent sends nothing more on a channel after EOF, so once every
+        // channel has ended the machine is gone even if the close never
+        // arrives (e.g. the network dropped right after)
+        self.channel_finished(channel).await;
         Ok(())
     }
 }
 
+/// Idle time after which the server checks the agent is still there with
+/// an SSH keepalive
+const SSH_KEEPALIVE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// Unanswered keepalives after which the connection is dropped, so a
+/// vanished network is noticed even without heartbeats
+const SSH_KEEPALIVE_MAX: usize = 3;
+
+/// Longest machine label kept, in characters
+const MAX_LABEL_LEN: usize = 64;
+
+/// Make an agent's free-form label safe to show: control characters (e.g.
+/// escape sequences) are dropped and long labels cut short
+fn sanitize_label(label: String) -> Option<String> {
+    let label: String = label
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_LABEL_LEN)
+        .collect();
+    let label = label.trim();
+    (!label.is_empty()).then(|| label.to_string())
+}
+
 /// Configuration for the SSH server
 #[derive(Clone)]
 pub struct ServerConfig {
@@ -544,9 +836,117 @@ impl ServerConfig {
         config.keys.push(host_key);
         config.auth_rejection_time = std::time::Duration::from_secs(1);
         config.auth_rejection_time_initial = Some(std::time::Duration::from_secs(0));
+        config.keepalive_interval = Some(SSH_KEEPALIVE_INTERVAL);
+        config.keepalive_max = SSH_KEEPALIVE_MAX;
 
         Self {
             ssh_config: Arc::new(config),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler(event_tx: mpsc::Sender<ConnectionEvent>) -> ClientHandler {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        ClientHandler::new(
+            Arc::new(state),
+            event_tx,
+            tokio_util::sync::CancellationToken::new(),
+            "127.0.0.1:1".parse().unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_announced_once() {
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        let mut handler = handler(event_tx);
+        assert!(
+            handler.disconnect_event().is_none(),
+            "not authenticated yet"
+        );
+
+        handler.set_machine_id(MachineId::new("laptop"));
+        // The tunnel ending cleanly announces the disconnect; the drop that
+        // follows must not announce it again
+        match handler.disconnect_event() {
+            Some(ConnectionEvent::MachineDisconnected { machine_id }) => {
+                assert_eq!(machine_id.as_str(), "laptop");
+            }
+            _ => panic!("expected MachineDisconnected"),
+        }
+        assert!(handler.disconnect_event().is_none());
+        drop(handler);
+        assert!(event_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drop_announces_unclean_disconnect() {
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        let mut handler = handler(event_tx);
+        handler.set_machine_id(MachineId::new("laptop"));
+        drop(handler);
+
+        assert!(matches!(
+            event_rx.recv().await,
+            Some(ConnectionEvent::MachineDisconnected { .. })
+        ));
+        assert!(event_rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejection_recorded_and_announced() {
+        let (event_tx, mut event_rx) = mpsc::channel(8);
+        let state = Arc::new(OrchestratorState::new(
+            kt_core::config::OrchestratorConfig::default(),
+        ));
+        // Neither loopback nor in the tailnet
+        let mut handler = ClientHandler::new(
+            state.clone(),
+            event_tx,
+            tokio_util::sync::CancellationToken::new(),
+            "192.0.2.1:40000".parse().unwrap(),
+        );
+        let key = russh_keys::key::KeyPair::generate_ed25519()
+            .unwrap()
+            .clone_public_key()
+            .unwrap();
+
+        let auth = handler.auth_publickey("agent", &key).await.unwrap();
+        assert!(matches!(auth, Auth::Reject { .. }));
+        match event_rx.try_recv() {
+            Ok(ConnectionEvent::MachineRejected {
+                hostname,
+                reason,
+                peer_addr,
+            }) => {
+                assert_eq!(hostname, None);
+                assert_eq!(reason, "Not in Tailscale network");
+                assert_eq!(peer_addr.ip().to_string(), "192.0.2.1");
+            }
+            _ => panic!("expected MachineRejected"),
+        }
+
+        // The agent retrying is counted but not announced again
+        let auth = handler.auth_publickey("agent", &key).await.unwrap();
+        assert!(matches!(auth, Auth::Reject { .. }));
+        assert!(event_rx.try_recv().is_err());
+        let rejections = state.rejections.list();
+        assert_eq!(rejections.len(), 1);
+        assert_eq!(rejections[0].peer_addr, "192.0.2.1");
+        assert_eq!(rejections[0].count, 2);
+    }
+
+    #[test]
+    fn test_sanitize_label() {
+        assert_eq!(
+            sanitize_label(" CI \u{1b}[31mrunner\n".to_string()).as_deref(),
+            Some("CI [31mrunner")
+        );
+        assert_eq!(sanitize_label("\u{7}  ".to_string()), None);
+        let long = sanitize_label("x".repeat(200)).unwrap();
+        assert_eq!(long.chars().count(), MAX_LABEL_LEN);
+    }
+}
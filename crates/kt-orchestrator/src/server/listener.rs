@@ -1,19 +1,31 @@
 //! SSH server listener
 //!
 //! Accepts incoming connections and spawns handlers for each client.
+//!
+//! When `bind_address` names Tailscale or an interface rather than an IP
+//! (see [`kt_core::net`]), the listener re-resolves it every
+//! [`REBIND_CHECK_INTERVAL`] and moves to the new address if it changed.
+//! Established connections are unaffected.
 
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use kt_core::net::{resolve_bind_address_now, BindTarget, ResolvedBind};
 use russh_keys::key::KeyPair;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc;
+use tokio::time::{Instant, MissedTickBehavior};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
 use crate::server::handler::{ClientHandler, ConnectionEvent, ServerConfig};
 use crate::state::OrchestratorState;
 
+/// How often a tailnet or interface bind address is re-resolved
+pub const REBIND_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
 /// SSH server that listens for incoming connections
 pub struct SshServer {
     /// Server configuration
@@ -42,14 +54,71 @@ impl SshServer {
         }
     }
 
-    /// Run the SSH server
-    pub async fn run(&self, bind_addr: &str) -> Result<()> {
+    /// Run the SSH server on the configured bind address
+    pub async fn run(&self) -> Result<()> {
+        let listener = self.bind_configured().await?;
+        self.serve(listener).await
+    }
+
+    /// Bind the SSH listener without accepting connections yet, so callers
+    /// can tell when the socket is ready (see [`SshServer::serve`])
+    pub async fn bind(&self, bind_addr: &str) -> Result<TcpListener> {
         let listener = TcpListener::bind(bind_addr)
             .await
             .with_context(|| format!("Failed to bind to {}", bind_addr))?;
 
         let local_addr = listener.local_addr()?;
+        self.state.set_ssh_address(local_addr);
         tracing::info!("SSH server listening on {}", local_addr);
+        Ok(listener)
+    }
+
+    /// Resolve the configured `bind_address` and bind to it (see
+    /// [`SshServer::bind`])
+    ///
+    /// Fails if it names Tailscale or an interface that has no address,
+    /// unless `bind_fallback` allows listening on loopback instead.
+    pub async fn bind_configured(&self) -> Result<TcpListener> {
+        let config = self.state.config();
+        let resolved = resolve_bind_address_now(&config.bind_address, config.bind_fallback)
+            .await
+            .with_context(|| {
+                format!(
+                    "Can't listen on bind_address = \"{}\" (set bind_fallback = \"loopback\" to listen on 127.0.0.1 until it is available)",
+                    config.bind_address
+                )
+            })?;
+        self.log_resolved(&resolved);
+        self.bind(&resolved.address).await
+    }
+
+    fn log_resolved(&self, resolved: &ResolvedBind) {
+        let bind_address = &self.state.config().bind_address;
+        match &resolved.fallback_reason {
+            Some(reason) => tracing::warn!(
+                "Can't listen on bind_address = \"{}\" ({}); falling back to {}",
+                bind_address,
+                reason,
+                resolved.address
+            ),
+            None if resolved.address != *bind_address => tracing::info!(
+                "Resolved bind_address = \"{}\" to {}",
+                bind_address,
+                resolved.address
+            ),
+            None => {}
+        }
+    }
+
+    /// Accept connections on a listener from [`SshServer::bind`] until
+    /// shutdown
+    pub async fn serve(&self, mut listener: TcpListener) -> Result<()> {
+        let dynamic = BindTarget::parse(&self.state.config().bind_address).is_dynamic();
+        let mut rebind_check = tokio::time::interval_at(
+            Instant::now() + REBIND_CHECK_INTERVAL,
+            REBIND_CHECK_INTERVAL,
+        );
+        rebind_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
 
         loop {
             tokio::select! {
@@ -59,6 +128,13 @@ impl SshServer {
                     break;
                 }
 
+                // Follow the Tailscale IP or interface address
+                _ = rebind_check.tick(), if dynamic => {
+                    if let Some(rebound) = self.rebind_if_changed(&listener).await {
+                        listener = rebound;
+                    }
+                }
+
                 // Accept new connections
                 result = listener.accept() => {
                     match result {
@@ -76,6 +152,41 @@ impl SshServer {
         Ok(())
     }
 
+    /// Re-resolve `bind_address` and bind the new address if it moved
+    async fn rebind_if_changed(&self, listener: &TcpListener) -> Option<TcpListener> {
+        let config = self.state.config();
+        let current = listener.local_addr().ok()?;
+        let resolved =
+            match resolve_bind_address_now(&config.bind_address, config.bind_fallback).await {
+                Ok(resolved) => resolved,
+                Err(e) => {
+                    tracing::debug!(
+                        "Can't re-resolve bind_address = \"{}\" ({}); still listening on {}",
+                        config.bind_address,
+                        e,
+                        current
+                    );
+                    return None;
+                }
+            };
+        let target: SocketAddr = resolved.address.parse().ok()?;
+        if target.ip() == current.ip() && (target.port() == 0 || target.port() == current.port()) {
+            return None;
+        }
+
+        self.log_resolved(&resolved);
+        match self.bind(&resolved.address).await {
+            Ok(rebound) => {
+                tracing::info!("SSH server moved from {} to {}", current, resolved.address);
+                Some(rebound)
+            }
+            Err(e) => {
+                tracing::warn!("{:#}; still listening on {}", e, current);
+                None
+            }
+        }
+    }
+
     /// Handle a new incoming connection
     async fn handle_connection(&self, socket: tokio::net::TcpStream, peer_addr: SocketAddr) {
         tracing::info!("New connection from {}", peer_addr);
@@ -88,8 +199,16 @@ impl SshServer {
         // Create a cancellation token for this specific connection
         let connection_cancel = CancellationToken::new();
 
+        // Everything logged for this connection carries the machine ID once
+        // the agent has authenticated
+        let span = tracing::info_span!(
+            "connection",
+            peer = %peer_addr,
+            machine_id = tracing::field::Empty
+        );
+
         // Spawn a task to handle this connection
-        tokio::spawn(async move {
+        let connection = async move {
             let handler = ClientHandler::new(state, event_tx, connection_cancel.clone(), peer_addr);
 
             let result = tokio::select! {
@@ -114,7 +233,8 @@ impl SshServer {
                     tracing::warn!("Connection from {} closed with error: {}", peer_addr, e);
                 }
             }
-        });
+        };
+        tokio::spawn(connection.instrument(span));
     }
 }
 
@@ -130,8 +250,7 @@ pub async fn load_or_generate_host_key(path: &std::path::Path) -> Result<KeyPair
 
         // Ensure parent directory exists
         if let Some(parent) = path.parent() {
-            tokio::fs::create_dir_all(parent)
-                .await
+            kt_core::permissions::create_private_dir_all(parent)
                 .with_context(|| format!("Failed to create directory {:?}", parent))?;
         }
 
@@ -145,26 +264,9 @@ pub async fn load_or_generate_host_key(path: &std::path::Path) -> Result<KeyPair
             .to_openssh(ssh_key::LineEnding::LF)
             .map_err(|e| anyhow::anyhow!("Failed to encode key: {}", e))?;
 
-        // Write to file with restricted permissions
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::OpenOptionsExt;
-            let mut file = std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .mode(0o600) // Owner read/write only
-                .open(path)
-                .with_context(|| format!("Failed to create key file {:?}", path))?;
-            std::io::Write::write_all(&mut file, openssh_pem.as_bytes())
-                .with_context(|| "Failed to write key file")?;
-        }
-        #[cfg(not(unix))]
-        {
-            tokio::fs::write(path, openssh_pem.as_bytes())
-                .await
-                .with_context(|| format!("Failed to write key file {:?}", path))?;
-        }
+        // Write to file with restricted permissions (owner read/write only)
+        kt_core::permissions::write_private_file(path, openssh_pem.as_bytes())
+            .with_context(|| format!("Failed to write key file {:?}", path))?;
 
         tracing::info!("Generated and saved new host key to {:?}", path);
 
@@ -175,3 +277,42 @@ pub async fn load_or_generate_host_key(path: &std::path::Path) -> Result<KeyPair
         Ok(key)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kt_core::config::{BindFallback, OrchestratorConfig};
+
+    fn server(bind_address: &str, bind_fallback: BindFallback) -> SshServer {
+        let config = OrchestratorConfig {
+            bind_address: bind_address.to_string(),
+            bind_fallback,
+            ..Default::default()
+        };
+        let (event_tx, _) = mpsc::channel(1);
+        SshServer::new(
+            KeyPair::generate_ed25519().unwrap(),
+            Arc::new(OrchestratorState::new(config)),
+            CancellationToken::new(),
+            event_tx,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_missing_tailscale_interface_fails_or_falls_back() {
+        let strict = server("tailscale-missing:0", BindFallback::None);
+        let err = strict.bind_configured().await.unwrap_err();
+        assert!(
+            format!("{:#}", err).contains("Tailscale is not running"),
+            "{:#}",
+            err
+        );
+        assert_eq!(strict.state.ssh_address(), None);
+
+        let lenient = server("tailscale-missing:0", BindFallback::Loopback);
+        let listener = lenient.bind_configured().await.unwrap();
+        let address = listener.local_addr().unwrap();
+        assert!(address.ip().is_loopback());
+        assert_eq!(lenient.state.ssh_address(), Some(address));
+    }
+}
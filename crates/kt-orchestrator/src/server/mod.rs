@@ -1,7 +1,9 @@
 //! SSH server implementation
 
+mod events;
 mod handler;
 mod listener;
 
+pub use events::handle_connection_event;
 pub use handler::{ClientHandler, ConnectionEvent, ServerConfig};
 pub use listener::{load_or_generate_host_key, SshServer};
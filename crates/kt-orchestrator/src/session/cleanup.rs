@@ -1,7 +1,8 @@
-//! Orphan session cleanup task
+//! Orphan and TTL session cleanup task
 //!
 //! This module provides a background task that periodically cleans up
-//! orphaned sessions whose grace period has expired.
+//! orphaned sessions whose grace period has expired, and sessions whose
+//! time to live ran out.
 //!
 //! # Grace Period
 //!
@@ -12,13 +13,37 @@
 //! After the grace period expires, the sessions are cleaned up:
 //! - A close command is sent to the agent to terminate the PTY
 //! - The session is removed from the session manager
+//! - IPC clients are told it closed with [`CloseReason::OrphanTimeout`]
+//!
+//! # Time to Live
+//!
+//! Sessions created with `ttl_secs` are closed the same way once it runs
+//! out, whatever they're doing, with [`CloseReason::Ttl`]. It's checked on
+//! every cleanup pass, so a session can outlive its TTL by up to
+//! [`CLEANUP_INTERVAL`].
+//!
+//! # Closing With the Owner
+//!
+//! Clients that authenticated with `close_on_disconnect` don't get the
+//! grace period: the IPC server closes their sessions with
+//! [`close_session`] as soon as they disconnect, with
+//! [`CloseReason::OwnerDisconnected`].
+//!
+//! Sessions created with `close_on_exit: false` aren't closed when their
+//! shell exits: [`keep_exited_session`] keeps them, output and all, until
+//! closed or cleaned up here.
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope};
+use kt_protocol::SessionId;
+
 use crate::connection::AgentCommand;
+use crate::session::SessionHandle;
 use crate::state::OrchestratorState;
 
 /// Grace period before orphaned sessions are cleaned up.
@@ -39,13 +64,18 @@ fn current_time_millis() -> u64 {
 /// Run the orphan cleanup task.
 ///
 /// This task periodically checks for orphaned sessions whose grace period
-/// has expired and cleans them up.
+/// has expired, and sessions whose TTL ran out, and cleans them up.
 ///
 /// # Arguments
 ///
 /// * `state` - The orchestrator state containing the session manager
+/// * `events` - IPC event channel the closed sessions are announced on
 /// * `cancel` - Cancellation token for graceful shutdown
-pub async fn run_orphan_cleanup(state: Arc<OrchestratorState>, cancel: CancellationToken) {
+pub async fn run_orphan_cleanup(
+    state: Arc<OrchestratorState>,
+    events: broadcast::Sender<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
     let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
 
     tracing::info!(
@@ -57,7 +87,8 @@ pub async fn run_orphan_cleanup(state: Arc<OrchestratorState>, cancel: Cancellat
     loop {
         tokio::select! {
             _ = interval.tick() => {
-                cleanup_expired_orphans(&state, ORPHAN_GRACE_PERIOD);
+                cleanup_expired_orphans(&state, &events, ORPHAN_GRACE_PERIOD);
+                close_expired_sessions(&state, &events, Instant::now());
             }
             _ = cancel.cancelled() => {
                 tracing::info!("Orphan cleanup task shutting down");
@@ -68,13 +99,21 @@ pub async fn run_orphan_cleanup(state: Arc<OrchestratorState>, cancel: Cancellat
 }
 
 /// Clean up orphaned sessions whose grace period has expired.
-fn cleanup_expired_orphans(state: &OrchestratorState, grace_period: Duration) {
+fn cleanup_expired_orphans(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    grace_period: Duration,
+) {
     let now = current_time_millis();
     let cutoff = now.saturating_sub(grace_period.as_millis() as u64);
     let mut cleaned_count = 0;
 
     // Use coordinator.sessions for proper state management
     for session in state.coordinator.sessions.list() {
+        // Detached sessions stay until they're reattached or closed
+        if session.is_detached() {
+            continue;
+        }
         if let Some(orphaned_at) = session.orphaned_at() {
             if orphaned_at < cutoff {
                 // Use try_close() CAS to ensure only one cleanup path wins
@@ -87,23 +126,7 @@ fn cleanup_expired_orphans(state: &OrchestratorState, grace_period: Duration) {
                         now.saturating_sub(orphaned_at),
                         session.owner_client_id
                     );
-
-                    // Send close command to agent if machine is still connected
-                    if let Some(conn) = state.coordinator.connections.get(&session.machine_id) {
-                        let command = AgentCommand::CloseSession {
-                            session_id: session.id,
-                        };
-                        // Best effort - don't block on sending
-                        if conn.command_tx.try_send(command).is_err() {
-                            tracing::warn!(
-                                "Failed to send close command for expired orphan session {}",
-                                session.id
-                            );
-                        }
-                    }
-
-                    // Remove from session manager
-                    state.coordinator.sessions.remove(session.id);
+                    close_session(state, events, &session, CloseReason::OrphanTimeout);
                     cleaned_count += 1;
                 }
                 // If try_close() returns false, another cleanup path already claimed this session
@@ -116,6 +139,100 @@ fn cleanup_expired_orphans(state: &OrchestratorState, grace_period: Duration) {
     }
 }
 
+/// Close sessions whose TTL ran out by `now`.
+fn close_expired_sessions(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    now: Instant,
+) {
+    for session in state.coordinator.sessions.list() {
+        // try_close() CAS, as for orphans
+        if session.is_expired(now) && session.try_close() {
+            tracing::info!(
+                "Closing session {}: its TTL ran out (owner: {:?})",
+                session.id,
+                session.owner_client_id
+            );
+            close_session(state, events, &session, CloseReason::Ttl);
+        }
+    }
+}
+
+/// Tell the agent to close a session claimed with `try_close()`, remove it
+/// and announce why it closed.
+pub(crate) fn close_session(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    session: &SessionHandle,
+    reason: CloseReason,
+) {
+    // Send close command to agent if machine is still connected
+    if let Some(conn) = state.coordinator.connections.get(&session.machine_id) {
+        let command = AgentCommand::CloseSession {
+            session_id: session.id,
+        };
+        // Best effort - don't block on sending
+        if conn.command_tx.try_send(command).is_err() {
+            tracing::warn!("Failed to send close command for session {}", session.id);
+        }
+    }
+
+    // Remove from session manager
+    state.coordinator.sessions.remove(session.id);
+    session.emit(
+        events,
+        &state.epoch,
+        IpcEvent::SessionClosed {
+            session_id: session.id.to_string(),
+            // Set if its shell exited while kept open
+            exit_code: session.exit_code(),
+            reason: Some(reason),
+        },
+    );
+}
+
+/// Keep a session open after its shell exited, if it was created with
+/// `close_on_exit: false`.
+///
+/// Called when the agent reports the shell of `session_id` exited, before
+/// the session is removed. Returns whether it stays; if so, IPC clients
+/// are told the first time the shell exits, with a notice.
+pub fn keep_exited_session(
+    state: &OrchestratorState,
+    events: &broadcast::Sender<IpcEventEnvelope>,
+    session_id: SessionId,
+    exit_code: Option<i32>,
+    reason: &CloseReason,
+) -> bool {
+    if !matches!(reason, CloseReason::ProcessExited { .. }) {
+        return false;
+    }
+    let Some(session) = state.coordinator.sessions.get(session_id) else {
+        return false;
+    };
+    if !session.keeps_on_exit() {
+        return false;
+    }
+
+    if session.mark_exited(exit_code) {
+        let ended = CloseReason::ProcessExited { code: exit_code };
+        tracing::info!(%session_id, ?exit_code, "Shell exited; keeping the session open");
+        session.emit(
+            events,
+            &state.epoch,
+            IpcEvent::Notice {
+                message: format!(
+                    "Session {}: {}; it stays open until closed",
+                    session_id, ended
+                ),
+                session_id: Some(session_id.to_string()),
+                client_id: session.owner_client_id.clone(),
+            },
+        );
+    }
+    true
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,4 +252,136 @@ mod tests {
         // Cleanup interval should be less than grace period
         assert!(CLEANUP_INTERVAL < ORPHAN_GRACE_PERIOD);
     }
+
+    #[test]
+    fn test_expired_orphan_announced_as_timeout() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (events, mut event_rx) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+        let session = state.coordinator.sessions.get(session_id).unwrap();
+        assert!(session.try_orphan(current_time_millis() - 60_000));
+
+        cleanup_expired_orphans(&state, &events, ORPHAN_GRACE_PERIOD);
+
+        assert!(state.coordinator.sessions.get(session_id).is_none());
+        let envelope = event_rx.try_recv().unwrap();
+        let IpcEvent::SessionClosed { reason, .. } = envelope.event else {
+            panic!("Expected SessionClosed, got {:?}", envelope.event);
+        };
+        assert_eq!(reason, Some(CloseReason::OrphanTimeout));
+    }
+
+    #[test]
+    fn test_detached_session_outlives_grace_period() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (events, mut event_rx) = broadcast::channel(16);
+        let session_id = state.coordinator.sessions.create_with_env(
+            kt_core::MachineId::new("machine-1"),
+            None,
+            vec![],
+            Some("owner".to_string()),
+        );
+        let session = state.coordinator.sessions.get(session_id).unwrap();
+        assert!(session.try_detach(current_time_millis() - 60_000));
+
+        cleanup_expired_orphans(&state, &events, ORPHAN_GRACE_PERIOD);
+
+        assert!(state.coordinator.sessions.get(session_id).is_some());
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    fn create(state: &OrchestratorState, options: crate::session::SessionOptions) -> SessionId {
+        state.coordinator.sessions.create_with_options(
+            kt_core::MachineId::new("machine-1"),
+            options,
+            Some("owner".to_string()),
+        )
+    }
+
+    #[test]
+    fn test_session_closed_when_ttl_runs_out() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (events, mut event_rx) = broadcast::channel(16);
+        let timed = create(
+            &state,
+            crate::session::SessionOptions {
+                ttl: Some(Duration::from_secs(2)),
+                ..Default::default()
+            },
+        );
+        let shell = create(&state, Default::default());
+
+        close_expired_sessions(&state, &events, Instant::now());
+        assert!(state.coordinator.sessions.get(timed).is_some());
+        assert!(event_rx.try_recv().is_err());
+
+        close_expired_sessions(&state, &events, Instant::now() + Duration::from_secs(3));
+        assert!(state.coordinator.sessions.get(timed).is_none());
+        let envelope = event_rx.try_recv().unwrap();
+        let IpcEvent::SessionClosed {
+            session_id, reason, ..
+        } = envelope.event
+        else {
+            panic!("Expected SessionClosed, got {:?}", envelope.event);
+        };
+        assert_eq!(session_id, timed.to_string());
+        assert_eq!(reason, Some(CloseReason::Ttl));
+        let close = state
+            .coordinator
+            .sessions
+            .recently_closed(&timed.to_string())
+            .and_then(|session| session.close())
+            .unwrap();
+        assert_eq!(close.reason, Some(CloseReason::Ttl));
+
+        // A shell without a TTL is left alone
+        assert!(state.coordinator.sessions.get(shell).is_some());
+        assert!(event_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_session_kept_on_exit_until_closed() {
+        let state = OrchestratorState::new(kt_core::config::OrchestratorConfig::default());
+        let (events, mut event_rx) = broadcast::channel(16);
+        let kept = create(
+            &state,
+            crate::session::SessionOptions {
+                keep_on_exit: true,
+                ..Default::default()
+            },
+        );
+        let shell = create(&state, Default::default());
+        let exited = CloseReason::ProcessExited { code: Some(2) };
+
+        assert!(keep_exited_session(&state, &events, kept, Some(2), &exited));
+        let session = state.coordinator.sessions.get(kept).unwrap();
+        assert!(session.has_exited());
+        assert_eq!(session.exit_code(), Some(2));
+        let envelope = event_rx.try_recv().unwrap();
+        assert!(
+            matches!(&envelope.event, IpcEvent::Notice { message, .. } if message.contains("code 2")),
+            "{:?}",
+            envelope.event
+        );
+
+        // Told once; other reasons to close still close it
+        assert!(keep_exited_session(&state, &events, kept, Some(2), &exited));
+        assert!(event_rx.try_recv().is_err());
+        let gone = CloseReason::Error {
+            message: "lost".to_string(),
+        };
+        assert!(!keep_exited_session(&state, &events, kept, None, &gone));
+        assert!(!keep_exited_session(
+            &state,
+            &events,
+            shell,
+            Some(0),
+            &exited
+        ));
+    }
 }
@@ -0,0 +1,268 @@
+//! Adaptive coalescing of terminal output
+//!
+//! Every chunk of output a session produces normally becomes its own
+//! [`IpcEvent::TerminalOutput`]. When IPC clients can't keep up, the event
+//! broadcast overflows and they see `Lagged` (and `EventsDropped`). Each such
+//! lag is reported to [`OutputCoalescing`]; once lags keep coming, it
+//! engages, and sessions merge consecutive output chunks into fewer, larger
+//! events until clients have been caught up for a while.
+//!
+//! Only terminal output is ever held back, and only for
+//! [`COALESCE_WINDOW`] or until [`MAX_COALESCED_BYTES`] have collected,
+//! whichever comes first. Any other event for a session flushes its pending
+//! output first, so ordering is unchanged.
+//!
+//! [`IpcEvent::TerminalOutput`]: kt_core::ipc::IpcEvent::TerminalOutput
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use kt_core::ipc::{CoalescingStatus, IpcEventEnvelope, OutputStream};
+
+use crate::state::OrchestratorState;
+
+/// Longest output is held back while coalescing
+pub const COALESCE_WINDOW: Duration = Duration::from_millis(20);
+
+/// Most output bytes merged into one event
+pub const MAX_COALESCED_BYTES: usize = 64 * 1024;
+
+/// How far back lag events count towards engaging
+const LAG_WINDOW: Duration = Duration::from_secs(10);
+
+/// Lag events within [`LAG_WINDOW`] that engage coalescing
+const ENGAGE_LAG_EVENTS: usize = 3;
+
+/// Time without lag events after which coalescing disengages
+///
+/// Longer than [`LAG_WINDOW`], so coalescing doesn't switch off just before
+/// the next lag would switch it on again.
+const DISENGAGE_AFTER: Duration = Duration::from_secs(30);
+
+/// Interval between checks for output that has waited long enough
+const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Default)]
+struct LagTracker {
+    /// Recent lag events, oldest first
+    lags: VecDeque<Instant>,
+    engaged: bool,
+    times_engaged: u64,
+}
+
+impl LagTracker {
+    fn prune(&mut self, now: Instant) {
+        while let Some(&at) = self.lags.front() {
+            if now.saturating_duration_since(at) < LAG_WINDOW {
+                break;
+            }
+            self.lags.pop_front();
+        }
+    }
+
+    /// Disengage once lags have stopped for [`DISENGAGE_AFTER`]
+    fn update(&mut self, now: Instant) {
+        if !self.engaged {
+            return;
+        }
+        let calm = match self.lags.back() {
+            Some(&last) => now.saturating_duration_since(last) >= DISENGAGE_AFTER,
+            None => true,
+        };
+        if calm {
+            self.engaged = false;
+            tracing::info!("IPC clients caught up, no longer coalescing terminal output");
+        }
+    }
+}
+
+/// Decides when terminal output is coalesced, from how often IPC clients lag
+#[derive(Debug, Default)]
+pub struct OutputCoalescing {
+    tracker: Mutex<LagTracker>,
+    /// Output events merged into others
+    coalesced_events: AtomicU64,
+    /// Some session may have output waiting to be flushed
+    pending: AtomicBool,
+}
+
+impl OutputCoalescing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LagTracker> {
+        self.tracker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record an IPC client lagging behind the event broadcast at `now`
+    pub fn record_lag(&self, now: Instant) {
+        let mut tracker = self.lock();
+        tracker.lags.push_back(now);
+        tracker.prune(now);
+        if !tracker.engaged && tracker.lags.len() >= ENGAGE_LAG_EVENTS {
+            tracker.engaged = true;
+            tracker.times_engaged += 1;
+            tracing::info!(
+                "IPC clients lagged {} times in {:?}, coalescing terminal output",
+                tracker.lags.len(),
+                LAG_WINDOW
+            );
+        }
+    }
+
+    /// Whether output should be coalesced as of `now`
+    pub fn is_engaged(&self, now: Instant) -> bool {
+        let mut tracker = self.lock();
+        tracker.update(now);
+        tracker.engaged
+    }
+
+    /// Current state, for status output
+    pub fn status(&self, now: Instant) -> CoalescingStatus {
+        let mut tracker = self.lock();
+        tracker.prune(now);
+        tracker.update(now);
+        CoalescingStatus {
+            engaged: tracker.engaged,
+            recent_lag_events: tracker.lags.len() as u32,
+            times_engaged: tracker.times_engaged,
+            coalesced_events: self.coalesced_events.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Count an output chunk merged into a pending event
+    pub(crate) fn record_merged(&self) {
+        self.coalesced_events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Note that a session holds output the flusher must send
+    pub(crate) fn mark_pending(&self) {
+        self.pending.store(true, Ordering::Release);
+    }
+
+    fn take_pending(&self) -> bool {
+        self.pending.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Output held back by a session while coalescing
+#[derive(Debug)]
+pub(crate) struct PendingOutput {
+    pub(crate) data: Vec<u8>,
+    pub(crate) stream: OutputStream,
+    /// When the first chunk arrived
+    pub(crate) since: Instant,
+}
+
+impl PendingOutput {
+    pub(crate) fn new(data: Vec<u8>, stream: OutputStream, now: Instant) -> Self {
+        Self {
+            data,
+            stream,
+            since: now,
+        }
+    }
+
+    /// Whether this must be sent now rather than wait for more
+    pub(crate) fn is_due(&self, now: Instant, max_age: Duration) -> bool {
+        self.data.len() >= MAX_COALESCED_BYTES
+            || now.saturating_duration_since(self.since) >= max_age
+    }
+}
+
+/// Run the output flusher task.
+///
+/// Sends coalesced output once it has waited [`COALESCE_WINDOW`], or right
+/// away once coalescing has disengaged, so held-back output never waits for
+/// more output to push it out. Output held for a session's owner is sent
+/// once the owner's pause ends.
+///
+/// # Arguments
+///
+/// * `state` - The orchestrator state containing the session manager
+/// * `events` - IPC event channel the output is broadcast on
+/// * `cancel` - Cancellation token for graceful shutdown
+pub async fn run_output_flusher(
+    state: Arc<OrchestratorState>,
+    events: broadcast::Sender<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if !state.coalescing.take_pending() {
+                    continue;
+                }
+                let now = Instant::now();
+                let max_age = if state.coalescing.is_engaged(now) {
+                    COALESCE_WINDOW
+                } else {
+                    Duration::ZERO
+                };
+                for session in state.coordinator.sessions.list() {
+                    if session.flush_output(&events, &state.epoch, now, max_age) {
+                        state.coalescing.mark_pending();
+                    }
+                }
+            }
+            _ = cancel.cancelled() => {
+                tracing::debug!("Output flusher shutting down");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_engages_on_repeated_lag_with_hysteresis() {
+        let start = Instant::now();
+        let coalescing = OutputCoalescing::new();
+
+        // Lags spread too thin don't engage
+        coalescing.record_lag(start);
+        coalescing.record_lag(start + secs(6));
+        coalescing.record_lag(start + secs(12));
+        assert!(!coalescing.is_engaged(start + secs(12)));
+
+        coalescing.record_lag(start + secs(13));
+        coalescing.record_lag(start + secs(14));
+        assert!(coalescing.is_engaged(start + secs(14)));
+
+        // Stays engaged well after the lag window, until calm for long enough
+        assert!(coalescing.is_engaged(start + secs(14) + LAG_WINDOW));
+        assert!(!coalescing.is_engaged(start + secs(14) + DISENGAGE_AFTER));
+
+        let status = coalescing.status(start + secs(14) + DISENGAGE_AFTER);
+        assert!(!status.engaged);
+        assert_eq!(status.times_engaged, 1);
+        assert_eq!(status.recent_lag_events, 0);
+    }
+
+    #[test]
+    fn test_pending_output_is_bounded() {
+        let start = Instant::now();
+        let mut pending = PendingOutput::new(b"abc".to_vec(), OutputStream::Stdout, start);
+        assert!(!pending.is_due(start, COALESCE_WINDOW));
+        assert!(pending.is_due(start + COALESCE_WINDOW, COALESCE_WINDOW));
+        assert!(pending.is_due(start, Duration::ZERO));
+
+        pending.data.resize(MAX_COALESCED_BYTES, b'x');
+        assert!(pending.is_due(start, COALESCE_WINDOW));
+    }
+}
@@ -0,0 +1,271 @@
+//! Per-session input rate and the runaway-input circuit breaker
+//!
+//! A script writing to a session as fast as it can swamps the agent and the
+//! connection every other session shares. Each session's input is metered
+//! over the last [`INPUT_RATE_WINDOW`], and reported in `SessionInfo`. With
+//! `max_input_rate_kib_per_min` set, a session that goes over it trips the
+//! breaker: its input is refused with `RateLimited` for
+//! [`INPUT_BLOCK_PERIOD`], and IPC clients get a `Notice` naming the client
+//! that owns it.
+//!
+//! The per-client IPC rate limits count requests, so they can't tell a
+//! typist from a script sending large writes; this counts bytes.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use kt_core::ipc::InputBreakerStatus;
+
+use super::SessionHandle;
+
+/// Window input rates are measured over
+pub const INPUT_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How long a session's input is refused once it trips the breaker
+pub const INPUT_BLOCK_PERIOD: Duration = Duration::from_secs(30);
+
+/// What to do with a session's input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputVerdict {
+    /// Forward it
+    Allowed,
+    /// Refuse it: it took the session over the limit just now
+    Tripped,
+    /// Refuse it: the session tripped the breaker a moment ago
+    Blocked,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    bytes: u64,
+    requests: u64,
+}
+
+#[derive(Debug, Default)]
+struct MeterState {
+    /// Start of the current window (None = nothing recorded yet)
+    window_start: Option<Instant>,
+    current: Counts,
+    /// Counts of the window before the current one
+    previous: Counts,
+    /// Input is refused until then
+    blocked_until: Option<Instant>,
+}
+
+impl MeterState {
+    /// Move the window along to `now`
+    fn advance(&mut self, now: Instant) {
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed >= INPUT_RATE_WINDOW * 2 {
+            self.previous = Counts::default();
+            self.current = Counts::default();
+            self.window_start = Some(now);
+        } else if elapsed >= INPUT_RATE_WINDOW {
+            self.previous = self.current;
+            self.current = Counts::default();
+            self.window_start = Some(start + INPUT_RATE_WINDOW);
+        }
+    }
+
+    /// Counts over the last [`INPUT_RATE_WINDOW`], estimated by weighting
+    /// the previous window by how much of it that still covers
+    fn per_window(&self, now: Instant) -> Counts {
+        let Some(start) = self.window_start else {
+            return Counts::default();
+        };
+        let elapsed = now.saturating_duration_since(start).min(INPUT_RATE_WINDOW);
+        let window = INPUT_RATE_WINDOW.as_millis() as u64;
+        let remaining = window - elapsed.as_millis() as u64;
+        let weigh = |previous: u64| previous.saturating_mul(remaining) / window;
+        Counts {
+            bytes: self.current.bytes + weigh(self.previous.bytes),
+            requests: self.current.requests + weigh(self.previous.requests),
+        }
+    }
+
+    fn is_blocked(&self, now: Instant) -> bool {
+        self.blocked_until.is_some_and(|until| now < until)
+    }
+}
+
+/// Input sent to one session over the last minute
+#[derive(Debug, Default)]
+pub struct InputRateMeter {
+    state: Mutex<MeterState>,
+}
+
+impl InputRateMeter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MeterState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record `len` bytes of input at `now`, unless the session's input is
+    /// refused
+    ///
+    /// Input that takes the rate over `max_bytes_per_min` trips the breaker:
+    /// it and everything for the next [`INPUT_BLOCK_PERIOD`] is refused, and
+    /// metering starts over afterwards.
+    pub fn record(&self, len: usize, max_bytes_per_min: Option<u64>, now: Instant) -> InputVerdict {
+        let mut state = self.lock();
+        if state.is_blocked(now) {
+            return InputVerdict::Blocked;
+        }
+        state.blocked_until = None;
+        state.advance(now);
+        state.current.bytes += len as u64;
+        state.current.requests += 1;
+
+        match max_bytes_per_min {
+            Some(max) if state.per_window(now).bytes > max => {
+                state.blocked_until = Some(now + INPUT_BLOCK_PERIOD);
+                state.window_start = None;
+                state.current = Counts::default();
+                state.previous = Counts::default();
+                InputVerdict::Tripped
+            }
+            _ => InputVerdict::Allowed,
+        }
+    }
+
+    /// Input bytes and requests over the last minute, as of `now`
+    pub fn per_minute(&self, now: Instant) -> (u64, u64) {
+        let mut state = self.lock();
+        state.advance(now);
+        let counts = state.per_window(now);
+        (counts.bytes, counts.requests)
+    }
+
+    /// Whether the session's input is being refused at `now`
+    pub fn is_blocked(&self, now: Instant) -> bool {
+        self.lock().is_blocked(now)
+    }
+}
+
+/// The input circuit breaker's limit and how often it tripped
+#[derive(Debug)]
+pub struct InputBreaker {
+    max_bytes_per_min: Option<u64>,
+    times_tripped: AtomicU64,
+}
+
+impl InputBreaker {
+    /// Refuse input over `max_bytes_per_min` per session (None = never)
+    pub fn new(max_bytes_per_min: Option<u64>) -> Self {
+        Self {
+            max_bytes_per_min,
+            times_tripped: AtomicU64::new(0),
+        }
+    }
+
+    /// Meter `len` bytes of input to `session` at `now`
+    pub fn check(&self, session: &SessionHandle, len: usize, now: Instant) -> InputVerdict {
+        let verdict = session
+            .input_rate()
+            .record(len, self.max_bytes_per_min, now);
+        if verdict == InputVerdict::Tripped {
+            self.times_tripped.fetch_add(1, Ordering::Relaxed);
+        }
+        verdict
+    }
+
+    /// Configured limit in KiB per minute (0 = none)
+    pub fn max_kib_per_min(&self) -> u64 {
+        self.max_bytes_per_min.map_or(0, |bytes| bytes / 1024)
+    }
+
+    /// Breaker state across `sessions` at `now`
+    pub fn status<'a>(
+        &self,
+        sessions: impl IntoIterator<Item = &'a SessionHandle>,
+        now: Instant,
+    ) -> InputBreakerStatus {
+        InputBreakerStatus {
+            max_kib_per_min: self.max_kib_per_min(),
+            blocked_sessions: sessions
+                .into_iter()
+                .filter(|session| session.input_rate().is_blocked(now))
+                .count(),
+            times_tripped: self.times_tripped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMIT: Option<u64> = Some(kt_core::config::DEFAULT_MAX_INPUT_RATE_KIB_PER_MIN * 1024);
+
+    /// Write `bytes_per_min` in `writes_per_min` equal writes for `minutes`,
+    /// returning the first verdict that isn't `Allowed` and when it came
+    fn simulate(
+        meter: &InputRateMeter,
+        bytes_per_min: u64,
+        writes_per_min: u64,
+        minutes: u64,
+        start: Instant,
+    ) -> Option<(InputVerdict, Duration)> {
+        let len = (bytes_per_min / writes_per_min) as usize;
+        let gap = INPUT_RATE_WINDOW / writes_per_min as u32;
+        (0..writes_per_min * minutes).find_map(|i| {
+            let at = gap * i as u32;
+            match meter.record(len, LIMIT, start + at) {
+                InputVerdict::Allowed => None,
+                verdict => Some((verdict, at)),
+            }
+        })
+    }
+
+    #[test]
+    fn test_runaway_writer_trips_breaker() {
+        let meter = InputRateMeter::new();
+        let start = Instant::now();
+
+        // 10 MB a minute in 4 KiB writes
+        let (verdict, at) = simulate(&meter, 10_000_000, 2_500, 2, start).unwrap();
+        assert_eq!(verdict, InputVerdict::Tripped);
+        assert!(at < INPUT_RATE_WINDOW, "tripped after {:?}", at);
+
+        let tripped = start + at;
+        assert!(meter.is_blocked(tripped));
+        assert_eq!(
+            meter.record(1, LIMIT, tripped + Duration::from_secs(1)),
+            InputVerdict::Blocked
+        );
+        assert_eq!(
+            meter.record(1, LIMIT, tripped + INPUT_BLOCK_PERIOD),
+            InputVerdict::Allowed
+        );
+    }
+
+    #[test]
+    fn test_typist_never_trips_breaker() {
+        let meter = InputRateMeter::new();
+        let start = Instant::now();
+
+        // 1 KB a minute, a keystroke or two at a time, for an hour
+        assert_eq!(simulate(&meter, 1_000, 500, 60, start), None);
+        let (bytes, requests) = meter.per_minute(start + INPUT_RATE_WINDOW * 60);
+        assert!((900..=1_000).contains(&bytes), "{} bytes", bytes);
+        assert!((450..=500).contains(&requests), "{} requests", requests);
+    }
+
+    #[test]
+    fn test_rate_decays_when_idle() {
+        let meter = InputRateMeter::new();
+        let start = Instant::now();
+        assert_eq!(meter.record(6_000, None, start), InputVerdict::Allowed);
+        assert_eq!(meter.per_minute(start), (6_000, 1));
+
+        // Half way through the next window, half of the last one counts
+        let later = start + INPUT_RATE_WINDOW + INPUT_RATE_WINDOW / 2;
+        assert_eq!(meter.per_minute(later), (3_000, 0));
+        assert_eq!(meter.per_minute(start + INPUT_RATE_WINDOW * 3), (0, 0));
+    }
+}
@@ -21,15 +21,52 @@
 //!
 //! The session manager uses `DashMap` for concurrent access, allowing multiple
 //! tasks to read/write sessions without explicit locking.
-
+//!
+//! # Session IDs
+//!
+//! Session IDs are 32 bits on the wire, and 0 is reserved for the CONTROL
+//! channel. IDs are handed out in order and wrap around after the last one,
+//! skipping IDs still used by open sessions, so a long-lived orchestrator
+//! never routes two sessions to the same ID. A warning is logged once per
+//! cycle when three quarters of the IDs have been handed out.
+//!
+//! # Recently closed sessions
+//!
+//! Removed sessions are remembered for [`RECENTLY_CLOSED_RETENTION`] (at
+//! most [`RECENTLY_CLOSED_LIMIT`] of them), so `GetSession` can still say
+//! how and why a session ended after it is gone.
+//!
+//! # Owner backpressure
+//!
+//! Output is broadcast to IPC clients, and one that can't keep up loses
+//! events rather than slowing anybody down. With `owner_backpressure` on,
+//! the owner's own subscription can pause the session's output instead
+//! ([`SubscriberGuard::pause_output`]): [`SessionHandle::emit_output`] then
+//! holds it in the session's own queue, and the output flusher sends it once
+//! the pause ends, so the owner loses nothing. The agent connection never
+//! waits, as it carries the machine's other sessions and its heartbeats too.
+//! Observers never pause output. A pause lasts until the owner has caught
+//! up or its subscription ends, so an owner that unsubscribes, detaches or
+//! disconnects can't leave the session stuck; a session holding more than
+//! [`MAX_HELD_OUTPUT`] sends what it holds anyway.
+
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
-use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
-use std::sync::Arc;
-use std::time::{Instant, SystemTime};
-
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+
+use kt_core::ipc::{
+    CloseReason, IpcEvent, IpcEventEnvelope, OutputStream, StateEpoch, TerminalSize,
+};
 use kt_core::types::MachineId;
 use kt_protocol::SessionId;
 
+use crate::session::coalesce::{PendingOutput, MAX_COALESCED_BYTES};
+use crate::session::{ActivityMonitor, InputRateMeter, OutputCoalescing, COALESCE_WINDOW};
+
 /// Session state machine states.
 ///
 /// Sessions progress through these states during their lifecycle:
@@ -70,6 +107,12 @@ impl SessionState {
     }
 }
 
+/// Most removed sessions [`SessionManager::recently_closed`] remembers
+pub const RECENTLY_CLOSED_LIMIT: usize = 64;
+
+/// How long [`SessionManager::recently_closed`] remembers a removed session
+pub const RECENTLY_CLOSED_RETENTION: Duration = Duration::from_secs(10 * 60);
+
 // Packing format for state: AtomicU64
 // - Low 8 bits: SessionState (0-3)
 // - High 56 bits: orphaned_at timestamp / 256 (milliseconds, ~8 million years range)
@@ -123,6 +166,48 @@ impl std::fmt::Display for SessionLimitExceeded {
 
 impl std::error::Error for SessionLimitExceeded {}
 
+/// Error returned when the orchestrator-wide session cap is reached
+#[derive(Debug, Clone)]
+pub struct CapacityExceeded {
+    /// Number of sessions open across all machines
+    pub current: usize,
+    /// Maximum allowed sessions across all machines
+    pub max: usize,
+}
+
+impl std::fmt::Display for CapacityExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Session capacity exceeded: {} sessions open (max {})",
+            self.current, self.max
+        )
+    }
+}
+
+impl std::error::Error for CapacityExceeded {}
+
+/// Options a session is created with.
+///
+/// Everything is optional; the default spawns the agent's default shell with
+/// no extra environment in the agent's working directory, and the session
+/// lasts until its shell exits.
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    /// Shell command (None = agent default)
+    pub shell: Option<String>,
+    /// Extra environment variables for the shell
+    pub env: Vec<(String, String)>,
+    /// Working directory to start the shell in
+    pub cwd: Option<String>,
+    /// Display name for the session
+    pub name: Option<String>,
+    /// Close the session this long after it was created (None = never)
+    pub ttl: Option<Duration>,
+    /// Keep the session open after its shell exits, until it's closed
+    pub keep_on_exit: bool,
+}
+
 /// Manages all active sessions across all connections.
 ///
 /// The session manager provides thread-safe session tracking with ownership
@@ -146,6 +231,13 @@ pub struct SessionManager {
     sessions: DashMap<SessionId, Arc<SessionHandle>>,
     /// Next session ID to allocate (starts at 1, 0 is reserved for CONTROL channel)
     next_session_id: AtomicU32,
+    /// Highest session ID handed out before wrapping around to 1
+    max_session_id: u32,
+    /// Number of sessions inserted or reserved but not yet removed.
+    /// Slots are reserved here before insertion so capacity checks can't race.
+    session_count: AtomicUsize,
+    /// Removed sessions and when they were removed, oldest first
+    recently_closed: Mutex<VecDeque<(Instant, Arc<SessionHandle>)>>,
 }
 
 /// Handle to an active session.
@@ -170,12 +262,23 @@ pub struct SessionManager {
 /// - `Creating` -> `Active`: Agent confirmed session creation
 /// - `Active` -> `Orphaned`: Client disconnected, session in grace period
 /// - `Orphaned` -> `Active`: Client reconnected and reclaimed session
+/// - `Active` -> `Orphaned` (detached): Client detached on purpose; the
+///   session is kept until it is reattached or closed, with no grace period
 /// - Any -> `Closing`: Session is being cleaned up (terminal state)
 ///
 /// # Thread Safety
 ///
 /// The `pid` field uses `AtomicU32` and `state` uses `AtomicU64` to allow
 /// lock-free updates from multiple tasks without risk of lock poisoning.
+///
+/// # Event Ordering
+///
+/// Output and close events for a session come from different tasks (the
+/// connection handler, cleanup, the health monitor, IPC requests). They all
+/// go through [`SessionHandle::emit`], which sequences and broadcasts them
+/// one at a time, so clients never see output after `SessionClosed`.
+/// Output may be held back while coalescing (see [`Self::emit_output`]),
+/// but any other event sends it first.
 pub struct SessionHandle {
     /// Session ID - unique identifier for this session
     pub id: SessionId,
@@ -184,6 +287,13 @@ pub struct SessionHandle {
     pub machine_id: MachineId,
     /// Shell command (if specified, otherwise uses default shell)
     pub shell: Option<String>,
+    /// Environment variables passed to the agent when the session was created
+    /// (after validation). Does not include the agent's own defaults.
+    pub env: Vec<(String, String)>,
+    /// Working directory the shell was started in (None = agent default)
+    pub cwd: Option<String>,
+    /// Display name given by the client that created the session
+    pub name: Option<String>,
     /// Process ID on the remote machine (0 means not set yet).
     /// Uses AtomicU32 to avoid RwLock poisoning panics.
     pid: AtomicU32,
@@ -198,6 +308,88 @@ pub struct SessionHandle {
     /// Low 8 bits: SessionState enum value
     /// High 56 bits: orphaned_at timestamp / 256 (only valid when state is Orphaned)
     state: AtomicU64,
+    /// Serializes the session's IPC events
+    emitter: Mutex<EmitState>,
+    /// Bell, activity and silence tracking for the session's output
+    monitor: ActivityMonitor,
+    /// Input bytes forwarded to the agent
+    bytes_in: AtomicU64,
+    /// Output bytes received from the agent
+    bytes_out: AtomicU64,
+    /// Input sent over the last minute, for the input circuit breaker
+    input_rate: InputRateMeter,
+    /// Whether the orphaned session was detached rather than left behind by
+    /// a disconnecting client
+    detached: AtomicBool,
+    /// Terminal size it was created with or last resized to
+    size: Mutex<Option<TerminalSize>>,
+    /// When input was last forwarded to the agent
+    last_input: Mutex<Option<Instant>>,
+    /// Connections subscribed to its output (see [`SubscriberGuard`])
+    subscribers: AtomicUsize,
+    /// Client that claimed its input (None = its owner's)
+    input_holder: Mutex<Option<String>>,
+    /// When its TTL runs out and the cleanup task closes it
+    expires_at: Option<Instant>,
+    /// Whether it stays open after its shell exits
+    keep_on_exit: bool,
+    /// Set once its shell exited, if it stays open; holds the exit code
+    exited: Mutex<Option<Option<i32>>>,
+    /// Subscriptions pausing its output (see [`SubscriberGuard::pause_output`])
+    output_pausers: AtomicUsize,
+}
+
+/// Most output bytes a session holds back for its owner before sending them
+/// anyway
+pub const MAX_HELD_OUTPUT: usize = 8 * 1024 * 1024;
+
+/// How and when a session closed, from its `SessionClosed` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionClose {
+    /// Why it closed (None if whoever closed it didn't say)
+    pub reason: Option<CloseReason>,
+    /// Exit code of the shell, if it exited
+    pub exit_code: Option<i32>,
+    /// When the event was emitted
+    pub at: SystemTime,
+}
+
+/// A connection's subscription to a session's output, counted in
+/// [`SessionHandle::subscribers`] until dropped
+pub struct SubscriberGuard {
+    session: Arc<SessionHandle>,
+    /// Whether this subscription pauses the session's output
+    pausing: bool,
+}
+
+impl SubscriberGuard {
+    /// The session subscribed to
+    pub fn session(&self) -> &Arc<SessionHandle> {
+        &self.session
+    }
+
+    /// Pause the session's output until called with `false` or dropped
+    ///
+    /// Only for its owner's subscription, while the owner falls behind (see
+    /// the module docs).
+    pub fn pause_output(&mut self, paused: bool) {
+        if paused == self.pausing {
+            return;
+        }
+        self.pausing = paused;
+        if paused {
+            self.session.output_pausers.fetch_add(1, Ordering::SeqCst);
+        } else {
+            self.session.output_pausers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.pause_output(false);
+        self.session.subscribers.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl SessionHandle {
@@ -219,22 +411,155 @@ impl SessionHandle {
         self.created_at.elapsed()
     }
 
-    /// Get creation time as ISO 8601 string
+    /// Get creation time as an ISO-8601 UTC timestamp
     pub fn created_at_iso(&self) -> String {
-        // Convert SystemTime to a simple ISO-ish format
-        match self
-            .created_at_system
-            .duration_since(SystemTime::UNIX_EPOCH)
-        {
-            Ok(duration) => {
-                let secs = duration.as_secs();
-                // Simple UTC timestamp format
-                format!("{}Z", secs)
-            }
-            Err(_) => String::new(),
+        kt_core::time::format_iso8601(self.created_at_system)
+    }
+
+    /// Bell, activity and silence tracking for this session
+    pub fn monitor(&self) -> &ActivityMonitor {
+        &self.monitor
+    }
+
+    /// Count input forwarded to the agent
+    pub fn record_input(&self, len: usize) {
+        self.bytes_in.fetch_add(len as u64, Ordering::Relaxed);
+        *self.last_input.lock().unwrap_or_else(|e| e.into_inner()) = Some(Instant::now());
+    }
+
+    /// How long the session has had neither input nor output, as of `now`
+    pub fn idle_for(&self, now: Instant) -> Duration {
+        let last_output = self.monitor.last_output();
+        let last_input = *self.last_input.lock().unwrap_or_else(|e| e.into_inner());
+        let last = last_input.map_or(last_output, |input| input.max(last_output));
+        now.saturating_duration_since(last)
+    }
+
+    /// Terminal size the session was created with or last resized to, if
+    /// known
+    pub fn size(&self) -> Option<TerminalSize> {
+        *self.size.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record the terminal size asked for
+    pub fn set_size(&self, size: TerminalSize) {
+        *self.size.lock().unwrap_or_else(|e| e.into_inner()) = Some(size);
+    }
+
+    /// Count a subscriber to the session's output until the guard is dropped
+    pub fn subscribe(self: &Arc<Self>) -> SubscriberGuard {
+        self.subscribers.fetch_add(1, Ordering::SeqCst);
+        SubscriberGuard {
+            session: Arc::clone(self),
+            pausing: false,
         }
     }
 
+    /// Connections subscribed to the session's output
+    pub fn subscribers(&self) -> usize {
+        self.subscribers.load(Ordering::SeqCst)
+    }
+
+    /// Whether its owner has paused its output
+    pub fn output_paused(&self) -> bool {
+        self.output_pausers.load(Ordering::SeqCst) > 0
+    }
+
+    /// Client whose input the session takes: the one that claimed it, or
+    /// else its owner (None = anyone's, for sessions without an owner)
+    pub fn input_holder(&self) -> Option<String> {
+        self.input_holder
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .or_else(|| self.owner_client_id.clone())
+    }
+
+    /// Give the session's input to `client_id`, or back to the owner
+    /// (None), returning whether that changed who claimed it
+    pub fn set_input_holder(&self, client_id: Option<String>) -> bool {
+        let mut holder = self.input_holder.lock().unwrap_or_else(|e| e.into_inner());
+        if *holder == client_id {
+            return false;
+        }
+        *holder = client_id;
+        true
+    }
+
+    /// Give the session's input back to the owner if `client_id` claimed
+    /// it, returning whether it had
+    pub fn release_input(&self, client_id: &str) -> bool {
+        let mut holder = self.input_holder.lock().unwrap_or_else(|e| e.into_inner());
+        if holder.as_deref() != Some(client_id) {
+            return false;
+        }
+        *holder = None;
+        true
+    }
+
+    /// When the session's TTL runs out (None = no TTL)
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    /// Whether the session's TTL ran out by `now`
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+
+    /// Whether the session stays open after its shell exits
+    pub fn keeps_on_exit(&self) -> bool {
+        self.keep_on_exit
+    }
+
+    /// Record that the session's shell exited while it stays open,
+    /// returning whether it hadn't been recorded already
+    pub fn mark_exited(&self, exit_code: Option<i32>) -> bool {
+        let mut exited = self.exited.lock().unwrap_or_else(|e| e.into_inner());
+        if exited.is_some() {
+            return false;
+        }
+        *exited = Some(exit_code);
+        true
+    }
+
+    /// Whether the shell of this still open session exited
+    pub fn has_exited(&self) -> bool {
+        self.exited
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+
+    /// Exit code of the shell of this still open session, if it exited
+    /// with one
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exited
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .flatten()
+    }
+
+    /// How the session closed, once its `SessionClosed` has been emitted
+    pub fn close(&self) -> Option<SessionClose> {
+        self.lock_emitter().close.clone()
+    }
+
+    /// Input bytes forwarded to the agent so far
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::Relaxed)
+    }
+
+    /// Output bytes received from the agent so far
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out.load(Ordering::Relaxed)
+    }
+
+    /// Input sent to the session over the last minute
+    pub fn input_rate(&self) -> &InputRateMeter {
+        &self.input_rate
+    }
+
     // ========== State Machine Methods ==========
 
     /// Get the current session state.
@@ -276,9 +601,33 @@ impl SessionHandle {
         }
 
         let new_packed = pack_state(SessionState::Active, 0);
-        self.state
+        let reclaimed = self
+            .state
             .compare_exchange(current, new_packed, Ordering::SeqCst, Ordering::SeqCst)
-            .is_ok()
+            .is_ok();
+        if reclaimed {
+            self.detached.store(false, Ordering::SeqCst);
+        }
+        reclaimed
+    }
+
+    /// Attempt to detach the session from its client (Active to Orphaned).
+    ///
+    /// Unlike an orphan left by a disconnecting client, a detached session
+    /// isn't cleaned up after the grace period or reclaimed when its client
+    /// reconnects; it stays until it is reattached with `Subscribe` or
+    /// closed.
+    pub fn try_detach(&self, time_millis: u64) -> bool {
+        if !self.try_orphan(time_millis) {
+            return false;
+        }
+        self.detached.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Check if this session was detached.
+    pub fn is_detached(&self) -> bool {
+        self.is_orphaned() && self.detached.load(Ordering::SeqCst)
     }
 
     /// Attempt to transition to Closing state from any state.
@@ -366,6 +715,198 @@ impl SessionHandle {
     pub fn is_orphaned(&self) -> bool {
         self.state() == SessionState::Orphaned
     }
+
+    // ========== Event Emission ==========
+
+    fn lock_emitter(&self) -> std::sync::MutexGuard<'_, EmitState> {
+        self.emitter.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Sequence and broadcast an event for this session.
+    ///
+    /// This is the session's single serialization point: the sequence
+    /// number is taken and the event sent under one lock, so events reach
+    /// subscribers in sequence order. `SessionClosed` is emitted at most
+    /// once, and nothing is emitted after it.
+    ///
+    /// Returns `false` if the event was dropped because the session's close
+    /// had already been emitted.
+    pub fn emit(
+        &self,
+        events: &broadcast::Sender<IpcEventEnvelope>,
+        epoch: &StateEpoch,
+        event: IpcEvent,
+    ) -> bool {
+        let mut emitter = self.lock_emitter();
+        if emitter.close.is_some() {
+            return false;
+        }
+        self.send_pending(&mut emitter, events, epoch);
+        if let IpcEvent::SessionClosed {
+            exit_code, reason, ..
+        } = &event
+        {
+            emitter.close = Some(SessionClose {
+                reason: reason.clone(),
+                exit_code: *exit_code,
+                at: SystemTime::now(),
+            });
+        }
+        // Ignore send errors (no subscribers is fine)
+        let _ = events.send(epoch.wrap_event(event));
+        true
+    }
+
+    /// Broadcast output from the session as [`IpcEvent::TerminalOutput`].
+    ///
+    /// While `coalescing` is engaged, the output is held back and merged
+    /// with what follows, to be sent by the next event or by
+    /// [`Self::flush_output`]. While its owner paused it, the output is
+    /// queued until [`Self::flush_output`] finds the pause over (see the
+    /// module docs). Returns `false` like [`Self::emit`].
+    pub fn emit_output(
+        &self,
+        events: &broadcast::Sender<IpcEventEnvelope>,
+        epoch: &StateEpoch,
+        coalescing: &OutputCoalescing,
+        data: Vec<u8>,
+        stream: OutputStream,
+    ) -> bool {
+        let now = Instant::now();
+        let mut emitter = self.lock_emitter();
+        if emitter.close.is_some() {
+            return false;
+        }
+        self.bytes_out
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        if self.output_paused() {
+            emitter.hold(data, stream, now);
+            if emitter.held_bytes > MAX_HELD_OUTPUT {
+                tracing::debug!(
+                    "Session {} held {} bytes for its owner, sending them",
+                    self.id,
+                    emitter.held_bytes
+                );
+                self.send_pending(&mut emitter, events, epoch);
+            }
+            coalescing.mark_pending();
+            return true;
+        }
+        if !coalescing.is_engaged(now) {
+            self.send_pending(&mut emitter, events, epoch);
+            let _ = events.send(epoch.wrap_event(self.output_event(data, stream)));
+            return true;
+        }
+
+        match emitter.pending.as_mut() {
+            Some(pending) if pending.stream == stream => {
+                pending.data.extend_from_slice(&data);
+                coalescing.record_merged();
+            }
+            _ => {
+                self.send_pending(&mut emitter, events, epoch);
+                emitter.pending = Some(PendingOutput::new(data, stream, now));
+            }
+        }
+        if emitter
+            .pending
+            .as_ref()
+            .is_some_and(|p| p.is_due(now, COALESCE_WINDOW))
+        {
+            self.send_pending(&mut emitter, events, epoch);
+        } else {
+            coalescing.mark_pending();
+        }
+        true
+    }
+
+    /// Send output held back for longer than `max_age`, and output held for
+    /// its owner once the pause is over.
+    ///
+    /// Returns whether output is still held back.
+    pub fn flush_output(
+        &self,
+        events: &broadcast::Sender<IpcEventEnvelope>,
+        epoch: &StateEpoch,
+        now: Instant,
+        max_age: Duration,
+    ) -> bool {
+        let mut emitter = self.lock_emitter();
+        if !emitter.held.is_empty() {
+            if self.output_paused() {
+                return true;
+            }
+            self.send_pending(&mut emitter, events, epoch);
+            return false;
+        }
+        match &emitter.pending {
+            Some(pending) if pending.is_due(now, max_age) => {
+                self.send_pending(&mut emitter, events, epoch);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    fn send_pending(
+        &self,
+        emitter: &mut EmitState,
+        events: &broadcast::Sender<IpcEventEnvelope>,
+        epoch: &StateEpoch,
+    ) {
+        // Held output is older than coalesced output: nothing is coalesced
+        // while output is held, and holding takes over what was
+        for held in emitter.held.drain(..) {
+            let event = self.output_event(held.data, held.stream);
+            let _ = events.send(epoch.wrap_event(event));
+        }
+        emitter.held_bytes = 0;
+        if let Some(pending) = emitter.pending.take() {
+            let event = self.output_event(pending.data, pending.stream);
+            let _ = events.send(epoch.wrap_event(event));
+        }
+    }
+
+    fn output_event(&self, data: Vec<u8>, stream: OutputStream) -> IpcEvent {
+        IpcEvent::TerminalOutput {
+            session_id: self.id.to_string(),
+            data,
+            stream,
+        }
+    }
+}
+
+/// What [`SessionHandle::emit`] keeps between events
+#[derive(Debug, Default)]
+struct EmitState {
+    /// `SessionClosed` has been emitted, closing it so; nothing more is
+    close: Option<SessionClose>,
+    /// Output held back while coalescing
+    pending: Option<PendingOutput>,
+    /// Output held while its owner paused it, oldest first
+    held: VecDeque<PendingOutput>,
+    /// Bytes in `held`
+    held_bytes: usize,
+}
+
+impl EmitState {
+    /// Queue output while the owner paused it, merging chunks into events
+    /// of up to [`MAX_COALESCED_BYTES`]
+    fn hold(&mut self, data: Vec<u8>, stream: OutputStream, now: Instant) {
+        if let Some(pending) = self.pending.take() {
+            self.held.push_back(pending);
+        }
+        self.held_bytes += data.len();
+        match self.held.back_mut() {
+            Some(last)
+                if last.stream == stream && last.data.len() + data.len() <= MAX_COALESCED_BYTES =>
+            {
+                last.data.extend_from_slice(&data)
+            }
+            _ => self.held.push_back(PendingOutput::new(data, stream, now)),
+        }
+    }
 }
 
 impl SessionManager {
@@ -375,12 +916,54 @@ impl SessionManager {
             sessions: DashMap::new(),
             // Start at 1 since 0 is reserved for CONTROL
             next_session_id: AtomicU32::new(1),
+            max_session_id: u32::MAX,
+            session_count: AtomicUsize::new(0),
+            recently_closed: Mutex::new(VecDeque::new()),
         }
     }
 
-    /// Allocate a new session ID
-    pub fn allocate_id(&self) -> SessionId {
-        SessionId::new(self.next_session_id.fetch_add(1, Ordering::SeqCst))
+    /// Allocate a session ID that no open session uses
+    ///
+    /// IDs count up from 1 and wrap around after the last one, skipping 0
+    /// (CONTROL) and IDs of sessions that are still open. Fails, rather
+    /// than searching forever, only if every ID is in use.
+    pub fn allocate_id(&self) -> Result<SessionId, CapacityExceeded> {
+        let space = self.max_session_id as usize;
+        let exhausted = || CapacityExceeded {
+            current: self.sessions.len(),
+            max: space,
+        };
+        if self.sessions.len() >= space {
+            return Err(exhausted());
+        }
+
+        for _ in 0..space {
+            let id = self.next_candidate_id();
+            if !self.sessions.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+        Err(exhausted())
+    }
+
+    /// The next session ID in order, whether in use or not
+    fn next_candidate_id(&self) -> SessionId {
+        let max = self.max_session_id;
+        let id = self
+            .next_session_id
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                Some(if n >= max { 1 } else { n + 1 })
+            })
+            .unwrap_or_else(|n| n);
+        if id == max / 4 * 3 {
+            tracing::warn!(
+                "Three quarters of the {} session IDs have been handed out; IDs will wrap \
+                 around to 1 after session-{}, skipping open sessions",
+                max,
+                max
+            );
+        }
+        SessionId::new(id)
     }
 
     /// Create a new session
@@ -395,21 +978,135 @@ impl SessionManager {
         shell: Option<String>,
         owner_client_id: Option<String>,
     ) -> SessionId {
-        let id = self.allocate_id();
-        let handle = Arc::new(SessionHandle {
-            id,
-            machine_id,
+        self.create_with_env(machine_id, shell, Vec::new(), owner_client_id)
+    }
+
+    /// Create a new session, recording the environment it was created with.
+    ///
+    /// The environment is stored on the handle so it can be inspected later
+    /// (e.g. via `IpcRequest::GetSessionEnv`). Callers are responsible for
+    /// validating variable names before passing them in.
+    pub fn create_with_env(
+        &self,
+        machine_id: MachineId,
+        shell: Option<String>,
+        env: Vec<(String, String)>,
+        owner_client_id: Option<String>,
+    ) -> SessionId {
+        let options = SessionOptions {
             shell,
-            pid: AtomicU32::new(0), // 0 indicates PID not yet set
-            created_at: Instant::now(),
-            created_at_system: SystemTime::now(),
-            owner_client_id,
-            // Start in Active state (state=1, timestamp=0)
-            // Note: Could start in Creating state if we want to wait for agent confirmation
-            state: AtomicU64::new(pack_state(SessionState::Active, 0)),
-        });
-        self.sessions.insert(id, handle);
-        id
+            env,
+            ..Default::default()
+        };
+        self.create_with_options(machine_id, options, owner_client_id)
+    }
+
+    /// Create a new session from a full set of creation options.
+    ///
+    /// Like `create_with_env`, the options are stored as given; validation
+    /// is the caller's responsibility.
+    ///
+    /// # Panics
+    ///
+    /// If every session ID is in use; `try_create_with_options` returns an
+    /// error instead.
+    pub fn create_with_options(
+        &self,
+        machine_id: MachineId,
+        options: SessionOptions,
+        owner_client_id: Option<String>,
+    ) -> SessionId {
+        self.try_create_with_options(machine_id, options, owner_client_id, None)
+            .expect("every session ID is in use")
+    }
+
+    /// Try to create a new session, checking against an orchestrator-wide cap.
+    ///
+    /// A slot is reserved atomically before the session is inserted, so
+    /// concurrent callers can never push the total past `max_total_sessions`.
+    /// If `max_total_sessions` is `None`, only running out of session IDs
+    /// (see [`SessionManager::allocate_id`]) fails.
+    pub fn try_create_with_options(
+        &self,
+        machine_id: MachineId,
+        options: SessionOptions,
+        owner_client_id: Option<String>,
+        max_total_sessions: Option<usize>,
+    ) -> Result<SessionId, CapacityExceeded> {
+        match max_total_sessions {
+            Some(max) => {
+                self.session_count
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                        (n < max).then_some(n + 1)
+                    })
+                    .map_err(|current| CapacityExceeded { current, max })?;
+            }
+            None => {
+                self.session_count.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let result = self.insert_session(machine_id, options, owner_client_id);
+        if result.is_err() {
+            self.session_count.fetch_sub(1, Ordering::SeqCst);
+        }
+        result
+    }
+
+    /// Build and insert a session handle under a free ID. The caller must
+    /// already have accounted for it in `session_count`.
+    fn insert_session(
+        &self,
+        machine_id: MachineId,
+        options: SessionOptions,
+        owner_client_id: Option<String>,
+    ) -> Result<SessionId, CapacityExceeded> {
+        let SessionOptions {
+            shell,
+            env,
+            cwd,
+            name,
+            ttl,
+            keep_on_exit,
+        } = options;
+        loop {
+            let id = self.allocate_id()?;
+            // Another session may have taken the ID since it was allocated
+            let Entry::Vacant(entry) = self.sessions.entry(id) else {
+                continue;
+            };
+            let now = Instant::now();
+            entry.insert(Arc::new(SessionHandle {
+                id,
+                machine_id,
+                shell,
+                env,
+                cwd,
+                name,
+                pid: AtomicU32::new(0), // 0 indicates PID not yet set
+                created_at: now,
+                created_at_system: SystemTime::now(),
+                owner_client_id,
+                // Start in Active state (state=1, timestamp=0)
+                // Note: Could start in Creating state if we want to wait for agent confirmation
+                state: AtomicU64::new(pack_state(SessionState::Active, 0)),
+                emitter: Mutex::new(EmitState::default()),
+                monitor: ActivityMonitor::new(now),
+                bytes_in: AtomicU64::new(0),
+                bytes_out: AtomicU64::new(0),
+                input_rate: InputRateMeter::new(),
+                detached: AtomicBool::new(false),
+                size: Mutex::new(None),
+                last_input: Mutex::new(None),
+                subscribers: AtomicUsize::new(0),
+                input_holder: Mutex::new(None),
+                expires_at: ttl.map(|ttl| now + ttl),
+                keep_on_exit,
+                exited: Mutex::new(None),
+                output_pausers: AtomicUsize::new(0),
+            }));
+            return Ok(id);
+        }
     }
 
     /// Try to create a new session, checking against a per-machine session limit.
@@ -463,19 +1160,52 @@ impl SessionManager {
 
     /// Look up a session by string ID
     pub fn get_by_string_id(&self, id_str: &str) -> Option<Arc<SessionHandle>> {
-        // Try to parse "session-N" format
-        let id_num = if let Some(stripped) = id_str.strip_prefix("session-") {
-            stripped.parse::<u32>().ok()?
-        } else {
-            // Try direct number parsing
-            id_str.parse::<u32>().ok()?
-        };
-        self.get(SessionId::new(id_num))
+        self.get(parse_session_id(id_str)?)
     }
 
     /// Remove a session
     pub fn remove(&self, id: SessionId) -> Option<Arc<SessionHandle>> {
-        self.sessions.remove(&id).map(|(_, v)| v)
+        let removed = self.sessions.remove(&id).map(|(_, v)| v);
+        if let Some(session) = &removed {
+            self.session_count.fetch_sub(1, Ordering::SeqCst);
+            self.remember_closed(Arc::clone(session), Instant::now());
+        }
+        removed
+    }
+
+    fn lock_recently_closed(
+        &self,
+    ) -> std::sync::MutexGuard<'_, VecDeque<(Instant, Arc<SessionHandle>)>> {
+        self.recently_closed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn remember_closed(&self, session: Arc<SessionHandle>, now: Instant) {
+        let mut closed = self.lock_recently_closed();
+        while closed.len() >= RECENTLY_CLOSED_LIMIT
+            || closed.front().is_some_and(|(removed, _)| {
+                now.saturating_duration_since(*removed) > RECENTLY_CLOSED_RETENTION
+            })
+        {
+            closed.pop_front();
+        }
+        closed.push_back((now, session));
+    }
+
+    /// A session removed in the last [`RECENTLY_CLOSED_RETENTION`], by
+    /// string ID; the last one removed if its ID was used again since
+    pub fn recently_closed(&self, id_str: &str) -> Option<Arc<SessionHandle>> {
+        let id = parse_session_id(id_str)?;
+        let now = Instant::now();
+        self.lock_recently_closed()
+            .iter()
+            .rev()
+            .take_while(|(removed, _)| {
+                now.saturating_duration_since(*removed) <= RECENTLY_CLOSED_RETENTION
+            })
+            .find(|(_, session)| session.id == id)
+            .map(|(_, session)| Arc::clone(session))
     }
 
     /// List all sessions
@@ -546,6 +1276,12 @@ impl SessionManager {
     }
 }
 
+/// Parse a session ID given as `session-<n>` or `<n>`
+fn parse_session_id(id_str: &str) -> Option<SessionId> {
+    let number = id_str.strip_prefix("session-").unwrap_or(id_str);
+    number.parse::<u32>().ok().map(SessionId::new)
+}
+
 impl Default for SessionManager {
     fn default() -> Self {
         Self::new()
@@ -567,9 +1303,9 @@ mod tests {
     fn test_session_manager_allocate_id() {
         let manager = SessionManager::new();
 
-        let id1 = manager.allocate_id();
-        let id2 = manager.allocate_id();
-        let id3 = manager.allocate_id();
+        let id1 = manager.allocate_id().unwrap();
+        let id2 = manager.allocate_id().unwrap();
+        let id3 = manager.allocate_id().unwrap();
 
         // IDs should start at 1 (0 is reserved for CONTROL)
         assert_eq!(id1.as_u32(), 1);
@@ -577,6 +1313,55 @@ mod tests {
         assert_eq!(id3.as_u32(), 3);
     }
 
+    /// A manager whose next session ID is `next`, handing out at most `max`
+    fn manager_with_ids(next: u32, max: u32) -> SessionManager {
+        SessionManager {
+            next_session_id: AtomicU32::new(next),
+            max_session_id: max,
+            ..SessionManager::new()
+        }
+    }
+
+    #[test]
+    fn test_allocate_id_wraps_around_skipping_control_and_open_sessions() {
+        let manager = manager_with_ids(u32::MAX, u32::MAX);
+        let machine_id = MachineId::new("test-machine");
+        let ids: Vec<u32> = (0..3)
+            .map(|_| manager.create(machine_id.clone(), None).as_u32())
+            .collect();
+        // Wrapping skips 0 (CONTROL)
+        assert_eq!(ids, [u32::MAX, 1, 2]);
+
+        // The next cycle skips the IDs of sessions still open
+        manager.next_session_id.store(u32::MAX, Ordering::SeqCst);
+        assert_eq!(manager.create(machine_id.clone(), None).as_u32(), 3);
+
+        // A closed session's ID is free again
+        manager.remove(SessionId::new(1));
+        manager.next_session_id.store(u32::MAX, Ordering::SeqCst);
+        assert_eq!(manager.allocate_id().unwrap().as_u32(), 1);
+    }
+
+    #[test]
+    fn test_allocate_id_fails_when_every_id_is_in_use() {
+        let manager = manager_with_ids(1, 3);
+        let machine_id = MachineId::new("test-machine");
+        for _ in 0..3 {
+            manager.create(machine_id.clone(), None);
+        }
+
+        let err = manager.allocate_id().unwrap_err();
+        assert_eq!((err.current, err.max), (3, 3));
+        let options = SessionOptions::default();
+        let result = manager.try_create_with_options(machine_id.clone(), options, None, None);
+        assert!(result.is_err());
+        assert_eq!(manager.len(), 3);
+
+        // Space frees up as sessions close
+        manager.remove(SessionId::new(2));
+        assert_eq!(manager.create(machine_id, None), SessionId::new(2));
+    }
+
     #[test]
     fn test_session_manager_create() {
         let manager = SessionManager::new();
@@ -649,6 +1434,46 @@ mod tests {
         assert!(manager.get(id2).is_some());
     }
 
+    #[test]
+    fn test_removed_session_is_remembered_with_its_close() {
+        use kt_core::ipc::CloseReason;
+
+        let manager = SessionManager::new();
+        let id = manager.create(MachineId::new("machine-1"), None);
+        let session = manager.get(id).unwrap();
+
+        let first = session.subscribe();
+        let second = session.subscribe();
+        assert_eq!(session.subscribers(), 2);
+        drop(first);
+        assert_eq!(session.subscribers(), 1);
+        drop(second);
+        assert_eq!(session.subscribers(), 0);
+
+        assert!(manager.recently_closed(&id.to_string()).is_none());
+        let (events, _rx) = broadcast::channel(16);
+        session.emit(
+            &events,
+            &StateEpoch::new(),
+            IpcEvent::SessionClosed {
+                session_id: id.to_string(),
+                exit_code: Some(3),
+                reason: Some(CloseReason::ProcessExited { code: Some(3) }),
+            },
+        );
+        manager.remove(id);
+
+        let closed = manager.recently_closed(&id.to_string()).unwrap();
+        assert!(Arc::ptr_eq(&closed, &session));
+        let close = closed.close().unwrap();
+        assert_eq!(
+            close.reason,
+            Some(CloseReason::ProcessExited { code: Some(3) })
+        );
+        assert_eq!(close.exit_code, Some(3));
+        assert!(manager.recently_closed("not-a-session").is_none());
+    }
+
     #[test]
     fn test_session_manager_remove_nonexistent() {
         let manager = SessionManager::new();
@@ -747,9 +1572,10 @@ mod tests {
         let session = manager.get(session_id).unwrap();
         let created_at = session.created_at_iso();
 
-        // Should end with 'Z' and contain a timestamp
-        assert!(created_at.ends_with('Z'));
-        assert!(!created_at.is_empty());
+        // Should be a real ISO-8601 timestamp close to now
+        let parsed = kt_core::time::parse_iso8601(&created_at).unwrap();
+        assert!(created_at.contains('T'), "{}", created_at);
+        assert!(parsed.elapsed().unwrap_or_default() < std::time::Duration::from_secs(5));
     }
 
     #[test]
@@ -815,6 +1641,113 @@ mod tests {
 
         let session = manager.get(session_id).expect("Session should exist");
         assert_eq!(session.owner_client_id.as_deref(), Some(client_id));
+        assert!(session.env.is_empty());
+    }
+
+    #[test]
+    fn test_session_manager_create_with_env() {
+        let manager = SessionManager::new();
+        let env = vec![
+            ("EDITOR".to_string(), "vim".to_string()),
+            ("API_TOKEN".to_string(), "secret".to_string()),
+        ];
+
+        let session_id = manager.create_with_env(
+            MachineId::new("test-machine"),
+            None,
+            env.clone(),
+            Some("client-123".to_string()),
+        );
+
+        let session = manager.get(session_id).expect("Session should exist");
+        assert_eq!(session.env, env);
+        assert_eq!(session.owner_client_id.as_deref(), Some("client-123"));
+    }
+
+    #[test]
+    fn test_session_manager_create_with_options() {
+        let manager = SessionManager::new();
+        let options = SessionOptions {
+            shell: Some("/bin/zsh".to_string()),
+            env: vec![("EDITOR".to_string(), "vim".to_string())],
+            cwd: Some("/srv/app".to_string()),
+            name: Some("build".to_string()),
+            ..Default::default()
+        };
+
+        let session_id = manager.create_with_options(MachineId::new("test-machine"), options, None);
+
+        let session = manager.get(session_id).expect("Session should exist");
+        assert_eq!(session.shell.as_deref(), Some("/bin/zsh"));
+        assert_eq!(session.env.len(), 1);
+        assert_eq!(session.cwd.as_deref(), Some("/srv/app"));
+        assert_eq!(session.name.as_deref(), Some("build"));
+        assert!(session.owner_client_id.is_none());
+    }
+
+    #[test]
+    fn test_session_manager_try_create_respects_total_cap() {
+        let manager = SessionManager::new();
+        let machine_a = MachineId::new("machine-a");
+        let machine_b = MachineId::new("machine-b");
+
+        let first = manager
+            .try_create_with_options(machine_a.clone(), SessionOptions::default(), None, Some(2))
+            .expect("first session fits");
+        manager
+            .try_create_with_options(machine_b.clone(), SessionOptions::default(), None, Some(2))
+            .expect("second session fits");
+
+        // The cap applies across machines
+        let err = manager
+            .try_create_with_options(machine_a.clone(), SessionOptions::default(), None, Some(2))
+            .unwrap_err();
+        assert_eq!(err.current, 2);
+        assert_eq!(err.max, 2);
+        assert_eq!(manager.len(), 2);
+
+        // Removing a session frees its slot
+        manager.remove(first);
+        assert!(manager
+            .try_create_with_options(machine_a, SessionOptions::default(), None, Some(2))
+            .is_ok());
+
+        // No cap means no limit
+        assert!(manager
+            .try_create_with_options(machine_b, SessionOptions::default(), None, None)
+            .is_ok());
+        assert_eq!(manager.len(), 3);
+    }
+
+    #[test]
+    fn test_session_manager_try_create_concurrent() {
+        let manager = Arc::new(SessionManager::new());
+        let max = 5;
+
+        let handles: Vec<_> = (0..32)
+            .map(|i| {
+                let manager = Arc::clone(&manager);
+                std::thread::spawn(move || {
+                    manager
+                        .try_create_with_options(
+                            MachineId::new(format!("machine-{}", i % 4)),
+                            SessionOptions::default(),
+                            None,
+                            Some(max),
+                        )
+                        .is_ok()
+                })
+            })
+            .collect();
+
+        let created = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .filter(|ok| *ok)
+            .count();
+
+        assert_eq!(created, max);
+        assert_eq!(manager.len(), max);
     }
 
     // ========== State Machine Tests ==========
@@ -888,6 +1821,26 @@ mod tests {
         assert_eq!(session.state(), SessionState::Active);
     }
 
+    #[test]
+    fn test_session_detach_and_reattach() {
+        let manager = SessionManager::new();
+        let session_id = manager.create(MachineId::new("test"), None);
+        let session = manager.get(session_id).unwrap();
+
+        assert!(session.try_detach(1000));
+        assert!(session.is_orphaned());
+        assert!(session.is_detached());
+        // Already detached
+        assert!(!session.try_detach(2000));
+
+        assert!(session.try_reclaim());
+        assert!(!session.is_detached());
+
+        // A plain orphan isn't detached
+        assert!(session.try_orphan(3000));
+        assert!(!session.is_detached());
+    }
+
     #[test]
     fn test_session_try_close_from_active() {
         let manager = SessionManager::new();
@@ -926,6 +1879,181 @@ mod tests {
         assert_eq!(session.state(), SessionState::Closing);
     }
 
+    #[test]
+    fn test_session_counts_traffic() {
+        use kt_core::ipc::OutputStream;
+
+        let manager = SessionManager::new();
+        let session_id = manager.create(MachineId::new("test"), None);
+        let session = manager.get(session_id).unwrap();
+        let epoch = StateEpoch::new();
+        let (events, _rx) = broadcast::channel(16);
+        let coalescing = OutputCoalescing::new();
+
+        session.record_input(3);
+        let output = |data: &[u8]| {
+            session.emit_output(
+                &events,
+                &epoch,
+                &coalescing,
+                data.to_vec(),
+                OutputStream::Stdout,
+            )
+        };
+        assert!(output(b"hello"));
+        assert_eq!((session.bytes_in(), session.bytes_out()), (3, 5));
+
+        // Output after the close is dropped and not counted
+        session.emit(
+            &events,
+            &epoch,
+            IpcEvent::SessionClosed {
+                session_id: session_id.to_string(),
+                exit_code: None,
+                reason: None,
+            },
+        );
+        assert!(!output(b"late"));
+        assert_eq!(session.bytes_out(), 5);
+    }
+
+    #[test]
+    fn test_coalesced_output_is_merged_and_flushed_in_order() {
+        use kt_core::ipc::{ActivityKind, OutputStream};
+
+        let manager = SessionManager::new();
+        let session_id = manager.create(MachineId::new("test"), None);
+        let session = manager.get(session_id).unwrap();
+        let epoch = StateEpoch::new();
+        let (events, mut rx) = broadcast::channel(16);
+        let coalescing = OutputCoalescing::new();
+        let now = Instant::now();
+        for _ in 0..3 {
+            coalescing.record_lag(now);
+        }
+        assert!(coalescing.is_engaged(now));
+
+        let output = |data: &[u8]| {
+            session.emit_output(
+                &events,
+                &epoch,
+                &coalescing,
+                data.to_vec(),
+                OutputStream::Stdout,
+            )
+        };
+        assert!(output(b"ab"));
+        assert!(output(b"cd"));
+        assert!(rx.try_recv().is_err(), "output should be held back");
+
+        // Another event sends the pending output first
+        session.emit(
+            &events,
+            &epoch,
+            IpcEvent::SessionActivity {
+                session_id: session_id.to_string(),
+                kind: ActivityKind::Bell,
+            },
+        );
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::TerminalOutput { ref data, .. } if data == b"abcd"
+        ));
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::SessionActivity { .. }
+        ));
+
+        // The flusher sends output once it's old enough
+        assert!(output(b"ef"));
+        assert!(session.flush_output(&events, &epoch, Instant::now(), COALESCE_WINDOW * 10));
+        assert!(!session.flush_output(&events, &epoch, Instant::now(), Duration::ZERO));
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::TerminalOutput { ref data, .. } if data == b"ef"
+        ));
+        assert_eq!(coalescing.status(Instant::now()).coalesced_events, 1);
+    }
+
+    #[test]
+    fn test_no_output_emitted_after_session_closed() {
+        use kt_core::ipc::{CloseReason, OutputStream};
+
+        let manager = SessionManager::new();
+        let session_id = manager.create(MachineId::new("test"), None);
+        let session = manager.get(session_id).unwrap();
+        let epoch = Arc::new(StateEpoch::new());
+        let (events, mut rx) = broadcast::channel(100_000);
+
+        let output = {
+            let (session, epoch, events) = (session.clone(), epoch.clone(), events.clone());
+            std::thread::spawn(move || {
+                let mut emitted = 0;
+                for i in 0..20_000u32 {
+                    let event = IpcEvent::TerminalOutput {
+                        session_id: session.id.to_string(),
+                        data: i.to_be_bytes().to_vec(),
+                        stream: OutputStream::Stdout,
+                    };
+                    if session.emit(&events, &epoch, event) {
+                        emitted += 1;
+                    }
+                }
+                emitted
+            })
+        };
+        // Two cleanup paths race to close the session mid-output
+        let closers: Vec<_> = [CloseReason::UserRequested, CloseReason::MachineDisconnected]
+            .into_iter()
+            .map(|reason| {
+                let (session, epoch, events) = (session.clone(), epoch.clone(), events.clone());
+                std::thread::spawn(move || {
+                    std::thread::sleep(std::time::Duration::from_millis(2));
+                    session.emit(
+                        &events,
+                        &epoch,
+                        IpcEvent::SessionClosed {
+                            session_id: session.id.to_string(),
+                            exit_code: None,
+                            reason: Some(reason),
+                        },
+                    )
+                })
+            })
+            .collect();
+        let emitted = output.join().unwrap();
+        let closes = closers
+            .into_iter()
+            .map(|closer| closer.join().unwrap())
+            .filter(|&emitted| emitted)
+            .count();
+        assert_eq!(closes, 1, "SessionClosed must be emitted exactly once");
+        drop(events);
+
+        let mut received = Vec::new();
+        while let Ok(envelope) = rx.try_recv() {
+            received.push(envelope);
+        }
+        assert!(received.windows(2).all(|w| w[0].seq < w[1].seq));
+        let close_at = received
+            .iter()
+            .position(|e| matches!(e.event, IpcEvent::SessionClosed { .. }))
+            .unwrap();
+        assert_eq!(close_at, received.len() - 1, "output after SessionClosed");
+        // Everything emitted before the close was delivered, in order
+        assert_eq!(close_at, emitted);
+        let chunks: Vec<u32> = received[..close_at]
+            .iter()
+            .map(|e| match &e.event {
+                IpcEvent::TerminalOutput { data, .. } => {
+                    u32::from_be_bytes(data[..].try_into().unwrap())
+                }
+                other => panic!("Expected TerminalOutput, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(chunks, (0..emitted as u32).collect::<Vec<_>>());
+    }
+
     #[test]
     fn test_session_no_transition_from_closing() {
         let manager = SessionManager::new();
@@ -1035,4 +2163,107 @@ mod tests {
         let packed_orphaned = pack_state(SessionState::Orphaned, time_millis);
         assert!(unpack_orphaned_at(packed_orphaned) > 0);
     }
+
+    #[test]
+    fn test_paused_output_is_held_until_the_pause_ends() {
+        use kt_core::ipc::{CloseReason, OutputStream};
+
+        let manager = SessionManager::new();
+        let id = manager.create(MachineId::new("machine-1"), None);
+        let session = manager.get(id).unwrap();
+        let epoch = StateEpoch::new();
+        let (events, mut rx) = broadcast::channel(16);
+        let coalescing = OutputCoalescing::new();
+        let output = |data: &[u8]| {
+            session.emit_output(
+                &events,
+                &epoch,
+                &coalescing,
+                data.to_vec(),
+                OutputStream::Stdout,
+            )
+        };
+
+        let mut owner = session.subscribe();
+        let mut second = session.subscribe();
+        owner.pause_output(true);
+        second.pause_output(true);
+        assert!(output(b"ab"));
+        assert!(output(b"cd"));
+        assert!(rx.try_recv().is_err(), "output should be held");
+
+        // Held for as long as any subscription pauses it, however long
+        second.pause_output(false);
+        assert!(session.flush_output(&events, &epoch, Instant::now(), Duration::ZERO));
+        assert!(rx.try_recv().is_err());
+
+        // Dropping the last pausing subscription lets the flusher send it
+        drop(owner);
+        assert!(!session.output_paused());
+        assert!(!session.flush_output(&events, &epoch, Instant::now(), Duration::ZERO));
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::TerminalOutput { ref data, .. } if data == b"abcd"
+        ));
+
+        // Closing sends held output first
+        second.pause_output(true);
+        assert!(output(b"ef"));
+        session.emit(
+            &events,
+            &epoch,
+            IpcEvent::SessionClosed {
+                session_id: id.to_string(),
+                exit_code: None,
+                reason: Some(CloseReason::UserRequested),
+            },
+        );
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::TerminalOutput { ref data, .. } if data == b"ef"
+        ));
+        assert!(matches!(
+            rx.try_recv().unwrap().event,
+            IpcEvent::SessionClosed { .. }
+        ));
+    }
+
+    #[test]
+    fn test_held_output_is_bounded() {
+        use kt_core::ipc::OutputStream;
+
+        let manager = SessionManager::new();
+        let id = manager.create(MachineId::new("machine-1"), None);
+        let session = manager.get(id).unwrap();
+        let epoch = StateEpoch::new();
+        let (events, mut rx) = broadcast::channel(1024);
+        let coalescing = OutputCoalescing::new();
+
+        let output = || {
+            let chunk = vec![b'x'; MAX_COALESCED_BYTES];
+            session.emit_output(&events, &epoch, &coalescing, chunk, OutputStream::Stdout)
+        };
+
+        let mut owner = session.subscribe();
+        owner.pause_output(true);
+        for _ in 0..MAX_HELD_OUTPUT / MAX_COALESCED_BYTES {
+            output();
+        }
+        assert!(rx.try_recv().is_err());
+
+        // One more chunk sends everything held, in events of bounded size
+        output();
+        let mut sent = 0;
+        while let Ok(envelope) = rx.try_recv() {
+            match envelope.event {
+                IpcEvent::TerminalOutput { data, .. } => {
+                    assert!(data.len() <= MAX_COALESCED_BYTES);
+                    sent += data.len();
+                }
+                other => panic!("expected output, got {:?}", other),
+            }
+        }
+        assert_eq!(sent, MAX_HELD_OUTPUT + MAX_COALESCED_BYTES);
+        assert!(session.output_paused());
+    }
 }
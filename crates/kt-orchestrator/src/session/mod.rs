@@ -1,9 +1,27 @@
 //! Session management
 
 mod cleanup;
+mod coalesce;
+mod input_rate;
 mod manager;
+mod monitor;
 mod multiplexer;
+mod resize;
 
-pub use cleanup::{run_orphan_cleanup, ORPHAN_GRACE_PERIOD};
-pub use manager::{SessionHandle, SessionLimitExceeded, SessionManager, SessionState};
+pub(crate) use cleanup::close_session;
+pub use cleanup::{keep_exited_session, run_orphan_cleanup, ORPHAN_GRACE_PERIOD};
+pub use coalesce::{run_output_flusher, OutputCoalescing, COALESCE_WINDOW, MAX_COALESCED_BYTES};
+pub use input_rate::{
+    InputBreaker, InputRateMeter, InputVerdict, INPUT_BLOCK_PERIOD, INPUT_RATE_WINDOW,
+};
+pub use manager::{
+    CapacityExceeded, SessionClose, SessionHandle, SessionLimitExceeded, SessionManager,
+    SessionOptions, SessionState, SubscriberGuard, MAX_HELD_OUTPUT, RECENTLY_CLOSED_LIMIT,
+    RECENTLY_CLOSED_RETENTION,
+};
+pub use monitor::{
+    run_silence_monitor, ActivityMonitor, MonitorSettings, ACTIVITY_EVENT_INTERVAL,
+    ACTIVITY_QUIET_PERIOD,
+};
 pub use multiplexer::SessionMultiplexer;
+pub use resize::{run_resize_flusher, ResizeDebouncer};
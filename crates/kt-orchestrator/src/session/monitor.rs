@@ -0,0 +1,250 @@
+//! Bell, activity and silence detection for sessions
+//!
+//! Every session watches its own output for the terminal bell (BEL, 0x07).
+//! Clients can additionally ask, via `SetSessionMonitor`, to hear when
+//! output resumes after a quiet spell (activity) or when a session has
+//! produced nothing for a while (silence).
+//!
+//! Detections are announced as [`IpcEvent::SessionActivity`]. Each kind is
+//! rate-limited per session so a shell spamming bells or output doesn't
+//! flood IPC clients.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use kt_core::ipc::{ActivityKind, IpcEvent, IpcEventEnvelope};
+
+use crate::state::OrchestratorState;
+
+/// How long a session must be quiet before new output counts as activity.
+pub const ACTIVITY_QUIET_PERIOD: Duration = Duration::from_secs(5);
+
+/// Minimum time between two events of the same kind for one session.
+pub const ACTIVITY_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Interval between silence checks.
+const SILENCE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The terminal bell character.
+const BEL: u8 = 0x07;
+
+/// What a client asked to be told about for a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MonitorSettings {
+    /// Report output that follows a quiet spell
+    pub activity: bool,
+    /// Report this much time without output (once per silence)
+    pub silence: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct MonitorState {
+    settings: MonitorSettings,
+    last_output: Instant,
+    silence_reported: bool,
+    last_bell: Option<Instant>,
+    last_activity: Option<Instant>,
+}
+
+/// Per-session bell, activity and silence tracker
+#[derive(Debug)]
+pub struct ActivityMonitor {
+    state: Mutex<MonitorState>,
+}
+
+impl ActivityMonitor {
+    /// Create a monitor for a session that started at `now`
+    pub fn new(now: Instant) -> Self {
+        Self {
+            state: Mutex::new(MonitorState {
+                settings: MonitorSettings::default(),
+                last_output: now,
+                silence_reported: false,
+                last_bell: None,
+                last_activity: None,
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MonitorState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Current settings
+    pub fn settings(&self) -> MonitorSettings {
+        self.lock().settings
+    }
+
+    /// Replace the settings; a pending silence can be reported again
+    pub fn set_settings(&self, settings: MonitorSettings) {
+        let mut state = self.lock();
+        state.settings = settings;
+        state.silence_reported = false;
+    }
+
+    /// When the session last produced output (when it started if it hasn't)
+    pub fn last_output(&self) -> Instant {
+        self.lock().last_output
+    }
+
+    /// Record output received at `now`, returning what to report for it
+    pub fn on_output(&self, data: &[u8], now: Instant) -> Vec<ActivityKind> {
+        let mut state = self.lock();
+        let mut kinds = Vec::new();
+
+        if data.contains(&BEL) && due(state.last_bell, now) {
+            state.last_bell = Some(now);
+            kinds.push(ActivityKind::Bell);
+        }
+
+        let quiet = now.saturating_duration_since(state.last_output) >= ACTIVITY_QUIET_PERIOD;
+        if state.settings.activity && quiet && due(state.last_activity, now) {
+            state.last_activity = Some(now);
+            kinds.push(ActivityKind::Activity);
+        }
+
+        state.last_output = now;
+        state.silence_reported = false;
+        kinds
+    }
+
+    /// Whether the session has just gone silent as of `now`
+    ///
+    /// Reports each silence once; output starts a new one.
+    pub fn check_silence(&self, now: Instant) -> Option<ActivityKind> {
+        let mut state = self.lock();
+        let silence = state.settings.silence?;
+        if state.silence_reported || now.saturating_duration_since(state.last_output) < silence {
+            return None;
+        }
+        state.silence_reported = true;
+        Some(ActivityKind::Silence)
+    }
+}
+
+/// Whether a rate-limited event last sent at `last` may be sent again
+fn due(last: Option<Instant>, now: Instant) -> bool {
+    match last {
+        Some(last) => now.saturating_duration_since(last) >= ACTIVITY_EVENT_INTERVAL,
+        None => true,
+    }
+}
+
+/// Run the silence monitor task.
+///
+/// Periodically checks every session with a silence period configured and
+/// announces the ones that have gone quiet.
+///
+/// # Arguments
+///
+/// * `state` - The orchestrator state containing the session manager
+/// * `events` - IPC event channel the silences are announced on
+/// * `cancel` - Cancellation token for graceful shutdown
+pub async fn run_silence_monitor(
+    state: Arc<OrchestratorState>,
+    events: broadcast::Sender<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
+    let mut interval = tokio::time::interval(SILENCE_CHECK_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let now = Instant::now();
+                for session in state.coordinator.sessions.list() {
+                    if let Some(kind) = session.monitor().check_silence(now) {
+                        session.emit(
+                            &events,
+                            &state.epoch,
+                            IpcEvent::SessionActivity {
+                                session_id: session.id.to_string(),
+                                kind,
+                            },
+                        );
+                    }
+                }
+            }
+            _ = cancel.cancelled() => {
+                tracing::debug!("Silence monitor shutting down");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(n: u64) -> Duration {
+        Duration::from_secs(n)
+    }
+
+    #[test]
+    fn test_bell_detected_and_rate_limited() {
+        let start = Instant::now();
+        let monitor = ActivityMonitor::new(start);
+
+        assert!(monitor.on_output(b"no bell here", start).is_empty());
+        assert_eq!(
+            monitor.on_output(b"ding\x07", start + Duration::from_millis(10)),
+            vec![ActivityKind::Bell]
+        );
+        assert!(monitor
+            .on_output(b"\x07", start + Duration::from_millis(500))
+            .is_empty());
+        assert_eq!(
+            monitor.on_output(b"\x07", start + Duration::from_millis(1010)),
+            vec![ActivityKind::Bell]
+        );
+    }
+
+    #[test]
+    fn test_activity_only_after_quiet_period_and_when_enabled() {
+        let start = Instant::now();
+        let monitor = ActivityMonitor::new(start);
+
+        // Not enabled
+        assert!(monitor.on_output(b"x", start + secs(10)).is_empty());
+
+        monitor.set_settings(MonitorSettings {
+            activity: true,
+            silence: None,
+        });
+        // Still busy: no activity
+        assert!(monitor.on_output(b"x", start + secs(11)).is_empty());
+        // After a quiet spell
+        assert_eq!(
+            monitor.on_output(b"x", start + secs(20)),
+            vec![ActivityKind::Activity]
+        );
+    }
+
+    #[test]
+    fn test_silence_reported_once_per_quiet_spell() {
+        let start = Instant::now();
+        let monitor = ActivityMonitor::new(start);
+        assert_eq!(monitor.check_silence(start + secs(60)), None);
+
+        monitor.set_settings(MonitorSettings {
+            activity: false,
+            silence: Some(secs(30)),
+        });
+        assert_eq!(monitor.check_silence(start + secs(10)), None);
+        assert_eq!(
+            monitor.check_silence(start + secs(30)),
+            Some(ActivityKind::Silence)
+        );
+        assert_eq!(monitor.check_silence(start + secs(40)), None);
+
+        monitor.on_output(b"x", start + secs(50));
+        assert_eq!(monitor.check_silence(start + secs(70)), None);
+        assert_eq!(
+            monitor.check_silence(start + secs(80)),
+            Some(ActivityKind::Silence)
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Debouncing of terminal resizes
+//!
+//! Dragging a window edge makes IPC clients send dozens of `SessionResize`
+//! requests per second. Forwarding each one costs a protocol message and an
+//! `ioctl` on the agent, and the PTY re-flows its output every time. Instead,
+//! [`ResizeDebouncer`] forwards at most one resize per session every
+//! `resize_debounce` (see [`OrchestratorConfig`]). A resize arriving sooner
+//! replaces any size already waiting, and [`run_resize_flusher`] sends the
+//! waiting size once the interval is up, so the final size always arrives.
+//!
+//! [`OrchestratorConfig`]: kt_core::config::OrchestratorConfig
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio_util::sync::CancellationToken;
+
+use kt_protocol::{SessionId, TerminalSize};
+
+use crate::connection::AgentCommand;
+use crate::state::OrchestratorState;
+
+/// Interval between checks for resizes that have waited long enough
+const FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug)]
+struct ResizeSlot {
+    /// When a resize was last forwarded
+    last_sent: Instant,
+    /// Latest size held back since then
+    pending: Option<TerminalSize>,
+}
+
+/// Limits how often resizes are forwarded to agents, per session
+///
+/// Sessions are keyed by `K`, so IPC clients that only have session IDs as
+/// strings can debounce their requests too.
+#[derive(Debug)]
+pub struct ResizeDebouncer<K = SessionId> {
+    interval: Duration,
+    sessions: Mutex<HashMap<K, ResizeSlot>>,
+}
+
+impl<K: Eq + Hash + Clone> ResizeDebouncer<K> {
+    /// Forward at most one resize per session every `interval` (zero
+    /// forwards every resize)
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<K, ResizeSlot>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record a resize of `session_id` to `size` at `now`
+    ///
+    /// Returns the size if it should be forwarded right away, or `None` if it
+    /// is held back for [`Self::take_due`].
+    pub fn submit(&self, session_id: K, size: TerminalSize, now: Instant) -> Option<TerminalSize> {
+        if self.interval.is_zero() {
+            return Some(size);
+        }
+        let mut sessions = self.lock();
+        match sessions.get_mut(&session_id) {
+            Some(slot) if now.saturating_duration_since(slot.last_sent) < self.interval => {
+                slot.pending = Some(size);
+                None
+            }
+            _ => {
+                let slot = ResizeSlot {
+                    last_sent: now,
+                    pending: None,
+                };
+                sessions.insert(session_id, slot);
+                Some(size)
+            }
+        }
+    }
+
+    /// Take the held-back sizes that are due to be forwarded as of `now`
+    ///
+    /// Sessions with nothing held back are forgotten once their interval is
+    /// up, as their next resize is forwarded right away anyway.
+    pub fn take_due(&self, now: Instant) -> Vec<(K, TerminalSize)> {
+        let mut due = Vec::new();
+        self.lock().retain(|session_id, slot| {
+            if now.saturating_duration_since(slot.last_sent) < self.interval {
+                return true;
+            }
+            match slot.pending.take() {
+                Some(size) => {
+                    due.push((session_id.clone(), size));
+                    slot.last_sent = now;
+                    true
+                }
+                None => false,
+            }
+        });
+        due
+    }
+}
+
+/// Run the resize flusher task.
+///
+/// Forwards resizes held back by [`ResizeDebouncer`] once their session's
+/// interval is up.
+///
+/// # Arguments
+///
+/// * `state` - The orchestrator state containing the debouncer and sessions
+/// * `cancel` - Cancellation token for graceful shutdown
+pub async fn run_resize_flusher(state: Arc<OrchestratorState>, cancel: CancellationToken) {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                for (session_id, size) in state.resizes.take_due(Instant::now()) {
+                    forward_resize(&state, session_id, size).await;
+                }
+            }
+            _ = cancel.cancelled() => {
+                tracing::debug!("Resize flusher shutting down");
+                break;
+            }
+        }
+    }
+}
+
+/// Send a held-back resize to the agent, unless the session has gone
+async fn forward_resize(state: &OrchestratorState, session_id: SessionId, size: TerminalSize) {
+    let Some(session) = state.coordinator.sessions.get(session_id) else {
+        return;
+    };
+    let Some(conn) = state.coordinator.connections.get(&session.machine_id) else {
+        return;
+    };
+    let command = AgentCommand::SessionResize { session_id, size };
+    if let Err(e) = conn.command_tx.send(command).await {
+        tracing::warn!("Failed to send resize for session {}: {}", session_id, e);
+        return;
+    }
+    tracing::debug!(
+        "Resized session {} to {}x{}",
+        session_id,
+        size.cols,
+        size.rows
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(50);
+
+    fn size(cols: u16) -> TerminalSize {
+        TerminalSize::new(24, cols)
+    }
+
+    #[test]
+    fn test_burst_is_debounced_and_last_size_delivered() {
+        let debouncer = ResizeDebouncer::new(INTERVAL);
+        let session_id = SessionId::new(1);
+        let start = Instant::now();
+        let mut forwarded = Vec::new();
+
+        // 100 resizes, one every millisecond, with the flusher ticking every 10
+        for i in 0..100u16 {
+            let now = start + Duration::from_millis(i as u64);
+            forwarded.extend(debouncer.submit(session_id, size(80 + i), now));
+            if i % 10 == 0 {
+                forwarded.extend(debouncer.take_due(now).into_iter().map(|(_, s)| s));
+            }
+        }
+        let end = start + Duration::from_millis(100) + INTERVAL;
+        forwarded.extend(debouncer.take_due(end).into_iter().map(|(_, s)| s));
+
+        assert!(
+            forwarded.len() <= 5,
+            "forwarded {} resizes",
+            forwarded.len()
+        );
+        assert_eq!(forwarded.first(), Some(&size(80)));
+        assert_eq!(forwarded.last(), Some(&size(179)));
+        assert!(debouncer.take_due(end + INTERVAL).is_empty());
+        assert!(debouncer.lock().is_empty());
+    }
+
+    #[test]
+    fn test_sessions_are_debounced_separately() {
+        let debouncer = ResizeDebouncer::new(INTERVAL);
+        let now = Instant::now();
+        assert!(debouncer.submit(SessionId::new(1), size(80), now).is_some());
+        assert!(debouncer.submit(SessionId::new(2), size(90), now).is_some());
+        assert!(debouncer.submit(SessionId::new(1), size(81), now).is_none());
+
+        // Nothing is due before the interval is up
+        assert!(debouncer.take_due(now).is_empty());
+        let due = debouncer.take_due(now + INTERVAL);
+        assert_eq!(due, vec![(SessionId::new(1), size(81))]);
+    }
+
+    #[test]
+    fn test_zero_interval_forwards_everything() {
+        let debouncer = ResizeDebouncer::new(Duration::ZERO);
+        let now = Instant::now();
+        for cols in 80..90 {
+            assert_eq!(
+                debouncer.submit(SessionId::new(1), size(cols), now),
+                Some(size(cols))
+            );
+        }
+        assert!(debouncer.take_due(now).is_empty());
+    }
+}
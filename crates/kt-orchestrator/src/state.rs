@@ -1,13 +1,20 @@
 //! Global orchestrator state
 
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
 
 use kt_core::config::OrchestratorConfig;
-use kt_core::ipc::StateEpoch;
+use kt_core::ipc::{OrchestratorOwner, StateEpoch};
+use kt_core::Secret;
 use rand::Rng;
 
 use crate::auth::TailscaleVerifier;
 use crate::coordinator::StateCoordinator;
+use crate::groups::MachineGroups;
+use crate::ipc::{ActiveClients, IdempotencyKeys};
+use crate::memory::MemoryPressure;
+use crate::rejections::Rejections;
+use crate::session::{InputBreaker, OutputCoalescing, ResizeDebouncer};
 
 /// Pairing code length.
 ///
@@ -36,45 +43,125 @@ fn generate_pairing_code() -> String {
 
 /// Global state for the orchestrator daemon
 pub struct OrchestratorState {
-    /// Configuration
-    pub config: OrchestratorConfig,
+    /// Configuration, swapped when reloaded
+    config: RwLock<Arc<OrchestratorConfig>>,
     /// State coordinator for centralized connection/session management
     pub coordinator: Arc<StateCoordinator>,
     /// Tailscale peer verifier
     pub tailscale: Arc<TailscaleVerifier>,
     /// Pairing code for easy agent connection
-    pub pairing_code: String,
+    pairing_code: Secret<String>,
     /// Global state epoch for event sequencing
     pub epoch: Arc<StateEpoch>,
+    /// What kind of process runs this orchestrator
+    pub owner: OrchestratorOwner,
+    /// Named machine groups, selected as `@name`
+    pub groups: MachineGroups,
+    /// When to coalesce terminal output for lagging IPC clients
+    pub coalescing: OutputCoalescing,
+    /// How often session resizes are forwarded to agents
+    pub resizes: ResizeDebouncer,
+    /// Recent `CreateSession` idempotency keys, per client
+    pub idempotency_keys: IdempotencyKeys,
+    /// Logical client IDs held by live IPC connections
+    pub active_clients: ActiveClients,
+    /// Whether load is shed to save memory
+    pub memory: MemoryPressure,
+    /// Recently rejected agent registrations
+    pub rejections: Rejections,
+    /// Refuses input to sessions that get too much of it
+    pub input_breaker: InputBreaker,
+    /// Address the SSH server is listening on, once bound
+    ssh_address: RwLock<Option<SocketAddr>>,
+    /// Why new sessions are refused, while in maintenance mode
+    maintenance: RwLock<Option<String>>,
 }
 
 impl OrchestratorState {
     /// Create new orchestrator state
     pub fn new(config: OrchestratorConfig) -> Self {
-        let pairing_code = generate_pairing_code();
+        let pairing_code = Secret::new(generate_pairing_code());
         // Log at debug level to avoid exposing pairing code in production logs
         // The code is displayed in the desktop app UI for secure access
         tracing::debug!("Generated pairing code (view in desktop app)");
 
         let coordinator = Arc::new(StateCoordinator::new());
+        let groups = MachineGroups::from_config(&config.groups);
+        let resizes = ResizeDebouncer::new(config.resize_debounce);
+        let input_breaker = InputBreaker::new(config.max_input_bytes_per_min());
 
         Self {
-            config,
+            config: RwLock::new(Arc::new(config)),
             coordinator,
             tailscale: Arc::new(TailscaleVerifier::new()),
             pairing_code,
             epoch: Arc::new(StateEpoch::new()),
+            owner: OrchestratorOwner::Standalone,
+            groups,
+            coalescing: OutputCoalescing::new(),
+            resizes,
+            idempotency_keys: IdempotencyKeys::new(),
+            active_clients: ActiveClients::new(),
+            memory: MemoryPressure::new(),
+            rejections: Rejections::new(),
+            input_breaker,
+            ssh_address: RwLock::new(None),
+            maintenance: RwLock::new(None),
         }
     }
 
-    /// Get the pairing code
+    /// Record what kind of process runs this orchestrator (call before
+    /// creating the IPC server, which writes it to the token file)
+    pub fn with_owner(mut self, owner: OrchestratorOwner) -> Self {
+        self.owner = owner;
+        self
+    }
+
+    /// Current configuration
+    ///
+    /// Read it where it's used rather than keeping it, so reloaded settings
+    /// take effect.
+    pub fn config(&self) -> Arc<OrchestratorConfig> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Replace the configuration (see [`crate::reload`])
+    pub fn set_config(&self, config: OrchestratorConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+    }
+
+    /// Get the pairing code, for showing to the user
     pub fn pairing_code(&self) -> &str {
-        &self.pairing_code
+        self.pairing_code.expose()
+    }
+
+    /// Address the SSH server is listening on, if it has bound yet
+    pub fn ssh_address(&self) -> Option<SocketAddr> {
+        *self.ssh_address.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Record the address the SSH server is listening on
+    pub fn set_ssh_address(&self, address: SocketAddr) {
+        *self.ssh_address.write().unwrap_or_else(|e| e.into_inner()) = Some(address);
+    }
+
+    /// Why new sessions are refused, if in maintenance mode
+    pub fn maintenance(&self) -> Option<String> {
+        self.maintenance
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Enter maintenance mode refusing new sessions with `message`, or
+    /// leave it (None)
+    pub fn set_maintenance(&self, message: Option<String>) {
+        *self.maintenance.write().unwrap_or_else(|e| e.into_inner()) = message;
     }
 
     /// Verify a pairing code matches
     pub fn verify_pairing_code(&self, code: &str) -> bool {
-        self.pairing_code.eq_ignore_ascii_case(code)
+        self.pairing_code.expose().eq_ignore_ascii_case(code)
     }
 }
 
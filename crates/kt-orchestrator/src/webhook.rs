@@ -0,0 +1,845 @@
+//! Outbound webhook notifications for machine and session lifecycle events
+//!
+//! Each `[[orchestrator.webhook]]` gets a JSON `POST` for the events it asks
+//! for, e.g. to ping Slack or ntfy when a machine drops off or a session
+//! exits non-zero. The notifier listens on the same event broadcast as IPC
+//! clients and hands matching events to one delivery task per webhook
+//! through a bounded queue, so a slow or unreachable endpoint only ever
+//! drops its own notifications, never stalls the event pipeline.
+//!
+//! # Payload
+//!
+//! ```json
+//! {"event":"session_failed","text":"Session 7 on gpu-box exited with code 1",
+//!  "machine_id":"gpu-box","session_id":"7","exit_code":1,
+//!  "reason":{"kind":"process_exited","code":1},
+//!  "timestamp":"2026-05-01T16:00:00Z","seq":42}
+//! ```
+//!
+//! `text` is a one-line summary, which is what Slack incoming webhooks show.
+//!
+//! # Verifying signatures
+//!
+//! When a webhook has a `secret`, each request carries two headers:
+//!
+//! - `X-KTerminus-Timestamp`: Unix time in seconds when the request was signed
+//! - `X-KTerminus-Signature`: `sha256=` followed by the lowercase hex
+//!   HMAC-SHA256 of `"<timestamp>.<body>"`, keyed with the secret
+//!
+//! A receiver should recompute the HMAC over the timestamp header, a `.`
+//! and the raw request body (before any JSON parsing), compare it to the
+//! signature header in constant time, and reject timestamps more than a few
+//! minutes old so captured requests can't be replayed later.
+//!
+//! Failed deliveries (connection errors and non-2xx responses) are retried
+//! with exponential backoff, up to [`WEBHOOK_MAX_ATTEMPTS`] attempts.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+use kt_core::config::{WebhookConfig, WebhookEvent};
+use kt_core::ipc::{CloseReason, IpcEvent, IpcEventEnvelope};
+
+/// Header carrying the request's signing time (Unix seconds)
+pub const TIMESTAMP_HEADER: &str = "X-KTerminus-Timestamp";
+
+/// Header carrying the request's HMAC-SHA256 signature
+pub const SIGNATURE_HEADER: &str = "X-KTerminus-Signature";
+
+/// Notifications queued per webhook before new ones are dropped
+pub const WEBHOOK_QUEUE_CAPACITY: usize = 64;
+
+/// Delivery attempts per notification, including the first
+pub const WEBHOOK_MAX_ATTEMPTS: u32 = 4;
+
+/// Delay before the first retry; doubles after each failure
+const WEBHOOK_RETRY_INITIAL: Duration = Duration::from_secs(1);
+
+/// Timeout for a single delivery attempt
+const WEBHOOK_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body of a webhook request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebhookPayload {
+    /// What happened
+    pub event: WebhookEvent,
+    /// One-line human-readable summary
+    pub text: String,
+    /// Machine the event is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub machine_id: Option<String>,
+    /// The machine's alias, if it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Session the event is about
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// Exit code of the session's shell
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// Why the session ended
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<CloseReason>,
+    /// When the orchestrator saw the event (ISO-8601)
+    pub timestamp: String,
+    /// Event sequence number, as seen by IPC clients
+    pub seq: u64,
+}
+
+/// Signature header value for `body` signed at `timestamp` (see the
+/// [module docs](self) for how to verify it)
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Decides which webhooks an event goes to
+///
+/// Remembers machine aliases and which machine each session runs on, since
+/// disconnect and close events carry only IDs.
+pub struct WebhookRouter {
+    webhooks: Vec<WebhookConfig>,
+    /// Last machine notification per webhook, by machine ID
+    last_machine_notice: Vec<HashMap<String, Instant>>,
+    /// Alias of each connected machine
+    aliases: HashMap<String, Option<String>>,
+    /// Machine ID of each open session
+    session_machines: HashMap<String, String>,
+}
+
+impl WebhookRouter {
+    /// Create a router for the given webhooks
+    pub fn new(webhooks: Vec<WebhookConfig>) -> Self {
+        Self {
+            last_machine_notice: vec![HashMap::new(); webhooks.len()],
+            webhooks,
+            aliases: HashMap::new(),
+            session_machines: HashMap::new(),
+        }
+    }
+
+    /// Payloads for `envelope`, as (webhook index, payload), at time `now`
+    pub fn route(
+        &mut self,
+        envelope: &IpcEventEnvelope,
+        now: Instant,
+    ) -> Vec<(usize, WebhookPayload)> {
+        let Some(payload) = self.payload_for(envelope) else {
+            return Vec::new();
+        };
+        let is_machine_event = matches!(
+            payload.event,
+            WebhookEvent::MachineConnected | WebhookEvent::MachineDisconnected
+        );
+        let machine_id = payload.machine_id.as_deref().unwrap_or_default();
+
+        let mut routed = Vec::new();
+        for (index, webhook) in self.webhooks.iter().enumerate() {
+            if !webhook.wants_event(payload.event)
+                || !webhook.wants_machine(machine_id, payload.alias.as_deref())
+            {
+                continue;
+            }
+
+            if is_machine_event {
+                // One machine notification per debounce window, so a
+                // flapping connection doesn't flood the channel
+                let last = &mut self.last_machine_notice[index];
+                if let Some(at) = last.get(machine_id) {
+                    if now.duration_since(*at) < webhook.debounce {
+                        tracing::debug!(
+                            "Suppressing {} webhook for {} (debounced)",
+                            payload.event,
+                            machine_id
+                        );
+                        continue;
+                    }
+                }
+                last.insert(machine_id.to_string(), now);
+            }
+
+            routed.push((index, payload.clone()));
+        }
+        routed
+    }
+
+    /// Build the payload for a lifecycle event, updating what the router
+    /// knows about machines and sessions
+    fn payload_for(&mut self, envelope: &IpcEventEnvelope) -> Option<WebhookPayload> {
+        let (event, machine_id, session_id, exit_code, text) = match &envelope.event {
+            IpcEvent::MachineConnected(info) => {
+                self.aliases.insert(info.id.clone(), info.alias.clone());
+                let text = format!("Machine {} connected", describe(&info.id, &info.alias));
+                (
+                    WebhookEvent::MachineConnected,
+                    info.id.clone(),
+                    None,
+                    None,
+                    text,
+                )
+            }
+            IpcEvent::MachineDisconnected { machine_id } => {
+                let alias = self.aliases.get(machine_id).cloned().flatten();
+                let text = format!("Machine {} disconnected", describe(machine_id, &alias));
+                (
+                    WebhookEvent::MachineDisconnected,
+                    machine_id.clone(),
+                    None,
+                    None,
+                    text,
+                )
+            }
+            IpcEvent::SessionCreated(info) => {
+                self.session_machines
+                    .insert(info.id.clone(), info.machine_id.clone());
+                let text = format!("Session {} started on {}", info.id, info.machine_id);
+                (
+                    WebhookEvent::SessionCreated,
+                    info.machine_id.clone(),
+                    Some(info.id.clone()),
+                    None,
+                    text,
+                )
+            }
+            IpcEvent::SessionClosed {
+                session_id,
+                exit_code,
+                reason,
+            } => {
+                let machine_id = self.session_machines.remove(session_id)?;
+                let (event, text) = match (exit_code, reason) {
+                    (Some(code), _) if *code != 0 => (
+                        WebhookEvent::SessionFailed,
+                        format!(
+                            "Session {} on {} exited with code {}",
+                            session_id, machine_id, code
+                        ),
+                    ),
+                    (_, Some(CloseReason::Error { message })) => (
+                        WebhookEvent::SessionFailed,
+                        format!(
+                            "Session {} on {} failed: {}",
+                            session_id, machine_id, message
+                        ),
+                    ),
+                    (_, Some(reason)) => (
+                        WebhookEvent::SessionClosed,
+                        format!(
+                            "Session {} on {} closed: {}",
+                            session_id, machine_id, reason
+                        ),
+                    ),
+                    (_, None) => (
+                        WebhookEvent::SessionClosed,
+                        format!("Session {} on {} closed", session_id, machine_id),
+                    ),
+                };
+                (
+                    event,
+                    machine_id,
+                    Some(session_id.clone()),
+                    *exit_code,
+                    text,
+                )
+            }
+            _ => return None,
+        };
+
+        let reason = match &envelope.event {
+            IpcEvent::SessionClosed { reason, .. } => reason.clone(),
+            _ => None,
+        };
+        let alias = self.aliases.get(&machine_id).cloned().flatten();
+        if event == WebhookEvent::MachineDisconnected {
+            self.aliases.remove(&machine_id);
+        }
+
+        Some(WebhookPayload {
+            event,
+            text,
+            machine_id: Some(machine_id),
+            alias,
+            session_id,
+            exit_code,
+            reason,
+            timestamp: kt_core::time::format_iso8601(
+                UNIX_EPOCH + Duration::from_millis(envelope.timestamp),
+            ),
+            seq: envelope.seq,
+        })
+    }
+}
+
+/// `id (alias)`, or just `id` without an alias
+fn describe(id: &str, alias: &Option<String>) -> String {
+    match alias {
+        Some(alias) if alias != id => format!("{} ({})", id, alias),
+        _ => id.to_string(),
+    }
+}
+
+/// Sends notifications to one webhook, retrying failures
+struct Delivery {
+    client: reqwest::Client,
+    url: String,
+    secret: Option<String>,
+    retry_initial: Duration,
+}
+
+impl Delivery {
+    fn new(client: reqwest::Client, config: &WebhookConfig) -> Self {
+        Self {
+            client,
+            url: config.url.clone(),
+            secret: config.secret.clone(),
+            retry_initial: WEBHOOK_RETRY_INITIAL,
+        }
+    }
+
+    /// Deliver `payload`, retrying with backoff. Returns whether it arrived.
+    async fn send(&self, payload: &WebhookPayload) -> bool {
+        let body = match serde_json::to_vec(payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize webhook payload: {}", e);
+                return false;
+            }
+        };
+
+        let mut delay = self.retry_initial;
+        for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+            match self.post(&body).await {
+                Ok(()) => return true,
+                Err(e) if attempt < WEBHOOK_MAX_ATTEMPTS => {
+                    tracing::warn!(
+                        "Webhook {} failed (attempt {}/{}), retrying in {:?}: {}",
+                        self.url,
+                        attempt,
+                        WEBHOOK_MAX_ATTEMPTS,
+                        delay,
+                        e
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => {
+                    tracing::error!(
+                        "Giving up on {} webhook to {} after {} attempts: {}",
+                        payload.event,
+                        self.url,
+                        WEBHOOK_MAX_ATTEMPTS,
+                        e
+                    );
+                }
+            }
+        }
+        false
+    }
+
+    async fn post(&self, body: &[u8]) -> anyhow::Result<()> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_vec());
+        if let Some(secret) = &self.secret {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, sign(secret, timestamp, body));
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("HTTP {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// Route events from `event_rx` to `webhooks` until the channel closes or
+/// `cancel` fires.
+///
+/// Returns `None` when there are no webhooks to notify.
+pub fn spawn_notifier(
+    webhooks: Vec<WebhookConfig>,
+    event_rx: broadcast::Receiver<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) -> Option<JoinHandle<()>> {
+    if webhooks.is_empty() {
+        return None;
+    }
+
+    let client = match reqwest::Client::builder()
+        .timeout(WEBHOOK_REQUEST_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Webhooks disabled: failed to create HTTP client: {}", e);
+            return None;
+        }
+    };
+
+    let queues: Vec<_> = webhooks
+        .iter()
+        .map(|config| {
+            let (tx, rx) = mpsc::channel(WEBHOOK_QUEUE_CAPACITY);
+            let delivery = Delivery::new(client.clone(), config);
+            tokio::spawn(run_delivery(delivery, rx, cancel.clone()));
+            tx
+        })
+        .collect();
+
+    tracing::info!("Webhook notifications enabled ({} endpoints)", queues.len());
+    let router = WebhookRouter::new(webhooks);
+    Some(tokio::spawn(run_router(router, queues, event_rx, cancel)))
+}
+
+async fn run_router(
+    mut router: WebhookRouter,
+    queues: Vec<mpsc::Sender<WebhookPayload>>,
+    mut event_rx: broadcast::Receiver<IpcEventEnvelope>,
+    cancel: CancellationToken,
+) {
+    loop {
+        let envelope = tokio::select! {
+            _ = cancel.cancelled() => break,
+            result = event_rx.recv() => match result {
+                Ok(envelope) => envelope,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Webhook notifier lagged, {} events skipped", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+
+        for (index, payload) in router.route(&envelope, Instant::now()) {
+            // Never wait on a slow endpoint; drop instead
+            if queues[index].try_send(payload).is_err() {
+                tracing::warn!(
+                    "Webhook queue for {} is full, dropping notification",
+                    router.webhooks[index].url
+                );
+            }
+        }
+    }
+}
+
+async fn run_delivery(
+    delivery: Delivery,
+    mut rx: mpsc::Receiver<WebhookPayload>,
+    cancel: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            payload = rx.recv() => match payload {
+                Some(payload) => {
+                    delivery.send(&payload).await;
+                }
+                None => break,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::State;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use kt_core::ipc::{MachineInfo, MachineStatus, SessionInfo};
+
+    fn envelope(seq: u64, event: IpcEvent) -> IpcEventEnvelope {
+        IpcEventEnvelope {
+            seq,
+            timestamp: 1_700_000_000_000,
+            event,
+            session_seq: None,
+        }
+    }
+
+    fn connected(id: &str, alias: &str) -> IpcEvent {
+        IpcEvent::MachineConnected(MachineInfo {
+            id: id.to_string(),
+            alias: Some(alias.to_string()),
+            label: None,
+            hostname: format!("{}.local", id),
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            status: MachineStatus::Connected,
+            connected_at: None,
+            last_heartbeat: None,
+            session_count: 0,
+            tags: vec![],
+            capabilities: vec![],
+            reconnect_count: 0,
+        })
+    }
+
+    fn disconnected(id: &str) -> IpcEvent {
+        IpcEvent::MachineDisconnected {
+            machine_id: id.to_string(),
+        }
+    }
+
+    fn session_created(session_id: &str, machine_id: &str) -> IpcEvent {
+        IpcEvent::SessionCreated(SessionInfo {
+            id: session_id.to_string(),
+            machine_id: machine_id.to_string(),
+            shell: None,
+            created_at: String::new(),
+            pid: Some(42),
+            size: None,
+            name: None,
+            bytes_in: 0,
+            bytes_out: 0,
+            detached: false,
+            input_bytes_per_min: 0,
+            input_requests_per_min: 0,
+        })
+    }
+
+    fn session_closed(session_id: &str, exit_code: Option<i32>) -> IpcEvent {
+        IpcEvent::SessionClosed {
+            session_id: session_id.to_string(),
+            exit_code,
+            reason: Some(CloseReason::ProcessExited { code: exit_code }),
+        }
+    }
+
+    #[test]
+    fn test_filters_by_event_and_machine() {
+        let mut webhook = WebhookConfig::new("http://example.invalid/hook");
+        webhook.events = vec![
+            WebhookEvent::MachineDisconnected,
+            WebhookEvent::SessionFailed,
+        ];
+        webhook.machines = vec!["LAB-GPU".to_string()];
+        let mut router = WebhookRouter::new(vec![webhook]);
+        let now = Instant::now();
+
+        // Connect events aren't wanted, but the alias is remembered
+        assert!(router
+            .route(&envelope(1, connected("gpu-box", "lab-gpu")), now)
+            .is_empty());
+        assert!(router
+            .route(&envelope(2, connected("laptop", "mine")), now)
+            .is_empty());
+
+        router.route(&envelope(3, session_created("1", "gpu-box")), now);
+        router.route(&envelope(4, session_created("2", "gpu-box")), now);
+        router.route(&envelope(5, session_created("3", "laptop")), now);
+
+        // Normal exit is filtered out; non-zero exit is a failure
+        assert!(router
+            .route(&envelope(6, session_closed("1", Some(0))), now)
+            .is_empty());
+        let routed = router.route(&envelope(7, session_closed("2", Some(2))), now);
+        assert_eq!(routed.len(), 1);
+        let payload = &routed[0].1;
+        assert_eq!(payload.event, WebhookEvent::SessionFailed);
+        assert_eq!(payload.exit_code, Some(2));
+        assert_eq!(payload.alias.as_deref(), Some("lab-gpu"));
+        assert_eq!(payload.text, "Session 2 on gpu-box exited with code 2");
+
+        // Other machines are filtered out
+        assert!(router
+            .route(&envelope(8, session_closed("3", Some(1))), now)
+            .is_empty());
+        assert!(router
+            .route(&envelope(9, disconnected("laptop")), now)
+            .is_empty());
+
+        let routed = router.route(&envelope(10, disconnected("gpu-box")), now);
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].1.event, WebhookEvent::MachineDisconnected);
+        assert_eq!(routed[0].1.text, "Machine gpu-box (lab-gpu) disconnected");
+        assert_eq!(routed[0].1.seq, 10);
+    }
+
+    #[test]
+    fn test_ignores_output_and_unknown_sessions() {
+        let mut router = WebhookRouter::new(vec![WebhookConfig::new("http://example.invalid")]);
+        let now = Instant::now();
+
+        let output = IpcEvent::TerminalOutput {
+            session_id: "1".to_string(),
+            data: b"hi".to_vec(),
+            stream: kt_core::ipc::OutputStream::Stdout,
+        };
+        assert!(router.route(&envelope(1, output), now).is_empty());
+        assert!(router
+            .route(&envelope(2, session_closed("99", Some(1))), now)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_close_reason_in_text_and_payload() {
+        let mut router = WebhookRouter::new(vec![WebhookConfig::new("http://example.invalid")]);
+        let now = Instant::now();
+        router.route(&envelope(1, session_created("1", "gpu-box")), now);
+        router.route(&envelope(2, session_created("2", "gpu-box")), now);
+
+        let closed = IpcEvent::SessionClosed {
+            session_id: "1".to_string(),
+            exit_code: None,
+            reason: Some(CloseReason::MachineDisconnected),
+        };
+        let routed = router.route(&envelope(3, closed), now);
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].1.event, WebhookEvent::SessionClosed);
+        assert_eq!(
+            routed[0].1.text,
+            "Session 1 on gpu-box closed: machine disconnected"
+        );
+        assert_eq!(routed[0].1.reason, Some(CloseReason::MachineDisconnected));
+
+        // An error with no exit code is still a failure
+        let failed = IpcEvent::SessionClosed {
+            session_id: "2".to_string(),
+            exit_code: None,
+            reason: Some(CloseReason::Error {
+                message: "no PTY".to_string(),
+            }),
+        };
+        let routed = router.route(&envelope(4, failed), now);
+        assert_eq!(routed.len(), 1);
+        assert_eq!(routed[0].1.event, WebhookEvent::SessionFailed);
+        assert_eq!(routed[0].1.text, "Session 2 on gpu-box failed: no PTY");
+    }
+
+    #[test]
+    fn test_debounces_flapping_machine() {
+        let mut webhook = WebhookConfig::new("http://example.invalid");
+        webhook.debounce = Duration::from_secs(300);
+        let mut router = WebhookRouter::new(vec![webhook, WebhookConfig::new("http://other")]);
+        let start = Instant::now();
+
+        let routed = router.route(&envelope(1, disconnected("gpu-box")), start);
+        assert_eq!(routed.len(), 2);
+
+        // Flapping within the window: suppressed for both kinds of event
+        let soon = start + Duration::from_secs(30);
+        assert!(router
+            .route(&envelope(2, connected("gpu-box", "gpu")), soon)
+            .is_empty());
+        assert!(router
+            .route(&envelope(3, disconnected("gpu-box")), soon)
+            .is_empty());
+
+        // Other machines have their own window
+        assert_eq!(
+            router
+                .route(&envelope(4, disconnected("laptop")), soon)
+                .len(),
+            2
+        );
+
+        // Session events aren't debounced
+        router.route(&envelope(5, session_created("1", "gpu-box")), soon);
+        assert_eq!(
+            router
+                .route(&envelope(6, session_closed("1", Some(1))), soon)
+                .len(),
+            2
+        );
+
+        let later = start + Duration::from_secs(301);
+        assert_eq!(
+            router
+                .route(&envelope(7, connected("gpu-box", "gpu")), later)
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_sign_matches_known_vector() {
+        // echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(
+            sign("secret", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+    }
+
+    /// A request captured by the test server
+    #[derive(Debug, Clone)]
+    struct Captured {
+        headers: HeaderMap,
+        body: Vec<u8>,
+    }
+
+    #[derive(Clone, Default)]
+    struct Capture {
+        requests: Arc<Mutex<Vec<Captured>>>,
+        /// Respond 500 to this many requests before succeeding
+        failures: Arc<Mutex<u32>>,
+    }
+
+    async fn record_request(
+        State(capture): State<Capture>,
+        headers: HeaderMap,
+        body: axum::body::Bytes,
+    ) -> StatusCode {
+        capture.requests.lock().unwrap().push(Captured {
+            headers,
+            body: body.to_vec(),
+        });
+        let mut failures = capture.failures.lock().unwrap();
+        if *failures > 0 {
+            *failures -= 1;
+            StatusCode::INTERNAL_SERVER_ERROR
+        } else {
+            StatusCode::NO_CONTENT
+        }
+    }
+
+    /// Start a local HTTP server recording every POST to `/hook`
+    async fn start_server(failures: u32) -> (String, Capture) {
+        let capture = Capture::default();
+        *capture.failures.lock().unwrap() = failures;
+        let app = Router::new()
+            .route("/hook", post(record_request))
+            .with_state(capture.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (url, capture)
+    }
+
+    fn test_payload() -> WebhookPayload {
+        WebhookPayload {
+            event: WebhookEvent::MachineDisconnected,
+            text: "Machine gpu-box disconnected".to_string(),
+            machine_id: Some("gpu-box".to_string()),
+            alias: None,
+            session_id: None,
+            exit_code: None,
+            reason: None,
+            timestamp: "2023-11-14T22:13:20Z".to_string(),
+            seq: 7,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delivery_posts_signed_payload() {
+        let (url, capture) = start_server(0).await;
+        let mut config = WebhookConfig::new(url);
+        config.secret = Some("s3cret".to_string());
+        let delivery = Delivery::new(reqwest::Client::new(), &config);
+
+        assert!(delivery.send(&test_payload()).await);
+
+        let requests = capture.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        let request = &requests[0];
+
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["event"], "machine_disconnected");
+        assert_eq!(body["machine_id"], "gpu-box");
+        assert_eq!(body["seq"], 7);
+        assert!(body.get("session_id").is_none());
+
+        let timestamp: u64 = request.headers[TIMESTAMP_HEADER]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            request.headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign("s3cret", timestamp, &request.body)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivery_unsigned_without_secret() {
+        let (url, capture) = start_server(0).await;
+        let delivery = Delivery::new(reqwest::Client::new(), &WebhookConfig::new(url));
+
+        assert!(delivery.send(&test_payload()).await);
+
+        let requests = capture.requests.lock().unwrap().clone();
+        assert!(!requests[0].headers.contains_key(SIGNATURE_HEADER));
+        assert!(!requests[0].headers.contains_key(TIMESTAMP_HEADER));
+    }
+
+    #[tokio::test]
+    async fn test_delivery_retries_then_gives_up() {
+        let (url, capture) = start_server(2).await;
+        let mut delivery = Delivery::new(reqwest::Client::new(), &WebhookConfig::new(&url));
+        delivery.retry_initial = Duration::from_millis(10);
+
+        // Two failures, then success on the third attempt
+        assert!(delivery.send(&test_payload()).await);
+        assert_eq!(capture.requests.lock().unwrap().len(), 3);
+
+        // An endpoint that always fails gets every attempt, then is dropped
+        *capture.failures.lock().unwrap() = u32::MAX;
+        capture.requests.lock().unwrap().clear();
+        assert!(!delivery.send(&test_payload()).await);
+        assert_eq!(
+            capture.requests.lock().unwrap().len(),
+            WEBHOOK_MAX_ATTEMPTS as usize
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notifier_delivers_broadcast_events() {
+        let (url, capture) = start_server(0).await;
+        let mut webhook = WebhookConfig::new(url);
+        webhook.events = vec![WebhookEvent::MachineDisconnected];
+
+        let (event_tx, event_rx) = broadcast::channel(16);
+        let cancel = CancellationToken::new();
+        let handle = spawn_notifier(vec![webhook], event_rx, cancel.clone()).unwrap();
+
+        event_tx
+            .send(envelope(1, connected("gpu-box", "gpu")))
+            .unwrap();
+        event_tx.send(envelope(2, disconnected("gpu-box"))).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while capture.requests.lock().unwrap().is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let requests = capture.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["event"], "machine_disconnected");
+        assert_eq!(body["alias"], "gpu");
+
+        cancel.cancel();
+        handle.await.unwrap();
+    }
+
+    #[test]
+    fn test_spawn_notifier_without_webhooks() {
+        let (_event_tx, event_rx) = broadcast::channel(1);
+        assert!(spawn_notifier(vec![], event_rx, CancellationToken::new()).is_none());
+    }
+}
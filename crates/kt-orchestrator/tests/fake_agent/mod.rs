@@ -0,0 +1,480 @@
+//! In-process orchestrator with fake agents, for session flow tests
+//!
+//! [`Harness`] runs what the daemon runs for sessions: the IPC server, the
+//! handler for connection events and the task removing machines that don't
+//! reconnect in time. [`FakeAgent`] takes the place of an agent and its SSH
+//! connection: it registers the way the SSH handler does once an agent
+//! authenticated, answers the [`AgentCommand`]s the orchestrator sends with
+//! the [`ConnectionEvent`]s a real connection would report (setting changes
+//! it acks on the connection, as the SSH handler does), and can be told to
+//! misbehave (see [`Behavior`]). [`TestClient`] speaks the IPC protocol
+//! like the CLI, keeping events that arrive between responses.
+//!
+//! No external binaries or network beyond a loopback IPC port are involved,
+//! so the flows are deterministic apart from how long they take.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Notify};
+use tokio_util::sync::CancellationToken;
+
+use kt_core::config::OrchestratorConfig;
+use kt_core::ipc::{
+    CloseReason, IpcEvent, IpcEventEnvelope, IpcRequest, IpcResponse, OutputStream,
+};
+use kt_core::types::MachineId;
+use kt_orchestrator::connection::{run_reconnect_grace, AgentCommand};
+use kt_orchestrator::ipc::IpcServer;
+use kt_orchestrator::server::{handle_connection_event, ConnectionEvent};
+use kt_orchestrator::OrchestratorState;
+use kt_protocol::{AgentCapabilities, AgentConfigKey, Capability, SessionId};
+
+/// How long to wait for an event or command before failing the test
+pub const WAIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An orchestrator running in-process, without its SSH server
+pub struct Harness {
+    pub state: Arc<OrchestratorState>,
+    address: String,
+    auth_token: String,
+    /// Where fake agents report, as SSH handlers do
+    connection_tx: mpsc::Sender<ConnectionEvent>,
+    cancel: CancellationToken,
+}
+
+impl Harness {
+    /// Start an orchestrator with `config` on a free loopback port
+    pub async fn start(config: OrchestratorConfig) -> Self {
+        let state = Arc::new(OrchestratorState::new(config));
+        let server = Arc::new(
+            IpcServer::new("127.0.0.1:0".to_string(), Arc::clone(&state))
+                .expect("Failed to create IPC server"),
+        );
+        let listener = server.bind().await.expect("Failed to bind IPC server");
+        let address = listener.local_addr().unwrap().to_string();
+        let auth_token = server.auth_token().to_string();
+        let events = server.event_sender();
+        let cancel = CancellationToken::new();
+
+        let serve_cancel = cancel.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = server.serve(listener) => {}
+                _ = serve_cancel.cancelled() => {}
+            }
+        });
+
+        let (connection_tx, mut connection_rx) = mpsc::channel::<ConnectionEvent>(256);
+        let handler_state = Arc::clone(&state);
+        let handler_events = events.clone();
+        tokio::spawn(async move {
+            while let Some(event) = connection_rx.recv().await {
+                handle_connection_event(&handler_state, event, &handler_events).await;
+            }
+        });
+        tokio::spawn(run_reconnect_grace(
+            Arc::clone(&state),
+            events,
+            cancel.clone(),
+        ));
+
+        Self {
+            state,
+            address,
+            auth_token,
+            connection_tx,
+            cancel,
+        }
+    }
+
+    /// Connect and authenticate an IPC client
+    pub async fn client(&self) -> TestClient {
+        self.authenticated_client(false).await
+    }
+
+    /// Connect and authenticate an IPC client whose sessions close when it
+    /// disconnects
+    pub async fn client_closing_on_disconnect(&self) -> TestClient {
+        self.authenticated_client(true).await
+    }
+
+    async fn authenticated_client(&self, close_on_disconnect: bool) -> TestClient {
+        let mut client = TestClient::connect(&self.address).await;
+        let response = client
+            .request(IpcRequest::Authenticate {
+                token: self.auth_token.clone(),
+                client_id: None,
+                instance_id: None,
+                force_takeover: false,
+                close_on_disconnect,
+            })
+            .await;
+        assert!(
+            matches!(response, IpcResponse::Authenticated { .. }),
+            "Authentication failed: {:?}",
+            response
+        );
+        client
+    }
+
+    /// Connect a fake agent registering as machine `name`
+    pub async fn agent(&self, name: &str, behavior: Behavior) -> FakeAgent {
+        FakeAgent::connect(
+            Arc::clone(&self.state),
+            self.connection_tx.clone(),
+            name,
+            behavior,
+        )
+        .await
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// How a fake agent answers the orchestrator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Behavior {
+    /// Confirms new sessions right away and echoes their input
+    Normal,
+    /// Never confirms new sessions, as if their shell hung on startup
+    NeverConfirm,
+    /// Confirms new sessions only after this long
+    SlowAck(Duration),
+}
+
+/// Stands in for an agent and its SSH connection
+pub struct FakeAgent {
+    pub machine_id: MachineId,
+    connection_tx: mpsc::Sender<ConnectionEvent>,
+    /// Every command received from the orchestrator, in order
+    received: Arc<Mutex<Vec<AgentCommand>>>,
+    received_notify: Arc<Notify>,
+    /// Cancelled when the connection goes away, from either side
+    cancel: CancellationToken,
+}
+
+impl FakeAgent {
+    async fn connect(
+        state: Arc<OrchestratorState>,
+        connection_tx: mpsc::Sender<ConnectionEvent>,
+        name: &str,
+        behavior: Behavior,
+    ) -> Self {
+        let machine_id = MachineId::new(name);
+        let (command_tx, command_rx) = mpsc::channel(64);
+        let cancel = CancellationToken::new();
+        let agent = Self {
+            machine_id: machine_id.clone(),
+            connection_tx: connection_tx.clone(),
+            received: Arc::new(Mutex::new(Vec::new())),
+            received_notify: Arc::new(Notify::new()),
+            cancel: cancel.clone(),
+        };
+        tokio::spawn(run_agent(
+            state,
+            machine_id.clone(),
+            behavior,
+            command_rx,
+            connection_tx.clone(),
+            Arc::clone(&agent.received),
+            Arc::clone(&agent.received_notify),
+            cancel.clone(),
+        ));
+
+        connection_tx
+            .send(ConnectionEvent::MachineConnected {
+                machine_id,
+                alias: name.to_string(),
+                label: None,
+                hostname: format!("{}.test", name),
+                os: "linux".to_string(),
+                arch: "x86_64".to_string(),
+                capabilities: AgentCapabilities::empty().with(Capability::AgentConfig),
+                reconnect_count: 0,
+                command_tx,
+                cancel,
+            })
+            .await
+            .expect("Orchestrator stopped");
+        agent
+    }
+
+    async fn report(&self, event: ConnectionEvent) {
+        self.connection_tx
+            .send(event)
+            .await
+            .expect("Orchestrator stopped");
+    }
+
+    /// The shell of `session_id` prints `data`
+    pub async fn output(&self, session_id: &str, data: &[u8]) {
+        self.report(ConnectionEvent::SessionData {
+            machine_id: self.machine_id.clone(),
+            session_id: parse_session_id(session_id),
+            data: data.to_vec(),
+            stream: OutputStream::Stdout,
+        })
+        .await;
+    }
+
+    /// The shell of `session_id` exits with `code`
+    pub async fn exit(&self, session_id: &str, code: i32) {
+        self.report(ConnectionEvent::SessionClosed {
+            machine_id: self.machine_id.clone(),
+            session_id: parse_session_id(session_id),
+            exit_code: Some(code),
+            reason: CloseReason::ProcessExited { code: Some(code) },
+        })
+        .await;
+    }
+
+    /// The connection drops mid-session; the agent stops answering
+    pub async fn disconnect(&self) {
+        self.cancel.cancel();
+        self.report(ConnectionEvent::MachineDisconnected {
+            machine_id: self.machine_id.clone(),
+        })
+        .await;
+    }
+
+    /// Wait for a command matching `predicate`, returning it
+    pub async fn wait_for_command(
+        &self,
+        mut predicate: impl FnMut(&AgentCommand) -> bool,
+    ) -> AgentCommand {
+        let wait = async {
+            loop {
+                let notified = self.received_notify.notified();
+                let found = {
+                    let received = self.received.lock().unwrap_or_else(|e| e.into_inner());
+                    received.iter().find(|command| predicate(command)).cloned()
+                };
+                match found {
+                    Some(command) => return command,
+                    None => notified.await,
+                }
+            }
+        };
+        tokio::time::timeout(WAIT_TIMEOUT, wait)
+            .await
+            .expect("Timed out waiting for an agent command")
+    }
+}
+
+/// Answer commands from the orchestrator the way `behavior` says
+#[allow(clippy::too_many_arguments)]
+async fn run_agent(
+    state: Arc<OrchestratorState>,
+    machine_id: MachineId,
+    behavior: Behavior,
+    mut command_rx: mpsc::Receiver<AgentCommand>,
+    connection_tx: mpsc::Sender<ConnectionEvent>,
+    received: Arc<Mutex<Vec<AgentCommand>>>,
+    received_notify: Arc<Notify>,
+    cancel: CancellationToken,
+) {
+    let mut next_pid = 1000;
+    loop {
+        let command = tokio::select! {
+            command = command_rx.recv() => match command {
+                Some(command) => command,
+                None => break,
+            },
+            _ = cancel.cancelled() => break,
+        };
+        received
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(command.clone());
+        received_notify.notify_waiters();
+
+        let reply = match command {
+            AgentCommand::CreateSession { session_id, .. } => {
+                let delay = match behavior {
+                    Behavior::Normal => Duration::ZERO,
+                    Behavior::NeverConfirm => continue,
+                    Behavior::SlowAck(delay) => delay,
+                };
+                next_pid += 1;
+                let event = ConnectionEvent::SessionCreated {
+                    machine_id: machine_id.clone(),
+                    session_id,
+                    pid: next_pid,
+                };
+                // Later commands wait for the confirmation, as they queue
+                // behind it on a real connection
+                tokio::time::sleep(delay).await;
+                event
+            }
+            // A shell with a PTY echoes what's typed
+            AgentCommand::SessionInput { session_id, data } => ConnectionEvent::SessionData {
+                machine_id: machine_id.clone(),
+                session_id,
+                data: data.to_vec(),
+                stream: OutputStream::Stdout,
+            },
+            // The agent reports the shell it closed, as for any exit
+            AgentCommand::CloseSession { session_id } => ConnectionEvent::SessionClosed {
+                machine_id: machine_id.clone(),
+                session_id,
+                exit_code: None,
+                reason: CloseReason::ProcessExited { code: None },
+            },
+            // Known settings with valid values apply; the agent keeps none
+            AgentCommand::UpdateConfig { key, value } => {
+                let result = match AgentConfigKey::from_name(&key) {
+                    Some(known) => known.validate(&value),
+                    None => Err(format!("Unknown setting '{}'", key)),
+                };
+                if let Some(conn) = state.coordinator.connections.get(&machine_id) {
+                    conn.ack_config(&key, result);
+                }
+                continue;
+            }
+            AgentCommand::SessionResize { .. } | AgentCommand::Heartbeat { .. } => continue,
+        };
+        if connection_tx.send(reply).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Parse an IPC session ID (`session-<n>`)
+fn parse_session_id(session_id: &str) -> SessionId {
+    let number = session_id.strip_prefix("session-").unwrap_or(session_id);
+    SessionId::new(number.parse().expect("Invalid session ID"))
+}
+
+/// IPC client keeping the events that arrive while it waits for responses
+pub struct TestClient {
+    reader: BufReader<tokio::net::tcp::OwnedReadHalf>,
+    writer: BufWriter<tokio::net::tcp::OwnedWriteHalf>,
+    events: VecDeque<IpcEvent>,
+}
+
+/// A line from the orchestrator
+enum Incoming {
+    Response(IpcResponse),
+    Event(IpcEvent),
+}
+
+impl TestClient {
+    async fn connect(address: &str) -> Self {
+        let stream = TcpStream::connect(address)
+            .await
+            .unwrap_or_else(|e| panic!("Failed to connect to IPC server at {}: {}", address, e));
+        let (reader, writer) = stream.into_split();
+        Self {
+            reader: BufReader::new(reader),
+            writer: BufWriter::new(writer),
+            events: VecDeque::new(),
+        }
+    }
+
+    async fn read(&mut self) -> Incoming {
+        let mut line = String::new();
+        let read = tokio::time::timeout(WAIT_TIMEOUT, self.reader.read_line(&mut line))
+            .await
+            .expect("Timed out waiting for the orchestrator")
+            .expect("Failed to read from the orchestrator");
+        assert!(read > 0, "Orchestrator closed the connection");
+
+        // Events are envelopes; anything else answers the last request
+        match serde_json::from_str::<IpcEventEnvelope>(&line) {
+            Ok(envelope) => Incoming::Event(envelope.event),
+            Err(_) => {
+                Incoming::Response(serde_json::from_str(&line).expect("Failed to parse response"))
+            }
+        }
+    }
+
+    /// Send `request` and wait for its response
+    pub async fn request(&mut self, request: IpcRequest) -> IpcResponse {
+        let mut json = serde_json::to_string(&request).expect("Failed to serialize request");
+        json.push('\n');
+        self.writer
+            .write_all(json.as_bytes())
+            .await
+            .expect("Failed to write request");
+        self.writer.flush().await.expect("Failed to flush");
+
+        loop {
+            match self.read().await {
+                Incoming::Response(response) => return response,
+                Incoming::Event(event) => self.events.push_back(event),
+            }
+        }
+    }
+
+    /// Wait for an event matching `predicate`, returning it and dropping
+    /// the events before it
+    pub async fn wait_for_event(
+        &mut self,
+        mut predicate: impl FnMut(&IpcEvent) -> bool,
+    ) -> IpcEvent {
+        while let Some(event) = self.events.pop_front() {
+            if predicate(&event) {
+                return event;
+            }
+        }
+        loop {
+            match self.read().await {
+                Incoming::Event(event) if predicate(&event) => return event,
+                Incoming::Event(_) => {}
+                Incoming::Response(response) => panic!("Unexpected response {:?}", response),
+            }
+        }
+    }
+
+    /// Create a session on `machine_id` with defaults, returning its ID
+    pub async fn create_session(
+        &mut self,
+        machine_id: &str,
+        close_on_exit: Option<bool>,
+    ) -> String {
+        let response = self
+            .request(IpcRequest::CreateSession {
+                machine_id: machine_id.to_string(),
+                shell: None,
+                cwd: None,
+                env: vec![],
+                name: None,
+                size: None,
+                allocate_pty: true,
+                log_level: None,
+                term: None,
+                truecolor: false,
+                idempotency_key: None,
+                ttl_secs: None,
+                close_on_exit,
+            })
+            .await;
+        match response {
+            IpcResponse::SessionCreated(info) => info.id,
+            other => panic!("Expected SessionCreated response, got {:?}", other),
+        }
+    }
+
+    /// Wait for `session_id` to close, returning its exit code and reason
+    pub async fn wait_for_close(&mut self, session_id: &str) -> (Option<i32>, Option<CloseReason>) {
+        match self
+            .wait_for_event(|event| {
+                matches!(event, IpcEvent::SessionClosed { session_id: id, .. } if id == session_id)
+            })
+            .await
+        {
+            IpcEvent::SessionClosed {
+                exit_code, reason, ..
+            } => (exit_code, reason),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -12,7 +12,7 @@ use tokio::time::timeout;
 use tokio_util::sync::CancellationToken;
 
 use kt_core::config::OrchestratorConfig;
-use kt_core::ipc::{IpcRequest, IpcResponse};
+use kt_core::ipc::{IpcRequest, IpcResponse, OrchestratorOwner};
 use kt_orchestrator::ipc::IpcServer;
 use kt_orchestrator::OrchestratorState;
 
@@ -94,10 +94,13 @@ impl TestClient {
             .send_request(IpcRequest::Authenticate {
                 token: token.to_string(),
                 client_id: None,
+                instance_id: None,
+                force_takeover: false,
+                close_on_disconnect: false,
             })
             .await;
         assert!(
-            matches!(response, IpcResponse::Authenticated { epoch_id: _, current_seq: _ }),
+            matches!(response, IpcResponse::Authenticated { .. }),
             "Authentication failed: {:?}",
             response
         );
@@ -151,13 +154,56 @@ async fn test_ipc_get_status() {
     let mut client = TestClient::connect(&address).await;
     client.authenticate(&auth_token).await;
 
-    let response = client.send_request(IpcRequest::GetStatus).await;
+    let response = client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: false,
+        })
+        .await;
 
     match response {
         IpcResponse::Status(status) => {
             assert!(status.running);
             assert_eq!(status.machine_count, 0);
             assert_eq!(status.session_count, 0);
+            assert_eq!(status.owner, OrchestratorOwner::Standalone);
+        }
+        other => panic!("Expected Status response, got {:?}", other),
+    }
+
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_ipc_get_status_reports_embedded_owner() {
+    let port = get_test_port();
+    let address = format!("127.0.0.1:{}", port);
+    let state = Arc::new(
+        OrchestratorState::new(OrchestratorConfig::default())
+            .with_owner(OrchestratorOwner::DesktopEmbedded),
+    );
+
+    let server =
+        Arc::new(IpcServer::new(address.clone(), state).expect("Failed to create IPC server"));
+    let auth_token = server.auth_token().to_string();
+    let server_clone = Arc::clone(&server);
+
+    let server_handle = tokio::spawn(async move {
+        let _ = server_clone.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = TestClient::connect(&address).await;
+    client.authenticate(&auth_token).await;
+
+    match client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: false,
+        })
+        .await
+    {
+        IpcResponse::Status(status) => {
+            assert_eq!(status.owner, OrchestratorOwner::DesktopEmbedded);
         }
         other => panic!("Expected Status response, got {:?}", other),
     }
@@ -216,11 +262,16 @@ async fn test_ipc_list_sessions_empty() {
     client.authenticate(&auth_token).await;
 
     let response = client
-        .send_request(IpcRequest::ListSessions { machine_id: None })
+        .send_request(IpcRequest::ListSessions {
+            machine_id: None,
+            cursor: None,
+            offset: None,
+            limit: None,
+        })
         .await;
 
     match response {
-        IpcResponse::Sessions { sessions } => {
+        IpcResponse::Sessions { sessions, .. } => {
             assert!(sessions.is_empty());
         }
         other => panic!("Expected Sessions response, got {:?}", other),
@@ -287,6 +338,17 @@ async fn test_ipc_create_session_machine_not_found() {
         .send_request(IpcRequest::CreateSession {
             machine_id: "nonexistent".to_string(),
             shell: None,
+            cwd: None,
+            env: vec![],
+            name: None,
+            size: None,
+            allocate_pty: true,
+            log_level: None,
+            term: None,
+            truecolor: false,
+            idempotency_key: None,
+            ttl_secs: None,
+            close_on_exit: None,
         })
         .await;
 
@@ -406,7 +468,11 @@ async fn test_ipc_multiple_requests() {
     }
 
     // Mixed requests
-    let response = client.send_request(IpcRequest::GetStatus).await;
+    let response = client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: false,
+        })
+        .await;
     assert!(matches!(response, IpcResponse::Status(_)));
 
     let response = client.send_request(IpcRequest::ListMachines).await;
@@ -614,7 +680,11 @@ async fn test_ipc_status_includes_pairing_code() {
     let mut client = TestClient::connect(&address).await;
     client.authenticate(&auth_token).await;
 
-    let response = client.send_request(IpcRequest::GetStatus).await;
+    let response = client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: true,
+        })
+        .await;
 
     match response {
         IpcResponse::Status(status) => {
@@ -624,5 +694,147 @@ async fn test_ipc_status_includes_pairing_code() {
         other => panic!("Expected Status response, got {:?}", other),
     }
 
+    // Left out unless asked for
+    let response = client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: false,
+        })
+        .await;
+
+    match response {
+        IpcResponse::Status(status) => assert_eq!(status.pairing_code, None),
+        other => panic!("Expected Status response, got {:?}", other),
+    }
+
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_ipc_rotate_token_keeps_previous_token_briefly() {
+    let port = get_test_port();
+    let address = format!("127.0.0.1:{}", port);
+    let state = create_test_state();
+
+    let server =
+        Arc::new(IpcServer::new(address.clone(), state).expect("Failed to create IPC server"));
+    let old_token = server.auth_token();
+    let server_clone = Arc::clone(&server);
+
+    let server_handle = tokio::spawn(async move {
+        let _ = server_clone.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let mut client = TestClient::connect(&address).await;
+
+    // Rotation needs authentication
+    let response = client.send_request(IpcRequest::RotateIpcToken).await;
+    assert!(matches!(response, IpcResponse::AuthenticationRequired));
+
+    client.authenticate(&old_token).await;
+    let response = client.send_request(IpcRequest::RotateIpcToken).await;
+    match response {
+        IpcResponse::TokenRotated {
+            previous_valid_secs,
+            expires_at,
+            ..
+        } => {
+            assert_eq!(
+                previous_valid_secs,
+                kt_orchestrator::ipc::TOKEN_ROTATION_OVERLAP.as_secs()
+            );
+            assert_eq!(expires_at, None);
+        }
+        other => panic!("Expected TokenRotated response, got {:?}", other),
+    }
+    let new_token = server.auth_token();
+    assert_ne!(new_token, old_token);
+
+    // This connection stays authenticated
+    let response = client
+        .send_request(IpcRequest::GetStatus {
+            include_pairing_code: false,
+        })
+        .await;
+    assert!(matches!(response, IpcResponse::Status(_)));
+
+    // Both tokens work for new connections during the overlap
+    for token in [&old_token, &new_token] {
+        let mut other = TestClient::connect(&address).await;
+        other.authenticate(token).await;
+    }
+
+    let mut other = TestClient::connect(&address).await;
+    let response = other
+        .send_request(IpcRequest::Authenticate {
+            token: "0".repeat(64),
+            client_id: None,
+            instance_id: None,
+            force_takeover: false,
+            close_on_disconnect: false,
+        })
+        .await;
+    assert!(matches!(response, IpcResponse::Error { .. }));
+
+    server_handle.abort();
+}
+
+#[tokio::test]
+async fn test_ipc_client_id_held_by_another_process() {
+    let port = get_test_port();
+    let address = format!("127.0.0.1:{}", port);
+    let state = create_test_state();
+
+    let server =
+        Arc::new(IpcServer::new(address.clone(), state).expect("Failed to create IPC server"));
+    let token = server.auth_token();
+    let server_clone = Arc::clone(&server);
+
+    let server_handle = tokio::spawn(async move {
+        let _ = server_clone.run().await;
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let authenticate = |instance: &str, force_takeover: bool| IpcRequest::Authenticate {
+        token: token.clone(),
+        client_id: Some("shared-client".to_string()),
+        instance_id: Some(instance.to_string()),
+        force_takeover,
+        close_on_disconnect: false,
+    };
+
+    let mut first = TestClient::connect(&address).await;
+    let response = first.send_request(authenticate("process-a", false)).await;
+    assert!(matches!(response, IpcResponse::Authenticated { .. }));
+
+    // A second connection of the same process shares the ID
+    let mut sibling = TestClient::connect(&address).await;
+    let response = sibling.send_request(authenticate("process-a", false)).await;
+    assert!(matches!(response, IpcResponse::Authenticated { .. }));
+
+    // Another process is refused while they are connected
+    let mut other = TestClient::connect(&address).await;
+    match other.send_request(authenticate("process-b", false)).await {
+        IpcResponse::Error { message } => assert!(message.contains("force"), "{}", message),
+        response => panic!("Expected Error response, got {:?}", response),
+    }
+    let response = other.send_request(IpcRequest::ListMachines).await;
+    assert!(matches!(response, IpcResponse::AuthenticationRequired));
+
+    // ...unless it takes the ID over, which closes their connections
+    let response = other.send_request(authenticate("process-b", true)).await;
+    assert!(matches!(response, IpcResponse::Authenticated { .. }));
+    let mut line = String::new();
+    let read = timeout(Duration::from_secs(2), first.reader.read_line(&mut line)).await;
+    assert_eq!(read.expect("connection not closed").unwrap(), 0);
+
+    // Once the holder's process is gone, its ID is free again
+    drop((first, sibling, other));
+    let mut again = TestClient::connect(&address).await;
+    let response = again.send_request(authenticate("process-a", false)).await;
+    assert!(matches!(response, IpcResponse::Authenticated { .. }));
+
     server_handle.abort();
 }
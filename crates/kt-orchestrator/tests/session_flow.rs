@@ -0,0 +1,396 @@
+//! Session flow tests
+//!
+//! Drives sessions over IPC from CreateSession to SessionClosed, against
+//! fake agents that behave and ones that don't (see `fake_agent`), and
+//! agent settings from SetAgentOption to the agent's ack, and sessions
+//! whose client disconnects.
+
+mod fake_agent;
+
+use std::time::Duration;
+
+use kt_core::config::{DurationString, OrchestratorConfig};
+use kt_core::ipc::{
+    CloseReason, IpcEvent, IpcRequest, IpcResponse, MachineStatus, SessionDetails, SessionLifecycle,
+};
+use kt_orchestrator::connection::AgentCommand;
+
+use fake_agent::{Behavior, FakeAgent, Harness, TestClient};
+
+/// Default config, with machines kept `reconnect_grace` after dropping
+fn config(reconnect_grace: Duration) -> OrchestratorConfig {
+    OrchestratorConfig {
+        reconnect_grace: DurationString(reconnect_grace),
+        ..Default::default()
+    }
+}
+
+/// Connect an agent and wait for clients to see it
+async fn connect_agent(
+    harness: &Harness,
+    client: &mut TestClient,
+    behavior: Behavior,
+) -> FakeAgent {
+    let agent = harness.agent("web", behavior).await;
+    let machine_id = agent.machine_id.to_string();
+    client
+        .wait_for_event(
+            |event| matches!(event, IpcEvent::MachineConnected(info) if info.id == machine_id),
+        )
+        .await;
+    agent
+}
+
+/// Wait for the agent to confirm `session_id`, returning its PID
+async fn wait_for_confirmation(client: &mut TestClient, session_id: &str) -> u32 {
+    match client
+        .wait_for_event(
+            |event| matches!(event, IpcEvent::SessionCreated(info) if info.id == session_id),
+        )
+        .await
+    {
+        IpcEvent::SessionCreated(info) => info.pid.expect("Confirmed without a PID"),
+        _ => unreachable!(),
+    }
+}
+
+async fn session_details(client: &mut TestClient, session_id: &str) -> SessionDetails {
+    let request = IpcRequest::GetSession {
+        session_id: session_id.to_string(),
+    };
+    match client.request(request).await {
+        IpcResponse::Session(details) => details,
+        other => panic!("Expected Session response, got {:?}", other),
+    }
+}
+
+async fn send_input(client: &mut TestClient, session_id: &str, data: &[u8]) -> IpcResponse {
+    client
+        .request(IpcRequest::SessionInput {
+            session_id: session_id.to_string(),
+            data: data.to_vec(),
+        })
+        .await
+}
+
+async fn close_session(client: &mut TestClient, session_id: &str) {
+    let request = IpcRequest::CloseSession {
+        session_id: session_id.to_string(),
+        force: false,
+    };
+    assert!(matches!(client.request(request).await, IpcResponse::Ok));
+}
+
+async fn subscribe(client: &mut TestClient, session_id: &str) {
+    let request = IpcRequest::Subscribe {
+        session_id: session_id.to_string(),
+    };
+    assert!(matches!(client.request(request).await, IpcResponse::Ok));
+}
+
+async fn wait_for_output(client: &mut TestClient, session_id: &str, expected: &[u8]) {
+    client
+        .wait_for_event(|event| {
+            matches!(
+                event,
+                IpcEvent::TerminalOutput { session_id: id, data, .. }
+                    if id == session_id && data == expected
+            )
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_session_flow_from_create_to_exit() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::Normal).await;
+
+    let session_id = client.create_session("web", None).await;
+    assert!(wait_for_confirmation(&mut client, &session_id).await > 0);
+    assert_eq!(
+        session_details(&mut client, &session_id).await.state,
+        SessionLifecycle::Active
+    );
+
+    // Input reaches the agent and its echo comes back, then the shell prints
+    subscribe(&mut client, &session_id).await;
+    assert!(matches!(
+        send_input(&mut client, &session_id, b"ls\r").await,
+        IpcResponse::Ok
+    ));
+    wait_for_output(&mut client, &session_id, b"ls\r").await;
+    agent.output(&session_id, b"notes.txt\r\n").await;
+    wait_for_output(&mut client, &session_id, b"notes.txt\r\n").await;
+
+    agent.exit(&session_id, 0).await;
+    assert_eq!(
+        client.wait_for_close(&session_id).await,
+        (Some(0), Some(CloseReason::ProcessExited { code: Some(0) }))
+    );
+    match client
+        .request(IpcRequest::ListSessions {
+            machine_id: None,
+            cursor: None,
+            offset: None,
+            limit: None,
+        })
+        .await
+    {
+        IpcResponse::Sessions { sessions, .. } => assert!(sessions.is_empty()),
+        other => panic!("Expected Sessions response, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_unconfirmed_session_closed_on_request() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::NeverConfirm).await;
+
+    let session_id = client.create_session("web", None).await;
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::CreateSession { .. }))
+        .await;
+    // Sessions count as active once requested; only the PID is missing
+    assert_eq!(
+        session_details(&mut client, &session_id).await.session.pid,
+        None
+    );
+
+    // Closing doesn't wait for an agent that never answered
+    close_session(&mut client, &session_id).await;
+    assert_eq!(
+        client.wait_for_close(&session_id).await,
+        (None, Some(CloseReason::UserRequested))
+    );
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::CloseSession { .. }))
+        .await;
+}
+
+#[tokio::test]
+async fn test_unconfirmed_session_closed_when_agent_disconnects() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::NeverConfirm).await;
+
+    let session_id = client.create_session("web", None).await;
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::CreateSession { .. }))
+        .await;
+
+    agent.disconnect().await;
+    assert_eq!(
+        client.wait_for_close(&session_id).await,
+        (None, Some(CloseReason::MachineDisconnected))
+    );
+    client
+        .wait_for_event(|event| matches!(event, IpcEvent::MachineDisconnected { .. }))
+        .await;
+    assert_eq!(harness.state.coordinator.sessions.len(), 0);
+}
+
+#[tokio::test]
+async fn test_mid_session_disconnect_reaped_after_grace() {
+    let harness = Harness::start(config(Duration::from_millis(300))).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::Normal).await;
+    let session_id = client.create_session("web", None).await;
+    wait_for_confirmation(&mut client, &session_id).await;
+
+    // Kept while the machine may still come back...
+    agent.disconnect().await;
+    client
+        .wait_for_event(|event| {
+            matches!(event, IpcEvent::MachineUpdated(info)
+                if info.status == MachineStatus::Reconnecting)
+        })
+        .await;
+    assert_eq!(
+        session_details(&mut client, &session_id).await.state,
+        SessionLifecycle::Active
+    );
+
+    // ...and closed by the reconnect grace task once it hasn't
+    assert_eq!(
+        client.wait_for_close(&session_id).await,
+        (None, Some(CloseReason::MachineDisconnected))
+    );
+    client
+        .wait_for_event(|event| matches!(event, IpcEvent::MachineDisconnected { .. }))
+        .await;
+    assert_eq!(harness.state.coordinator.sessions.len(), 0);
+    assert!(harness
+        .state
+        .coordinator
+        .connections
+        .list_reconnecting()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_agent_back_within_grace_keeps_session() {
+    let harness = Harness::start(config(Duration::from_secs(30))).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::Normal).await;
+    let session_id = client.create_session("web", None).await;
+    wait_for_confirmation(&mut client, &session_id).await;
+    subscribe(&mut client, &session_id).await;
+
+    agent.disconnect().await;
+    client
+        .wait_for_event(|event| {
+            matches!(event, IpcEvent::MachineUpdated(info)
+                if info.status == MachineStatus::Reconnecting)
+        })
+        .await;
+
+    // Input goes to the new connection
+    let agent = harness.agent("web", Behavior::Normal).await;
+    client
+        .wait_for_event(|event| {
+            matches!(event, IpcEvent::MachineUpdated(info)
+                if info.status == MachineStatus::Connected && info.session_count == 1)
+        })
+        .await;
+    assert!(matches!(
+        send_input(&mut client, &session_id, b"pwd\r").await,
+        IpcResponse::Ok
+    ));
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::SessionInput { .. }))
+        .await;
+    wait_for_output(&mut client, &session_id, b"pwd\r").await;
+}
+
+#[tokio::test]
+async fn test_input_before_slow_confirmation_is_delivered() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(
+        &harness,
+        &mut client,
+        Behavior::SlowAck(Duration::from_millis(300)),
+    )
+    .await;
+
+    let session_id = client.create_session("web", None).await;
+    assert_eq!(
+        session_details(&mut client, &session_id).await.session.pid,
+        None
+    );
+    subscribe(&mut client, &session_id).await;
+    assert!(matches!(
+        send_input(&mut client, &session_id, b"whoami\r").await,
+        IpcResponse::Ok
+    ));
+
+    // The input waits behind the confirmation, as on a real connection
+    let pid = wait_for_confirmation(&mut client, &session_id).await;
+    wait_for_output(&mut client, &session_id, b"whoami\r").await;
+    assert_eq!(
+        session_details(&mut client, &session_id).await.session.pid,
+        Some(pid)
+    );
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::SessionInput { .. }))
+        .await;
+}
+
+#[tokio::test]
+async fn test_exited_session_kept_until_closed() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::Normal).await;
+    let session_id = client.create_session("web", Some(false)).await;
+    wait_for_confirmation(&mut client, &session_id).await;
+
+    agent.exit(&session_id, 3).await;
+    client
+        .wait_for_event(|event| matches!(event, IpcEvent::Notice { .. }))
+        .await;
+    let details = session_details(&mut client, &session_id).await;
+    assert_eq!(details.state, SessionLifecycle::Exited);
+    assert_eq!(details.exit_code, Some(3));
+    match send_input(&mut client, &session_id, b"ls\r").await {
+        IpcResponse::Error { message } => assert!(message.contains("exited"), "{}", message),
+        other => panic!("Expected Error response, got {:?}", other),
+    }
+
+    close_session(&mut client, &session_id).await;
+    assert_eq!(
+        client.wait_for_close(&session_id).await,
+        (Some(3), Some(CloseReason::UserRequested))
+    );
+}
+
+#[tokio::test]
+async fn test_agent_option_answered_with_ack() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut client = harness.client().await;
+    let agent = connect_agent(&harness, &mut client, Behavior::Normal).await;
+    let set = |key: &str| IpcRequest::SetAgentOption {
+        machine_id: "web".to_string(),
+        key: key.to_string(),
+        value: "30".to_string(),
+    };
+
+    assert!(matches!(
+        client.request(set("metrics_interval")).await,
+        IpcResponse::Ok
+    ));
+    match agent
+        .wait_for_command(|command| matches!(command, AgentCommand::UpdateConfig { .. }))
+        .await
+    {
+        AgentCommand::UpdateConfig { key, value } => {
+            assert_eq!((key.as_str(), value.as_str()), ("metrics_interval", "30"));
+        }
+        _ => unreachable!(),
+    }
+
+    // Keys the agent doesn't know reach it, and come back refused
+    match client.request(set("output_rate_cap")).await {
+        IpcResponse::Error { message } => {
+            assert!(message.contains("Unknown setting"), "{}", message)
+        }
+        other => panic!("Expected Error response, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_owner_disconnect_closes_only_when_asked() {
+    let harness = Harness::start(config(Duration::ZERO)).await;
+    let mut observer = harness.client().await;
+    let agent = connect_agent(&harness, &mut observer, Behavior::Normal).await;
+
+    let mut closing = harness.client_closing_on_disconnect().await;
+    let closed_id = closing.create_session("web", None).await;
+    wait_for_confirmation(&mut observer, &closed_id).await;
+    drop(closing);
+    assert_eq!(
+        observer.wait_for_close(&closed_id).await.1,
+        Some(CloseReason::OwnerDisconnected)
+    );
+    agent
+        .wait_for_command(|command| matches!(command, AgentCommand::CloseSession { .. }))
+        .await;
+
+    // Without the flag, the session is orphaned for its client to reclaim
+    let mut keeping = harness.client().await;
+    let kept_id = keeping.create_session("web", None).await;
+    wait_for_confirmation(&mut observer, &kept_id).await;
+    drop(keeping);
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while session_details(&mut observer, &kept_id)
+            .await
+            .orphaned_at
+            .is_none()
+        {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await
+    .expect("Session not orphaned");
+}
@@ -0,0 +1,102 @@
+//! Agent settings the orchestrator can change at runtime
+//!
+//! The orchestrator sends `AgentConfigUpdate { key, value }` on the control
+//! session, and the agent answers every update with `AgentConfigAck`, saying
+//! whether it applied it and why not. Updates of keys the agent doesn't know
+//! are answered too, as not applied, so an orchestrator newer than its agent
+//! learns the setting had no effect instead of assuming it did.
+//!
+//! Only agents advertising [`crate::Capability::AgentConfig`] understand the
+//! messages at all; older ones would drop the connection on them. Settings
+//! last as long as the connection: an agent that reconnects starts over with
+//! its configured defaults.
+
+use std::fmt;
+use std::time::Duration;
+
+/// A setting known to this version of the protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AgentConfigKey {
+    /// Seconds between the agent's reports of host metrics, `0` to stop
+    /// them (see [`parse_metrics_interval`])
+    MetricsInterval,
+}
+
+/// Longest metrics interval accepted
+pub const MAX_METRICS_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+impl AgentConfigKey {
+    /// All known keys
+    pub const ALL: [AgentConfigKey; 1] = [AgentConfigKey::MetricsInterval];
+
+    /// Name used on the wire
+    pub fn name(self) -> &'static str {
+        match self {
+            AgentConfigKey::MetricsInterval => "metrics_interval",
+        }
+    }
+
+    /// The key named `name`, if it's known
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|key| key.name() == name)
+    }
+
+    /// Check that `value` is valid for this key
+    pub fn validate(self, value: &str) -> Result<(), String> {
+        match self {
+            AgentConfigKey::MetricsInterval => parse_metrics_interval(value).map(|_| ()),
+        }
+    }
+}
+
+impl fmt::Display for AgentConfigKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Parse the value of [`AgentConfigKey::MetricsInterval`]
+///
+/// Whole seconds up to [`MAX_METRICS_INTERVAL_SECS`]; `0` turns reports off
+/// and gives `None`.
+pub fn parse_metrics_interval(value: &str) -> Result<Option<Duration>, String> {
+    let secs: u64 = value.trim().parse().map_err(|_| {
+        format!(
+            "Invalid metrics interval '{}': expected whole seconds",
+            value
+        )
+    })?;
+    if secs > MAX_METRICS_INTERVAL_SECS {
+        return Err(format!(
+            "Metrics interval too long: {}s (max {}s)",
+            secs, MAX_METRICS_INTERVAL_SECS
+        ));
+    }
+    Ok((secs > 0).then(|| Duration::from_secs(secs)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_names_roundtrip() {
+        for key in AgentConfigKey::ALL {
+            assert_eq!(AgentConfigKey::from_name(key.name()), Some(key));
+        }
+        assert_eq!(AgentConfigKey::from_name("output_rate_cap"), None);
+    }
+
+    #[test]
+    fn test_parse_metrics_interval() {
+        assert_eq!(
+            parse_metrics_interval("30"),
+            Ok(Some(Duration::from_secs(30)))
+        );
+        assert_eq!(parse_metrics_interval("0"), Ok(None));
+        assert!(parse_metrics_interval("1.5").is_err());
+        assert!(parse_metrics_interval("-1").is_err());
+        assert!(parse_metrics_interval("86401").is_err());
+        assert!(AgentConfigKey::MetricsInterval.validate("soon").is_err());
+    }
+}
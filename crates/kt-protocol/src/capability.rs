@@ -0,0 +1,161 @@
+//! Agent capability advertisement
+//!
+//! Agents report the optional features they support in their `Register`
+//! message. The orchestrator stores the set on the connection and checks it
+//! before sending a command that relies on one of those features, so newer
+//! orchestrators degrade gracefully against older agents. Agents from before
+//! this field (protocol 1.0) register with an empty set; their `Register` is
+//! decoded in its old layout (see [`crate::codec`]).
+//!
+//! Basic session I/O (create, data, resize, close) is always available and is
+//! not represented here: an agent advertising no capabilities is fully usable
+//! for plain terminal sessions.
+
+use serde::{Deserialize, Serialize};
+
+/// An optional agent feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Compressed `Data` payloads
+    Compression,
+    /// Delivering signals to the session's process
+    Signals,
+    /// File upload and download
+    FileTransfer,
+    /// Host and session resource metrics
+    Metrics,
+    /// Starting a session in a given working directory
+    Cwd,
+    /// Passing arguments to the session's shell
+    ShellArgs,
+    /// Sessions on plain pipes instead of a PTY, with stderr kept apart
+    Pipes,
+    /// Per-session log level for the agent's session lifecycle logs
+    SessionLogLevel,
+    /// Setting the session's `TERM` (and `COLORTERM`) from the client
+    Term,
+    /// Settings changed at runtime with `AgentConfigUpdate` (see
+    /// [`crate::agent_config`])
+    AgentConfig,
+}
+
+impl Capability {
+    /// All known capabilities, in bit order
+    pub const ALL: [Capability; 10] = [
+        Capability::Compression,
+        Capability::Signals,
+        Capability::FileTransfer,
+        Capability::Metrics,
+        Capability::Cwd,
+        Capability::ShellArgs,
+        Capability::Pipes,
+        Capability::SessionLogLevel,
+        Capability::Term,
+        Capability::AgentConfig,
+    ];
+
+    /// Bit used for this capability on the wire
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// Stable name used in logs, errors and IPC responses
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Compression => "compression",
+            Capability::Signals => "signals",
+            Capability::FileTransfer => "file_transfer",
+            Capability::Metrics => "metrics",
+            Capability::Cwd => "cwd",
+            Capability::ShellArgs => "shell_args",
+            Capability::Pipes => "pipes",
+            Capability::SessionLogLevel => "session_log_level",
+            Capability::Term => "term",
+            Capability::AgentConfig => "agent_config",
+        }
+    }
+}
+
+impl std::fmt::Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Set of capabilities advertised by an agent.
+///
+/// Encoded as a bitset so it stays compact on the wire. Bits the receiver
+/// doesn't know about are preserved but ignored, which lets agents advertise
+/// features added after the orchestrator was built.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AgentCapabilities(u32);
+
+impl AgentCapabilities {
+    /// No optional capabilities (basic session I/O only)
+    pub fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Check whether a capability is present
+    pub fn contains(&self, capability: Capability) -> bool {
+        self.0 & capability.bit() != 0
+    }
+
+    /// Add a capability to the set
+    pub fn insert(&mut self, capability: Capability) {
+        self.0 |= capability.bit();
+    }
+
+    /// Return the set with a capability added
+    pub fn with(mut self, capability: Capability) -> Self {
+        self.insert(capability);
+        self
+    }
+
+    /// Check whether no capabilities are advertised
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the known capabilities in this set
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        Capability::ALL.into_iter().filter(|c| self.contains(*c))
+    }
+
+    /// Names of the known capabilities in this set
+    pub fn names(&self) -> Vec<String> {
+        self.iter().map(|c| c.name().to_string()).collect()
+    }
+}
+
+impl FromIterator<Capability> for AgentCapabilities {
+    fn from_iter<I: IntoIterator<Item = Capability>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(Self::empty(), |caps, capability| caps.with(capability))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_set_operations() {
+        let caps: AgentCapabilities = [Capability::Cwd, Capability::Signals].into_iter().collect();
+
+        assert!(caps.contains(Capability::Cwd));
+        assert!(caps.contains(Capability::Signals));
+        assert!(!caps.contains(Capability::FileTransfer));
+        assert_eq!(caps.names(), vec!["signals", "cwd"]);
+
+        assert!(AgentCapabilities::empty().is_empty());
+        assert_eq!(AgentCapabilities::empty().iter().count(), 0);
+    }
+
+    #[test]
+    fn test_capabilities_ignore_unknown_bits() {
+        let caps = AgentCapabilities(1 << 31).with(Capability::Metrics);
+        assert_eq!(caps.iter().collect::<Vec<_>>(), vec![Capability::Metrics]);
+    }
+}
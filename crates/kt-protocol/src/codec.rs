@@ -1,10 +1,28 @@
 //! Tokio codec for framed protocol messages
-
+//!
+//! Frames arrive from the network, so decoding treats every byte as
+//! untrusted:
+//!
+//! - The declared payload length is checked against [`MAX_PAYLOAD_SIZE`]
+//!   before anything is buffered, and the codec never reserves space based
+//!   on it; it waits for the bytes to actually arrive.
+//! - Length prefixes inside the payload (strings, byte buffers, vectors) are
+//!   bounded by the payload length, so a small frame can't claim a
+//!   multi-gigabyte field and trigger a huge allocation.
+//! - Empty payloads, trailing bytes and payloads that don't match the
+//!   header's message type are errors.
+//!
+//! A payload that doesn't decode as the current [`Message`] is tried in the
+//! 1.0 layout (see [`crate::legacy`]), whose messages lack the fields added
+//! since.
+
+use bincode::Options;
 use bytes::BytesMut;
 use tokio_util::codec::{Decoder, Encoder};
 
 use crate::error::ProtocolError;
 use crate::frame::{FrameHeader, MAX_PAYLOAD_SIZE};
+use crate::legacy;
 use crate::message::Message;
 use crate::session::SessionId;
 
@@ -43,6 +61,17 @@ impl FrameCodec {
     }
 }
 
+/// Bincode options for decoding a payload of `payload_len` bytes.
+///
+/// Same wire format as `bincode::serialize`, but inner length prefixes can't
+/// exceed the payload and the whole payload must be consumed.
+fn payload_options(payload_len: usize) -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(payload_len as u64)
+        .reject_trailing_bytes()
+}
+
 impl Decoder for FrameCodec {
     type Item = Frame;
     type Error = ProtocolError;
@@ -66,6 +95,11 @@ impl Decoder for FrameCodec {
             });
         }
 
+        // Every message carries at least its 4-byte variant tag
+        if payload_len == 0 {
+            return Err(ProtocolError::EmptyPayload(header.message_type));
+        }
+
         // Check if we have enough data for the payload
         if src.len() < payload_len {
             // Save header and wait for more data
@@ -77,7 +111,17 @@ impl Decoder for FrameCodec {
         let payload_bytes = src.split_to(payload_len).freeze();
 
         // Deserialize message
-        let message: Message = bincode::deserialize(&payload_bytes)?;
+        let message: Message = match payload_options(payload_len).deserialize(&payload_bytes) {
+            Ok(message) => message,
+            Err(e) => legacy::decode_v1_0(payload_options(payload_len), &payload_bytes)
+                .ok_or(ProtocolError::from(e))?,
+        };
+        if message.message_type() != header.message_type {
+            return Err(ProtocolError::MessageTypeMismatch {
+                header: header.message_type,
+                payload: message.message_type(),
+            });
+        }
 
         Ok(Some(Frame {
             session_id: header.session_id,
@@ -121,7 +165,7 @@ impl Encoder<Frame> for FrameCodec {
 mod tests {
     use super::*;
     use crate::frame::HEADER_SIZE;
-    use crate::message::TerminalSize;
+    use crate::message::{MessageType, TerminalSize};
     use bytes::Bytes;
 
     #[test]
@@ -134,6 +178,11 @@ mod tests {
                 shell: Some("/bin/bash".to_string()),
                 env: vec![("TERM".to_string(), "xterm-256color".to_string())],
                 initial_size: TerminalSize::new(24, 80),
+                cwd: None,
+                allocate_pty: true,
+                log_level: None,
+                term: Some("xterm-256color".to_string()),
+                truecolor: true,
             },
         );
 
@@ -148,6 +197,43 @@ mod tests {
         // Message comparison would need PartialEq
     }
 
+    #[test]
+    fn test_codec_register_capabilities() {
+        let mut codec = FrameCodec::new();
+        let capabilities = crate::AgentCapabilities::empty().with(crate::Capability::Cwd);
+
+        let frame = Frame::new(
+            SessionId::CONTROL,
+            Message::Register {
+                machine_id: "laptop".to_string(),
+                hostname: "laptop.local".to_string(),
+                os: "linux".to_string(),
+                arch: "x86_64".to_string(),
+                version: Some(crate::PROTOCOL_VERSION.to_string()),
+                capabilities,
+                reconnect_count: 3,
+                alias: Some("laptop".to_string()),
+                label: None,
+            },
+        );
+
+        let mut buf = BytesMut::new();
+        codec.encode(frame, &mut buf).unwrap();
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+
+        match decoded.message {
+            Message::Register {
+                capabilities: decoded_caps,
+                reconnect_count,
+                ..
+            } => {
+                assert_eq!(decoded_caps, capabilities);
+                assert_eq!(reconnect_count, 3);
+            }
+            other => panic!("Expected Register, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_codec_data_message() {
         let mut codec = FrameCodec::new();
@@ -170,6 +256,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_codec_stderr_message() {
+        let mut codec = FrameCodec::new();
+
+        let mut buf = BytesMut::new();
+        codec
+            .encode(
+                Frame::new(SessionId::new(3), Message::Stderr(Bytes::from("oops\n"))),
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(buf[4], MessageType::Stderr.as_u8());
+
+        match codec.decode(&mut buf).unwrap().unwrap().message {
+            Message::Stderr(data) => assert_eq!(data.as_ref(), b"oops\n"),
+            other => panic!("Expected Stderr, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_codec_agent_config_messages() {
+        let mut codec = FrameCodec::new();
+        let mut buf = BytesMut::new();
+        for message in [
+            Message::AgentConfigUpdate {
+                key: "metrics_interval".to_string(),
+                value: "30".to_string(),
+            },
+            Message::AgentConfigAck {
+                key: "output_rate_cap".to_string(),
+                applied: false,
+                reason: Some("Unknown setting".to_string()),
+            },
+        ] {
+            codec
+                .encode(Frame::new(SessionId::CONTROL, message), &mut buf)
+                .unwrap();
+        }
+        assert_eq!(buf[4], MessageType::AgentConfigUpdate.as_u8());
+
+        match codec.decode(&mut buf).unwrap().unwrap() {
+            Frame {
+                session_id: SessionId::CONTROL,
+                message: Message::AgentConfigUpdate { key, value },
+            } => assert_eq!((key.as_str(), value.as_str()), ("metrics_interval", "30")),
+            other => panic!("Expected AgentConfigUpdate, got {:?}", other),
+        }
+        match codec.decode(&mut buf).unwrap().unwrap().message {
+            Message::AgentConfigAck {
+                key,
+                applied,
+                reason,
+            } => {
+                assert_eq!(key, "output_rate_cap");
+                assert!(!applied);
+                assert_eq!(reason.as_deref(), Some("Unknown setting"));
+            }
+            other => panic!("Expected AgentConfigAck, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_codec_partial_read() {
         let mut codec = FrameCodec::new();
@@ -196,4 +343,185 @@ mod tests {
             panic!("Expected Heartbeat message");
         }
     }
+
+    /// Encode a frame into a fresh buffer
+    fn encoded(session_id: SessionId, message: Message) -> BytesMut {
+        let mut buf = BytesMut::new();
+        FrameCodec::new()
+            .encode(Frame::new(session_id, message), &mut buf)
+            .unwrap();
+        buf
+    }
+
+    /// Header bytes with an arbitrary declared length
+    fn raw_header(message_type: MessageType, payload_length: u32) -> BytesMut {
+        let mut buf = BytesMut::new();
+        FrameHeader::new(SessionId::new(1), message_type, payload_length).encode(&mut buf);
+        buf
+    }
+
+    /// Decode until the buffer is exhausted or an error occurs, panicking if
+    /// the codec loops without making progress
+    fn decode_all(bytes: &[u8]) {
+        let mut codec = FrameCodec::new();
+        let mut buf = BytesMut::from(bytes);
+        for _ in 0..=bytes.len() {
+            match codec.decode(&mut buf) {
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(_) => return,
+            }
+        }
+        panic!("decoder made no progress");
+    }
+
+    #[test]
+    fn test_huge_declared_length_does_not_allocate() {
+        let mut codec = FrameCodec::new();
+        let mut buf = raw_header(MessageType::Data, MAX_PAYLOAD_SIZE as u32);
+        buf.extend_from_slice(&[0u8; 16]);
+        let capacity = buf.capacity();
+
+        // Waits for the payload instead of reserving 16MB up front
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.capacity() <= capacity);
+    }
+
+    #[test]
+    fn test_length_over_max_rejected() {
+        // The 24-bit field can't express more than MAX_PAYLOAD_SIZE, but a
+        // header built in code can
+        let mut codec = FrameCodec {
+            pending_header: Some(FrameHeader::new(
+                SessionId::new(1),
+                MessageType::Data,
+                MAX_PAYLOAD_SIZE as u32 + 1,
+            )),
+        };
+        let mut buf = BytesMut::new();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(ProtocolError::PayloadTooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn test_huge_inner_length_rejected() {
+        // A Data message whose byte-buffer length prefix claims u64::MAX bytes
+        let mut payload = Vec::new();
+        payload.extend_from_slice(&2u32.to_le_bytes()); // Message::Data variant
+        payload.extend_from_slice(&u64::MAX.to_le_bytes());
+        payload.extend_from_slice(b"tiny");
+
+        let mut buf = raw_header(MessageType::Data, payload.len() as u32);
+        buf.extend_from_slice(&payload);
+
+        let mut codec = FrameCodec::new();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(ProtocolError::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_empty_payload_rejected() {
+        let mut codec = FrameCodec::new();
+        let mut buf = raw_header(MessageType::Heartbeat, 0);
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(ProtocolError::EmptyPayload(MessageType::Heartbeat))
+        ));
+    }
+
+    #[test]
+    fn test_type_mismatch_rejected() {
+        let mut buf = encoded(SessionId::new(1), Message::Heartbeat { timestamp: 1 });
+        buf[4] = MessageType::Data.as_u8();
+
+        let mut codec = FrameCodec::new();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(ProtocolError::MessageTypeMismatch {
+                header: MessageType::Data,
+                payload: MessageType::Heartbeat,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_trailing_payload_bytes_rejected() {
+        let frame = encoded(SessionId::new(1), Message::Heartbeat { timestamp: 1 });
+        let payload = &frame[HEADER_SIZE..];
+
+        let mut buf = raw_header(MessageType::Heartbeat, payload.len() as u32 + 1);
+        buf.extend_from_slice(payload);
+        buf.extend_from_slice(&[0]);
+
+        let mut codec = FrameCodec::new();
+        assert!(matches!(
+            codec.decode(&mut buf),
+            Err(ProtocolError::Serialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_truncated_frames_never_panic() {
+        let frame = encoded(
+            SessionId::new(7),
+            Message::SessionCreate {
+                shell: Some("/bin/zsh".to_string()),
+                env: vec![("LANG".to_string(), "C.UTF-8".to_string())],
+                initial_size: TerminalSize::new(40, 120),
+                cwd: Some("/tmp".to_string()),
+                allocate_pty: false,
+                log_level: Some("debug".to_string()),
+                term: None,
+                truecolor: false,
+            },
+        );
+
+        for len in 0..frame.len() {
+            let mut codec = FrameCodec::new();
+            let mut buf = BytesMut::from(&frame[..len]);
+            assert!(codec.decode(&mut buf).unwrap().is_none());
+        }
+
+        // Shortening the declared length cuts the payload instead. Cut right
+        // after the 1.0 fields it is a valid 1.0 frame
+        for len in 1..frame.len() - HEADER_SIZE {
+            let mut buf = raw_header(MessageType::SessionCreate, len as u32);
+            buf.extend_from_slice(&frame[HEADER_SIZE..HEADER_SIZE + len]);
+            match FrameCodec::new().decode(&mut buf) {
+                Err(_) => {}
+                Ok(Some(Frame {
+                    message: Message::SessionCreate { cwd: None, .. },
+                    ..
+                })) => {}
+                Ok(other) => panic!("Decoded a cut frame as {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_bytes_never_panic() {
+        // xorshift64, so the test is deterministic without a rand dependency
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..2000 {
+            let len = (next() % 64) as usize;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            decode_all(&bytes);
+
+            // Also behind a valid header, so the payload decoder sees it
+            let message_type = MessageType::from_u8((next() % 10) as u8 + 1).unwrap();
+            let mut framed = raw_header(message_type, bytes.len() as u32).to_vec();
+            framed.append(&mut bytes);
+            decode_all(&framed);
+        }
+    }
 }
@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::message::MessageType;
+
 /// Errors that can occur during protocol operations
 #[derive(Error, Debug)]
 pub enum ProtocolError {
@@ -21,6 +23,17 @@ pub enum ProtocolError {
     #[error("Incomplete frame: expected {expected} bytes, got {actual}")]
     IncompleteFrame { expected: usize, actual: usize },
 
+    /// Frame has no payload, but every message needs at least its variant tag
+    #[error("Empty payload for {0:?} frame")]
+    EmptyPayload(MessageType),
+
+    /// Payload decoded to a different message type than the header declared
+    #[error("Header declared {header:?} but payload contains {payload:?}")]
+    MessageTypeMismatch {
+        header: MessageType,
+        payload: MessageType,
+    },
+
     /// Serialization error
     #[error("Serialization error: {0}")]
     Serialization(#[from] bincode::Error),
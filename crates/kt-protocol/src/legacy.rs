@@ -0,0 +1,301 @@
+//! Message layouts of older protocol versions
+//!
+//! Bincode has no optional fields: a message is its fields in order, and
+//! `#[serde(default)]` never applies. A frame from a peer whose messages
+//! lack fields added since therefore fails to decode as the current
+//! [`Message`] (`UnexpectedEof`). Fields are only ever appended, and 1.0
+//! peers decode with trailing bytes allowed, so they read current frames and
+//! ignore what they don't know; this module covers the other direction by
+//! decoding the 1.0 layout and filling in the newer fields with defaults.
+
+use bincode::Options;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use crate::capability::AgentCapabilities;
+use crate::message::{ErrorCode, Message, TerminalSize};
+
+/// Protocol 1.0 messages, in their original variant order
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum MessageV1_0 {
+    SessionCreate {
+        shell: Option<String>,
+        env: Vec<(String, String)>,
+        initial_size: TerminalSize,
+    },
+    SessionReady {
+        pid: u32,
+    },
+    Data(Bytes),
+    Resize(TerminalSize),
+    SessionClose {
+        exit_code: Option<i32>,
+    },
+    Heartbeat {
+        timestamp: u64,
+    },
+    HeartbeatAck {
+        timestamp: u64,
+    },
+    Register {
+        machine_id: String,
+        hostname: String,
+        os: String,
+        arch: String,
+        version: Option<String>,
+    },
+    RegisterAck {
+        accepted: bool,
+        reason: Option<String>,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
+
+impl From<MessageV1_0> for Message {
+    fn from(message: MessageV1_0) -> Self {
+        match message {
+            MessageV1_0::SessionCreate {
+                shell,
+                env,
+                initial_size,
+            } => Message::SessionCreate {
+                shell,
+                env,
+                initial_size,
+                cwd: None,
+                allocate_pty: true,
+                log_level: None,
+                term: None,
+                truecolor: false,
+            },
+            MessageV1_0::SessionReady { pid } => Message::SessionReady { pid },
+            MessageV1_0::Data(data) => Message::Data(data),
+            MessageV1_0::Resize(size) => Message::Resize(size),
+            MessageV1_0::SessionClose { exit_code } => Message::SessionClose { exit_code },
+            MessageV1_0::Heartbeat { timestamp } => Message::Heartbeat { timestamp },
+            MessageV1_0::HeartbeatAck { timestamp } => Message::HeartbeatAck { timestamp },
+            MessageV1_0::Register {
+                machine_id,
+                hostname,
+                os,
+                arch,
+                version,
+            } => Message::Register {
+                machine_id,
+                hostname,
+                os,
+                arch,
+                version,
+                capabilities: AgentCapabilities::empty(),
+                reconnect_count: 0,
+                alias: None,
+                label: None,
+            },
+            MessageV1_0::RegisterAck { accepted, reason } => Message::RegisterAck {
+                accepted,
+                reason,
+                version: None,
+            },
+            MessageV1_0::Error { code, message } => Message::Error { code, message },
+        }
+    }
+}
+
+/// Decode `payload` in the 1.0 layout with the codec's `options`
+pub(crate) fn decode_v1_0(options: impl Options, payload: &[u8]) -> Option<Message> {
+    options
+        .deserialize::<MessageV1_0>(payload)
+        .ok()
+        .map(Message::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capability::Capability;
+    use crate::codec::{Frame, FrameCodec};
+    use crate::frame::FrameHeader;
+    use crate::message::MessageType;
+    use crate::session::SessionId;
+    use bytes::BytesMut;
+    use tokio_util::codec::{Decoder, Encoder};
+
+    /// A frame as a 1.0 peer encodes it
+    fn v1_0_frame(message_type: MessageType, message: &MessageV1_0) -> BytesMut {
+        let payload = bincode::serialize(message).unwrap();
+        let mut buf = BytesMut::new();
+        FrameHeader::new(SessionId::CONTROL, message_type, payload.len() as u32).encode(&mut buf);
+        buf.extend_from_slice(&payload);
+        buf
+    }
+
+    #[test]
+    fn test_decode_v1_0_register() {
+        let mut buf = v1_0_frame(
+            MessageType::Register,
+            &MessageV1_0::Register {
+                machine_id: "laptop".to_string(),
+                hostname: "laptop.local".to_string(),
+                os: "linux".to_string(),
+                arch: "x86_64".to_string(),
+                version: Some("1.0".to_string()),
+            },
+        );
+
+        match FrameCodec::new().decode(&mut buf).unwrap().unwrap().message {
+            Message::Register {
+                machine_id,
+                version,
+                capabilities,
+                reconnect_count,
+                alias,
+                label,
+                ..
+            } => {
+                assert_eq!(machine_id, "laptop");
+                assert_eq!(version.as_deref(), Some("1.0"));
+                assert!(capabilities.is_empty());
+                assert_eq!(reconnect_count, 0);
+                assert_eq!(alias, None);
+                assert_eq!(label, None);
+            }
+            other => panic!("Expected Register, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_v1_0_session_create_and_ack() {
+        let mut buf = v1_0_frame(
+            MessageType::SessionCreate,
+            &MessageV1_0::SessionCreate {
+                shell: None,
+                env: vec![],
+                initial_size: TerminalSize::new(24, 80),
+            },
+        );
+        buf.extend_from_slice(&v1_0_frame(
+            MessageType::RegisterAck,
+            &MessageV1_0::RegisterAck {
+                accepted: true,
+                reason: None,
+            },
+        ));
+
+        let mut codec = FrameCodec::new();
+        match codec.decode(&mut buf).unwrap().unwrap().message {
+            Message::SessionCreate {
+                allocate_pty, cwd, ..
+            } => {
+                assert!(allocate_pty);
+                assert_eq!(cwd, None);
+            }
+            other => panic!("Expected SessionCreate, got {:?}", other),
+        }
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap().unwrap().message,
+            Message::RegisterAck {
+                accepted: true,
+                version: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_v1_0_peer_reads_current_frames() {
+        // 1.0 peers decoded with `bincode::deserialize`, which ignores
+        // trailing bytes, so appended fields are skipped
+        let messages = [
+            Message::Register {
+                machine_id: "laptop".to_string(),
+                hostname: "laptop.local".to_string(),
+                os: "linux".to_string(),
+                arch: "x86_64".to_string(),
+                version: None,
+                capabilities: AgentCapabilities::empty(),
+                reconnect_count: 2,
+                alias: Some("laptop".to_string()),
+                label: None,
+            },
+            Message::RegisterAck {
+                accepted: true,
+                reason: None,
+                version: Some("0.1.0".to_string()),
+            },
+            Message::SessionCreate {
+                shell: Some("/bin/zsh".to_string()),
+                env: vec![],
+                initial_size: TerminalSize::new(40, 120),
+                cwd: Some("/tmp".to_string()),
+                allocate_pty: true,
+                log_level: None,
+                term: None,
+                truecolor: true,
+            },
+        ];
+        for message in messages {
+            let mut buf = BytesMut::new();
+            FrameCodec::new()
+                .encode(Frame::new(SessionId::CONTROL, message), &mut buf)
+                .unwrap();
+            let payload = &buf[crate::HEADER_SIZE..];
+            let old: MessageV1_0 = bincode::deserialize(payload).unwrap();
+            match old {
+                MessageV1_0::Register {
+                    machine_id,
+                    version,
+                    ..
+                } => {
+                    assert_eq!(machine_id, "laptop");
+                    assert_eq!(version, None);
+                }
+                MessageV1_0::RegisterAck { accepted, .. } => assert!(accepted),
+                MessageV1_0::SessionCreate { initial_size, .. } => {
+                    assert_eq!(initial_size, TerminalSize::new(40, 120))
+                }
+                other => panic!("Unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_register_without_version_roundtrips() {
+        let mut buf = BytesMut::new();
+        FrameCodec::new()
+            .encode(
+                Frame::new(
+                    SessionId::CONTROL,
+                    Message::Register {
+                        machine_id: "laptop".to_string(),
+                        hostname: "laptop.local".to_string(),
+                        os: "linux".to_string(),
+                        arch: "x86_64".to_string(),
+                        version: None,
+                        capabilities: AgentCapabilities::empty().with(Capability::Cwd),
+                        reconnect_count: 5,
+                        alias: None,
+                        label: Some("desk".to_string()),
+                    },
+                ),
+                &mut buf,
+            )
+            .unwrap();
+
+        match FrameCodec::new().decode(&mut buf).unwrap().unwrap().message {
+            Message::Register {
+                version,
+                capabilities,
+                reconnect_count,
+                ..
+            } => {
+                assert_eq!(version, None);
+                assert!(capabilities.contains(Capability::Cwd));
+                assert_eq!(reconnect_count, 5);
+            }
+            other => panic!("Expected Register, got {:?}", other),
+        }
+    }
+}
@@ -3,14 +3,22 @@
 //! This crate defines the binary protocol used for communication between
 //! the orchestrator and client agents over SSH tunnels.
 
+pub mod agent_config;
+pub mod capability;
 pub mod codec;
 pub mod error;
 pub mod frame;
+mod legacy;
 pub mod message;
 pub mod session;
 
+pub use agent_config::{parse_metrics_interval, AgentConfigKey, MAX_METRICS_INTERVAL_SECS};
+pub use capability::{AgentCapabilities, Capability};
 pub use codec::{Frame, FrameCodec};
 pub use error::ProtocolError;
 pub use frame::{FrameHeader, HEADER_SIZE, MAX_PAYLOAD_SIZE};
-pub use message::{ErrorCode, Message, MessageType, TerminalSize, PROTOCOL_VERSION};
+pub use message::{
+    is_compatible_protocol, ErrorCode, Message, MessageType, TerminalSize, COMPATIBLE_PROTOCOLS,
+    PROTOCOL_VERSION,
+};
 pub use session::SessionId;
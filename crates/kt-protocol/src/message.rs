@@ -10,10 +10,12 @@
 //! optional `version` field. This enables:
 //!
 //! - **Version negotiation**: Orchestrator can reject incompatible agents
-//! - **Feature detection**: Enable features based on agent capabilities
+//! - **Feature detection**: Enable features based on the agent's advertised
+//!   `capabilities`
 //! - **Compatibility logging**: Track protocol versions in deployments
 //!
-//! Current protocol version: 1.0
+//! Current protocol version: 1.1 (also talks to 1.0, see
+//! [`COMPATIBLE_PROTOCOLS`])
 //!
 //! # Message Flow
 //!
@@ -26,15 +28,45 @@
 //! 5. Terminal I/O: `Data` messages flow bidirectionally
 //! 6. Window resize: `Resize` from orchestrator
 //! 7. Session end: `SessionClose` (can be sent by either side)
+//!
+//! At any time after registration the orchestrator may change an agent
+//! setting with `AgentConfigUpdate`, which the agent answers with
+//! `AgentConfigAck` (see [`crate::agent_config`]).
 
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
+use crate::capability::AgentCapabilities;
+
 /// Current protocol version string.
 ///
 /// This should be included in Register messages to enable version negotiation.
-/// Format: "MAJOR.MINOR" where MAJOR changes indicate breaking changes.
-pub const PROTOCOL_VERSION: &str = "1.0";
+/// Format: "MAJOR.MINOR". The minor version goes up with every change to the
+/// wire layout, such as a field appended to a message; see
+/// [`COMPATIBLE_PROTOCOLS`].
+pub const PROTOCOL_VERSION: &str = "1.1";
+
+/// Protocol versions this build can talk to, oldest first
+///
+/// Bincode has no optional fields: a message decodes only in the exact
+/// layout it was encoded with, and this build rejects trailing bytes. So a
+/// version is listed only if the codec decodes its layout (1.0 through
+/// [`crate::legacy`]) and its peers read this build's frames (1.0 peers
+/// ignore trailing bytes, so they skip the fields appended since). A newer
+/// minor version isn't compatible until a build lists it.
+pub const COMPATIBLE_PROTOCOLS: [&str; 2] = ["1.0", PROTOCOL_VERSION];
+
+/// Major and minor parts of a protocol version ("1" is "1.0")
+fn protocol_parts(version: &str) -> (&str, &str) {
+    version.split_once('.').unwrap_or((version, "0"))
+}
+
+/// Whether a peer speaking protocol `version` can talk to this build
+pub fn is_compatible_protocol(version: &str) -> bool {
+    COMPATIBLE_PROTOCOLS
+        .iter()
+        .any(|compatible| protocol_parts(compatible) == protocol_parts(version))
+}
 
 /// Terminal dimensions
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -85,6 +117,12 @@ pub enum MessageType {
     Register = 0x08,
     /// Registration acknowledgment
     RegisterAck = 0x09,
+    /// Stderr output of a session without a PTY
+    Stderr = 0x0A,
+    /// Runtime change of an agent setting
+    AgentConfigUpdate = 0x0B,
+    /// Answer to a setting change
+    AgentConfigAck = 0x0C,
     /// Error response
     Error = 0xFF,
 }
@@ -107,6 +145,9 @@ impl MessageType {
             0x07 => Some(Self::HeartbeatAck),
             0x08 => Some(Self::Register),
             0x09 => Some(Self::RegisterAck),
+            0x0A => Some(Self::Stderr),
+            0x0B => Some(Self::AgentConfigUpdate),
+            0x0C => Some(Self::AgentConfigAck),
             0xFF => Some(Self::Error),
             _ => None,
         }
@@ -142,6 +183,21 @@ pub enum Message {
         env: Vec<(String, String)>,
         /// Initial terminal size
         initial_size: TerminalSize,
+        /// Working directory to start the shell in (None = agent default)
+        cwd: Option<String>,
+        /// Run the shell in a PTY. Without one it gets plain pipes: stdout
+        /// comes back as `Data` and stderr as `Stderr`, and an empty `Data`
+        /// closes its stdin. Needs [`crate::Capability::Pipes`].
+        allocate_pty: bool,
+        /// Log level for the agent's logs about this session (`error`
+        /// through `trace`; None = agent default). Needs
+        /// [`crate::Capability::SessionLogLevel`].
+        log_level: Option<String>,
+        /// Terminal type for the session's `TERM` (None = agent default).
+        /// Needs [`crate::Capability::Term`].
+        term: Option<String>,
+        /// The client renders 24-bit color: set `COLORTERM=truecolor`
+        truecolor: bool,
     },
 
     /// Session is ready
@@ -150,7 +206,8 @@ pub enum Message {
         pid: u32,
     },
 
-    /// Terminal data
+    /// Terminal data (for a session without a PTY: stdout, or stdin from
+    /// the orchestrator, where an empty payload means end of input)
     Data(Bytes),
 
     /// Terminal resize
@@ -185,8 +242,12 @@ pub enum Message {
     /// - If present, orchestrator can check compatibility
     /// - If absent, orchestrator assumes protocol version 1.0
     /// - Version mismatch may result in `RegisterAck { accepted: false }`
+    ///
+    /// `capabilities` lists optional features (see [`crate::Capability`]);
+    /// an empty set still supports basic session I/O.
     Register {
-        /// Machine ID (derived from public key fingerprint or Tailscale identity)
+        /// Stable machine ID, derived from the agent's install ID. Agents
+        /// that don't send an `alias` report their alias here instead.
         machine_id: String,
         /// Hostname of the agent machine
         hostname: String,
@@ -196,8 +257,21 @@ pub enum Message {
         arch: String,
         /// Protocol version (e.g., "1.0"). Optional for backward compatibility.
         /// Use `PROTOCOL_VERSION` constant when sending.
-        #[serde(default, skip_serializing_if = "Option::is_none")]
+        #[serde(default)]
         version: Option<String>,
+        /// Optional features this agent supports
+        #[serde(default)]
+        capabilities: AgentCapabilities,
+        /// Times the agent has reconnected since it started; only ever grows
+        /// while it runs. High counts flag an unstable machine.
+        #[serde(default)]
+        reconnect_count: u64,
+        /// Machine alias
+        #[serde(default)]
+        alias: Option<String>,
+        /// Free-form label shown next to the machine
+        #[serde(default)]
+        label: Option<String>,
     },
 
     /// Registration acknowledgment
@@ -206,6 +280,10 @@ pub enum Message {
         accepted: bool,
         /// Reason if not accepted
         reason: Option<String>,
+        /// Orchestrator release version (e.g. "0.1.0"), so the agent can
+        /// warn about a skew. Distinct from the protocol version.
+        #[serde(default)]
+        version: Option<String>,
     },
 
     /// Error response
@@ -215,6 +293,31 @@ pub enum Message {
         /// Human-readable message
         message: String,
     },
+
+    /// Stderr output of a session without a PTY (PTY sessions send all
+    /// output as `Data`)
+    Stderr(Bytes),
+
+    /// Change an agent setting at runtime, on the control session. Keys and
+    /// their values are listed in [`crate::AgentConfigKey`]. Needs
+    /// [`crate::Capability::AgentConfig`].
+    AgentConfigUpdate {
+        /// Setting to change
+        key: String,
+        /// New value, in the key's format
+        value: String,
+    },
+
+    /// Answer to every `AgentConfigUpdate`, including those of keys the
+    /// agent doesn't know
+    AgentConfigAck {
+        /// Setting the update was for
+        key: String,
+        /// Whether the agent applied it
+        applied: bool,
+        /// Why not, if it didn't
+        reason: Option<String>,
+    },
 }
 
 impl Message {
@@ -231,6 +334,9 @@ impl Message {
             Message::Register { .. } => MessageType::Register,
             Message::RegisterAck { .. } => MessageType::RegisterAck,
             Message::Error { .. } => MessageType::Error,
+            Message::Stderr(_) => MessageType::Stderr,
+            Message::AgentConfigUpdate { .. } => MessageType::AgentConfigUpdate,
+            Message::AgentConfigAck { .. } => MessageType::AgentConfigAck,
         }
     }
 }
@@ -239,6 +345,17 @@ impl Message {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_protocol_compatibility() {
+        assert!(is_compatible_protocol(PROTOCOL_VERSION));
+        assert!(is_compatible_protocol("1.0"));
+        assert!(is_compatible_protocol("1"));
+        // A newer minor version may append fields this build can't decode
+        assert!(!is_compatible_protocol("1.7"));
+        assert!(!is_compatible_protocol("2.0"));
+        assert!(!is_compatible_protocol("10.0"));
+    }
+
     #[test]
     fn test_message_type_roundtrip() {
         for msg_type in [
@@ -251,6 +368,9 @@ mod tests {
             MessageType::HeartbeatAck,
             MessageType::Register,
             MessageType::RegisterAck,
+            MessageType::Stderr,
+            MessageType::AgentConfigUpdate,
+            MessageType::AgentConfigAck,
             MessageType::Error,
         ] {
             let byte = msg_type.as_u8();